@@ -0,0 +1,305 @@
+//! Python bindings for the structural analysis of [`bincc`].
+//!
+//! The module `bcc` exposes the construction of a [`CFG`], its structuring into a [`CFS`], the
+//! export of the resulting tree and the comparison of trees. The GIL is released while the
+//! structures are computed, so several Python threads can analyze functions at the same time.
+use ::bincc::analysis::{
+    same_structure, structure_diff, tree_distance, tree_similarity, BasicBlock, CfsBuilder, Graph,
+    StructureBlock, CFG, CFS,
+};
+use ::bincc::disasm::{Architecture, Statement, StatementFamily};
+use ::bincc::Error;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use std::path::PathBuf;
+
+// converts the errors of the crate into python exceptions: IOError for the I/O errors and
+// ValueError for everything else.
+fn py_err(err: Error) -> PyErr {
+    match err {
+        Error::Io(err) => err.into(),
+        other => PyValueError::new_err(other.to_string()),
+    }
+}
+
+fn block_tuple(block: &BasicBlock) -> (u64, u64) {
+    (block.offset, block.length)
+}
+
+/// Control Flow Graph of a function.
+#[pyclass(name = "CFG", module = "bcc")]
+#[derive(Clone)]
+struct PyCfg {
+    cfg: CFG,
+}
+
+#[pymethods]
+impl PyCfg {
+    /// Builds the CFG of a function from its statements.
+    ///
+    /// `statements` is a list of `(offset, family, instruction)` tuples, where `family` is the
+    /// radare2 type of the instruction, like `"cjmp"` or `"ret"`. `end` is the offset where the
+    /// function ends, and `arch` and `bits` the architecture, like `("x86", 64)`.
+    #[new]
+    #[pyo3(signature = (statements, end, arch, bits = 64))]
+    fn new(
+        statements: Vec<(u64, String, String)>,
+        end: u64,
+        arch: &str,
+        bits: u32,
+    ) -> PyResult<Self> {
+        let arch = Architecture::from_name(arch, bits)
+            .ok_or_else(|| PyValueError::new_err(format!("unknown architecture {}", arch)))?;
+        let stmts = statements
+            .iter()
+            .map(|(offset, family, instruction)| {
+                let family = StatementFamily::try_from(family.as_str())?;
+                Ok(Statement::new(*offset, family, instruction))
+            })
+            .collect::<Result<Vec<_>, std::io::Error>>()
+            .map_err(|err| PyValueError::new_err(err.to_string()))?;
+        Ok(PyCfg {
+            cfg: CFG::new(&stmts, end, arch),
+        })
+    }
+
+    /// Parses a CFG written in Graphviz dot format, like the one returned by `to_dot`.
+    #[staticmethod]
+    fn from_dot(dot: &str) -> PyResult<Self> {
+        let cfg = CFG::from_dot(dot).map_err(py_err)?;
+        Ok(PyCfg { cfg })
+    }
+
+    /// Reads a CFG from a file written in Graphviz dot format.
+    #[staticmethod]
+    fn from_file(path: PathBuf) -> PyResult<Self> {
+        let cfg = CFG::from_file(path).map_err(py_err)?;
+        Ok(PyCfg { cfg })
+    }
+
+    /// Returns the CFG in Graphviz dot format.
+    fn to_dot(&self) -> String {
+        self.cfg.to_dot()
+    }
+
+    /// Returns the `(offset, length)` of every basic block, sorted by offset.
+    fn blocks(&self) -> Vec<(u64, u64)> {
+        self.cfg
+            .blocks_sorted()
+            .into_iter()
+            .map(block_tuple)
+            .collect()
+    }
+
+    /// Returns the `(offset, length)` of the successors of the block starting at `offset`.
+    fn successors(&self, offset: u64) -> Vec<(u64, u64)> {
+        self.cfg
+            .blocks_sorted()
+            .into_iter()
+            .find(|block| block.offset == offset)
+            .map(|block| self.cfg.neighbours(block).iter().map(block_tuple).collect())
+            .unwrap_or_default()
+    }
+
+    /// Structures the CFG, releasing the GIL during the analysis.
+    ///
+    /// Raises ValueError if the CFG is malformed.
+    fn structure(&self, py: Python<'_>) -> PyResult<PyCfs> {
+        let cfs = py.allow_threads(|| CFS::new(&self.cfg)).map_err(py_err)?;
+        Ok(PyCfs { cfs })
+    }
+
+    fn __len__(&self) -> usize {
+        self.cfg.len()
+    }
+}
+
+/// Control Flow Structure of a function: the result of structuring its CFG.
+#[pyclass(name = "CFS", module = "bcc")]
+struct PyCfs {
+    cfs: CFS,
+}
+
+#[pymethods]
+impl PyCfs {
+    /// Returns the structure tree, or None if the CFG is empty.
+    fn tree(&self) -> Option<PyTree> {
+        self.cfs.get_tree().map(|tree| PyTree { tree })
+    }
+
+    /// Returns the CFG that originated this structure.
+    fn cfg(&self) -> PyCfg {
+        PyCfg {
+            cfg: self.cfs.get_cfg().clone(),
+        }
+    }
+
+    /// Returns true if the whole CFG was reduced into structures.
+    fn is_complete(&self) -> bool {
+        self.cfs.result().is_complete()
+    }
+
+    /// Returns a score between 0.0 and 1.0 estimating how faithfully the structure represents
+    /// the CFG.
+    fn fidelity(&self) -> f64 {
+        self.cfs.fidelity()
+    }
+
+    /// Returns the edges `(source, target)` removed from loops with multiple exits, as offsets.
+    fn removed_edges(&self) -> Vec<(u64, u64)> {
+        self.cfs
+            .removed_edges()
+            .iter()
+            .map(|(src, dst)| (src.offset, dst.offset))
+            .collect()
+    }
+
+    /// Returns the structure in Graphviz dot format, with each structure as a cluster.
+    fn to_dot(&self) -> String {
+        self.cfs.to_dot()
+    }
+
+    /// Returns the structure tree in Graphviz dot format.
+    fn to_dot_tree(&self) -> String {
+        self.cfs.to_dot_tree()
+    }
+}
+
+/// Node of a structure tree: either a basic block or a structure nesting other nodes.
+#[pyclass(name = "StructureTree", module = "bcc")]
+#[derive(Clone)]
+struct PyTree {
+    tree: StructureBlock,
+}
+
+#[pymethods]
+impl PyTree {
+    /// Parses a tree written in the textual form returned by `to_sexpr`.
+    #[staticmethod]
+    fn from_sexpr(sexpr: &str) -> PyResult<Self> {
+        let tree = StructureBlock::from_sexpr(sexpr)
+            .map_err(|err| PyValueError::new_err(err.to_string()))?;
+        Ok(PyTree { tree })
+    }
+
+    /// Name of the type of this node, like `"If-Then"`.
+    #[getter]
+    fn block_type(&self) -> &'static str {
+        self.tree.get_type_name()
+    }
+
+    /// Offset of the first basic block of this node.
+    #[getter]
+    fn offset(&self) -> u64 {
+        self.tree.offset()
+    }
+
+    /// Depth of the tree rooted in this node.
+    #[getter]
+    fn depth(&self) -> u32 {
+        self.tree.depth()
+    }
+
+    /// Returns the direct children of this node.
+    fn children(&self) -> Vec<PyTree> {
+        self.tree
+            .children()
+            .iter()
+            .map(|child| PyTree {
+                tree: child.clone(),
+            })
+            .collect()
+    }
+
+    /// Returns the `(offset, length)` of every basic block contained in this node.
+    fn basic_blocks(&self) -> Vec<(u64, u64)> {
+        self.tree.basic_blocks().iter().map(block_tuple).collect()
+    }
+
+    /// Returns a hash of the shape of the tree, ignoring the offsets of the basic blocks.
+    fn structural_hash(&self) -> u64 {
+        self.tree.structural_hash()
+    }
+
+    /// Returns the tree in a compact textual form, like `(seq (if b0 b1) b2)`.
+    fn to_sexpr(&self) -> String {
+        self.tree.to_sexpr()
+    }
+
+    /// Returns an indented outline of the tree.
+    fn pretty(&self) -> String {
+        self.tree.pretty()
+    }
+
+    /// Returns the C-like pseudocode of the tree.
+    fn to_pseudocode(&self) -> String {
+        self.tree.to_pseudocode()
+    }
+
+    fn __len__(&self) -> usize {
+        self.tree.len()
+    }
+
+    fn __eq__(&self, other: &PyTree) -> bool {
+        self.tree == other.tree
+    }
+
+    fn __repr__(&self) -> String {
+        format!("StructureTree({})", self.tree.to_sexpr())
+    }
+}
+
+/// Structures every CFG in parallel, releasing the GIL during the analysis.
+///
+/// Returns a list with the CFS of each CFG, in the same order, or None for the CFGs that can not
+/// be structured.
+#[pyfunction]
+fn structure_all(py: Python<'_>, cfgs: Vec<PyRef<'_, PyCfg>>) -> Vec<Option<PyCfs>> {
+    let cfgs = cfgs.iter().map(|cfg| cfg.cfg.clone()).collect::<Vec<_>>();
+    py.allow_threads(|| CfsBuilder::new().build_all(cfgs))
+        .into_iter()
+        .map(|cfs| cfs.ok().map(|cfs| PyCfs { cfs }))
+        .collect()
+}
+
+/// Returns true if the two structures have the same shape.
+#[pyfunction]
+#[pyo3(name = "same_structure")]
+fn py_same_structure(a: &PyCfs, b: &PyCfs) -> bool {
+    same_structure(&a.cfs, &b.cfs)
+}
+
+/// Describes the first difference between the shapes of the two structures, or returns None if
+/// they are the same.
+#[pyfunction]
+#[pyo3(name = "structure_diff")]
+fn py_structure_diff(a: &PyCfs, b: &PyCfs) -> Option<String> {
+    structure_diff(&a.cfs, &b.cfs)
+}
+
+/// Returns the edit distance between two structure trees.
+#[pyfunction]
+#[pyo3(name = "tree_distance")]
+fn py_tree_distance(py: Python<'_>, a: &PyTree, b: &PyTree) -> u32 {
+    py.allow_threads(|| tree_distance(&a.tree, &b.tree))
+}
+
+/// Returns a similarity score between 0.0 and 1.0 for two structure trees.
+#[pyfunction]
+#[pyo3(name = "tree_similarity")]
+fn py_tree_similarity(py: Python<'_>, a: &PyTree, b: &PyTree) -> f64 {
+    py.allow_threads(|| tree_similarity(&a.tree, &b.tree))
+}
+
+#[pymodule]
+fn bcc(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyCfg>()?;
+    m.add_class::<PyCfs>()?;
+    m.add_class::<PyTree>()?;
+    m.add_function(wrap_pyfunction!(structure_all, m)?)?;
+    m.add_function(wrap_pyfunction!(py_same_structure, m)?)?;
+    m.add_function(wrap_pyfunction!(py_structure_diff, m)?)?;
+    m.add_function(wrap_pyfunction!(py_tree_distance, m)?)?;
+    m.add_function(wrap_pyfunction!(py_tree_similarity, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,58 @@
+import bcc
+import pytest
+
+# the if-then-else of the example of CFG::new
+STATEMENTS = [
+    (0x38, "cmp", "cmp dword [var_4h], 0"),
+    (0x3C, "cjmp", "jle 0x45"),
+    (0x3E, "mov", "mov eax, 0"),
+    (0x43, "jmp", "jmp 0x4a"),
+    (0x45, "mov", "mov eax, 1"),
+    (0x4A, "ret", "ret"),
+]
+
+
+def test_cfg():
+    cfg = bcc.CFG(STATEMENTS, 0x4B, "x86", 64)
+    assert len(cfg) == 4
+    assert cfg.blocks()[0] == (0x38, 6)
+    assert sorted(cfg.successors(0x38)) == [(0x3E, 7), (0x45, 5)]
+    assert len(bcc.CFG.from_dot(cfg.to_dot())) == 4
+
+
+def test_invalid_cfg():
+    with pytest.raises(ValueError):
+        bcc.CFG(STATEMENTS, 0x4B, "unknown", 64)
+    with pytest.raises(ValueError):
+        bcc.CFG([(0x38, "unknown", "cmp eax, 0")], 0x3C, "x86", 64)
+
+
+def test_structure():
+    cfs = bcc.CFG(STATEMENTS, 0x4B, "x86", 64).structure()
+    tree = cfs.tree()
+    assert cfs.is_complete()
+    assert len(tree.basic_blocks()) == 4
+    parsed = bcc.StructureTree.from_sexpr(tree.to_sexpr())
+    assert parsed.to_sexpr() == tree.to_sexpr()
+    assert parsed.structural_hash() == tree.structural_hash()
+    assert bcc.same_structure(cfs, cfs)
+    assert bcc.structure_diff(cfs, cfs) is None
+    assert bcc.tree_distance(tree, tree) == 0
+    assert bcc.tree_similarity(tree, tree) == 1.0
+
+
+def test_structure_all():
+    cfgs = [bcc.CFG(STATEMENTS, 0x4B, "x86", 64)] * 3
+    structures = bcc.structure_all(cfgs)
+    assert len(structures) == 3
+    assert all(cfs is not None and cfs.is_complete() for cfs in structures)
+
+
+def test_sexpr():
+    tree = bcc.StructureTree.from_sexpr("(seq b0 (while b1 b2) b3)")
+    assert tree.block_type == "Sequence"
+    assert tree.children()[1].block_type == "While"
+    assert tree.children()[1].offset == 1
+    assert tree.to_sexpr() == "(seq b0 (while b1 b2) b3)"
+    with pytest.raises(ValueError):
+        bcc.StructureTree.from_sexpr("(seq b0")
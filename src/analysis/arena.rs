@@ -0,0 +1,266 @@
+use crate::analysis::blocks::StructureBlock;
+use crate::analysis::{BasicBlock, BlockType, NestedBlock, CFS};
+use std::sync::Arc;
+
+/// Identifier of a node inside a [`StructureArena`].
+///
+/// Identifiers are assigned in depth-first preorder, so the root is always the first one and
+/// every node precedes its children.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(u32);
+
+impl NodeId {
+    /// Returns the position of the node in the arena.
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ArenaNode {
+    block_type: BlockType,
+    offset: u64,
+    depth: u32,
+    // only for basic blocks
+    basic: Option<BasicBlock>,
+    parent: Option<NodeId>,
+    // range in the children vector of the arena
+    first_child: u32,
+    children_len: u32,
+    exits: Vec<(BasicBlock, BasicBlock)>,
+    polarity: Option<bool>,
+    condition: Vec<Vec<(BasicBlock, bool)>>,
+}
+
+/// Flat representation of a [`StructureBlock`] tree.
+///
+/// Every node of the tree is stored in a single vector and referred by its [`NodeId`]. Unlike
+/// [`StructureBlock`], comparing two different nodes never visits their subtrees, there is no
+/// reference counting involved in visiting the tree, and each node knows its parent. This makes
+/// the arena a better fit for analyses running repeated queries over big trees.
+///
+/// The arena is an immutable copy of the tree, built once the tree is complete: the [`CFS`]
+/// creation does not use it, and reduces [`StructureBlock`]s instead. It can be created from any
+/// tree with [`StructureArena::new`] or from a [`CFS`] with [`CFS::arena`], and converted back
+/// with [`StructureArena::to_structure`].
+/// # Examples
+/// Basic usage:
+/// ```
+/// use bincc::analysis::{BasicBlock, BlockType, NestedBlock, StructureArena, StructureBlock};
+/// use std::sync::Arc;
+///
+/// let bb0 = StructureBlock::from(BasicBlock { offset: 0x10, length: 4 });
+/// let bb1 = StructureBlock::from(BasicBlock { offset: 0x14, length: 8 });
+/// let ifthen = NestedBlock::new(BlockType::IfThen, vec![bb0, bb1]);
+/// let arena = StructureArena::new(&StructureBlock::from(Arc::new(ifthen)));
+/// let root = arena.root().unwrap();
+/// let then = arena.children(root)[1];
+///
+/// assert_eq!(arena.len(), 3);
+/// assert_eq!(arena.offset(then), 0x14);
+/// assert_eq!(arena.parent(then), Some(root));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StructureArena {
+    nodes: Vec<ArenaNode>,
+    children: Vec<NodeId>,
+}
+
+impl StructureArena {
+    /// Creates the flat representation of the given tree.
+    pub fn new(tree: &StructureBlock) -> StructureArena {
+        let mut arena = StructureArena::default();
+        arena.insert(tree, None);
+        arena
+    }
+
+    // inserts the node and its subtree, returning the id of the node.
+    fn insert(&mut self, node: &StructureBlock, parent: Option<NodeId>) -> NodeId {
+        let id = NodeId(self.nodes.len() as u32);
+        let (basic, exits, polarity, condition) = match node {
+            StructureBlock::Basic(bb) => (Some(*bb), Vec::new(), None, Vec::new()),
+            StructureBlock::Nested(nb) => {
+                (None, nb.exits.clone(), nb.polarity, nb.condition.clone())
+            }
+        };
+        self.nodes.push(ArenaNode {
+            block_type: node.block_type(),
+            offset: node.offset(),
+            depth: node.depth(),
+            basic,
+            parent,
+            first_child: 0,
+            children_len: 0,
+            exits,
+            polarity,
+            condition,
+        });
+        // children are inserted after the whole subtree of their siblings, so their ids are
+        // collected first and stored contiguously.
+        let children = node
+            .children()
+            .iter()
+            .map(|child| self.insert(child, Some(id)))
+            .collect::<Vec<_>>();
+        let arena_node = &mut self.nodes[id.index()];
+        arena_node.first_child = self.children.len() as u32;
+        arena_node.children_len = children.len() as u32;
+        self.children.extend(children);
+        id
+    }
+
+    /// Returns the root of the tree, or [`None`] if the arena is empty.
+    pub fn root(&self) -> Option<NodeId> {
+        if self.nodes.is_empty() {
+            None
+        } else {
+            Some(NodeId(0))
+        }
+    }
+
+    /// Returns the amount of nodes in the arena.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns true if the arena contains no nodes.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Returns every node of the arena, in depth-first preorder.
+    pub fn nodes(&self) -> impl Iterator<Item = NodeId> {
+        (0..self.nodes.len() as u32).map(NodeId)
+    }
+
+    /// Returns the label of the given node.
+    ///
+    /// Like every other method accepting a [`NodeId`], this method panics if the node does not
+    /// belong to the arena.
+    pub fn block_type(&self, node: NodeId) -> BlockType {
+        self.nodes[node.index()].block_type
+    }
+
+    /// Returns the offset of the given node, as in [`StructureBlock::offset`].
+    pub fn offset(&self, node: NodeId) -> u64 {
+        self.nodes[node.index()].offset
+    }
+
+    /// Returns the depth of the given node, as in [`StructureBlock::depth`].
+    pub fn depth(&self, node: NodeId) -> u32 {
+        self.nodes[node.index()].depth
+    }
+
+    /// Returns the basic block represented by the given node, if the node is a leaf of type
+    /// [`BlockType::Basic`].
+    pub fn basic_block(&self, node: NodeId) -> Option<&BasicBlock> {
+        self.nodes[node.index()].basic.as_ref()
+    }
+
+    /// Returns the children of the given node, in order.
+    pub fn children(&self, node: NodeId) -> &[NodeId] {
+        let node = &self.nodes[node.index()];
+        let start = node.first_child as usize;
+        &self.children[start..start + node.children_len as usize]
+    }
+
+    /// Returns the parent of the given node, or [`None`] for the root.
+    pub fn parent(&self, node: NodeId) -> Option<NodeId> {
+        self.nodes[node.index()].parent
+    }
+
+    /// Returns the exits of the given node, as in [`StructureBlock::exits`].
+    pub fn exits(&self, node: NodeId) -> &[(BasicBlock, BasicBlock)] {
+        &self.nodes[node.index()].exits
+    }
+
+    /// Converts the subtree rooted in the given node back into a [`StructureBlock`].
+    pub fn to_structure(&self, node: NodeId) -> StructureBlock {
+        let arena_node = &self.nodes[node.index()];
+        match arena_node.basic {
+            Some(bb) => StructureBlock::from(bb),
+            None => StructureBlock::from(Arc::new(NestedBlock {
+                offset: arena_node.offset,
+                block_type: arena_node.block_type,
+                content: self
+                    .children(node)
+                    .iter()
+                    .map(|child| self.to_structure(*child))
+                    .collect(),
+                depth: arena_node.depth,
+                exits: arena_node.exits.clone(),
+                polarity: arena_node.polarity,
+                condition: arena_node.condition.clone(),
+            })),
+        }
+    }
+}
+
+impl CFS {
+    /// Returns the flat representation of the tree returned by [`CFS::get_tree`].
+    ///
+    /// Returns [`None`] if the tree is not available.
+    pub fn arena(&self) -> Option<StructureArena> {
+        self.get_tree().map(|tree| StructureArena::new(&tree))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::{BasicBlock, BlockType, NestedBlock, StructureArena, StructureBlock};
+    use std::sync::Arc;
+
+    fn bb(offset: u64) -> StructureBlock {
+        StructureBlock::from(BasicBlock { offset, length: 1 })
+    }
+
+    fn nest(bt: BlockType, children: Vec<StructureBlock>) -> StructureBlock {
+        StructureBlock::from(Arc::new(NestedBlock::new(bt, children)))
+    }
+
+    fn tree() -> StructureBlock {
+        let ifthen = nest(BlockType::IfThen, vec![bb(2), bb(3)]);
+        let whileb = nest(BlockType::While, vec![bb(4), bb(5)]);
+        nest(BlockType::Sequence, vec![bb(1), ifthen, whileb])
+    }
+
+    #[test]
+    fn preorder_ids() {
+        let tree = tree();
+        let arena = StructureArena::new(&tree);
+        assert_eq!(arena.len(), tree.iter_preorder().count());
+        let types = arena
+            .nodes()
+            .map(|id| (arena.block_type(id), arena.offset(id)))
+            .collect::<Vec<_>>();
+        let expected = tree
+            .iter_preorder()
+            .map(|node| (node.block_type(), node.offset()))
+            .collect::<Vec<_>>();
+        assert_eq!(types, expected);
+    }
+
+    #[test]
+    fn navigation() {
+        let arena = StructureArena::new(&tree());
+        let root = arena.root().unwrap();
+        assert_eq!(arena.parent(root), None);
+        let children = arena.children(root);
+        assert_eq!(children.len(), 3);
+        assert_eq!(arena.block_type(children[2]), BlockType::While);
+        let body = arena.children(children[2])[1];
+        assert_eq!(arena.parent(body), Some(children[2]));
+        assert_eq!(arena.basic_block(body).unwrap().offset, 5);
+        assert!(arena.basic_block(root).is_none());
+        assert_eq!(arena.depth(root), 2);
+    }
+
+    #[test]
+    fn back_to_structure() {
+        let tree = tree();
+        let arena = StructureArena::new(&tree);
+        assert_eq!(arena.to_structure(arena.root().unwrap()), tree);
+        let ifthen = arena.children(arena.root().unwrap())[1];
+        assert_eq!(arena.to_structure(ifthen), tree.children()[1]);
+    }
+}
@@ -1,22 +1,91 @@
-use crate::analysis::BasicBlock;
-use std::collections::hash_map::DefaultHasher;
-use std::fmt::{Display, Formatter};
-use std::hash::Hash;
+use crate::analysis::serial::type_tag;
+use crate::analysis::{BasicBlock, PseudocodeWriter};
+use fnv::FnvHasher;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt::{Debug, Display, Formatter};
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
 use std::sync::Arc;
 
 /// High-level structure label assigned to a [`NestedBlock`].
-#[derive(Debug, PartialEq, Eq, Copy, Clone, Hash)]
+///
+/// Labels are ordered as they are declared.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Hash, PartialOrd, Ord)]
 pub enum BlockType {
     Basic,
     SelfLooping,
     Sequence,
     IfThen,
     IfThenElse,
+    /// Loop checking its condition before the body. This block contains the block computing the
+    /// condition followed by the body. When the head of the loop is also the head of a
+    /// [`BlockType::Switch`], this block contains only the switch, possibly followed by the block
+    /// joining its cases in a [`BlockType::Sequence`], and the loop is exited by the head.
     While,
     DoWhile,
     Switch,
     ProperInterval,
     ImproperInterval,
+    /// Marker for an early exit from a loop. This block has no children and its offset is the
+    /// offset of the basic block performing the exit.
+    Break,
+    /// Copy of a block that has been duplicated to make an irreducible region reducible. This
+    /// block has exactly one child: the duplicated block.
+    Duplicate,
+    /// Region that could not be reduced by any other pattern. This block contains the unreduced
+    /// nodes of the smallest single entry single exit region enclosing them, in depth-first
+    /// preorder.
+    ImproperRegion,
+    /// Short-circuit condition of a loop, like `a && b`. This block contains the blocks computing
+    /// the condition in evaluation order, and each one of them can exit the loop.
+    Condition,
+    /// Early exit from the function, like `if (cond) return;`. This block contains the block
+    /// computing the condition followed by the block performing the exit.
+    Guard,
+    /// Marker for a jump from a loop to a block that is not the loop exit, like `goto label;`.
+    /// This block has no children and its offset is the offset of the basic block performing
+    /// the jump. Generated only by
+    /// [`CfsBuilder::preserve_loops`](crate::analysis::CfsBuilder::preserve_loops).
+    Goto,
+    /// Short-circuit condition of an if-then or if-then-else, like `a && b`. This block contains
+    /// the blocks computing the condition in evaluation order, and each one of them can skip the
+    /// then block. This block is the first child of a [`BlockType::IfThen`] or
+    /// [`BlockType::IfThenElse`]: when the condition is not short-circuited, the first child is
+    /// instead the block computing it.
+    IfChain,
+    /// Block executed only when a condition holds, like `if (a && !b) { ... }`. Generated only by
+    /// [`CfsBuilder::refine_conditions`](crate::analysis::CfsBuilder::refine_conditions) in
+    /// place of unstructured regions. This block has exactly one child, and its condition is
+    /// returned by [`StructureBlock::reaching_condition`].
+    Conditional,
+    /// Marker for a jump entering a loop in a block other than its header. This block has no
+    /// children and its offset is the offset of the basic block performing the jump, while the
+    /// jump is returned by [`StructureBlock::exits`]. Generated only by
+    /// [`annotate_abnormal_entries`](crate::analysis::CfsBuilder::annotate_abnormal_entries).
+    AbnormalEntry,
+    /// Basic block ending the function with a return. This block has exactly one child: the basic
+    /// block performing the return. Like [`BlockType::Duplicate`], it does not increase the depth
+    /// of the structure containing it. Generated only for the [`CFG`](crate::analysis::CFG)s
+    /// recording their terminators, see [`CFG::terminator`](crate::analysis::CFG::terminator).
+    Return,
+    /// Basic block ending the function with a trap or an invalid instruction, so the control flow
+    /// never continues after it. This block has exactly one child, as in [`BlockType::Return`].
+    Unreachable,
+    /// Marker for a jump from the body of a loop back to its header, like `continue;`. This block
+    /// has no children and its offset is the offset of the basic block performing the jump.
+    Continue,
+    /// Loop whose header is the head of a switch, like `while (true) { switch (x) { ... } }`,
+    /// where every case jumps back to the header and the header is the only block exiting the
+    /// loop. This block has exactly one child: the [`BlockType::Switch`], or a
+    /// [`BlockType::Sequence`] of the switch and the block joining its cases.
+    SwitchLoop,
+    /// Structure defined by the user, identified by an arbitrary number. Never generated by the
+    /// built-in reductions, but it can be emitted by a custom
+    /// [`Reduction`](crate::analysis::Reduction) to represent domain-specific structures, like a
+    /// virtual call dispatch. The children are not constrained and are treated as opaque by the
+    /// analyses, except for their order.
+    Custom(u16),
 }
 
 impl Display for BlockType {
@@ -32,17 +101,50 @@ impl Display for BlockType {
             BlockType::Switch => write!(f, "Switch"),
             BlockType::ProperInterval => write!(f, "Proper Interval"),
             BlockType::ImproperInterval => write!(f, "Improper Interval"),
+            BlockType::Break => write!(f, "Break"),
+            BlockType::Duplicate => write!(f, "Duplicate"),
+            BlockType::ImproperRegion => write!(f, "Improper Region"),
+            BlockType::Condition => write!(f, "Condition"),
+            BlockType::Guard => write!(f, "Guard"),
+            BlockType::Goto => write!(f, "Goto"),
+            BlockType::IfChain => write!(f, "If-Chain"),
+            BlockType::Conditional => write!(f, "Conditional"),
+            BlockType::AbnormalEntry => write!(f, "Abnormal Entry"),
+            BlockType::Return => write!(f, "Return"),
+            BlockType::Unreachable => write!(f, "Unreachable"),
+            BlockType::Continue => write!(f, "Continue"),
+            BlockType::SwitchLoop => write!(f, "Switch Loop"),
+            BlockType::Custom(id) => write!(f, "Custom({})", id),
         }
     }
 }
 
+/// Leaf of a [`StructureBlock`] tree.
+///
+/// The trees built by a [`CFS`](crate::analysis::CFS) have [`BasicBlock`] leaves, but any type can
+/// be a leaf, like the nodes of an intermediate representation structured with
+/// [`CfsBuilder::build_graph`](crate::analysis::CfsBuilder::build_graph) and replaced with
+/// [`StructureBlock::map_leaves`]. This trait is implemented for every type that can be cloned,
+/// compared and hashed.
+pub trait AbstractBlock: Clone + Debug + Eq + Hash {}
+
+impl<T: Clone + Debug + Eq + Hash> AbstractBlock for T {}
+
 /// A group of [`StructureBlock`] with the same [`BlockType`] label.
-#[derive(Debug, PartialEq, Eq, Hash)]
-pub struct NestedBlock {
+///
+/// The leaves are [`BasicBlock`]s unless a different [`AbstractBlock`] is given.
+#[derive(Debug, PartialEq, Eq)]
+pub struct NestedBlock<B = BasicBlock> {
     pub(crate) offset: u64,
     pub(crate) block_type: BlockType,
-    pub(crate) content: Vec<StructureBlock>,
+    pub(crate) content: Vec<StructureBlock<B>>,
     pub(crate) depth: u32,
+    // (exit block, target) pairs of the loops
+    pub(crate) exits: Vec<(B, B)>,
+    // true if the taken edge of the branch leads to the then/body block
+    pub(crate) polarity: Option<bool>,
+    // reaching condition of the Conditional blocks, in disjunctive normal form
+    pub(crate) condition: Vec<Vec<(B, bool)>>,
 }
 
 impl NestedBlock {
@@ -57,25 +159,100 @@ impl NestedBlock {
             block_type: label,
             content: children,
             depth: old_depth + 1,
+            exits: Vec::new(),
+            polarity: None,
+            condition: Vec::new(),
+        }
+    }
+
+    /// Sets the polarity of the condition of this block.
+    pub(crate) fn with_polarity(mut self, polarity: Option<bool>) -> NestedBlock {
+        self.polarity = polarity;
+        self
+    }
+
+    /// Creates a copy of the given block, wrapped in a [`BlockType::Duplicate`] block.
+    ///
+    /// Copies does not increase the depth of the structure containing them.
+    pub(crate) fn duplicate(original: &StructureBlock) -> NestedBlock {
+        NestedBlock {
+            offset: original.offset(),
+            block_type: BlockType::Duplicate,
+            content: vec![original.clone()],
+            depth: original.depth(),
+            exits: Vec::new(),
+            polarity: None,
+            condition: Vec::new(),
+        }
+    }
+
+    /// Wraps the given basic block, ending the function, into a [`BlockType::Return`] or
+    /// [`BlockType::Unreachable`] block.
+    ///
+    /// Like copies, terminators does not increase the depth of the structure containing them.
+    pub(crate) fn terminator(label: BlockType, bb: &BasicBlock) -> NestedBlock {
+        NestedBlock {
+            offset: bb.offset,
+            block_type: label,
+            content: vec![StructureBlock::from(*bb)],
+            depth: 0,
+            exits: Vec::new(),
+            polarity: None,
+            condition: Vec::new(),
+        }
+    }
+
+    /// Creates a new marker block: a block with no children pointing to a specific basic block.
+    ///
+    /// Markers does not increase the depth of the structure containing them.
+    pub(crate) fn marker(label: BlockType, location: &BasicBlock) -> NestedBlock {
+        NestedBlock {
+            offset: location.offset,
+            block_type: label,
+            content: Vec::new(),
+            depth: 0,
+            exits: Vec::new(),
+            polarity: None,
+            condition: Vec::new(),
         }
     }
 }
 
-impl Display for NestedBlock {
+// The hash does not visit the children, otherwise hashing a block would take time proportional to
+// the size of the whole tree.
+impl<B> Hash for NestedBlock<B> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.offset.hash(state);
+        self.block_type.hash(state);
+        self.depth.hash(state);
+        self.content.len().hash(state);
+    }
+}
+
+impl<B> Display for NestedBlock<B> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}_{}", self.block_type, self.offset)
     }
 }
 
-/// Contains either a [`BasicBlock`] or a [`NestedBlock`].
+/// Contains either a leaf or a [`NestedBlock`].
+///
+/// The leaves are [`BasicBlock`]s unless a different [`AbstractBlock`] is given: the analyses of
+/// the trees, like the serialization and the comparison, require [`BasicBlock`] leaves, while the
+/// other trees can only be visited.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub enum StructureBlock {
-    Basic(BasicBlock),
-    Nested(Arc<NestedBlock>),
+pub enum StructureBlock<B = BasicBlock> {
+    Basic(B),
+    Nested(Arc<NestedBlock<B>>),
 }
 
 impl Display for StructureBlock {
+    /// Writes the label of the block or, with the alternate flag (`{:#}`), the whole tree as
+    /// returned by [`StructureBlock::pretty`].
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if f.alternate() {
+            return write!(f, "{}", self.pretty());
+        }
         match self {
             StructureBlock::Basic(bb) => write!(f, "{}", bb),
             StructureBlock::Nested(n) => write!(f, "{}", n),
@@ -83,7 +260,7 @@ impl Display for StructureBlock {
     }
 }
 
-impl StructureBlock {
+impl<B> StructureBlock<B> {
     /// Returns the label of this block.
     pub fn block_type(&self) -> BlockType {
         match self {
@@ -103,38 +280,54 @@ impl StructureBlock {
     }
 
     /// Returns the children of this block.
-    pub fn children(&self) -> &[StructureBlock] {
+    pub fn children(&self) -> &[StructureBlock<B>] {
         match self {
             StructureBlock::Basic(_) => &[],
             StructureBlock::Nested(nb) => nb.content.as_slice(),
         }
     }
 
-    /// Calculate a unique hash for this block that does not account for basic block offsets.
-    pub fn structural_hash(&self, state: &mut DefaultHasher) {
-        self.children()
-            .iter()
-            .for_each(|x| x.structural_hash(state));
-        self.block_type().hash(state);
+    /// Returns the exits of this block, as pairs of (exiting block, target of the exit).
+    ///
+    /// Only loops have exits: every edge from a basic block inside the loop to a basic block
+    /// outside of it in the original [`CFG`](crate::analysis::CFG) is reported, sorted by
+    /// exiting block. This includes the exits removed while structuring the loop, that are
+    /// otherwise represented only by [`BlockType::Break`] markers.
+    ///
+    /// Markers created by [`preserve_loops`](crate::analysis::CfsBuilder::preserve_loops) or
+    /// [`annotate_abnormal_entries`](crate::analysis::CfsBuilder::annotate_abnormal_entries) report
+    /// the edge they represent.
+    pub fn exits(&self) -> &[(B, B)] {
+        match self {
+            StructureBlock::Basic(_) => &[],
+            StructureBlock::Nested(nb) => nb.exits.as_slice(),
+        }
     }
 
-    /// Checks if two blocks have the same structure (does not check for basic blocks equality).
-    pub fn structural_equality(&self, b: &StructureBlock) -> bool {
-        if self.block_type() == b.block_type() {
-            let children_a = self.children();
-            let children_b = b.children();
-            if children_a.is_empty() && children_b.is_empty() {
-                true //basic block
-            } else if children_a.len() == children_b.len() {
-                children_a
-                    .iter()
-                    .zip(children_b.iter())
-                    .fold(true, |acc, (a, b)| acc & a.structural_equality(b))
-            } else {
-                false
-            }
-        } else {
-            false
+    /// Returns the polarity of the condition deciding whether the then or body block is executed.
+    ///
+    /// For [`BlockType::IfThen`], [`BlockType::IfThenElse`] and [`BlockType::While`] blocks
+    /// created by a [`CFS`](crate::analysis::CFS), returns true if the block enters the then (or
+    /// body) block when its jump is taken, and false if it enters it by falling through, i.e. the
+    /// condition must be negated. Returns [`None`] for the other blocks, for blocks created
+    /// manually and when the condition is composed of more than one block.
+    pub fn condition_polarity(&self) -> Option<bool> {
+        match self {
+            StructureBlock::Basic(_) => None,
+            StructureBlock::Nested(nb) => nb.polarity,
+        }
+    }
+
+    /// Returns the condition under which a [`BlockType::Conditional`] block is executed.
+    ///
+    /// The condition is in disjunctive normal form: the block is executed if all the literals of
+    /// any of the returned clauses hold. Each literal is a basic block ending with a conditional
+    /// jump, and is true if the jump must be taken or false if the jump must not be taken.
+    /// Returns an empty slice for the other blocks.
+    pub fn reaching_condition(&self) -> &[Vec<(B, bool)>] {
+        match self {
+            StructureBlock::Basic(_) => &[],
+            StructureBlock::Nested(nb) => nb.condition.as_slice(),
         }
     }
 
@@ -148,6 +341,144 @@ impl StructureBlock {
         self.children().is_empty()
     }
 
+    /// Returns a copy of this tree with every leaf replaced by the result of the given function.
+    ///
+    /// The leaves in the exits and in the reaching conditions are replaced as well, while the
+    /// offsets of the nested blocks are kept. This allows replacing the synthetic basic blocks
+    /// of a structured graph with its original nodes.
+    /// # Examples
+    /// Basic usage:
+    /// ```
+    /// use bincc::analysis::{BasicBlock, BlockType, NestedBlock, StructureBlock};
+    /// use std::sync::Arc;
+    ///
+    /// let bb0 = StructureBlock::from(BasicBlock { offset: 0, length: 1 });
+    /// let bb1 = StructureBlock::from(BasicBlock { offset: 1, length: 1 });
+    /// let seq = NestedBlock::new(BlockType::Sequence, vec![bb0, bb1]);
+    /// let names = ["head", "tail"];
+    /// let tree = StructureBlock::from(Arc::new(seq)).map_leaves(|bb| names[bb.offset as usize]);
+    ///
+    /// assert_eq!(tree.block_type(), BlockType::Sequence);
+    /// assert_eq!(tree.children()[1], StructureBlock::Basic("tail"));
+    /// ```
+    pub fn map_leaves<C: AbstractBlock, F: FnMut(&B) -> C>(&self, mut map: F) -> StructureBlock<C> {
+        map_tree(self, &mut map)
+    }
+}
+
+impl StructureBlock {
+    /// Calculate a hash of the structure of this block.
+    ///
+    /// The hash depends only on the block types and the amount of children of each block in
+    /// the tree, visited in preorder, so basic block offsets and lengths are ignored. The hash is
+    /// stable, meaning that the same structure always results in the same value, even across
+    /// different executions or platforms.
+    /// # Examples
+    /// Basic usage:
+    /// ```
+    /// use bincc::analysis::{BasicBlock, BlockType, NestedBlock, StructureBlock};
+    /// use std::sync::Arc;
+    ///
+    /// let bb0 = StructureBlock::from(BasicBlock { offset: 0x10, length: 4 });
+    /// let bb1 = StructureBlock::from(BasicBlock { offset: 0x20, length: 8 });
+    /// let seq0 = NestedBlock::new(BlockType::Sequence, vec![bb0.clone(), bb1.clone()]);
+    /// let seq1 = NestedBlock::new(BlockType::Sequence, vec![bb1, bb0]);
+    /// let hash0 = StructureBlock::from(Arc::new(seq0)).structural_hash();
+    /// let hash1 = StructureBlock::from(Arc::new(seq1)).structural_hash();
+    ///
+    /// assert_eq!(hash0, hash1);
+    /// ```
+    pub fn structural_hash(&self) -> u64 {
+        let mut hasher = FnvHasher::default();
+        for node in self.iter_preorder() {
+            // fixed endianness, so the hash does not depend on the platform
+            hash_type(&mut hasher, node.block_type());
+            hasher.write(&(node.len() as u32).to_le_bytes());
+        }
+        hasher.finish()
+    }
+
+    /// Checks if two blocks have the same structure (does not check for basic blocks equality).
+    pub fn structural_equality(&self, b: &StructureBlock) -> bool {
+        self.structural_cmp(b) == Ordering::Equal
+    }
+
+    /// Enumerates the rooted subtrees of this tree up to the given depth, returning their hashes
+    /// along with the amount of times each one appears.
+    ///
+    /// For every block of the tree, the subtrees rooted in the block and containing its
+    /// descendants up to `depth` levels below it are considered, from the block alone up to its
+    /// whole subtree, if not deeper than `depth`. As in [`StructureBlock::structural_hash`], the
+    /// hashes depend only on the block types and the amount of children, are stable, and
+    /// identical subtrees have the same hash regardless of their position in the tree. The
+    /// resulting multiset can be used as a feature vector to search similar trees.
+    /// # Examples
+    /// Basic usage:
+    /// ```
+    /// use bincc::analysis::{BasicBlock, BlockType, NestedBlock, StructureBlock};
+    /// use std::sync::Arc;
+    ///
+    /// let bb0 = StructureBlock::from(BasicBlock { offset: 0x10, length: 4 });
+    /// let bb1 = StructureBlock::from(BasicBlock { offset: 0x20, length: 8 });
+    /// let seq = NestedBlock::new(BlockType::Sequence, vec![bb0, bb1]);
+    /// let features = StructureBlock::from(Arc::new(seq)).subtree_features(1);
+    ///
+    /// // the two basic blocks, the sequence alone, and the whole tree
+    /// assert_eq!(features.len(), 3);
+    /// assert_eq!(features.values().sum::<usize>(), 4);
+    /// ```
+    pub fn subtree_features(&self, depth: usize) -> HashMap<u64, usize> {
+        let mut features = HashMap::new();
+        truncated_hashes(self, depth, &mut features);
+        features
+    }
+
+    /// Compares the structure of two blocks, ignoring the basic blocks they contain.
+    ///
+    /// The trees are visited in preorder and compared by block type first and amount of children
+    /// later, so two blocks are equal for this ordering only if they have the same shape and the
+    /// same [`BlockType`] in every position. This ordering is consistent with
+    /// [`StructureBlock::structural_hash`] and [`StructureBlock::structural_equality`].
+    /// # Examples
+    /// Basic usage:
+    /// ```
+    /// use bincc::analysis::{BasicBlock, BlockType, NestedBlock, StructureBlock};
+    /// use std::cmp::Ordering;
+    /// use std::sync::Arc;
+    ///
+    /// let bb0 = StructureBlock::from(BasicBlock { offset: 0x10, length: 4 });
+    /// let bb1 = StructureBlock::from(BasicBlock { offset: 0x20, length: 8 });
+    /// let seq = NestedBlock::new(BlockType::Sequence, vec![bb0.clone(), bb1.clone()]);
+    /// let ifthen = NestedBlock::new(BlockType::IfThen, vec![bb1, bb0]);
+    /// let seq = StructureBlock::from(Arc::new(seq));
+    /// let ifthen = StructureBlock::from(Arc::new(ifthen));
+    ///
+    /// assert_eq!(seq.structural_cmp(&ifthen), Ordering::Less);
+    /// assert_eq!(seq.structural_cmp(&seq), Ordering::Equal);
+    /// ```
+    pub fn structural_cmp(&self, other: &StructureBlock) -> Ordering {
+        let key = |node: &StructureBlock| (node.block_type(), node.len());
+        self.iter_preorder()
+            .map(key)
+            .cmp(other.iter_preorder().map(key))
+    }
+
+    /// Compares two blocks, including the basic blocks they contain.
+    ///
+    /// This is equivalent to [`StructureBlock::structural_cmp`], except that basic blocks in the
+    /// same position are compared by their offset and length. Two blocks are equal for this
+    /// ordering only if they have the same structure and contain the same basic blocks in the
+    /// same positions.
+    pub fn strict_cmp(&self, other: &StructureBlock) -> Ordering {
+        let key = |node: &StructureBlock| match node {
+            StructureBlock::Basic(bb) => (node.block_type(), node.len(), Some(*bb)),
+            StructureBlock::Nested(_) => (node.block_type(), node.len(), None),
+        };
+        self.iter_preorder()
+            .map(key)
+            .cmp(other.iter_preorder().map(key))
+    }
+
     /// Returns a string representing this block type.
     pub fn get_type_name(&self) -> &'static str {
         let bt = match self {
@@ -165,6 +496,20 @@ impl StructureBlock {
             BlockType::Switch => "Switch",
             BlockType::ProperInterval => "Proper Interval",
             BlockType::ImproperInterval => "Improper Interval",
+            BlockType::Break => "Break",
+            BlockType::Duplicate => "Duplicate",
+            BlockType::ImproperRegion => "Improper Region",
+            BlockType::Condition => "Condition",
+            BlockType::Guard => "Guard",
+            BlockType::Goto => "Goto",
+            BlockType::IfChain => "If-Chain",
+            BlockType::Conditional => "Conditional",
+            BlockType::AbnormalEntry => "Abnormal Entry",
+            BlockType::Return => "Return",
+            BlockType::Unreachable => "Unreachable",
+            BlockType::Continue => "Continue",
+            BlockType::SwitchLoop => "Switch Loop",
+            BlockType::Custom(_) => "Custom",
         }
     }
 
@@ -176,19 +521,260 @@ impl StructureBlock {
         }
     }
 
+    /// Returns the C-like pseudocode of this block.
+    ///
+    /// This is equivalent to calling [`PseudocodeWriter::write`] on a default writer.
+    pub fn to_pseudocode(&self) -> String {
+        PseudocodeWriter::new().write(self)
+    }
+
+    /// Returns an indented outline of this tree.
+    ///
+    /// Each nested block is printed as its type followed by its children enclosed in braces,
+    /// while each basic block is printed as the range of addresses it covers. Markers are printed
+    /// as their type followed by the offset of the basic block they refer to. The same outline is
+    /// obtained by formatting the block with `{:#}`.
+    /// # Examples
+    /// Basic usage:
+    /// ```
+    /// use bincc::analysis::{BasicBlock, BlockType, NestedBlock, StructureBlock};
+    /// use std::sync::Arc;
+    ///
+    /// let bb0 = StructureBlock::from(BasicBlock { offset: 0x10, length: 4 });
+    /// let bb1 = StructureBlock::from(BasicBlock { offset: 0x14, length: 8 });
+    /// let ifthen = NestedBlock::new(BlockType::IfThen, vec![bb0, bb1]);
+    /// let outline = StructureBlock::from(Arc::new(ifthen)).pretty();
+    ///
+    /// assert_eq!(outline, "If-Then {\n    0x10..0x14\n    0x14..0x1c\n}\n");
+    /// ```
+    pub fn pretty(&self) -> String {
+        let mut out = String::new();
+        pretty_rec(self, 0, &mut out);
+        out
+    }
+
     /// Returns the list of basic blocks contained in this cluster, ordered by offset.
     pub fn basic_blocks(&self) -> Vec<BasicBlock> {
-        let mut retval = Vec::new();
-        let mut stack = vec![self];
-        while let Some(node) = stack.pop() {
-            if let StructureBlock::Basic(bb) = node {
-                retval.push(*bb);
+        let mut retval = self
+            .iter_preorder()
+            .filter_map(|node| match node {
+                StructureBlock::Basic(bb) => Some(*bb),
+                StructureBlock::Nested(_) => None,
+            })
+            .collect::<Vec<_>>();
+        retval.sort_unstable();
+        retval
+    }
+
+    /// Returns the address ranges covered by the basic blocks contained in this cluster.
+    ///
+    /// Ranges are half-open, sorted, and adjacent or overlapping ranges are merged together.
+    /// Artificial blocks, having no length, do not cover any address.
+    /// # Examples
+    /// Basic usage:
+    /// ```
+    /// use bincc::analysis::{BasicBlock, BlockType, NestedBlock, StructureBlock};
+    /// use std::sync::Arc;
+    ///
+    /// let bb0 = StructureBlock::from(BasicBlock { offset: 0x10, length: 4 });
+    /// let bb1 = StructureBlock::from(BasicBlock { offset: 0x14, length: 8 });
+    /// let bb2 = StructureBlock::from(BasicBlock { offset: 0x30, length: 2 });
+    /// let seq = NestedBlock::new(BlockType::Sequence, vec![bb0, bb1, bb2]);
+    /// let ranges = StructureBlock::from(Arc::new(seq)).address_ranges();
+    ///
+    /// assert_eq!(ranges, vec![0x10..0x1C, 0x30..0x32]);
+    /// ```
+    pub fn address_ranges(&self) -> Vec<Range<u64>> {
+        let mut ranges = Vec::<Range<u64>>::new();
+        for bb in self.basic_blocks().into_iter().filter(|bb| bb.length > 0) {
+            let end = bb.offset.saturating_add(bb.length);
+            match ranges.last_mut() {
+                Some(last) if last.end >= bb.offset => last.end = last.end.max(end),
+                _ => ranges.push(bb.offset..end),
+            }
+        }
+        ranges
+    }
+
+    /// Returns true if any basic block contained in this cluster covers the given address.
+    pub fn contains_address(&self, address: u64) -> bool {
+        self.iter_preorder().any(|node| match node {
+            StructureBlock::Basic(bb) => bb.offset <= address && address - bb.offset < bb.length,
+            StructureBlock::Nested(_) => false,
+        })
+    }
+
+    /// Returns the metrics of the tree rooted in this block.
+    /// # Examples
+    /// Basic usage:
+    /// ```
+    /// use bincc::analysis::{BasicBlock, BlockType, NestedBlock, StructureBlock};
+    /// use std::sync::Arc;
+    ///
+    /// let bb0 = StructureBlock::from(BasicBlock { offset: 0x10, length: 4 });
+    /// let bb1 = StructureBlock::from(BasicBlock { offset: 0x20, length: 8 });
+    /// let seq = NestedBlock::new(BlockType::Sequence, vec![bb0, bb1]);
+    /// let metrics = StructureBlock::from(Arc::new(seq)).metrics();
+    ///
+    /// assert_eq!(metrics.nodes(), 3);
+    /// assert_eq!(metrics.leaves(), 2);
+    /// assert_eq!(metrics.count(BlockType::Sequence), 1);
+    /// assert_eq!(metrics.max_sequence_len(), 2);
+    /// ```
+    pub fn metrics(&self) -> TreeMetrics {
+        let mut metrics = TreeMetrics {
+            depth: self.depth(),
+            ..Default::default()
+        };
+        for node in self.iter_preorder() {
+            metrics.nodes += 1;
+            if node.is_empty() {
+                metrics.leaves += 1;
+            }
+            if node.block_type() == BlockType::Sequence {
+                metrics.max_sequence = metrics.max_sequence.max(node.len());
+            }
+            *metrics.blocks.entry(node.block_type()).or_insert(0) += 1;
+        }
+        metrics
+    }
+}
+
+impl<B> StructureBlock<B> {
+    /// Returns an iterator visiting this block and all its descendants in preorder.
+    ///
+    /// Every node is reported before its children, and children are visited in order.
+    /// # Examples
+    /// Basic usage:
+    /// ```
+    /// use bincc::analysis::{BasicBlock, BlockType, NestedBlock, StructureBlock};
+    /// use std::sync::Arc;
+    ///
+    /// let bb0 = StructureBlock::from(BasicBlock { offset: 0x10, length: 4 });
+    /// let bb1 = StructureBlock::from(BasicBlock { offset: 0x20, length: 8 });
+    /// let seq = NestedBlock::new(BlockType::Sequence, vec![bb0, bb1]);
+    /// let tree = StructureBlock::from(Arc::new(seq));
+    /// let offsets = tree.iter_preorder().map(|x| x.offset()).collect::<Vec<_>>();
+    ///
+    /// assert_eq!(offsets, vec![0x10, 0x10, 0x20]);
+    /// ```
+    pub fn iter_preorder(&self) -> StructurePreIter<'_, B> {
+        StructurePreIter { stack: vec![self] }
+    }
+
+    /// Returns an iterator visiting this block and all its descendants in postorder.
+    ///
+    /// Every node is reported after its children, and children are visited in order.
+    /// # Examples
+    /// Basic usage:
+    /// ```
+    /// use bincc::analysis::{BasicBlock, BlockType, NestedBlock, StructureBlock};
+    /// use std::sync::Arc;
+    ///
+    /// let bb0 = StructureBlock::from(BasicBlock { offset: 0x10, length: 4 });
+    /// let bb1 = StructureBlock::from(BasicBlock { offset: 0x20, length: 8 });
+    /// let seq = NestedBlock::new(BlockType::Sequence, vec![bb0, bb1]);
+    /// let tree = StructureBlock::from(Arc::new(seq));
+    /// let types = tree
+    ///     .iter_postorder()
+    ///     .map(|x| x.block_type())
+    ///     .collect::<Vec<_>>();
+    ///
+    /// assert_eq!(
+    ///     types,
+    ///     vec![BlockType::Basic, BlockType::Basic, BlockType::Sequence]
+    /// );
+    /// ```
+    pub fn iter_postorder(&self) -> StructurePostIter<'_, B> {
+        StructurePostIter {
+            stack: vec![(self, 0)],
+        }
+    }
+}
+
+/// Metrics about the shape of a [`StructureBlock`] tree.
+///
+/// This struct is created by the [`StructureBlock::metrics`] method.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TreeMetrics {
+    depth: u32,
+    nodes: usize,
+    leaves: usize,
+    blocks: HashMap<BlockType, usize>,
+    max_sequence: usize,
+}
+
+impl TreeMetrics {
+    /// Returns the depth of the tree, as in [`StructureBlock::depth`].
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    /// Returns the amount of nodes in the tree, including the root.
+    pub fn nodes(&self) -> usize {
+        self.nodes
+    }
+
+    /// Returns the amount of nodes without children in the tree.
+    ///
+    /// These are the basic blocks and the markers, like [`BlockType::Break`].
+    pub fn leaves(&self) -> usize {
+        self.leaves
+    }
+
+    /// Returns the amount of nodes of the given type in the tree.
+    pub fn count(&self, block_type: BlockType) -> usize {
+        *self.blocks.get(&block_type).unwrap_or(&0)
+    }
+
+    /// Returns the amount of children of the longest [`BlockType::Sequence`] in the tree.
+    ///
+    /// If the tree contains no sequences, 0 is returned.
+    pub fn max_sequence_len(&self) -> usize {
+        self.max_sequence
+    }
+}
+
+/// Iterator visiting a [`StructureBlock`] tree in preorder.
+///
+/// This struct is created by the [`StructureBlock::iter_preorder`] method.
+pub struct StructurePreIter<'a, B = BasicBlock> {
+    stack: Vec<&'a StructureBlock<B>>,
+}
+
+impl<'a, B> Iterator for StructurePreIter<'a, B> {
+    type Item = &'a StructureBlock<B>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        self.stack.extend(node.children().iter().rev());
+        Some(node)
+    }
+}
+
+/// Iterator visiting a [`StructureBlock`] tree in postorder.
+///
+/// This struct is created by the [`StructureBlock::iter_postorder`] method.
+pub struct StructurePostIter<'a, B = BasicBlock> {
+    // nodes being visited, with the index of the next child to visit.
+    stack: Vec<(&'a StructureBlock<B>, usize)>,
+}
+
+impl<'a, B> Iterator for StructurePostIter<'a, B> {
+    type Item = &'a StructureBlock<B>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((node, next_child)) = self.stack.last_mut() {
+            let node = *node;
+            if let Some(child) = node.children().get(*next_child) {
+                *next_child += 1;
+                self.stack.push((child, 0));
             } else {
-                stack.extend(node.children());
+                self.stack.pop();
+                return Some(node);
             }
         }
-        retval.sort_unstable();
-        retval
+        None
     }
 }
 
@@ -198,28 +784,136 @@ impl From<BasicBlock> for StructureBlock {
     }
 }
 
-impl From<Arc<NestedBlock>> for StructureBlock {
-    fn from(nb: Arc<NestedBlock>) -> Self {
+impl<B> From<Arc<NestedBlock<B>>> for StructureBlock<B> {
+    fn from(nb: Arc<NestedBlock<B>>) -> Self {
         StructureBlock::Nested(nb)
     }
 }
 
+fn pretty_rec(node: &StructureBlock, lv: usize, out: &mut String) {
+    for _ in 0..lv {
+        out.push_str("    ");
+    }
+    match node {
+        StructureBlock::Basic(bb) if bb.is_entry_point() => out.push_str("entry\n"),
+        StructureBlock::Basic(bb) if bb.is_sink() => out.push_str("sink\n"),
+        StructureBlock::Basic(bb) => out.push_str(&format!(
+            "{:#x}..{:#x}\n",
+            bb.offset,
+            bb.offset.saturating_add(bb.length)
+        )),
+        StructureBlock::Nested(nb) if nb.content.is_empty() => {
+            out.push_str(&format!("{} @{:#x}\n", nb.block_type, nb.offset))
+        }
+        StructureBlock::Nested(nb) => {
+            out.push_str(&format!("{} {{\n", nb.block_type));
+            for child in &nb.content {
+                pretty_rec(child, lv + 1, out);
+            }
+            for _ in 0..lv {
+                out.push_str("    ");
+            }
+            out.push_str("}\n");
+        }
+    }
+}
+
+// copy of the tree rooted in the node with the leaves replaced by the given function.
+fn map_tree<B, C>(node: &StructureBlock<B>, map: &mut impl FnMut(&B) -> C) -> StructureBlock<C> {
+    match node {
+        StructureBlock::Basic(leaf) => StructureBlock::Basic(map(leaf)),
+        StructureBlock::Nested(nb) => {
+            let content = nb
+                .content
+                .iter()
+                .map(|child| map_tree(child, map))
+                .collect();
+            let exits = nb
+                .exits
+                .iter()
+                .map(|(src, dst)| (map(src), map(dst)))
+                .collect();
+            let condition = nb
+                .condition
+                .iter()
+                .map(|clause| {
+                    clause
+                        .iter()
+                        .map(|(leaf, taken)| (map(leaf), *taken))
+                        .collect()
+                })
+                .collect();
+            StructureBlock::Nested(Arc::new(NestedBlock {
+                offset: nb.offset,
+                block_type: nb.block_type,
+                content,
+                depth: nb.depth,
+                exits,
+                polarity: nb.polarity,
+                condition,
+            }))
+        }
+    }
+}
+
+// writes the block type into an hash, using the same tags of the serialized trees so the value
+// does not depend on the platform.
+fn hash_type(hasher: &mut FnvHasher, block_type: BlockType) {
+    hasher.write(&[type_tag(block_type)]);
+    if let BlockType::Custom(id) = block_type {
+        hasher.write(&id.to_le_bytes());
+    }
+}
+
+// hashes of the subtrees rooted in the node and truncated at every depth up to the given one,
+// stopping at the first depth containing the whole subtree. Every hash is also recorded in the
+// features.
+fn truncated_hashes(
+    node: &StructureBlock,
+    depth: usize,
+    features: &mut HashMap<u64, usize>,
+) -> Vec<u64> {
+    let children = node
+        .children()
+        .iter()
+        .map(|child| truncated_hashes(child, depth, features))
+        .collect::<Vec<_>>();
+    let height = children
+        .iter()
+        .map(|hashes| hashes.len())
+        .max()
+        .unwrap_or(0);
+    let hashes = (0..=height.min(depth))
+        .map(|level| {
+            let mut hasher = FnvHasher::default();
+            hash_type(&mut hasher, node.block_type());
+            if level == 0 && !children.is_empty() {
+                // the children are cut away
+                hasher.write(&u32::MAX.to_le_bytes());
+            } else {
+                hasher.write(&(children.len() as u32).to_le_bytes());
+                for child in &children {
+                    hasher.write_u64(child[(level - 1).min(child.len() - 1)]);
+                }
+            }
+            hasher.finish()
+        })
+        .collect::<Vec<_>>();
+    for hash in &hashes {
+        *features.entry(*hash).or_insert(0) += 1;
+    }
+    hashes
+}
+
 #[cfg(test)]
 mod tests {
     use crate::analysis::blocks::StructureBlock;
-    use crate::analysis::{BasicBlock, BlockType, NestedBlock};
-    use std::collections::hash_map::DefaultHasher;
-    use std::hash::Hasher;
+    use crate::analysis::{BasicBlock, BlockType, NestedBlock, StructureRewriter};
+    use std::cmp::Ordering;
     use std::sync::Arc;
 
     fn calculate_hashes(a: StructureBlock, b: StructureBlock) -> (u64, u64) {
-        let mut hasher0 = DefaultHasher::new();
-        a.structural_hash(&mut hasher0);
-        let hash0 = hasher0.finish();
-        let mut hasher1 = DefaultHasher::new();
-        b.structural_hash(&mut hasher1);
-        let hash1 = hasher1.finish();
-        (hash0, hash1)
+        (a.structural_hash(), b.structural_hash())
     }
 
     #[test]
@@ -252,6 +946,31 @@ mod tests {
         assert!(sb0.structural_equality(&sb1));
     }
 
+    #[test]
+    fn subtree_features() {
+        let bb = |offset| StructureBlock::from(BasicBlock { offset, length: 1 });
+        let nest = |bt, children| StructureBlock::from(Arc::new(NestedBlock::new(bt, children)));
+        let whileb = nest(BlockType::While, vec![bb(1), bb(2)]);
+        let a = nest(BlockType::Sequence, vec![bb(0), whileb.clone()]);
+        let b = nest(BlockType::IfThen, vec![bb(3), whileb.clone()]);
+        // depth 0: the block types
+        let types = a.subtree_features(0);
+        assert_eq!(types.len(), 3);
+        assert_eq!(types.values().sum::<usize>(), 5);
+        // the whole loop is shared, as its depth is 1
+        let fa = a.subtree_features(1);
+        let fb = b.subtree_features(1);
+        let loop_hash = whileb.subtree_features(1);
+        assert!(loop_hash
+            .keys()
+            .all(|h| fa.contains_key(h) && fb.contains_key(h)));
+        let shared = fa.keys().filter(|h| fb.contains_key(h)).count();
+        assert_eq!(shared, 3);
+        // deeper limits do not add anything to a tree of depth 2
+        assert_eq!(a.subtree_features(2), a.subtree_features(10));
+        assert_eq!(a.subtree_features(2).values().sum::<usize>(), 8);
+    }
+
     #[test]
     fn structural_hash_different_id() {
         let bb0 = StructureBlock::from(BasicBlock {
@@ -292,6 +1011,54 @@ mod tests {
         assert_eq!(hashes.0, hashes.1)
     }
 
+    #[test]
+    fn structural_hash_arity() {
+        // same types in preorder, different shape: Seq(Seq(bb, bb), bb) vs Seq(Seq(bb), bb, bb)
+        let bb = StructureBlock::from(BasicBlock {
+            offset: 1,
+            length: 1,
+        });
+        let inner0 = StructureBlock::from(Arc::new(NestedBlock::new(
+            BlockType::Sequence,
+            vec![bb.clone(), bb.clone()],
+        )));
+        let sequence0 = StructureBlock::from(Arc::new(NestedBlock::new(
+            BlockType::Sequence,
+            vec![inner0, bb.clone()],
+        )));
+        let inner1 = StructureBlock::from(Arc::new(NestedBlock::new(
+            BlockType::Sequence,
+            vec![bb.clone()],
+        )));
+        let sequence1 = StructureBlock::from(Arc::new(NestedBlock::new(
+            BlockType::Sequence,
+            vec![inner1, bb.clone(), bb],
+        )));
+        let hashes = calculate_hashes(sequence0, sequence1);
+        assert_ne!(hashes.0, hashes.1)
+    }
+
+    #[test]
+    fn structural_hash_stable() {
+        let bb = StructureBlock::from(BasicBlock {
+            offset: 1,
+            length: 1,
+        });
+        let self_loop =
+            StructureBlock::from(Arc::new(NestedBlock::new(BlockType::SelfLooping, vec![bb])));
+        // FNV-1a of [Basic, 0] and [SelfLooping, 1, Basic, 0], with u32 little-endian arity
+        let expected_bb = fnv1a(&[0, 0, 0, 0, 0]);
+        let expected_loop = fnv1a(&[1, 1, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(self_loop.children()[0].structural_hash(), expected_bb);
+        assert_eq!(self_loop.structural_hash(), expected_loop);
+    }
+
+    fn fnv1a(bytes: &[u8]) -> u64 {
+        bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+            (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+        })
+    }
+
     #[test]
     fn structural_equality_same_order() {
         let bb = StructureBlock::from(BasicBlock {
@@ -400,4 +1167,182 @@ mod tests {
         assert_eq!(bbs[2].offset, 100);
         assert_eq!(bbs[3].offset, 1000);
     }
+
+    fn nested_tree() -> StructureBlock {
+        let bbs = [1, 10, 100, 1000]
+            .into_iter()
+            .map(|offset| StructureBlock::from(BasicBlock { offset, length: 1 }))
+            .collect::<Vec<_>>();
+        let ifelse = StructureBlock::from(Arc::new(NestedBlock::new(
+            BlockType::IfThenElse,
+            bbs[1..].to_vec(),
+        )));
+        StructureBlock::from(Arc::new(NestedBlock::new(
+            BlockType::Sequence,
+            vec![bbs[0].clone(), ifelse],
+        )))
+    }
+
+    #[test]
+    fn pretty() {
+        let tree = nested_tree();
+        let expected = "Sequence {\n    0x1..0x2\n    If-Then-Else {\n        0xa..0xb\n        \
+                        0x64..0x65\n        0x3e8..0x3e9\n    }\n}\n";
+        assert_eq!(tree.pretty(), expected);
+        assert_eq!(format!("{:#}", tree), expected);
+        assert_eq!(format!("{}", tree), "Sequence_1");
+    }
+
+    #[test]
+    fn pretty_markers() {
+        let bb0 = BasicBlock {
+            offset: 0x20,
+            length: 4,
+        };
+        let marker = StructureBlock::from(Arc::new(NestedBlock::marker(BlockType::Break, &bb0)));
+        let sink = StructureBlock::from(BasicBlock::new_sink());
+        let tree = StructureBlock::from(Arc::new(NestedBlock::new(
+            BlockType::SelfLooping,
+            vec![StructureBlock::from(bb0), marker, sink],
+        )));
+        let expected = "Self Loop {\n    0x20..0x24\n    Break @0x20\n    sink\n}\n";
+        assert_eq!(tree.pretty(), expected);
+    }
+
+    struct Shift(u64);
+
+    impl StructureRewriter for Shift {
+        fn leave_block(&mut self, block: StructureBlock, _: usize) -> StructureBlock {
+            match block {
+                StructureBlock::Basic(bb) => StructureBlock::from(BasicBlock {
+                    offset: bb.offset + self.0,
+                    length: bb.length,
+                }),
+                nested => nested,
+            }
+        }
+    }
+
+    #[test]
+    fn iter_preorder() {
+        let tree = nested_tree();
+        let visited = tree
+            .iter_preorder()
+            .map(|node| (node.block_type(), node.offset()))
+            .collect::<Vec<_>>();
+        let expected = vec![
+            (BlockType::Sequence, 1),
+            (BlockType::Basic, 1),
+            (BlockType::IfThenElse, 10),
+            (BlockType::Basic, 10),
+            (BlockType::Basic, 100),
+            (BlockType::Basic, 1000),
+        ];
+        assert_eq!(visited, expected);
+    }
+
+    #[test]
+    fn iter_postorder() {
+        let tree = nested_tree();
+        let visited = tree
+            .iter_postorder()
+            .map(|node| (node.block_type(), node.offset()))
+            .collect::<Vec<_>>();
+        let expected = vec![
+            (BlockType::Basic, 1),
+            (BlockType::Basic, 10),
+            (BlockType::Basic, 100),
+            (BlockType::Basic, 1000),
+            (BlockType::IfThenElse, 10),
+            (BlockType::Sequence, 1),
+        ];
+        assert_eq!(visited, expected);
+    }
+
+    #[test]
+    fn address_ranges() {
+        let tree = nested_tree();
+        assert_eq!(
+            tree.address_ranges(),
+            vec![1..2, 10..11, 100..101, 1000..1001]
+        );
+        let bbs = [(0x10, 4), (0x18, 4), (0x14, 4), (0x1A, 1)]
+            .into_iter()
+            .map(|(offset, length)| StructureBlock::from(BasicBlock { offset, length }))
+            .chain(std::iter::once(
+                StructureBlock::from(BasicBlock::new_sink()),
+            ))
+            .collect();
+        let seq = StructureBlock::from(Arc::new(NestedBlock::new(BlockType::Sequence, bbs)));
+        assert_eq!(seq.address_ranges(), vec![0x10..0x1C]);
+    }
+
+    #[test]
+    fn contains_address() {
+        let tree = nested_tree();
+        assert!(tree.contains_address(1));
+        assert!(tree.contains_address(100));
+        assert!(!tree.contains_address(2));
+        assert!(!tree.children()[1].contains_address(1));
+    }
+
+    #[test]
+    fn structural_cmp_ignores_leaves() {
+        let tree = nested_tree();
+        let shifted = nested_tree().rewrite(&mut Shift(0x100));
+        assert_ne!(tree, shifted);
+        assert_eq!(tree.structural_cmp(&shifted), Ordering::Equal);
+        assert_eq!(tree.strict_cmp(&shifted), Ordering::Less);
+        assert_eq!(shifted.strict_cmp(&tree), Ordering::Greater);
+        assert_eq!(tree.strict_cmp(&nested_tree()), Ordering::Equal);
+    }
+
+    #[test]
+    fn structural_cmp_shape() {
+        let tree = nested_tree();
+        let ifelse = tree.children()[1].clone();
+        // a sequence of 2 elements is smaller than an if-then-else, that is declared later
+        assert_eq!(tree.structural_cmp(&ifelse), Ordering::Less);
+        let bb = StructureBlock::from(BasicBlock {
+            offset: 1,
+            length: 1,
+        });
+        let seq3 = StructureBlock::from(Arc::new(NestedBlock::new(
+            BlockType::Sequence,
+            vec![bb.clone(), bb.clone(), bb],
+        )));
+        // same type at the root, but more children
+        assert_eq!(tree.structural_cmp(&seq3), Ordering::Less);
+        assert_eq!(seq3.structural_cmp(&tree), Ordering::Greater);
+    }
+
+    #[test]
+    fn metrics() {
+        let metrics = nested_tree().metrics();
+        assert_eq!(metrics.depth(), 2);
+        assert_eq!(metrics.nodes(), 6);
+        assert_eq!(metrics.leaves(), 4);
+        assert_eq!(metrics.count(BlockType::Basic), 4);
+        assert_eq!(metrics.count(BlockType::IfThenElse), 1);
+        assert_eq!(metrics.count(BlockType::While), 0);
+        assert_eq!(metrics.max_sequence_len(), 2);
+        let bb = StructureBlock::from(BasicBlock {
+            offset: 1,
+            length: 1,
+        });
+        let metrics = bb.metrics();
+        assert_eq!(metrics.depth(), 0);
+        assert_eq!(metrics.nodes(), 1);
+        assert_eq!(metrics.max_sequence_len(), 0);
+    }
+
+    #[test]
+    fn iter_basic_block() {
+        let bb = StructureBlock::from(BasicBlock {
+            offset: 1,
+            length: 1,
+        });
+        assert_eq!(bb.iter_preorder().collect::<Vec<_>>(), vec![&bb]);
+        assert_eq!(bb.iter_postorder().collect::<Vec<_>>(), vec![&bb]);
+    }
 }
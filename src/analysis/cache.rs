@@ -0,0 +1,524 @@
+use crate::analysis::blocks::StructureBlock;
+use crate::analysis::serial::{decode_tree, encode_tree, BlockTable, Reader, FORMAT_VERSION};
+use crate::analysis::{BasicBlock, BlockType, Graph, ReductionKind, CFG};
+use fnv::FnvHasher;
+use std::collections::HashMap;
+#[cfg(feature = "io")]
+use std::fs::File;
+use std::hash::Hasher;
+use std::io;
+use std::io::ErrorKind;
+#[cfg(feature = "io")]
+use std::io::{Read, Write};
+#[cfg(feature = "io")]
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+const MAGIC: &[u8; 4] = b"BCCS";
+
+/// Cache of already structured [`CFG`]s.
+///
+/// Functions with the same shape are structured in the same way, so the result of a structuring
+/// can be reused for every [`CFG`] with the same edges, regardless of the offset where it is
+/// located. This is common in large binaries, where thunks and template instantiations generate
+/// thousands of identical small functions.
+///
+/// The cache is keyed by a canonical hash of the [`CFG`] (the edges between the nodes numbered in
+/// depth-first preorder, and the distance of each node from the root) and the enabled reductions.
+/// Builders with custom reductions never consult the cache.
+///
+/// A cache is attached to a [`CfsBuilder`](crate::analysis::CfsBuilder) with
+/// [`CfsBuilder::with_cache`](crate::analysis::CfsBuilder::with_cache), and can be shared between
+/// several builders and threads. It can be persisted with [`StructureCache::to_file`] and
+/// loaded with [`StructureCache::from_file`].
+/// # Examples
+/// Basic usage:
+/// ```
+/// use bincc::analysis::{CfsBuilder, StructureCache, CFG};
+/// use bincc::disasm::{Architecture, Statement, StatementFamily};
+/// use std::sync::Arc;
+///
+/// let stmts_a = vec![
+///     Statement::new(0x610, StatementFamily::CJMP, "je 0x618"),
+///     Statement::new(0x614, StatementFamily::MOV, "mov eax, 6"),
+///     Statement::new(0x618, StatementFamily::RET, "ret"),
+/// ];
+/// let stmts_b = vec![
+///     Statement::new(0x810, StatementFamily::CJMP, "je 0x818"),
+///     Statement::new(0x814, StatementFamily::MOV, "mov eax, 6"),
+///     Statement::new(0x818, StatementFamily::RET, "ret"),
+/// ];
+/// let arch = Architecture::X86(64);
+/// let cache = Arc::new(StructureCache::new());
+/// let builder = CfsBuilder::new().with_cache(cache.clone());
+///
+/// builder.build(&CFG::new(&stmts_a, 0x619, arch)).unwrap();
+/// let cfs = builder.build(&CFG::new(&stmts_b, 0x819, arch)).unwrap();
+///
+/// assert_eq!(cache.hits(), 1);
+/// assert_eq!(cfs.get_tree().unwrap().offset(), 0x810);
+/// ```
+#[derive(Debug, Default)]
+pub struct StructureCache {
+    entries: Mutex<HashMap<u64, CacheEntry>>,
+    hits: AtomicUsize,
+}
+
+#[derive(Debug, Clone)]
+struct CacheEntry {
+    // canonical form of the CFG, stored to detect hash collisions.
+    shape: Vec<u64>,
+    tree: Vec<u8>,
+    removed: Vec<(u32, u32)>,
+    duplicated: usize,
+    iterations: usize,
+}
+
+/// Result of a structuring retrieved from the [`StructureCache`].
+pub(crate) struct CachedStructure {
+    pub(crate) tree: StructureBlock,
+    pub(crate) removed: Vec<(BasicBlock, BasicBlock)>,
+    pub(crate) duplicated: usize,
+    pub(crate) iterations: usize,
+}
+
+/// Canonical form of a [`CFG`], used as key in the [`StructureCache`].
+pub(crate) struct CanonicalCfg {
+    hash: u64,
+    shape: Vec<u64>,
+    // blocks in canonical order, followed by the artificial sink and entry point.
+    table: BlockTable,
+}
+
+impl CanonicalCfg {
+    /// Computes the canonical form of a [`CFG`] structured with the given reductions, with or
+    /// without preserving the loop exits.
+    ///
+    /// Returns [`None`] if the [`CFG`] can not be cached: if it is empty, contains unreachable
+    /// nodes or contains nodes at the same offset of the artificial sink or entry point.
+    pub(crate) fn new(
+        cfg: &CFG,
+        reductions: &[ReductionKind],
+        preserve_loops: bool,
+        refine_conditions: bool,
+        annotate_entries: bool,
+    ) -> Option<CanonicalCfg> {
+        let mut blocks = cfg.dfs_preorder().copied().collect::<Vec<_>>();
+        if blocks.is_empty() || blocks.len() != cfg.len() {
+            return None;
+        }
+        let sink = BasicBlock::new_sink();
+        let entry = BasicBlock::new_entry_point();
+        if blocks
+            .iter()
+            .any(|bb| bb.offset == sink.offset || bb.offset == entry.offset)
+        {
+            return None;
+        }
+        let base = blocks[0].offset;
+        let index = blocks
+            .iter()
+            .enumerate()
+            .map(|(index, bb)| (*bb, index as u32))
+            .collect::<HashMap<_, _>>();
+        let mut shape = reductions
+            .iter()
+            .map(|r| ReductionKind::all().iter().position(|x| x == r).unwrap() as u64)
+            .collect::<Vec<_>>();
+        shape.push(u64::MAX);
+        shape.push(preserve_loops as u64);
+        shape.push(refine_conditions as u64);
+        shape.push(annotate_entries as u64);
+        shape.push(blocks.len() as u64);
+        for bb in &blocks {
+            let children = cfg.neighbours(bb);
+            shape.push(bb.offset.wrapping_sub(base));
+            shape.push(children.len() as u64);
+            shape.extend(children.iter().map(|child| index[child] as u64));
+            shape.push(match cfg.terminator(bb) {
+                Some(BlockType::Return) => 1,
+                Some(_) => 2,
+                None => 0,
+            });
+        }
+        let mut hasher = FnvHasher::default();
+        for word in &shape {
+            hasher.write_u64(*word);
+        }
+        blocks.push(sink);
+        blocks.push(entry);
+        Some(CanonicalCfg {
+            hash: hasher.finish(),
+            shape,
+            table: BlockTable::new(blocks),
+        })
+    }
+}
+
+impl StructureCache {
+    /// Creates a new, empty, cache.
+    pub fn new() -> StructureCache {
+        StructureCache::default()
+    }
+
+    /// Returns the amount of different shapes stored in the cache.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    /// Returns true if the cache contains no shapes.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the amount of structurings served by the cache instead of being computed.
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Removes every entry from the cache.
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// Returns the structure of a [`CFG`] with the given canonical form, if cached and obtained
+    /// in at most `max_iterations` steps.
+    pub(crate) fn get(
+        &self,
+        key: &CanonicalCfg,
+        max_iterations: Option<usize>,
+    ) -> Option<CachedStructure> {
+        let entry = self.entries.lock().unwrap().get(&key.hash).cloned()?;
+        if entry.shape != key.shape || max_iterations.is_some_and(|max| entry.iterations > max) {
+            return None;
+        }
+        let mut reader = Reader::new(&entry.tree);
+        let tree = decode_tree(&mut reader, &key.table)?;
+        let removed = entry
+            .removed
+            .iter()
+            .map(|(src, dst)| Some((key.table.block(*src)?, key.table.block(*dst)?)))
+            .collect::<Option<Vec<_>>>()?;
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        Some(CachedStructure {
+            tree,
+            removed,
+            duplicated: entry.duplicated,
+            iterations: entry.iterations,
+        })
+    }
+
+    /// Stores the structure of a [`CFG`] with the given canonical form.
+    ///
+    /// Structures referring to blocks not belonging to the [`CFG`] are not stored.
+    pub(crate) fn insert(
+        &self,
+        key: &CanonicalCfg,
+        tree: &StructureBlock,
+        removed: &[(BasicBlock, BasicBlock)],
+        duplicated: usize,
+        iterations: usize,
+    ) {
+        let mut encoded = Vec::new();
+        if encode_tree(tree, &key.table, &mut encoded).is_none() {
+            return;
+        }
+        let removed = removed
+            .iter()
+            .map(|(src, dst)| Some((key.table.index(src)?, key.table.index(dst)?)))
+            .collect::<Option<Vec<_>>>();
+        let Some(removed) = removed else {
+            return;
+        };
+        let entry = CacheEntry {
+            shape: key.shape.clone(),
+            tree: encoded,
+            removed,
+            duplicated,
+            iterations,
+        };
+        self.entries.lock().unwrap().insert(key.hash, entry);
+    }
+
+    /// Returns the binary representation of the current cache.
+    ///
+    /// The data can be loaded again with [`StructureCache::from_bytes`].
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let entries = self.entries.lock().unwrap();
+        let mut data = MAGIC.to_vec();
+        data.extend(FORMAT_VERSION.to_le_bytes());
+        data.extend((entries.len() as u64).to_le_bytes());
+        for (hash, entry) in entries.iter() {
+            data.extend(hash.to_le_bytes());
+            data.extend((entry.shape.len() as u64).to_le_bytes());
+            for word in &entry.shape {
+                data.extend(word.to_le_bytes());
+            }
+            data.extend((entry.tree.len() as u64).to_le_bytes());
+            data.extend(&entry.tree);
+            data.extend((entry.removed.len() as u64).to_le_bytes());
+            for (src, dst) in &entry.removed {
+                data.extend(src.to_le_bytes());
+                data.extend(dst.to_le_bytes());
+            }
+            data.extend((entry.duplicated as u64).to_le_bytes());
+            data.extend((entry.iterations as u64).to_le_bytes());
+        }
+        data
+    }
+
+    /// Loads a cache previously serialized with [`StructureCache::to_bytes`].
+    ///
+    /// This method returns [`std::io::Error`] in case of malformed input or if the data was
+    /// written by a version of this library using a different format.
+    pub fn from_bytes(data: &[u8]) -> Result<StructureCache, io::Error> {
+        parse_entries(data)
+            .map(|entries| StructureCache {
+                entries: Mutex::new(entries),
+                hits: AtomicUsize::new(0),
+            })
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "malformed structure cache"))
+    }
+
+    /// Writes the current cache into a file.
+    ///
+    /// The file can be loaded again with [`StructureCache::from_file`].
+    #[cfg(feature = "io")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "io")))]
+    pub fn to_file<S: AsRef<Path>>(&self, filename: S) -> Result<(), io::Error> {
+        let mut file = File::create(filename)?;
+        file.write_all(&self.to_bytes())
+    }
+
+    /// Loads a cache previously saved with [`StructureCache::to_file`].
+    ///
+    /// This method returns [`std::io::Error`] in case of malformed input or if the file was
+    /// written by a version of this library using a different format.
+    #[cfg(feature = "io")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "io")))]
+    pub fn from_file<S: AsRef<Path>>(filename: S) -> Result<StructureCache, io::Error> {
+        let mut file = File::open(filename)?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        StructureCache::from_bytes(&data)
+    }
+}
+
+fn parse_entries(data: &[u8]) -> Option<HashMap<u64, CacheEntry>> {
+    let mut reader = Reader::new(data);
+    if reader.bytes(MAGIC.len())? != MAGIC || reader.u32()? != FORMAT_VERSION {
+        return None;
+    }
+    let len = reader.u64()?;
+    let mut entries = HashMap::new();
+    for _ in 0..len {
+        let hash = reader.u64()?;
+        let shape_len = reader.u64()? as usize;
+        let shape = (0..shape_len)
+            .map(|_| reader.u64())
+            .collect::<Option<Vec<_>>>()?;
+        let tree_len = reader.u64()? as usize;
+        let tree = reader.bytes(tree_len)?.to_vec();
+        let removed_len = reader.u64()? as usize;
+        let removed = (0..removed_len)
+            .map(|_| Some((reader.u32()?, reader.u32()?)))
+            .collect::<Option<Vec<_>>>()?;
+        let duplicated = reader.u64()? as usize;
+        let iterations = reader.u64()? as usize;
+        let entry = CacheEntry {
+            shape,
+            tree,
+            removed,
+            duplicated,
+            iterations,
+        };
+        entries.insert(hash, entry);
+    }
+    if reader.is_empty() {
+        Some(entries)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::{BasicBlock, CfsBuilder, ReductionKind, StructureCache, CFG, CFS};
+    use std::collections::HashMap;
+    #[cfg(feature = "io")]
+    use std::error::Error;
+    #[cfg(feature = "io")]
+    use std::fs::File;
+    #[cfg(feature = "io")]
+    use std::io::Write;
+    use std::sync::Arc;
+    #[cfg(feature = "io")]
+    use tempfile::tempdir;
+
+    // creates a CFG with the nodes placed every `step` bytes starting from `base`.
+    fn shifted(edges: &[&[usize]], base: u64, step: u64) -> CFG {
+        let nodes = (0..edges.len() as u64)
+            .map(|x| BasicBlock {
+                offset: base + x * step,
+                length: 1,
+            })
+            .collect::<Vec<_>>();
+        let edges = edges
+            .iter()
+            .enumerate()
+            .map(|(src, dsts)| (nodes[src], dsts.iter().map(|dst| nodes[*dst]).collect()))
+            .collect::<HashMap<_, _>>();
+        CFG {
+            root: nodes.first().copied(),
+            edges,
+            info: HashMap::new(),
+            metadata: None,
+        }
+    }
+
+    // loop with two exits, so the result contains removed edges and loop exits.
+    fn multi_exit_loop(base: u64, step: u64) -> CFG {
+        shifted(&[&[1], &[2, 5], &[3], &[4, 5], &[1], &[]], base, step)
+    }
+
+    #[test]
+    fn hit_same_shape() {
+        let cache = Arc::new(StructureCache::new());
+        let builder = CfsBuilder::new().with_cache(cache.clone());
+        builder.build(&multi_exit_loop(0x100, 4)).unwrap();
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.hits(), 0);
+        let cfg = multi_exit_loop(0x900, 4);
+        let cached = builder.build(&cfg).unwrap();
+        let expected = CFS::new(&cfg).unwrap();
+        assert_eq!(cache.hits(), 1);
+        assert_eq!(cached.get_tree(), expected.get_tree());
+        assert_eq!(cached.removed_edges(), expected.removed_edges());
+        assert_eq!(cached.stats(), expected.stats());
+        assert!(cached.trace().is_empty());
+    }
+
+    #[test]
+    fn miss_different_offsets() {
+        let cache = Arc::new(StructureCache::new());
+        let builder = CfsBuilder::new().with_cache(cache.clone());
+        builder.build(&multi_exit_loop(0x100, 4)).unwrap();
+        builder.build(&multi_exit_loop(0x100, 8)).unwrap();
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.hits(), 0);
+    }
+
+    #[test]
+    fn miss_different_reductions() {
+        let cache = Arc::new(StructureCache::new());
+        let cfg = multi_exit_loop(0x100, 4);
+        CfsBuilder::new()
+            .with_cache(cache.clone())
+            .build(&cfg)
+            .unwrap();
+        CfsBuilder::new()
+            .disable(ReductionKind::Switch)
+            .with_cache(cache.clone())
+            .build(&cfg)
+            .unwrap();
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.hits(), 0);
+    }
+
+    #[test]
+    fn miss_preserve_loops() {
+        let cache = Arc::new(StructureCache::new());
+        let cfg = multi_exit_loop(0x100, 4);
+        CfsBuilder::new()
+            .with_cache(cache.clone())
+            .build(&cfg)
+            .unwrap();
+        CfsBuilder::new()
+            .preserve_loops()
+            .with_cache(cache.clone())
+            .build(&cfg)
+            .unwrap();
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.hits(), 0);
+    }
+
+    #[test]
+    fn miss_over_budget() {
+        let cache = Arc::new(StructureCache::new());
+        let cfg = multi_exit_loop(0x100, 4);
+        let cfs = CfsBuilder::new()
+            .with_cache(cache.clone())
+            .build(&cfg)
+            .unwrap();
+        let limited = CfsBuilder::new()
+            .with_cache(cache.clone())
+            .max_iterations(cfs.stats().iterations() - 1);
+        assert!(limited.try_build(&cfg).is_err());
+        assert_eq!(cache.hits(), 0);
+    }
+
+    #[test]
+    fn to_bytes_and_from_bytes() {
+        let cache = Arc::new(StructureCache::new());
+        CfsBuilder::new()
+            .with_cache(cache.clone())
+            .build(&multi_exit_loop(0x100, 4))
+            .unwrap();
+        let loaded = Arc::new(StructureCache::from_bytes(&cache.to_bytes()).unwrap());
+        assert_eq!(loaded.len(), 1);
+        let cfg = multi_exit_loop(0x2000, 4);
+        let cfs = CfsBuilder::new()
+            .with_cache(loaded.clone())
+            .build(&cfg)
+            .unwrap();
+        assert_eq!(loaded.hits(), 1);
+        assert_eq!(cfs.get_tree(), CFS::new(&cfg).unwrap().get_tree());
+        assert!(StructureCache::from_bytes(b"BCCS\x01").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "io")]
+    fn save_and_retrieve() -> Result<(), Box<dyn Error>> {
+        let dir = tempdir()?;
+        let path = dir.path().join("cache.bin");
+        let cache = Arc::new(StructureCache::new());
+        CfsBuilder::new()
+            .with_cache(cache.clone())
+            .build(&multi_exit_loop(0x100, 4))
+            .unwrap();
+        cache.to_file(&path)?;
+        let loaded = Arc::new(StructureCache::from_file(&path)?);
+        assert_eq!(loaded.len(), 1);
+        let cfg = multi_exit_loop(0x2000, 4);
+        let cfs = CfsBuilder::new()
+            .with_cache(loaded.clone())
+            .build(&cfg)
+            .unwrap();
+        assert_eq!(loaded.hits(), 1);
+        assert_eq!(cfs.get_tree(), CFS::new(&cfg).unwrap().get_tree());
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "io")]
+    fn retrieve_malformed() -> Result<(), Box<dyn Error>> {
+        let dir = tempdir()?;
+        let path = dir.path().join("cache.bin");
+        let mut file = File::create(&path)?;
+        file.write_all(b"BCCS\x01")?;
+        assert!(StructureCache::from_file(&path).is_err());
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "io")]
+    fn retrieve_other_version() -> Result<(), Box<dyn Error>> {
+        let dir = tempdir()?;
+        let path = dir.path().join("cache.bin");
+        let mut file = File::create(&path)?;
+        file.write_all(b"BCCS\xFF\xFF\xFF\xFF\x00\x00\x00\x00\x00\x00\x00\x00")?;
+        assert!(StructureCache::from_file(&path).is_err());
+        Ok(())
+    }
+}
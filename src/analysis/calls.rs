@@ -0,0 +1,603 @@
+use crate::analysis::cfg::{jump_target, parse_number};
+use crate::analysis::{BasicBlock, CFG};
+use crate::disasm::{Architecture, JumpType, Statement, StatementFamily};
+use std::collections::HashSet;
+
+/// Destination of a call instruction, or of a [`Reference`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum CallTarget {
+    /// Direct call to a known address.
+    Address(u64),
+    /// Direct call to a named function, like the `sym.imp.printf` import written by radare2.
+    Symbol(String),
+    /// Call to an address computed at runtime. Contains the operand of the call, like
+    /// `qword [rax + 8]`.
+    Indirect(String),
+}
+
+impl CallTarget {
+    /// Returns true if the target is known without executing the code.
+    pub fn is_direct(&self) -> bool {
+        !matches!(self, CallTarget::Indirect(_))
+    }
+}
+
+/// Call instruction contained in a [`BasicBlock`].
+///
+/// Call sites are recorded only by the constructors aware of the statements of the function,
+/// [`CFG::new`] and [`CFG::with_statements`], and can be retrieved with [`CFG::calls`].
+/// # Examples
+/// Basic usage:
+/// ```
+/// use bincc::analysis::{BasicBlock, CallTarget, CFG};
+/// use bincc::disasm::{Architecture, Statement, StatementFamily};
+///
+/// let stmts = vec![
+///     Statement::new(0x10, StatementFamily::CALL, "call 0x400"),
+///     Statement::new(0x15, StatementFamily::CALL, "call sym.imp.puts"),
+///     Statement::new(0x1A, StatementFamily::CALL, "call rax"),
+///     Statement::new(0x1C, StatementFamily::RET, "ret"),
+/// ];
+/// let cfg = CFG::new(&stmts, 0x1D, Architecture::X86(64));
+/// let calls = cfg.calls(&BasicBlock { offset: 0x10, length: 0xD });
+///
+/// assert_eq!(calls.len(), 3);
+/// assert_eq!(calls[0].target(), &CallTarget::Address(0x400));
+/// assert_eq!(calls[1].target(), &CallTarget::Symbol("sym.imp.puts".to_string()));
+/// assert_eq!(calls[2].offset(), 0x1A);
+/// assert!(!calls[2].target().is_direct());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CallSite {
+    pub(super) offset: u64,
+    pub(super) length: u64,
+    pub(super) target: CallTarget,
+}
+
+impl CallSite {
+    /// Records the call performed by the given statement, of the given size in bytes.
+    ///
+    /// Operands that are not numbers are considered symbols if they contain a `.`, as the names
+    /// given by radare2, and indirect otherwise, as registers or memory locations. The link
+    /// register of the MIPS `jalr rd, rs` is not part of the target, which is only `rs`.
+    fn from_statement(stmt: &Statement, length: u64) -> CallSite {
+        let args = stmt.get_args();
+        let target = if let Some(address) = parse_number(args) {
+            CallTarget::Address(address)
+        } else if let Some(symbol) = symbol(args) {
+            CallTarget::Symbol(symbol.to_string())
+        } else if let Some(register) = link_target(stmt) {
+            CallTarget::Indirect(register.to_string())
+        } else {
+            CallTarget::Indirect(args.to_string())
+        };
+        CallSite {
+            offset: stmt.get_offset(),
+            length,
+            target,
+        }
+    }
+
+    /// Records the calls performed by the statements of a basic block ending at the given
+    /// offset.
+    ///
+    /// Calls through a register loaded from a `$gp`-relative address, the idiom of MIPS position
+    /// independent code, are indirect calls targeting the loaded slot of the global offset
+    /// table, like `-0x7fd0(gp)`, so calls to the same function share the same target.
+    pub(super) fn from_block(stmts: &[Statement], end: u64) -> Vec<CallSite> {
+        stmts
+            .iter()
+            .enumerate()
+            .filter(|(_, stmt)| stmt.get_family() == StatementFamily::CALL)
+            .map(|(index, stmt)| {
+                let mut call = CallSite::from_statement(stmt, length(stmts, index, end));
+                if let CallTarget::Indirect(args) = &call.target {
+                    if let Some(slot) = gp_slot(&stmts[..index], args) {
+                        call.target = CallTarget::Indirect(slot.to_string());
+                    }
+                }
+                call
+            })
+            .collect()
+    }
+
+    /// Returns the offset of the call instruction.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the size in bytes of the call instruction.
+    ///
+    /// This is the distance from the following instruction, or from the end of the block for
+    /// the last one, so it includes the operands patched by a relocation.
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
+    /// Returns the destination of the call.
+    pub fn target(&self) -> &CallTarget {
+        &self.target
+    }
+
+    // moves the call and its direct target by the given amount of bytes.
+    pub(super) fn rebase(&mut self, delta: u64) {
+        self.offset = self.offset.wrapping_add(delta);
+        rebase_target(&mut self.target, delta);
+    }
+}
+
+/// Kind of a [`Reference`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ReferenceKind {
+    /// Direct jump to a location outside the function, like a tail call.
+    Jump,
+    /// Access to the memory at an address encoded in the instruction, like the `[rip + 0x2fe2]`
+    /// operand of x86 or the page loaded by `adrp` in AArch64.
+    Data,
+}
+
+/// Instruction contained in a [`BasicBlock`] referring to a location outside its function
+/// without calling it.
+///
+/// References are recorded along with the [`CallSite`]s, by [`CFG::new`] and
+/// [`CFG::with_statements`], and can be retrieved with [`CFG::references`].
+/// # Examples
+/// Basic usage:
+/// ```
+/// use bincc::analysis::{BasicBlock, CallTarget, ReferenceKind, CFG};
+/// use bincc::disasm::{Architecture, Statement, StatementFamily};
+///
+/// let stmts = vec![
+///     Statement::new(0x10, StatementFamily::LEA, "lea rdi, [rip + 0x100]"),
+///     Statement::new(0x17, StatementFamily::JMP, "jmp 0x400"),
+/// ];
+/// let cfg = CFG::new(&stmts, 0x1C, Architecture::X86(64));
+/// let refs = cfg.references(&BasicBlock { offset: 0x10, length: 0xC });
+///
+/// assert_eq!(refs.len(), 2);
+/// assert_eq!(refs[0].kind(), ReferenceKind::Data);
+/// assert_eq!(refs[0].target(), &CallTarget::Address(0x117));
+/// assert_eq!(refs[1].kind(), ReferenceKind::Jump);
+/// assert_eq!(refs[1].length(), 5);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Reference {
+    pub(super) offset: u64,
+    pub(super) length: u64,
+    pub(super) kind: ReferenceKind,
+    pub(super) target: CallTarget,
+}
+
+impl Reference {
+    /// Records the references of the statements of a basic block ending at the given offset.
+    ///
+    /// Jumps are references if their target is not one of the given blocks of the function.
+    pub(super) fn from_block(
+        stmts: &[Statement],
+        end: u64,
+        blocks: &HashSet<u64>,
+        arch: Architecture,
+    ) -> Vec<Reference> {
+        stmts
+            .iter()
+            .enumerate()
+            .filter(|(_, stmt)| stmt.get_family() != StatementFamily::CALL)
+            .filter_map(|(index, stmt)| {
+                let length = length(stmts, index, end);
+                let (kind, target) = match arch.jump(stmt.get_mnemonic()) {
+                    JumpType::JumpConditional | JumpType::JumpUnconditional
+                        if stmt.get_family() != StatementFamily::RET =>
+                    {
+                        let target = match jump_target(stmt) {
+                            Some(address) if blocks.contains(&address) => return None,
+                            Some(address) => CallTarget::Address(address),
+                            None => CallTarget::Symbol(symbol(stmt.get_args())?.to_string()),
+                        };
+                        (ReferenceKind::Jump, target)
+                    }
+                    JumpType::NoJump => {
+                        let next = stmt.get_offset().wrapping_add(length);
+                        let address = data_address(stmt, next)?;
+                        (ReferenceKind::Data, CallTarget::Address(address))
+                    }
+                    _ => return None,
+                };
+                Some(Reference {
+                    offset: stmt.get_offset(),
+                    length,
+                    kind,
+                    target,
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the offset of the referring instruction.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the size in bytes of the referring instruction, see [`CallSite::length`].
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
+    /// Returns whether the instruction jumps to the location or accesses its data.
+    pub fn kind(&self) -> ReferenceKind {
+        self.kind
+    }
+
+    /// Returns the referenced location.
+    pub fn target(&self) -> &CallTarget {
+        &self.target
+    }
+
+    // moves the reference and its target by the given amount of bytes.
+    pub(super) fn rebase(&mut self, delta: u64) {
+        self.offset = self.offset.wrapping_add(delta);
+        rebase_target(&mut self.target, delta);
+    }
+}
+
+// size in bytes of the statement at the given index of a block ending at the given offset.
+fn length(stmts: &[Statement], index: usize, end: u64) -> u64 {
+    let next = stmts.get(index + 1).map_or(end, Statement::get_offset);
+    next.saturating_sub(stmts[index].get_offset())
+}
+
+// operand naming a symbol, like the `sym.imp.puts` written by radare2.
+fn symbol(args: &str) -> Option<&str> {
+    (args.contains('.') && !args.contains(['[', ' '])).then_some(args)
+}
+
+// address accessed by a pc-relative or absolute memory operand, like `[rip + 0x10]` or
+// `[0x601040]`, or loaded by the AArch64 `adr` and `adrp`. The pc is the next instruction.
+fn data_address(stmt: &Statement, next: u64) -> Option<u64> {
+    let args = stmt.get_args();
+    if matches!(stmt.get_mnemonic(), "adr" | "adrp") {
+        return parse_number(args.rsplit_once(',')?.1.trim());
+    }
+    let (_, operand) = args.split_once('[')?;
+    let (operand, _) = operand.split_once(']')?;
+    match operand.trim() {
+        "rip" => Some(next),
+        operand => match operand.strip_prefix("rip + ") {
+            Some(disp) => Some(next.wrapping_add(parse_number(disp)?)),
+            None => match operand.strip_prefix("rip - ") {
+                Some(disp) => Some(next.wrapping_sub(parse_number(disp)?)),
+                None => parse_number(operand),
+            },
+        },
+    }
+}
+
+// moves a direct target by the given amount of bytes.
+fn rebase_target(target: &mut CallTarget, delta: u64) {
+    if let CallTarget::Address(address) = target {
+        *address = address.wrapping_add(delta);
+    }
+}
+
+// register called by `jalr rd, rs`, that jumps to `rs` and stores the return address in `rd`.
+fn link_target(stmt: &Statement) -> Option<&str> {
+    match stmt.get_mnemonic() {
+        "jalr" | "jalr.hb" => stmt.get_args().rsplit_once(',').map(|(_, rs)| rs.trim()),
+        _ => None,
+    }
+}
+
+// address loaded in the register called by `jalr t9` or `jalr ra, t9`, if relative to $gp.
+fn gp_slot<'a>(stmts: &'a [Statement], register: &str) -> Option<&'a str> {
+    let (load, slot) = stmts.iter().rev().find_map(|stmt| {
+        let (dst, src) = stmt.get_args().split_once(',')?;
+        (dst.trim() == register).then(|| (stmt.get_mnemonic(), src.trim()))
+    })?;
+    let relative = slot.ends_with("(gp)") || slot.ends_with("($gp)");
+    (matches!(load, "lw" | "ld") && relative).then_some(slot)
+}
+
+impl CFG {
+    /// Returns the calls performed inside the given basic block, in order.
+    ///
+    /// The slice is empty if the block performs no calls, does not belong to this CFG, or the
+    /// CFG was not created by [`CFG::new`] or [`CFG::with_statements`].
+    pub fn calls(&self, block: &BasicBlock) -> &[CallSite] {
+        self.info
+            .get(block)
+            .map_or(&[], |info| info.calls.as_slice())
+    }
+
+    /// Replaces the target of every call with the one returned by the given function, if any.
+    ///
+    /// This is used to name the calls after the CFG is built, as in
+    /// [`Binary::extract`](crate::disasm::Binary::extract) for the calls to the import thunks
+    /// and the relocated calls.
+    pub fn resolve_calls<F: FnMut(&CallSite) -> Option<CallTarget>>(&mut self, mut resolve: F) {
+        for call in self
+            .info
+            .values_mut()
+            .flat_map(|info| info.calls.iter_mut())
+        {
+            if let Some(target) = resolve(call) {
+                call.target = target;
+            }
+        }
+    }
+
+    /// Returns the references to the locations outside the function performed inside the given
+    /// basic block, in order.
+    ///
+    /// The slice is empty under the same conditions of [`CFG::calls`].
+    pub fn references(&self, block: &BasicBlock) -> &[Reference] {
+        self.info
+            .get(block)
+            .map_or(&[], |info| info.references.as_slice())
+    }
+
+    /// Replaces the target of every reference with the one returned by the given function, if
+    /// any.
+    ///
+    /// This is the equivalent of [`CFG::resolve_calls`] for the [`Reference`]s, used by
+    /// [`Binary::extract`](crate::disasm::Binary::extract) for the relocated references.
+    pub fn resolve_references<F>(&mut self, mut resolve: F)
+    where
+        F: FnMut(&Reference) -> Option<CallTarget>,
+    {
+        for reference in self
+            .info
+            .values_mut()
+            .flat_map(|info| info.references.iter_mut())
+        {
+            if let Some(target) = resolve(reference) {
+                reference.target = target;
+            }
+        }
+    }
+
+    /// Returns true if this CFG is a thunk: a single basic block of a few instructions, ending
+    /// with a jump to an address computed at runtime.
+    ///
+    /// This is the shape of the PLT stubs and of the other import thunks, like
+    /// `jmp qword [rip + 0x2fe2]`. Thunks are recognized only in the CFGs created by
+    /// [`CFG::new`] or [`CFG::with_statements`], as the instructions are required.
+    /// # Examples
+    /// Basic usage:
+    /// ```
+    /// use bincc::analysis::CFG;
+    /// use bincc::disasm::{Architecture, Statement, StatementFamily};
+    ///
+    /// let plt = vec![Statement::new(0x1030, StatementFamily::JMP, "jmp qword [rip + 0x2fe2]")];
+    /// let cfg = CFG::new(&plt, 0x1036, Architecture::X86(64));
+    ///
+    /// assert!(cfg.is_thunk());
+    /// ```
+    pub fn is_thunk(&self) -> bool {
+        // instructions of the longest stub: adrp, ldr, add, br of AArch64
+        const MAX_INSTRUCTIONS: usize = 4;
+        let mut blocks = self
+            .edges
+            .keys()
+            .filter(|bb| !bb.is_sink() && !bb.is_entry_point());
+        match (blocks.next(), blocks.next()) {
+            (Some(bb), None) => {
+                self.ends_with_indirect_branch(bb)
+                    && self.calls(bb).is_empty()
+                    && self
+                        .instruction_count(bb)
+                        .is_some_and(|count| count <= MAX_INSTRUCTIONS)
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::{BasicBlock, CallSite, CallTarget, ReferenceKind, CFG, CFS};
+    use crate::disasm::radare2::BareCFG;
+    use crate::disasm::{Architecture, Statement, StatementFamily};
+
+    #[test]
+    fn recorded_per_block() {
+        let stmts = vec![
+            Statement::new(0x10, StatementFamily::CALL, "call fcn.00000400"),
+            Statement::new(0x15, StatementFamily::CJMP, "je 0x1F"),
+            Statement::new(0x17, StatementFamily::CALL, "call qword [rip + 0x2fe2]"),
+            Statement::new(0x1D, StatementFamily::CALL, "call 0x400"),
+            Statement::new(0x1F, StatementFamily::RET, "ret"),
+        ];
+        let cfg = CFG::new(&stmts, 0x20, Architecture::X86(64));
+        let bb = |offset, length| BasicBlock { offset, length };
+        let head = cfg.calls(&bb(0x10, 7));
+        assert_eq!(head.len(), 1);
+        assert_eq!(
+            head[0].target(),
+            &CallTarget::Symbol("fcn.00000400".to_string())
+        );
+        let then = cfg.calls(&bb(0x17, 8));
+        assert_eq!(then.len(), 2);
+        assert_eq!(
+            then[0].target(),
+            &CallTarget::Indirect("qword [rip + 0x2fe2]".to_string())
+        );
+        assert_eq!(then[1].offset(), 0x1D);
+        assert!(then[1].target().is_direct());
+        assert!(cfg.calls(&bb(0x1F, 1)).is_empty());
+        assert!(cfg.calls(&bb(0x100, 1)).is_empty());
+        let loaded = CFS::from_bytes(&CFS::new(&cfg).unwrap().to_bytes()).unwrap();
+        assert_eq!(loaded.get_cfg().calls(&bb(0x17, 8)), then);
+    }
+
+    #[test]
+    fn bare_cfg() {
+        // the same function of recorded_per_block, with the blocks found by a disassembler
+        let stmts = vec![
+            Statement::new(0x10, StatementFamily::CALL, "call fcn.00000400"),
+            Statement::new(0x15, StatementFamily::CJMP, "je 0x1F"),
+            Statement::new(0x17, StatementFamily::CALL, "call qword [rip + 0x2fe2]"),
+            Statement::new(0x1D, StatementFamily::CALL, "call 0x400"),
+            Statement::new(0x1F, StatementFamily::RET, "ret"),
+        ];
+        let bare = BareCFG {
+            root: Some(0x10),
+            blocks: vec![(0x10, 7), (0x17, 8), (0x1F, 1)],
+            edges: vec![(0x10, 0x17), (0x10, 0x1F), (0x17, 0x1F)],
+        };
+        let arch = Architecture::X86(64);
+        let expected = CFG::new(&stmts, 0x20, arch);
+        // the statements may come in any order
+        let mut shuffled = stmts.clone();
+        shuffled.reverse();
+        let cfg = CFG::with_statements(bare.clone(), &shuffled, arch);
+        let bb = |offset, length| BasicBlock { offset, length };
+        for block in [bb(0x10, 7), bb(0x17, 8), bb(0x1F, 1)] {
+            assert_eq!(cfg.calls(&block), expected.calls(&block));
+        }
+        assert_eq!(cfg.calls(&bb(0x17, 8)).len(), 2);
+        assert!(CFG::from(bare).calls(&bb(0x17, 8)).is_empty());
+    }
+
+    #[test]
+    fn references() {
+        let stmts = vec![
+            Statement::new(0x10, StatementFamily::CJMP, "je 0x1D"),
+            Statement::new(0x12, StatementFamily::MOV, "mov eax, dword [rip - 0x10]"),
+            Statement::new(0x18, StatementFamily::JMP, "jmp sym.exit"),
+            Statement::new(0x1D, StatementFamily::MOV, "mov dword [rax + 8], 0"),
+            Statement::new(0x24, StatementFamily::CALL, "call qword [0x601040]"),
+            Statement::new(0x2A, StatementFamily::JMP, "jmp 0x400"),
+        ];
+        let cfg = CFG::new(&stmts, 0x2F, Architecture::X86(64));
+        let bb = |offset, length| BasicBlock { offset, length };
+        assert!(cfg.references(&bb(0x10, 2)).is_empty());
+        let refs = cfg.references(&bb(0x12, 0xB));
+        assert_eq!(refs.len(), 2);
+        assert_eq!(refs[0].kind(), ReferenceKind::Data);
+        assert_eq!(refs[0].target(), &CallTarget::Address(0x8));
+        assert_eq!(refs[0].length(), 6);
+        assert_eq!(refs[1].kind(), ReferenceKind::Jump);
+        assert_eq!(
+            refs[1].target(),
+            &CallTarget::Symbol("sym.exit".to_string())
+        );
+        // calls are not references, even if reading the memory
+        let refs = cfg.references(&bb(0x1D, 0x12));
+        assert_eq!(refs.len(), 1);
+        assert_eq!(refs[0].offset(), 0x2A);
+        assert_eq!(refs[0].target(), &CallTarget::Address(0x400));
+        assert_eq!(cfg.calls(&bb(0x1D, 0x12))[0].length(), 6);
+        let loaded = CFS::from_bytes(&CFS::new(&cfg).unwrap().to_bytes()).unwrap();
+        assert_eq!(
+            loaded.get_cfg().references(&bb(0x12, 0xB)),
+            cfg.references(&bb(0x12, 0xB))
+        );
+        let rebased = cfg.rebase(0, 0x1000);
+        let refs = rebased.references(&bb(0x1012, 0xB));
+        assert_eq!(refs[0].offset(), 0x1012);
+        assert_eq!(refs[0].target(), &CallTarget::Address(0x1008));
+    }
+
+    #[test]
+    fn references_aarch64() {
+        let stmts = vec![
+            Statement::new(0x500, StatementFamily::LOAD, "adrp x0, 0x11000"),
+            Statement::new(0x504, StatementFamily::ADD, "add x0, x0, 0xf80"),
+            Statement::new(0x508, StatementFamily::JMP, "b 0x600"),
+        ];
+        let cfg = CFG::new(&stmts, 0x50C, Architecture::Arm(64));
+        let refs = cfg.references(&BasicBlock {
+            offset: 0x500,
+            length: 0xC,
+        });
+        let targets = refs
+            .iter()
+            .map(|reference| (reference.kind(), reference.target().clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            targets,
+            vec![
+                (ReferenceKind::Data, CallTarget::Address(0x11000)),
+                (ReferenceKind::Jump, CallTarget::Address(0x600)),
+            ]
+        );
+    }
+
+    #[test]
+    fn thunks() {
+        let arch = Architecture::Arm(64);
+        let plt = vec![
+            Statement::new(0x500, StatementFamily::LOAD, "adrp x16, 0x11000"),
+            Statement::new(0x504, StatementFamily::LOAD, "ldr x17, [x16, 0xf80]"),
+            Statement::new(0x508, StatementFamily::ADD, "add x16, x16, 0xf80"),
+            Statement::new(0x50C, StatementFamily::JMP, "br x17"),
+        ];
+        assert!(CFG::new(&plt, 0x510, arch).is_thunk());
+        let wrapper = vec![
+            Statement::new(0x500, StatementFamily::CALL, "bl 0x600"),
+            Statement::new(0x504, StatementFamily::JMP, "br x17"),
+        ];
+        assert!(!CFG::new(&wrapper, 0x508, arch).is_thunk());
+        let direct = vec![Statement::new(0x500, StatementFamily::JMP, "b 0x600")];
+        assert!(!CFG::new(&direct, 0x504, arch).is_thunk());
+        let branching = vec![
+            Statement::new(0x500, StatementFamily::CJMP, "cbz x0, 0x508"),
+            Statement::new(0x504, StatementFamily::JMP, "br x17"),
+            Statement::new(0x508, StatementFamily::RET, "ret"),
+        ];
+        assert!(!CFG::new(&branching, 0x50C, arch).is_thunk());
+    }
+
+    #[test]
+    fn resolve_calls() {
+        let stmts = vec![
+            Statement::new(0x10, StatementFamily::CALL, "call 0x400"),
+            Statement::new(0x15, StatementFamily::CALL, "call 0x500"),
+            Statement::new(0x1A, StatementFamily::RET, "ret"),
+        ];
+        let mut cfg = CFG::new(&stmts, 0x1B, Architecture::X86(64));
+        cfg.resolve_calls(|call| match call.target() {
+            CallTarget::Address(0x400) => Some(CallTarget::Symbol("puts".to_string())),
+            _ => None,
+        });
+        let calls = cfg.calls(&BasicBlock {
+            offset: 0x10,
+            length: 0xB,
+        });
+        assert_eq!(calls[0].target(), &CallTarget::Symbol("puts".to_string()));
+        assert_eq!(calls[1].target(), &CallTarget::Address(0x500));
+    }
+
+    #[test]
+    fn mips_gp_relative() {
+        let stmts = vec![
+            Statement::new(0x00, StatementFamily::LOAD, "lw t9, -0x7fd0(gp)"),
+            Statement::new(0x04, StatementFamily::CALL, "jalr t9"),
+            Statement::new(0x08, StatementFamily::NOP, "nop"),
+            Statement::new(0x0C, StatementFamily::LOAD, "lw t9, 0x10(sp)"),
+            Statement::new(0x10, StatementFamily::CALL, "jalr t9"),
+            Statement::new(0x14, StatementFamily::NOP, "nop"),
+            Statement::new(0x18, StatementFamily::CALL, "jalr ra, a0"),
+            Statement::new(0x1C, StatementFamily::NOP, "nop"),
+            Statement::new(0x20, StatementFamily::LOAD, "lw t9, -0x7fcc(gp)"),
+            Statement::new(0x24, StatementFamily::CALL, "jalr ra, t9"),
+            Statement::new(0x28, StatementFamily::NOP, "nop"),
+            Statement::new(0x2C, StatementFamily::JMP, "jr ra"),
+            Statement::new(0x30, StatementFamily::NOP, "nop"),
+        ];
+        let cfg = CFG::new(&stmts, 0x34, Architecture::Mips(32));
+        let calls = cfg.calls(&BasicBlock {
+            offset: 0,
+            length: 0x34,
+        });
+        let targets = calls.iter().map(CallSite::target).collect::<Vec<_>>();
+        assert_eq!(
+            targets,
+            vec![
+                &CallTarget::Indirect("-0x7fd0(gp)".to_string()),
+                &CallTarget::Indirect("t9".to_string()),
+                &CallTarget::Indirect("a0".to_string()),
+                &CallTarget::Indirect("-0x7fcc(gp)".to_string()),
+            ]
+        );
+    }
+}
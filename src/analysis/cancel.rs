@@ -0,0 +1,55 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Flag shared with a long analysis, used to stop it before completion.
+///
+/// The token is cloned and passed to the analysis, for example with
+/// [`CfsBuilder::with_cancellation`](crate::analysis::CfsBuilder::with_cancellation), while
+/// another thread, like the one of a GUI, keeps a clone and calls [`CancellationToken::cancel`]
+/// when the results are no longer needed. The analysis checks the token regularly and stops as
+/// soon as possible, returning the results computed so far.
+///
+/// Once cancelled, a token remains cancelled: a new token is required for each analysis that may
+/// be cancelled independently.
+/// # Examples
+/// Basic usage:
+/// ```
+/// use bincc::analysis::{CancellationToken, CfsBuilder, CFG};
+/// use bincc::disasm::{Architecture, Statement, StatementFamily};
+/// use bincc::Error;
+///
+/// let stmts = vec![
+///     Statement::new(0x10, StatementFamily::CJMP, "je 0x14"),
+///     Statement::new(0x12, StatementFamily::MOV, "mov eax, 0"),
+///     Statement::new(0x14, StatementFamily::RET, "ret"),
+/// ];
+/// let cfg = CFG::new(&stmts, 0x15, Architecture::X86(64));
+/// let token = CancellationToken::new();
+/// let builder = CfsBuilder::new().with_cancellation(token.clone());
+///
+/// assert!(builder.try_build(&cfg).is_ok());
+/// token.cancel();
+/// assert!(matches!(builder.try_build(&cfg), Err(Error::Cancelled)));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /// Creates a token that is not cancelled.
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Cancels the analyses using this token, or any of its clones.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns true if [`CancellationToken::cancel`] has been called on this token, or on any of
+    /// its clones.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
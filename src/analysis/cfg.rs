@@ -1,16 +1,24 @@
-use crate::analysis::Graph;
+use crate::analysis::{BlockType, CallSite, FunctionMetadata, Graph, Reference};
 use crate::disasm::radare2::BareCFG;
-use crate::disasm::{Architecture, JumpType, Statement, StatementFamily};
-use fnv::FnvHashMap;
+#[cfg(feature = "disasm")]
+use crate::disasm::MemoryImage;
+use crate::disasm::{Architecture, CategoryHistogram, JumpType, Statement, StatementFamily};
+use crate::Error;
+use fnv::{FnvHashMap, FnvHasher};
+#[cfg(feature = "io")]
 use lazy_static::lazy_static;
-use parse_int::parse;
-use regex::Regex;
+#[cfg(feature = "io")]
+use regex::{Captures, Regex};
 use std::collections::{BTreeSet, HashMap, HashSet};
-use std::error::Error;
 use std::fmt::Display;
+#[cfg(feature = "io")]
 use std::fs::File;
+use std::hash::Hasher;
+#[cfg(feature = "io")]
 use std::io;
-use std::io::{ErrorKind, Read, Write};
+#[cfg(feature = "io")]
+use std::io::{Read, Write};
+#[cfg(feature = "io")]
 use std::path::Path;
 
 /// Offset of an artificially created exit node.
@@ -29,6 +37,9 @@ const EXTERN_DOT_TRUE_COLOUR: &str = "forestgreen";
 const EXTERN_DOT_FALSE_COLOUR: &str = "crimson";
 /// Color of the unconditional jumps edges in the saved CFG .dot file.
 const EXTERN_DOT_JUMP_COLOUR: &str = "dodgerblue";
+/// Maximum amount of entries read from a jump table.
+#[cfg(feature = "disasm")]
+const MAX_TABLE_ENTRIES: u64 = 1024;
 
 /// A Control Flow Graph.
 ///
@@ -38,6 +49,26 @@ const EXTERN_DOT_JUMP_COLOUR: &str = "dodgerblue";
 pub struct CFG {
     pub(super) root: Option<BasicBlock>,
     pub(super) edges: HashMap<BasicBlock, Vec<BasicBlock>>,
+    // additional information about the blocks, if known.
+    pub(super) info: HashMap<BasicBlock, BlockInfo>,
+    pub(super) metadata: Option<FunctionMetadata>,
+}
+
+/// Information about a basic block, recorded while building a [`CFG`] from its statements.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(super) struct BlockInfo {
+    pub(super) instructions: Option<usize>,
+    // kind of the last instruction, if the block ends the function.
+    pub(super) terminator: Option<BlockType>,
+    // the last instruction is a call.
+    pub(super) call: bool,
+    // the last instruction is a jump to a target computed at runtime.
+    pub(super) indirect: bool,
+    // calls performed inside the block, in order.
+    pub(super) calls: Vec<CallSite>,
+    // jumps leaving the function and data accesses performed inside the block, in order.
+    pub(super) references: Vec<Reference>,
+    pub(super) categories: Option<CategoryHistogram>,
 }
 
 /// Minimum portion of code without any jump.
@@ -51,6 +82,10 @@ pub struct CFG {
 ///
 /// This class does not contains the actual statements, rather than their offsets in the original
 /// code.
+///
+/// Basic blocks are ordered by their starting address and then by their length. Given that the
+/// artificial entry point starts at [ENTRY_ADDR] and the sink at [SINK_ADDR], the former precedes
+/// every other block and the latter follows them.
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
 pub struct BasicBlock {
     /// Offset, in the original code, of the **first** instruction belonging to this basic block.
@@ -75,6 +110,15 @@ impl BasicBlock {
         self.length == 0 && self.offset == ENTRY_ADDR
     }
 
+    /// Returns the size of this basic block in bytes.
+    ///
+    /// This is the same value of [BasicBlock::length], and is 0 for the artificial sink and entry
+    /// point. The amount of instructions in the block is instead recorded by the [CFG] containing
+    /// it, see [CFG::instruction_count()].
+    pub fn byte_len(&self) -> u64 {
+        self.length
+    }
+
     /// Creates a new sink block.
     pub fn new_sink() -> BasicBlock {
         BasicBlock {
@@ -84,7 +128,7 @@ impl BasicBlock {
     }
 
     /// Creates a new artificial entry point.
-    fn new_entry_point() -> BasicBlock {
+    pub(crate) fn new_entry_point() -> BasicBlock {
         BasicBlock {
             offset: ENTRY_ADDR,
             length: 0,
@@ -93,8 +137,21 @@ impl BasicBlock {
 }
 
 impl Display for BasicBlock {
+    /// Writes the range of addresses covered by the block in hexadecimal, like
+    /// `0x400123..0x400147`, or `entry` and `sink` for the artificial blocks.
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.offset)
+        if self.is_entry_point() {
+            write!(f, "entry")
+        } else if self.is_sink() {
+            write!(f, "sink")
+        } else {
+            write!(
+                f,
+                "{:#x}..{:#x}",
+                self.offset,
+                self.offset.saturating_add(self.length)
+            )
+        }
     }
 }
 
@@ -152,7 +209,12 @@ impl From<BareCFG> for CFG {
             // if the root written in the BareCFG does not exists (weird), pick the lowest offset
             root = bbs.iter().map(|(_, bb)| bb).min().cloned();
         }
-        CFG { root, edges }
+        CFG {
+            root,
+            edges,
+            info: HashMap::new(),
+            metadata: None,
+        }
     }
 }
 
@@ -169,6 +231,19 @@ impl CFG {
     /// The newly returned CFG will not contain a sink and will contain only reachable nodes
     /// (thus eliminating indirect jumps).
     /// One should use [CFG::add_sink()] to refine the CFG.
+    ///
+    /// Traps and invalid instructions end their basic block without any successor. The blocks
+    /// ending the function are recorded, and can be retrieved with [CFG::terminator()].
+    ///
+    /// The target of a jump is its last operand, so compare and branch instructions like
+    /// `cbz x0, 0x400` are supported. The instructions in the delay slots of a jump, see
+    /// [`Architecture::delay_slots`], belong to the basic block of the jump.
+    ///
+    /// Jump tables are not resolved, as their content is not known: the block ending with the
+    /// indirect jump has no successor. Use [CFG::with_memory()] to resolve them. The CFG built by
+    /// radare2, see
+    /// [`R2Disasm::get_function_cfg`](crate::disasm::radare2::R2Disasm::get_function_cfg),
+    /// contains instead the cases of the tables recovered by its analysis.
     /// # Examples
     /// Basic usage:
     /// ```
@@ -188,7 +263,305 @@ impl CFG {
     /// assert_eq!(cfg.len(), 4);
     /// ```
     pub fn new(stmts: &[Statement], fn_end: u64, arch: Architecture) -> CFG {
-        CFG::from(to_bare_cfg(stmts, fn_end, arch))
+        CFG::with_tables(stmts, fn_end, arch, &HashMap::new())
+    }
+
+    /// Same as [CFG::new()], but resolves the jump tables of the function reading their entries
+    /// from the given [`MemoryImage`].
+    ///
+    /// The tables of absolute addresses indexed by a register, like
+    /// `jmp qword [rax*8 + 0x4020]`, are resolved: each entry, decoded with the endianness of the
+    /// memory, is a case of the table as long as it is the offset of a statement of the function.
+    /// The block ending with the indirect jump has an edge to every case, instead of no
+    /// successor.
+    ///
+    /// On AArch64 the address of the table is built by `adrp` and `add`, and the tables emitted
+    /// by the compilers are resolved: absolute addresses read by `ldr x0, [x1, x0, lsl 3]`, and
+    /// offsets read by `ldrb`, `ldrh`, `ldrsw` or `ldr`, optionally shifted, and added to a base
+    /// set by `adr` or to the address of the table, before the `br` jumping to the result.
+    /// # Examples
+    /// Basic usage:
+    /// ```
+    /// # use bincc::analysis::{BasicBlock, Graph, CFG};
+    /// # use bincc::disasm::{Architecture, Endianness, MemoryImage, Statement, StatementFamily};
+    /// let stmts = vec![
+    ///     Statement::new(0x10, StatementFamily::CMP, "cmp eax, 2"),
+    ///     Statement::new(0x13, StatementFamily::CJMP, "ja 0x30"),
+    ///     Statement::new(0x15, StatementFamily::JMP, "jmp qword [rax*8 + 0x1000]"),
+    ///     Statement::new(0x1C, StatementFamily::MOV, "mov eax, 1"),
+    ///     Statement::new(0x21, StatementFamily::JMP, "jmp 0x30"),
+    ///     Statement::new(0x23, StatementFamily::MOV, "mov eax, 2"),
+    ///     Statement::new(0x28, StatementFamily::JMP, "jmp 0x30"),
+    ///     Statement::new(0x2A, StatementFamily::MOV, "mov eax, 3"),
+    ///     Statement::new(0x30, StatementFamily::RET, "ret"),
+    /// ];
+    /// let table = [0x1C_u64, 0x23, 0x2A]
+    ///     .iter()
+    ///     .flat_map(|case| case.to_le_bytes())
+    ///     .collect();
+    /// let memory = MemoryImage::new(Endianness::Little).with_segment(0x1000, table);
+    /// let arch = Architecture::X86(64);
+    /// let cfg = CFG::with_memory(&stmts, 0x31, arch, &memory);
+    /// let jump = BasicBlock { offset: 0x15, length: 7 };
+    ///
+    /// assert_eq!(cfg.len(), 6);
+    /// assert_eq!(cfg.neighbours(&jump).len(), 3);
+    /// assert!(CFG::new(&stmts, 0x31, arch).neighbours(&jump).is_empty());
+    /// ```
+    #[cfg(feature = "disasm")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "disasm")))]
+    pub fn with_memory(
+        stmts: &[Statement],
+        fn_end: u64,
+        arch: Architecture,
+        memory: &MemoryImage,
+    ) -> CFG {
+        let tables = jump_tables(stmts, fn_end, arch, memory);
+        CFG::with_tables(stmts, fn_end, arch, &tables)
+    }
+
+    // builds the CFG, given the cases of the jump tables indexed by the offset of the jump.
+    fn with_tables(
+        stmts: &[Statement],
+        fn_end: u64,
+        arch: Architecture,
+        tables: &HashMap<u64, Vec<u64>>,
+    ) -> CFG {
+        let (bare, terminators) = to_bare_cfg(stmts, fn_end, arch, tables);
+        let mut cfg = CFG::from(bare);
+        cfg.info = block_info(&cfg.edges, stmts, arch, |bb, _| {
+            terminators.get(&bb.offset).copied()
+        });
+        cfg
+    }
+
+    /// Creates a CFG with the shape of the given [BareCFG], labeling its basic blocks with the
+    /// statements of the function.
+    ///
+    /// This is the constructor for the CFGs computed by a disassembler, like the ones returned by
+    /// [`R2Disasm::get_function_cfg`](crate::disasm::radare2::R2Disasm::get_function_cfg): the
+    /// blocks and edges, including the cases of the jump tables recovered by the disassembler,
+    /// are the ones of the [BareCFG], while the statements provide the information recorded by
+    /// [CFG::new()], like the calls, the terminators and the amount of instructions of each
+    /// block. Statements outside every block are ignored.
+    ///
+    /// Converting the [BareCFG] with [From] instead results in a CFG without this information.
+    /// This is also the result for the architectures whose jumps are not known, see
+    /// [`Architecture::supports_jumps`].
+    /// # Examples
+    /// Basic usage:
+    /// ```
+    /// # use bincc::analysis::{BasicBlock, BlockType, CFG};
+    /// # use bincc::disasm::radare2::BareCFG;
+    /// # use bincc::disasm::{Architecture, Statement, StatementFamily};
+    /// let bare = BareCFG {
+    ///     root: Some(0x10),
+    ///     blocks: vec![(0x10, 7), (0x17, 1)],
+    ///     edges: vec![(0x10, 0x17)],
+    /// };
+    /// let stmts = vec![
+    ///     Statement::new(0x10, StatementFamily::MOV, "mov edi, 1"),
+    ///     Statement::new(0x15, StatementFamily::CALL, "call 0x40"),
+    ///     Statement::new(0x17, StatementFamily::RET, "ret"),
+    /// ];
+    /// let cfg = CFG::with_statements(bare, &stmts, Architecture::X86(64));
+    /// let bb = |offset, length| BasicBlock { offset, length };
+    ///
+    /// assert_eq!(cfg.instruction_count(&bb(0x10, 7)), Some(2));
+    /// assert!(cfg.ends_with_call(&bb(0x10, 7)));
+    /// assert_eq!(cfg.terminator(&bb(0x17, 1)), Some(BlockType::Return));
+    /// ```
+    pub fn with_statements(bare: BareCFG, stmts: &[Statement], arch: Architecture) -> CFG {
+        let mut cfg = CFG::from(bare);
+        if !arch.supports_jumps() {
+            return cfg;
+        }
+        let mut sorted = stmts.to_vec();
+        sorted.sort_by_key(Statement::get_offset);
+        cfg.info = block_info(&cfg.edges, &sorted, arch, |_, block_stmts| {
+            terminator(block_stmts, arch)
+        });
+        cfg
+    }
+
+    /// Returns how the function ends in the given basic block.
+    ///
+    /// Returns [BlockType::Return] if the block ends with a return or a jump outside the function,
+    /// [BlockType::Unreachable] if it ends with a trap or an invalid instruction, or
+    /// [Option::None] if the block does not end the function or its terminator is unknown.
+    /// Terminators are recorded only by [CFG::new()] and [CFG::with_statements()].
+    /// # Examples
+    /// Basic usage:
+    /// ```
+    /// # use bincc::analysis::{BasicBlock, BlockType, CFG};
+    /// # use bincc::disasm::{Architecture, Statement, StatementFamily};
+    /// let stmts = vec![
+    ///     Statement::new(0x10, StatementFamily::CJMP, "je 0x14"),
+    ///     Statement::new(0x12, StatementFamily::TRAP, "ud2"),
+    ///     Statement::new(0x14, StatementFamily::RET, "ret"),
+    /// ];
+    /// let cfg = CFG::new(&stmts, 0x15, Architecture::X86(64));
+    /// let bb = |offset, length| BasicBlock { offset, length };
+    ///
+    /// assert_eq!(cfg.terminator(&bb(0x10, 2)), None);
+    /// assert_eq!(cfg.terminator(&bb(0x12, 2)), Some(BlockType::Unreachable));
+    /// assert_eq!(cfg.terminator(&bb(0x14, 1)), Some(BlockType::Return));
+    /// ```
+    pub fn terminator(&self, block: &BasicBlock) -> Option<BlockType> {
+        self.info.get(block).and_then(|info| info.terminator)
+    }
+
+    /// Returns the amount of instructions contained in the given basic block.
+    ///
+    /// Returns [Option::None] if the amount is unknown: instructions are counted only by
+    /// [CFG::new()] and [CFG::with_statements()], as the other constructors are not aware of the
+    /// statements of the function.
+    /// # Examples
+    /// Basic usage:
+    /// ```
+    /// # use bincc::analysis::{BasicBlock, CFG};
+    /// # use bincc::disasm::{Architecture, Statement, StatementFamily};
+    /// let stmts = vec![
+    ///     Statement::new(0x10, StatementFamily::CMP, "cmp eax, 0"),
+    ///     Statement::new(0x13, StatementFamily::CJMP, "je 0x17"),
+    ///     Statement::new(0x15, StatementFamily::ADD, "inc eax"),
+    ///     Statement::new(0x17, StatementFamily::RET, "ret"),
+    /// ];
+    /// let cfg = CFG::new(&stmts, 0x18, Architecture::X86(64));
+    /// let head = BasicBlock { offset: 0x10, length: 5 };
+    ///
+    /// assert_eq!(cfg.instruction_count(&head), Some(2));
+    /// assert_eq!(head.byte_len(), 5);
+    /// ```
+    pub fn instruction_count(&self, block: &BasicBlock) -> Option<usize> {
+        self.info.get(block).and_then(|info| info.instructions)
+    }
+
+    /// Returns the amount of instructions of each category contained in the given basic block.
+    ///
+    /// As in [CFG::instruction_count()], this is known only for the CFGs created by [CFG::new()]
+    /// and [CFG::with_statements()].
+    /// # Examples
+    /// Basic usage:
+    /// ```
+    /// # use bincc::analysis::{BasicBlock, CFG};
+    /// # use bincc::disasm::{Architecture, InstructionCategory, Statement, StatementFamily};
+    /// let stmts = vec![
+    ///     Statement::new(0x10, StatementFamily::CMP, "cmp eax, 0"),
+    ///     Statement::new(0x13, StatementFamily::CJMP, "je 0x17"),
+    ///     Statement::new(0x15, StatementFamily::ADD, "inc eax"),
+    ///     Statement::new(0x17, StatementFamily::RET, "ret"),
+    /// ];
+    /// let cfg = CFG::new(&stmts, 0x18, Architecture::X86(64));
+    /// let head = BasicBlock { offset: 0x10, length: 5 };
+    /// let categories = cfg.categories(&head).unwrap();
+    ///
+    /// assert_eq!(categories.count(InstructionCategory::Compare), 1);
+    /// assert_eq!(categories.count(InstructionCategory::Branch), 1);
+    /// assert_eq!(categories.count(InstructionCategory::Arithmetic), 0);
+    /// ```
+    pub fn categories(&self, block: &BasicBlock) -> Option<&CategoryHistogram> {
+        self.info
+            .get(block)
+            .and_then(|info| info.categories.as_ref())
+    }
+
+    /// Returns the amount of instructions of each category contained in the whole function.
+    ///
+    /// Only the blocks reachable from the root are counted. Returns [Option::None] if the
+    /// categories of these blocks are unknown, see [CFG::categories()].
+    pub fn function_categories(&self) -> Option<CategoryHistogram> {
+        let mut total = None;
+        for bb in self.bfs() {
+            if let Some(categories) = self.categories(bb) {
+                total
+                    .get_or_insert_with(CategoryHistogram::default)
+                    .merge(categories);
+            }
+        }
+        total
+    }
+
+    /// Returns true if the given basic block is the root of this CFG.
+    pub fn is_entry(&self, block: &BasicBlock) -> bool {
+        self.root.as_ref() == Some(block)
+    }
+
+    /// Returns true if the given basic block belongs to this CFG and has no successors.
+    ///
+    /// These are the blocks leaving the function, or the sink, if added with [CFG::add_sink()].
+    pub fn is_exit(&self, block: &BasicBlock) -> bool {
+        self.edges
+            .get(block)
+            .is_some_and(|children| children.is_empty())
+    }
+
+    /// Returns true if the last instruction of the given basic block is a call.
+    ///
+    /// Calls do not end a basic block, so this happens only when the instruction following the
+    /// call is the target of a jump, or the call does not return and ends the function.
+    /// Always false for the CFGs not created by [CFG::new()] or [CFG::with_statements()], as the
+    /// other constructors are not aware of the statements of the function.
+    pub fn ends_with_call(&self, block: &BasicBlock) -> bool {
+        self.info.get(block).is_some_and(|info| info.call)
+    }
+
+    /// Returns true if the given basic block ends with a jump to a target computed at runtime,
+    /// like `jmp rax`.
+    ///
+    /// The targets of these jumps are unknown, so the block has no successors other than the
+    /// fallthrough of a conditional jump. As in [CFG::ends_with_call()], this is always false for
+    /// the CFGs not created by [CFG::new()] or [CFG::with_statements()].
+    /// # Examples
+    /// Basic usage:
+    /// ```
+    /// # use bincc::analysis::{BasicBlock, CFG};
+    /// # use bincc::disasm::{Architecture, Statement, StatementFamily};
+    /// let stmts = vec![
+    ///     Statement::new(0x10, StatementFamily::CJMP, "je 0x14"),
+    ///     Statement::new(0x12, StatementFamily::JMP, "jmp rax"),
+    ///     Statement::new(0x14, StatementFamily::RET, "ret"),
+    /// ];
+    /// let cfg = CFG::new(&stmts, 0x15, Architecture::X86(64));
+    /// let bb = |offset, length| BasicBlock { offset, length };
+    ///
+    /// assert!(cfg.is_entry(&bb(0x10, 2)));
+    /// assert!(cfg.ends_with_indirect_branch(&bb(0x12, 2)));
+    /// assert!(!cfg.ends_with_indirect_branch(&bb(0x10, 2)));
+    /// assert!(cfg.is_exit(&bb(0x14, 1)));
+    /// ```
+    pub fn ends_with_indirect_branch(&self, block: &BasicBlock) -> bool {
+        self.info.get(block).is_some_and(|info| info.indirect)
+    }
+
+    /// Returns the basic blocks of this CFG, sorted by address.
+    ///
+    /// The order is the one of [BasicBlock], so the result is the same regardless of the
+    /// construction of the CFG.
+    /// # Examples
+    /// Basic usage:
+    /// ```
+    /// use bincc::analysis::{BasicBlock, CFG};
+    /// use bincc::disasm::{Architecture, Statement, StatementFamily};
+    ///
+    /// let stmts = vec![
+    ///     Statement::new(0x610, StatementFamily::JMP, "jmp 0x618"),
+    ///     Statement::new(0x614, StatementFamily::RET, "ret"),
+    ///     Statement::new(0x618, StatementFamily::JMP, "jmp 0x614"),
+    /// ];
+    /// let cfg = CFG::new(&stmts, 0x61C, Architecture::X86(64));
+    /// let offsets = cfg
+    ///     .blocks_sorted()
+    ///     .into_iter()
+    ///     .map(|bb| bb.offset)
+    ///     .collect::<Vec<_>>();
+    ///
+    /// assert_eq!(offsets, vec![0x610, 0x614, 0x618]);
+    /// ```
+    pub fn blocks_sorted(&self) -> Vec<&BasicBlock> {
+        let mut blocks = self.edges.keys().collect::<Vec<_>>();
+        blocks.sort_unstable();
+        blocks
     }
 
     /// Returns the next basic block.
@@ -197,8 +570,8 @@ impl CFG {
     /// This means the target of an unconditional jump or the next block in case the current block
     /// ends with a conditional jump.
     ///
-    /// Returns [Option::None] if there is no next block, the current basic block does not belong to this CFG
-    /// or the original BasicBlock is None.
+    /// Returns [Option::None] if there is no next block, the current basic block does not belong to
+    /// this CFG or the original BasicBlock is None.
     pub fn next(&self, block: Option<&BasicBlock>) -> Option<&BasicBlock> {
         if let Some(bb) = block {
             let maybe_children = self.edges.get(bb);
@@ -216,8 +589,8 @@ impl CFG {
     ///
     /// Given an optional basic block, returns the conditional jump target.
     ///
-    /// Returns [Option::None] if the current basic block does not have conditional jumps, does not belong to
-    /// this CFG or the original BasicBlock is None.
+    /// Returns [Option::None] if the current basic block does not have conditional jumps, does not
+    /// belong to this CFG or the original BasicBlock is None.
     pub fn cond(&self, block: Option<&BasicBlock>) -> Option<&BasicBlock> {
         if let Some(bb) = block {
             let maybe_children = self.edges.get(bb);
@@ -235,6 +608,152 @@ impl CFG {
         }
     }
 
+    /// Checks if two CFGs have the same shape, regardless of the basic blocks offsets.
+    ///
+    /// Each basic block is renamed with its index in the reverse postorder visit of the CFG, then
+    /// the edges of the two CFGs are compared using these indices. The order of the edges matters
+    /// (i.e. a graph with swapped true and false edges is not considered the same).
+    ///
+    /// This is the cheapest equivalence check available and can be used to detect identical
+    /// functions loaded at different addresses.
+    /// # Examples
+    /// Basic usage:
+    /// ```
+    /// # use bincc::analysis::CFG;
+    /// # use bincc::disasm::{Architecture, Statement, StatementFamily};
+    /// let stmts_a = vec![
+    ///     Statement::new(0x10, StatementFamily::CJMP, "je 0x14"),
+    ///     Statement::new(0x12, StatementFamily::MOV, "mov eax, 0"),
+    ///     Statement::new(0x14, StatementFamily::RET, "ret"),
+    /// ];
+    /// let stmts_b = vec![
+    ///     Statement::new(0x80, StatementFamily::CJMP, "je 0x88"),
+    ///     Statement::new(0x82, StatementFamily::MOV, "mov eax, 0"),
+    ///     Statement::new(0x88, StatementFamily::RET, "ret"),
+    /// ];
+    /// let arch = Architecture::X86(64);
+    /// let cfg_a = CFG::new(&stmts_a, 0x15, arch);
+    /// let cfg_b = CFG::new(&stmts_b, 0x89, arch);
+    ///
+    /// assert!(cfg_a.same_shape(&cfg_b));
+    /// ```
+    pub fn same_shape(&self, other: &CFG) -> bool {
+        if self.len() != other.len() {
+            return false;
+        }
+        let rpo_self = self.dfs_postorder().collect::<Vec<_>>();
+        let rpo_other = other.dfs_postorder().collect::<Vec<_>>();
+        if rpo_self.len() != rpo_other.len() {
+            return false;
+        }
+        let lookup_self = rpo_self
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(index, node)| (*node, index))
+            .collect::<HashMap<_, _>>();
+        let lookup_other = rpo_other
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(index, node)| (*node, index))
+            .collect::<HashMap<_, _>>();
+        rpo_self
+            .iter()
+            .rev()
+            .zip(rpo_other.iter().rev())
+            .all(|(node_self, node_other)| {
+                let children_self = self
+                    .neighbours(node_self)
+                    .iter()
+                    .map(|child| lookup_self.get(child));
+                let children_other = other
+                    .neighbours(node_other)
+                    .iter()
+                    .map(|child| lookup_other.get(child));
+                children_self.eq(children_other)
+            })
+    }
+
+    /// Calculates a Weisfeiler-Lehman hash of the shape of the CFG.
+    ///
+    /// Every basic block reachable from the root is labeled with its amount of predecessors and
+    /// successors, then, for the given amount of iterations, each label is replaced by the hash of
+    /// itself, the labels of its successors in order, and the sorted labels of its predecessors.
+    /// The result is the hash of the sorted final labels. As in [`CFG::same_shape`], the offsets
+    /// of the basic blocks are ignored, so CFGs with the same shape have the same hash, while CFGs
+    /// with different shapes have different hashes with high probability. The hash is stable
+    /// across executions and platforms.
+    /// # Examples
+    /// Basic usage:
+    /// ```
+    /// # use bincc::analysis::CFG;
+    /// # use bincc::disasm::{Architecture, Statement, StatementFamily};
+    /// let stmts_a = vec![
+    ///     Statement::new(0x10, StatementFamily::CJMP, "je 0x14"),
+    ///     Statement::new(0x12, StatementFamily::MOV, "mov eax, 0"),
+    ///     Statement::new(0x14, StatementFamily::RET, "ret"),
+    /// ];
+    /// let stmts_b = vec![
+    ///     Statement::new(0x80, StatementFamily::CJMP, "je 0x88"),
+    ///     Statement::new(0x82, StatementFamily::MOV, "mov eax, 0"),
+    ///     Statement::new(0x88, StatementFamily::RET, "ret"),
+    /// ];
+    /// let arch = Architecture::X86(64);
+    /// let cfg_a = CFG::new(&stmts_a, 0x15, arch);
+    /// let cfg_b = CFG::new(&stmts_b, 0x89, arch);
+    ///
+    /// assert_eq!(cfg_a.wl_hash(3), cfg_b.wl_hash(3));
+    /// ```
+    pub fn wl_hash(&self, iterations: usize) -> u64 {
+        let nodes = self.bfs().collect::<Vec<_>>();
+        let mut preds = HashMap::<&BasicBlock, Vec<&BasicBlock>>::new();
+        for node in &nodes {
+            for child in self.neighbours(node) {
+                preds.entry(child).or_default().push(node);
+            }
+        }
+        let hash = |values: &[u64]| {
+            let mut hasher = FnvHasher::default();
+            // fixed endianness, so the hash does not depend on the platform
+            values
+                .iter()
+                .for_each(|value| hasher.write(&value.to_le_bytes()));
+            hasher.finish()
+        };
+        let mut labels = nodes
+            .iter()
+            .map(|node| {
+                let in_degree = preds.get(node).map_or(0, Vec::len) as u64;
+                (
+                    *node,
+                    hash(&[in_degree, self.neighbours(node).len() as u64]),
+                )
+            })
+            .collect::<HashMap<_, _>>();
+        for _ in 0..iterations {
+            labels = nodes
+                .iter()
+                .map(|node| {
+                    let mut values = vec![labels[node]];
+                    values.extend(self.neighbours(node).iter().map(|child| labels[child]));
+                    let mut incoming = preds
+                        .get(node)
+                        .into_iter()
+                        .flatten()
+                        .map(|pred| labels[pred])
+                        .collect::<Vec<_>>();
+                    incoming.sort_unstable();
+                    values.extend(incoming);
+                    (*node, hash(&values))
+                })
+                .collect();
+        }
+        let mut labels = labels.into_values().collect::<Vec<_>>();
+        labels.sort_unstable();
+        hash(&labels)
+    }
+
     /// Converts the current CFG into a Graphviz dot representation.
     ///
     /// The generated file contains also each Basic Blocks starting and ending offset.
@@ -300,9 +819,11 @@ impl CFG {
     /// [CFG::to_file] methods. This method expects some additional metadata that otherwise is not
     /// present in a dot file.
     ///
-    /// This method returns [std::io::Error] in case of malformed input or [std::num::ParseIntError]
-    /// in case the input file contains non-parsable numbers.
-    pub fn from_dot(str: &str) -> Result<CFG, Box<dyn Error>> {
+    /// This method returns [`Error::InvalidInput`] in case of malformed input, including
+    /// non-parsable numbers.
+    #[cfg(feature = "io")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "io")))]
+    pub fn from_dot(str: &str) -> Result<CFG, Error> {
         // this parser is super dumb, but even a smart one will never work with **any** .dot file
         // because I need to store some metadata about nodes
         let mut lines = str.lines().collect::<Vec<_>>();
@@ -319,11 +840,17 @@ impl CFG {
             lazy_static! {
                 static ref DOT_EDGES_RE: Regex = Regex::new(r#"(\d+)->(\d+)(?:\[.*];)?"#).unwrap();
             }
+            let number = |cap: &Captures, group: usize| {
+                let value = cap.get(group).map_or("", |m| m.as_str());
+                value
+                    .parse::<u64>()
+                    .map_err(|err| Error::InvalidInput(format!("{}: {}", err, value)))
+            };
             while let Some(line) = lines.pop() {
                 if let Some(cap) = node_re.captures(line) {
-                    let id = cap.get(1).unwrap().as_str().parse::<usize>()?;
-                    let offset = cap.get(2).unwrap().as_str().parse::<u64>()?;
-                    let length = cap.get(3).unwrap().as_str().parse::<u64>()?;
+                    let id = number(&cap, 1)?;
+                    let offset = number(&cap, 2)?;
+                    let length = number(&cap, 3)?;
                     let node = BasicBlock { offset, length };
                     if let Some(shape) = cap.get(4) {
                         if shape.as_str() == EXTERN_DOT_ROOT {
@@ -332,22 +859,17 @@ impl CFG {
                     }
                     nodes.insert(id, node);
                 } else if let Some(cap) = DOT_EDGES_RE.captures(line) {
-                    let from = cap.get(1).unwrap().as_str().parse::<usize>()?;
-                    let to = cap.get(2).unwrap().as_str().parse::<usize>()?;
+                    let from = number(&cap, 1)?;
+                    let to = number(&cap, 2)?;
                     edges_ids
                         .entry(from)
-                        .and_modify(|e: &mut Vec<usize>| e.push(to))
+                        .and_modify(|e: &mut Vec<u64>| e.push(to))
                         .or_insert_with(|| vec![to]);
                 }
             }
             let mut edges = HashMap::new();
             // Invalid files may have inconsistent data. This error is used to avoid panicking.
-            let parse_err = || {
-                Box::new(std::io::Error::new(
-                    ErrorKind::InvalidInput,
-                    "inconsistent data",
-                ))
-            };
+            let parse_err = || Error::InvalidInput("inconsistent data".to_string());
             for (src, dst_vec) in edges_ids {
                 let src_node = *nodes.get(&src).ok_or_else(parse_err)?;
                 for dst in dst_vec {
@@ -367,12 +889,14 @@ impl CFG {
             for exit in exits {
                 edges.insert(exit, Vec::with_capacity(0));
             }
-            Ok(CFG { root, edges })
+            Ok(CFG {
+                root,
+                edges,
+                info: HashMap::new(),
+                metadata: None,
+            })
         } else {
-            Err(Box::new(std::io::Error::new(
-                ErrorKind::InvalidInput,
-                "unexpected input filetype",
-            )))
+            Err(Error::InvalidInput("unexpected input filetype".to_string()))
         }
     }
 
@@ -380,6 +904,8 @@ impl CFG {
     ///
     /// Given a path to file, saves the current CFG as a Graphviz .dot file.
     /// This is equivalent of calling [CFG::to_dot()] and then saving the String content to file.
+    #[cfg(feature = "io")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "io")))]
     pub fn to_file<S: AsRef<Path>>(&self, filename: S) -> Result<(), io::Error> {
         let mut file = File::create(filename)?;
         file.write_all(self.to_dot().as_bytes())
@@ -390,9 +916,11 @@ impl CFG {
     /// Given a path to file, retrieves a CFG previously created with [CFG::to_file] method (or with
     /// [CFG::to_dot] later saved to a file).
     ///
-    /// This method returns [std::io::Error] in case of malformed input or [std::num::ParseIntError]
-    /// in case the input file contains non-parsable numbers.
-    pub fn from_file<S: AsRef<Path>>(filename: S) -> Result<CFG, Box<dyn Error>> {
+    /// This method returns [`Error::Io`] if the file can not be read, or the same errors of
+    /// [CFG::from_dot].
+    #[cfg(feature = "io")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "io")))]
+    pub fn from_file<S: AsRef<Path>>(filename: S) -> Result<CFG, Error> {
         let mut file = File::open(filename)?;
         let mut content = String::new();
         file.read_to_string(&mut content)?;
@@ -446,6 +974,109 @@ impl CFG {
         }
         self
     }
+
+    /// Moves the current CFG from the load address `from` to the load address `to`.
+    ///
+    /// Every address is moved by the same amount: the basic blocks, the call sites, the direct
+    /// call targets and the start of the [`FunctionMetadata`]. The artificial sink and entry point
+    /// are left untouched. Rebasing the CFGs of a position independent executable to the same
+    /// load address makes them comparable at the address level.
+    /// # Examples
+    /// Basic usage:
+    /// ```
+    /// # use bincc::analysis::{BasicBlock, Graph, CFG};
+    /// # use bincc::disasm::{Architecture, Statement, StatementFamily};
+    /// let stmts = vec![
+    ///     Statement::new(0x1010, StatementFamily::CJMP, "je 0x1014"),
+    ///     Statement::new(0x1012, StatementFamily::MOV, "mov eax, 0"),
+    ///     Statement::new(0x1014, StatementFamily::RET, "ret"),
+    /// ];
+    /// let cfg = CFG::new(&stmts, 0x1015, Architecture::X86(64)).rebase(0x1000, 0x5000);
+    ///
+    /// assert_eq!(cfg.root().unwrap().offset, 0x5010);
+    /// assert_eq!(cfg.neighbours(&BasicBlock { offset: 0x5010, length: 2 }).len(), 2);
+    /// ```
+    #[must_use]
+    pub fn rebase(self, from: u64, to: u64) -> CFG {
+        let delta = to.wrapping_sub(from);
+        let move_block = |block: BasicBlock| {
+            if block.is_sink() || block.is_entry_point() {
+                block
+            } else {
+                BasicBlock {
+                    offset: block.offset.wrapping_add(delta),
+                    length: block.length,
+                }
+            }
+        };
+        let edges = self
+            .edges
+            .into_iter()
+            .map(|(src, dst)| (move_block(src), dst.into_iter().map(move_block).collect()))
+            .collect();
+        let info = self
+            .info
+            .into_iter()
+            .map(|(block, mut info)| {
+                info.calls.iter_mut().for_each(|call| call.rebase(delta));
+                info.references
+                    .iter_mut()
+                    .for_each(|reference| reference.rebase(delta));
+                (move_block(block), info)
+            })
+            .collect();
+        let metadata = self.metadata.map(|mut metadata| {
+            metadata.rebase(delta);
+            metadata
+        });
+        CFG {
+            root: self.root.map(move_block),
+            edges,
+            info,
+            metadata,
+        }
+    }
+}
+
+impl Display for CFG {
+    /// Writes the adjacency list of the CFG, one block per line in address order, followed by its
+    /// successors in order (the fallthrough first). The root is marked with a `*`.
+    /// # Examples
+    /// Basic usage:
+    /// ```
+    /// use bincc::analysis::CFG;
+    /// use bincc::disasm::{Architecture, Statement, StatementFamily};
+    ///
+    /// let stmts = vec![
+    ///     Statement::new(0x610, StatementFamily::CJMP, "je 0x618"),
+    ///     Statement::new(0x614, StatementFamily::MOV, "mov eax, 6"),
+    ///     Statement::new(0x618, StatementFamily::RET, "ret"),
+    /// ];
+    /// let cfg = CFG::new(&stmts, 0x619, Architecture::X86(64));
+    ///
+    /// assert_eq!(
+    ///     cfg.to_string(),
+    ///     "* 0x610..0x614 -> 0x614..0x618, 0x618..0x619\n\
+    ///      \x20 0x614..0x618 -> 0x618..0x619\n\
+    ///      \x20 0x618..0x619 ->\n"
+    /// );
+    /// ```
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for bb in self.blocks_sorted() {
+            let mark = if self.root.as_ref() == Some(bb) {
+                '*'
+            } else {
+                ' '
+            };
+            write!(f, "{} {} ->", mark, bb)?;
+            for (index, succ) in self.edges[bb].iter().enumerate() {
+                let separator = if index == 0 { " " } else { ", " };
+                write!(f, "{}{}", separator, succ)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
 }
 
 impl Graph for CFG {
@@ -476,34 +1107,356 @@ struct TargetMap {
     srcs_cond: FnvHashMap<u64, u64>,
     // map for unconditional jumps: <source offset, dest offset>
     srcs_uncond: FnvHashMap<u64, u64>,
+    // map for jumps through a jump table: <source offset, dest offsets>
+    srcs_table: FnvHashMap<u64, Vec<u64>>,
     // set for conditional returns containing the return offset
     deadend_cond: BTreeSet<u64>,
     // set for unconditional returns containing the return offset
     deadend_uncond: BTreeSet<u64>,
+    // set for traps and invalid instructions containing their offset
+    traps: BTreeSet<u64>,
+}
+
+// destination of a direct jump: the last operand, as in `cbz x0, 0x400` or `tbnz w1, 3, 0x400`.
+pub(super) fn jump_target(stmt: &Statement) -> Option<u64> {
+    let args = stmt.get_args();
+    let target = args.rsplit_once(',').map_or(args, |(_, last)| last.trim());
+    parse_number(target)
+}
+
+// unsigned number written in decimal or, with the `0x`, `0o` or `0b` prefixes, in hexadecimal,
+// octal or binary.
+pub(super) fn parse_number(string: &str) -> Option<u64> {
+    let (digits, radix) = if let Some(hex) = string.strip_prefix("0x") {
+        (hex, 16)
+    } else if let Some(oct) = string.strip_prefix("0o") {
+        (oct, 8)
+    } else if let Some(bin) = string.strip_prefix("0b") {
+        (bin, 2)
+    } else {
+        (string, 10)
+    };
+    u64::from_str_radix(digits, radix).ok()
+}
+
+// cases of the jump tables of the function, indexed by the offset of the indirect jump reading
+// them. The entries are read until one of them is not the offset of a statement of the function.
+#[cfg(feature = "disasm")]
+fn jump_tables(
+    stmts: &[Statement],
+    fn_end: u64,
+    arch: Architecture,
+    memory: &MemoryImage,
+) -> HashMap<u64, Vec<u64>> {
+    let size = u64::from(arch.bits() / 8);
+    let offsets = stmts
+        .iter()
+        .map(Statement::get_offset)
+        .filter(|offset| *offset < fn_end)
+        .collect::<HashSet<_>>();
+    let mut tables = HashMap::new();
+    for (position, stmt) in stmts.iter().enumerate() {
+        if arch.jump(stmt.get_mnemonic()) != JumpType::JumpUnconditional
+            || jump_target(stmt).is_some()
+        {
+            continue;
+        }
+        let table = match arch {
+            Architecture::Arm(64) => aarch64_table(&stmts[..position], stmt.get_args()),
+            _ => table_address(stmt.get_args(), size).map(|address| JumpTable {
+                address,
+                size,
+                signed: false,
+                base: None,
+            }),
+        };
+        let Some(table) = table else {
+            continue;
+        };
+        let mut cases = Vec::new();
+        for index in 0..MAX_TABLE_ENTRIES {
+            let entry = table
+                .case(memory, index)
+                .filter(|case| offsets.contains(case));
+            match entry {
+                Some(case) if !cases.contains(&case) => cases.push(case),
+                Some(_) => {}
+                None => break,
+            }
+        }
+        if !cases.is_empty() {
+            tables.insert(stmt.get_offset(), cases);
+        }
+    }
+    tables
+}
+
+// address of a table of pointers with the given size read by a memory operand like
+// `qword [rax*8 + 0x4020]`: a constant displacement and a register scaled by the size.
+#[cfg(feature = "disasm")]
+fn table_address(args: &str, size: u64) -> Option<u64> {
+    let start = args.find('[')?;
+    let end = args.rfind(']')?;
+    let mut scale = None;
+    let mut displacement = None;
+    for term in args.get(start + 1..end)?.split('+').map(str::trim) {
+        match term.split_once('*') {
+            Some((_, factor)) if scale.is_none() => scale = Some(parse_number(factor.trim())?),
+            // a base register makes the address of the table unknown
+            None if displacement.is_none() => displacement = Some(parse_number(term)?),
+            _ => return None,
+        }
+    }
+    (scale? == size).then_some(displacement?)
+}
+
+// table of the cases of an indirect jump.
+#[cfg(feature = "disasm")]
+struct JumpTable {
+    // address of the first entry
+    address: u64,
+    // size in bytes of each entry
+    size: u64,
+    // true if the entries are sign-extended
+    signed: bool,
+    // for tables of offsets, the address they are relative to and the left shift applied to them
+    base: Option<(u64, u32)>,
+}
+
+#[cfg(feature = "disasm")]
+impl JumpTable {
+    // returns the case stored in the entry with the given index.
+    fn case(&self, memory: &MemoryImage, index: u64) -> Option<u64> {
+        let address = self.address.checked_add(index.checked_mul(self.size)?)?;
+        let raw = match self.size {
+            1 => u64::from(memory.read(address, 1)?[0]),
+            2 => u64::from(memory.read_u16(address)?),
+            4 => u64::from(memory.read_u32(address)?),
+            8 => memory.read_u64(address)?,
+            _ => return None,
+        };
+        let unused = 64 - 8 * self.size as u32;
+        let value = match self.signed {
+            true => (((raw << unused) as i64) >> unused) as u64,
+            false => raw,
+        };
+        match self.base {
+            Some((base, shift)) => Some(base.wrapping_add(value << shift)),
+            None => Some(value),
+        }
+    }
+}
+
+// table read to compute the register of an AArch64 `br`, in one of the forms emitted by the
+// compilers:
+// - offsets added to a base: `ldrb w0, [x1, w0, uxtw]`, `adr x2, base`,
+//   `add x0, x2, w0, sxtb 2`, `br x0`
+// - offsets added to the table: `ldrsw x0, [x1, x0, lsl 2]`, `add x0, x1, x0`, `br x0`
+// - absolute addresses: `ldr x0, [x1, x0, lsl 3]`, `br x0`
+// where the address of the table in x1 is set by `adrp` and `add`.
+#[cfg(feature = "disasm")]
+fn aarch64_table(stmts: &[Statement], args: &str) -> Option<JumpTable> {
+    let target = *aarch64_operands(args).first()?;
+    let (def, before) = last_write(stmts, target)?;
+    match (
+        def.get_mnemonic(),
+        aarch64_operands(def.get_args()).as_slice(),
+    ) {
+        ("add", [_, base, index, extension @ ..]) => {
+            let (extend, shift) = match extension {
+                [] => (None, 0),
+                [extension] => aarch64_extension(extension)?,
+                _ => return None,
+            };
+            let base = register_value(before, base)?;
+            let (load, before_load) = last_write(before, index)?;
+            let mut table = aarch64_load(load, before_load)?;
+            match extend {
+                // the loaded value is truncated
+                Some((size, _)) if size < table.size => return None,
+                Some((size, signed)) if size == table.size => table.signed = signed,
+                _ => {}
+            }
+            table.base = Some((base, shift));
+            Some(table)
+        }
+        (mnemonic, _) if mnemonic.starts_with("ldr") => {
+            aarch64_load(def, before).filter(|table| table.size == 8)
+        }
+        _ => None,
+    }
+}
+
+// table read by a load indexed by a register, like `ldrh w0, [x1, w0, uxtw 1]`, with the size
+// and signedness of the loaded entries.
+#[cfg(feature = "disasm")]
+fn aarch64_load(load: &Statement, before: &[Statement]) -> Option<JumpTable> {
+    let operands = aarch64_operands(load.get_args());
+    let [dst, memory] = operands.as_slice() else {
+        return None;
+    };
+    let (size, signed) = match load.get_mnemonic() {
+        "ldrb" => (1, false),
+        "ldrsb" => (1, true),
+        "ldrh" => (2, false),
+        "ldrsh" => (2, true),
+        "ldrsw" => (4, true),
+        "ldr" if dst.starts_with('w') => (4, false),
+        "ldr" if dst.starts_with('x') => (8, false),
+        _ => return None,
+    };
+    let inner = memory.strip_prefix('[')?.strip_suffix(']')?;
+    let shift = match aarch64_operands(inner).as_slice() {
+        [_, index] if parse_number(index).is_none() => 0,
+        [_, _, extension] => aarch64_extension(extension)?.1,
+        _ => return None,
+    };
+    // the index must be scaled by the size of the entries
+    if 1_u64 << shift != size {
+        return None;
+    }
+    let table = aarch64_operands(inner)[0];
+    Some(JumpTable {
+        address: register_value(before, table)?,
+        size,
+        signed,
+        base: None,
+    })
+}
+
+// size in bytes and signedness of an extension like `sxtb 2`, if any, and the left shift applied
+// after it.
+#[cfg(feature = "disasm")]
+fn aarch64_extension(extension: &str) -> Option<(Option<(u64, bool)>, u32)> {
+    let mut parts = extension.split_whitespace();
+    let extend = match parts.next()? {
+        "lsl" => None,
+        "uxtb" => Some((1, false)),
+        "sxtb" => Some((1, true)),
+        "uxth" => Some((2, false)),
+        "sxth" => Some((2, true)),
+        "uxtw" => Some((4, false)),
+        "sxtw" => Some((4, true)),
+        "uxtx" | "sxtx" => Some((8, false)),
+        _ => return None,
+    };
+    let shift = match parts.next() {
+        Some(amount) => u32::try_from(parse_number(amount.trim_start_matches('#'))?).ok()?,
+        None => 0,
+    };
+    (shift <= 4).then_some((extend, shift))
+}
+
+// constant value of the register, set by `adrp` or `adr` and optionally incremented by `add`.
+#[cfg(feature = "disasm")]
+fn register_value(stmts: &[Statement], register: &str) -> Option<u64> {
+    let (def, before) = last_write(stmts, register)?;
+    match (
+        def.get_mnemonic(),
+        aarch64_operands(def.get_args()).as_slice(),
+    ) {
+        ("adrp" | "adr", [_, address]) => parse_number(address),
+        ("add", [_, source, offset]) => {
+            let offset = parse_number(offset)?;
+            register_value(before, source)?.checked_add(offset)
+        }
+        _ => None,
+    }
+}
+
+// last statement writing the given AArch64 register, and the statements preceding it.
+#[cfg(feature = "disasm")]
+fn last_write<'a>(
+    stmts: &'a [Statement],
+    register: &str,
+) -> Option<(&'a Statement, &'a [Statement])> {
+    // the first operand of these instructions is read, not written
+    let reads = |mnemonic: &str| {
+        let compares = [
+            "cmp", "cmn", "tst", "ccmp", "ccmn", "cbz", "cbnz", "tbz", "tbnz",
+        ];
+        let branches = ["b", "bl", "br", "blr", "ret"];
+        mnemonic.starts_with("st")
+            || mnemonic.starts_with("b.")
+            || compares.contains(&mnemonic)
+            || branches.contains(&mnemonic)
+    };
+    let register = register_id(register);
+    let position = stmts.iter().rposition(|stmt| {
+        !reads(stmt.get_mnemonic())
+            && aarch64_operands(stmt.get_args())
+                .first()
+                .is_some_and(|dst| register_id(dst) == register)
+    })?;
+    Some((&stmts[position], &stmts[..position]))
+}
+
+// number of an AArch64 general purpose register, so `w0` and `x0` are the same register.
+#[cfg(feature = "disasm")]
+fn register_id(register: &str) -> &str {
+    match register.strip_prefix(|c| c == 'w' || c == 'x') {
+        Some(number) if !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit()) => number,
+        _ => register,
+    }
+}
+
+// operands of an AArch64 instruction, without the `#` of the immediates. The commas of a memory
+// operand, like `[x1, w0, uxtw]`, do not separate operands.
+#[cfg(feature = "disasm")]
+fn aarch64_operands(args: &str) -> Vec<&str> {
+    let mut operands = Vec::new();
+    let mut depth = 0_usize;
+    let mut start = 0;
+    for (index, c) in args.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                operands.push(args[start..index].trim().trim_start_matches('#'));
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    let last = args[start..].trim();
+    if !last.is_empty() {
+        operands.push(last.trim_start_matches('#'));
+    }
+    operands
 }
 
 // given a list of Statements and an Architecture creates the TargetMap struct
-fn get_targets(stmts: &[Statement], arch: Architecture) -> TargetMap {
+fn get_targets(
+    stmts: &[Statement],
+    arch: Architecture,
+    tables: &HashMap<u64, Vec<u64>>,
+) -> TargetMap {
     let mut targets = BTreeSet::default();
     let mut srcs_cond = FnvHashMap::default();
     let mut srcs_uncond = FnvHashMap::default();
+    let mut srcs_table = FnvHashMap::default();
     let mut deadend_cond = BTreeSet::default();
     let mut deadend_uncond = BTreeSet::default();
+    let mut traps = BTreeSet::default();
     let empty_stmt = Statement::new(0x0, StatementFamily::UNK, "");
     let func_lower_bound = stmts.first().unwrap_or(&empty_stmt).get_offset();
     let func_upper_bound = stmts.last().unwrap_or(&empty_stmt).get_offset();
-    let mut previous_was_jump = true;
+    // amount of statements before the next block starts: jumps end their block after the delay
+    // slots
+    let mut block_end = 1;
+    let delay = arch.delay_slots();
     for stmt in stmts {
-        if previous_was_jump {
-            previous_was_jump = false;
-            targets.insert(stmt.get_offset());
+        if block_end > 0 {
+            block_end -= 1;
+            if block_end == 0 {
+                targets.insert(stmt.get_offset());
+            }
         }
         let mnemonic = stmt.get_mnemonic();
         let jump_type = arch.jump(mnemonic);
         match jump_type {
             JumpType::JumpUnconditional => {
-                let maybe_target = parse::<u64>(stmt.get_args());
-                if let Ok(target) = maybe_target {
+                if let Some(target) = jump_target(stmt) {
                     // direct jump
                     if target >= func_lower_bound && target <= func_upper_bound {
                         // inside the current function
@@ -513,15 +1466,18 @@ fn get_targets(stmts: &[Statement], arch: Architecture) -> TargetMap {
                         // unconditional jump outside the function, so it's like a return
                         deadend_uncond.insert(stmt.get_offset());
                     }
+                } else if let Some(cases) = tables.get(&stmt.get_offset()) {
+                    // jump through a table with known content
+                    srcs_table.insert(stmt.get_offset(), cases.clone());
+                    targets.extend(cases);
                 } else {
                     // unconditional jump to an unknown target. this is a problem.
                     deadend_uncond.insert(stmt.get_offset());
                 }
-                previous_was_jump = true;
+                block_end = delay + 1;
             }
             JumpType::JumpConditional => {
-                let maybe_target = parse::<u64>(stmt.get_args());
-                if let Ok(target) = maybe_target {
+                if let Some(target) = jump_target(stmt) {
                     // direct jump
                     if target >= func_lower_bound && target <= func_upper_bound {
                         // inside the current function
@@ -529,31 +1485,128 @@ fn get_targets(stmts: &[Statement], arch: Architecture) -> TargetMap {
                     }
                     targets.insert(target);
                 }
-                previous_was_jump = true;
+                block_end = delay + 1;
             }
             JumpType::RetUnconditional => {
                 deadend_uncond.insert(stmt.get_offset());
-                previous_was_jump = true;
+                block_end = delay + 1;
             }
             JumpType::RetConditional => {
                 deadend_cond.insert(stmt.get_offset());
-                previous_was_jump = true;
+                block_end = delay + 1;
+            }
+            JumpType::NoJump => {
+                if matches!(
+                    stmt.get_family(),
+                    StatementFamily::TRAP | StatementFamily::ILL
+                ) {
+                    traps.insert(stmt.get_offset());
+                    block_end = 1;
+                }
             }
-            JumpType::NoJump => {}
         }
     }
     TargetMap {
         targets,
         srcs_cond,
         srcs_uncond,
+        srcs_table,
         deadend_cond,
         deadend_uncond,
+        traps,
     }
 }
 
-// actual cfg building
-fn to_bare_cfg(stmts: &[Statement], fn_end: u64, arch: Architecture) -> BareCFG {
-    let tgmap = get_targets(stmts, arch);
+// information about each block, given the statements of the function sorted by offset and the
+// terminator of each block ending the function.
+fn block_info<F>(
+    edges: &HashMap<BasicBlock, Vec<BasicBlock>>,
+    stmts: &[Statement],
+    arch: Architecture,
+    terminator: F,
+) -> HashMap<BasicBlock, BlockInfo>
+where
+    F: Fn(&BasicBlock, &[Statement]) -> Option<BlockType>,
+{
+    let blocks = edges.keys().map(|bb| bb.offset).collect::<HashSet<_>>();
+    edges
+        .iter()
+        .map(|(bb, children)| {
+            let block_end = bb.offset + bb.length;
+            let start = stmts.partition_point(|stmt| stmt.get_offset() < bb.offset);
+            let end = stmts.partition_point(|stmt| stmt.get_offset() < block_end);
+            let block_stmts = &stmts[start..end];
+            let terminator = match children.is_empty() {
+                true => terminator(bb, block_stmts),
+                false => None,
+            };
+            let calls = CallSite::from_block(block_stmts, block_end);
+            let references = Reference::from_block(block_stmts, block_end, &blocks, arch);
+            // the block ends with the last jump or call, followed only by its delay slots
+            let last = block_stmts
+                .iter()
+                .rev()
+                .take(arch.delay_slots() + 1)
+                .find(|stmt| {
+                    stmt.get_family() == StatementFamily::CALL
+                        || arch.jump(stmt.get_mnemonic()) != JumpType::NoJump
+                })
+                .or(block_stmts.last());
+            let call = last.is_some_and(|stmt| stmt.get_family() == StatementFamily::CALL);
+            let indirect = last.is_some_and(|stmt| {
+                let jump = arch.jump(stmt.get_mnemonic());
+                matches!(
+                    jump,
+                    JumpType::JumpConditional | JumpType::JumpUnconditional
+                ) && stmt.get_family() != StatementFamily::RET
+                    && jump_target(stmt).is_none()
+            });
+            let info = BlockInfo {
+                instructions: Some(end.saturating_sub(start)),
+                terminator,
+                call,
+                indirect,
+                calls,
+                references,
+                categories: Some(CategoryHistogram::new(block_stmts)),
+            };
+            (*bb, info)
+        })
+        .collect()
+}
+
+// kind of the last instruction of a block without successors, as recorded by CFG::new: returns
+// and unconditional jumps leave the function, traps and invalid instructions never complete.
+fn terminator(block_stmts: &[Statement], arch: Architecture) -> Option<BlockType> {
+    let last = block_stmts
+        .iter()
+        .rev()
+        .take(arch.delay_slots() + 1)
+        .find(|stmt| arch.jump(stmt.get_mnemonic()) != JumpType::NoJump)
+        .or(block_stmts.last())?;
+    match arch.jump(last.get_mnemonic()) {
+        JumpType::RetUnconditional | JumpType::JumpUnconditional => Some(BlockType::Return),
+        JumpType::NoJump
+            if matches!(
+                last.get_family(),
+                StatementFamily::TRAP | StatementFamily::ILL
+            ) =>
+        {
+            Some(BlockType::Unreachable)
+        }
+        _ => None,
+    }
+}
+
+// actual cfg building, returning also the kind of the last instruction of the blocks ending the
+// function
+fn to_bare_cfg(
+    stmts: &[Statement],
+    fn_end: u64,
+    arch: Architecture,
+    tables: &HashMap<u64, Vec<u64>>,
+) -> (BareCFG, HashMap<u64, BlockType>) {
+    let tgmap = get_targets(stmts, arch, tables);
     // This target is used for a strictly lower bound.
     let mut nodes = Vec::with_capacity(tgmap.targets.len());
     // the capacity here is not perfect but it's a good estimation
@@ -569,7 +1622,7 @@ fn to_bare_cfg(stmts: &[Statement], fn_end: u64, arch: Architecture) -> BareCFG
             edges.push((*current, *next_target));
         }
     }
-    // remove node->next_node edges where node contains a jump or a return
+    // remove node->next_node edges where node contains a jump, a return or a trap
     let nodes_ordered = nodes
         .iter()
         .map(|&(first, _)| first)
@@ -578,12 +1631,14 @@ fn to_bare_cfg(stmts: &[Statement], fn_end: u64, arch: Architecture) -> BareCFG
         .srcs_cond
         .keys()
         .chain(tgmap.srcs_uncond.keys())
+        .chain(tgmap.srcs_table.keys())
         .map(|src| *nodes_ordered.range(..=src).next_back().unwrap())
         .collect::<HashSet<_>>();
     let return_blocks = tgmap
         .deadend_cond
         .iter()
         .chain(tgmap.deadend_uncond.iter())
+        .chain(tgmap.traps.iter())
         .map(|src| *nodes_ordered.range(..=src).next_back().unwrap())
         .collect::<HashSet<_>>();
     edges = edges
@@ -591,11 +1646,22 @@ fn to_bare_cfg(stmts: &[Statement], fn_end: u64, arch: Architecture) -> BareCFG
         .filter(|(src, _)| !jump_blocks.contains(src))
         .filter(|(src, _)| !return_blocks.contains(src))
         .collect();
+    let terminators = tgmap
+        .deadend_uncond
+        .iter()
+        .map(|src| (*src, BlockType::Return))
+        .chain(tgmap.traps.iter().map(|src| (*src, BlockType::Unreachable)))
+        .map(|(src, bt)| (*nodes_ordered.range(..=src).next_back().unwrap(), bt))
+        .collect::<HashMap<_, _>>();
     // add jump edges
     for (off_src, off_dst) in tgmap.srcs_uncond {
         let src_bb = *nodes_ordered.range(..=off_src).next_back().unwrap();
         edges.push((src_bb, off_dst));
     }
+    for (off_src, cases) in tgmap.srcs_table {
+        let src_bb = *nodes_ordered.range(..=off_src).next_back().unwrap();
+        edges.extend(cases.into_iter().map(|case| (src_bb, case)));
+    }
     for (off_src, off_dst) in tgmap.srcs_cond {
         let src_bb = *nodes_ordered.range(..=off_src).next_back().unwrap();
         let next_dst = *nodes_ordered.range(off_src + 1..).next().unwrap_or(&fn_end);
@@ -620,22 +1686,26 @@ fn to_bare_cfg(stmts: &[Statement], fn_end: u64, arch: Architecture) -> BareCFG
         // every preds has at least 1 entry, pick the lowest offset
         root = nodes.iter().min().map(|(first, _)| first).copied();
     }
-    BareCFG {
+    let bare = BareCFG {
         root,
         blocks: nodes,
         edges,
-    }
+    };
+    (bare, terminators)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::analysis::{BasicBlock, Graph, CFG};
+    use crate::analysis::{BasicBlock, BlockType, CallTarget, FunctionMetadata, Graph, CFG};
     use crate::disasm::radare2::BareCFG;
-    use crate::disasm::{Architecture, Statement, StatementFamily};
+    use crate::disasm::{Architecture, InstructionCategory, Statement, StatementFamily};
     use maplit::hashmap;
     use std::collections::{HashMap, HashSet};
+    #[cfg(feature = "io")]
     use std::error::Error;
+    #[cfg(feature = "io")]
     use std::io::{Read, Seek, SeekFrom, Write};
+    #[cfg(feature = "io")]
     use tempfile::tempfile;
 
     /// Removes unreachable nodes.
@@ -655,6 +1725,8 @@ mod tests {
             CFG {
                 root: cfg.root,
                 edges,
+                info: HashMap::new(),
+                metadata: None,
             }
         } else {
             cfg
@@ -679,6 +1751,8 @@ mod tests {
         CFG {
             root: Some(nodes[0]),
             edges,
+            info: HashMap::new(),
+            metadata: None,
         }
     }
 
@@ -700,6 +1774,8 @@ mod tests {
         CFG {
             root: Some(nodes[0]),
             edges,
+            info: HashMap::new(),
+            metadata: None,
         }
     }
 
@@ -743,6 +1819,8 @@ mod tests {
                 ][0],
             ),
             edges,
+            info: HashMap::new(),
+            metadata: None,
         };
         //conversion
         let bare = BareCFG {
@@ -845,6 +1923,55 @@ mod tests {
         assert!(cfg.cond(root).is_none())
     }
 
+    #[test]
+    fn same_shape_different_offsets() {
+        let stmts_a = vec![
+            Statement::new(0x610, StatementFamily::CMP, "test edi, edi"),
+            Statement::new(0x612, StatementFamily::CJMP, "je 0x618"),
+            Statement::new(0x614, StatementFamily::MOV, "mov eax, 6"),
+            Statement::new(0x618, StatementFamily::RET, "ret"),
+        ];
+        let stmts_b = vec![
+            Statement::new(0x4610, StatementFamily::CMP, "test edi, edi"),
+            Statement::new(0x4614, StatementFamily::CJMP, "je 0x4630"),
+            Statement::new(0x4618, StatementFamily::MOV, "mov eax, 6"),
+            Statement::new(0x4630, StatementFamily::RET, "ret"),
+        ];
+        let arch = Architecture::X86(64);
+        let cfg_a = CFG::new(&stmts_a, 0x619, arch);
+        let cfg_b = CFG::new(&stmts_b, 0x4631, arch);
+        assert!(cfg_a.same_shape(&cfg_b));
+        assert!(cfg_b.same_shape(&cfg_a));
+    }
+
+    #[test]
+    fn same_shape_different_edges() {
+        let cfg = sequence();
+        let mut edited = cfg.clone();
+        let last = BasicBlock {
+            offset: 3,
+            length: 1,
+        };
+        edited.edges.insert(last, vec![*cfg.root().unwrap()]);
+        assert!(cfg.same_shape(&cfg));
+        assert!(!cfg.same_shape(&edited));
+        assert!(!cfg.same_shape(&two_sequences()));
+    }
+
+    #[test]
+    fn wl_hash() {
+        let cfg = sequence();
+        let mut edited = cfg.clone();
+        let last = BasicBlock {
+            offset: 3,
+            length: 1,
+        };
+        edited.edges.insert(last, vec![*cfg.root().unwrap()]);
+        assert_eq!(cfg.wl_hash(3), cfg.clone().rebase(0, 0x1000).wl_hash(3));
+        assert_ne!(cfg.wl_hash(3), edited.wl_hash(3));
+        assert_ne!(cfg.wl_hash(3), two_sequences().wl_hash(3));
+    }
+
     #[test]
     fn build_cfg_conditional_jumps() {
         let stmts = vec![
@@ -988,6 +2115,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "io")]
     fn save_and_retrieve_empty() -> Result<(), Box<dyn Error>> {
         let stmts = Vec::new();
         let arch = Architecture::X86(64);
@@ -1003,6 +2131,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "io")]
     fn save_and_retrieve() -> Result<(), Box<dyn Error>> {
         let stmts = vec![
             Statement::new(0x61E, StatementFamily::PUSH, "push rbp"), //0
@@ -1028,6 +2157,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "io")]
     fn save_and_retrieve_with_entry_point() -> Result<(), Box<dyn Error>> {
         let stmts = vec![
             Statement::new(0x61E, StatementFamily::PUSH, "push rbp"), //0
@@ -1112,6 +2242,8 @@ mod tests {
         let cfg = CFG {
             root: None,
             edges: HashMap::new(),
+            info: HashMap::new(),
+            metadata: None,
         };
         let cfg_with_eep = cfg.add_entry_point();
         assert!(cfg_with_eep.is_empty());
@@ -1135,6 +2267,8 @@ mod tests {
         let cfg = CFG {
             root: None,
             edges: HashMap::new(),
+            info: HashMap::new(),
+            metadata: None,
         };
         let cfg_only_reachables = reachable(cfg);
         assert!(cfg_only_reachables.is_empty());
@@ -1229,4 +2363,440 @@ mod tests {
         assert!(cfg.root().is_some());
         assert_eq!(cfg.root().unwrap().offset, 0x61C);
     }
+
+    #[test]
+    fn new_terminators() {
+        let stmts = vec![
+            Statement::new(0x10, StatementFamily::CJMP, "je 0x1a"),
+            Statement::new(0x12, StatementFamily::CMP, "cmp eax, 0"),
+            Statement::new(0x14, StatementFamily::CJMP, "je 0x18"),
+            Statement::new(0x16, StatementFamily::TRAP, "ud2"),
+            Statement::new(0x18, StatementFamily::JMP, "jmp 0x400"),
+            Statement::new(0x1a, StatementFamily::RET, "ret"),
+        ];
+        let arch = Architecture::X86(64);
+        let cfg = CFG::new(&stmts, 0x1b, arch);
+        let bb = |offset, length| BasicBlock { offset, length };
+        // the trap has no fallthrough edge
+        assert_eq!(cfg.neighbours(&bb(0x16, 2)), &[]);
+        assert_eq!(cfg.terminator(&bb(0x10, 2)), None);
+        assert_eq!(cfg.terminator(&bb(0x12, 4)), None);
+        assert_eq!(cfg.terminator(&bb(0x16, 2)), Some(BlockType::Unreachable));
+        assert_eq!(cfg.terminator(&bb(0x18, 2)), Some(BlockType::Return));
+        assert_eq!(cfg.terminator(&bb(0x1a, 1)), Some(BlockType::Return));
+        let bare = CFG::from(BareCFG {
+            root: Some(0),
+            blocks: vec![(0, 1)],
+            edges: Vec::new(),
+        });
+        assert_eq!(bare.terminator(&bb(0, 1)), None);
+    }
+
+    #[test]
+    fn with_statements_terminators() {
+        let stmts = vec![
+            Statement::new(0x10, StatementFamily::CJMP, "je 0x1a"),
+            Statement::new(0x12, StatementFamily::CMP, "cmp eax, 0"),
+            Statement::new(0x14, StatementFamily::CJMP, "je 0x18"),
+            Statement::new(0x16, StatementFamily::TRAP, "ud2"),
+            Statement::new(0x18, StatementFamily::JMP, "jmp 0x400"),
+            Statement::new(0x1a, StatementFamily::RET, "ret"),
+        ];
+        let arch = Architecture::X86(64);
+        let expected = CFG::new(&stmts, 0x1b, arch);
+        let bare = BareCFG {
+            root: Some(0x10),
+            blocks: vec![(0x10, 2), (0x12, 4), (0x16, 2), (0x18, 2), (0x1a, 1)],
+            edges: vec![(0x10, 0x12), (0x10, 0x1a), (0x12, 0x16), (0x12, 0x18)],
+        };
+        let cfg = CFG::with_statements(bare, &stmts, arch);
+        for bb in expected.blocks_sorted() {
+            assert_eq!(cfg.terminator(bb), expected.terminator(bb));
+        }
+        let bb = |offset, length| BasicBlock { offset, length };
+        assert_eq!(cfg.terminator(&bb(0x16, 2)), Some(BlockType::Unreachable));
+        assert_eq!(cfg.terminator(&bb(0x18, 2)), Some(BlockType::Return));
+        // a block without successors ending with a call has no known terminator
+        let bare = BareCFG {
+            root: Some(0x10),
+            blocks: vec![(0x10, 5)],
+            edges: Vec::new(),
+        };
+        let stmts = vec![Statement::new(0x10, StatementFamily::CALL, "call 0x400")];
+        let cfg = CFG::with_statements(bare, &stmts, arch);
+        assert_eq!(cfg.terminator(&bb(0x10, 5)), None);
+    }
+
+    #[test]
+    fn with_statements_unsupported_architecture() {
+        let bare = || BareCFG {
+            root: Some(0x10),
+            blocks: vec![(0x10, 8), (0x18, 4)],
+            edges: vec![(0x10, 0x18)],
+        };
+        let stmts = vec![
+            Statement::new(0x10, StatementFamily::CALL, "jal ra, 0x400"),
+            Statement::new(0x14, StatementFamily::CJMP, "beqz a0, 0x18"),
+            Statement::new(0x18, StatementFamily::RET, "ret"),
+        ];
+        let cfg = CFG::with_statements(bare(), &stmts, Architecture::Riscv(64));
+        assert_eq!(cfg, CFG::from(bare()));
+    }
+
+    #[test]
+    fn basic_block_display() {
+        let bb = BasicBlock {
+            offset: 0x400123,
+            length: 0x24,
+        };
+        assert_eq!(bb.to_string(), "0x400123..0x400147");
+        let last = BasicBlock {
+            offset: u64::MAX - 1,
+            length: 4,
+        };
+        assert_eq!(last.to_string(), "0xfffffffffffffffe..0xffffffffffffffff");
+        assert_eq!(BasicBlock::new_sink().to_string(), "sink");
+        assert_eq!(BasicBlock::new_entry_point().to_string(), "entry");
+    }
+
+    #[test]
+    fn blocks_sorted() {
+        let stmts = vec![
+            Statement::new(0x61C, StatementFamily::MOV, "mov eax, 5"),
+            Statement::new(0x620, StatementFamily::CJMP, "je 0x628"),
+            Statement::new(0x624, StatementFamily::RET, "ret"),
+            Statement::new(0x628, StatementFamily::MOV, "mov eax, 6"),
+            Statement::new(0x62C, StatementFamily::RET, "ret"),
+        ];
+        let cfg = CFG::new(&stmts, 0x630, Architecture::X86(64)).add_sink();
+        let sorted = cfg.blocks_sorted();
+        let offsets = sorted.iter().map(|bb| bb.offset).collect::<Vec<_>>();
+        assert_eq!(offsets, vec![0x61C, 0x624, 0x628, u64::MAX]);
+        assert!(sorted.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(BasicBlock::new_entry_point() < *sorted[0]);
+    }
+
+    #[test]
+    fn new_block_flags() {
+        let stmts = vec![
+            Statement::new(0x10, StatementFamily::CMP, "cmp eax, 0"),
+            Statement::new(0x12, StatementFamily::CJMP, "je 0x19"),
+            Statement::new(0x14, StatementFamily::CALL, "call 0x400"),
+            Statement::new(0x19, StatementFamily::CJMP, "jne qword [rbx]"),
+            Statement::new(0x1B, StatementFamily::RET, "ret"),
+        ];
+        let cfg = CFG::new(&stmts, 0x1C, Architecture::X86(64));
+        let bb = |offset, length| BasicBlock { offset, length };
+        assert!(cfg.is_entry(&bb(0x10, 4)));
+        assert!(!cfg.is_entry(&bb(0x14, 5)));
+        assert!(cfg.ends_with_call(&bb(0x14, 5)));
+        assert!(!cfg.ends_with_call(&bb(0x10, 4)));
+        assert!(cfg.ends_with_indirect_branch(&bb(0x19, 2)));
+        assert!(!cfg.ends_with_indirect_branch(&bb(0x10, 4)));
+        assert!(cfg.is_exit(&bb(0x1B, 1)));
+        assert!(!cfg.is_exit(&bb(0x19, 2)));
+        assert!(!cfg.is_exit(&bb(0x100, 1)));
+        let loaded = CFG {
+            info: HashMap::new(),
+            ..cfg
+        };
+        assert!(!loaded.ends_with_call(&bb(0x14, 5)));
+    }
+
+    #[test]
+    fn with_statements_block_flags() {
+        let stmts = vec![
+            Statement::new(0x10, StatementFamily::CMP, "cmp eax, 0"),
+            Statement::new(0x12, StatementFamily::CJMP, "je 0x19"),
+            Statement::new(0x14, StatementFamily::CALL, "call 0x400"),
+            Statement::new(0x19, StatementFamily::CJMP, "jne qword [rbx]"),
+            Statement::new(0x1B, StatementFamily::RET, "ret"),
+        ];
+        // radare2 may know the targets of the indirect jump, here 0x10
+        let bare = BareCFG {
+            root: Some(0x10),
+            blocks: vec![(0x10, 4), (0x14, 5), (0x19, 2), (0x1B, 1)],
+            edges: vec![
+                (0x10, 0x14),
+                (0x10, 0x19),
+                (0x14, 0x19),
+                (0x19, 0x1B),
+                (0x19, 0x10),
+            ],
+        };
+        let cfg = CFG::with_statements(bare, &stmts, Architecture::X86(64));
+        let bb = |offset, length| BasicBlock { offset, length };
+        assert!(cfg.ends_with_call(&bb(0x14, 5)));
+        assert!(!cfg.ends_with_call(&bb(0x10, 4)));
+        assert!(cfg.ends_with_indirect_branch(&bb(0x19, 2)));
+        assert!(!cfg.ends_with_indirect_branch(&bb(0x10, 4)));
+        assert!(!cfg.ends_with_indirect_branch(&bb(0x1B, 1)));
+    }
+
+    #[test]
+    fn new_aarch64() {
+        let stmts = vec![
+            Statement::new(0x00, StatementFamily::CJMP, "cbz x0, 0x10"),
+            Statement::new(0x04, StatementFamily::CJMP, "tbnz w1, 3, 0x14"),
+            Statement::new(0x08, StatementFamily::LOAD, "adrp x16, 0x20000"),
+            Statement::new(0x0C, StatementFamily::JMP, "br x16"),
+            Statement::new(0x10, StatementFamily::MOV, "mov x0, 1"),
+            Statement::new(0x14, StatementFamily::RET, "ret"),
+        ];
+        let cfg = CFG::new(&stmts, 0x18, Architecture::Arm(64));
+        let bb = |offset, length| BasicBlock { offset, length };
+        let succs = |block| {
+            let mut succs = cfg.edges[&block].clone();
+            succs.sort();
+            succs
+        };
+        assert_eq!(cfg.len(), 5);
+        assert_eq!(succs(bb(0x00, 4)), vec![bb(0x04, 4), bb(0x10, 4)]);
+        assert_eq!(succs(bb(0x04, 4)), vec![bb(0x08, 8), bb(0x14, 4)]);
+        assert!(succs(bb(0x08, 8)).is_empty());
+        assert!(cfg.ends_with_indirect_branch(&bb(0x08, 8)));
+        assert!(!cfg.ends_with_indirect_branch(&bb(0x04, 4)));
+        assert!(cfg.is_exit(&bb(0x14, 4)));
+    }
+
+    #[test]
+    #[cfg(feature = "disasm")]
+    fn with_memory_jump_table() {
+        use crate::disasm::{Endianness, MemoryImage};
+        let stmts = |jump| {
+            vec![
+                Statement::new(0x10, StatementFamily::JMP, jump),
+                Statement::new(0x17, StatementFamily::MOV, "mov eax, 1"),
+                Statement::new(0x1C, StatementFamily::RET, "ret"),
+            ]
+        };
+        // big endian entries, the third one is outside the function and ends the table
+        let table = [0x17_u32, 0x1C, 0x17, 0x100]
+            .iter()
+            .flat_map(|case| case.to_be_bytes())
+            .collect();
+        let memory = MemoryImage::new(Endianness::Big).with_segment(0x800, table);
+        let bb = |offset, length| BasicBlock { offset, length };
+        let jump = stmts("jmp dword [eax*4 + 0x800]");
+        let cfg = CFG::with_memory(&jump, 0x1D, Architecture::X86(32), &memory);
+        let mut succs = cfg.edges[&bb(0x10, 7)].clone();
+        succs.sort();
+        assert_eq!(succs, vec![bb(0x17, 5), bb(0x1C, 1)]);
+        assert!(cfg.ends_with_indirect_branch(&bb(0x10, 7)));
+        assert_eq!(cfg.terminator(&bb(0x1C, 1)), Some(BlockType::Return));
+        // a table of 64-bit pointers does not match the scale of the operand
+        let cfg = CFG::with_memory(&jump, 0x1D, Architecture::X86(64), &memory);
+        assert!(cfg.edges[&bb(0x10, 7)].is_empty());
+        // the address of the table depends on a base register
+        let based = stmts("jmp dword [ebx + eax*4 + 0x800]");
+        let cfg = CFG::with_memory(&based, 0x1D, Architecture::X86(32), &memory);
+        assert!(cfg.edges[&bb(0x10, 7)].is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "disasm")]
+    fn with_memory_aarch64_jump_table() {
+        use crate::disasm::{Endianness, MemoryImage};
+        let stmts = |load, add| {
+            vec![
+                Statement::new(0x00, StatementFamily::CMP, "cmp w0, 2"),
+                Statement::new(0x04, StatementFamily::CJMP, "b.hi 0x30"),
+                Statement::new(0x08, StatementFamily::LOAD, "adrp x1, 0x800"),
+                Statement::new(0x0C, StatementFamily::ADD, "add x1, x1, 0x10"),
+                Statement::new(0x10, StatementFamily::LOAD, load),
+                Statement::new(0x14, StatementFamily::LOAD, "adr x2, 0x28"),
+                Statement::new(0x18, StatementFamily::ADD, add),
+                Statement::new(0x1C, StatementFamily::JMP, "br x0"),
+                Statement::new(0x20, StatementFamily::MOV, "mov w0, 1"),
+                Statement::new(0x24, StatementFamily::JMP, "b 0x30"),
+                Statement::new(0x28, StatementFamily::MOV, "mov w0, 2"),
+                Statement::new(0x2C, StatementFamily::JMP, "b 0x30"),
+                Statement::new(0x30, StatementFamily::RET, "ret"),
+            ]
+        };
+        let bb = |offset, length| BasicBlock { offset, length };
+        let succs = |cfg: &CFG| {
+            let mut succs = cfg.edges[&bb(0x08, 0x18)].clone();
+            succs.sort();
+            succs
+        };
+        let cases = vec![bb(0x20, 8), bb(0x28, 8), bb(0x30, 4)];
+        let arch = Architecture::Arm(64);
+        // signed bytes, shifted and added to the base: -2, 0 and 2 instructions from 0x28
+        let bytes =
+            MemoryImage::new(Endianness::Little).with_segment(0x810, vec![0xFE, 0, 2, 0x40]);
+        let gcc = stmts("ldrb w0, [x1, w0, uxtw]", "add x0, x2, w0, sxtb 2");
+        let cfg = CFG::with_memory(&gcc, 0x34, arch, &bytes);
+        assert_eq!(succs(&cfg), cases);
+        // the same entries, unsigned, reach outside the function starting from the first one
+        let unsigned = stmts("ldrb w0, [x1, w0, uxtw]", "add x0, x2, w0, uxtb 2");
+        let cfg = CFG::with_memory(&unsigned, 0x34, arch, &bytes);
+        assert!(succs(&cfg).is_empty());
+        // words relative to the table
+        let words = [0x20_i32 - 0x810, 0x28 - 0x810, 0x30 - 0x810]
+            .iter()
+            .flat_map(|case| case.to_le_bytes())
+            .collect();
+        let words = MemoryImage::new(Endianness::Little).with_segment(0x810, words);
+        let clang = stmts("ldrsw x0, [x1, w0, sxtw 2]", "add x0, x1, x0");
+        let cfg = CFG::with_memory(&clang, 0x34, arch, &words);
+        assert_eq!(succs(&cfg), cases);
+        // absolute addresses
+        let addresses = [0x20_u64, 0x28, 0x30]
+            .iter()
+            .flat_map(|case| case.to_le_bytes())
+            .collect();
+        let addresses = MemoryImage::new(Endianness::Little).with_segment(0x810, addresses);
+        let absolute = stmts("ldr x0, [x1, w0, uxtw 3]", "nop");
+        let cfg = CFG::with_memory(&absolute, 0x34, arch, &addresses);
+        assert_eq!(succs(&cfg), cases);
+        // the index is not scaled by the size of the entries
+        let unscaled = stmts("ldr x0, [x1, w0, uxtw]", "nop");
+        let cfg = CFG::with_memory(&unscaled, 0x34, arch, &addresses);
+        assert!(succs(&cfg).is_empty());
+    }
+
+    #[test]
+    fn new_mips_delay_slots() {
+        let stmts = vec![
+            Statement::new(0x00, StatementFamily::CJMP, "beqz a0, 0x14"),
+            Statement::new(0x04, StatementFamily::MOV, "move v0, zero"),
+            Statement::new(0x08, StatementFamily::LOAD, "lw t9, -0x7fd0(gp)"),
+            Statement::new(0x0C, StatementFamily::CALL, "jalr t9"),
+            Statement::new(0x10, StatementFamily::NOP, "nop"),
+            Statement::new(0x14, StatementFamily::RET, "jr ra"),
+            Statement::new(0x18, StatementFamily::NOP, "nop"),
+        ];
+        let cfg = CFG::new(&stmts, 0x1C, Architecture::Mips(32));
+        let bb = |offset, length| BasicBlock { offset, length };
+        assert_eq!(cfg.len(), 3);
+        assert_eq!(cfg.edges[&bb(0x00, 8)], vec![bb(0x08, 0xC), bb(0x14, 8)]);
+        assert_eq!(cfg.edges[&bb(0x08, 0xC)], vec![bb(0x14, 8)]);
+        assert!(cfg.edges[&bb(0x14, 8)].is_empty());
+        assert!(cfg.ends_with_call(&bb(0x08, 0xC)));
+        assert!(cfg.is_exit(&bb(0x14, 8)));
+        assert!(!cfg.ends_with_indirect_branch(&bb(0x14, 8)));
+        assert_eq!(cfg.terminator(&bb(0x14, 8)), Some(BlockType::Return));
+    }
+
+    #[test]
+    fn new_ppc_ctr_loop() {
+        let stmts = vec![
+            Statement::new(0x00, StatementFamily::MOV, "mtctr r3"),
+            Statement::new(0x04, StatementFamily::ADD, "addi r4, r4, 1"),
+            Statement::new(0x08, StatementFamily::CJMP, "bdnz 0x4"),
+            Statement::new(0x0C, StatementFamily::RET, "blr"),
+        ];
+        let cfg = CFG::new(&stmts, 0x10, Architecture::PowerPC(32));
+        let bb = |offset, length| BasicBlock { offset, length };
+        assert_eq!(cfg.len(), 3);
+        assert_eq!(cfg.edges[&bb(0x00, 4)], vec![bb(0x04, 8)]);
+        assert_eq!(cfg.edges[&bb(0x04, 8)], vec![bb(0x04, 8), bb(0x0C, 4)]);
+        assert_eq!(cfg.terminator(&bb(0x0C, 4)), Some(BlockType::Return));
+    }
+
+    #[test]
+    fn new_instruction_count() {
+        let stmts = vec![
+            Statement::new(0x10, StatementFamily::MOV, "mov eax, 0"),
+            Statement::new(0x15, StatementFamily::CMP, "cmp ebx, 0"),
+            Statement::new(0x18, StatementFamily::CJMP, "je 0x1c"),
+            Statement::new(0x1a, StatementFamily::ADD, "inc eax"),
+            Statement::new(0x1c, StatementFamily::RET, "ret"),
+        ];
+        let arch = Architecture::X86(64);
+        let cfg = CFG::new(&stmts, 0x1d, arch);
+        let bb = |offset, length| BasicBlock { offset, length };
+        assert_eq!(cfg.instruction_count(&bb(0x10, 10)), Some(3));
+        assert_eq!(cfg.instruction_count(&bb(0x1a, 2)), Some(1));
+        assert_eq!(cfg.instruction_count(&bb(0x1c, 1)), Some(1));
+        assert_eq!(cfg.instruction_count(&bb(0x30, 1)), None);
+        let cfg = cfg.add_sink();
+        assert_eq!(cfg.instruction_count(&BasicBlock::new_sink()), None);
+        assert_eq!(BasicBlock::new_sink().byte_len(), 0);
+    }
+
+    #[test]
+    fn with_statements_instruction_count() {
+        let stmts = vec![
+            Statement::new(0x10, StatementFamily::MOV, "mov eax, 0"),
+            Statement::new(0x15, StatementFamily::CMP, "cmp ebx, 0"),
+            Statement::new(0x18, StatementFamily::CJMP, "je 0x1c"),
+            Statement::new(0x1a, StatementFamily::ADD, "inc eax"),
+            Statement::new(0x1c, StatementFamily::RET, "ret"),
+            // not part of any block
+            Statement::new(0x40, StatementFamily::NOP, "nop"),
+        ];
+        let bare = BareCFG {
+            root: Some(0x10),
+            blocks: vec![(0x10, 10), (0x1a, 2), (0x1c, 1)],
+            edges: vec![(0x10, 0x1a), (0x10, 0x1c), (0x1a, 0x1c)],
+        };
+        let cfg = CFG::with_statements(bare.clone(), &stmts, Architecture::X86(64));
+        let bb = |offset, length| BasicBlock { offset, length };
+        assert_eq!(cfg.instruction_count(&bb(0x10, 10)), Some(3));
+        assert_eq!(cfg.instruction_count(&bb(0x1a, 2)), Some(1));
+        assert_eq!(cfg.instruction_count(&bb(0x1c, 1)), Some(1));
+        assert_eq!(cfg.instruction_count(&bb(0x40, 1)), None);
+        let unlabeled = CFG::from(bare);
+        assert_eq!(unlabeled.instruction_count(&bb(0x10, 10)), None);
+    }
+
+    #[test]
+    fn with_statements_categories() {
+        let stmts = vec![
+            Statement::new(0x10, StatementFamily::CMP, "cmp eax, 0"),
+            Statement::new(0x13, StatementFamily::CJMP, "je 0x17"),
+            Statement::new(0x15, StatementFamily::ADD, "inc eax"),
+            Statement::new(0x17, StatementFamily::RET, "ret"),
+        ];
+        let bare = BareCFG {
+            root: Some(0x10),
+            blocks: vec![(0x10, 5), (0x15, 2), (0x17, 1)],
+            edges: vec![(0x10, 0x15), (0x10, 0x17), (0x15, 0x17)],
+        };
+        let arch = Architecture::X86(64);
+        let cfg = CFG::with_statements(bare.clone(), &stmts, arch);
+        let expected = CFG::new(&stmts, 0x18, arch);
+        let bb = |offset, length| BasicBlock { offset, length };
+        for block in [bb(0x10, 5), bb(0x15, 2), bb(0x17, 1)] {
+            assert!(cfg.categories(&block).is_some());
+            assert_eq!(cfg.categories(&block), expected.categories(&block));
+        }
+        let total = cfg.function_categories().unwrap();
+        assert_eq!(total.count(InstructionCategory::Arithmetic), 1);
+        assert_eq!(total.count(InstructionCategory::Branch), 1);
+        assert_eq!(total.count(InstructionCategory::Return), 1);
+        assert_eq!(Some(total), expected.function_categories());
+        assert!(CFG::from(bare).function_categories().is_none());
+    }
+
+    #[test]
+    fn rebase() {
+        let stmts = vec![
+            Statement::new(0x1000, StatementFamily::CJMP, "je 0x1007"),
+            Statement::new(0x1002, StatementFamily::CALL, "call 0x1100"),
+            Statement::new(0x1007, StatementFamily::RET, "ret"),
+            Statement::new(0x1008, StatementFamily::RET, "ret"),
+        ];
+        let metadata = FunctionMetadata::new("main", 0x1000, 9);
+        let cfg = CFG::new(&stmts, 0x1009, Architecture::X86(64))
+            .with_metadata(metadata)
+            .add_sink();
+        let rebased = cfg.clone().rebase(0x1000, 0x7000);
+        let bb = |offset, length| BasicBlock { offset, length };
+        assert_eq!(rebased.len(), cfg.len());
+        assert_eq!(rebased.root(), Some(&bb(0x7000, 2)));
+        assert_eq!(rebased.next(Some(&bb(0x7000, 2))), Some(&bb(0x7002, 5)));
+        assert_eq!(rebased.cond(Some(&bb(0x7000, 2))), Some(&bb(0x7007, 1)));
+        assert!(rebased.neighbours(&bb(0x7007, 1))[0].is_sink());
+        assert_eq!(rebased.instruction_count(&bb(0x7002, 5)), Some(1));
+        let call = &rebased.calls(&bb(0x7002, 5))[0];
+        assert_eq!(call.offset(), 0x7002);
+        assert_eq!(call.target(), &CallTarget::Address(0x7100));
+        assert_eq!(rebased.metadata().unwrap().start(), 0x7000);
+        assert!(rebased.same_shape(&cfg));
+        // rebasing to a lower address
+        assert_eq!(rebased.rebase(0x7000, 0x1000), cfg);
+    }
 }
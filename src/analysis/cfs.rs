@@ -1,17 +1,30 @@
 use crate::analysis::blocks::StructureBlock;
-use crate::analysis::{BasicBlock, BlockType, DirectedGraph, Graph, NestedBlock, CFG};
-use fnv::FnvHashSet;
+use crate::analysis::cache::{CanonicalCfg, StructureCache};
+use crate::analysis::cfg::BlockInfo;
+use crate::analysis::{
+    BasicBlock, BlockType, CancellationToken, DirectedGraph, Graph, NestedBlock, Phase,
+    ProgressSink, StructureRewriter, Timings, CFG,
+};
+use crate::Error;
+use fnv::{FnvHashSet, FnvHasher};
 use maplit::hashset;
-use std::cmp::{max, Ordering};
-use std::collections::{HashMap, HashSet};
-use std::fmt::Write as WriteFmt;
+use std::borrow::Cow;
+use std::cmp::{max, min, Ordering};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt::{Display, Formatter, Write as WriteFmt};
+#[cfg(feature = "io")]
 use std::fs::File;
-use std::hash::Hash;
+use std::hash::{Hash, Hasher};
+#[cfg(feature = "io")]
 use std::io;
+#[cfg(feature = "io")]
 use std::io::Write as WriteIo;
-use std::mem::swap;
+use std::mem::{replace, swap, take};
+#[cfg(feature = "io")]
 use std::path::Path;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
 // how many times the reduction may NOT decrease the amount of nodes before the CFS is
 // terminated.
@@ -19,38 +32,388 @@ use std::sync::Arc;
 // decrease the node amount.
 const BUILD_TOLERANCE: usize = 32;
 
+// maximum amount of nodes that can be duplicated in order to make irreducible regions reducible.
+const MAX_DUPLICATIONS: usize = 16;
+
 #[derive(Clone)]
 /// A High-Level control flow structure, representing a function in form of [`StructureBlock`]
 /// tree.
 pub struct CFS {
-    cfg: CFG,
-    tree: DirectedGraph<StructureBlock>,
+    pub(super) cfg: CFG,
+    pub(super) tree: DirectedGraph<StructureBlock>,
+    // graph where the reduction stalled for the first time, before wrapping any ImproperRegion.
+    pub(super) partial: Option<DirectedGraph<StructureBlock>>,
+    pub(super) duplicated: usize,
+    // edges removed from the natural loops with multiple exits.
+    pub(super) removed: Vec<(BasicBlock, BasicBlock)>,
+    pub(super) trace: Vec<TraceEntry>,
+    // intermediate graphs, recorded only if requested.
+    pub(super) snapshots: Vec<DirectedGraph<StructureBlock>>,
+    pub(super) stats: CfsStats,
+    // recorded only if requested.
+    pub(super) timings: Option<Timings>,
+}
+
+/// Statistics about the [`CFS`] creation.
+///
+/// This struct is created by the [`CFS::stats`] method.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CfsStats {
+    blocks: HashMap<BlockType, usize>,
+    iterations: usize,
+    removed_edges: usize,
+    depth: u32,
+}
+
+impl CfsStats {
+    pub(super) fn new(
+        tree: &DirectedGraph<StructureBlock>,
+        iterations: usize,
+        removed_edges: usize,
+    ) -> Self {
+        let mut blocks = HashMap::new();
+        let depth = tree.adjacency.keys().map(|node| node.depth()).max();
+        for node in tree.adjacency.keys().flat_map(|node| node.iter_preorder()) {
+            *blocks.entry(node.block_type()).or_insert(0) += 1;
+        }
+        CfsStats {
+            blocks,
+            iterations,
+            removed_edges,
+            depth: depth.unwrap_or(0),
+        }
+    }
+
+    /// Returns the amount of blocks of the given type contained in the final tree.
+    pub fn count(&self, block_type: BlockType) -> usize {
+        *self.blocks.get(&block_type).unwrap_or(&0)
+    }
+
+    /// Returns the amount of steps performed by the reduction loop: every reduction and every
+    /// duplication counts as a single step.
+    pub fn iterations(&self) -> usize {
+        self.iterations
+    }
+
+    /// Returns the amount of edges removed while transforming natural loops with multiple exits,
+    /// including the abnormal loop entries, if annotated.
+    pub fn removed_edges(&self) -> usize {
+        self.removed_edges
+    }
+
+    /// Returns the depth of the final tree.
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+}
+
+/// Outcome of the [`CFS`] creation, exposing the work done even when the reduction is not complete.
+///
+/// This struct is created by the [`CFS::result`] method.
+pub struct StructuringResult<'a> {
+    graph: &'a DirectedGraph<StructureBlock>,
+    complete: bool,
+}
+
+impl<'a> StructuringResult<'a> {
+    /// Returns true if the [`CFG`] has been fully reduced without resorting to a
+    /// [`BlockType::ImproperRegion`].
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// Returns the graph where the reduction stalled, before wrapping any
+    /// [`BlockType::ImproperRegion`].
+    ///
+    /// If the reduction is complete, this graph contains a single node.
+    pub fn get_graph(&self) -> &'a DirectedGraph<StructureBlock> {
+        self.graph
+    }
+
+    /// Returns the nodes of the final graph that have not been merged into any structure.
+    ///
+    /// The nodes are returned in depth-first preorder.
+    pub fn irreducible_nodes(&self) -> Vec<&'a StructureBlock> {
+        self.graph
+            .dfs_preorder()
+            .filter(|node| matches!(node, StructureBlock::Basic(_)))
+            .collect()
+    }
+
+    /// Returns the structures successfully built for each region of the final graph.
+    ///
+    /// The structures are returned in depth-first preorder.
+    pub fn partial_trees(&self) -> Vec<&'a StructureBlock> {
+        self.graph
+            .dfs_preorder()
+            .filter(|node| matches!(node, StructureBlock::Nested(_)))
+            .collect()
+    }
 }
 
 impl CFS {
     /// Creates the control flow structure from a [`CFG`].
     ///
-    /// This procedure is **NOT** guaranteed to complete successfully. If the procedure fails, the
-    /// [`CFS::get_tree`] method will return [`None`].
-    pub fn new(cfg: &CFG) -> CFS {
-        let sinked_cfg = cfg.clone();
-        let tree = build_cfs(&sinked_cfg);
-        CFS {
-            cfg: sinked_cfg,
-            tree,
+    /// Regions that can not be reduced to any known structure are wrapped in a
+    /// [`BlockType::ImproperRegion`] block, so the resulting [`CFS`] is always a tree, unless the
+    /// input [`CFG`] is empty. Only the smallest region with a single entry and a single successor
+    /// is wrapped, so the rest of the [`CFG`] can still be structured around it.
+    ///
+    /// The result is deterministic: the same [`CFG`] always results in the same tree.
+    ///
+    /// All the reductions are enabled and no [`StructureCache`] is used, use a [`CfsBuilder`] to
+    /// customize them.
+    ///
+    /// Returns [`Error::MalformedCfg`] if the [`CFG`] is not consistent, for example because it
+    /// has no root or an edge reaches a block that is not part of the [`CFG`]. The [`CFG`]s
+    /// created with [`CFG::new`] are always consistent.
+    pub fn new(cfg: &CFG) -> Result<CFS, Error> {
+        CfsBuilder::new().build(cfg)
+    }
+
+    /// Creates a new [`CFS`], taking ownership of the given [`CFG`].
+    ///
+    /// This is equivalent to [`CFS::new`], but avoids copying the [`CFG`].
+    pub fn from_cfg(cfg: CFG) -> Result<CFS, Error> {
+        CfsBuilder::new().build_from_cfg(cfg)
+    }
+
+    /// Creates the [`CFS`] of every [`CFG`] in the given set, distributing the work across all the
+    /// available threads.
+    ///
+    /// The returned vector follows the order of the input set, and contains the error of each
+    /// [`CFG`] that can not be structured, as returned by [`CFS::new`]. All the reductions are
+    /// enabled, use [`CfsBuilder::build_all`] to customize them.
+    pub fn analyze_all<I: IntoIterator<Item = CFG>>(cfgs: I) -> Vec<Result<CFS, Error>> {
+        CfsBuilder::new().build_all(cfgs)
+    }
+
+    // returns the CFS and true if the structuring stopped because of the limits.
+    fn with_reductions(
+        cfg: CFG,
+        reductions: &[ReductionKind],
+        custom: &[Arc<dyn Reduction>],
+        options: &Options,
+    ) -> Result<(CFS, bool), Error> {
+        let output = build_cfs(&cfg, reductions, custom, options)?;
+        let cfs = CFS {
+            cfg,
+            tree: output.tree,
+            partial: output.partial,
+            duplicated: output.duplicated,
+            removed: output.removed,
+            trace: output.trace,
+            snapshots: output.snapshots,
+            stats: output.stats,
+            timings: output.timings,
+        };
+        Ok((cfs, output.exhausted))
+    }
+
+    fn with_cache(
+        cfg: CFG,
+        reductions: &[ReductionKind],
+        options: &Options,
+        cache: &StructureCache,
+    ) -> Result<(CFS, bool), Error> {
+        let key = match CanonicalCfg::new(
+            &cfg,
+            reductions,
+            options.preserve_loops,
+            options.refine_conditions,
+            options.annotate_entries,
+        ) {
+            Some(key) => key,
+            None => return CFS::with_reductions(cfg, reductions, &[], options),
+        };
+        // structures requiring more work than allowed are computed again, so they stop at the
+        // same point they would stop without the cache.
+        let cached = if options.allows(0, cfg.len()) {
+            cache.get(&key, options.iterations)
+        } else {
+            None
+        };
+        if let Some(cached) = cached {
+            let tree = DirectedGraph {
+                root: Some(cached.tree.clone()),
+                adjacency: HashMap::from([(cached.tree, Vec::new())]),
+            };
+            let stats = CfsStats::new(&tree, cached.iterations, cached.removed.len());
+            let cfs = CFS {
+                cfg,
+                tree,
+                partial: None,
+                duplicated: cached.duplicated,
+                removed: cached.removed,
+                trace: Vec::new(),
+                snapshots: Vec::new(),
+                stats,
+                timings: None,
+            };
+            Ok((cfs, false))
+        } else {
+            let (cfs, exhausted) = CFS::with_reductions(cfg, reductions, &[], options)?;
+            if let (Some(tree), None, false) = (cfs.get_tree(), &cfs.partial, exhausted) {
+                cache.insert(
+                    &key,
+                    &tree,
+                    &cfs.removed,
+                    cfs.duplicated,
+                    cfs.stats.iterations,
+                );
+            }
+            Ok((cfs, exhausted))
+        }
+    }
+
+    /// Returns the statistics about the [`CFS`] creation.
+    pub fn stats(&self) -> &CfsStats {
+        &self.stats
+    }
+
+    /// Returns the sequence of steps performed during the [`CFS`] creation.
+    ///
+    /// Each step is also emitted as a log record with `trace` level and, with the `tracing`
+    /// feature, as a `tracing` event inside a `reduction` span, nested in the `structure` span of
+    /// the function.
+    pub fn trace(&self) -> &[TraceEntry] {
+        &self.trace
+    }
+
+    /// Returns the intermediate graphs obtained while creating the [`CFS`], if recorded with
+    /// [`CfsBuilder::record_snapshots`].
+    ///
+    /// The first graph is the one before any reduction, and another one is recorded after every
+    /// reduction, duplication or wrapping into a [`BlockType::ImproperRegion`], in the same order
+    /// of the corresponding [`CFS::trace`] entries. The loop annotations and the conditions
+    /// refined at the end of the creation are not part of the snapshots, and are visible only in
+    /// [`CFS::get_tree`]. The list is empty if the snapshots were not requested.
+    pub fn snapshots(&self) -> &[DirectedGraph<StructureBlock>] {
+        &self.snapshots
+    }
+
+    /// Returns the time spent creating the [`CFS`], if recorded with
+    /// [`CfsBuilder::record_timings`].
+    ///
+    /// A [`CFS`] retrieved from a [`StructureCache`] reports only the time spent retrieving it,
+    /// as [`Timings::structuring`].
+    pub fn timings(&self) -> Option<&Timings> {
+        self.timings.as_ref()
+    }
+
+    /// Returns the detailed outcome of the [`CFS`] creation.
+    ///
+    /// Unlike [`CFS::get_tree`], the returned [`StructuringResult`] contains the graph where the
+    /// reduction stalled for the first time, so the regions that were successfully reduced can
+    /// still be used when the [`CFG`] is not fully reducible.
+    pub fn result(&self) -> StructuringResult<'_> {
+        match &self.partial {
+            Some(partial) => StructuringResult {
+                graph: partial,
+                complete: false,
+            },
+            None => StructuringResult {
+                graph: &self.tree,
+                complete: true,
+            },
+        }
+    }
+
+    /// Returns the edges removed from the [`CFG`] during the [`CFS`] creation.
+    ///
+    /// Natural loops with multiple exits can not be structured directly, so only one exit target
+    /// is kept and the edges reaching the other ones are removed. The jumps back to the header of
+    /// a loop performed in the middle of its body, like `continue;`, are removed as well. Each
+    /// edge is returned as a pair of (source block, removed target), sorted by source block.
+    ///
+    /// When built with [`CfsBuilder::preserve_loops`], the edges recorded by the markers of the
+    /// loops are kept in the tree, so they are not returned.
+    ///
+    /// When built with [`CfsBuilder::annotate_abnormal_entries`], the jumps entering a loop in a
+    /// block other than its header are removed as well, and returned along with the other edges.
+    pub fn removed_edges(&self) -> &[(BasicBlock, BasicBlock)] {
+        &self.removed
+    }
+
+    /// Returns the innermost structure containing the given address.
+    ///
+    /// Only [`NestedBlock`]s are considered, so [`None`] is returned if the address does not
+    /// belong to any basic block of the [`CFS`] or if its basic block is not part of any
+    /// structure. If the address belongs to a duplicated block, the first structure containing it
+    /// in preorder is returned.
+    pub fn structure_at(&self, address: u64) -> Option<&StructureBlock> {
+        let mut current = self.tree.root.as_ref().filter(|_| self.tree.len() == 1)?;
+        if !matches!(current, StructureBlock::Nested(_)) || !current.contains_address(address) {
+            return None;
+        }
+        while let Some(child) = current.children().iter().find(|child| {
+            matches!(child, StructureBlock::Nested(_)) && child.contains_address(address)
+        }) {
+            current = child;
+        }
+        Some(current)
+    }
+
+    /// Returns the amount of nodes duplicated during the [`CFS`] creation.
+    ///
+    /// Irreducible loops (loops with more than one entry point) can not be structured. When one of
+    /// these is found, one of its entry points is duplicated and the duplicate is marked as
+    /// [`BlockType::Duplicate`]. This operation is performed at most a limited amount of times.
+    pub fn duplicated(&self) -> usize {
+        self.duplicated
+    }
+
+    /// Returns a score between 0.0 and 1.0 estimating how faithfully the structure represents
+    /// the input [`CFG`].
+    ///
+    /// The score is 1.0 when the [`CFG`] is fully structured without altering it, and decreases
+    /// with:
+    /// - the fraction of edges removed from natural loops with multiple exits (see
+    ///   [`CFS::removed_edges`]);
+    /// - the fraction of basic blocks left unstructured, either in a
+    ///   [`BlockType::ImproperRegion`] or not merged into any structure;
+    /// - the fraction of basic blocks copied to make irreducible loops reducible (see
+    ///   [`CFS::duplicated`]).
+    ///
+    /// The score is the product of the complements of these fractions, so it is 0.0 when the
+    /// whole [`CFG`] is left unstructured.
+    pub fn fidelity(&self) -> f64 {
+        let ratio = |part: usize, total: usize| {
+            if total == 0 {
+                1.0
+            } else {
+                1.0 - (part as f64 / total as f64).min(1.0)
+            }
+        };
+        let edges = self.cfg.edges.values().map(Vec::len).sum::<usize>();
+        let nodes = self.cfg.len();
+        let mut improper = 0;
+        let mut duplicated = 0;
+        for node in self.tree.adjacency.keys() {
+            if self.tree.len() > 1 && node.block_type() == BlockType::Basic {
+                improper += 1;
+            }
+            for block in node.iter_preorder() {
+                match block.block_type() {
+                    BlockType::ImproperRegion => improper += block.basic_blocks().len(),
+                    BlockType::Duplicate => duplicated += block.basic_blocks().len(),
+                    _ => {}
+                }
+            }
         }
+        ratio(self.removed.len(), edges) * ratio(improper, nodes) * ratio(duplicated, nodes)
     }
 
     /// Returns the final result of the [`CFS`] creation.
     ///
-    /// If the process fails, a graph will be created, otherwise a tree will be created.
+    /// This graph is composed of a single node, unless the input [`CFG`] is empty.
     pub fn get_graph(&self) -> &DirectedGraph<StructureBlock> {
         &self.tree
     }
 
-    /// Returns the final result of the [`CFS`] creations only if successfull.
+    /// Returns the final result of the [`CFS`] creation in form of [`StructureBlock`] tree.
     ///
-    /// This method will always return a [`StructureBlock`] tree.
+    /// This method returns [`None`] only if the input [`CFG`] is empty.
     pub fn get_tree(&self) -> Option<StructureBlock> {
         if self.tree.len() == 1 {
             Some(self.tree.root.clone().unwrap())
@@ -83,33 +446,41 @@ impl CFS {
     ///  Writes the current [`CFS`] into a file in Graphviz dot format.
     ///
     ///  This is equivalent to saving the [`CFS::to_dot`] string into a file.
+    #[cfg(feature = "io")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "io")))]
     pub fn to_file<S: AsRef<Path>>(&self, filename: S) -> Result<(), io::Error> {
         let mut file = File::create(filename)?;
         file.write_all(self.to_dot().as_bytes())
     }
 
     /// Returns the tree structure of the [`CFS`] in form of Graphviz dot format.
+    ///
+    /// Nested blocks are labelled with their [`BlockType`], while basic blocks are represented as
+    /// boxes labelled with their offset.
     pub fn to_dot_tree(&self) -> String {
         let mut dot = "digraph {\n".to_string();
-        let mut stack = self.get_tree().iter().cloned().collect::<Vec<_>>();
-        while let Some(node) = stack.pop() {
-            let node_id = node.offset();
-            match node {
-                StructureBlock::Basic(_) => writeln!(
-                    dot,
-                    "{}[label=\"{}\";shape=\"box\"];",
-                    node_id,
-                    node.block_type()
-                ),
+        // the offset is not unique (a nested block has the offset of its first child), so each
+        // node is given a progressive id.
+        let mut next_id = 0;
+        let mut stack = self
+            .get_tree()
+            .into_iter()
+            .map(|node| (node, 0))
+            .collect::<Vec<_>>();
+        while let Some((node, node_id)) = stack.pop() {
+            match &node {
+                StructureBlock::Basic(bb) => {
+                    writeln!(dot, "{}[label=\"{}\";shape=\"box\"];", node_id, bb)
+                }
                 StructureBlock::Nested(_) => {
                     writeln!(dot, "{}[label=\"{}\"];", node_id, node.block_type())
                 }
             }
             .unwrap();
             for child in node.children().iter().cloned() {
-                let child_id = child.offset();
-                writeln!(dot, "{}->{}", node_id, child_id).unwrap();
-                stack.push(child);
+                next_id += 1;
+                writeln!(dot, "{}->{}", node_id, next_id).unwrap();
+                stack.push((child, next_id));
             }
         }
         dot.push('}');
@@ -120,199 +491,1356 @@ impl CFS {
     ///  Writes the current [`CFS`] tree into a file in Graphviz dot format.
     ///
     ///  This is equivalent to saving the [`CFS::to_dot_tree`] string into a file.
+    #[cfg(feature = "io")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "io")))]
     pub fn to_file_tree<S: AsRef<Path>>(&self, filename: S) -> Result<(), io::Error> {
         let mut file = File::create(filename)?;
         file.write_all(self.to_dot_tree().as_bytes())
     }
 }
 
-fn print_subgraph<T: std::fmt::Write>(node: &StructureBlock, id: usize, fmt: &mut T) -> usize {
-    let mut latest = id;
-    match node {
-        StructureBlock::Basic(bb) => {
-            if !bb.is_entry_point() && !bb.is_sink() {
-                writeln!(fmt, "{};", bb.offset).unwrap();
-            }
-        }
-        StructureBlock::Nested(_) => {
-            writeln!(fmt, "subgraph cluster_{}{{", id).unwrap();
-            for child in node.children().iter() {
-                latest = print_subgraph(child, latest + 1, fmt);
-            }
-            writeln!(fmt, "label=\"{}\";\n}}", node.block_type()).unwrap();
+/// Patterns that can be recognized during the [`CFS`] creation.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ReductionKind {
+    /// Reduces a node looping on itself into a [`BlockType::SelfLooping`].
+    SelfLoop,
+    /// Reduces loops into [`BlockType::While`] or [`BlockType::DoWhile`], and loops around a
+    /// switch sharing the head of the loop into [`BlockType::SwitchLoop`].
+    Loop,
+    /// Reduces conditionals into [`BlockType::IfThen`].
+    IfThen,
+    /// Reduces early returns into [`BlockType::Guard`].
+    Guard,
+    /// Reduces conditionals into [`BlockType::IfThenElse`].
+    IfThenElse,
+    /// Reduces nodes executed one after another into [`BlockType::Sequence`].
+    Sequence,
+    /// Reduces multiway branches into [`BlockType::Switch`].
+    Switch,
+    /// Reduces nodes into [`BlockType::ProperInterval`].
+    ProperInterval,
+    /// Reduces nodes into [`BlockType::ImproperInterval`].
+    ImproperInterval,
+}
+
+impl ReductionKind {
+    /// Returns all the reductions, in the default priority order.
+    pub fn all() -> [ReductionKind; 9] {
+        [
+            ReductionKind::SelfLoop,
+            ReductionKind::Loop,
+            ReductionKind::IfThen,
+            ReductionKind::Guard,
+            ReductionKind::IfThenElse,
+            ReductionKind::Sequence,
+            ReductionKind::Switch,
+            ReductionKind::ProperInterval,
+            ReductionKind::ImproperInterval,
+        ]
+    }
+
+    fn function(self) -> ReduceFn {
+        match self {
+            ReductionKind::SelfLoop => reduce_self_loop,
+            ReductionKind::Loop => reduce_loop,
+            ReductionKind::IfThen => reduce_ifthen,
+            ReductionKind::IfThenElse => reduce_ifelse,
+            ReductionKind::Guard => reduce_guard,
+            ReductionKind::Sequence => reduce_sequence,
+            ReductionKind::Switch => reduce_switch,
+            ReductionKind::ProperInterval => reduce_proper_interval,
+            ReductionKind::ImproperInterval => reduce_improper_interval,
         }
     }
-    latest
 }
 
-// result of a reduce_xxx method
-struct Reduction<'a> {
-    // old nodes that will be removed. Not necessary equal to new.children()
-    // for example structures may expand previous structures, forcing the previous structure to
-    // be discarded and a new one to be created.
-    old: HashSet<&'a StructureBlock>,
-    // new node that will replace the old one
-    new: StructureBlock,
-    // successor of the newly created node
-    next: Option<&'a StructureBlock>,
+impl Display for ReductionKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReductionKind::SelfLoop => write!(f, "Self Loop"),
+            ReductionKind::Loop => write!(f, "Loop"),
+            ReductionKind::IfThen => write!(f, "If-Then"),
+            ReductionKind::IfThenElse => write!(f, "If-Then-Else"),
+            ReductionKind::Guard => write!(f, "Guard"),
+            ReductionKind::Sequence => write!(f, "Sequence"),
+            ReductionKind::Switch => write!(f, "Switch"),
+            ReductionKind::ProperInterval => write!(f, "Proper Interval"),
+            ReductionKind::ImproperInterval => write!(f, "Improper Interval"),
+        }
+    }
 }
 
-fn reduce_self_loop<'a>(
-    node: &'a StructureBlock,
-    graph: &'a DirectedGraph<StructureBlock>,
-    _: &HashMap<&'a StructureBlock, HashSet<&'a StructureBlock>>,
-    _: &LoopHelper<'a>,
-) -> Option<Reduction<'a>> {
-    match node {
-        StructureBlock::Basic(_) => {
-            let children = graph.neighbours(node);
-            if children.len() == 2 && children.contains(node) {
-                let next = children.iter().filter(|x| x != &node).last().unwrap();
-                let block = Arc::new(NestedBlock::new(BlockType::SelfLooping, vec![node.clone()]));
-                Some(Reduction {
-                    old: hashset![node],
-                    new: StructureBlock::from(block),
-                    next: Some(next),
-                })
-            } else {
-                None
-            }
-        }
-        StructureBlock::Nested(_) => None,
+/// A user-defined pattern that can be recognized during the [`CFS`] creation.
+///
+/// Custom reductions are registered with [`CfsBuilder::with_reduction`] and are attempted before
+/// the built-in ones. Reductions must be thread-safe, as [`CfsBuilder::build_all`] applies them to
+/// several [`CFG`] at once.
+pub trait Reduction: Send + Sync {
+    /// Tries to reduce the given `node` of the `graph`.
+    ///
+    /// `preds` contains the predecessors of every node in the `graph`, and `loops` is true for
+    /// every node being part of a loop.
+    ///
+    /// On success, returns the new block and its successor in the graph. The children of the new
+    /// block are the nodes of the graph being replaced, and they must all be nodes of the graph,
+    /// otherwise the reduction is ignored. Any outgoing edge of the replaced nodes is discarded,
+    /// except the one to the returned successor.
+    ///
+    /// The structuring fails with [`Error::Structuring`] if the successor is one of the replaced
+    /// nodes, or if the new block is already part of the graph.
+    fn reduce(
+        &self,
+        node: &StructureBlock,
+        graph: &DirectedGraph<StructureBlock>,
+        preds: &HashMap<StructureBlock, HashSet<StructureBlock>>,
+        loops: &HashMap<StructureBlock, bool>,
+    ) -> Option<(StructureBlock, Option<StructureBlock>)>;
+
+    /// Returns the name of this reduction, as reported by [`CFS::trace`].
+    fn name(&self) -> &str {
+        "Custom"
     }
 }
 
-fn reduce_switch<'a>(
-    node: &'a StructureBlock,
-    graph: &'a DirectedGraph<StructureBlock>,
-    preds: &HashMap<&'a StructureBlock, HashSet<&'a StructureBlock>>,
-    _: &LoopHelper<'a>,
-) -> Option<Reduction<'a>> {
-    let children = graph.neighbours(node);
-    if children.len() >= 3 {
-        let mut components = HashSet::new();
-        components.insert(node);
-        let mut oldlen = 0;
-        // iteratively add nodes (having all preds inside the switch) until the switch is complete
-        while components.len() != oldlen {
-            oldlen = components.len();
-            let neighbours = components
-                .iter()
-                .flat_map(|&x| graph.neighbours(x))
-                .collect::<HashSet<_>>();
-            for child in neighbours {
-                if let Some(cur_preds) = preds.get(child) {
-                    if !cur_preds.iter().any(|&x| !components.contains(x)) {
-                        components.insert(child);
-                    }
-                }
-            }
+/// Single step performed during the [`CFS`] creation.
+///
+/// The steps are recorded in the order they are performed and can be retrieved with
+/// [`CFS::trace`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub(super) pattern: String,
+    pub(super) consumed: Vec<StructureBlock>,
+    pub(super) result: StructureBlock,
+}
+
+impl TraceEntry {
+    fn new(pattern: String, mut consumed: Vec<StructureBlock>, result: StructureBlock) -> Self {
+        consumed.sort_by_key(|node| node.offset());
+        if log::log_enabled!(log::Level::Trace) {
+            let names = consumed.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+            log::trace!("{}: [{}] -> {}", pattern, names.join(", "), result);
         }
-        // now we need to find the next node.
-        // first find the nodes with no children considering only the switch components
-        let no_exit = components
-            .iter()
-            .filter(|&x| !graph.neighbours(x).iter().any(|y| components.contains(y)))
-            .collect::<Vec<_>>();
-        let next;
-        if no_exit.len() == 1 {
-            // the exit is part of the components set
-            let exit = **no_exit.last().unwrap();
-            components.remove(exit);
-            next = Some(exit);
-            let block = Arc::new(NestedBlock::new(
-                BlockType::Switch,
-                components.iter().copied().cloned().collect(),
-            ));
-            Some(Reduction {
-                old: components,
-                new: StructureBlock::from(block),
-                next,
-            })
-        } else {
-            let exit_set = no_exit
-                .into_iter()
-                .flat_map(|&x| graph.neighbours(x))
-                .collect::<HashSet<_>>();
-            if exit_set.len() == 1 {
-                // all the nodes point to the same exit
-                next = Some(exit_set.into_iter().next().unwrap());
-                let block = Arc::new(NestedBlock::new(
-                    BlockType::Switch,
-                    components.iter().copied().cloned().collect(),
-                ));
-                Some(Reduction {
-                    old: components,
-                    new: StructureBlock::from(block),
-                    next,
-                })
-            } else {
-                None
-            }
+        #[cfg(feature = "tracing")]
+        tracing::trace!(
+            pattern = %pattern,
+            consumed = consumed.len(),
+            result = %result,
+            "reduction"
+        );
+        TraceEntry {
+            pattern,
+            consumed,
+            result,
         }
-    } else {
-        None
+    }
+
+    /// Returns the name of the pattern applied in this step.
+    ///
+    /// This is the name of the [`ReductionKind`] or of the custom [`Reduction`]. Duplications of
+    /// irreducible loop entries and the final fallback have the name of the [`BlockType`] they
+    /// create.
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Returns the nodes replaced in this step, ordered by offset.
+    pub fn consumed(&self) -> &[StructureBlock] {
+        &self.consumed
+    }
+
+    /// Returns the node created in this step.
+    pub fn result(&self) -> &StructureBlock {
+        &self.result
     }
 }
 
-fn reduce_sequence<'a>(
-    node: &'a StructureBlock,
-    graph: &'a DirectedGraph<StructureBlock>,
-    preds: &HashMap<&'a StructureBlock, HashSet<&'a StructureBlock>>,
-    _: &LoopHelper<'a>,
-) -> Option<Reduction<'a>> {
-    // conditions for a sequence:
-    // - current node has only one successor node
-    // - successor has only one predecessor (the current node)
-    // - successor has one or none successors
-    //   ^--- this is necessary to avoid a double exit sequence
-    let children = graph.neighbours(node);
-    if children.len() == 1 {
-        let next = children.first().unwrap();
-        let nextnexts = graph.neighbours(next);
-        if preds.get(next).map_or(0, |x| x.len()) == 1 {
-            let mut reduction = construct_and_flatten_sequence(node, next);
-            match nextnexts.len() {
-                0 => Some(reduction),
-                1 => {
-                    let nextnext = nextnexts.first().unwrap();
-                    if nextnext != node {
-                        reduction.next = Some(nextnext);
-                    } else {
-                        // particular type of looping sequence, still don't know how to handle this
-                        match reduction.new {
-                            StructureBlock::Nested(ref mut nb) => {
-                                Arc::get_mut(nb).unwrap().block_type = BlockType::SelfLooping
-                            }
-                            _ => panic!(),
-                        }
-                    }
-                    Some(reduction)
-                }
-                _ => None,
-            }
-        } else {
-            None
+/// Builder used to customize the reductions applied during the [`CFS`] creation.
+///
+/// Reductions are attempted in priority order on every node and the first one succeeding is
+/// applied. By default, all the reductions are enabled in the order given by
+/// [`ReductionKind::all`].
+/// # Examples
+/// Basic usage:
+/// ```
+/// use bincc::analysis::{CfsBuilder, ReductionKind};
+///
+/// let builder = CfsBuilder::new()
+///     .disable(ReductionKind::Switch)
+///     .disable(ReductionKind::ProperInterval);
+///
+/// assert!(!builder.reductions().contains(&ReductionKind::Switch));
+/// ```
+#[derive(Clone)]
+pub struct CfsBuilder {
+    reductions: Vec<ReductionKind>,
+    custom: Vec<Arc<dyn Reduction>>,
+    cache: Option<Arc<StructureCache>>,
+    progress: Option<Arc<dyn ProgressSink>>,
+    options: Options,
+}
+
+// settings of the structuring, other than the reductions.
+#[derive(Debug, Clone, Default)]
+struct Options {
+    // upper bounds to the work performed while structuring a CFG
+    iterations: Option<usize>,
+    size: Option<usize>,
+    preserve_loops: bool,
+    refine_conditions: bool,
+    annotate_entries: bool,
+    snapshots: bool,
+    timings: bool,
+    cancellation: Option<CancellationToken>,
+}
+
+impl Options {
+    fn allows(&self, iterations: usize, size: usize) -> bool {
+        self.iterations.is_none_or(|max| iterations <= max)
+            && self.size.is_none_or(|max| size <= max)
+            && !self.is_cancelled()
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancellation
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+    }
+}
+
+impl Default for CfsBuilder {
+    fn default() -> Self {
+        CfsBuilder {
+            reductions: ReductionKind::all().to_vec(),
+            custom: Vec::new(),
+            cache: None,
+            progress: None,
+            options: Options::default(),
         }
-    } else {
-        None
     }
 }
 
-fn ascend_if_chain<'a>(
-    mut rev_chain: Vec<&'a StructureBlock>,
-    cont: &'a StructureBlock,
-    graph: &DirectedGraph<StructureBlock>,
-    preds: &HashMap<&'a StructureBlock, HashSet<&'a StructureBlock>>,
-) -> Vec<&'a StructureBlock> {
-    let mut visited = rev_chain.iter().cloned().collect::<HashSet<_>>();
-    let mut cur_head = *rev_chain.last().unwrap();
-    while preds.get(cur_head).unwrap().len() == 1 {
-        cur_head = preds.get(cur_head).unwrap().iter().last().unwrap();
-        if !visited.contains(cur_head) {
-            visited.insert(cur_head);
-            let head_children = graph.neighbours(cur_head);
-            if head_children.len() == 2 {
+impl CfsBuilder {
+    /// Creates a new builder with all the reductions enabled.
+    pub fn new() -> CfsBuilder {
+        CfsBuilder::default()
+    }
+
+    /// Enables a reduction.
+    ///
+    /// If the reduction was disabled, it is added with the lowest priority.
+    pub fn enable(mut self, reduction: ReductionKind) -> CfsBuilder {
+        if !self.reductions.contains(&reduction) {
+            self.reductions.push(reduction);
+        }
+        self
+    }
+
+    /// Disables a reduction.
+    pub fn disable(mut self, reduction: ReductionKind) -> CfsBuilder {
+        self.reductions.retain(|r| r != &reduction);
+        self
+    }
+
+    /// Sets the enabled reductions and their priority, from the highest to the lowest.
+    ///
+    /// Reductions not appearing in the list are disabled, and duplicates are ignored.
+    pub fn order(mut self, reductions: &[ReductionKind]) -> CfsBuilder {
+        self.reductions.clear();
+        for reduction in reductions {
+            if !self.reductions.contains(reduction) {
+                self.reductions.push(*reduction);
+            }
+        }
+        self
+    }
+
+    /// Registers a custom reduction.
+    ///
+    /// Custom reductions have higher priority than the built-in ones, and are attempted in the
+    /// same order they are registered.
+    pub fn with_reduction<R: Reduction + 'static>(mut self, reduction: R) -> CfsBuilder {
+        self.custom.push(Arc::new(reduction));
+        self
+    }
+
+    /// Uses the given cache to avoid structuring again [`CFG`]s with an already seen shape.
+    ///
+    /// [`CFS`] retrieved from the cache have an empty [`CFS::trace`] and [`CFS::snapshots`]. The
+    /// cache is not used if custom reductions are registered.
+    pub fn with_cache(mut self, cache: Arc<StructureCache>) -> CfsBuilder {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Reports the progress of [`CfsBuilder::build_all`] to the given sink, as
+    /// [`Phase::Structuring`].
+    ///
+    /// Each function is identified by the start of its
+    /// [`FunctionMetadata`](crate::analysis::FunctionMetadata), if any, or by the offset of its
+    /// root otherwise.
+    pub fn with_progress(mut self, progress: Arc<dyn ProgressSink>) -> CfsBuilder {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Stops the structuring when the given token is cancelled.
+    ///
+    /// The token is checked before every step, so a [`CFG`] being structured when the token is
+    /// cancelled stops as if [`CfsBuilder::max_iterations`] were reached: the remaining nodes are
+    /// wrapped into a [`BlockType::ImproperRegion`], and the partial structures are available
+    /// from [`CFS::result`]. [`CfsBuilder::try_build`] returns instead [`Error::Cancelled`].
+    ///
+    /// The [`CFG`]s of [`CfsBuilder::build_all`] not yet started when the token is cancelled are
+    /// not structured at all, and result in [`Error::Cancelled`].
+    pub fn with_cancellation(mut self, token: CancellationToken) -> CfsBuilder {
+        self.options.cancellation = Some(token);
+        self
+    }
+
+    /// Sets the maximum amount of steps performed while structuring a [`CFG`].
+    ///
+    /// Steps are counted as in [`CfsStats::iterations`]. When the limit is reached, the
+    /// structuring stops and the remaining nodes are wrapped into a
+    /// [`BlockType::ImproperRegion`], or [`Error::Budget`] is returned by
+    /// [`CfsBuilder::try_build`]. By default there is no limit.
+    pub fn max_iterations(mut self, iterations: usize) -> CfsBuilder {
+        self.options.iterations = Some(iterations);
+        self
+    }
+
+    /// Represents every edge removed from the loops with an explicit marker.
+    ///
+    /// Loops with more than one exit, or jumping back to their header from the middle of their
+    /// body, can not be structured as they are, so these edges are removed before the reduction
+    /// (see [`CFS::removed_edges`]). Every removed edge is represented by a marker in the
+    /// innermost loop containing its source: a [`BlockType::Continue`] if it reaches the header
+    /// of that loop, and a [`BlockType::Break`] otherwise, even when it reaches a block other
+    /// than the loop exit. With this mode, an edge reaching neither the header nor the exit of
+    /// the loop is instead represented by a [`BlockType::Goto`] marker, and the edge is
+    /// available in [`StructureBlock::exits`] of every marker. These edges are then part of the
+    /// structure: they are not returned by [`CFS::removed_edges`], and they are re-created by
+    /// [`StructureBlock::to_cfg`], so the original control flow can be recovered from the tree.
+    pub fn preserve_loops(mut self) -> CfsBuilder {
+        self.options.preserve_loops = true;
+        self
+    }
+
+    /// Structures the unstructured regions using reaching conditions instead of duplicating
+    /// nodes.
+    ///
+    /// By default, irreducible regions are made reducible by duplicating some of their nodes (see
+    /// [`CFS::duplicated`]), and acyclic regions matching no pattern are kept as
+    /// [`BlockType::ProperInterval`], [`BlockType::ImproperInterval`] or
+    /// [`BlockType::ImproperRegion`]. With this mode, no node is duplicated, and every acyclic
+    /// region of this kind is replaced by a sequence of its children in topological order. Each
+    /// child that is not always executed is wrapped into a [`BlockType::Conditional`] block,
+    /// guarded by the condition under which the control flow reaches it from the head of the
+    /// region (see [`StructureBlock::reaching_condition`]). Cyclic regions are kept as they are.
+    pub fn refine_conditions(mut self) -> CfsBuilder {
+        self.options.refine_conditions = true;
+        self
+    }
+
+    /// Removes the jumps entering a loop in a block other than its header, and represents each
+    /// one of them with a [`BlockType::AbnormalEntry`] marker.
+    ///
+    /// Loops with multiple entry points can not be structured, so by default one of their
+    /// entries is duplicated until the loop has a single entry (see [`CFS::duplicated`]). With
+    /// this mode, the entry reached first in depth-first preorder becomes the header of the loop,
+    /// and the edges reaching the other entries from outside the loop are removed before the
+    /// reduction, without duplicating any node. The removed edges are returned by
+    /// [`CFS::removed_edges`], and each one is annotated with a marker in the innermost loop
+    /// entered by the edge, if that loop is structured.
+    pub fn annotate_abnormal_entries(mut self) -> CfsBuilder {
+        self.options.annotate_entries = true;
+        self
+    }
+
+    /// Records a copy of the graph after every step performed while structuring a [`CFG`].
+    ///
+    /// The copies are returned by [`CFS::snapshots`], and can be used to replay the reduction
+    /// step by step. Nodes are shared between snapshots, but each copy still has the size of the
+    /// graph, so this mode is disabled by default.
+    pub fn record_snapshots(mut self) -> CfsBuilder {
+        self.options.snapshots = true;
+        self
+    }
+
+    /// Records the time spent in each phase of the structuring of a [`CFG`].
+    ///
+    /// The timings are returned by [`CFS::timings`], and can be used to find the functions, and
+    /// the reductions, dominating the runtime of a large analysis. Measuring every reduction
+    /// attempt has a small cost, so this mode is disabled by default. It requires a clock, so it
+    /// can not be used on `wasm32-unknown-unknown`.
+    pub fn record_timings(mut self) -> CfsBuilder {
+        self.options.timings = true;
+        self
+    }
+
+    /// Sets the maximum amount of nodes of the [`CFG`] that can be structured.
+    ///
+    /// Larger [`CFG`]s are not reduced at all and are wrapped into a single
+    /// [`BlockType::ImproperRegion`], or [`Error::Budget`] is returned by
+    /// [`CfsBuilder::try_build`]. By default there is no limit.
+    pub fn max_size(mut self, nodes: usize) -> CfsBuilder {
+        self.options.size = Some(nodes);
+        self
+    }
+
+    /// Returns the enabled reductions, from the highest priority to the lowest.
+    ///
+    /// Custom reductions are not included.
+    pub fn reductions(&self) -> &[ReductionKind] {
+        &self.reductions
+    }
+
+    /// Creates the control flow structure from a [`CFG`] using the current configuration.
+    ///
+    /// See [`CFS::new`] for details.
+    pub fn build(&self, cfg: &CFG) -> Result<CFS, Error> {
+        self.build_from_cfg(cfg.clone())
+    }
+
+    /// Creates the control flow structure from a [`CFG`] using the current configuration, taking
+    /// ownership of the [`CFG`].
+    ///
+    /// See [`CFS::from_cfg`] for details.
+    pub fn build_from_cfg(&self, cfg: CFG) -> Result<CFS, Error> {
+        self.structure(cfg).map(|(cfs, _)| cfs)
+    }
+
+    /// Creates the control flow structure of a graph of arbitrary nodes, like the nodes of an
+    /// intermediate representation, using the current configuration.
+    ///
+    /// Every node reachable from the root of the graph is replaced by a synthetic [`BasicBlock`]
+    /// with length 1, and offset equal to its position in a depth-first visit. As in a [`CFG`],
+    /// the first successor of a node is considered the fallthrough and the second one the jump.
+    /// Along with the [`CFS`], this method returns the original node of every synthetic
+    /// [`BasicBlock`], and [`StructureBlock::map_leaves`] turns the tree into one having the
+    /// original nodes as leaves. The artificial entry point and sink added while structuring have
+    /// no original node.
+    /// # Examples
+    /// Basic usage:
+    /// ```
+    /// use bincc::analysis::{BlockType, CfsBuilder, DirectedGraph, StructureBlock};
+    /// use std::collections::HashMap;
+    ///
+    /// let graph = DirectedGraph {
+    ///     root: Some("head"),
+    ///     adjacency: HashMap::from([
+    ///         ("head", vec!["then", "tail"]),
+    ///         ("then", vec!["tail"]),
+    ///         ("tail", vec![]),
+    ///     ]),
+    /// };
+    /// let (cfs, nodes) = CfsBuilder::new().build_graph(&graph).unwrap();
+    /// let tree = cfs.get_tree().unwrap();
+    ///
+    /// assert_eq!(tree.children()[0].block_type(), BlockType::IfThen);
+    /// let head = tree.children()[0].children()[0].basic_blocks()[0];
+    /// assert_eq!(nodes[&head], "head");
+    /// let tree = tree.map_leaves(|bb| nodes[bb]);
+    /// assert_eq!(tree.children()[0].children()[0], StructureBlock::Basic("head"));
+    /// ```
+    pub fn build_graph<T: Clone + Eq + Hash>(
+        &self,
+        graph: &DirectedGraph<T>,
+    ) -> Result<(CFS, HashMap<BasicBlock, T>), Error> {
+        let blocks = graph
+            .dfs_preorder()
+            .enumerate()
+            .map(|(index, node)| {
+                let bb = BasicBlock {
+                    offset: index as u64,
+                    length: 1,
+                };
+                (node.clone(), bb)
+            })
+            .collect::<HashMap<_, _>>();
+        let edges = blocks
+            .iter()
+            .map(|(node, bb)| {
+                let succs = graph
+                    .neighbours(node)
+                    .iter()
+                    .filter_map(|succ| blocks.get(succ).copied())
+                    .collect();
+                (*bb, succs)
+            })
+            .collect();
+        let cfg = CFG {
+            root: graph.root().and_then(|root| blocks.get(root).copied()),
+            edges,
+            info: HashMap::new(),
+            metadata: None,
+        };
+        let nodes = blocks.into_iter().map(|(node, bb)| (bb, node)).collect();
+        Ok((self.build_from_cfg(cfg)?, nodes))
+    }
+
+    /// Creates the control flow structure from a [`CFG`] using the current configuration,
+    /// failing if the options set with [`CfsBuilder::max_iterations`] or [`CfsBuilder::max_size`]
+    /// are exceeded, or if the structuring is cancelled, see [`CfsBuilder::with_cancellation`].
+    /// # Examples
+    /// Basic usage:
+    /// ```
+    /// use bincc::analysis::{CfsBuilder, CFG};
+    /// use bincc::disasm::{Architecture, Statement, StatementFamily};
+    /// use bincc::Error;
+    ///
+    /// let stmts = vec![
+    ///     Statement::new(0x610, StatementFamily::CJMP, "je 0x618"),
+    ///     Statement::new(0x614, StatementFamily::MOV, "mov eax, 6"),
+    ///     Statement::new(0x618, StatementFamily::RET, "ret"),
+    /// ];
+    /// let cfg = CFG::new(&stmts, 0x619, Architecture::X86(64));
+    ///
+    /// assert!(CfsBuilder::new().max_size(3).try_build(&cfg).is_ok());
+    /// assert!(matches!(
+    ///     CfsBuilder::new().max_size(2).try_build(&cfg),
+    ///     Err(Error::Budget)
+    /// ));
+    /// ```
+    pub fn try_build(&self, cfg: &CFG) -> Result<CFS, Error> {
+        self.try_build_from_cfg(cfg.clone())
+    }
+
+    /// Creates the control flow structure from a [`CFG`] using the current configuration, taking
+    /// ownership of the [`CFG`] and failing if the options are exceeded.
+    ///
+    /// See [`CfsBuilder::try_build`] for details.
+    pub fn try_build_from_cfg(&self, cfg: CFG) -> Result<CFS, Error> {
+        match self.structure(cfg)? {
+            (cfs, false) => Ok(cfs),
+            (_, true) if self.options.is_cancelled() => Err(Error::Cancelled),
+            (_, true) => Err(Error::Budget),
+        }
+    }
+
+    // returns the CFS and true if the structuring stopped because of the limits.
+    fn structure(&self, cfg: CFG) -> Result<(CFS, bool), Error> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("structure", offset = function_offset(&cfg)).entered();
+        check_consistency(&cfg)?;
+        let start = self.options.timings.then(Instant::now);
+        #[cfg(feature = "tracing")]
+        let (start_trace, blocks) = (Instant::now(), cfg.len());
+        let (mut cfs, exhausted) = match &self.cache {
+            Some(cache) if self.custom.is_empty() => {
+                CFS::with_cache(cfg, &self.reductions, &self.options, cache)
+            }
+            _ => CFS::with_reductions(cfg, &self.reductions, &self.custom, &self.options),
+        }?;
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            blocks,
+            iterations = cfs.stats.iterations,
+            exhausted,
+            elapsed = ?start_trace.elapsed(),
+            "function structured"
+        );
+        if let Some(start) = start {
+            cfs.timings.get_or_insert_with(Timings::default).structuring = start.elapsed();
+        }
+        Ok((cfs, exhausted))
+    }
+
+    /// Creates the control flow structure of every [`CFG`] in the given set using the current
+    /// configuration.
+    ///
+    /// See [`CFS::analyze_all`] for details.
+    pub fn build_all<I: IntoIterator<Item = CFG>>(&self, cfgs: I) -> Vec<Result<CFS, Error>> {
+        let cfgs = cfgs.into_iter().collect::<Vec<_>>();
+        let len = cfgs.len();
+        let threads = thread::available_parallelism()
+            .map_or(1, |n| n.get())
+            .min(len);
+        let progress = self.progress.as_deref().unwrap_or(&());
+        progress.phase_started(Phase::Structuring, len);
+        let queue = Mutex::new(cfgs.into_iter().enumerate());
+        let done = Mutex::new(Vec::with_capacity(len));
+        let worker = || loop {
+            let next = queue.lock().unwrap().next();
+            match next {
+                Some((index, cfg)) => {
+                    let offset = function_offset(&cfg);
+                    let cfs = match self.options.is_cancelled() {
+                        true => Err(Error::Cancelled),
+                        false => self.build_from_cfg(cfg),
+                    };
+                    done.lock().unwrap().push((index, cfs));
+                    progress.function_completed(Phase::Structuring, offset);
+                }
+                None => break,
+            }
+        };
+        if threads > 1 {
+            thread::scope(|scope| {
+                for _ in 0..threads {
+                    scope.spawn(worker);
+                }
+            });
+        } else {
+            // no threads are spawned on targets without them, like wasm32-unknown-unknown
+            worker();
+        }
+        progress.phase_finished(Phase::Structuring);
+        let mut done = done.into_inner().unwrap();
+        done.sort_unstable_by_key(|(index, _)| *index);
+        done.into_iter().map(|(_, cfs)| cfs).collect()
+    }
+}
+
+// checks that the CFG has a root, and that every edge reaches a block of the CFG.
+fn check_consistency(cfg: &CFG) -> Result<(), Error> {
+    let root = match cfg.root {
+        Some(root) => root,
+        None if cfg.edges.is_empty() => return Ok(()),
+        None => return Err(Error::MalformedCfg("missing root".to_string())),
+    };
+    if !cfg.edges.contains_key(&root) {
+        let msg = format!("root {} is not part of the CFG", root);
+        return Err(Error::MalformedCfg(msg));
+    }
+    for (src, dsts) in &cfg.edges {
+        if let Some(dst) = dsts.iter().find(|dst| !cfg.edges.contains_key(dst)) {
+            let msg = format!("edge {} -> {} reaches a missing block", src, dst);
+            return Err(Error::MalformedCfg(msg));
+        }
+        if let Some(dst) = dsts
+            .iter()
+            .enumerate()
+            .find_map(|(i, dst)| dsts[..i].contains(dst).then_some(dst))
+        {
+            let msg = format!("edge {} -> {} is duplicated", src, dst);
+            return Err(Error::MalformedCfg(msg));
+        }
+        if src.is_sink() && !dsts.is_empty() {
+            return Err(Error::MalformedCfg("the sink has successors".to_string()));
+        }
+        if src.is_entry_point() && *src != root {
+            let msg = "the artificial entry point is not the root".to_string();
+            return Err(Error::MalformedCfg(msg));
+        }
+    }
+    Ok(())
+}
+
+// offset identifying the function of a CFG: the start of its metadata or the offset of its root.
+fn function_offset(cfg: &CFG) -> u64 {
+    cfg.metadata()
+        .map(|metadata| metadata.start())
+        .or_else(|| cfg.root.map(|root| root.offset))
+        .unwrap_or(0)
+}
+
+/// Costs used by [`tree_distance_with_costs`] for each edit operation.
+///
+/// By default every operation costs 1, and relabelling a block with its own type costs 0.
+#[derive(Debug, Clone)]
+pub struct EditCosts {
+    indel: HashMap<BlockType, u32>,
+    relabel: u32,
+}
+
+impl Default for EditCosts {
+    fn default() -> Self {
+        EditCosts {
+            indel: HashMap::new(),
+            relabel: 1,
+        }
+    }
+}
+
+impl EditCosts {
+    /// Creates a new set of costs where every operation costs 1.
+    pub fn new() -> EditCosts {
+        EditCosts::default()
+    }
+
+    /// Sets the cost of inserting or deleting a block of the given type.
+    pub fn indel(mut self, block_type: BlockType, cost: u32) -> EditCosts {
+        self.indel.insert(block_type, cost);
+        self
+    }
+
+    /// Sets the cost of replacing a block with a block of a different type.
+    pub fn relabel(mut self, cost: u32) -> EditCosts {
+        self.relabel = cost;
+        self
+    }
+
+    fn indel_cost(&self, block_type: BlockType) -> u32 {
+        *self.indel.get(&block_type).unwrap_or(&1)
+    }
+
+    fn relabel_cost(&self, a: BlockType, b: BlockType) -> u32 {
+        if a == b {
+            0
+        } else {
+            self.relabel
+        }
+    }
+}
+
+/// Weights used by [`tree_similarity_with_weights`] for each [`BlockType`].
+///
+/// A matching subtree contributes to the similarity with the weight of its root type multiplied
+/// by its size. By default every type weighs 1, while [`SimilarityWeights::from_corpus`] assigns
+/// higher weights to the rarest types.
+#[derive(Debug, Clone)]
+pub struct SimilarityWeights {
+    pub(super) rarity: HashMap<BlockType, f64>,
+    // weight of the types not in the map
+    pub(super) default: f64,
+}
+
+impl Default for SimilarityWeights {
+    fn default() -> Self {
+        SimilarityWeights {
+            rarity: HashMap::new(),
+            default: 1.0,
+        }
+    }
+}
+
+impl SimilarityWeights {
+    /// Creates a new set of weights where every type weighs 1.
+    pub fn new() -> SimilarityWeights {
+        SimilarityWeights::default()
+    }
+
+    /// Creates the weights from the frequency of each [`BlockType`] in the given trees.
+    ///
+    /// Each type weighs `1 + ln(N/n)`, where `N` is the amount of blocks in the corpus and `n`
+    /// the amount of blocks of the given type. Types not found in the corpus are considered as
+    /// appearing once.
+    pub fn from_corpus<'a, I: IntoIterator<Item = &'a StructureBlock>>(
+        trees: I,
+    ) -> SimilarityWeights {
+        let mut counts = HashMap::new();
+        let mut total = 0;
+        for node in trees.into_iter().flat_map(|tree| tree.iter_preorder()) {
+            *counts.entry(node.block_type()).or_insert(0) += 1;
+            total += 1;
+        }
+        let idf = |count: usize| 1.0 + (max(total, 1) as f64 / count as f64).ln();
+        SimilarityWeights {
+            rarity: counts
+                .into_iter()
+                .map(|(block_type, count)| (block_type, idf(count)))
+                .collect(),
+            default: idf(1),
+        }
+    }
+
+    /// Sets the weight of the given type.
+    pub fn rarity(mut self, block_type: BlockType, weight: f64) -> SimilarityWeights {
+        self.rarity.insert(block_type, weight);
+        self
+    }
+
+    /// Returns the weight of the given type.
+    pub fn weight(&self, block_type: BlockType) -> f64 {
+        *self.rarity.get(&block_type).unwrap_or(&self.default)
+    }
+}
+
+/// Structures only the single-entry single-exit region of a [`CFG`] between two blocks.
+///
+/// The region contains every block reachable from `entry` without passing through `exit`. Every
+/// edge leaving the region must reach `exit`, and every block of the region other than `entry`
+/// must be reached only from blocks of the region. The region is structured with the default
+/// settings of [`CFS::new`], and `exit` is kept in the resulting tree as the last block, without
+/// successors, so the region has a single exit as every function structured by a [`CFS`].
+///
+/// Returns [`None`] if the blocks do not delimit a single-entry single-exit region.
+/// # Examples
+/// Basic usage:
+/// ```
+/// use bincc::analysis::{structure_region, BlockType, Graph, CFG};
+/// use bincc::disasm::{Architecture, Statement, StatementFamily};
+///
+/// let stmts = vec![
+///     Statement::new(0x10, StatementFamily::MOV, "mov eax, 0"),
+///     Statement::new(0x14, StatementFamily::CMP, "cmp eax, 10"),
+///     Statement::new(0x18, StatementFamily::CJMP, "jge 0x24"),
+///     Statement::new(0x1C, StatementFamily::ADD, "add eax, 1"),
+///     Statement::new(0x20, StatementFamily::JMP, "jmp 0x14"),
+///     Statement::new(0x24, StatementFamily::RET, "ret"),
+/// ];
+/// let cfg = CFG::new(&stmts, 0x25, Architecture::X86(64));
+/// let head = cfg.bfs().find(|bb| bb.offset == 0x14).unwrap();
+/// let exit = cfg.bfs().find(|bb| bb.offset == 0x24).unwrap();
+/// let region = structure_region(&cfg, head, exit).unwrap();
+///
+/// // the first child is the entry point added before the loop head
+/// assert_eq!(region.block_type(), BlockType::Sequence);
+/// assert_eq!(region.children()[1].block_type(), BlockType::While);
+/// ```
+pub fn structure_region(
+    cfg: &CFG,
+    entry: &BasicBlock,
+    exit: &BasicBlock,
+) -> Option<StructureBlock> {
+    if entry == exit || !cfg.edges.contains_key(entry) || !cfg.edges.contains_key(exit) {
+        return None;
+    }
+    let mut region = HashSet::from([*entry]);
+    let mut stack = vec![*entry];
+    let mut exited = false;
+    while let Some(node) = stack.pop() {
+        let succs = cfg.neighbours(&node);
+        if succs.is_empty() {
+            // the function returns from inside the region
+            return None;
+        }
+        for succ in succs {
+            if succ == exit {
+                exited = true;
+            } else if region.insert(*succ) {
+                stack.push(*succ);
+            }
+        }
+    }
+    let side_entry = cfg.edges.iter().any(|(src, dsts)| {
+        !region.contains(src) && dsts.iter().any(|dst| dst != entry && region.contains(dst))
+    });
+    if !exited || side_entry {
+        return None;
+    }
+    let mut edges = region
+        .iter()
+        .map(|bb| (*bb, cfg.neighbours(bb).to_vec()))
+        .collect::<HashMap<_, _>>();
+    edges.insert(*exit, Vec::new());
+    let info = cfg
+        .info
+        .iter()
+        .filter(|(bb, _)| region.contains(bb))
+        .map(|(bb, info)| (*bb, info.clone()))
+        .collect();
+    let subgraph = CFG {
+        root: Some(*entry),
+        edges,
+        info,
+        metadata: None,
+    };
+    CFS::new(&subgraph).ok()?.get_tree()
+}
+
+/// Checks if the trees of two [`CFS`] have the same shape.
+///
+/// Two trees have the same shape if they have the same [`BlockType`] and amount of children in
+/// every position, as in [`StructureBlock::structural_equality`]. Two [`CFS`] without a tree have
+/// the same shape. Use [`structure_diff`] to find where the two trees differ.
+pub fn same_structure(a: &CFS, b: &CFS) -> bool {
+    structure_diff(a, b).is_none()
+}
+
+/// Describes the first difference between the trees of two [`CFS`], or returns [`None`] if they
+/// have the same shape.
+///
+/// The trees are visited in preorder, and the first node with a different [`BlockType`] or
+/// amount of children is reported along with its path from the root, as in
+/// `root.children[2]: While vs DoWhile` or `root.children[0]: 3 children vs 2 children`. A
+/// missing tree is reported as `root: None vs Sequence`.
+/// # Examples
+/// Basic usage:
+/// ```
+/// use bincc::analysis::{structure_diff, CFG, CFS};
+/// use bincc::disasm::{Architecture, Statement, StatementFamily};
+///
+/// let ifthen = vec![
+///     Statement::new(0x10, StatementFamily::CJMP, "je 0x18"),
+///     Statement::new(0x14, StatementFamily::MOV, "mov eax, 6"),
+///     Statement::new(0x18, StatementFamily::RET, "ret"),
+/// ];
+/// let ifelse = vec![
+///     Statement::new(0x10, StatementFamily::CJMP, "je 0x1c"),
+///     Statement::new(0x14, StatementFamily::MOV, "mov eax, 6"),
+///     Statement::new(0x18, StatementFamily::JMP, "jmp 0x20"),
+///     Statement::new(0x1C, StatementFamily::MOV, "mov eax, 7"),
+///     Statement::new(0x20, StatementFamily::RET, "ret"),
+/// ];
+/// let arch = Architecture::X86(64);
+/// let a = CFS::new(&CFG::new(&ifthen, 0x19, arch)).unwrap();
+/// let b = CFS::new(&CFG::new(&ifelse, 0x21, arch)).unwrap();
+///
+/// assert_eq!(structure_diff(&a, &a), None);
+/// assert_eq!(
+///     structure_diff(&a, &b).unwrap(),
+///     "root.children[0]: If-Then vs If-Then-Else"
+/// );
+/// ```
+pub fn structure_diff(a: &CFS, b: &CFS) -> Option<String> {
+    match (a.get_tree(), b.get_tree()) {
+        (None, None) => None,
+        (Some(a), Some(b)) => first_divergence(&a, &b, "root".to_string()),
+        (a, b) => {
+            let name = |tree: Option<StructureBlock>| match tree {
+                Some(tree) => tree.block_type().to_string(),
+                None => "None".to_string(),
+            };
+            Some(format!("root: {} vs {}", name(a), name(b)))
+        }
+    }
+}
+
+fn first_divergence(a: &StructureBlock, b: &StructureBlock, path: String) -> Option<String> {
+    if a.block_type() != b.block_type() {
+        Some(format!(
+            "{}: {} vs {}",
+            path,
+            a.block_type(),
+            b.block_type()
+        ))
+    } else if a.children().len() != b.children().len() {
+        Some(format!(
+            "{}: {} children vs {} children",
+            path,
+            a.children().len(),
+            b.children().len()
+        ))
+    } else {
+        a.children()
+            .iter()
+            .zip(b.children())
+            .enumerate()
+            .find_map(|(index, (a, b))| {
+                first_divergence(a, b, format!("{}.children[{}]", path, index))
+            })
+    }
+}
+
+/// Calculates the ordered tree edit distance between two [`StructureBlock`] trees.
+///
+/// The distance is the minimum amount of insertions, deletions and relabelling of blocks
+/// required to transform one tree into the other. Only the [`BlockType`] of each block is
+/// considered, so basic block offsets are ignored.
+///
+/// This is equivalent to [`tree_distance_with_costs`] with the default [`EditCosts`].
+/// # Examples
+/// Basic usage:
+/// ```
+/// use bincc::analysis::{tree_distance, BasicBlock, BlockType, NestedBlock, StructureBlock};
+/// use std::sync::Arc;
+///
+/// let bb0 = StructureBlock::from(BasicBlock { offset: 0x10, length: 4 });
+/// let bb1 = StructureBlock::from(BasicBlock { offset: 0x20, length: 8 });
+/// let seq = NestedBlock::new(BlockType::Sequence, vec![bb0.clone(), bb1.clone()]);
+/// let ifthen = NestedBlock::new(BlockType::IfThen, vec![bb0, bb1]);
+/// let a = StructureBlock::from(Arc::new(seq));
+/// let b = StructureBlock::from(Arc::new(ifthen));
+///
+/// assert_eq!(tree_distance(&a, &a), 0);
+/// assert_eq!(tree_distance(&a, &b), 1);
+/// ```
+pub fn tree_distance(a: &StructureBlock, b: &StructureBlock) -> u32 {
+    tree_distance_with_costs(a, b, &EditCosts::default())
+}
+
+/// Calculates the ordered tree edit distance between two [`StructureBlock`] trees, using the
+/// given costs for each operation.
+///
+/// The distance is calculated with the Zhang-Shasha algorithm, and is exact.
+#[allow(clippy::needless_range_loop)] // the same indices are used on the postorder tables
+pub fn tree_distance_with_costs(a: &StructureBlock, b: &StructureBlock, costs: &EditCosts) -> u32 {
+    let ta = PostorderTree::new(a);
+    let tb = PostorderTree::new(b);
+    let (n, m) = (ta.labels.len(), tb.labels.len());
+    let mut treedist = vec![vec![0; m]; n];
+    for &i in &ta.keyroots {
+        for &j in &tb.keyroots {
+            let (li, lj) = (ta.leftmost[i], tb.leftmost[j]);
+            let mut forestdist = vec![vec![0; j - lj + 2]; i - li + 2];
+            for x in li..=i {
+                forestdist[x - li + 1][0] = forestdist[x - li][0] + costs.indel_cost(ta.labels[x]);
+            }
+            for y in lj..=j {
+                forestdist[0][y - lj + 1] = forestdist[0][y - lj] + costs.indel_cost(tb.labels[y]);
+            }
+            for x in li..=i {
+                for y in lj..=j {
+                    let (fx, fy) = (x - li + 1, y - lj + 1);
+                    let delete = forestdist[fx - 1][fy] + costs.indel_cost(ta.labels[x]);
+                    let insert = forestdist[fx][fy - 1] + costs.indel_cost(tb.labels[y]);
+                    if ta.leftmost[x] == li && tb.leftmost[y] == lj {
+                        let relabel = forestdist[fx - 1][fy - 1]
+                            + costs.relabel_cost(ta.labels[x], tb.labels[y]);
+                        forestdist[fx][fy] = delete.min(insert).min(relabel);
+                        treedist[x][y] = forestdist[fx][fy];
+                    } else {
+                        let (px, py) = (ta.leftmost[x] - li, tb.leftmost[y] - lj);
+                        let subtree = forestdist[px][py] + treedist[x][y];
+                        forestdist[fx][fy] = delete.min(insert).min(subtree);
+                    }
+                }
+            }
+        }
+    }
+    treedist[n - 1][m - 1]
+}
+
+// tree flattened in postorder, as required by the Zhang-Shasha algorithm.
+struct PostorderTree {
+    labels: Vec<BlockType>,
+    // index of the leftmost leaf of the subtree rooted in each node
+    leftmost: Vec<usize>,
+    keyroots: Vec<usize>,
+}
+
+impl PostorderTree {
+    fn new(root: &StructureBlock) -> PostorderTree {
+        let mut labels = Vec::new();
+        let mut leftmost = Vec::new();
+        // (node, index of the next child to visit, leftmost leaf of the node if known)
+        let mut stack = vec![(root, 0, None)];
+        while let Some((node, child, lml)) = stack.pop() {
+            if child < node.len() {
+                stack.push((node, child + 1, lml));
+                stack.push((&node.children()[child], 0, None));
+            } else {
+                let index = labels.len();
+                let lml = lml.unwrap_or(index);
+                labels.push(node.block_type());
+                leftmost.push(lml);
+                // the first child completed sets the leftmost leaf of its parent
+                if let Some(parent) = stack.last_mut() {
+                    if parent.2.is_none() {
+                        parent.2 = Some(lml);
+                    }
+                }
+            }
+        }
+        let mut highest = HashMap::new();
+        for (index, lml) in leftmost.iter().enumerate() {
+            highest.insert(*lml, index);
+        }
+        let mut keyroots = highest.into_values().collect::<Vec<_>>();
+        keyroots.sort_unstable();
+        PostorderTree {
+            labels,
+            leftmost,
+            keyroots,
+        }
+    }
+}
+
+/// Calculates the similarity between two [`StructureBlock`] trees, between 0 and 1.
+///
+/// Unlike [`tree_distance`], every block is weighted by the size of its subtree, so a matching
+/// big loop counts more than several matching basic blocks. Only the [`BlockType`] of each
+/// block is considered.
+///
+/// This is equivalent to [`tree_similarity_with_weights`] with the default
+/// [`SimilarityWeights`].
+/// # Examples
+/// Basic usage:
+/// ```
+/// use bincc::analysis::{tree_similarity, BasicBlock, BlockType, NestedBlock, StructureBlock};
+/// use std::sync::Arc;
+///
+/// let bb = |offset| StructureBlock::from(BasicBlock { offset, length: 1 });
+/// let nest = |bt, children| StructureBlock::from(Arc::new(NestedBlock::new(bt, children)));
+/// let a = nest(BlockType::Sequence, vec![bb(0), nest(BlockType::While, vec![bb(1), bb(2)])]);
+/// let b = nest(BlockType::IfThen, vec![bb(0), nest(BlockType::While, vec![bb(1), bb(2)])]);
+///
+/// assert_eq!(tree_similarity(&a, &a), 1.0);
+/// assert!(tree_similarity(&a, &b) > 0.5);
+/// ```
+pub fn tree_similarity(a: &StructureBlock, b: &StructureBlock) -> f64 {
+    tree_similarity_with_weights(a, b, &SimilarityWeights::default())
+}
+
+/// Calculates the similarity between two [`StructureBlock`] trees, using the given weights for
+/// each [`BlockType`].
+///
+/// Every subtree of both trees is weighted by its size multiplied by the weight of its root.
+/// The similarity is the total weight of the subtrees with the same shape in both trees (see
+/// [`StructureBlock::structural_equality`]) over the average weight of the two trees, so it is
+/// 1 for trees with the same shape and 0 for trees with nothing in common.
+pub fn tree_similarity_with_weights(
+    a: &StructureBlock,
+    b: &StructureBlock,
+    weights: &SimilarityWeights,
+) -> f64 {
+    let subtrees_a = weighted_subtrees(a, weights);
+    let subtrees_b = weighted_subtrees(b, weights);
+    let total = subtrees_a
+        .values()
+        .chain(subtrees_b.values())
+        .map(|(count, weight)| *count as f64 * weight)
+        .sum::<f64>();
+    if total == 0.0 {
+        return 1.0;
+    }
+    let common = subtrees_a
+        .iter()
+        .filter_map(|(hash, (count_a, weight))| {
+            subtrees_b
+                .get(hash)
+                .map(|(count_b, _)| min(*count_a, *count_b) as f64 * weight)
+        })
+        .sum::<f64>();
+    2.0 * common / total
+}
+
+// groups the subtrees by shape, recording their amount and the weight of each one.
+fn weighted_subtrees(
+    tree: &StructureBlock,
+    weights: &SimilarityWeights,
+) -> HashMap<u64, (usize, f64)> {
+    let mut subtrees = HashMap::new();
+    subtree_shape(tree, weights, &mut subtrees);
+    subtrees
+}
+
+// hash of the shape and size of the subtree rooted in the node, computed from the ones of its
+// children so every node is visited once. The subtree is also recorded in the groups.
+fn subtree_shape(
+    node: &StructureBlock,
+    weights: &SimilarityWeights,
+    subtrees: &mut HashMap<u64, (usize, f64)>,
+) -> (u64, usize) {
+    let mut hasher = FnvHasher::default();
+    node.block_type().hash(&mut hasher);
+    hasher.write_usize(node.len());
+    let mut size = 1;
+    for child in node.children().iter() {
+        let (child_hash, child_size) = subtree_shape(child, weights, subtrees);
+        hasher.write_u64(child_hash);
+        size += child_size;
+    }
+    let hash = hasher.finish();
+    let weight = weights.weight(node.block_type()) * size as f64;
+    subtrees.entry(hash).or_insert((0, weight)).0 += 1;
+    (hash, size)
+}
+
+fn print_subgraph<T: std::fmt::Write>(node: &StructureBlock, id: usize, fmt: &mut T) -> usize {
+    let mut latest = id;
+    match node {
+        StructureBlock::Basic(bb) => {
+            if !bb.is_entry_point() && !bb.is_sink() {
+                writeln!(fmt, "{};", bb.offset).unwrap();
+            }
+        }
+        StructureBlock::Nested(_) if node.is_empty() => {
+            // markers have no basic blocks to cluster
+        }
+        StructureBlock::Nested(_) => {
+            writeln!(fmt, "subgraph cluster_{}{{", id).unwrap();
+            for child in node.children().iter() {
+                latest = print_subgraph(child, latest + 1, fmt);
+            }
+            writeln!(fmt, "label=\"{}\";\n}}", node.block_type()).unwrap();
+        }
+    }
+    latest
+}
+
+// predecessors of every node of the graph being reduced.
+type PredMap = HashMap<StructureBlock, HashSet<StructureBlock>>;
+
+// signature of a reduce_xxx method
+type ReduceFn = for<'a> fn(
+    &'a StructureBlock,
+    &'a DirectedGraph<StructureBlock>,
+    &'a PredMap,
+    &LoopHelper,
+) -> Result<Option<Reduced<'a>>, Error>;
+
+// returns the predecessors of a node of the graph being reduced.
+fn preds_of<'a>(
+    preds: &'a PredMap,
+    node: &StructureBlock,
+) -> Result<&'a HashSet<StructureBlock>, Error> {
+    preds.get(node).ok_or_else(|| missing_node(node))
+}
+
+// error of a lookup of a node that is not part of the graph being reduced.
+fn missing_node(node: &StructureBlock) -> Error {
+    Error::Structuring(format!("node {} is not part of the reduced graph", node))
+}
+
+// result of a reduce_xxx method
+struct Reduced<'a> {
+    // old nodes that will be removed. Not necessary equal to new.children()
+    // for example structures may expand previous structures, forcing the previous structure to
+    // be discarded and a new one to be created.
+    old: HashSet<&'a StructureBlock>,
+    // new node that will replace the old one
+    new: StructureBlock,
+    // successor of the newly created node
+    next: Option<&'a StructureBlock>,
+}
+
+fn reduce_self_loop<'a>(
+    node: &'a StructureBlock,
+    graph: &'a DirectedGraph<StructureBlock>,
+    _: &'a PredMap,
+    _: &LoopHelper,
+) -> Result<Option<Reduced<'a>>, Error> {
+    match node {
+        StructureBlock::Basic(_) => {
+            let children = graph.neighbours(node);
+            if children.len() == 2 && children.contains(node) {
+                let next = children.iter().filter(|x| x != &node).last().unwrap();
+                let block = Arc::new(NestedBlock::new(BlockType::SelfLooping, vec![node.clone()]));
+                Ok(Some(Reduced {
+                    old: hashset![node],
+                    new: StructureBlock::from(block),
+                    next: Some(next),
+                }))
+            } else {
+                Ok(None)
+            }
+        }
+        StructureBlock::Nested(_) => Ok(None),
+    }
+}
+
+fn reduce_switch<'a>(
+    node: &'a StructureBlock,
+    graph: &'a DirectedGraph<StructureBlock>,
+    preds: &'a PredMap,
+    _: &LoopHelper,
+) -> Result<Option<Reduced<'a>>, Error> {
+    let children = graph.neighbours(node);
+    if children.len() >= 3 {
+        let mut components = HashSet::new();
+        components.insert(node);
+        let mut oldlen = 0;
+        // iteratively add nodes (having all preds inside the switch) until the switch is complete
+        while components.len() != oldlen {
+            oldlen = components.len();
+            let neighbours = components
+                .iter()
+                .flat_map(|&x| graph.neighbours(x))
+                .collect::<HashSet<_>>();
+            for child in neighbours {
+                if let Some(cur_preds) = preds.get(child) {
+                    if !cur_preds.iter().any(|x| !components.contains(x)) {
+                        components.insert(child);
+                    }
+                }
+            }
+        }
+        // the head is also the head of a loop, reduced as such
+        if components
+            .iter()
+            .any(|x| graph.neighbours(x).contains(node))
+        {
+            return Ok(None);
+        }
+        // now we need to find the next node.
+        // first find the nodes with no children considering only the switch components
+        let no_exit = components
+            .iter()
+            .filter(|&x| !graph.neighbours(x).iter().any(|y| components.contains(y)))
+            .collect::<Vec<_>>();
+        let next;
+        if no_exit.len() == 1 {
+            // the exit is part of the components set
+            let exit = **no_exit.last().unwrap();
+            components.remove(exit);
+            next = Some(exit);
+            Ok(Some(Reduced {
+                new: construct_switch(node, &components),
+                old: components,
+                next,
+            }))
+        } else {
+            let exit_set = no_exit
+                .into_iter()
+                .flat_map(|&x| graph.neighbours(x))
+                .collect::<HashSet<_>>();
+            if exit_set.len() == 1 {
+                // all the nodes point to the same exit
+                next = Some(exit_set.into_iter().next().unwrap());
+                Ok(Some(Reduced {
+                    new: construct_switch(node, &components),
+                    old: components,
+                    next,
+                }))
+            } else {
+                Ok(None)
+            }
+        }
+    } else {
+        Ok(None)
+    }
+}
+
+// creates the switch block: the head is always the first child, followed by the cases ordered by
+// offset.
+fn construct_switch(
+    head: &StructureBlock,
+    components: &HashSet<&StructureBlock>,
+) -> StructureBlock {
+    let mut cases = components
+        .iter()
+        .filter(|&&x| x != head)
+        .copied()
+        .cloned()
+        .collect::<Vec<_>>();
+    cases.sort_unstable_by_key(|x| x.offset());
+    let content = std::iter::once(head.clone()).chain(cases).collect();
+    StructureBlock::from(Arc::new(NestedBlock::new(BlockType::Switch, content)))
+}
+
+fn reduce_sequence<'a>(
+    node: &'a StructureBlock,
+    graph: &'a DirectedGraph<StructureBlock>,
+    preds: &'a PredMap,
+    _: &LoopHelper,
+) -> Result<Option<Reduced<'a>>, Error> {
+    // conditions for a sequence:
+    // - current node has only one successor node
+    // - successor has only one predecessor (the current node)
+    // - successor has one or none successors
+    //   ^--- this is necessary to avoid a double exit sequence
+    match graph.neighbours(node) {
+        [next] if preds.get(next).map_or(0, HashSet::len) == 1 => {
+            let reduction = match graph.neighbours(next) {
+                [] => construct_and_flatten_sequence(node, next, BlockType::Sequence),
+                [nextnext] if nextnext != node => {
+                    let mut reduction =
+                        construct_and_flatten_sequence(node, next, BlockType::Sequence);
+                    reduction.next = Some(nextnext);
+                    reduction
+                }
+                // particular type of looping sequence, still don't know how to handle this
+                [_] => construct_and_flatten_sequence(node, next, BlockType::SelfLooping),
+                _ => return Ok(None),
+            };
+            Ok(Some(reduction))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn ascend_if_chain<'a>(
+    mut rev_chain: Vec<&'a StructureBlock>,
+    cont: &'a StructureBlock,
+    graph: &DirectedGraph<StructureBlock>,
+    preds: &'a PredMap,
+) -> Result<Vec<&'a StructureBlock>, Error> {
+    let mut visited = rev_chain.iter().cloned().collect::<HashSet<_>>();
+    let Some(mut cur_head) = rev_chain.last().copied() else {
+        return Ok(rev_chain);
+    };
+    loop {
+        let head_preds = preds_of(preds, cur_head)?;
+        cur_head = match head_preds.iter().next() {
+            Some(pred) if head_preds.len() == 1 => pred,
+            _ => break,
+        };
+        if !visited.contains(cur_head) {
+            visited.insert(cur_head);
+            let head_children = graph.neighbours(cur_head);
+            if head_children.len() == 2 {
                 // one of the edges must point to the cont block.
                 // the other one obviously points to the current head
                 if &head_children[0] == cont || &head_children[1] == cont {
@@ -327,15 +1855,26 @@ fn ascend_if_chain<'a>(
             break;
         }
     }
-    rev_chain
+    Ok(rev_chain)
+}
+
+// creates the condition of an if: a single block or an IfChain block if short-circuited. The
+// conditions are given in reverse evaluation order, as returned by ascend_if_chain.
+fn construct_if_chain(rev_conds: &[&StructureBlock]) -> StructureBlock {
+    if rev_conds.len() == 1 {
+        rev_conds[0].clone()
+    } else {
+        let content = rev_conds.iter().rev().cloned().cloned().collect();
+        StructureBlock::from(Arc::new(NestedBlock::new(BlockType::IfChain, content)))
+    }
 }
 
 fn reduce_ifthen<'a>(
     node: &'a StructureBlock,
     graph: &'a DirectedGraph<StructureBlock>,
-    preds: &HashMap<&'a StructureBlock, HashSet<&'a StructureBlock>>,
-    _: &LoopHelper<'a>,
-) -> Option<Reduction<'a>> {
+    preds: &'a PredMap,
+    _: &LoopHelper,
+) -> Result<Option<Reduced<'a>>, Error> {
     let children = graph.neighbours(node);
     if children.len() == 2 {
         let head = node;
@@ -343,8 +1882,8 @@ fn reduce_ifthen<'a>(
         let mut cont_children = graph.neighbours(cont);
         let mut then = &children[1];
         let mut then_children = graph.neighbours(then);
-        let mut then_preds = preds.get(then).unwrap();
-        let mut cont_preds = preds.get(cont).unwrap();
+        let mut then_preds = preds_of(preds, then)?;
+        let mut cont_preds = preds_of(preds, cont)?;
         if cont_children.len() == 1 && &cont_children[0] == then && cont_preds.len() == 1 {
             swap(&mut cont, &mut then);
             swap(&mut cont_children, &mut then_children);
@@ -354,37 +1893,38 @@ fn reduce_ifthen<'a>(
             // we detected the innermost if-then block. Now we try to ascend the various preds
             // to see if these is a chain of if-then. In order to hold, every edge not pointing
             // to the current one should point to the exit.
-            let child_rev = ascend_if_chain(vec![then, head], cont, graph, preds);
+            let child_rev = ascend_if_chain(vec![then, head], cont, graph, preds)?;
+            // the polarity is recorded only for single conditions
+            let polarity = (child_rev.len() == 2).then(|| &children[1] == then);
             //now creates the block itself
-            let block = Arc::new(NestedBlock::new(
-                BlockType::IfThen,
-                child_rev.iter().cloned().cloned().rev().collect(),
-            ));
-            Some(Reduction {
+            let content = vec![construct_if_chain(&child_rev[1..]), then.clone()];
+            let block =
+                Arc::new(NestedBlock::new(BlockType::IfThen, content).with_polarity(polarity));
+            Ok(Some(Reduced {
                 old: child_rev.into_iter().collect(),
                 new: StructureBlock::from(block),
                 next: Some(cont),
-            })
+            }))
         } else {
-            None
+            Ok(None)
         }
     } else {
-        None
+        Ok(None)
     }
 }
 
 fn reduce_ifelse<'a>(
     node: &'a StructureBlock,
     graph: &'a DirectedGraph<StructureBlock>,
-    preds: &HashMap<&'a StructureBlock, HashSet<&'a StructureBlock>>,
-    _: &LoopHelper<'a>,
-) -> Option<Reduction<'a>> {
+    preds: &'a PredMap,
+    _: &LoopHelper,
+) -> Result<Option<Reduced<'a>>, Error> {
     let node_children = graph.neighbours(node);
     if node_children.len() == 2 {
         let mut thenb = &node_children[0];
-        let mut thenb_preds = preds.get(&thenb).unwrap();
+        let mut thenb_preds = preds_of(preds, thenb)?;
         let mut elseb = &node_children[1];
-        let mut elseb_preds = preds.get(&elseb).unwrap();
+        let mut elseb_preds = preds_of(preds, elseb)?;
         // check for swapped if-else blocks
         if thenb_preds.len() > 1 {
             if elseb_preds.len() == 1 {
@@ -393,7 +1933,7 @@ fn reduce_ifelse<'a>(
                 // ever modify this function without this line.
                 swap(&mut thenb_preds, &mut elseb_preds);
             } else {
-                return None;
+                return Ok(None);
             }
         }
         // checks that child of both then and else should go to the same node
@@ -406,42 +1946,97 @@ fn reduce_ifelse<'a>(
             // we detected the innermost if-else block. Now we try to ascend the various preds
             // to see if these is a chain of if-else. In order to hold, every edge not pointing
             // to the current one should point to the else block.
-            let child_rev = ascend_if_chain(vec![elseb, thenb, node], elseb, graph, preds);
+            let child_rev = ascend_if_chain(vec![elseb, thenb, node], elseb, graph, preds)?;
             let child_set = child_rev.iter().collect::<HashSet<_>>();
             let preds_ok = elseb_preds
                 .iter()
-                .fold(true, |acc, x| acc & child_set.contains(x));
+                .fold(true, |acc, x| acc & child_set.contains(&x));
             if preds_ok {
                 // in most cases the preds will be ok. However, to avoid wrong resolution due to
                 // visiting order, this check is inserted (mostly to avoid resolving a "proper
                 // interval" to a "if-then-else")
-                let block = Arc::new(NestedBlock::new(
-                    BlockType::IfThenElse,
-                    child_rev.iter().cloned().cloned().rev().collect(),
-                ));
-                Some(Reduction {
+                let polarity = (child_rev.len() == 3).then(|| &node_children[1] == thenb);
+                let content = vec![
+                    construct_if_chain(&child_rev[2..]),
+                    thenb.clone(),
+                    elseb.clone(),
+                ];
+                let block = Arc::new(
+                    NestedBlock::new(BlockType::IfThenElse, content).with_polarity(polarity),
+                );
+                Ok(Some(Reduced {
                     old: child_rev.into_iter().collect(),
                     new: StructureBlock::from(block),
                     next: Some(&elseb_children[0]),
-                })
+                }))
             } else {
-                None
+                Ok(None)
             }
         } else {
-            None
+            Ok(None)
         }
     } else {
-        None
+        Ok(None)
+    }
+}
+
+fn reduce_guard<'a>(
+    node: &'a StructureBlock,
+    graph: &'a DirectedGraph<StructureBlock>,
+    preds: &'a PredMap,
+    loop_helper: &LoopHelper,
+) -> Result<Option<Reduced<'a>>, Error> {
+    // conditions for a guard:
+    // - current node is not part of a loop (the exit would be the loop condition)
+    // - current node has two successors
+    // - one of them leaves the function (no successors other than the sink) and has only the
+    //   current node as predecessor
+    // - if both successors leave the function, the exit must be a basic block and the other one
+    //   an already reduced structure (otherwise this is a plain if-then-else)
+    let returns = |bb: &StructureBlock| match graph.neighbours(bb) {
+        [] => true,
+        [StructureBlock::Basic(sink)] => sink.is_sink(),
+        _ => false,
+    };
+    let children = graph.neighbours(node);
+    if children.len() == 2 && !loop_helper.is_loop(node)? {
+        let mut candidates = Vec::new();
+        for child in children.iter().filter(|&child| child != node) {
+            if returns(child) && preds_of(preds, child)?.len() == 1 {
+                candidates.push(child);
+            }
+        }
+        let exit = match candidates.as_slice() {
+            [exit] => *exit,
+            [a, b] => match (a, b) {
+                (StructureBlock::Basic(_), StructureBlock::Nested(_)) => *a,
+                (StructureBlock::Nested(_), StructureBlock::Basic(_)) => *b,
+                _ => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+        let next = children.iter().find(|&child| child != exit).unwrap();
+        let block = Arc::new(NestedBlock::new(
+            BlockType::Guard,
+            vec![node.clone(), exit.clone()],
+        ));
+        Ok(Some(Reduced {
+            old: hashset![node, exit],
+            new: StructureBlock::from(block),
+            next: Some(next),
+        }))
+    } else {
+        Ok(None)
     }
 }
 
 fn reduce_loop<'a>(
     node: &'a StructureBlock,
     graph: &'a DirectedGraph<StructureBlock>,
-    preds: &HashMap<&'a StructureBlock, HashSet<&'a StructureBlock>>,
-    lh: &LoopHelper<'a>,
-) -> Option<Reduction<'a>> {
-    if *lh.loops.get(&node).unwrap() && preds.get(&node).unwrap().len() > 1 {
+    preds: &'a PredMap,
+    lh: &LoopHelper,
+) -> Result<Option<Reduced<'a>>, Error> {
+    if lh.is_loop(node)? && preds_of(preds, node)?.len() > 1 {
         let head_children = graph.neighbours(node);
         if head_children.len() == 2 {
             // while loop
@@ -453,12 +2048,69 @@ fn reduce_loop<'a>(
             let tail = &head_children[0];
             let tail_children = graph.neighbours(tail);
             find_dowhile(node, tail, tail_children, preds, lh, graph)
+        } else if head_children.len() > 2 {
+            find_switch_loop(node, head_children, preds, lh, graph)
         } else {
-            None
+            Ok(None)
         }
     } else {
-        None
+        Ok(None)
+    }
+}
+
+// loop whose head is also the head of a switch: every case returns to the head, either directly
+// or through a single block joining the cases, and the head is the only block exiting the loop.
+fn find_switch_loop<'a>(
+    node: &'a StructureBlock,
+    head_children: &'a [StructureBlock],
+    preds: &'a PredMap,
+    lh: &LoopHelper,
+    graph: &'a DirectedGraph<StructureBlock>,
+) -> Result<Option<Reduced<'a>>, Error> {
+    let node_scc = lh.sccs.get(node);
+    let (cases, exits): (Vec<_>, Vec<_>) = head_children
+        .iter()
+        .partition(|child| lh.sccs.get(child) == node_scc);
+    let cases = cases.into_iter().collect::<HashSet<_>>();
+    if exits.len() != 1 || cases.len() + 1 != head_children.len() || cases.contains(node) {
+        return Ok(None);
+    }
+    let mut join = None;
+    for case in &cases {
+        let case_children = graph.neighbours(case);
+        if preds_of(preds, case)?.len() != 1 || case_children.len() != 1 {
+            return Ok(None);
+        }
+        let target = &case_children[0];
+        if target != node {
+            if join.is_some_and(|join| join != target) {
+                return Ok(None);
+            }
+            join = Some(target);
+        }
     }
+    let mut components = cases.iter().copied().chain([node]).collect::<HashSet<_>>();
+    let switch = construct_switch(node, &components);
+    let body = match join {
+        Some(join) => {
+            let join_ok = preds_of(preds, join)?.iter().all(|p| cases.contains(p))
+                && graph.neighbours(join) == [node.clone()]
+                && tail_preds_ok(join, preds, lh)?;
+            if !join_ok {
+                return Ok(None);
+            }
+            components.insert(join);
+            let content = vec![switch, join.clone()];
+            StructureBlock::from(Arc::new(NestedBlock::new(BlockType::Sequence, content)))
+        }
+        None => switch,
+    };
+    let block = Arc::new(NestedBlock::new(BlockType::SwitchLoop, vec![body]));
+    Ok(Some(Reduced {
+        old: components,
+        new: StructureBlock::from(block),
+        next: Some(exits[0]),
+    }))
 }
 
 // in a loop tail should NOT have predecessors coming from OUTSIDE the loop
@@ -466,53 +2118,147 @@ fn reduce_loop<'a>(
 // a counter-example)
 fn tail_preds_ok(
     tail: &StructureBlock,
-    preds: &HashMap<&StructureBlock, HashSet<&StructureBlock>>,
+    preds: &PredMap,
     loop_helper: &LoopHelper,
-) -> bool {
-    !preds
-        .get(tail)
-        .unwrap()
-        .iter()
-        .any(|pred| loop_helper.sccs.get(pred).unwrap() != loop_helper.sccs.get(tail).unwrap())
+) -> Result<bool, Error> {
+    let tail_scc = loop_helper.scc(tail)?;
+    for pred in preds_of(preds, tail)? {
+        if loop_helper.scc(pred)? != tail_scc {
+            return Ok(false);
+        }
+    }
+    Ok(true)
 }
 
 fn find_while<'a>(
     node: &'a StructureBlock,
     next: &'a StructureBlock,
     tail: &'a StructureBlock,
-    preds: &HashMap<&'a StructureBlock, HashSet<&'a StructureBlock>>,
-    lh: &LoopHelper<'a>,
+    preds: &'a PredMap,
+    lh: &LoopHelper,
     graph: &'a DirectedGraph<StructureBlock>,
-) -> Option<Reduction<'a>> {
+) -> Result<Option<Reduced<'a>>, Error> {
     let mut next = next;
     let mut tail = tail;
-    if graph.neighbours(next).contains(node) {
+    let node_scc = lh.sccs.get(node);
+    if graph.neighbours(next).contains(node)
+        || (lh.sccs.get(next) == node_scc && lh.sccs.get(tail) != node_scc)
+    {
         swap(&mut next, &mut tail);
     }
+    // short-circuit condition (e.g. `while(a && b)`): every block between the head and the tail
+    // has a single predecessor and exits to the same node of the head.
+    let mut conds = vec![node];
+    while let Some(other) = short_circuit_next(tail, next, preds, graph)? {
+        if conds.contains(&tail) || other == node || !tail_preds_ok(tail, preds, lh)? {
+            return Ok(None);
+        }
+        conds.push(tail);
+        tail = other;
+    }
     let tail_children = graph.neighbours(tail);
-    if tail_children.len() == 1 && &tail_children[0] == node && tail_preds_ok(tail, preds, lh) {
-        let block = Arc::new(NestedBlock::new(
-            BlockType::While,
-            vec![node.clone(), tail.clone()],
-        ));
-        Some(Reduction {
-            old: hashset![node, tail],
+    if tail_children.len() == 1 && &tail_children[0] == node && tail_preds_ok(tail, preds, lh)? {
+        let old = conds.iter().copied().chain([tail]).collect();
+        let polarity = (conds.len() == 1).then(|| &graph.neighbours(node)[1] == tail);
+        let block = Arc::new(
+            NestedBlock::new(
+                BlockType::While,
+                vec![construct_condition(conds), tail.clone()],
+            )
+            .with_polarity(polarity),
+        );
+        Ok(Some(Reduced {
+            old,
             new: StructureBlock::from(block),
             next: Some(next),
-        })
+        }))
     } else {
-        None
+        Ok(None)
+    }
+}
+
+// if `cond` is part of a short-circuit condition exiting to `exit`, returns its other child.
+fn short_circuit_next<'a>(
+    cond: &'a StructureBlock,
+    exit: &'a StructureBlock,
+    preds: &'a PredMap,
+    graph: &'a DirectedGraph<StructureBlock>,
+) -> Result<Option<&'a StructureBlock>, Error> {
+    let children = graph.neighbours(cond);
+    if children.len() == 2 && children.contains(exit) && preds_of(preds, cond)?.len() == 1 {
+        Ok(children.iter().find(|child| *child != exit))
+    } else {
+        Ok(None)
+    }
+}
+
+// creates the condition of a loop: a single block or a Condition block if short-circuited.
+fn construct_condition(conds: Vec<&StructureBlock>) -> StructureBlock {
+    if conds.len() == 1 {
+        conds[0].clone()
+    } else {
+        let content = conds.into_iter().cloned().collect();
+        StructureBlock::from(Arc::new(NestedBlock::new(BlockType::Condition, content)))
+    }
+}
+
+// do-while loop in the form head -> c1 -> ... -> cn -> head, where every c exits to the same node.
+fn find_dowhile_short_circuit<'a>(
+    node: &'a StructureBlock,
+    tail: &'a StructureBlock,
+    tail_children: &'a [StructureBlock],
+    preds: &'a PredMap,
+    lh: &LoopHelper,
+    graph: &'a DirectedGraph<StructureBlock>,
+) -> Result<Option<Reduced<'a>>, Error> {
+    let node_scc = lh.sccs.get(node);
+    let Some(next) = tail_children
+        .iter()
+        .find(|child| lh.sccs.get(child) != node_scc)
+    else {
+        return Ok(None);
+    };
+    let mut conds = vec![tail];
+    let mut cur = tail;
+    loop {
+        let Some(other) = short_circuit_next(cur, next, preds, graph)? else {
+            return Ok(None);
+        };
+        if !tail_preds_ok(cur, preds, lh)? {
+            return Ok(None);
+        }
+        if other == node {
+            break;
+        }
+        if conds.contains(&other) {
+            return Ok(None);
+        }
+        conds.push(other);
+        cur = other;
+    }
+    if conds.len() < 2 {
+        return Ok(None);
     }
+    let old = conds.iter().copied().chain([node]).collect();
+    let block = Arc::new(NestedBlock::new(
+        BlockType::DoWhile,
+        vec![node.clone(), construct_condition(conds)],
+    ));
+    Ok(Some(Reduced {
+        old,
+        new: StructureBlock::from(block),
+        next: Some(next),
+    }))
 }
 
 fn find_dowhile<'a>(
     node: &'a StructureBlock,
     tail: &'a StructureBlock,
     tail_children: &'a [StructureBlock],
-    preds: &HashMap<&'a StructureBlock, HashSet<&'a StructureBlock>>,
-    lh: &LoopHelper<'a>,
+    preds: &'a PredMap,
+    lh: &LoopHelper,
     graph: &'a DirectedGraph<StructureBlock>,
-) -> Option<Reduction<'a>> {
+) -> Result<Option<Reduced<'a>>, Error> {
     if tail_children.len() == 2 {
         if !tail_children.contains(node) {
             //type 3 or 4 (single node between tail and head) or no loop
@@ -529,20 +2275,20 @@ fn find_dowhile<'a>(
                 post_tail = &tail_children[1];
                 next = &tail_children[0];
             } else {
-                return None;
+                return find_dowhile_short_circuit(node, tail, tail_children, preds, lh, graph);
             }
-            if tail_preds_ok(tail, preds, lh) && tail_preds_ok(post_tail, preds, lh) {
+            if tail_preds_ok(tail, preds, lh)? && tail_preds_ok(post_tail, preds, lh)? {
                 let block = Arc::new(NestedBlock::new(
                     BlockType::DoWhile,
                     vec![node.clone(), tail.clone(), post_tail.clone()],
                 ));
-                Some(Reduction {
+                Ok(Some(Reduced {
                     old: hashset![node, tail, post_tail],
                     new: StructureBlock::from(block),
                     next: Some(next),
-                })
+                }))
             } else {
-                None
+                Ok(None)
             }
         } else {
             //type 1 or 2 (single or no node between head and tail)
@@ -550,31 +2296,31 @@ fn find_dowhile<'a>(
             if next == node {
                 next = &tail_children[1];
             }
-            if node != next && tail != next && tail_preds_ok(tail, preds, lh) {
+            if node != next && tail != next && tail_preds_ok(tail, preds, lh)? {
                 let block = Arc::new(NestedBlock::new(
                     BlockType::DoWhile,
                     vec![node.clone(), tail.clone()],
                 ));
-                Some(Reduction {
+                Ok(Some(Reduced {
                     old: hashset![node, tail],
                     new: StructureBlock::from(block),
                     next: Some(next),
-                })
+                }))
             } else {
-                None
+                Ok(None)
             }
         }
     } else {
-        None
+        Ok(None)
     }
 }
 
 fn reduce_improper_interval<'a>(
     node: &'a StructureBlock,
     graph: &'a DirectedGraph<StructureBlock>,
-    _: &HashMap<&'a StructureBlock, HashSet<&'a StructureBlock>>,
-    _: &LoopHelper<'a>,
-) -> Option<Reduction<'a>> {
+    _: &'a PredMap,
+    _: &LoopHelper,
+) -> Result<Option<Reduced<'a>>, Error> {
     let children = graph.neighbours(node);
     if children.len() == 2 {
         let left = &children[0];
@@ -596,28 +2342,28 @@ fn reduce_improper_interval<'a>(
                     BlockType::ImproperInterval,
                     vec![node.clone(), left.clone(), right.clone()],
                 ));
-                Some(Reduction {
+                Ok(Some(Reduced {
                     old: hashset![node, left, right],
                     new: StructureBlock::from(block),
                     next: next_set.into_iter().next(),
-                })
+                }))
             } else {
-                None
+                Ok(None)
             }
         } else {
-            None
+            Ok(None)
         }
     } else {
-        None
+        Ok(None)
     }
 }
 
 fn reduce_proper_interval<'a>(
     node: &'a StructureBlock,
     graph: &'a DirectedGraph<StructureBlock>,
-    preds: &HashMap<&'a StructureBlock, HashSet<&'a StructureBlock>>,
-    _: &LoopHelper<'a>,
-) -> Option<Reduction<'a>> {
+    preds: &'a PredMap,
+    _: &LoopHelper,
+) -> Result<Option<Reduced<'a>>, Error> {
     let children = graph.neighbours(node);
     if children.len() == 2 {
         let mut content = hashset![node, &children[0], &children[1]];
@@ -642,7 +2388,7 @@ fn reduce_proper_interval<'a>(
                 cross_exists = true;
             }
             if next_left.is_empty() || next_right.is_empty() {
-                return None;
+                return Ok(None);
             }
             let total_next = next_left.len() + next_right.len();
             let children_union = next_left
@@ -651,7 +2397,7 @@ fn reduce_proper_interval<'a>(
                 .collect::<HashSet<_>>();
             // if there is a backedge return immediately
             if children_union.intersection(&content).next().is_some() {
-                return None;
+                return Ok(None);
             }
             // if the union of the children is exactly 1, that's the exit point
             match children_union.len() {
@@ -660,7 +2406,7 @@ fn reduce_proper_interval<'a>(
                     break;
                 }
                 2 => {}
-                _ => return None,
+                _ => return Ok(None),
             }
             // else, continue iterating
             match total_next {
@@ -689,44 +2435,48 @@ fn reduce_proper_interval<'a>(
                         swap(&mut left, &mut right)
                     }
                 }
-                _ => return None,
+                _ => return Ok(None),
             }
             content.insert(left);
             content.insert(right);
             // check preds, everything should come from nodes either in left or right path
-            let preds_not_ok = preds
-                .get(left)
-                .unwrap()
+            let preds_not_ok = preds_of(preds, left)?
                 .iter()
-                .chain(preds.get(right).unwrap().iter())
-                .any(|&x| !content.contains(x));
+                .chain(preds_of(preds, right)?)
+                .any(|x| !content.contains(x));
             if preds_not_ok {
-                return None;
+                return Ok(None);
             }
         }
         if cross_exists && next.is_some() {
             // cross exits checks avoid incorrectly resolving a if-else as proper interval
+            // head first, then the other nodes ordered by offset.
+            let mut ordered = content.iter().copied().collect::<Vec<_>>();
+            ordered.sort_unstable_by_key(|x| (*x != node, x.offset()));
             let block = Arc::new(NestedBlock::new(
                 BlockType::ProperInterval,
-                content.iter().copied().cloned().collect(),
+                ordered.into_iter().cloned().collect(),
             ));
-            Some(Reduction {
+            Ok(Some(Reduced {
                 old: content,
                 new: StructureBlock::from(block),
                 next,
-            })
+            }))
         } else {
-            None
+            Ok(None)
         }
     } else {
-        None
+        Ok(None)
     }
 }
 
+// creates a block of the given type containing the two nodes, merging the content of the nodes
+// that are already sequences.
 fn construct_and_flatten_sequence<'a>(
     node: &'a StructureBlock,
     next: &'a StructureBlock,
-) -> Reduction<'a> {
+    block_type: BlockType,
+) -> Reduced<'a> {
     let flatten = |node: &'a StructureBlock| match node {
         StructureBlock::Basic(_) => {
             vec![node]
@@ -739,10 +2489,10 @@ fn construct_and_flatten_sequence<'a>(
             }
         }
     };
-    let mut reduction = Reduction {
-        old: flatten(node).into_iter().chain(flatten(next)).collect(),
+    Reduced {
+        old: hashset![node, next],
         new: StructureBlock::from(Arc::new(NestedBlock::new(
-            BlockType::Sequence,
+            block_type,
             flatten(node)
                 .into_iter()
                 .chain(flatten(next))
@@ -750,150 +2500,930 @@ fn construct_and_flatten_sequence<'a>(
                 .collect(),
         ))),
         next: None,
-    };
-    if node.block_type() == BlockType::Sequence {
-        reduction.old.insert(node);
     }
-    if next.block_type() == BlockType::Sequence {
-        reduction.old.insert(next);
+}
+
+// converts the result of a custom reduction, checking that every replaced node is in the graph.
+fn into_reduced(
+    new: StructureBlock,
+    next: Option<StructureBlock>,
+    graph: &DirectedGraph<StructureBlock>,
+) -> Option<Reduced<'_>> {
+    let node_in_graph = |node: &StructureBlock| graph.adjacency.get_key_value(node).map(|(k, _)| k);
+    let old = new
+        .children()
+        .iter()
+        .map(node_in_graph)
+        .collect::<Option<HashSet<_>>>()?;
+    let next = match next {
+        Some(next) => Some(node_in_graph(&next)?),
+        None => None,
+    };
+    if old.is_empty() {
+        None
+    } else {
+        Some(Reduced { old, new, next })
     }
-    reduction
 }
 
+// replaces the nodes consumed by a reduction with the new block, updating the predecessors and the
+// loop information without recomputing them from scratch whenever possible.
 fn remap_nodes(
-    reduction: Reduction,
-    graph: &DirectedGraph<StructureBlock>,
-) -> DirectedGraph<StructureBlock> {
-    if !graph.is_empty() {
-        let mut new_adjacency = HashMap::new();
-        for (node, children) in graph.adjacency.iter() {
-            if !reduction.old.contains(&node) {
-                let children_replaced = children
+    old: &HashSet<StructureBlock>,
+    new: StructureBlock,
+    next: Option<StructureBlock>,
+    graph: &mut DirectedGraph<StructureBlock>,
+    preds: &mut PredMap,
+    loop_helper: &mut LoopHelper,
+) -> Result<(), Error> {
+    // a successor among the replaced nodes would leave an edge to a node no longer in the graph,
+    // and a new block already in the graph would merge two unrelated nodes.
+    if let Some(next) = next.as_ref().filter(|next| old.contains(*next)) {
+        let msg = format!(
+            "block {} is both replaced and the successor of {}",
+            next, new
+        );
+        return Err(Error::Structuring(msg));
+    }
+    if !old.contains(&new) && graph.adjacency.contains_key(&new) {
+        let msg = format!(
+            "block {} replaces other nodes but is already in the graph",
+            new
+        );
+        return Err(Error::Structuring(msg));
+    }
+    // edges leaving the reduced region to a node other than the successor are discarded: in this
+    // case some nodes may become unreachable and the loops may change, so everything is recomputed.
+    let discarded = old.iter().any(|node| {
+        graph
+            .neighbours(node)
+            .iter()
+            .any(|child| !old.contains(child) && Some(child) != next.as_ref())
+    });
+    let entries = old
+        .iter()
+        .flat_map(|node| preds.get(node).into_iter().flatten())
+        .filter(|pred| !old.contains(*pred))
+        .cloned()
+        .collect::<HashSet<_>>();
+    let scc = if discarded {
+        None
+    } else {
+        loop_helper.contracted_scc(old, next.as_ref(), &entries, graph)
+    };
+    for pred in &entries {
+        if let Some(children) = graph.adjacency.get_mut(pred) {
+            for child in children.iter_mut() {
+                if old.contains(child) {
+                    *child = new.clone();
+                }
+            }
+        }
+    }
+    for node in old {
+        for child in graph.adjacency.remove(node).unwrap_or_default() {
+            if let Some(child_preds) = preds.get_mut(&child) {
+                child_preds.remove(node);
+            }
+        }
+        preds.remove(node);
+    }
+    let replacement = match &next {
+        None => vec![],
+        Some(next) => {
+            preds.entry(next.clone()).or_default().insert(new.clone());
+            vec![next.clone()]
+        }
+    };
+    graph.adjacency.insert(new.clone(), replacement);
+    preds.insert(new.clone(), entries);
+    if graph.root.as_ref().is_some_and(|root| old.contains(root)) {
+        graph.root = Some(new.clone());
+    }
+    match scc {
+        Some(scc_id) => loop_helper.replace(old, new, scc_id),
+        None => {
+            // throw away unreachable nodes, as they would not be in the predecessors map
+            let visit = graph.bfs().cloned().collect::<HashSet<_>>();
+            graph.adjacency.retain(|node, _| visit.contains(node));
+            *preds = predecessors(graph);
+            *loop_helper = LoopHelper::new(graph);
+        }
+    }
+    Ok(())
+}
+
+// owned version of the graph predecessors, so they can be updated while the graph changes.
+fn predecessors(graph: &DirectedGraph<StructureBlock>) -> PredMap {
+    graph
+        .predecessors()
+        .into_iter()
+        .map(|(node, node_preds)| (node.clone(), node_preds.into_iter().cloned().collect()))
+        .collect()
+}
+
+struct LoopHelper {
+    loops: HashMap<StructureBlock, bool>,
+    sccs: HashMap<StructureBlock, usize>,
+    // amount of nodes in each scc.
+    sizes: HashMap<usize, usize>,
+    next_id: usize,
+}
+
+impl LoopHelper {
+    fn new(graph: &DirectedGraph<StructureBlock>) -> LoopHelper {
+        let sccs = graph.scc();
+        let loops = is_loop(&sccs)
+            .into_iter()
+            .map(|(node, is_loop)| (node.clone(), is_loop))
+            .collect();
+        let mut sizes = HashMap::new();
+        for scc_id in sccs.values() {
+            *sizes.entry(*scc_id).or_insert(0) += 1;
+        }
+        let next_id = sizes.keys().max().map_or(0, |max| max + 1);
+        let sccs = sccs
+            .into_iter()
+            .map(|(node, scc_id)| (node.clone(), scc_id))
+            .collect();
+        LoopHelper {
+            loops,
+            sccs,
+            sizes,
+            next_id,
+        }
+    }
+
+    // returns true if the node of the graph being reduced is part of a loop.
+    fn is_loop(&self, node: &StructureBlock) -> Result<bool, Error> {
+        self.loops
+            .get(node)
+            .copied()
+            .ok_or_else(|| missing_node(node))
+    }
+
+    // returns the scc of the node of the graph being reduced.
+    fn scc(&self, node: &StructureBlock) -> Result<usize, Error> {
+        self.sccs
+            .get(node)
+            .copied()
+            .ok_or_else(|| missing_node(node))
+    }
+
+    // returns the scc of the node replacing the `old` ones, if it can be inferred without
+    // recomputing every scc. This happens if the old nodes belong to the same scc, or if they
+    // form an acyclic region with a single entry that reaches the successor.
+    fn contracted_scc(
+        &mut self,
+        old: &HashSet<StructureBlock>,
+        next: Option<&StructureBlock>,
+        entries: &HashSet<StructureBlock>,
+        graph: &DirectedGraph<StructureBlock>,
+    ) -> Option<usize> {
+        let ids = old
+            .iter()
+            .map(|node| self.sccs.get(node).copied())
+            .collect::<Option<HashSet<_>>>()?;
+        if ids.len() == 1 {
+            return ids.into_iter().next();
+        }
+        if ids.iter().any(|id| self.sizes.get(id) != Some(&1)) {
+            return None;
+        }
+        // the new node can not be part of a cycle if every path coming from the outside passes
+        // through the same entry and the entry reaches the successor of the region.
+        let heads = old
+            .iter()
+            .filter(|node| {
+                entries
                     .iter()
-                    .map(|child| {
-                        if !reduction.old.contains(&child) {
-                            child.clone()
-                        } else {
-                            reduction.new.clone()
+                    .any(|pred| graph.neighbours(pred).contains(*node))
+            })
+            .collect::<Vec<_>>();
+        let acyclic = match (heads.as_slice(), next) {
+            (_, None) => true,
+            ([], Some(_)) => true,
+            ([head], Some(next)) => {
+                let mut stack = vec![*head];
+                let mut visited = HashSet::new();
+                let mut found = false;
+                while let Some(node) = stack.pop() {
+                    if visited.insert(node) {
+                        for child in graph.neighbours(node) {
+                            if child == next {
+                                found = true;
+                            } else if old.contains(child) {
+                                stack.push(child);
+                            }
                         }
-                    })
-                    .collect();
-                new_adjacency.insert(node.clone(), children_replaced);
+                    }
+                }
+                found
+            }
+            _ => false,
+        };
+        if acyclic {
+            let id = self.next_id;
+            self.next_id += 1;
+            self.sizes.insert(id, 0);
+            Some(id)
+        } else {
+            None
+        }
+    }
+
+    // replaces the `old` nodes with the `new` one, belonging to the given scc.
+    fn replace(&mut self, old: &HashSet<StructureBlock>, new: StructureBlock, scc_id: usize) {
+        for node in old {
+            self.loops.remove(node);
+            if let Some(id) = self.sccs.remove(node) {
+                *self.sizes.get_mut(&id).unwrap() -= 1;
+            }
+        }
+        let size = self.sizes.entry(scc_id).or_insert(0);
+        *size += 1;
+        let is_loop = *size > 1;
+        self.sccs.insert(new.clone(), scc_id);
+        self.loops.insert(new, is_loop);
+    }
+}
+
+// nodes that may be reduced, visited following the depth-first postorder of the graph.
+struct Worklist {
+    // position of each node in the postorder. New nodes take the position of the last node they
+    // replace.
+    positions: HashMap<StructureBlock, usize>,
+    pending: BTreeMap<usize, StructureBlock>,
+}
+
+impl Worklist {
+    // creates a worklist containing every node of the graph.
+    fn new(graph: &DirectedGraph<StructureBlock>) -> Worklist {
+        let positions = graph
+            .dfs_postorder()
+            .cloned()
+            .enumerate()
+            .map(|(pos, node)| (node, pos))
+            .collect::<HashMap<_, _>>();
+        let pending = positions
+            .iter()
+            .map(|(node, pos)| (*pos, node.clone()))
+            .collect();
+        Worklist { positions, pending }
+    }
+
+    fn pop(&mut self) -> Option<StructureBlock> {
+        let pos = *self.pending.keys().next()?;
+        self.pending.remove(&pos)
+    }
+
+    // replaces the old nodes with the new one, and schedules the new node with its neighbours.
+    fn update(
+        &mut self,
+        old: &HashSet<StructureBlock>,
+        new: &StructureBlock,
+        graph: &DirectedGraph<StructureBlock>,
+        preds: &PredMap,
+    ) {
+        let positions = old
+            .iter()
+            .filter_map(|node| self.positions.remove(node))
+            .collect::<Vec<_>>();
+        for pos in &positions {
+            self.pending.remove(pos);
+        }
+        if let Some(pos) = positions.into_iter().max() {
+            self.positions.insert(new.clone(), pos);
+        }
+        let neighbours = graph
+            .neighbours(new)
+            .iter()
+            .chain(preds.get(new).into_iter().flatten());
+        for node in std::iter::once(new).chain(neighbours) {
+            if let Some(pos) = self.positions.get(node) {
+                self.pending.insert(*pos, node.clone());
+            }
+        }
+    }
+}
+
+struct BuildOutput {
+    tree: DirectedGraph<StructureBlock>,
+    partial: Option<DirectedGraph<StructureBlock>>,
+    duplicated: usize,
+    removed: Vec<(BasicBlock, BasicBlock)>,
+    trace: Vec<TraceEntry>,
+    snapshots: Vec<DirectedGraph<StructureBlock>>,
+    stats: CfsStats,
+    timings: Option<Timings>,
+    // true if the reduction stopped because of the options
+    exhausted: bool,
+}
+
+fn build_cfs(
+    cfg: &CFG,
+    enabled: &[ReductionKind],
+    custom: &[Arc<dyn Reduction>],
+    options: &Options,
+) -> Result<BuildOutput, Error> {
+    let (cfg, abnormal) = if options.annotate_entries {
+        let (cfg, abnormal) = remove_abnormal_entries(cfg);
+        (Cow::Owned(cfg), abnormal)
+    } else {
+        (Cow::Borrowed(cfg), Vec::new())
+    };
+    let cfg = cfg.as_ref();
+    // time spent in the denaturation, and in each custom and enabled reduction
+    let mut timer = options.timings.then(|| Timer {
+        denaturation: Duration::ZERO,
+        custom: vec![Duration::ZERO; custom.len()],
+        enabled: vec![Duration::ZERO; enabled.len()],
+        duplication: Duration::ZERO,
+    });
+    // a CFG exceeding the size budget is left untouched, without paying for the denaturation
+    let mut exhausted = !options.allows(0, cfg.len());
+    let (nonat_cfg, removed) = if exhausted {
+        (Cow::Borrowed(cfg), Vec::new())
+    } else {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!("denaturation").entered();
+        let start = timer.as_ref().map(|_| Instant::now());
+        let (nonat_cfg, removed) = remove_natural_loops(&cfg.scc(), &cfg.predecessors(), cfg)?;
+        if let (Some(timer), Some(start)) = (&mut timer, start) {
+            timer.denaturation = start.elapsed();
+        }
+        #[cfg(feature = "tracing")]
+        tracing::trace!(removed = removed.len(), "loops denaturated");
+        (nonat_cfg, removed)
+    };
+    let mut current_tolerance = 0;
+    let mut graph = deep_copy(&nonat_cfg);
+    let mut preds = predecessors(&graph);
+    let mut loop_helper = LoopHelper::new(&graph);
+    let mut prev_len = graph.len();
+    let mut duplicated = 0;
+    let mut trace = Vec::new();
+    let mut snapshots = Vec::new();
+    let mut snapshot = |graph: &DirectedGraph<StructureBlock>| {
+        if options.snapshots {
+            snapshots.push(graph.clone());
+        }
+    };
+    let mut iterations = 0;
+    // after every reduction only the neighbourhood of the new node is revisited, and the whole
+    // graph is scanned again only when nothing else can be reduced.
+    let mut worklist = Worklist::new(&graph);
+    let mut full_scan = true;
+    // graph where the reduction stalled for the first time, before wrapping any region
+    let mut stalled = None;
+    snapshot(&graph);
+    while graph.len() > 1 && current_tolerance < BUILD_TOLERANCE && !exhausted {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!("reduction", iteration = iterations, nodes = graph.len())
+            .entered();
+        if !options.allows(iterations + 1, cfg.len()) {
+            exhausted = true;
+            break;
+        }
+        let node = match worklist.pop() {
+            Some(node) => node,
+            None if !full_scan => {
+                worklist = Worklist::new(&graph);
+                full_scan = true;
+                continue;
+            }
+            None => {
+                // no node can be reduced: try to make an irreducible region reducible
+                let split = if duplicated < MAX_DUPLICATIONS && !options.refine_conditions {
+                    let start = timer.as_ref().map(|_| Instant::now());
+                    let split = split_irreducible(&graph, &preds, &loop_helper);
+                    if let (Some(timer), Some(start)) = (&mut timer, start) {
+                        timer.duplication += start.elapsed();
+                    }
+                    split
+                } else {
+                    None
+                };
+                match split {
+                    Some((split, copy)) => {
+                        let pattern = copy.get_type_name().to_string();
+                        trace.push(TraceEntry::new(pattern, copy.children().to_vec(), copy));
+                        graph = split;
+                        preds = predecessors(&graph);
+                        loop_helper = LoopHelper::new(&graph);
+                        worklist = Worklist::new(&graph);
+                        duplicated += 1;
+                        iterations += 1;
+                        snapshot(&graph);
+                        continue;
+                    }
+                    None => {
+                        // wrap only the smallest region that can not be reduced
+                        let Some((old, new, next)) = minimal_region(&graph) else {
+                            break;
+                        };
+                        stalled.get_or_insert_with(|| graph.clone());
+                        let pattern = new.get_type_name().to_string();
+                        trace.push(TraceEntry::new(
+                            pattern,
+                            new.children().to_vec(),
+                            new.clone(),
+                        ));
+                        remap_nodes(
+                            &old,
+                            new.clone(),
+                            next,
+                            &mut graph,
+                            &mut preds,
+                            &mut loop_helper,
+                        )?;
+                        worklist.update(&old, &new, &graph, &preds);
+                        full_scan = false;
+                        iterations += 1;
+                        snapshot(&graph);
+                        continue;
+                    }
+                }
+            }
+        };
+        if !graph.adjacency.contains_key(&node) {
+            // thrown away as unreachable after a previous reduction
+            continue;
+        }
+        let mut reduced = None;
+        for (index, reduction) in custom.iter().enumerate() {
+            let start = timer.as_ref().map(|_| Instant::now());
+            reduced = reduction
+                .reduce(&node, &graph, &preds, &loop_helper.loops)
+                .and_then(|(new, next)| into_reduced(new, next, &graph))
+                .map(|r| (reduction.name().to_string(), r));
+            if let (Some(timer), Some(start)) = (&mut timer, start) {
+                timer.custom[index] += start.elapsed();
+            }
+            if reduced.is_some() {
+                break;
+            }
+        }
+        if reduced.is_none() {
+            for (index, kind) in enabled.iter().enumerate() {
+                let start = timer.as_ref().map(|_| Instant::now());
+                reduced = (kind.function())(&node, &graph, &preds, &loop_helper)?
+                    .map(|r| (kind.to_string(), r));
+                if let (Some(timer), Some(start)) = (&mut timer, start) {
+                    timer.enabled[index] += start.elapsed();
+                }
+                if reduced.is_some() {
+                    break;
+                }
+            }
+        }
+        if let Some((pattern, reduction)) = reduced {
+            let old = reduction.old.into_iter().cloned().collect::<HashSet<_>>();
+            let consumed = old.iter().cloned().collect();
+            trace.push(TraceEntry::new(pattern, consumed, reduction.new.clone()));
+            let new = reduction.new;
+            let next = reduction.next.cloned();
+            remap_nodes(
+                &old,
+                new.clone(),
+                next,
+                &mut graph,
+                &mut preds,
+                &mut loop_helper,
+            )?;
+            worklist.update(&old, &new, &graph, &preds);
+            full_scan = false;
+            iterations += 1;
+            snapshot(&graph);
+            if graph.len() < prev_len {
+                current_tolerance = 0;
+                prev_len = graph.len();
+            } else {
+                current_tolerance += 1;
             }
         }
-        let replacement = match reduction.next {
-            None => vec![],
-            Some(next_unwrapped) => vec![next_unwrapped.clone()],
+    }
+    // throw away unreachable nodes
+    let visit = graph.bfs().cloned().collect::<HashSet<_>>();
+    graph.adjacency = graph
+        .adjacency
+        .into_iter()
+        .filter(|(node, _)| visit.contains(node))
+        .collect();
+    let residual = if graph.len() > 1 {
+        let region = reduce_improper_region(&graph);
+        let root = region
+            .root
+            .clone()
+            .ok_or_else(|| Error::Structuring("the residual region has no root".to_string()))?;
+        let pattern = root.get_type_name().to_string();
+        trace.push(TraceEntry::new(pattern, root.children().to_vec(), root));
+        let residual = replace(&mut graph, region);
+        snapshot(&graph);
+        Some(residual)
+    } else {
+        None
+    };
+    let partial = stalled.or(residual).map(|partial| {
+        // the loops reduced so far are annotated as they would be in the complete tree
+        let annotate = |node: &StructureBlock| {
+            let node = annotate_loops(node, cfg, &removed, options.preserve_loops);
+            annotate_entries(&node, &abnormal)
+        };
+        let annotated = partial
+            .adjacency
+            .keys()
+            .map(|node| (node.clone(), annotate(node)))
+            .collect::<HashMap<_, _>>();
+        let lookup = |node: &StructureBlock| annotated.get(node).unwrap_or(node).clone();
+        let adjacency = partial
+            .adjacency
+            .iter()
+            .map(|(node, children)| (lookup(node), children.iter().map(lookup).collect()))
+            .collect();
+        let root = partial.root.as_ref().map(lookup);
+        DirectedGraph { root, adjacency }
+    });
+    if graph.len() == 1 {
+        let old_root = graph
+            .root
+            .take()
+            .ok_or_else(|| Error::Structuring("the reduced graph has no root".to_string()))?;
+        let new_root = annotate_loops(&old_root, cfg, &removed, options.preserve_loops);
+        let new_root = annotate_entries(&new_root, &abnormal);
+        let new_root = if options.refine_conditions {
+            let mut refiner = ConditionRefiner {
+                cfg: &nonat_cfg,
+                refined: Vec::new(),
+            };
+            let refined = new_root.rewrite(&mut refiner);
+            for (old, new) in refiner.refined {
+                let pattern = BlockType::Conditional.to_string();
+                trace.push(TraceEntry::new(pattern, vec![old], new));
+            }
+            refined
+        } else {
+            new_root
+        };
+        let new_root = if cfg.info.values().all(|info| info.terminator.is_none()) {
+            new_root
+        } else {
+            new_root.rewrite(&mut TerminatorAnnotator { info: &cfg.info })
         };
-        new_adjacency.insert(reduction.new.clone(), replacement);
+        let children = graph
+            .adjacency
+            .into_values()
+            .flatten()
+            .map(|child| {
+                if child == old_root {
+                    new_root.clone()
+                } else {
+                    child
+                }
+            })
+            .collect();
+        graph.adjacency = HashMap::from([(new_root.clone(), children)]);
+        graph.root = Some(new_root);
+    }
+    let mut removed = removed;
+    if options.preserve_loops {
+        // the edges recorded by the loop markers are still part of the structure
+        let preserved = graph
+            .adjacency
+            .keys()
+            .flat_map(|node| node.iter_preorder())
+            .filter(|node| {
+                matches!(
+                    node.block_type(),
+                    BlockType::Break | BlockType::Continue | BlockType::Goto
+                )
+            })
+            .flat_map(|node| node.exits().iter().copied())
+            .collect::<HashSet<_>>();
+        removed.retain(|edge| !preserved.contains(edge));
+    }
+    removed.extend(abnormal);
+    removed.sort_unstable();
+    let stats = CfsStats::new(&graph, iterations, removed.len());
+    let timings = timer.map(|timer| timer.into_timings(enabled, custom));
+    Ok(BuildOutput {
+        tree: graph,
+        partial,
+        duplicated,
+        removed,
+        trace,
+        snapshots,
+        stats,
+        timings,
+        exhausted,
+    })
+}
 
-        let new_root = if !reduction.old.contains(graph.root.as_ref().unwrap()) {
-            graph.root.clone()
-        } else {
-            Some(reduction.new)
+// time spent in each phase of build_cfs, converted into Timings once the structuring ends.
+struct Timer {
+    denaturation: Duration,
+    // indexed as the custom and enabled reductions
+    custom: Vec<Duration>,
+    enabled: Vec<Duration>,
+    duplication: Duration,
+}
+
+impl Timer {
+    fn into_timings(self, enabled: &[ReductionKind], custom: &[Arc<dyn Reduction>]) -> Timings {
+        let mut timings = Timings {
+            denaturation: self.denaturation,
+            ..Timings::default()
         };
-        DirectedGraph {
-            root: new_root,
-            adjacency: new_adjacency,
+        let custom = custom
+            .iter()
+            .map(|reduction| reduction.name().to_string())
+            .zip(self.custom);
+        let enabled = enabled
+            .iter()
+            .map(ReductionKind::to_string)
+            .zip(self.enabled);
+        let duplication = (BlockType::Duplicate.to_string(), self.duplication);
+        for (name, elapsed) in custom.chain(enabled).chain([duplication]) {
+            if !elapsed.is_zero() {
+                *timings.reductions.entry(name).or_default() += elapsed;
+            }
         }
-    } else {
-        graph.clone()
+        timings
     }
 }
 
-struct LoopHelper<'a> {
-    loops: HashMap<&'a StructureBlock, bool>,
-    sccs: HashMap<&'a StructureBlock, usize>,
-}
-
-impl<'a> LoopHelper<'a> {
-    fn new(graph: &'a DirectedGraph<StructureBlock>) -> LoopHelper<'a> {
-        let sccs = graph.scc();
-        let loops = is_loop(&sccs);
-        LoopHelper { loops, sccs }
+// Catch-all reduction: wraps every node of the graph into a single ImproperRegion. Used only when
+// the budget is exhausted or no single entry single exit region can be wrapped.
+fn reduce_improper_region(graph: &DirectedGraph<StructureBlock>) -> DirectedGraph<StructureBlock> {
+    let content = graph.dfs_preorder().cloned().collect();
+    let region = StructureBlock::from(Arc::new(NestedBlock::new(
+        BlockType::ImproperRegion,
+        content,
+    )));
+    DirectedGraph {
+        root: Some(region.clone()),
+        adjacency: HashMap::from([(region, Vec::new())]),
     }
 }
 
-fn build_cfs(cfg: &CFG) -> DirectedGraph<StructureBlock> {
-    let nonat_cfg = remove_natural_loops(&cfg.scc(), &cfg.predecessors(), cfg.clone())
-        .add_sink()
-        .add_entry_point();
-    let mut current_tolerance = 0;
-    let mut graph = deep_copy(&nonat_cfg);
-    let mut prev_len = nonat_cfg.len();
-    loop {
-        if graph.len() == 1 {
-            break;
+// Finds the smallest region, other than the whole graph, with a single entry and a single
+// successor, bounded by the entry and one of its postdominators. Used when nothing else can be
+// reduced, so only this region is wrapped into an ImproperRegion and the reduction can continue
+// around it. Returns the nodes of the region, the ImproperRegion and its successor.
+fn minimal_region(
+    graph: &DirectedGraph<StructureBlock>,
+) -> Option<(
+    HashSet<StructureBlock>,
+    StructureBlock,
+    Option<StructureBlock>,
+)> {
+    let nodes = graph.dfs_preorder().collect::<Vec<_>>();
+    let index = nodes
+        .iter()
+        .enumerate()
+        .map(|(idx, node)| (*node, idx))
+        .collect::<HashMap<_, _>>();
+    let succs = nodes
+        .iter()
+        .map(|node| {
+            graph
+                .neighbours(node)
+                .iter()
+                .filter_map(|child| index.get(child).copied())
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let mut preds = vec![Vec::new(); nodes.len()];
+    // the postdominators are the dominators of the reversed graph, starting from a virtual exit
+    // reached by every node without successors
+    let exit = nodes.len();
+    let mut reversed = vec![Vec::new(); nodes.len() + 1];
+    for (node, children) in succs.iter().enumerate() {
+        if children.is_empty() {
+            reversed[exit].push(node);
         }
-        let mut modified = false;
-        let preds = graph.predecessors();
-        let loop_helper = LoopHelper::new(&graph);
-        for node in graph.dfs_postorder() {
-            let reductions = [
-                reduce_self_loop,
-                reduce_loop,
-                reduce_ifthen,
-                reduce_ifelse,
-                reduce_sequence,
-                reduce_switch,
-                reduce_proper_interval,
-                reduce_improper_interval,
-            ];
-            let mut reduced = None;
-            for reduction in &reductions {
-                reduced = (reduction)(node, &graph, &preds, &loop_helper);
-                if reduced.is_some() {
-                    break;
+        for child in children {
+            preds[*child].push(node);
+            reversed[*child].push(node);
+        }
+    }
+    let ipdom = immediate_dominators(&reversed, exit);
+    let mut best: Option<(Vec<bool>, Option<usize>, usize)> = None;
+    let mut best_len = usize::MAX;
+    for entry in 0..nodes.len() {
+        // candidate successors, from the closest postdominator to the virtual exit
+        let mut stop = ipdom[entry];
+        loop {
+            let succ = stop.filter(|succ| *succ != exit);
+            let mut region = vec![false; nodes.len()];
+            let mut len = 0;
+            let mut stack = vec![entry];
+            while let Some(node) = stack.pop() {
+                if Some(node) != succ && !region[node] {
+                    region[node] = true;
+                    len += 1;
+                    stack.extend(succs[node].iter().copied());
                 }
             }
-            if let Some(reduction) = reduced {
-                graph = remap_nodes(reduction, &graph);
-                if graph.len() < prev_len {
-                    current_tolerance = 0;
-                    prev_len = graph.len();
-                } else {
-                    current_tolerance += 1;
-                }
-                modified = true;
+            if len >= best_len || len == nodes.len() {
                 break;
             }
+            let single_entry = (0..nodes.len())
+                .filter(|node| region[*node] && *node != entry)
+                .all(|node| preds[node].iter().all(|pred| region[*pred]));
+            let single_exit = (0..nodes.len()).filter(|node| region[*node]).all(|node| {
+                succs[node]
+                    .iter()
+                    .all(|child| region[*child] || Some(*child) == succ)
+            });
+            if len >= 2 && single_entry && single_exit {
+                best = Some((region, succ, entry));
+                best_len = len;
+                break;
+            }
+            match succ {
+                Some(succ) => stop = ipdom[succ],
+                None => break,
+            }
         }
-        if !modified || current_tolerance >= BUILD_TOLERANCE {
-            break;
+    }
+    let (region, succ, entry) = best?;
+    let content = graph
+        .dfs_preorder_from(nodes[entry])
+        .filter(|node| index.get(node).is_some_and(|idx| region[*idx]))
+        .cloned()
+        .collect::<Vec<_>>();
+    let old = content.iter().cloned().collect();
+    let new = StructureBlock::from(Arc::new(NestedBlock::new(
+        BlockType::ImproperRegion,
+        content,
+    )));
+    Some((old, new, succ.map(|succ| nodes[succ].clone())))
+}
+
+// immediate dominator of every node reachable from the root, identified by its index in `succs`,
+// following "A Simple, Fast Dominance Algorithm" by Cooper, Harvey and Kennedy. The root and the
+// unreachable nodes have no immediate dominator.
+fn immediate_dominators(succs: &[Vec<usize>], root: usize) -> Vec<Option<usize>> {
+    // reverse postorder of the reachable nodes
+    let mut order = Vec::with_capacity(succs.len());
+    let mut visited = vec![false; succs.len()];
+    visited[root] = true;
+    let mut stack = vec![(root, 0)];
+    while let Some((node, next)) = stack.pop() {
+        match succs[node].get(next) {
+            Some(child) => {
+                stack.push((node, next + 1));
+                if !visited[*child] {
+                    visited[*child] = true;
+                    stack.push((*child, 0));
+                }
+            }
+            None => order.push(node),
         }
     }
-    // throw away unreachable nodes
-    let visit = graph.bfs().cloned().collect::<HashSet<_>>();
-    graph.adjacency = graph
-        .adjacency
-        .into_iter()
-        .filter(|(node, _)| visit.contains(node))
-        .collect();
-    graph
+    order.reverse();
+    let mut position = vec![usize::MAX; succs.len()];
+    let mut preds = vec![Vec::new(); succs.len()];
+    for (pos, node) in order.iter().enumerate() {
+        position[*node] = pos;
+        for child in &succs[*node] {
+            preds[*child].push(*node);
+        }
+    }
+    let mut idom = vec![None; succs.len()];
+    idom[root] = Some(root);
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for node in order.iter().skip(1) {
+            let mut new_idom = None;
+            for pred in preds[*node].iter().filter(|pred| idom[**pred].is_some()) {
+                new_idom = match new_idom {
+                    None => Some(*pred),
+                    Some(other) => {
+                        let (mut a, mut b) = (*pred, other);
+                        while a != b {
+                            while position[a] > position[b] {
+                                a = idom[a].unwrap_or(root);
+                            }
+                            while position[b] > position[a] {
+                                b = idom[b].unwrap_or(root);
+                            }
+                        }
+                        Some(a)
+                    }
+                };
+            }
+            if new_idom != idom[*node] {
+                idom[*node] = new_idom;
+                changed = true;
+            }
+        }
+    }
+    idom[root] = None;
+    idom
+}
+
+// Finds a loop with multiple entry points and splits one of its entries: the predecessors outside
+// the loop are redirected to a copy of the entry, so the loop loses one of its entry points.
+// Returns the new graph and the copy, or None if no irreducible loop exists.
+fn split_irreducible(
+    graph: &DirectedGraph<StructureBlock>,
+    preds: &PredMap,
+    loop_helper: &LoopHelper,
+) -> Option<(DirectedGraph<StructureBlock>, StructureBlock)> {
+    let mut entries_by_scc = HashMap::<usize, Vec<&StructureBlock>>::new();
+    for (node, scc_id) in &loop_helper.sccs {
+        let is_entry = preds
+            .get(node)
+            .map(|p| {
+                p.iter()
+                    .any(|pred| loop_helper.sccs.get(pred) != Some(scc_id))
+            })
+            .unwrap_or(false);
+        if *loop_helper.loops.get(node).unwrap() && is_entry {
+            entries_by_scc.entry(*scc_id).or_default().push(node);
+        }
+    }
+    // deterministic choice: the last entry of each loop, and the first of these entries. The
+    // duplicated nodes have the offset of the original, so the ties are broken by their content.
+    let order = |a: &&StructureBlock, b: &&StructureBlock| {
+        a.offset().cmp(&b.offset()).then_with(|| a.strict_cmp(b))
+    };
+    let entry = entries_by_scc
+        .into_values()
+        .filter(|entries| entries.len() > 1)
+        .map(|entries| entries.into_iter().max_by(order).unwrap())
+        .min_by(order)?;
+    let entry_scc = loop_helper.sccs.get(entry);
+    let copy = StructureBlock::from(Arc::new(NestedBlock::duplicate(entry)));
+    let mut adjacency = HashMap::with_capacity(graph.adjacency.len() + 1);
+    for (node, children) in &graph.adjacency {
+        let children = if loop_helper.sccs.get(node) != entry_scc {
+            children
+                .iter()
+                .map(|child| {
+                    if child == entry {
+                        copy.clone()
+                    } else {
+                        child.clone()
+                    }
+                })
+                .collect()
+        } else {
+            children.clone()
+        };
+        adjacency.insert(node.clone(), children);
+    }
+    adjacency.insert(copy.clone(), graph.adjacency.get(entry).unwrap().clone());
+    let root = if graph.root.as_ref() == Some(entry) {
+        Some(copy.clone())
+    } else {
+        graph.root.clone()
+    };
+    Some((DirectedGraph { root, adjacency }, copy))
 }
 
+// copies the CFG into a graph of StructureBlock, adding the sink and the entry point like
+// CFG::add_sink() and CFG::add_entry_point() would do.
 fn deep_copy(cfg: &CFG) -> DirectedGraph<StructureBlock> {
     let mut graph = DirectedGraph::default();
     if !cfg.is_empty() {
         let root = *cfg.root.as_ref().unwrap();
-        graph.root = Some(StructureBlock::from(root));
+        let has_sink = cfg.edges.values().filter(|x| x.is_empty()).count() > 1;
+        let sink = StructureBlock::from(BasicBlock::new_sink());
         let mut stack = vec![root];
         let mut visited = HashSet::with_capacity(cfg.len());
         while let Some(node) = stack.pop() {
             if !visited.contains(&node) {
                 visited.insert(node);
-                let children = cfg
+                let mut children = cfg
                     .edges
                     .get(&node)
                     .iter()
                     .flat_map(|x| x.iter())
                     .cloned()
                     .map(StructureBlock::from)
-                    .collect();
+                    .collect::<Vec<_>>();
+                if has_sink && children.is_empty() {
+                    children.push(sink.clone());
+                    graph.adjacency.insert(sink.clone(), Vec::new());
+                }
                 stack.extend(cfg.edges.get(&node).iter().flat_map(|x| x.iter()).cloned());
                 graph.adjacency.insert(StructureBlock::from(node), children);
             }
         }
+        let root_has_preds = cfg.edges.values().flatten().any(|x| *x == root);
+        if root_has_preds {
+            let entry = StructureBlock::from(BasicBlock::new_entry_point());
+            graph
+                .adjacency
+                .insert(entry.clone(), vec![StructureBlock::from(root)]);
+            graph.root = Some(entry);
+        } else {
+            graph.root = Some(StructureBlock::from(root));
+        }
     }
     graph
 }
@@ -919,16 +3449,16 @@ fn exits_and_targets(
     node: BasicBlock,
     sccs: &HashMap<&BasicBlock, usize>,
     cfg: &CFG,
-) -> (HashSet<BasicBlock>, HashSet<BasicBlock>) {
+) -> Result<(HashSet<BasicBlock>, HashSet<BasicBlock>), Error> {
     let mut visit = vec![node];
     let mut visited = [node].into_iter().collect::<HashSet<_>>();
     let mut exits = HashSet::new();
     let mut targets = HashSet::new();
     // checks the exits from the loop
     while let Some(node) = visit.pop() {
-        let node_scc_id = *sccs.get(&node).unwrap();
+        let node_scc_id = scc_id(sccs, &node)?;
         for child in cfg.neighbours(&node) {
-            let child_scc_id = *sccs.get(child).unwrap();
+            let child_scc_id = scc_id(sccs, child)?;
             if child_scc_id != node_scc_id {
                 exits.insert(node);
                 targets.insert(*child);
@@ -940,7 +3470,15 @@ fn exits_and_targets(
             visited.insert(*child);
         }
     }
-    (exits, targets)
+    Ok((exits, targets))
+}
+
+// strongly connected component of a block, failing if the block was not visited by the SCC
+// computation.
+fn scc_id(sccs: &HashMap<&BasicBlock, usize>, node: &BasicBlock) -> Result<usize, Error> {
+    sccs.get(node)
+        .copied()
+        .ok_or_else(|| Error::MalformedCfg(format!("block {} is not reachable", node)))
 }
 
 fn is_loop<'a, T: Hash + Eq>(sccs: &HashMap<&'a T, usize>) -> HashMap<&'a T, bool> {
@@ -956,153 +3494,683 @@ fn is_loop<'a, T: Hash + Eq>(sccs: &HashMap<&'a T, usize>) -> HashMap<&'a T, boo
             retval.insert(*node, true);
         }
     }
-    retval
+    retval
+}
+
+// remove all edges from a CFG that points to a list of targets.
+// the removed edges are appended to the `removed` vector. The CFG is copied only if some edge is
+// actually removed.
+fn remove_edges(
+    input_set: HashSet<BasicBlock>,
+    targets: HashSet<BasicBlock>,
+    cfg: &mut Cow<CFG>,
+    removed: &mut Vec<(BasicBlock, BasicBlock)>,
+) {
+    for src in input_set {
+        let has_targets = cfg
+            .edges
+            .get(&src)
+            .is_some_and(|dst| dst.iter().any(|child| targets.contains(child)));
+        if has_targets {
+            let dst = cfg.to_mut().edges.get_mut(&src).unwrap();
+            let (gone, kept): (Vec<_>, Vec<_>) =
+                dst.drain(..).partition(|child| targets.contains(child));
+            removed.extend(gone.into_iter().map(|child| (src, child)));
+            *dst = kept;
+        }
+    }
+}
+
+fn denaturate_loop(
+    node: BasicBlock,
+    sccs: &HashMap<&BasicBlock, usize>,
+    loops: &HashMap<&BasicBlock, bool>,
+    preds: &HashMap<&BasicBlock, HashSet<&BasicBlock>>,
+    depth_map: &HashMap<BasicBlock, usize>,
+    cfg: &mut Cow<CFG>,
+    removed: &mut Vec<(BasicBlock, BasicBlock)>,
+) -> Result<(), Error> {
+    let distance = |x, y| {
+        if x < y {
+            y - x
+        } else {
+            x - y
+        }
+    };
+    let (exits, mut targets) = exits_and_targets(node, sccs, cfg)?;
+    let is_loop = loops.get(&node).copied().unwrap_or(false);
+    if exits.len() > 1 && is_loop {
+        // harder case, more than 2 output targets, keep the target with the highest depth
+        if targets.len() >= 2 {
+            let correct = *targets
+                .iter()
+                .reduce(|a, b| {
+                    // keep the deepest. If two or more have the same depth keep closest to me
+                    match depth_map.get(a).cmp(&depth_map.get(b)) {
+                        Ordering::Less => b,
+                        Ordering::Equal => {
+                            let distance_a = distance(node.offset, a.offset);
+                            let distance_b = distance(node.offset, b.offset);
+                            match distance_a.cmp(&distance_b) {
+                                Ordering::Less => a,
+                                Ordering::Equal => {
+                                    // head is exactly midway between the targets
+                                    // at this point idk, keep the lowest
+                                    match a.offset.cmp(&b.offset) {
+                                        Ordering::Less => a,
+                                        Ordering::Equal => {
+                                            // no way this can happen without any error in the CFG
+                                            log::error!("Two blocks with the same offset");
+                                            a
+                                        }
+                                        Ordering::Greater => b,
+                                    }
+                                }
+                                Ordering::Greater => b,
+                            }
+                        }
+                        Ordering::Greater => a,
+                    }
+                })
+                .unwrap();
+            targets.remove(&correct);
+            remove_edges(exits, targets, cfg, removed);
+        }
+        let (exits, target) = exits_and_targets(node, sccs, cfg)?;
+        let correct_exit = if let Some(head) = exits.get(&node) {
+            // keep the exit which is either: the head (while case)
+            let mut set = HashSet::new();
+            set.insert(*head);
+            set
+        } else {
+            // or farther away from the entry point (do-while case) -> highest predecessor number
+            let preds_len = |x: &BasicBlock| preds.get(x).map_or(0, |preds| preds.len());
+            let max_preds = exits.iter().map(preds_len).max().unwrap_or(0);
+            let exits_vec = exits
+                .iter()
+                .cloned()
+                .filter(|x| preds_len(x) == max_preds)
+                .collect::<Vec<_>>();
+            let exit = if exits_vec.len() == 1 {
+                exits_vec.last().cloned()
+            } else {
+                //two or more exits with same amount of predecessors to the same target
+                //keep the one with further offset (and the highest one, if equally distant)
+                exits_vec
+                    .into_iter()
+                    .max_by_key(|x| (distance(node.offset, x.offset), x.offset))
+            };
+            match exit {
+                Some(exit) => [exit].into_iter().collect(),
+                None => return Ok(()),
+            }
+        };
+        let correct_exit = extend_short_circuit(node, correct_exit, &exits, sccs, preds, cfg);
+        let wrong_exits = exits
+            .difference(&correct_exit)
+            .cloned()
+            .collect::<HashSet<_>>();
+        remove_edges(wrong_exits, target, cfg, removed);
+    }
+    // 1 exit and >1 targets can't exist in a CFG loop
+    Ok(())
+}
+
+// adds to the kept exit the other exits forming a short-circuit condition with it: a chain of
+// blocks with a single predecessor, exiting the loop to the same target. The chain must start
+// from the loop head (while loop) or end in the block jumping back to the head (do-while loop).
+fn extend_short_circuit(
+    head: BasicBlock,
+    mut correct: HashSet<BasicBlock>,
+    exits: &HashSet<BasicBlock>,
+    sccs: &HashMap<&BasicBlock, usize>,
+    preds: &HashMap<&BasicBlock, HashSet<&BasicBlock>>,
+    cfg: &CFG,
+) -> HashSet<BasicBlock> {
+    let exit = match correct.iter().next() {
+        Some(exit) => *exit,
+        None => return correct,
+    };
+    let scc = sccs.get(&exit);
+    let target = cfg.neighbours(&exit).iter().find(|x| sccs.get(x) != scc);
+    let is_chained = |bb: &BasicBlock| {
+        let children = cfg.neighbours(bb);
+        exits.contains(bb)
+            && children.len() == 2
+            && children.iter().find(|x| sccs.get(x) != scc) == target
+    };
+    let in_loop_child = |bb: &BasicBlock| {
+        cfg.neighbours(bb)
+            .iter()
+            .find(|x| sccs.get(x) == scc)
+            .copied()
+    };
+    let single_pred = |bb: &BasicBlock| match preds.get(bb) {
+        Some(preds) if preds.len() == 1 => preds.iter().next().map(|pred| **pred),
+        _ => None,
+    };
+    if exit == head {
+        // descending: from the head to its successors (while loop)
+        let mut cur = exit;
+        while let Some(next) = in_loop_child(&cur) {
+            if single_pred(&next).is_some() && is_chained(&next) && correct.insert(next) {
+                cur = next;
+            } else {
+                break;
+            }
+        }
+    } else if in_loop_child(&exit) == Some(head) {
+        // ascending: from the block jumping to the head to its predecessors (do-while loop)
+        let mut cur = exit;
+        while let Some(pred) = single_pred(&cur) {
+            if pred != head && is_chained(&pred) && correct.insert(pred) {
+                cur = pred;
+            } else {
+                break;
+            }
+        }
+    }
+    correct
+}
+
+// removes the jumps back to the header of a loop performed in the middle of its body, like
+// `if (cond) continue;`. A jump is in the middle of the body if the block performing it has
+// another successor in the loop, and the blocks following this successor join the rest of the
+// body before reaching the header. These jumps would leave the body unstructured.
+fn remove_continues(
+    head: BasicBlock,
+    sccs: &HashMap<&BasicBlock, usize>,
+    cfg: &mut Cow<CFG>,
+    removed: &mut Vec<(BasicBlock, BasicBlock)>,
+) {
+    let scc = sccs.get(&head);
+    let in_body = |bb: &BasicBlock| *bb != head && sccs.get(bb) == scc;
+    // blocks of the body reachable from the given ones without passing through `avoid`
+    let reach = |cfg: &CFG, start: Vec<BasicBlock>, avoid: BasicBlock| {
+        let mut visited = HashSet::new();
+        let mut stack = start;
+        while let Some(bb) = stack.pop() {
+            if in_body(&bb) && bb != avoid && visited.insert(bb) {
+                stack.extend(cfg.neighbours(&bb).iter().copied());
+            }
+        }
+        visited
+    };
+    let mut latches = cfg
+        .edges
+        .iter()
+        .filter(|(src, dsts)| in_body(src) && dsts.contains(&head))
+        .map(|(src, _)| *src)
+        .collect::<Vec<_>>();
+    latches.sort_unstable();
+    for latch in latches {
+        let other = match cfg.neighbours(&latch) {
+            [a, b] if *a == head && in_body(b) => *b,
+            [a, b] if *b == head && in_body(a) => *a,
+            _ => continue,
+        };
+        // the successor must not jump back to the latch, like the head of an inner loop would do
+        let from_other = reach(cfg, vec![other], head);
+        if from_other.contains(&latch) {
+            continue;
+        }
+        let from_head = reach(cfg, cfg.neighbours(&head).to_vec(), latch);
+        if !from_other.is_disjoint(&from_head) {
+            remove_edges(hashset![latch], hashset![head], cfg, removed);
+        }
+    }
+}
+
+// removes the extra exits and the jumps back to the header from the middle of the body from
+// every loop of the CFG.
+// returns the new CFG and the list of removed edges, sorted by source block.
+fn remove_natural_loops<'a>(
+    sccs: &HashMap<&BasicBlock, usize>,
+    preds: &HashMap<&BasicBlock, HashSet<&BasicBlock>>,
+    cfg: &'a CFG,
+) -> Result<(Cow<'a, CFG>, Vec<(BasicBlock, BasicBlock)>), Error> {
+    let mut cfg = Cow::Borrowed(cfg);
+    let mut loops_done = FnvHashSet::default();
+    let mut removed = Vec::new();
+    let depth_map = calculate_depth(&cfg);
+    let loops = is_loop(sccs);
+    let nodes = cfg.dfs_preorder().cloned().collect::<Vec<_>>();
+    for node in nodes {
+        let scc_id = scc_id(sccs, &node)?;
+        if !loops_done.contains(&scc_id) {
+            denaturate_loop(
+                node,
+                sccs,
+                &loops,
+                preds,
+                &depth_map,
+                &mut cfg,
+                &mut removed,
+            )?;
+            if loops.get(&node).copied().unwrap_or(false) {
+                remove_continues(node, sccs, &mut cfg, &mut removed);
+            }
+            loops_done.insert(scc_id);
+        }
+    }
+    removed.sort_unstable();
+    Ok((cfg, removed))
+}
+
+// records the exits of every loop in the tree and appends a marker for every removed edge to the
+// innermost loop containing its source: a Continue if it reaches the header of that loop, a Break
+// otherwise. When preserving the loops, an edge reaching neither the header nor the exit of the
+// loop is a Goto instead, and every marker records its edge.
+fn annotate_loops(
+    node: &StructureBlock,
+    cfg: &CFG,
+    removed: &[(BasicBlock, BasicBlock)],
+    preserve: bool,
+) -> StructureBlock {
+    annotate_innermost(node, &|node, bbs, inner| {
+        let mut exits = bbs
+            .iter()
+            .flat_map(|src| {
+                cfg.neighbours(src)
+                    .iter()
+                    .filter(|dst| !bbs.contains(dst))
+                    .map(move |dst| (*src, *dst))
+            })
+            .collect::<Vec<_>>();
+        exits.sort_unstable();
+        exits.dedup();
+        let header = node.iter_preorder().find_map(|node| match node {
+            StructureBlock::Basic(bb) => Some(*bb),
+            StructureBlock::Nested(_) => None,
+        });
+        let targets = exits
+            .iter()
+            .filter(|edge| !removed.contains(edge))
+            .map(|(_, dst)| dst)
+            .collect::<HashSet<_>>();
+        let markers = removed
+            .iter()
+            .filter(|(src, _)| bbs.contains(src) && !inner.contains(src))
+            .map(|(src, dst)| {
+                let label = if header == Some(*dst) {
+                    BlockType::Continue
+                } else if !preserve || targets.contains(dst) {
+                    BlockType::Break
+                } else {
+                    BlockType::Goto
+                };
+                let mut marker = NestedBlock::marker(label, src);
+                if preserve {
+                    marker.exits = vec![(*src, *dst)];
+                }
+                StructureBlock::from(Arc::new(marker))
+            })
+            .collect();
+        (markers, Some(exits))
+    })
+}
+
+// rebuilds the tree passing every loop to `annotate`, along with its basic blocks and the ones
+// belonging to its inner loops, so each block is annotated only by the innermost loop containing
+// it. The returned markers are appended to the loop and the returned exits, if any, replace the
+// ones of the loop.
+fn annotate_innermost<F>(node: &StructureBlock, annotate: &F) -> StructureBlock
+where
+    F: Fn(&StructureBlock, &HashSet<BasicBlock>, &HashSet<BasicBlock>) -> LoopAnnotation,
+{
+    let is_loop = |node: &StructureBlock| {
+        matches!(
+            node.block_type(),
+            BlockType::While | BlockType::DoWhile | BlockType::SelfLooping | BlockType::SwitchLoop
+        )
+    };
+    match node {
+        StructureBlock::Basic(_) => node.clone(),
+        StructureBlock::Nested(nb) => {
+            let mut content = nb
+                .content
+                .iter()
+                .map(|child| annotate_innermost(child, annotate))
+                .collect::<Vec<_>>();
+            let mut exits = nb.exits.clone();
+            if is_loop(node) {
+                let bbs = node.basic_blocks().into_iter().collect::<HashSet<_>>();
+                let inner = nb
+                    .content
+                    .iter()
+                    .flat_map(|child| child.iter_preorder())
+                    .filter(|child| is_loop(child))
+                    .flat_map(|child| child.basic_blocks())
+                    .collect::<HashSet<_>>();
+                let (markers, loop_exits) = annotate(node, &bbs, &inner);
+                content.extend(markers);
+                if let Some(loop_exits) = loop_exits {
+                    exits = loop_exits;
+                }
+            }
+            if content == nb.content && exits == nb.exits {
+                node.clone()
+            } else {
+                StructureBlock::from(Arc::new(NestedBlock {
+                    offset: nb.offset,
+                    block_type: nb.block_type,
+                    content,
+                    depth: nb.depth,
+                    exits,
+                    polarity: nb.polarity,
+                    condition: nb.condition.clone(),
+                }))
+            }
+        }
+    }
+}
+
+// markers appended to a loop and, if recomputed, its exits.
+type LoopAnnotation = (Vec<StructureBlock>, Option<Vec<(BasicBlock, BasicBlock)>>);
+
+// removes every edge entering a loop in a block other than its header, the header being the
+// entry of the loop reached first in depth-first preorder.
+fn remove_abnormal_entries(cfg: &CFG) -> (CFG, Vec<(BasicBlock, BasicBlock)>) {
+    let sccs = cfg.scc();
+    let preds = cfg.predecessors();
+    let mut headers = HashMap::new();
+    for node in cfg.dfs_preorder() {
+        let scc_id = sccs.get(node);
+        let is_entry = cfg.root.as_ref() == Some(node)
+            || preds
+                .get(node)
+                .is_some_and(|p| p.iter().any(|pred| sccs.get(pred) != scc_id));
+        if is_entry {
+            headers.entry(scc_id).or_insert(node);
+        }
+    }
+    let mut abnormal = Vec::new();
+    let edges = cfg
+        .edges
+        .iter()
+        .map(|(src, dsts)| {
+            let kept = dsts
+                .iter()
+                .filter(|dst| {
+                    let scc_id = sccs.get(dst);
+                    let entering = sccs.get(src) != scc_id;
+                    let header = headers.get(&scc_id).copied();
+                    if entering && scc_id.is_some() && header != Some(*dst) {
+                        abnormal.push((*src, **dst));
+                        false
+                    } else {
+                        true
+                    }
+                })
+                .copied()
+                .collect();
+            (*src, kept)
+        })
+        .collect();
+    abnormal.sort_unstable();
+    let cfg = CFG {
+        root: cfg.root,
+        edges,
+        info: cfg.info.clone(),
+        metadata: None,
+    };
+    (cfg, abnormal)
+}
+
+// appends an AbnormalEntry marker for every given edge to the innermost loop containing its
+// target.
+fn annotate_entries(
+    node: &StructureBlock,
+    abnormal: &[(BasicBlock, BasicBlock)],
+) -> StructureBlock {
+    if abnormal.is_empty() {
+        return node.clone();
+    }
+    annotate_innermost(node, &|_, bbs, inner| {
+        let markers = abnormal
+            .iter()
+            .filter(|(_, dst)| bbs.contains(dst) && !inner.contains(dst))
+            .map(|(src, dst)| {
+                let mut marker = NestedBlock::marker(BlockType::AbnormalEntry, src);
+                marker.exits = vec![(*src, *dst)];
+                StructureBlock::from(Arc::new(marker))
+            })
+            .collect();
+        (markers, None)
+    })
+}
+
+// upper bound to the clauses of a reaching condition, to avoid an exponential growth on regions
+// with many paths.
+const MAX_CLAUSES: usize = 64;
+
+// a reaching condition, in disjunctive normal form.
+type Dnf = Vec<Vec<(BasicBlock, bool)>>;
+
+// replaces every acyclic unstructured region with a sequence of conditional blocks, recording
+// each replaced region along with its replacement.
+struct ConditionRefiner<'a> {
+    cfg: &'a CFG,
+    refined: Vec<(StructureBlock, StructureBlock)>,
+}
+
+impl StructureRewriter for ConditionRefiner<'_> {
+    fn leave_block(&mut self, block: StructureBlock, _: usize) -> StructureBlock {
+        let unstructured = matches!(
+            block.block_type(),
+            BlockType::ProperInterval | BlockType::ImproperInterval | BlockType::ImproperRegion
+        );
+        match unstructured
+            .then(|| refine_region(&block, self.cfg))
+            .flatten()
+        {
+            Some(refined) => {
+                self.refined.push((block, refined.clone()));
+                refined
+            }
+            None => block,
+        }
+    }
 }
 
-// remove all edges from a CFG that points to a list of targets
-fn remove_edges(input_set: HashSet<BasicBlock>, targets: HashSet<BasicBlock>, cfg: CFG) -> CFG {
-    type EdgesMap = HashMap<BasicBlock, Vec<BasicBlock>>;
-    let (keep, edit): (EdgesMap, EdgesMap) = cfg
-        .edges
-        .into_iter()
-        .partition(|(src, _)| !input_set.contains(src));
-    let done = edit
-        .into_iter()
-        .map(|(src, dst)| {
-            (
-                src,
-                dst.into_iter()
-                    .filter(|child| !targets.contains(child))
-                    .collect(),
-            )
-        })
-        .chain(keep)
-        .collect();
-    CFG {
-        root: cfg.root,
-        edges: done,
+// wraps every basic block ending the function into a Return or Unreachable block.
+struct TerminatorAnnotator<'a> {
+    info: &'a HashMap<BasicBlock, BlockInfo>,
+}
+
+impl StructureRewriter for TerminatorAnnotator<'_> {
+    fn enter_block(&mut self, block: &StructureBlock, _: usize) -> bool {
+        !matches!(
+            block.block_type(),
+            BlockType::Return | BlockType::Unreachable
+        )
+    }
+
+    fn leave_block(&mut self, block: StructureBlock, _: usize) -> StructureBlock {
+        match &block {
+            StructureBlock::Basic(bb) => match self.info.get(bb).and_then(|i| i.terminator) {
+                Some(bt) => StructureBlock::from(Arc::new(NestedBlock::terminator(bt, bb))),
+                None => block,
+            },
+            StructureBlock::Nested(_) => block,
+        }
     }
 }
 
-fn denaturate_loop(
-    node: BasicBlock,
-    sccs: &HashMap<&BasicBlock, usize>,
-    preds: &HashMap<&BasicBlock, HashSet<&BasicBlock>>,
-    depth_map: &HashMap<BasicBlock, usize>,
-    mut cfg: CFG,
-) -> CFG {
-    let distance = |x, y| {
-        if x < y {
-            y - x
-        } else {
-            x - y
+// computes the reaching condition of every child of the region and emits them in topological
+// order. Returns None if the region is cyclic or the conditions can not be computed.
+fn refine_region(region: &StructureBlock, cfg: &CFG) -> Option<StructureBlock> {
+    let children = region.children();
+    let mut owner = HashMap::new();
+    for (index, child) in children.iter().enumerate() {
+        for bb in child.basic_blocks() {
+            if owner.insert(bb, index).is_some() {
+                return None;
+            }
         }
-    };
-    let (exits, mut targets) = exits_and_targets(node, sccs, &cfg);
-    let is_loop = *is_loop(sccs).get(&node).unwrap();
-    if exits.len() > 1 && is_loop {
-        // harder case, more than 2 output targets, keep the target with the highest depth
-        if targets.len() >= 2 {
-            let correct = *targets
-                .iter()
-                .reduce(|a, b| {
-                    // keep the deepest. If two or more have the same depth keep closest to me
-                    match depth_map.get(a).cmp(&depth_map.get(b)) {
-                        Ordering::Less => b,
-                        Ordering::Equal => {
-                            let distance_a = distance(node.offset, a.offset);
-                            let distance_b = distance(node.offset, b.offset);
-                            match distance_a.cmp(&distance_b) {
-                                Ordering::Less => a,
-                                Ordering::Equal => {
-                                    // head is exactly midway between the targets
-                                    // at this point idk, keep the lowest
-                                    match a.offset.cmp(&b.offset) {
-                                        Ordering::Less => a,
-                                        Ordering::Equal => {
-                                            // no way this can happen without any error in the CFG
-                                            log::error!("Two blocks with the same offset");
-                                            a
-                                        }
-                                        Ordering::Greater => b,
-                                    }
-                                }
-                                Ordering::Greater => b,
-                            }
-                        }
-                        Ordering::Greater => a,
-                    }
-                })
-                .unwrap();
-            targets.remove(&correct);
-            cfg = remove_edges(exits, targets, cfg);
+    }
+    // edges between the children, with the literal that must hold to follow them
+    let mut edges = vec![Vec::new(); children.len()];
+    let mut in_degree = vec![0; children.len()];
+    for (index, child) in children.iter().enumerate() {
+        let mut sources = HashSet::new();
+        for bb in child.basic_blocks() {
+            let succs = cfg.neighbours(&bb);
+            for (position, succ) in succs.iter().enumerate() {
+                let target = owner.get(succ).copied();
+                if target == Some(index) {
+                    continue;
+                }
+                sources.insert(bb);
+                if let Some(target) = target {
+                    let literal = match succs.len() {
+                        1 => None,
+                        2 => Some((bb, position == 1)),
+                        _ => return None,
+                    };
+                    edges[index].push((target, literal));
+                    in_degree[target] += 1;
+                }
+            }
         }
-        let (exits, target) = exits_and_targets(node, sccs, &cfg);
-        let correct_exit = if let Some(head) = exits.get(&node) {
-            // keep the exit which is either: the head (while case)
-            let mut set = HashSet::new();
-            set.insert(*head);
-            set
-        } else {
-            // or farther away from the entry point (do-while case) -> highest predecessor number
-            let max_preds = exits
-                .iter()
-                .fold(0, |acc, x| acc.max(preds.get(x).unwrap().len()));
-            let exits_vec = exits
-                .iter()
-                .cloned()
-                .filter(|x| preds.get(x).unwrap().len() == max_preds)
-                .collect::<Vec<_>>();
-            let exit = if exits_vec.len() == 1 {
-                exits_vec.last().cloned().unwrap()
+        // the condition is known only if the child is left from a single block
+        if sources.len() > 1 {
+            return None;
+        }
+    }
+    let mut ready = in_degree
+        .iter()
+        .enumerate()
+        .filter(|(_, degree)| **degree == 0)
+        .map(|(index, _)| (children[index].offset(), index))
+        .collect::<BTreeSet<_>>();
+    if ready.len() != 1 {
+        return None;
+    }
+    let mut conditions = vec![Dnf::new(); children.len()];
+    conditions[ready.first().unwrap().1] = vec![Vec::new()];
+    let mut order = Vec::with_capacity(children.len());
+    while let Some((_, index)) = ready.pop_first() {
+        let condition = simplify(take(&mut conditions[index]));
+        for (target, literal) in &edges[index] {
+            conditions[*target].extend(condition.iter().map(|clause| {
+                let mut clause = clause.clone();
+                clause.extend(literal);
+                clause
+            }));
+            if conditions[*target].len() > MAX_CLAUSES {
+                return None;
+            }
+            in_degree[*target] -= 1;
+            if in_degree[*target] == 0 {
+                ready.insert((children[*target].offset(), *target));
+            }
+        }
+        conditions[index] = condition;
+        order.push(index);
+    }
+    if order.len() != children.len() {
+        return None;
+    }
+    // consecutive children with the same condition share the same conditional block
+    let mut content = Vec::new();
+    let mut group: Vec<StructureBlock> = Vec::new();
+    for (position, index) in order.iter().enumerate() {
+        group.push(children[*index].clone());
+        let last = order
+            .get(position + 1)
+            .is_none_or(|next| conditions[*next] != conditions[*index]);
+        if last {
+            let condition = &conditions[*index];
+            if condition.iter().any(|clause| clause.is_empty()) {
+                content.append(&mut group);
             } else {
-                //two or more exits with same amount of predecessors to the same target
-                //keep the one with further offset
-                let (_, index_max_diff) = exits_vec
-                    .iter()
-                    .map(|x| distance(node.offset, x.offset))
-                    .enumerate()
-                    .map(|(index, value)| (value, index))
-                    .max()
-                    .unwrap();
-                exits_vec[index_max_diff]
-            };
-            let mut set = HashSet::new();
-            set.insert(exit);
-            set
-        };
-        let wrong_exits = exits
-            .difference(&correct_exit)
-            .cloned()
-            .collect::<HashSet<_>>();
-        cfg = remove_edges(wrong_exits, target, cfg);
+                let body = if group.len() == 1 {
+                    group.pop().unwrap()
+                } else {
+                    let sequence = NestedBlock::new(BlockType::Sequence, take(&mut group));
+                    StructureBlock::from(Arc::new(sequence))
+                };
+                let mut conditional = NestedBlock::new(BlockType::Conditional, vec![body]);
+                conditional.condition = condition.clone();
+                content.push(StructureBlock::from(Arc::new(conditional)));
+            }
+        }
     }
-    // 1 exit and >1 targets can't exist in a CFG loop
-    cfg
+    let sequence = NestedBlock::new(BlockType::Sequence, content);
+    Some(StructureBlock::from(Arc::new(sequence)))
 }
 
-fn remove_natural_loops(
-    sccs: &HashMap<&BasicBlock, usize>,
-    preds: &HashMap<&BasicBlock, HashSet<&BasicBlock>>,
-    mut cfg: CFG,
-) -> CFG {
-    let mut loops_done = FnvHashSet::default();
-    let depth_map = calculate_depth(&cfg);
-    let nodes = cfg.dfs_preorder().cloned().collect::<Vec<_>>();
-    for node in nodes {
-        let scc_id = sccs.get(&node).unwrap();
-        if !loops_done.contains(scc_id) {
-            cfg = denaturate_loop(node, sccs, preds, &depth_map, cfg);
-            loops_done.insert(scc_id);
+// simplifies a condition in disjunctive normal form, by removing contradictions, absorbing the
+// clauses implied by other clauses and merging clauses differing only for a complementary
+// literal. An empty clause represents a condition that is always true.
+fn simplify(mut dnf: Dnf) -> Dnf {
+    loop {
+        for clause in dnf.iter_mut() {
+            clause.sort_unstable();
+            clause.dedup();
+        }
+        dnf.retain(|clause| clause.windows(2).all(|pair| pair[0].0 != pair[1].0));
+        dnf.sort_unstable();
+        dnf.dedup();
+        let absorbed = dnf
+            .iter()
+            .filter(|clause| {
+                !dnf.iter().any(|other| {
+                    other != *clause && other.iter().all(|literal| clause.contains(literal))
+                })
+            })
+            .cloned()
+            .collect::<Dnf>();
+        dnf = absorbed;
+        let mut merged = None;
+        'outer: for (i, a) in dnf.iter().enumerate() {
+            for b in dnf.iter().skip(i + 1) {
+                if a.len() != b.len() {
+                    continue;
+                }
+                let diff = a
+                    .iter()
+                    .filter(|literal| !b.contains(literal))
+                    .collect::<Vec<_>>();
+                if let [(bb, taken)] = diff.as_slice() {
+                    if b.contains(&(*bb, !*taken)) {
+                        merged = Some((i, (*bb, *taken)));
+                        break 'outer;
+                    }
+                }
+            }
+        }
+        match merged {
+            Some((i, literal)) => {
+                // the other clause becomes equal to this one, and is removed by the dedup
+                let (bb, taken) = literal;
+                let mut clause = dnf[i].clone();
+                clause.retain(|x| *x != literal);
+                dnf.retain(|other| {
+                    !(other.len() == clause.len() + 1
+                        && (other.contains(&(bb, taken)) || other.contains(&(bb, !taken)))
+                        && clause.iter().all(|literal| other.contains(literal)))
+                });
+                dnf.push(clause);
+            }
+            None => return dnf,
         }
     }
-    cfg
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::analysis::{cfs, BasicBlock, BlockType, Graph, CFG, CFS};
-    use std::collections::HashMap;
+    use crate::analysis::{
+        cfs, tree_distance, tree_distance_with_costs, tree_similarity,
+        tree_similarity_with_weights, BasicBlock, BlockType, CancellationToken, CfsBuilder,
+        DirectedGraph, EditCosts, FunctionMetadata, Graph, NestedBlock, Phase, ProgressSink,
+        Reduction, ReductionKind, SimilarityWeights, StructureBlock, CFG, CFS,
+    };
+    use crate::disasm::{Architecture, Statement, StatementFamily};
+    use crate::Error;
+    use std::collections::{HashMap, HashSet};
+    use std::sync::{Arc, Mutex};
 
     macro_rules! create_cfg {
     (@single $($x:tt)*) => (());
@@ -1125,6 +4193,8 @@ mod tests {
             CFG {
                 root,
                 edges,
+                info: HashMap::new(),
+                metadata: None,
             }
         }
     };
@@ -1134,6 +4204,8 @@ mod tests {
         CFG {
             root: None,
             edges: HashMap::default(),
+            info: HashMap::new(),
+            metadata: None,
         }
     }
 
@@ -1163,14 +4235,14 @@ mod tests {
     #[test]
     fn constructor_empty() {
         let cfg = create_cfg! {};
-        let cfs = CFS::new(&cfg);
+        let cfs = CFS::new(&cfg).unwrap();
         assert!(cfs.get_tree().is_none());
     }
 
     #[test]
     fn reduce_sequence() {
         let cfg = create_cfg! { 0 => [1], 1 => [2], 2 => [3], 3 => [4], 4 => [] };
-        let cfs = CFS::new(&cfg);
+        let cfs = CFS::new(&cfg).unwrap();
         let sequence = cfs.get_tree().unwrap();
         assert_eq!(sequence.len(), 5);
         assert_eq!(sequence.depth(), 1);
@@ -1180,7 +4252,7 @@ mod tests {
     #[test]
     fn reduce_self_loop() {
         let cfg = create_cfg! { 0 => [1], 1 => [2, 1], 2 => [] };
-        let cfs = CFS::new(&cfg);
+        let cfs = CFS::new(&cfg).unwrap();
         let sequence = cfs.get_tree().unwrap();
         assert_eq!(sequence.len(), 3);
         assert_eq!(sequence.depth(), 2);
@@ -1203,7 +4275,7 @@ mod tests {
             6 => [7],
             7 => []
         };
-        let cfs = CFS::new(&cfg);
+        let cfs = CFS::new(&cfg).unwrap();
         let sequence = cfs.get_tree().unwrap();
         assert_eq!(sequence.len(), 3);
         let children = sequence.children();
@@ -1211,10 +4283,33 @@ mod tests {
         assert_eq!(children[1].len(), 6);
     }
 
+    #[test]
+    fn switch_cases_order() {
+        let cfg = create_cfg! {
+            0 => [1],
+            1 => [5, 3, 4, 2],
+            2 => [6],
+            3 => [6],
+            4 => [6],
+            5 => [6],
+            6 => []
+        };
+        let cfs = CFS::new(&cfg).unwrap();
+        let sequence = cfs.get_tree().unwrap();
+        let switch = &sequence.children()[1];
+        assert_eq!(switch.block_type(), BlockType::Switch);
+        let offsets = switch
+            .children()
+            .iter()
+            .map(|x| x.offset())
+            .collect::<Vec<_>>();
+        assert_eq!(offsets, vec![1, 2, 3, 4, 5]);
+    }
+
     #[test]
     fn switch_fallthrough_single() {
         let cfg = create_cfg! { 0 => [1, 2, 3], 1 => [2], 2 => [4], 3 => [4], 4 => [] };
-        let cfs = CFS::new(&cfg);
+        let cfs = CFS::new(&cfg).unwrap();
         assert!(cfs.get_tree().is_some());
         let sequence = cfs.get_tree().unwrap();
         assert_eq!(sequence.len(), 2);
@@ -1225,7 +4320,7 @@ mod tests {
     #[test]
     fn switch_fallthrough_multiple() {
         let cfg = create_cfg! { 0 => [1, 2, 3], 1 => [2, 3], 2 => [4], 3 => [4], 4 => [] };
-        let cfs = CFS::new(&cfg);
+        let cfs = CFS::new(&cfg).unwrap();
         assert!(cfs.get_tree().is_some());
         let sequence = cfs.get_tree().unwrap();
         assert_eq!(sequence.len(), 2);
@@ -1243,7 +4338,7 @@ mod tests {
             4 => [5],
             5 => []
         };
-        let cfs = CFS::new(&cfg);
+        let cfs = CFS::new(&cfg).unwrap();
         assert!(cfs.get_tree().is_some());
         let sequence = cfs.get_tree().unwrap();
         assert_eq!(sequence.len(), 2);
@@ -1262,7 +4357,7 @@ mod tests {
             5 => [6],
             6 => []
         };
-        let cfs = CFS::new(&cfg);
+        let cfs = CFS::new(&cfg).unwrap();
         assert!(cfs.get_tree().is_some());
         let sequence = cfs.get_tree().unwrap();
         assert_eq!(sequence.len(), 2);
@@ -1283,7 +4378,7 @@ mod tests {
             7 => [8],
             8 => []
         };
-        let cfs = CFS::new(&cfg);
+        let cfs = CFS::new(&cfg).unwrap();
         assert!(cfs.get_tree().is_some());
         let sequence = cfs.get_tree().unwrap();
         assert_eq!(sequence.len(), 2);
@@ -1305,14 +4400,19 @@ mod tests {
             7 => [8],
             8 => []
         };
-        let cfs = CFS::new(&cfg);
-        assert!(cfs.get_tree().is_none())
+        let cfs = CFS::new(&cfg).unwrap();
+        let tree = cfs.get_tree().unwrap();
+        // only the region before the common successor is left unstructured
+        assert_eq!(tree.block_type(), BlockType::Sequence);
+        assert_eq!(tree.children().len(), 2);
+        assert_eq!(tree.children()[0].block_type(), BlockType::ImproperRegion);
+        assert_eq!(tree.children()[1].offset(), 8);
     }
 
     #[test]
     fn reduce_if_then_next() {
         let cfg = create_cfg! { 0 => [1], 1 => [2, 3], 2 => [3], 3 => [4], 4 => [] };
-        let cfs = CFS::new(&cfg);
+        let cfs = CFS::new(&cfg).unwrap();
         let sequence = cfs.get_tree().unwrap();
         assert_eq!(sequence.len(), 4);
         assert_eq!(sequence.depth(), 2);
@@ -1328,7 +4428,7 @@ mod tests {
     #[test]
     fn reduce_if_then_cond() {
         let cfg = create_cfg! { 0 => [1], 1 => [3, 2], 2 => [3], 3 => [4], 4 => [] };
-        let cfs = CFS::new(&cfg);
+        let cfs = CFS::new(&cfg).unwrap();
         let sequence = cfs.get_tree().unwrap();
         assert_eq!(sequence.len(), 4);
         assert_eq!(sequence.depth(), 2);
@@ -1345,15 +4445,20 @@ mod tests {
     fn short_circuit_if_then() {
         // 2 is reached iff 0 and 1 holds
         let cfg = create_cfg! { 0 => [1, 3], 1 => [2, 3], 2 => [3], 3 => [] };
-        let cfs = CFS::new(&cfg);
+        let cfs = CFS::new(&cfg).unwrap();
         let sequence = cfs.get_tree().unwrap();
         assert_eq!(sequence.len(), 2);
-        assert_eq!(sequence.depth(), 2);
+        assert_eq!(sequence.depth(), 3);
         assert_eq!(sequence.block_type(), BlockType::Sequence);
         let children = sequence.children();
         assert_eq!(children[0].block_type(), BlockType::IfThen);
-        assert_eq!(children[0].depth(), 1);
-        assert_eq!(children[0].len(), 3);
+        assert_eq!(children[0].depth(), 2);
+        assert_eq!(children[0].len(), 2);
+        let bb = |offset| StructureBlock::from(BasicBlock { offset, length: 1 });
+        let chain = &children[0].children()[0];
+        assert_eq!(chain.block_type(), BlockType::IfChain);
+        assert_eq!(chain.children(), &[bb(0), bb(1)]);
+        assert_eq!(children[0].children()[1], bb(2));
         assert_eq!(children[1].block_type(), BlockType::Basic);
     }
 
@@ -1363,15 +4468,16 @@ mod tests {
         let cfg = create_cfg! {
             0 => [1, 4], 1 => [2, 4], 2 => [4, 3], 3 => [4], 4 => []
         };
-        let cfs = CFS::new(&cfg);
+        let cfs = CFS::new(&cfg).unwrap();
         let sequence = cfs.get_tree().unwrap();
         assert_eq!(sequence.len(), 2);
         assert_eq!(sequence.block_type(), BlockType::Sequence);
         let children = sequence.children();
         assert_eq!(children[0].block_type(), BlockType::IfThen);
-        assert_eq!(children[0].len(), 4);
+        assert_eq!(children[0].len(), 2);
+        assert_eq!(children[0].children()[0].len(), 3);
         assert_eq!(children[1].block_type(), BlockType::Basic);
-        assert_eq!(sequence.depth(), 2);
+        assert_eq!(sequence.depth(), 3);
     }
 
     #[test]
@@ -1379,7 +4485,7 @@ mod tests {
         let cfg = create_cfg! {
             0 => [1], 1 => [2, 3], 2 => [4], 3 => [4], 4 => [5], 5 => []
         };
-        let cfs = CFS::new(&cfg);
+        let cfs = CFS::new(&cfg).unwrap();
         let sequence = cfs.get_tree().unwrap();
         assert_eq!(sequence.len(), 4);
         assert_eq!(sequence.block_type(), BlockType::Sequence);
@@ -1397,22 +4503,26 @@ mod tests {
         let cfg = create_cfg! {
             0 => [1, 3], 1 => [2, 3], 2 => [3, 4], 3 => [5], 4 => [5], 5 => []
         };
-        let cfs = CFS::new(&cfg);
+        let cfs = CFS::new(&cfg).unwrap();
         let sequence = cfs.get_tree().unwrap();
         assert_eq!(sequence.len(), 2);
         assert_eq!(sequence.block_type(), BlockType::Sequence);
         let children = sequence.children();
         assert_eq!(children[0].block_type(), BlockType::IfThenElse);
-        assert_eq!(children[0].len(), 5);
+        assert_eq!(children[0].len(), 3);
+        let bb = |offset| StructureBlock::from(BasicBlock { offset, length: 1 });
+        let chain = &children[0].children()[0];
+        assert_eq!(chain.block_type(), BlockType::IfChain);
+        assert_eq!(chain.children(), &[bb(0), bb(1), bb(2)]);
         assert_eq!(children[1].block_type(), BlockType::Basic);
-        assert_eq!(sequence.depth(), 2);
+        assert_eq!(sequence.depth(), 3);
     }
 
     #[test]
     fn if_else_looping() {
         // this test replicates a bug
         let cfg = create_cfg! { 0 => [1, 2], 1 => [3, 1], 2 => [3, 2], 3 => [] };
-        let cfs = CFS::new(&cfg);
+        let cfs = CFS::new(&cfg).unwrap();
         let sequence = cfs.get_tree().unwrap();
         assert_eq!(sequence.len(), 2);
         assert_eq!(sequence.block_type(), BlockType::Sequence);
@@ -1434,7 +4544,7 @@ mod tests {
     #[test]
     fn while_no_entry() {
         let cfg = create_cfg! { 0 => [1, 2], 1 => [0], 2 => [] };
-        let cfs = CFS::new(&cfg.add_entry_point());
+        let cfs = CFS::new(&cfg.add_entry_point()).unwrap();
         assert!(cfs.get_tree().is_some());
         let sequence = cfs.get_tree().unwrap();
         assert_eq!(sequence.children()[1].block_type(), BlockType::While);
@@ -1443,7 +4553,7 @@ mod tests {
     #[test]
     fn whileb() {
         let cfg = create_cfg! { 0 => [1], 1 => [2, 3], 2 => [1], 3 => [] };
-        let cfs = CFS::new(&cfg);
+        let cfs = CFS::new(&cfg).unwrap();
         let sequence = cfs.get_tree().unwrap();
         assert_eq!(sequence.block_type(), BlockType::Sequence);
         assert_eq!(sequence.len(), 3);
@@ -1454,7 +4564,7 @@ mod tests {
     #[test]
     fn dowhile_no_entry() {
         let cfg = create_cfg! { 0 => [1], 1 => [0, 2], 2 => [] };
-        let cfs = CFS::new(&cfg.add_entry_point());
+        let cfs = CFS::new(&cfg.add_entry_point()).unwrap();
         assert!(cfs.get_tree().is_some());
         let sequence = cfs.get_tree().unwrap();
         assert_eq!(sequence.children()[1].block_type(), BlockType::DoWhile);
@@ -1464,7 +4574,7 @@ mod tests {
     fn dowhile_type1() {
         // only 2 nodes, head and tail form the block
         let cfg = create_cfg! { 0 => [1], 1 => [2], 2 => [1, 3], 3 => [] };
-        let cfs = CFS::new(&cfg);
+        let cfs = CFS::new(&cfg).unwrap();
         let sequence = cfs.get_tree().unwrap();
         assert_eq!(sequence.block_type(), BlockType::Sequence);
         assert_eq!(sequence.len(), 3);
@@ -1476,7 +4586,7 @@ mod tests {
     fn dowhile_type2() {
         // three nodes form the block: head, extra, tail
         let cfg = create_cfg! { 0 => [1], 1 => [2], 2 => [3], 3 => [4, 1], 4 => [] };
-        let cfs = CFS::new(&cfg);
+        let cfs = CFS::new(&cfg).unwrap();
         let sequence = cfs.get_tree().unwrap();
         assert_eq!(sequence.block_type(), BlockType::Sequence);
         assert_eq!(sequence.len(), 3);
@@ -1488,7 +4598,7 @@ mod tests {
     fn dowhile_type3() {
         // three nodes form the block: head, tail, extra
         let cfg = create_cfg! { 0 => [1], 1 => [2], 2 => [3, 4], 3 => [1], 4 => [] };
-        let cfs = CFS::new(&cfg);
+        let cfs = CFS::new(&cfg).unwrap();
         let sequence = cfs.get_tree().unwrap();
         assert_eq!(sequence.block_type(), BlockType::Sequence);
         assert_eq!(sequence.len(), 3);
@@ -1502,7 +4612,7 @@ mod tests {
         let cfg = create_cfg! {
             0 => [1], 1 => [2], 2 => [3], 3 => [4, 5], 4 => [1], 5 => []
         };
-        let cfs = CFS::new(&cfg);
+        let cfs = CFS::new(&cfg).unwrap();
         let sequence = cfs.get_tree().unwrap();
         assert_eq!(sequence.block_type(), BlockType::Sequence);
         assert_eq!(sequence.len(), 3);
@@ -1516,7 +4626,7 @@ mod tests {
         let cfg = create_cfg! {
             0 => [1], 1 => [2], 2 => [3, 4], 3 => [5, 3], 4 => [5], 5 => [6, 1], 6 => []
         };
-        let cfs = CFS::new(&cfg);
+        let cfs = CFS::new(&cfg).unwrap();
         let sequence = cfs.get_tree().unwrap();
         assert_eq!(sequence.block_type(), BlockType::Sequence);
         assert_eq!(sequence.len(), 3);
@@ -1529,7 +4639,7 @@ mod tests {
     fn nested_while() {
         // while inside while, sharing a head-tail
         let cfg = create_cfg! { 0 => [1], 1 =>[4, 2], 2 => [3, 1], 3 => [2], 4 => [] };
-        let cfs = CFS::new(&cfg);
+        let cfs = CFS::new(&cfg).unwrap();
         let sequence = cfs.get_tree().unwrap();
         assert_eq!(sequence.block_type(), BlockType::Sequence);
         assert_eq!(sequence.len(), 3);
@@ -1546,7 +4656,7 @@ mod tests {
     fn nested_dowhile_sharing() {
         // do-while inside do-while, sharing a head-tail
         let cfg = create_cfg! { 0 => [1], 1 => [2], 2 => [3, 1], 3 => [4, 2], 4 => [] };
-        let cfs = CFS::new(&cfg);
+        let cfs = CFS::new(&cfg).unwrap();
         let sequence = cfs.get_tree().unwrap();
         assert_eq!(sequence.block_type(), BlockType::Sequence);
         assert_eq!(sequence.len(), 3);
@@ -1565,7 +4675,7 @@ mod tests {
         let cfg = create_cfg! {
             0 => [1], 1 => [2], 2 => [3], 3 => [4, 2], 4 => [5, 1], 5 => []
         };
-        let cfs = CFS::new(&cfg);
+        let cfs = CFS::new(&cfg).unwrap();
         let sequence = cfs.get_tree().unwrap();
         assert_eq!(sequence.block_type(), BlockType::Sequence);
         assert_eq!(sequence.len(), 3);
@@ -1580,7 +4690,7 @@ mod tests {
     #[test]
     fn nat_loop_break_while() {
         let cfg = create_cfg! { 0 => [1], 1 => [2, 4], 2 => [3, 4], 3 => [1], 4 => [] };
-        let cfs = CFS::new(&cfg);
+        let cfs = CFS::new(&cfg).unwrap();
         let sequence = cfs.get_tree().unwrap();
         assert_eq!(sequence.block_type(), BlockType::Sequence);
         assert_eq!(sequence.len(), 3);
@@ -1588,19 +4698,359 @@ mod tests {
         assert_eq!(sequence.depth(), 3);
     }
 
+    #[test]
+    fn nat_loop_break_marker() {
+        let cfg = create_cfg! { 0 => [1], 1 => [2, 5], 2 => [3], 3 => [4, 5], 4 => [1], 5 => [] };
+        let cfs = CFS::new(&cfg).unwrap();
+        let sequence = cfs.get_tree().unwrap();
+        let whileb = &sequence.children()[1];
+        assert_eq!(whileb.block_type(), BlockType::While);
+        let marker = whileb.children().last().unwrap();
+        assert_eq!(marker.block_type(), BlockType::Break);
+        assert_eq!(marker.offset(), 3);
+        assert_eq!(marker.depth(), 0);
+        assert!(marker.is_empty());
+        assert_eq!(sequence.depth(), 3);
+    }
+
+    #[test]
+    fn preserve_loops_break() {
+        let cfg = create_cfg! { 0 => [1], 1 => [2, 5], 2 => [3], 3 => [4, 5], 4 => [1], 5 => [] };
+        let cfs = CfsBuilder::new().preserve_loops().build(&cfg).unwrap();
+        let bb = |offset| BasicBlock { offset, length: 1 };
+        let sequence = cfs.get_tree().unwrap();
+        let marker = sequence.children()[1].children().last().unwrap().clone();
+        assert_eq!(marker.block_type(), BlockType::Break);
+        assert_eq!(marker.exits(), &[(bb(3), bb(5))]);
+    }
+
+    #[test]
+    fn preserve_loops_goto() {
+        let cfg = create_cfg! {
+            0 => [1], 1 => [2, 5], 2 => [3], 3 => [4, 6], 4 => [1], 5 => [6], 6 => []
+        };
+        let bb = |offset| BasicBlock { offset, length: 1 };
+        let default = CFS::new(&cfg).unwrap();
+        let markers = default
+            .get_tree()
+            .unwrap()
+            .iter_preorder()
+            .filter(|node| node.block_type() == BlockType::Break)
+            .count();
+        assert_eq!(markers, 1);
+        let cfs = CfsBuilder::new().preserve_loops().build(&cfg).unwrap();
+        let tree = cfs.get_tree().unwrap();
+        let gotos = tree
+            .iter_preorder()
+            .filter(|node| node.block_type() == BlockType::Goto)
+            .collect::<Vec<_>>();
+        assert_eq!(gotos.len(), 1);
+        assert_eq!(gotos[0].offset(), 3);
+        assert_eq!(gotos[0].exits(), &[(bb(3), bb(6))]);
+        assert!(!tree
+            .iter_preorder()
+            .any(|node| node.block_type() == BlockType::Break));
+    }
+
+    #[test]
+    fn nat_loop_continue() {
+        // while (1) { 2; if (3) { 4; if (5) continue; 6; } 7; }
+        let cfg = create_cfg! {
+            0 => [1], 1 => [2, 8], 2 => [3], 3 => [4, 7], 4 => [5], 5 => [1, 6], 6 => [7],
+            7 => [1], 8 => []
+        };
+        let bb = |offset| BasicBlock { offset, length: 1 };
+        let cfs = CFS::new(&cfg).unwrap();
+        assert_eq!(cfs.removed_edges(), &[(bb(5), bb(1))]);
+        let tree = cfs.get_tree().unwrap();
+        let whileb = tree
+            .iter_preorder()
+            .find(|node| node.block_type() == BlockType::While)
+            .unwrap();
+        let marker = whileb.children().last().unwrap();
+        assert_eq!(marker.block_type(), BlockType::Continue);
+        assert_eq!(marker.offset(), 5);
+        assert!(marker.exits().is_empty());
+        assert!(!tree
+            .iter_preorder()
+            .any(|node| node.block_type() == BlockType::ProperInterval));
+        let cfs = CfsBuilder::new().preserve_loops().build(&cfg).unwrap();
+        let tree = cfs.get_tree().unwrap();
+        let marker = tree
+            .iter_preorder()
+            .find(|node| node.block_type() == BlockType::Continue)
+            .unwrap();
+        assert_eq!(marker.exits(), &[(bb(5), bb(1))]);
+    }
+
+    #[test]
+    fn nat_loop_break_innermost() {
+        // while (1) { while (2) { if (3) break_outer; 4; } 5; }
+        let cfg = create_cfg! {
+            0 => [1], 1 => [2, 6], 2 => [3, 5], 3 => [4, 6], 4 => [2], 5 => [1], 6 => []
+        };
+        let cfs = CFS::new(&cfg).unwrap();
+        let tree = cfs.get_tree().unwrap();
+        let loops = tree
+            .iter_preorder()
+            .filter(|node| node.block_type() == BlockType::While)
+            .collect::<Vec<_>>();
+        assert_eq!(loops.len(), 2);
+        let outer = loops[0].children().last().unwrap();
+        assert_ne!(outer.block_type(), BlockType::Break);
+        let inner = loops[1].children().last().unwrap();
+        assert_eq!(inner.block_type(), BlockType::Break);
+        assert_eq!(inner.offset(), 3);
+    }
+
+    #[test]
+    fn partial_result_markers() {
+        let cfg = create_cfg! {
+            0 => [1], 1 => [2, 5], 2 => [3], 3 => [4, 5], 4 => [1], 5 => [6, 7], 6 => [8, 9],
+            7 => [9, 10], 8 => [11], 9 => [11], 10 => [11], 11 => [12], 12 => []
+        };
+        let cfs = CFS::new(&cfg).unwrap();
+        let result = cfs.result();
+        assert!(!result.is_complete());
+        let marker = result
+            .partial_trees()
+            .into_iter()
+            .flat_map(|tree| tree.iter_preorder())
+            .find(|node| node.block_type() == BlockType::Break)
+            .unwrap();
+        assert_eq!(marker.offset(), 3);
+    }
+
+    #[test]
+    fn no_break_marker() {
+        let cfg = create_cfg! { 0 => [1], 1 => [2, 3], 2 => [1], 3 => [] };
+        let cfs = CFS::new(&cfg).unwrap();
+        let sequence = cfs.get_tree().unwrap();
+        let whileb = &sequence.children()[1];
+        assert_eq!(whileb.block_type(), BlockType::While);
+        assert_eq!(whileb.len(), 2);
+    }
+
     #[test]
     fn nat_loop_break_dowhile() {
         let cfg = create_cfg! { 0 => [1], 1 => [2], 2 => [3, 4], 3 => [4, 1], 4 => [] };
-        let cfs = CFS::new(&cfg);
+        let cfs = CFS::new(&cfg).unwrap();
+        let sequence = cfs.get_tree().unwrap();
+        assert_eq!(sequence.block_type(), BlockType::Sequence);
+        assert_eq!(sequence.len(), 3);
+        assert_eq!(sequence.children()[1].block_type(), BlockType::DoWhile);
+        assert_eq!(sequence.depth(), 3);
+    }
+
+    #[test]
+    fn guard_clauses() {
+        let cfg = create_cfg! { 0 => [1, 2], 1 => [], 2 => [3, 4], 3 => [], 4 => [5], 5 => [] };
+        let cfs = CFS::new(&cfg).unwrap();
+        let sequence = cfs.get_tree().unwrap();
+        assert_eq!(sequence.block_type(), BlockType::Sequence);
+        let children = sequence.children();
+        assert_eq!(children[0].block_type(), BlockType::Guard);
+        assert_eq!(children[0].children()[1].offset(), 1);
+        assert_eq!(children[1].block_type(), BlockType::Guard);
+        assert_eq!(children[1].children()[1].offset(), 3);
+        assert_eq!(sequence.depth(), 2);
+    }
+
+    #[test]
+    fn guard_not_if_else() {
+        let cfg = create_cfg! { 0 => [1, 2], 1 => [], 2 => [] };
+        let cfs = CFS::new(&cfg).unwrap();
+        let tree = cfs.get_tree().unwrap();
+        assert_eq!(tree.children()[0].block_type(), BlockType::IfThenElse);
+        assert_eq!(cfs.stats().count(BlockType::Guard), 0);
+    }
+
+    #[test]
+    fn from_cfg() {
+        let cfg = create_cfg! { 0 => [1], 1 => [2, 5], 2 => [3], 3 => [4, 5], 4 => [1], 5 => [] };
+        let borrowed = CFS::new(&cfg).unwrap();
+        let owned = CFS::from_cfg(cfg.clone()).unwrap();
+        assert_eq!(borrowed.get_tree(), owned.get_tree());
+        assert_eq!(owned.get_cfg(), &cfg);
+        assert_eq!(owned.removed_edges(), borrowed.removed_edges());
+    }
+
+    #[test]
+    fn analyze_all() {
+        let cfgs = vec![
+            create_cfg! { 0 => [1, 2], 1 => [2], 2 => [] },
+            create_cfg! { 0 => [1], 1 => [2, 5], 2 => [3], 3 => [4, 5], 4 => [1], 5 => [] },
+            create_cfg! { 0 => [1, 2], 1 => [], 2 => [] },
+        ];
+        let expected = cfgs
+            .iter()
+            .map(|cfg| CFS::new(cfg).unwrap())
+            .collect::<Vec<_>>();
+        let mut malformed = create_cfg! { 0 => [1], 1 => [] };
+        malformed.edges.remove(&BasicBlock {
+            offset: 1,
+            length: 1,
+        });
+        let mut actual = CFS::analyze_all(cfgs.into_iter().chain([malformed]));
+        assert!(matches!(actual.pop(), Some(Err(Error::MalformedCfg(_)))));
+        assert_eq!(actual.len(), expected.len());
+        for (actual, expected) in actual.iter().zip(expected.iter()) {
+            assert_eq!(actual.as_ref().unwrap().get_tree(), expected.get_tree());
+        }
+        assert!(CFS::analyze_all(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn malformed_cfg() {
+        let bb = |offset| BasicBlock { offset, length: 1 };
+        let mut dangling = create_cfg! { 0 => [1, 2], 1 => [2], 2 => [] };
+        dangling.edges.remove(&bb(2));
+        let err = CFS::new(&dangling).err().unwrap();
+        assert!(matches!(err, Error::MalformedCfg(_)));
+        assert!(err.to_string().contains("reaches a missing block"));
+        let mut rootless = create_cfg! { 0 => [1], 1 => [] };
+        rootless.root = None;
+        assert!(matches!(CFS::new(&rootless), Err(Error::MalformedCfg(_))));
+        let mut detached = create_cfg! { 0 => [1], 1 => [] };
+        detached.root = Some(bb(5));
+        assert!(matches!(CFS::new(&detached), Err(Error::MalformedCfg(_))));
+        assert!(CFS::new(&empty()).is_ok());
+    }
+
+    #[test]
+    fn malformed_reserved_blocks() {
+        let bb = |offset| BasicBlock { offset, length: 1 };
+        let duplicated = create_cfg! { 0 => [1, 1], 1 => [] };
+        let err = CFS::new(&duplicated).err().unwrap();
+        assert!(matches!(err, Error::MalformedCfg(_)));
+        assert!(err.to_string().contains("is duplicated"));
+        let mut sink = create_cfg! { 0 => [1], 1 => [] };
+        sink.edges.insert(bb(1), vec![BasicBlock::new_sink()]);
+        sink.edges.insert(BasicBlock::new_sink(), vec![bb(0)]);
+        assert!(matches!(CFS::new(&sink), Err(Error::MalformedCfg(_))));
+        let mut entry = create_cfg! { 0 => [1], 1 => [] };
+        entry
+            .edges
+            .insert(BasicBlock::new_entry_point(), vec![bb(0)]);
+        assert!(matches!(CFS::new(&entry), Err(Error::MalformedCfg(_))));
+    }
+
+    #[test]
+    fn malformed_mutations() {
+        // every block removed or with a duplicated edge must be reported, never panic
+        let cfgs = vec![
+            create_cfg! { 0 => [1, 2], 1 => [2], 2 => [] },
+            create_cfg! { 0 => [1], 1 => [4, 2], 2 => [3, 1], 3 => [2], 4 => [] },
+            create_cfg! { 0 => [1], 1 => [2], 2 => [3, 1], 3 => [4, 2], 4 => [] },
+            create_cfg! { 0 => [1, 2, 3], 1 => [2], 2 => [4], 3 => [4], 4 => [] },
+        ];
+        for cfg in cfgs {
+            assert!(CFS::new(&cfg).is_ok());
+            for (node, children) in &cfg.edges {
+                let mut removed = cfg.clone();
+                removed.edges.remove(node);
+                assert!(matches!(CFS::new(&removed), Err(Error::MalformedCfg(_))));
+                if let Some(child) = children.first() {
+                    let mut duplicated = cfg.clone();
+                    duplicated.edges.get_mut(node).unwrap().push(*child);
+                    assert!(matches!(CFS::new(&duplicated), Err(Error::MalformedCfg(_))));
+                }
+            }
+        }
+    }
+
+    #[derive(Default)]
+    struct ProgressLog(Mutex<Vec<String>>);
+
+    impl ProgressSink for ProgressLog {
+        fn phase_started(&self, phase: Phase, total: usize) {
+            let event = format!("start {} {}", phase.to_str(), total);
+            self.0.lock().unwrap().push(event);
+        }
+
+        fn function_completed(&self, phase: Phase, offset: u64) {
+            let event = format!("done {} {:#x}", phase.to_str(), offset);
+            self.0.lock().unwrap().push(event);
+        }
+
+        fn phase_finished(&self, phase: Phase) {
+            let event = format!("end {}", phase.to_str());
+            self.0.lock().unwrap().push(event);
+        }
+    }
+
+    #[test]
+    fn build_all_progress() {
+        let cfgs = vec![
+            create_cfg! { 0 => [1, 2], 1 => [2], 2 => [] },
+            create_cfg! { 0 => [1, 2], 1 => [], 2 => [] }
+                .with_metadata(FunctionMetadata::new("main", 0x400, 3)),
+        ];
+        let log = Arc::new(ProgressLog::default());
+        let cfss = CfsBuilder::new().with_progress(log.clone()).build_all(cfgs);
+        assert_eq!(cfss.len(), 2);
+        let mut events = log.0.lock().unwrap().clone();
+        assert_eq!(events.first().unwrap(), "start structuring 2");
+        assert_eq!(events.last().unwrap(), "end structuring");
+        // functions may complete in any order
+        events.sort();
+        assert_eq!(
+            events[..2],
+            ["done structuring 0x0", "done structuring 0x400"]
+        );
+    }
+
+    #[test]
+    fn long_function() {
+        // chain of if-then followed by a loop, to check that big functions are reduced quickly
+        let amount = 2000;
+        let mut cfg = CFG {
+            root: None,
+            edges: HashMap::new(),
+            info: HashMap::new(),
+            metadata: None,
+        };
+        let bb = |offset| BasicBlock { offset, length: 1 };
+        for i in 0..amount {
+            cfg.edges
+                .insert(bb(2 * i), vec![bb(2 * i + 1), bb(2 * i + 2)]);
+            cfg.edges.insert(bb(2 * i + 1), vec![bb(2 * i + 2)]);
+        }
+        cfg.edges
+            .insert(bb(2 * amount), vec![bb(2 * amount), bb(2 * amount + 1)]);
+        cfg.edges.insert(bb(2 * amount + 1), vec![]);
+        cfg.root = Some(bb(0));
+        let cfs = CFS::new(&cfg).unwrap();
+        let sequence = cfs.get_tree().unwrap();
+        assert_eq!(sequence.block_type(), BlockType::Sequence);
+        assert_eq!(sequence.len(), amount as usize + 2);
+        assert_eq!(cfs.stats().count(BlockType::IfThen), amount as usize);
+        assert_eq!(cfs.stats().count(BlockType::SelfLooping), 1);
+    }
+
+    #[test]
+    fn nat_loop_return_while() {
+        let cfg = create_cfg! {
+            0 => [1],
+            1 => [2, 6],
+            2 => [3, 6],
+            3 => [6, 4],
+            4 => [5, 8],
+            5 => [8, 1],
+            6 => [7],
+            7 => [8],
+            8 => []
+        };
+        let cfs = CFS::new(&cfg).unwrap();
         let sequence = cfs.get_tree().unwrap();
         assert_eq!(sequence.block_type(), BlockType::Sequence);
-        assert_eq!(sequence.len(), 3);
-        assert_eq!(sequence.children()[1].block_type(), BlockType::DoWhile);
+        assert_eq!(sequence.len(), 5);
+        assert_eq!(sequence.children()[1].block_type(), BlockType::While);
         assert_eq!(sequence.depth(), 3);
     }
 
     #[test]
-    fn nat_loop_return_while() {
+    fn nat_loop_exits() {
         let cfg = create_cfg! {
             0 => [1],
             1 => [2, 6],
@@ -1612,12 +5062,156 @@ mod tests {
             7 => [8],
             8 => []
         };
-        let cfs = CFS::new(&cfg);
+        let cfs = CFS::new(&cfg).unwrap();
         let sequence = cfs.get_tree().unwrap();
-        assert_eq!(sequence.block_type(), BlockType::Sequence);
-        assert_eq!(sequence.len(), 5);
-        assert_eq!(sequence.children()[1].block_type(), BlockType::While);
-        assert_eq!(sequence.depth(), 3);
+        let whileb = &sequence.children()[1];
+        let bb = |offset| BasicBlock { offset, length: 1 };
+        let expected = vec![
+            (bb(1), bb(6)),
+            (bb(2), bb(6)),
+            (bb(3), bb(6)),
+            (bb(4), bb(8)),
+            (bb(5), bb(8)),
+        ];
+        assert_eq!(whileb.exits(), expected.as_slice());
+        assert!(sequence.exits().is_empty());
+    }
+
+    #[test]
+    fn removed_edges() {
+        let cfg = create_cfg! { 0 => [1], 1 => [2, 5], 2 => [3], 3 => [4, 5], 4 => [1], 5 => [] };
+        let cfs = CFS::new(&cfg).unwrap();
+        let bb = |offset| BasicBlock { offset, length: 1 };
+        assert_eq!(cfs.removed_edges(), &[(bb(3), bb(5))]);
+        let cfg = create_cfg! { 0 => [1], 1 => [2, 3], 2 => [1], 3 => [] };
+        assert!(CFS::new(&cfg).unwrap().removed_edges().is_empty());
+    }
+
+    #[test]
+    fn deterministic_output() {
+        // cases where the visit order of hash-based collections used to leak into the tree
+        let interval = create_cfg! { 0 => [1, 2], 1 => [2, 3], 2 => [3, 2], 3 => [] };
+        let equidistant_exits = create_cfg! {
+            0 => [5], 1 => [0], 2 => [2, 11], 3 => [4, 7], 4 => [5, 7, 9], 5 => [6], 6 => [7, 4],
+            7 => [8], 8 => [9], 9 => [9, 8], 10 => [11], 11 => []
+        };
+        for cfg in [interval, equidistant_exits] {
+            let expected = CFS::new(&cfg).unwrap();
+            for _ in 0..16 {
+                let actual = CFS::new(&cfg).unwrap();
+                assert_eq!(actual.get_tree(), expected.get_tree());
+                assert_eq!(actual.removed_edges(), expected.removed_edges());
+            }
+        }
+    }
+
+    #[test]
+    fn budget_iterations() {
+        let cfg = create_cfg! { 0 => [1, 2], 1 => [3], 2 => [3], 3 => [4], 4 => [] };
+        let needed = CFS::new(&cfg).unwrap().stats().iterations();
+        let builder = CfsBuilder::new().max_iterations(needed);
+        assert_eq!(
+            builder.try_build(&cfg).unwrap().get_tree(),
+            CFS::new(&cfg).unwrap().get_tree()
+        );
+        let builder = CfsBuilder::new().max_iterations(needed - 1);
+        assert!(matches!(builder.try_build(&cfg), Err(Error::Budget)));
+        let cfs = builder.build(&cfg).unwrap();
+        assert!(!cfs.result().is_complete());
+        assert_eq!(cfs.stats().iterations(), needed - 1);
+        assert_eq!(
+            cfs.get_tree().unwrap().block_type(),
+            BlockType::ImproperRegion
+        );
+    }
+
+    #[test]
+    fn budget_size() {
+        let cfg = create_cfg! { 0 => [1, 2], 1 => [3], 2 => [3], 3 => [] };
+        assert!(CfsBuilder::new().max_size(4).try_build(&cfg).is_ok());
+        let builder = CfsBuilder::new().max_size(3);
+        assert!(matches!(builder.try_build(&cfg), Err(Error::Budget)));
+        let cfs = builder.build(&cfg).unwrap();
+        assert_eq!(cfs.stats().iterations(), 0);
+        let tree = cfs.get_tree().unwrap();
+        assert_eq!(tree.block_type(), BlockType::ImproperRegion);
+        assert_eq!(tree.len(), 4);
+    }
+
+    // cancels the token the first time it is attempted, without reducing anything.
+    struct Canceller(CancellationToken);
+
+    impl Reduction for Canceller {
+        fn reduce(
+            &self,
+            _: &StructureBlock,
+            _: &DirectedGraph<StructureBlock>,
+            _: &HashMap<StructureBlock, HashSet<StructureBlock>>,
+            _: &HashMap<StructureBlock, bool>,
+        ) -> Option<(StructureBlock, Option<StructureBlock>)> {
+            self.0.cancel();
+            None
+        }
+    }
+
+    #[test]
+    fn cancellation() {
+        let cfg = create_cfg! { 0 => [1, 2], 1 => [3], 2 => [3], 3 => [4], 4 => [] };
+        let token = CancellationToken::new();
+        let builder = CfsBuilder::new()
+            .with_reduction(Canceller(token.clone()))
+            .with_cancellation(token.clone());
+        // cancelled while structuring
+        let cfs = builder.build(&cfg).unwrap();
+        assert!(token.is_cancelled());
+        assert!(!cfs.result().is_complete());
+        assert!(cfs.stats().iterations() <= 1);
+        assert!(matches!(builder.try_build(&cfg), Err(Error::Cancelled)));
+        // cancelled before starting
+        let cfss = builder.build_all(vec![cfg.clone(), cfg]);
+        assert!(cfss.iter().all(|cfs| matches!(cfs, Err(Error::Cancelled))));
+    }
+
+    #[test]
+    fn condition_polarity() {
+        // edges are in (fallthrough, jump taken) order
+        let cfg = create_cfg! { 0 => [1, 2], 1 => [2], 2 => [] };
+        let tree = CFS::new(&cfg).unwrap().get_tree().unwrap();
+        assert_eq!(tree.children()[0].condition_polarity(), Some(false));
+        let cfg = create_cfg! { 0 => [2, 1], 1 => [2], 2 => [] };
+        let tree = CFS::new(&cfg).unwrap().get_tree().unwrap();
+        assert_eq!(tree.children()[0].condition_polarity(), Some(true));
+        let cfg = create_cfg! { 0 => [1, 2], 1 => [3], 2 => [3], 3 => [] };
+        let tree = CFS::new(&cfg).unwrap().get_tree().unwrap();
+        assert_eq!(tree.children()[0].block_type(), BlockType::IfThenElse);
+        assert_eq!(tree.children()[0].condition_polarity(), Some(false));
+        let cfg = create_cfg! { 0 => [1], 1 => [2, 3], 2 => [1], 3 => [] };
+        let tree = CFS::new(&cfg).unwrap().get_tree().unwrap();
+        assert_eq!(tree.children()[1].block_type(), BlockType::While);
+        assert_eq!(tree.children()[1].condition_polarity(), Some(false));
+        assert_eq!(tree.condition_polarity(), None);
+        // short-circuit conditions have no polarity
+        let cfg = create_cfg! { 0 => [1, 3], 1 => [2, 3], 2 => [3], 3 => [] };
+        let tree = CFS::new(&cfg).unwrap().get_tree().unwrap();
+        assert_eq!(tree.children()[0].block_type(), BlockType::IfThen);
+        assert_eq!(tree.children()[0].condition_polarity(), None);
+    }
+
+    #[test]
+    fn structure_at() {
+        let cfg = create_cfg! { 0 => [1], 1 => [2, 5], 2 => [3, 4], 3 => [4], 4 => [1], 5 => [] };
+        let cfs = CFS::new(&cfg).unwrap();
+        assert_eq!(
+            cfs.structure_at(0).unwrap().block_type(),
+            BlockType::Sequence
+        );
+        let body = cfs.structure_at(3).unwrap();
+        assert_eq!(body.block_type(), BlockType::IfThen);
+        assert_eq!(cfs.structure_at(2), Some(body));
+        assert_eq!(cfs.structure_at(1).unwrap().block_type(), BlockType::While);
+        assert!(cfs.structure_at(6).is_none());
+        let single = create_cfg! { 0 => [] };
+        assert!(CFS::new(&single).unwrap().structure_at(0).is_none());
     }
 
     #[test]
@@ -1633,7 +5227,7 @@ mod tests {
             7 => [8],
             8 => []
         };
-        let cfs = CFS::new(&cfg);
+        let cfs = CFS::new(&cfg).unwrap();
         let sequence = cfs.get_tree().unwrap();
         assert_eq!(sequence.block_type(), BlockType::Sequence);
         assert_eq!(sequence.len(), 5);
@@ -1656,7 +5250,7 @@ mod tests {
             8 => [9],
             9 => []
         };
-        let cfs = CFS::new(&cfg);
+        let cfs = CFS::new(&cfg).unwrap();
         let sequence = cfs.get_tree().unwrap();
         assert_eq!(sequence.block_type(), BlockType::Sequence);
         assert_eq!(sequence.len(), 5);
@@ -1686,14 +5280,14 @@ mod tests {
              5 => [ 6, 12],
              7 => [ 8, 11],
         };
-        let cfs = CFS::new(&cfg);
+        let cfs = CFS::new(&cfg).unwrap();
         assert!(cfs.get_tree().is_some());
     }
 
     #[test]
     fn proper_interval_mini() {
         let cfg = create_cfg! { 0 => [1, 2], 1 => [2, 3], 2 => [3], 3 => [] };
-        let cfs = CFS::new(&cfg);
+        let cfs = CFS::new(&cfg).unwrap();
         let sequence = cfs.get_tree().unwrap();
         assert_eq!(sequence.block_type(), BlockType::Sequence);
         assert_eq!(
@@ -1707,7 +5301,7 @@ mod tests {
         let cfg = create_cfg! {
             0 => [1, 2], 1 => [3, 4], 2 => [4], 3 => [5], 4 => [5], 5 => []
         };
-        let cfs = CFS::new(&cfg);
+        let cfs = CFS::new(&cfg).unwrap();
         let sequence = cfs.get_tree().unwrap();
         assert_eq!(sequence.block_type(), BlockType::Sequence);
         assert_eq!(
@@ -1721,7 +5315,7 @@ mod tests {
         let cfg = create_cfg! {
             0 => [1, 2], 1 => [3], 2 => [3, 4], 3 => [5], 4 => [5], 5 => []
         };
-        let cfs = CFS::new(&cfg);
+        let cfs = CFS::new(&cfg).unwrap();
         let sequence = cfs.get_tree().unwrap();
         assert_eq!(sequence.block_type(), BlockType::Sequence);
         assert_eq!(
@@ -1735,7 +5329,7 @@ mod tests {
         let cfg = create_cfg! {
             0 => [1, 2], 1 => [3, 4], 2 => [3, 4], 3 => [5], 4 => [5], 5 => []
         };
-        let cfs = CFS::new(&cfg);
+        let cfs = CFS::new(&cfg).unwrap();
         let sequence = cfs.get_tree().unwrap();
         assert_eq!(sequence.block_type(), BlockType::Sequence);
         assert_eq!(
@@ -1758,7 +5352,7 @@ mod tests {
             6 => [7],
             7 => [],
         };
-        let cfs = CFS::new(&cfg);
+        let cfs = CFS::new(&cfg).unwrap();
         let sequence = cfs.get_tree().unwrap();
         assert_eq!(sequence.block_type(), BlockType::Sequence);
         assert_eq!(
@@ -1772,14 +5366,19 @@ mod tests {
         let cfg = create_cfg! {
             0 => [1, 2], 1 => [3, 4], 2 => [4, 5], 3 => [6], 4 => [6], 5 => [6], 6 => []
         };
-        let cfs = CFS::new(&cfg);
-        assert!(cfs.get_tree().is_none());
+        let cfs = CFS::new(&cfg).unwrap();
+        let tree = cfs.get_tree().unwrap();
+        assert_eq!(tree.block_type(), BlockType::Sequence);
+        let region = &tree.children()[0];
+        assert_eq!(region.block_type(), BlockType::ImproperRegion);
+        assert_eq!(region.len(), 6);
+        assert_eq!(tree.children()[1].offset(), 6);
     }
 
     #[test]
     fn improper_interval() {
         let cfg = create_cfg! { 0 => [1, 2], 1 => [2, 3], 2 => [1 ,3], 3 => [] };
-        let cfs = CFS::new(&cfg);
+        let cfs = CFS::new(&cfg).unwrap();
         let sequence = cfs.get_tree().unwrap();
         assert_eq!(sequence.block_type(), BlockType::Sequence);
         assert_eq!(
@@ -1788,6 +5387,750 @@ mod tests {
         );
     }
 
+    #[test]
+    fn irreducible_loop_split() {
+        // 1, 2 and 3 form a loop with two entry points: 1 and 3
+        let cfg = create_cfg! { 0 => [1, 3], 1 => [2], 2 => [3, 4], 3 => [1], 4 => [] };
+        let cfs = CFS::new(&cfg).unwrap();
+        assert!(cfs.get_tree().is_some());
+        assert_eq!(cfs.duplicated(), 1);
+    }
+
+    #[test]
+    fn refine_conditions_interval() {
+        let cfg = create_cfg! { 0 => [1, 2], 1 => [2, 3], 2 => [3], 3 => [] };
+        let bb = |offset| BasicBlock { offset, length: 1 };
+        let cfs = CfsBuilder::new().refine_conditions().build(&cfg).unwrap();
+        let tree = cfs.get_tree().unwrap();
+        let refined = &tree.children()[0];
+        assert_eq!(refined.block_type(), BlockType::Sequence);
+        let children = refined.children();
+        assert_eq!(children.len(), 3);
+        assert_eq!(children[0].offset(), 0);
+        assert_eq!(children[1].block_type(), BlockType::Conditional);
+        assert_eq!(children[1].offset(), 1);
+        assert_eq!(children[1].reaching_condition(), [vec![(bb(0), false)]]);
+        assert_eq!(children[2].block_type(), BlockType::Conditional);
+        let expected = [vec![(bb(0), false), (bb(1), false)], vec![(bb(0), true)]];
+        assert_eq!(children[2].reaching_condition(), expected);
+        assert!(cfs
+            .trace()
+            .iter()
+            .any(|entry| entry.pattern() == "Conditional"));
+    }
+
+    #[test]
+    fn refine_conditions_region() {
+        let cfg = create_cfg! {
+            0 => [1, 2], 1 => [3, 4], 2 => [4, 5], 3 => [6], 4 => [6], 5 => [6], 6 => []
+        };
+        let cfs = CfsBuilder::new().refine_conditions().build(&cfg).unwrap();
+        let tree = cfs.get_tree().unwrap();
+        assert!(tree
+            .iter_preorder()
+            .all(|node| node.block_type() != BlockType::ImproperRegion));
+        assert!(tree
+            .iter_preorder()
+            .any(|node| node.block_type() == BlockType::Conditional));
+        let mut bbs = tree.basic_blocks();
+        bbs.sort_unstable();
+        let expected = (0..7)
+            .map(|offset| BasicBlock { offset, length: 1 })
+            .collect::<Vec<_>>();
+        assert_eq!(bbs, expected);
+    }
+
+    #[test]
+    fn refine_conditions_no_duplication() {
+        let cfg = create_cfg! { 0 => [1, 3], 1 => [2], 2 => [3, 4], 3 => [1], 4 => [] };
+        let cfs = CfsBuilder::new().refine_conditions().build(&cfg).unwrap();
+        assert_eq!(cfs.duplicated(), 0);
+        assert!(cfs.get_tree().is_some());
+    }
+
+    #[test]
+    fn structure_region_loop() {
+        let cfg = create_cfg! {
+            0 => [1, 5], 1 => [2], 2 => [3, 4], 3 => [2], 4 => [5], 5 => []
+        };
+        let bb = |offset| BasicBlock { offset, length: 1 };
+        let region = cfs::structure_region(&cfg, &bb(2), &bb(4)).unwrap();
+        assert_eq!(region.block_type(), BlockType::Sequence);
+        // the loop head has predecessors, so an entry point is added
+        assert!(region.basic_blocks()[0].is_entry_point());
+        assert_eq!(region.children()[1].block_type(), BlockType::While);
+        assert_eq!(region.basic_blocks()[1..], [bb(2), bb(3), bb(4)]);
+        let region = cfs::structure_region(&cfg, &bb(1), &bb(5)).unwrap();
+        assert_eq!(region.offset(), 1);
+        assert_eq!(region.basic_blocks().len(), 5);
+    }
+
+    #[test]
+    fn structure_region_invalid() {
+        let cfg = create_cfg! { 0 => [1, 2], 1 => [2, 3], 2 => [3], 3 => [4], 4 => [] };
+        let bb = |offset| BasicBlock { offset, length: 1 };
+        // 2 is also reached from 0
+        assert!(cfs::structure_region(&cfg, &bb(1), &bb(3)).is_none());
+        // 4 returns without reaching 2
+        assert!(cfs::structure_region(&cfg, &bb(0), &bb(2)).is_none());
+        assert!(cfs::structure_region(&cfg, &bb(0), &bb(5)).is_none());
+        assert!(cfs::structure_region(&cfg, &bb(3), &bb(3)).is_none());
+        assert!(cfs::structure_region(&cfg, &bb(0), &bb(3)).is_some());
+    }
+
+    #[test]
+    fn same_structure_diff() {
+        let ifelse = create_cfg! { 0 => [1, 2], 1 => [3], 2 => [3], 3 => [] };
+        let ifthen = create_cfg! { 0 => [1, 2], 1 => [2], 2 => [3], 3 => [] };
+        let chain = create_cfg! { 0 => [1], 1 => [2, 3], 2 => [4], 3 => [4], 4 => [] };
+        let a = CFS::new(&ifelse).unwrap();
+        assert!(cfs::same_structure(&a, &CFS::new(&ifelse).unwrap()));
+        assert_eq!(cfs::structure_diff(&a, &a), None);
+        let b = CFS::new(&ifthen).unwrap();
+        assert!(!cfs::same_structure(&a, &b));
+        assert_eq!(
+            cfs::structure_diff(&a, &b).unwrap(),
+            "root: 2 children vs 3 children"
+        );
+        let c = CFS::new(&chain).unwrap();
+        assert_eq!(
+            cfs::structure_diff(&b, &c).unwrap(),
+            "root.children[0]: If-Then vs Basic Block"
+        );
+        let none = CFS::new(&empty()).unwrap();
+        assert!(cfs::same_structure(&none, &CFS::new(&empty()).unwrap()));
+        assert_eq!(
+            cfs::structure_diff(&none, &a).unwrap(),
+            "root: None vs Sequence"
+        );
+    }
+
+    #[test]
+    fn abnormal_entry_annotated() {
+        // 1, 2 and 3 form a loop with two entry points: 1 and 3
+        let cfg = create_cfg! { 0 => [1, 3], 1 => [2], 2 => [3, 4], 3 => [1], 4 => [] };
+        let bb = |offset| BasicBlock { offset, length: 1 };
+        let cfs = CfsBuilder::new()
+            .annotate_abnormal_entries()
+            .build(&cfg)
+            .unwrap();
+        assert_eq!(cfs.duplicated(), 0);
+        assert_eq!(cfs.removed_edges(), [(bb(0), bb(3))]);
+        let tree = cfs.get_tree().unwrap();
+        let markers = tree
+            .iter_preorder()
+            .filter(|node| node.block_type() == BlockType::AbnormalEntry)
+            .collect::<Vec<_>>();
+        assert_eq!(markers.len(), 1);
+        assert_eq!(markers[0].offset(), 0);
+        assert_eq!(markers[0].exits(), [(bb(0), bb(3))]);
+        let parent = tree
+            .iter_preorder()
+            .find(|node| node.children().contains(markers[0]))
+            .unwrap();
+        assert_eq!(parent.block_type(), BlockType::DoWhile);
+        // reducible loops are not affected
+        let cfg = create_cfg! { 0 => [1], 1 => [2, 3], 2 => [1], 3 => [] };
+        let cfs = CfsBuilder::new()
+            .annotate_abnormal_entries()
+            .build(&cfg)
+            .unwrap();
+        assert!(cfs.removed_edges().is_empty());
+        assert!(cfs
+            .get_tree()
+            .unwrap()
+            .structural_equality(&CFS::new(&cfg).unwrap().get_tree().unwrap()));
+    }
+
+    #[test]
+    fn switch_loop() {
+        let cfg = create_cfg! {
+            0 => [1], 1 => [5, 2, 3, 4], 2 => [1], 3 => [1], 4 => [1], 5 => []
+        };
+        let cfs = CFS::new(&cfg).unwrap();
+        let tree = cfs.get_tree().unwrap();
+        assert_eq!(tree.children().len(), 3);
+        let whileb = &tree.children()[1];
+        assert_eq!(whileb.block_type(), BlockType::SwitchLoop);
+        assert_eq!(whileb.children().len(), 1);
+        let switch = &whileb.children()[0];
+        assert_eq!(switch.block_type(), BlockType::Switch);
+        assert_eq!(switch.children().len(), 4);
+        assert_eq!(tree.children()[2].offset(), 5);
+        let expanded = tree.to_cfg().unwrap();
+        assert_eq!(expanded.edges, cfg.edges);
+    }
+
+    #[test]
+    fn switch_loop_join() {
+        let cfg = create_cfg! {
+            0 => [1], 1 => [5, 2, 3, 4], 2 => [6], 3 => [6], 4 => [1], 6 => [1], 5 => []
+        };
+        let bb = |offset| BasicBlock { offset, length: 1 };
+        let cfs = CFS::new(&cfg).unwrap();
+        let tree = cfs.get_tree().unwrap();
+        let whileb = &tree.children()[1];
+        assert_eq!(whileb.block_type(), BlockType::SwitchLoop);
+        let body = &whileb.children()[0];
+        assert_eq!(body.block_type(), BlockType::Sequence);
+        assert_eq!(body.children()[0].block_type(), BlockType::Switch);
+        assert_eq!(body.children()[1].offset(), 6);
+        assert_eq!(whileb.exits(), [(bb(1), bb(5))]);
+    }
+
+    #[test]
+    fn fidelity() {
+        let cfg = create_cfg! { 0 => [1], 1 => [2, 3], 2 => [1], 3 => [] };
+        assert_eq!(CFS::new(&cfg).unwrap().fidelity(), 1.0);
+        let cfg = create_cfg! { 0 => [1], 1 => [2, 5], 2 => [3], 3 => [4, 5], 4 => [1], 5 => [] };
+        assert!((CFS::new(&cfg).unwrap().fidelity() - 6.0 / 7.0).abs() < 1e-9);
+        let cfg = create_cfg! { 0 => [1, 3], 1 => [2], 2 => [3, 4], 3 => [1], 4 => [] };
+        let fidelity = CFS::new(&cfg).unwrap().fidelity();
+        assert!(fidelity > 0.0 && fidelity < 1.0);
+        let cfg = create_cfg! { 0 => [1, 2], 1 => [1], 2 => [] };
+        let fidelity = CFS::new(&cfg).unwrap().fidelity();
+        assert!(fidelity > 0.0 && fidelity <= 1.0 / 3.0 + 1e-9);
+        assert_eq!(CFS::new(&empty()).unwrap().fidelity(), 1.0);
+    }
+
+    #[test]
+    fn improper_region_fallback() {
+        // 1 is an infinite loop, so the function has no single exit
+        let cfg = create_cfg! { 0 => [1, 2], 1 => [1], 2 => [] };
+        let cfs = CFS::new(&cfg).unwrap();
+        let tree = cfs.get_tree().unwrap();
+        assert_eq!(tree.block_type(), BlockType::Sequence);
+        // the exit is not part of the region
+        let region = &tree.children()[0];
+        assert_eq!(region.block_type(), BlockType::ImproperRegion);
+        let mut bbs = region.basic_blocks();
+        bbs.retain(|bb| !bb.is_sink() && !bb.is_entry_point());
+        bbs.sort();
+        let offsets = bbs.into_iter().map(|bb| bb.offset).collect::<Vec<_>>();
+        assert_eq!(offsets, vec![0, 1]);
+        assert_eq!(tree.children()[1].offset(), 2);
+    }
+
+    #[test]
+    fn improper_region_minimal() {
+        let cfg = create_cfg! {
+            0 => [1], 1 => [2, 3], 2 => [4, 5], 3 => [5, 6], 4 => [7], 5 => [7], 6 => [7],
+            7 => [8, 9], 8 => [9], 9 => []
+        };
+        let cfs = CFS::new(&cfg).unwrap();
+        assert!(!cfs.result().is_complete());
+        let tree = cfs.get_tree().unwrap();
+        let regions = tree
+            .iter_preorder()
+            .filter(|node| node.block_type() == BlockType::ImproperRegion)
+            .collect::<Vec<_>>();
+        assert_eq!(regions.len(), 1);
+        let mut offsets = regions[0]
+            .basic_blocks()
+            .into_iter()
+            .map(|bb| bb.offset)
+            .collect::<Vec<_>>();
+        offsets.sort_unstable();
+        assert_eq!(offsets, (1..7).collect::<Vec<_>>());
+        // the blocks following the region are still structured
+        assert!(tree
+            .iter_preorder()
+            .any(|node| node.block_type() == BlockType::IfThen));
+    }
+
+    #[test]
+    fn partial_result() {
+        let cfg = create_cfg! {
+            0 => [1, 2], 1 => [3, 4], 2 => [4, 5], 3 => [6], 4 => [6], 5 => [6], 6 => [7], 7 => []
+        };
+        let cfs = CFS::new(&cfg).unwrap();
+        let result = cfs.result();
+        assert!(!result.is_complete());
+        assert!(result.get_graph().len() > 1);
+        let partial = result.partial_trees();
+        assert!(!partial.is_empty());
+        assert!(partial
+            .iter()
+            .any(|tree| tree.block_type() == BlockType::Sequence));
+        let irreducible = result.irreducible_nodes();
+        assert!(!irreducible.is_empty());
+        assert!(irreducible
+            .iter()
+            .all(|node| node.block_type() == BlockType::Basic));
+    }
+
+    #[test]
+    fn complete_result() {
+        let cfg = create_cfg! { 0 => [1], 1 => [2], 2 => [] };
+        let cfs = CFS::new(&cfg).unwrap();
+        let result = cfs.result();
+        assert!(result.is_complete());
+        assert_eq!(result.get_graph().len(), 1);
+        assert!(result.irreducible_nodes().is_empty());
+        assert_eq!(result.partial_trees().len(), 1);
+    }
+
+    #[test]
+    fn to_dot_tree_unique_ids() {
+        let cfg = create_cfg! { 0 => [1, 2], 1 => [2], 2 => [] };
+        let cfs = CFS::new(&cfg).unwrap();
+        let dot = cfs.to_dot_tree();
+        let tree = cfs.get_tree().unwrap();
+        let mut count = 0;
+        let mut stack = vec![tree];
+        while let Some(node) = stack.pop() {
+            count += 1;
+            stack.extend(node.children().iter().cloned());
+        }
+        let declared = dot.lines().filter(|l| l.contains("[label=")).count();
+        let edges = dot.lines().filter(|l| l.contains("->")).count();
+        assert_eq!(declared, count);
+        assert_eq!(edges, count - 1);
+        assert!(dot.contains("[label=\"If-Then\"];"));
+        assert!(dot.contains("[label=\"0x1..0x2\";shape=\"box\"];"));
+    }
+
+    #[test]
+    fn to_dot_clusters() {
+        let cfg = create_cfg! { 0 => [1, 2], 1 => [2], 2 => [] };
+        let cfs = CFS::new(&cfg).unwrap();
+        let dot = cfs.to_dot();
+        assert!(dot.starts_with("digraph"));
+        assert!(dot.contains("label=\"If-Then\";"));
+        assert_eq!(
+            dot.matches("subgraph cluster_").count(),
+            dot.matches("label=\"").count()
+        );
+    }
+
+    fn nest(bt: BlockType, children: Vec<StructureBlock>) -> StructureBlock {
+        StructureBlock::from(Arc::new(NestedBlock::new(bt, children)))
+    }
+
+    fn leaf(offset: u64) -> StructureBlock {
+        StructureBlock::from(BasicBlock { offset, length: 1 })
+    }
+
+    #[test]
+    fn tree_distance_identical() {
+        let a = nest(
+            BlockType::Sequence,
+            vec![nest(BlockType::SelfLooping, vec![leaf(0)]), leaf(1)],
+        );
+        let b = nest(
+            BlockType::Sequence,
+            vec![nest(BlockType::SelfLooping, vec![leaf(5)]), leaf(7)],
+        );
+        assert_eq!(tree_distance(&a, &b), 0);
+    }
+
+    #[test]
+    fn tree_distance_insert_and_relabel() {
+        let a = nest(BlockType::Sequence, vec![leaf(0), leaf(1)]);
+        let b = nest(BlockType::Sequence, vec![leaf(0), leaf(1), leaf(2)]);
+        let c = nest(
+            BlockType::IfThen,
+            vec![leaf(0), nest(BlockType::SelfLooping, vec![leaf(1)])],
+        );
+        assert_eq!(tree_distance(&a, &b), 1);
+        assert_eq!(tree_distance(&b, &a), 1);
+        // relabel the root and insert the self loop
+        assert_eq!(tree_distance(&a, &c), 2);
+    }
+
+    #[test]
+    fn tree_distance_custom_costs() {
+        let a = nest(BlockType::Sequence, vec![leaf(0), leaf(1)]);
+        let b = nest(
+            BlockType::Sequence,
+            vec![leaf(0), nest(BlockType::SelfLooping, vec![leaf(1)])],
+        );
+        let c = nest(BlockType::IfThen, vec![leaf(0), leaf(1)]);
+        let costs = EditCosts::new().indel(BlockType::SelfLooping, 5);
+        // relabel the leaf as self loop and insert a new leaf
+        assert_eq!(tree_distance_with_costs(&a, &b, &costs), 2);
+        let costs = costs.relabel(10);
+        assert_eq!(tree_distance_with_costs(&a, &b, &costs), 5);
+        // delete and insert the root, as it is cheaper than relabelling
+        assert_eq!(tree_distance_with_costs(&a, &c, &costs), 2);
+        let costs = costs
+            .relabel(3)
+            .indel(BlockType::Sequence, 4)
+            .indel(BlockType::IfThen, 4);
+        assert_eq!(tree_distance_with_costs(&a, &c, &costs), 3);
+    }
+
+    #[test]
+    fn tree_similarity_weights_size() {
+        let whileb = || nest(BlockType::While, vec![leaf(1), leaf(2)]);
+        let a = nest(BlockType::Sequence, vec![leaf(0), whileb()]);
+        let b = nest(BlockType::Sequence, vec![leaf(0), leaf(3), whileb()]);
+        let c = nest(
+            BlockType::Sequence,
+            vec![leaf(0), nest(BlockType::IfThen, vec![leaf(1), leaf(2)])],
+        );
+        // same edit distance, but only b shares the loop with a
+        assert_eq!(tree_distance(&a, &b), tree_distance(&a, &c));
+        assert!(tree_similarity(&a, &b) > tree_similarity(&a, &c));
+        assert_eq!(tree_similarity(&a, &a), 1.0);
+        assert_eq!(tree_similarity(&b, &a), tree_similarity(&a, &b));
+        let d = nest(BlockType::SelfLooping, vec![nest(BlockType::Guard, vec![])]);
+        assert_eq!(tree_similarity(&c, &d), 0.0);
+    }
+
+    #[test]
+    fn tree_similarity_weights_rarity() {
+        let a = nest(BlockType::Sequence, vec![leaf(0), leaf(1), leaf(2)]);
+        let b = nest(
+            BlockType::Sequence,
+            vec![leaf(0), nest(BlockType::SelfLooping, vec![leaf(1)])],
+        );
+        let weights = SimilarityWeights::from_corpus([&a, &b]);
+        assert!(weights.weight(BlockType::SelfLooping) > weights.weight(BlockType::Sequence));
+        assert!(weights.weight(BlockType::Sequence) > weights.weight(BlockType::Basic));
+        // unseen types are as rare as the rarest ones
+        assert_eq!(
+            weights.weight(BlockType::While),
+            weights.weight(BlockType::SelfLooping)
+        );
+        let c = nest(
+            BlockType::IfThen,
+            vec![leaf(0), nest(BlockType::SelfLooping, vec![leaf(1)])],
+        );
+        let plain = tree_similarity(&b, &c);
+        let weights = SimilarityWeights::new().rarity(BlockType::SelfLooping, 10.0);
+        assert_eq!(tree_similarity_with_weights(&b, &b, &weights), 1.0);
+        assert!(tree_similarity_with_weights(&b, &c, &weights) > plain);
+    }
+
+    #[test]
+    fn builder_disable() {
+        let cfg = create_cfg! { 0 => [1, 2], 1 => [2], 2 => [] };
+        let tree = CFS::new(&cfg).unwrap().get_tree().unwrap();
+        assert_eq!(tree.children()[0].block_type(), BlockType::IfThen);
+        let cfs = CfsBuilder::new()
+            .disable(ReductionKind::IfThen)
+            .build(&cfg)
+            .unwrap();
+        let tree = cfs.get_tree().unwrap();
+        assert_eq!(tree.children()[0].block_type(), BlockType::ImproperRegion);
+        let cfs = CfsBuilder::new()
+            .disable(ReductionKind::IfThen)
+            .enable(ReductionKind::IfThen)
+            .build(&cfg)
+            .unwrap();
+        assert_eq!(
+            cfs.get_tree().unwrap(),
+            CFS::new(&cfg).unwrap().get_tree().unwrap()
+        );
+    }
+
+    #[test]
+    fn builder_order() {
+        let builder = CfsBuilder::new().order(&[
+            ReductionKind::Sequence,
+            ReductionKind::IfThen,
+            ReductionKind::Sequence,
+        ]);
+        assert_eq!(
+            builder.reductions(),
+            &[ReductionKind::Sequence, ReductionKind::IfThen]
+        );
+        let cfg = create_cfg! { 0 => [1], 1 => [1, 2], 2 => [] };
+        let cfs = builder.build(&cfg).unwrap();
+        assert_eq!(
+            cfs.get_tree().unwrap().children()[0].block_type(),
+            BlockType::ImproperRegion
+        );
+        assert_eq!(CfsBuilder::new().reductions(), &ReductionKind::all());
+    }
+
+    // recognizes the if-then pattern, as the built-in reduction would do, labeling it with the
+    // given type.
+    struct CustomIfThen(BlockType);
+
+    impl Reduction for CustomIfThen {
+        fn reduce(
+            &self,
+            node: &StructureBlock,
+            graph: &DirectedGraph<StructureBlock>,
+            preds: &HashMap<StructureBlock, HashSet<StructureBlock>>,
+            _: &HashMap<StructureBlock, bool>,
+        ) -> Option<(StructureBlock, Option<StructureBlock>)> {
+            let children = graph.neighbours(node);
+            if children.len() == 2
+                && graph.neighbours(&children[0]) == [children[1].clone()]
+                && preds.get(&children[0]).unwrap().len() == 1
+            {
+                let content = vec![node.clone(), children[0].clone()];
+                Some((nest(self.0, content), Some(children[1].clone())))
+            } else {
+                None
+            }
+        }
+    }
+
+    // always returns a block made of nodes not belonging to the graph.
+    struct InvalidReduction;
+
+    impl Reduction for InvalidReduction {
+        fn reduce(
+            &self,
+            _: &StructureBlock,
+            _: &DirectedGraph<StructureBlock>,
+            _: &HashMap<StructureBlock, HashSet<StructureBlock>>,
+            _: &HashMap<StructureBlock, bool>,
+        ) -> Option<(StructureBlock, Option<StructureBlock>)> {
+            Some((nest(BlockType::Sequence, vec![leaf(1000)]), None))
+        }
+    }
+
+    // replaces a node and its first child, with the node itself as successor.
+    struct CyclicReduction;
+
+    impl Reduction for CyclicReduction {
+        fn reduce(
+            &self,
+            node: &StructureBlock,
+            graph: &DirectedGraph<StructureBlock>,
+            _: &HashMap<StructureBlock, HashSet<StructureBlock>>,
+            _: &HashMap<StructureBlock, bool>,
+        ) -> Option<(StructureBlock, Option<StructureBlock>)> {
+            let child = graph.neighbours(node).first()?.clone();
+            let content = vec![node.clone(), child];
+            Some((nest(BlockType::Sequence, content), Some(node.clone())))
+        }
+    }
+
+    #[test]
+    fn builder_custom_reduction() {
+        let cfg = create_cfg! { 0 => [1, 2], 1 => [2], 2 => [] };
+        let expected = CFS::new(&cfg).unwrap().get_tree().unwrap();
+        let cfs = CfsBuilder::new()
+            .disable(ReductionKind::IfThen)
+            .with_reduction(CustomIfThen(BlockType::IfThen))
+            .build(&cfg)
+            .unwrap();
+        assert!(cfs.get_tree().unwrap().structural_equality(&expected));
+        let cfs = CfsBuilder::new()
+            .with_reduction(InvalidReduction)
+            .build(&cfg)
+            .unwrap();
+        assert_eq!(cfs.get_tree().unwrap(), expected);
+    }
+
+    #[test]
+    fn builder_inconsistent_reduction() {
+        let cfg = create_cfg! { 0 => [1, 2], 1 => [2], 2 => [] };
+        let cfs = CfsBuilder::new()
+            .with_reduction(CyclicReduction)
+            .build(&cfg);
+        let err = cfs.err().unwrap();
+        assert!(matches!(err, Error::Structuring(_)));
+        assert!(err.to_string().starts_with("inconsistent structuring"));
+    }
+
+    #[test]
+    fn missing_node_lookup() {
+        let preds = cfs::PredMap::new();
+        let err = cfs::preds_of(&preds, &leaf(0)).err().unwrap();
+        assert!(matches!(err, Error::Structuring(_)));
+    }
+
+    #[test]
+    fn trace_reductions() {
+        let cfg = create_cfg! { 0 => [1, 2], 1 => [2], 2 => [] };
+        let cfs = CFS::new(&cfg).unwrap();
+        let trace = cfs.trace();
+        assert_eq!(trace.len(), 2);
+        assert_eq!(trace[0].pattern(), "If-Then");
+        assert_eq!(trace[0].result().block_type(), BlockType::IfThen);
+        assert_eq!(trace[0].consumed().len(), 2);
+        assert_eq!(trace[0].consumed()[0], leaf(0));
+        assert_eq!(trace[1].pattern(), "Sequence");
+        assert_eq!(trace[1].result(), &cfs.get_tree().unwrap());
+        let cfs = CfsBuilder::new()
+            .disable(ReductionKind::IfThen)
+            .with_reduction(CustomIfThen(BlockType::IfThen))
+            .build(&cfg)
+            .unwrap();
+        assert_eq!(cfs.trace()[0].pattern(), "Custom");
+    }
+
+    #[test]
+    fn builder_arbitrary_graph() {
+        let graph = DirectedGraph {
+            root: Some('a'),
+            adjacency: HashMap::from([
+                ('a', vec!['b']),
+                ('b', vec!['c', 'f']),
+                ('c', vec!['d']),
+                ('d', vec!['e', 'f']),
+                ('e', vec!['b']),
+                ('f', vec![]),
+                ('z', vec!['a']),
+            ]),
+        };
+        let (cfs, nodes) = CfsBuilder::new().build_graph(&graph).unwrap();
+        let cfg = create_cfg! { 0 => [1], 1 => [2, 5], 2 => [3], 3 => [4, 5], 4 => [1], 5 => [] };
+        let expected = CFS::new(&cfg).unwrap().get_tree().unwrap();
+        let tree = cfs.get_tree().unwrap();
+        assert!(tree.structural_equality(&expected));
+        assert_eq!(nodes.len(), 6);
+        let labels = tree
+            .basic_blocks()
+            .iter()
+            .map(|bb| nodes[bb])
+            .collect::<String>();
+        assert_eq!(labels, "abcdef");
+        let mapped = tree.map_leaves(|bb| nodes[bb]);
+        assert_eq!(mapped.iter_preorder().count(), tree.iter_preorder().count());
+        assert!(mapped
+            .iter_preorder()
+            .flat_map(|node| node.exits())
+            .any(|exit| exit == &('d', 'f')));
+        let (empty, nodes) = CfsBuilder::new()
+            .build_graph(&DirectedGraph::<char>::default())
+            .unwrap();
+        assert!(empty.get_tree().is_none());
+        assert!(nodes.is_empty());
+    }
+
+    #[test]
+    fn custom_block_type() {
+        let cfg = create_cfg! { 0 => [1, 2], 1 => [2], 2 => [] };
+        let cfs = CfsBuilder::new()
+            .disable(ReductionKind::IfThen)
+            .with_reduction(CustomIfThen(BlockType::Custom(42)))
+            .build(&cfg)
+            .unwrap();
+        let tree = cfs.get_tree().unwrap();
+        assert_eq!(tree.children()[0].block_type(), BlockType::Custom(42));
+        assert_eq!(tree.to_sexpr(), "(seq (custom:42 b0 b1) b2)");
+        assert_eq!(tree.validate(), Ok(()));
+        let loaded = CFS::from_bytes(&cfs.to_bytes()).unwrap();
+        assert_eq!(loaded.get_tree().unwrap(), tree);
+        let other = CfsBuilder::new()
+            .disable(ReductionKind::IfThen)
+            .with_reduction(CustomIfThen(BlockType::Custom(43)))
+            .build(&cfg)
+            .unwrap();
+        assert_ne!(
+            other.get_tree().unwrap().structural_hash(),
+            tree.structural_hash()
+        );
+    }
+
+    #[test]
+    fn trace_duplication_and_fallback() {
+        let cfg = create_cfg! { 0 => [1, 3], 1 => [2], 2 => [3, 4], 3 => [1], 4 => [] };
+        let cfs = CFS::new(&cfg).unwrap();
+        assert!(cfs.trace().iter().any(|t| t.pattern() == "Duplicate"));
+        let cfg = create_cfg! { 0 => [1, 2], 1 => [1], 2 => [] };
+        let cfs = CFS::new(&cfg).unwrap();
+        let last = cfs.trace().last().unwrap();
+        assert_eq!(last.pattern(), "Improper Region");
+        assert_eq!(last.result(), &cfs.get_tree().unwrap());
+    }
+
+    #[test]
+    fn terminators() {
+        let stmts = vec![
+            Statement::new(0x10, StatementFamily::CMP, "cmp eax, 0"),
+            Statement::new(0x12, StatementFamily::CJMP, "je 0x16"),
+            Statement::new(0x14, StatementFamily::TRAP, "ud2"),
+            Statement::new(0x16, StatementFamily::ADD, "inc eax"),
+            Statement::new(0x18, StatementFamily::RET, "ret"),
+        ];
+        let cfg = CFG::new(&stmts, 0x19, Architecture::X86(64));
+        let cfs = CFS::new(&cfg).unwrap();
+        let tree = cfs.get_tree().unwrap();
+        assert_eq!(
+            tree.to_sexpr(),
+            "(seq (ifelse b10+4 (unreachable b14+2) (return b16+3)) sink)"
+        );
+        assert_eq!(tree.validate(), Ok(()));
+        let ret = &tree.children()[0].children()[2];
+        assert_eq!(ret.block_type(), BlockType::Return);
+        assert_eq!(ret.depth(), 0);
+        assert_eq!(tree.depth(), 2);
+        assert!(tree.to_pseudocode().contains("return;"));
+        let expanded = tree.to_cfg().unwrap();
+        for bb in cfg.edges.keys() {
+            assert_eq!(expanded.terminator(bb), cfg.terminator(bb));
+        }
+        let loaded = CFS::from_bytes(&cfs.to_bytes()).unwrap();
+        assert_eq!(loaded.get_cfg(), &cfg);
+    }
+
+    #[test]
+    fn snapshots() {
+        let cfg = create_cfg! { 0 => [1, 2], 1 => [2], 2 => [] };
+        assert!(CFS::new(&cfg).unwrap().snapshots().is_empty());
+        let cfs = CfsBuilder::new().record_snapshots().build(&cfg).unwrap();
+        let snapshots = cfs.snapshots();
+        assert_eq!(snapshots.len(), cfs.trace().len() + 1);
+        assert_eq!(
+            snapshots.iter().map(|s| s.len()).collect::<Vec<_>>(),
+            [3, 2, 1]
+        );
+        assert!(snapshots[1].adjacency.contains_key(cfs.trace()[0].result()));
+        assert_eq!(snapshots[2].root, cfs.get_tree());
+        let cfg = create_cfg! { 0 => [1, 2], 1 => [1], 2 => [] };
+        let cfs = CfsBuilder::new().record_snapshots().build(&cfg).unwrap();
+        let last = cfs.snapshots().last().unwrap();
+        assert_eq!(last.len(), 1);
+        assert_eq!(last.root, cfs.get_tree());
+    }
+
+    #[test]
+    fn stats() {
+        let cfg = create_cfg! { 0 => [1, 2], 1 => [2], 2 => [] };
+        let stats = CFS::new(&cfg).unwrap().stats().clone();
+        assert_eq!(stats.count(BlockType::Sequence), 1);
+        assert_eq!(stats.count(BlockType::IfThen), 1);
+        assert_eq!(stats.count(BlockType::Basic), 3);
+        assert_eq!(stats.count(BlockType::While), 0);
+        assert_eq!(stats.iterations(), 2);
+        assert_eq!(stats.removed_edges(), 0);
+        assert_eq!(stats.depth(), 2);
+        let cfg = create_cfg! { 0 => [1], 1 => [2, 5], 2 => [3], 3 => [4, 5], 4 => [1], 5 => [] };
+        let stats = CFS::new(&cfg).unwrap().stats().clone();
+        assert_eq!(stats.removed_edges(), 1);
+        assert_eq!(stats.count(BlockType::Break), 1);
+    }
+
+    #[test]
+    fn while_short_circuit() {
+        // while (1 && 2) { 3 }
+        let cfg = create_cfg! { 0 => [1], 1 => [4, 2], 2 => [4, 3], 3 => [1], 4 => [] };
+        let cfs = CFS::new(&cfg).unwrap();
+        let sequence = cfs.get_tree().unwrap();
+        let whileb = &sequence.children()[1];
+        assert_eq!(whileb.block_type(), BlockType::While);
+        assert_eq!(whileb.len(), 2);
+        let cond = &whileb.children()[0];
+        assert_eq!(cond.block_type(), BlockType::Condition);
+        assert_eq!(cond.children(), &[leaf(1), leaf(2)]);
+        assert_eq!(whileb.children()[1], leaf(3));
+        assert_eq!(cfs.stats().removed_edges(), 0);
+    }
+
+    #[test]
+    fn dowhile_short_circuit() {
+        // do { 1 } while (2 && 3)
+        let cfg = create_cfg! { 0 => [1], 1 => [2], 2 => [4, 3], 3 => [4, 1], 4 => [] };
+        let cfs = CFS::new(&cfg).unwrap();
+        let sequence = cfs.get_tree().unwrap();
+        let dowhile = &sequence.children()[1];
+        assert_eq!(dowhile.block_type(), BlockType::DoWhile);
+        assert_eq!(dowhile.children()[0], leaf(1));
+        let cond = &dowhile.children()[1];
+        assert_eq!(cond.block_type(), BlockType::Condition);
+        assert_eq!(cond.children(), &[leaf(2), leaf(3)]);
+        assert_eq!(cfs.stats().removed_edges(), 0);
+    }
+
     #[test]
     fn sequence_extension() {
         // some interesting stuff here:
@@ -1796,7 +6139,7 @@ mod tests {
         let cfg = create_cfg! {
             0 => [1, 2], 1 => [2], 2 => [3], 3 => [4, 6], 4 => [5, 6], 5 => [0], 6 => []
         };
-        let cfs = CFS::new(&cfg.add_entry_point());
+        let cfs = CFS::new(&cfg.add_entry_point()).unwrap();
         assert!(cfs.get_tree().is_some());
     }
 }
@@ -3,7 +3,6 @@ use crate::disasm::Statement;
 use fnv::FnvHashMap;
 use std::collections::{HashMap, HashSet};
 use std::hash::Hash;
-use std::{collections::hash_map::DefaultHasher, hash::Hasher};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 /// Contains all the binaries and function names belonging to the same clone class.
@@ -107,9 +106,7 @@ impl<'a> CFSComparator<'a> {
                     func_id: function_id,
                     structure: node,
                 };
-                let mut hasher = DefaultHasher::new();
-                node.structural_hash(&mut hasher);
-                let hash = hasher.finish();
+                let hash = node.structural_hash();
                 self.hashes
                     .entry(hash)
                     .and_modify(|e| e.push(candidate.clone()))
@@ -358,7 +355,7 @@ mod tests {
     fn structural_cloned_full() {
         let stmts = create_function();
         let cfg = CFG::new(&stmts, 0x6C, Architecture::X86(64)).add_sink();
-        let cfs = CFS::new(&cfg).get_tree().unwrap();
+        let cfs = CFS::new(&cfg).unwrap().get_tree().unwrap();
         let mut diff = CFSComparator::new(7);
         diff.insert(0, 10, &cfs);
         diff.insert(1, 11, &cfs);
@@ -376,7 +373,7 @@ mod tests {
     fn structural_cloned_partial() {
         let mut stmts = create_function();
         let cfg0 = CFG::new(&stmts, 0x6C, Architecture::X86(64)).add_sink();
-        let cfs0 = CFS::new(&cfg0).get_tree().unwrap();
+        let cfs0 = CFS::new(&cfg0).unwrap().get_tree().unwrap();
         let string_cache = create_string_cache();
         let mut diff = CFSComparator::new(2);
         diff.insert(0, 10, &cfs0);
@@ -387,7 +384,7 @@ mod tests {
         stmts[11] = Statement::new(0x2C, StatementFamily::NOP, "nop");
         stmts[12] = Statement::new(0x30, StatementFamily::NOP, "nop");
         let cfg1 = CFG::new(&stmts, 0x6C, Architecture::X86(64)).add_sink();
-        let cfs1 = CFS::new(&cfg1).get_tree().unwrap();
+        let cfs1 = CFS::new(&cfg1).unwrap().get_tree().unwrap();
         diff.insert(1, 11, &cfs1);
         let clones = diff.clones(&string_cache);
         assert_eq!(clones.len(), 2);
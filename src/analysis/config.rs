@@ -0,0 +1,494 @@
+use crate::analysis::diff::DEFAULT_THRESHOLD;
+use crate::analysis::sexpr::{keyword_type, type_keyword};
+use crate::analysis::{BlockType, CfsBuilder, ComparisonMode, ReductionKind, SimilarityWeights};
+use crate::disasm::{Architecture, Endianness, ExtractionConfig};
+use crate::Error;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Settings of every step of an analysis: the extraction of the [`CFG`](crate::analysis::CFG)s,
+/// their structuring and the comparison of the resulting trees.
+///
+/// The configuration can be stored with [`AnalysisConfig::to_file`] and read back with
+/// [`AnalysisConfig::from_file`], so an analysis can be repeated with the same settings. The
+/// textual form contains one `key = value` setting per line, and lines starting with `#` are
+/// comments:
+/// ```text
+/// # extraction, see ExtractionConfig
+/// arch = arm
+/// bits = 32
+/// endianness = big
+/// # structuring, see CfsBuilder
+/// max_iterations = 10000
+/// max_size = 5000
+/// reductions = Self Loop, Loop, If-Then, Guard, If-Then-Else, Sequence, Switch
+/// preserve_loops = false
+/// refine_conditions = true
+/// annotate_abnormal_entries = false
+/// record_timings = false
+/// # comparison, see BinaryDiff and SimilarityWeights
+/// mode = cross-architecture
+/// threshold = 0.6
+/// weight.default = 1
+/// weight.while = 2.5
+/// ```
+/// The reductions are written in priority order, with the names of [`ReductionKind`], and the
+/// weights use the keywords of
+/// [`StructureBlock::to_sexpr`](crate::analysis::StructureBlock::to_sexpr), or `basic` for the
+/// basic blocks. Missing settings keep their default value, and the `mode` is detected from the
+/// binaries if missing. The `arch` is the name of an [`Architecture`], like `x86` or `arm`.
+///
+/// There are no settings for normalization passes, as the extraction performs none: the
+/// [`CFG`](crate::analysis::CFG)s are structured as they are disassembled.
+/// # Examples
+/// Basic usage:
+/// ```
+/// use bincc::analysis::{AnalysisConfig, ComparisonMode};
+///
+/// let config = AnalysisConfig::new()
+///     .max_size(5000)
+///     .with_mode(ComparisonMode::CrossArchitecture);
+/// let text = config.to_string();
+/// let parsed = text.parse::<AnalysisConfig>().unwrap();
+///
+/// assert_eq!(parsed.mode(), Some(ComparisonMode::CrossArchitecture));
+/// assert_eq!(parsed.to_string(), text);
+/// ```
+#[derive(Debug, Clone)]
+#[cfg_attr(docsrs, doc(cfg(feature = "disasm")))]
+pub struct AnalysisConfig {
+    extraction: ExtractionConfig,
+    max_iterations: Option<usize>,
+    max_size: Option<usize>,
+    reductions: Vec<ReductionKind>,
+    preserve_loops: bool,
+    refine_conditions: bool,
+    annotate_entries: bool,
+    timings: bool,
+    mode: Option<ComparisonMode>,
+    threshold: f64,
+    weights: SimilarityWeights,
+}
+
+impl Default for AnalysisConfig {
+    fn default() -> Self {
+        AnalysisConfig {
+            extraction: ExtractionConfig::default(),
+            max_iterations: None,
+            max_size: None,
+            reductions: ReductionKind::all().to_vec(),
+            preserve_loops: false,
+            refine_conditions: false,
+            annotate_entries: false,
+            timings: false,
+            mode: None,
+            threshold: DEFAULT_THRESHOLD,
+            weights: SimilarityWeights::default(),
+        }
+    }
+}
+
+impl AnalysisConfig {
+    /// Creates the default configuration, equivalent to the defaults of each step.
+    pub fn new() -> AnalysisConfig {
+        AnalysisConfig::default()
+    }
+
+    /// Sets the options of the extraction, see
+    /// [`Binary::analyze_with`](crate::disasm::Binary::analyze_with).
+    pub fn with_extraction(mut self, extraction: ExtractionConfig) -> AnalysisConfig {
+        self.extraction = extraction;
+        self
+    }
+
+    /// Returns the options of the extraction.
+    pub fn extraction(&self) -> ExtractionConfig {
+        self.extraction
+    }
+
+    /// Limits the amount of reductions applied to a single [`CFG`](crate::analysis::CFG), see
+    /// [`CfsBuilder::max_iterations`].
+    pub fn max_iterations(mut self, iterations: usize) -> AnalysisConfig {
+        self.max_iterations = Some(iterations);
+        self
+    }
+
+    /// Limits the amount of blocks of the [`CFG`](crate::analysis::CFG)s that are structured,
+    /// see [`CfsBuilder::max_size`].
+    pub fn max_size(mut self, nodes: usize) -> AnalysisConfig {
+        self.max_size = Some(nodes);
+        self
+    }
+
+    /// Sets the enabled reductions and their priority, see [`CfsBuilder::order`].
+    pub fn order(mut self, reductions: &[ReductionKind]) -> AnalysisConfig {
+        self.reductions = CfsBuilder::new().order(reductions).reductions().to_vec();
+        self
+    }
+
+    /// Returns the enabled reductions, in priority order.
+    pub fn reductions(&self) -> &[ReductionKind] {
+        &self.reductions
+    }
+
+    /// Keeps the loops with multiple exits, see [`CfsBuilder::preserve_loops`].
+    pub fn preserve_loops(mut self) -> AnalysisConfig {
+        self.preserve_loops = true;
+        self
+    }
+
+    /// Replaces the unstructured regions with conditional blocks, see
+    /// [`CfsBuilder::refine_conditions`].
+    pub fn refine_conditions(mut self) -> AnalysisConfig {
+        self.refine_conditions = true;
+        self
+    }
+
+    /// Marks the jumps entering a loop in the middle, see
+    /// [`CfsBuilder::annotate_abnormal_entries`].
+    pub fn annotate_abnormal_entries(mut self) -> AnalysisConfig {
+        self.annotate_entries = true;
+        self
+    }
+
+    /// Measures the time spent structuring each function, see [`CfsBuilder::record_timings`].
+    ///
+    /// When comparing two binaries, the time spent comparing each pair of functions is measured
+    /// as well, see [`BinaryDiff::timings`](crate::analysis::BinaryDiff::timings).
+    pub fn record_timings(mut self) -> AnalysisConfig {
+        self.timings = true;
+        self
+    }
+
+    /// Returns true if the time spent in each phase of the analysis is measured.
+    pub fn timings(&self) -> bool {
+        self.timings
+    }
+
+    /// Returns a [`CfsBuilder`] structuring the [`CFG`](crate::analysis::CFG)s with this
+    /// configuration.
+    pub fn cfs_builder(&self) -> CfsBuilder {
+        let mut builder = CfsBuilder::new().order(&self.reductions);
+        if let Some(iterations) = self.max_iterations {
+            builder = builder.max_iterations(iterations);
+        }
+        if let Some(nodes) = self.max_size {
+            builder = builder.max_size(nodes);
+        }
+        if self.preserve_loops {
+            builder = builder.preserve_loops();
+        }
+        if self.refine_conditions {
+            builder = builder.refine_conditions();
+        }
+        if self.annotate_entries {
+            builder = builder.annotate_abnormal_entries();
+        }
+        if self.timings {
+            builder = builder.record_timings();
+        }
+        builder
+    }
+
+    /// Sets the signals used to compare the functions of two binaries.
+    ///
+    /// By default, the mode is detected from the architecture of the binaries, see
+    /// [`BinaryDiff::with_threshold`](crate::analysis::BinaryDiff::with_threshold).
+    pub fn with_mode(mut self, mode: ComparisonMode) -> AnalysisConfig {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Returns the signals used to compare the functions of two binaries, if forced.
+    pub fn mode(&self) -> Option<ComparisonMode> {
+        self.mode
+    }
+
+    /// Sets the minimum similarity of two functions paired by their structure alone.
+    ///
+    /// The default threshold is `0.5`.
+    pub fn with_threshold(mut self, threshold: f64) -> AnalysisConfig {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Returns the minimum similarity of two functions paired by their structure alone.
+    pub fn threshold(&self) -> f64 {
+        self.threshold
+    }
+
+    /// Sets the weights used to compute the similarity of two structure trees.
+    pub fn with_weights(mut self, weights: SimilarityWeights) -> AnalysisConfig {
+        self.weights = weights;
+        self
+    }
+
+    /// Returns the weights used to compute the similarity of two structure trees.
+    pub fn weights(&self) -> &SimilarityWeights {
+        &self.weights
+    }
+
+    /// Reads a configuration from a file, in the textual form described in [`AnalysisConfig`].
+    ///
+    /// Returns [`Error::Io`] if the file can not be read and [`Error::InvalidInput`] if it
+    /// contains an invalid setting.
+    pub fn from_file<S: AsRef<Path>>(filename: S) -> Result<AnalysisConfig, Error> {
+        fs::read_to_string(filename)?.parse()
+    }
+
+    /// Writes the configuration to a file, in the textual form described in [`AnalysisConfig`].
+    pub fn to_file<S: AsRef<Path>>(&self, filename: S) -> Result<(), io::Error> {
+        fs::write(filename, self.to_string())
+    }
+}
+
+impl Display for AnalysisConfig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(arch) = self.extraction.arch() {
+            writeln!(f, "arch = {}", arch)?;
+        }
+        if let Some(bits) = self.extraction.bits() {
+            writeln!(f, "bits = {}", bits)?;
+        }
+        if let Some(endianness) = self.extraction.endianness() {
+            let endianness = match endianness {
+                Endianness::Little => "little",
+                Endianness::Big => "big",
+            };
+            writeln!(f, "endianness = {}", endianness)?;
+        }
+        if let Some(iterations) = self.max_iterations {
+            writeln!(f, "max_iterations = {}", iterations)?;
+        }
+        if let Some(nodes) = self.max_size {
+            writeln!(f, "max_size = {}", nodes)?;
+        }
+        let reductions = self
+            .reductions
+            .iter()
+            .map(ReductionKind::to_string)
+            .collect::<Vec<_>>();
+        writeln!(f, "reductions = {}", reductions.join(", "))?;
+        writeln!(f, "preserve_loops = {}", self.preserve_loops)?;
+        writeln!(f, "refine_conditions = {}", self.refine_conditions)?;
+        writeln!(f, "annotate_abnormal_entries = {}", self.annotate_entries)?;
+        writeln!(f, "record_timings = {}", self.timings)?;
+        if let Some(mode) = self.mode {
+            writeln!(f, "mode = {}", mode_name(mode))?;
+        }
+        writeln!(f, "threshold = {}", self.threshold)?;
+        writeln!(f, "weight.default = {}", self.weights.default)?;
+        let mut rarity = self.weights.rarity.iter().collect::<Vec<_>>();
+        rarity.sort_unstable_by_key(|(block_type, _)| **block_type);
+        for (block_type, weight) in rarity {
+            writeln!(f, "weight.{} = {}", type_keyword(*block_type), weight)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for AnalysisConfig {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = AnalysisConfig::default();
+        for (index, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |msg: String| Error::InvalidInput(format!("line {}: {}", index + 1, msg));
+            let (key, value) = line
+                .split_once('=')
+                .map(|(key, value)| (key.trim(), value.trim()))
+                .ok_or_else(|| invalid("expected `key = value`".to_string()))?;
+            let bad_value = || invalid(format!("invalid value `{}` for `{}`", value, key));
+            let number = || value.parse::<usize>().map_err(|_| bad_value());
+            let flag = || value.parse::<bool>().map_err(|_| bad_value());
+            let weight = || value.parse::<f64>().map_err(|_| bad_value());
+            match key {
+                "arch" => {
+                    // only the name is kept, so the bits are irrelevant
+                    let arch = Architecture::from_name(value, 0).ok_or_else(bad_value)?;
+                    config.extraction = config.extraction.with_arch(arch);
+                }
+                "bits" => {
+                    let bits = value.parse().map_err(|_| bad_value())?;
+                    config.extraction = config.extraction.with_bits(bits);
+                }
+                "endianness" => {
+                    let endianness = match value {
+                        "little" => Endianness::Little,
+                        "big" => Endianness::Big,
+                        _ => return Err(bad_value()),
+                    };
+                    config.extraction = config.extraction.with_endianness(endianness);
+                }
+                "max_iterations" => config.max_iterations = Some(number()?),
+                "max_size" => config.max_size = Some(number()?),
+                "reductions" => {
+                    let reductions = value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|name| !name.is_empty())
+                        .map(|name| {
+                            ReductionKind::all()
+                                .into_iter()
+                                .find(|kind| kind.to_string() == name)
+                                .ok_or_else(|| invalid(format!("unknown reduction `{}`", name)))
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    config = config.order(&reductions);
+                }
+                "preserve_loops" => config.preserve_loops = flag()?,
+                "refine_conditions" => config.refine_conditions = flag()?,
+                "annotate_abnormal_entries" => config.annotate_entries = flag()?,
+                "record_timings" => config.timings = flag()?,
+                "mode" => {
+                    let mode = [
+                        ComparisonMode::SameArchitecture,
+                        ComparisonMode::CrossArchitecture,
+                    ]
+                    .into_iter()
+                    .find(|mode| mode_name(*mode) == value)
+                    .ok_or_else(bad_value)?;
+                    config.mode = Some(mode);
+                }
+                "threshold" => config.threshold = weight()?,
+                "weight.default" => config.weights.default = weight()?,
+                _ => {
+                    let block_type = match key.strip_prefix("weight.") {
+                        Some("basic") => BlockType::Basic,
+                        Some(keyword) => keyword_type(keyword)
+                            .ok_or_else(|| invalid(format!("unknown block type `{}`", keyword)))?,
+                        None => return Err(invalid(format!("unknown setting `{}`", key))),
+                    };
+                    config.weights.rarity.insert(block_type, weight()?);
+                }
+            }
+        }
+        Ok(config)
+    }
+}
+
+fn mode_name(mode: ComparisonMode) -> &'static str {
+    match mode {
+        ComparisonMode::SameArchitecture => "same-architecture",
+        ComparisonMode::CrossArchitecture => "cross-architecture",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::{
+        AnalysisConfig, BlockType, ComparisonMode, ReductionKind, SimilarityWeights, CFG,
+    };
+    use crate::disasm::{Architecture, Endianness, ExtractionConfig, Statement, StatementFamily};
+    use crate::Error;
+
+    #[test]
+    fn default() {
+        let config = "".parse::<AnalysisConfig>().unwrap();
+        assert_eq!(config.to_string(), AnalysisConfig::new().to_string());
+        assert_eq!(config.reductions(), ReductionKind::all());
+        assert_eq!(config.extraction(), ExtractionConfig::default());
+        assert_eq!(config.mode(), None);
+        assert_eq!(config.threshold(), 0.5);
+    }
+
+    #[test]
+    fn round_trip() {
+        let weights = SimilarityWeights::new()
+            .rarity(BlockType::While, 2.5)
+            .rarity(BlockType::Basic, 0.5)
+            .rarity(BlockType::Custom(7), 3.0);
+        let extraction = ExtractionConfig::new()
+            .with_arch(Architecture::Arm(64))
+            .with_bits(32)
+            .with_endianness(Endianness::Big);
+        let config = AnalysisConfig::new()
+            .with_extraction(extraction)
+            .max_iterations(100)
+            .max_size(20)
+            .order(&[ReductionKind::Sequence, ReductionKind::Loop])
+            .refine_conditions()
+            .record_timings()
+            .with_mode(ComparisonMode::CrossArchitecture)
+            .with_threshold(0.75)
+            .with_weights(weights);
+        let text = config.to_string();
+        assert!(text.starts_with("arch = arm\nbits = 32\n"));
+        assert!(text.contains("reductions = Sequence, Loop\n"));
+        assert!(text.contains("weight.while = 2.5\n"));
+        assert!(text.contains("weight.basic = 0.5\n"));
+        assert!(text.contains("weight.custom:7 = 3\n"));
+        let parsed = text.parse::<AnalysisConfig>().unwrap();
+        assert_eq!(parsed.to_string(), text);
+        assert_eq!(parsed.extraction(), extraction);
+        assert!(parsed.timings());
+        assert_eq!(parsed.weights().weight(BlockType::While), 2.5);
+        assert_eq!(parsed.weights().weight(BlockType::IfThen), 1.0);
+    }
+
+    #[test]
+    fn comments_and_whitespace() {
+        let text = "# comment\n\n  threshold=0.9  \nreductions =\nmode = same-architecture\n";
+        let config = text.parse::<AnalysisConfig>().unwrap();
+        assert_eq!(config.threshold(), 0.9);
+        assert!(config.reductions().is_empty());
+        assert_eq!(config.mode(), Some(ComparisonMode::SameArchitecture));
+    }
+
+    #[test]
+    fn malformed() {
+        let inputs = [
+            "threshold",
+            "threshold = high",
+            "arch = pdp11",
+            "bits = -1",
+            "endianness = middle",
+            "reductions = Loop, Spiral",
+            "preserve_loops = yes",
+            "mode = same",
+            "weight.spiral = 2",
+            "colour = blue",
+        ];
+        for input in inputs {
+            let text = format!("max_size = 10\n{}", input);
+            let err = text.parse::<AnalysisConfig>().err().unwrap();
+            assert!(matches!(err, Error::InvalidInput(_)), "{}", input);
+            assert!(err.to_string().contains("line 2"), "{}", input);
+        }
+    }
+
+    #[test]
+    fn cfs_builder() {
+        let config = AnalysisConfig::new()
+            .order(&[ReductionKind::Sequence, ReductionKind::Sequence])
+            .max_size(2);
+        let builder = config.cfs_builder();
+        assert_eq!(builder.reductions(), [ReductionKind::Sequence]);
+        let stmts = vec![
+            Statement::new(0x610, StatementFamily::CJMP, "je 0x618"),
+            Statement::new(0x614, StatementFamily::MOV, "mov eax, 6"),
+            Statement::new(0x618, StatementFamily::RET, "ret"),
+        ];
+        let cfg = CFG::new(&stmts, 0x619, Architecture::X86(64));
+        assert!(matches!(builder.try_build(&cfg), Err(Error::Budget)));
+    }
+
+    #[test]
+    fn file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bincc.conf");
+        let config = AnalysisConfig::new().max_iterations(10).preserve_loops();
+        config.to_file(&path).unwrap();
+        let read = AnalysisConfig::from_file(&path).unwrap();
+        assert_eq!(read.to_string(), config.to_string());
+        let missing = AnalysisConfig::from_file(dir.path().join("missing.conf"));
+        assert!(matches!(missing, Err(Error::Io(_))));
+    }
+}
@@ -0,0 +1,497 @@
+use crate::analysis::{Fingerprint, CFS};
+use crate::disasm::Binary;
+use crate::Error;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+// must be increased every time the schema changes.
+const SCHEMA_VERSION: u32 = 3;
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS fingerprints (
+    binary TEXT NOT NULL,
+    function TEXT NOT NULL,
+    offset INTEGER NOT NULL,
+    structural_hash INTEGER NOT NULL,
+    wl_hash INTEGER NOT NULL,
+    blocks INTEGER NOT NULL,
+    edges INTEGER NOT NULL,
+    depth INTEGER NOT NULL,
+    nodes INTEGER NOT NULL,
+    PRIMARY KEY (binary, offset)
+);
+CREATE INDEX IF NOT EXISTS structural_index ON fingerprints (structural_hash);
+CREATE INDEX IF NOT EXISTS wl_index ON fingerprints (wl_hash);
+CREATE TABLE IF NOT EXISTS binaries (
+    name TEXT PRIMARY KEY,
+    content_hash BLOB NOT NULL
+);
+CREATE INDEX IF NOT EXISTS content_index ON binaries (content_hash);
+";
+const COLUMNS: &str =
+    "binary, function, offset, structural_hash, wl_hash, blocks, edges, depth, nodes";
+
+/// Function stored in a [`FingerprintDatabase`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(docsrs, doc(cfg(feature = "compare-db")))]
+pub struct FingerprintRecord {
+    binary: String,
+    function: String,
+    offset: u64,
+    fingerprint: Fingerprint,
+}
+
+impl FingerprintRecord {
+    /// Returns the name of the binary containing the function.
+    pub fn binary(&self) -> &str {
+        &self.binary
+    }
+
+    /// Returns the name of the function.
+    pub fn function(&self) -> &str {
+        &self.function
+    }
+
+    /// Returns the offset of the function in the binary.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the fingerprint of the function.
+    pub fn fingerprint(&self) -> &Fingerprint {
+        &self.fingerprint
+    }
+}
+
+/// Persistent index of the [`Fingerprint`]s of the functions of many binaries.
+///
+/// The fingerprints are stored in a SQLite database, keyed by the name of the binary and the
+/// offset of the function, and indexed by their hashes, so the functions with the same structure
+/// can be found without loading the whole corpus in memory.
+///
+/// Binaries can be appended incrementally with [`FingerprintDatabase::append_binary`], which
+/// records the hash of the content of each binary and skips the binaries already indexed, even
+/// if under a different name.
+///
+/// This struct is available only with the `compare-db` feature.
+/// # Examples
+/// Basic usage:
+/// ```
+/// use bincc::analysis::{Fingerprint, FingerprintDatabase, CFG, CFS};
+/// use bincc::disasm::{Architecture, Statement, StatementFamily};
+///
+/// let stmts = vec![
+///     Statement::new(0x10, StatementFamily::CJMP, "je 0x14"),
+///     Statement::new(0x12, StatementFamily::MOV, "mov eax, 0"),
+///     Statement::new(0x14, StatementFamily::RET, "ret"),
+/// ];
+/// let cfg = CFG::new(&stmts, 0x15, Architecture::X86(64));
+/// let fingerprint = Fingerprint::new(&CFS::new(&cfg).unwrap()).unwrap();
+/// let mut database = FingerprintDatabase::open_in_memory().unwrap();
+/// database.insert("libfoo.so", "foo", 0x10, &fingerprint).unwrap();
+///
+/// let found = database.find(&fingerprint).unwrap();
+/// assert_eq!(found[0].function(), "foo");
+/// ```
+#[cfg_attr(docsrs, doc(cfg(feature = "compare-db")))]
+pub struct FingerprintDatabase {
+    connection: Connection,
+}
+
+impl FingerprintDatabase {
+    /// Opens the database stored in the given file, creating it if it does not exist.
+    ///
+    /// Returns [`Error::InvalidInput`] if the database was written by a version of this library
+    /// using a different schema, and [`Error::Database`] for any error reported by SQLite.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<FingerprintDatabase, Error> {
+        FingerprintDatabase::init(Connection::open(path)?)
+    }
+
+    /// Creates a new database kept in memory, discarded when dropped.
+    pub fn open_in_memory() -> Result<FingerprintDatabase, Error> {
+        FingerprintDatabase::init(Connection::open_in_memory()?)
+    }
+
+    fn init(connection: Connection) -> Result<FingerprintDatabase, Error> {
+        let version =
+            connection.pragma_query_value(None, "user_version", |row| row.get::<_, u32>(0))?;
+        match version {
+            // new database, version 1 lacking only the binaries table, or version 2 whose
+            // binaries are addressed by a weaker hash and must be appended again
+            0..=2 => {
+                connection.execute_batch("DROP TABLE IF EXISTS binaries")?;
+                connection.execute_batch(SCHEMA)?;
+                connection.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+            }
+            SCHEMA_VERSION => {}
+            _ => {
+                return Err(Error::InvalidInput(format!(
+                    "unsupported database format version {}",
+                    version
+                )))
+            }
+        }
+        Ok(FingerprintDatabase { connection })
+    }
+
+    /// Stores the fingerprint of the function starting at the given offset of the given binary.
+    ///
+    /// The fingerprint previously stored for the same function, if any, is replaced.
+    pub fn insert(
+        &mut self,
+        binary: &str,
+        function: &str,
+        offset: u64,
+        fingerprint: &Fingerprint,
+    ) -> Result<(), Error> {
+        insert(&self.connection, binary, function, offset, fingerprint)
+    }
+
+    /// Stores the fingerprint of every function of the given [`Binary`], labeled with the given
+    /// name.
+    ///
+    /// The functions without a [`CFG`](crate::analysis::CFG), or whose [`CFS`] is not a tree, are
+    /// skipped. Every function is stored in a single transaction, so either all of them are
+    /// stored or none is. Returns the amount of stored functions.
+    pub fn insert_binary(&mut self, name: &str, binary: &Binary) -> Result<usize, Error> {
+        let transaction = self.connection.transaction()?;
+        let stored = insert_functions(&transaction, name, binary)?;
+        transaction.commit()?;
+        Ok(stored)
+    }
+
+    /// Stores the fingerprint of every function of the given [`Binary`], labeled with the given
+    /// name, unless a binary with the same content is already stored.
+    ///
+    /// The content is the raw executable the [`Binary`] was extracted from, and is used only to
+    /// compute its SHA-256 hash: this allows calling [`FingerprintDatabase::contains_content`]
+    /// before the expensive analysis of the executable. If another binary with the same name but
+    /// a different content is stored, its functions are replaced.
+    ///
+    /// Every function is stored in a single transaction, like in
+    /// [`FingerprintDatabase::insert_binary`]. Returns the amount of stored functions, or
+    /// [`None`] if the binary was already stored.
+    pub fn append_binary(
+        &mut self,
+        name: &str,
+        content: &[u8],
+        binary: &Binary,
+    ) -> Result<Option<usize>, Error> {
+        if self.contains_content(content)? {
+            return Ok(None);
+        }
+        let transaction = self.connection.transaction()?;
+        transaction.execute("DELETE FROM fingerprints WHERE binary = ?1", params![name])?;
+        transaction.execute(
+            "INSERT OR REPLACE INTO binaries (name, content_hash) VALUES (?1, ?2)",
+            params![name, content_hash(content)],
+        )?;
+        let stored = insert_functions(&transaction, name, binary)?;
+        transaction.commit()?;
+        Ok(Some(stored))
+    }
+
+    /// Returns true if a binary with the given content was stored with
+    /// [`FingerprintDatabase::append_binary`].
+    pub fn contains_content(&self, content: &[u8]) -> Result<bool, Error> {
+        self.connection
+            .query_row(
+                "SELECT 1 FROM binaries WHERE content_hash = ?1",
+                params![content_hash(content)],
+                |_| Ok(()),
+            )
+            .optional()
+            .map(|found| found.is_some())
+            .map_err(Error::from)
+    }
+
+    /// Returns the function starting at the given offset of the given binary, if stored.
+    pub fn get(&self, binary: &str, offset: u64) -> Result<Option<FingerprintRecord>, Error> {
+        let query = format!(
+            "SELECT {} FROM fingerprints WHERE binary = ?1 AND offset = ?2",
+            COLUMNS
+        );
+        self.connection
+            .query_row(&query, params![binary, offset as i64], record)
+            .optional()
+            .map_err(Error::from)
+    }
+
+    /// Returns every function with the given [`Fingerprint::structural_hash`].
+    pub fn find_structural_hash(&self, hash: u64) -> Result<Vec<FingerprintRecord>, Error> {
+        self.select("structural_hash = ?1", params![hash as i64])
+    }
+
+    /// Returns every function with the given [`Fingerprint::wl_hash`].
+    pub fn find_wl_hash(&self, hash: u64) -> Result<Vec<FingerprintRecord>, Error> {
+        self.select("wl_hash = ?1", params![hash as i64])
+    }
+
+    /// Returns every function with the same fingerprint of the given one.
+    pub fn find(&self, fingerprint: &Fingerprint) -> Result<Vec<FingerprintRecord>, Error> {
+        let found = self.find_structural_hash(fingerprint.structural_hash())?;
+        Ok(found
+            .into_iter()
+            .filter(|record| record.fingerprint == *fingerprint)
+            .collect())
+    }
+
+    /// Returns every function whose fingerprint has a [`Fingerprint::similarity`] of at least the
+    /// given threshold with the given one, and its similarity.
+    ///
+    /// The functions are sorted by decreasing similarity, then by binary and offset.
+    pub fn find_similar(
+        &self,
+        fingerprint: &Fingerprint,
+        threshold: f64,
+    ) -> Result<Vec<(FingerprintRecord, f64)>, Error> {
+        // the similarity is at most (r + 3) / 4, where r is the ratio of the amount of blocks
+        let ratio = (4.0 * threshold - 3.0).max(0.0);
+        let blocks = fingerprint.blocks() as f64;
+        let min_blocks = (blocks * ratio).ceil() as i64;
+        let max_blocks = match ratio > 0.0 {
+            true => (blocks / ratio).floor() as i64,
+            false => i64::MAX,
+        };
+        let found = self.select(
+            "structural_hash = ?1 OR blocks BETWEEN ?2 AND ?3",
+            params![fingerprint.structural_hash() as i64, min_blocks, max_blocks],
+        )?;
+        let mut similar = found
+            .into_iter()
+            .map(|record| {
+                let similarity = record.fingerprint.similarity(fingerprint);
+                (record, similarity)
+            })
+            .filter(|(_, similarity)| *similarity >= threshold)
+            .collect::<Vec<_>>();
+        // records are already sorted by binary and offset
+        similar.sort_by(|a, b| b.1.total_cmp(&a.1));
+        Ok(similar)
+    }
+
+    /// Removes every function of the given binary, returning the amount of removed functions.
+    ///
+    /// The hash of the content of the binary is removed as well, so the same content can be
+    /// appended again.
+    pub fn remove_binary(&mut self, binary: &str) -> Result<usize, Error> {
+        self.connection
+            .execute("DELETE FROM binaries WHERE name = ?1", params![binary])?;
+        self.connection
+            .execute(
+                "DELETE FROM fingerprints WHERE binary = ?1",
+                params![binary],
+            )
+            .map_err(Error::from)
+    }
+
+    /// Returns the amount of functions stored in the database.
+    pub fn len(&self) -> Result<usize, Error> {
+        self.connection
+            .query_row("SELECT COUNT(*) FROM fingerprints", [], |row| {
+                row.get::<_, i64>(0)
+            })
+            .map(|count| count as usize)
+            .map_err(Error::from)
+    }
+
+    /// Returns true if the database contains no functions.
+    pub fn is_empty(&self) -> Result<bool, Error> {
+        self.len().map(|len| len == 0)
+    }
+
+    fn select(
+        &self,
+        condition: &str,
+        params: &[&dyn rusqlite::ToSql],
+    ) -> Result<Vec<FingerprintRecord>, Error> {
+        let query = format!(
+            "SELECT {} FROM fingerprints WHERE {} ORDER BY binary, offset",
+            COLUMNS, condition
+        );
+        let mut statement = self.connection.prepare(&query)?;
+        let records = statement
+            .query_map(params, record)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(records)
+    }
+}
+
+// stores the fingerprints of the functions of the binary, returning the amount of them.
+fn insert_functions(connection: &Connection, name: &str, binary: &Binary) -> Result<usize, Error> {
+    let mut stored = 0;
+    for function in binary.functions() {
+        let offset = function.get_offset();
+        let fingerprint = binary
+            .cfg(offset)
+            .and_then(|cfg| Fingerprint::new(&CFS::new(cfg).ok()?));
+        if let Some(fingerprint) = fingerprint {
+            insert(connection, name, function.get_name(), offset, &fingerprint)?;
+            stored += 1;
+        }
+    }
+    Ok(stored)
+}
+
+// a cryptographic hash, as a collision would silently skip a different binary.
+fn content_hash(content: &[u8]) -> Vec<u8> {
+    Sha256::digest(content).to_vec()
+}
+
+// the integers are stored as i64, the only integer type of SQLite.
+fn insert(
+    connection: &Connection,
+    binary: &str,
+    function: &str,
+    offset: u64,
+    fingerprint: &Fingerprint,
+) -> Result<(), Error> {
+    let query = format!(
+        "INSERT OR REPLACE INTO fingerprints ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        COLUMNS
+    );
+    connection
+        .execute(
+            &query,
+            params![
+                binary,
+                function,
+                offset as i64,
+                fingerprint.structural_hash as i64,
+                fingerprint.wl_hash as i64,
+                fingerprint.blocks,
+                fingerprint.edges,
+                fingerprint.depth,
+                fingerprint.nodes,
+            ],
+        )
+        .map(|_| ())
+        .map_err(Error::from)
+}
+
+fn record(row: &Row) -> rusqlite::Result<FingerprintRecord> {
+    Ok(FingerprintRecord {
+        binary: row.get(0)?,
+        function: row.get(1)?,
+        offset: row.get::<_, i64>(2)? as u64,
+        fingerprint: Fingerprint {
+            structural_hash: row.get::<_, i64>(3)? as u64,
+            wl_hash: row.get::<_, i64>(4)? as u64,
+            blocks: row.get(5)?,
+            edges: row.get(6)?,
+            depth: row.get(7)?,
+            nodes: row.get(8)?,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::test_utils::binary;
+    use crate::analysis::{Fingerprint, FingerprintDatabase, CFG, CFS};
+    use crate::disasm::{Architecture, Statement, StatementFamily};
+    use tempfile::tempdir;
+
+    fn fingerprint(cond: bool) -> Fingerprint {
+        let first = if cond {
+            Statement::new(0x10, StatementFamily::CJMP, "je 0x14")
+        } else {
+            Statement::new(0x10, StatementFamily::JMP, "jmp 0x12")
+        };
+        let stmts = vec![
+            first,
+            Statement::new(0x12, StatementFamily::MOV, "mov eax, 0"),
+            Statement::new(0x14, StatementFamily::RET, "ret"),
+        ];
+        let cfg = CFG::new(&stmts, 0x15, Architecture::X86(64));
+        Fingerprint::new(&CFS::new(&cfg).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn insert_query() {
+        let mut db = FingerprintDatabase::open_in_memory().unwrap();
+        let ifthen = fingerprint(true);
+        let sequence = fingerprint(false);
+        assert!(db.is_empty().unwrap());
+        db.insert("a", "foo", 0x10, &ifthen).unwrap();
+        db.insert("b", "bar", u64::MAX, &ifthen).unwrap();
+        db.insert("b", "baz", 0x20, &sequence).unwrap();
+        // replaces the previous function at the same offset
+        db.insert("b", "qux", 0x20, &sequence).unwrap();
+        assert_eq!(db.len().unwrap(), 3);
+        let found = db.find(&ifthen).unwrap();
+        let names = found.iter().map(|r| r.function()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["foo", "bar"]);
+        assert_eq!(found[1].offset(), u64::MAX);
+        assert_eq!(found[1].fingerprint(), &ifthen);
+        let found = db.find_wl_hash(sequence.wl_hash()).unwrap();
+        assert_eq!(found[0].function(), "qux");
+        let record = db.get("a", 0x10).unwrap().unwrap();
+        assert_eq!(record.binary(), "a");
+        assert!(db.get("a", 0x20).unwrap().is_none());
+        assert_eq!(db.remove_binary("b").unwrap(), 2);
+        assert_eq!(db.len().unwrap(), 1);
+    }
+
+    #[test]
+    fn similar() {
+        let mut db = FingerprintDatabase::open_in_memory().unwrap();
+        let ifthen = fingerprint(true);
+        let sequence = fingerprint(false);
+        db.insert("a", "foo", 0x10, &sequence).unwrap();
+        db.insert("b", "bar", 0x10, &ifthen).unwrap();
+        let found = db.find_similar(&ifthen, 0.0).unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].0.function(), "bar");
+        assert_eq!(found[0].1, 1.0);
+        assert_eq!(found[1].1, ifthen.similarity(&sequence));
+        let found = db.find_similar(&ifthen, 1.0).unwrap();
+        assert_eq!(found.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn append() {
+        let mut db = FingerprintDatabase::open_in_memory().unwrap();
+        let first = binary(vec![(0x0, "fcn0", 0), (0x10, "fcn1", 0), (0x20, "fcn2", 0)]).await;
+        let second = binary(vec![(0x0, "fcn0", 0), (0x10, "fcn1", 0)]).await;
+        assert!(!db.contains_content(b"first").unwrap());
+        assert_eq!(db.append_binary("a", b"first", &first).unwrap(), Some(3));
+        assert!(db.contains_content(b"first").unwrap());
+        // same content, even with a different name
+        assert_eq!(db.append_binary("a", b"first", &first).unwrap(), None);
+        assert_eq!(db.append_binary("b", b"first", &first).unwrap(), None);
+        assert_eq!(db.len().unwrap(), 3);
+        // new version of the same binary
+        assert_eq!(db.append_binary("a", b"second", &second).unwrap(), Some(2));
+        assert_eq!(db.len().unwrap(), 2);
+        assert!(!db.contains_content(b"first").unwrap());
+        assert_eq!(db.remove_binary("a").unwrap(), 2);
+        assert!(!db.contains_content(b"second").unwrap());
+    }
+
+    #[test]
+    fn persistent() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("fingerprints.db");
+        let mut db = FingerprintDatabase::open(&path).unwrap();
+        db.insert("a", "foo", 0x10, &fingerprint(true)).unwrap();
+        drop(db);
+        let db = FingerprintDatabase::open(&path).unwrap();
+        assert_eq!(db.len().unwrap(), 1);
+        drop(db);
+        // the binaries table is added to the previous version
+        let connection = rusqlite::Connection::open(&path).unwrap();
+        connection.execute_batch("DROP TABLE binaries").unwrap();
+        connection.pragma_update(None, "user_version", 1).unwrap();
+        drop(connection);
+        let db = FingerprintDatabase::open(&path).unwrap();
+        assert_eq!(db.len().unwrap(), 1);
+        assert!(!db.contains_content(b"").unwrap());
+        drop(db);
+        let connection = rusqlite::Connection::open(&path).unwrap();
+        connection.pragma_update(None, "user_version", 99).unwrap();
+        drop(connection);
+        let error = FingerprintDatabase::open(&path).err().unwrap();
+        assert!(matches!(error, crate::Error::InvalidInput(_)));
+    }
+}
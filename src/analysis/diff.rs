@@ -0,0 +1,952 @@
+use crate::analysis::patch::patched_regions;
+use crate::analysis::{
+    tree_similarity_with_weights, AnalysisConfig, BlockType, CallTarget, CancellationToken,
+    CfsBuilder, Graph, PatchedRegion, SimilarityWeights, StructureBlock, Timings, CFG,
+};
+use crate::disasm::{Binary, CategoryHistogram, Function};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::time::Instant;
+
+// minimum similarity of two functions paired by their structure alone.
+pub(super) const DEFAULT_THRESHOLD: f64 = 0.5;
+// weight of the tree similarity when comparing functions of different architectures, the rest
+// of the similarity is given by the function profiles.
+const CROSS_TREE_WEIGHT: f64 = 0.75;
+// weight of the similarity of the instruction categories, when known for both functions.
+const CATEGORY_WEIGHT: f64 = 0.25;
+// prefix of the names assigned by radare2 to the functions without a symbol.
+pub(super) const GENERATED_PREFIX: &str = "fcn.";
+
+/// Signals used by [`BinaryDiff`] to compare the functions of two binaries.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(docsrs, doc(cfg(feature = "disasm")))]
+pub enum ComparisonMode {
+    /// Both binaries are compiled for the same architecture.
+    ///
+    /// Functions are compared by their structure with
+    /// [`tree_similarity`](crate::analysis::tree_similarity), and two functions are unchanged only
+    /// if their basic blocks have the same length.
+    #[default]
+    SameArchitecture,
+    /// The binaries are compiled for different architectures, like the same firmware built for
+    /// ARM and MIPS.
+    ///
+    /// Only the signals that do not depend on the instruction set are used: the shape of the
+    /// structure tree, the amount of loops, the amount of calls and distinct callees, and the
+    /// amount of basic blocks. The length of the basic blocks and the size of the functions are
+    /// ignored.
+    CrossArchitecture,
+}
+
+/// Function of the old binary paired with a function of the new binary by [`BinaryDiff`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(docsrs, doc(cfg(feature = "disasm")))]
+pub struct FunctionMatch {
+    old: Function,
+    new: Function,
+    old_ranges: Vec<Range<u64>>,
+    new_ranges: Vec<Range<u64>>,
+    similarity: f64,
+    identical: bool,
+    changes: Vec<PatchedRegion>,
+}
+
+impl FunctionMatch {
+    /// Returns the function of the old binary.
+    pub fn old_function(&self) -> &Function {
+        &self.old
+    }
+
+    /// Returns the function of the new binary.
+    pub fn new_function(&self) -> &Function {
+        &self.new
+    }
+
+    /// Returns the address ranges covered by the function of the old binary, see
+    /// [`StructureBlock::address_ranges`].
+    pub fn old_ranges(&self) -> &[Range<u64>] {
+        &self.old_ranges
+    }
+
+    /// Returns the address ranges covered by the function of the new binary.
+    pub fn new_ranges(&self) -> &[Range<u64>] {
+        &self.new_ranges
+    }
+
+    /// Returns the similarity of the two functions, between 0 and 1.
+    ///
+    /// This is the [`tree_similarity`](crate::analysis::tree_similarity) of the structures of the
+    /// two functions, weighted as requested by [`BinaryDiff::with_config`]. In
+    /// [`ComparisonMode::CrossArchitecture`] this is combined with the similarity of the amount of
+    /// loops, calls, callees and basic blocks of the two functions. If the instruction categories
+    /// of both functions are known, see [`CFG::function_categories`], their
+    /// [`CategoryHistogram::similarity`] accounts for a quarter of the result, so functions with
+    /// the same structure but different bodies are told apart.
+    pub fn similarity(&self) -> f64 {
+        self.similarity
+    }
+
+    /// Returns true if the two functions are unchanged.
+    ///
+    /// Two functions are unchanged if their [`CFG`]s have the same shape, see
+    /// [`CFG::same_shape`], their basic blocks have the same length and contain the same amount
+    /// of instructions of each category, see [`CFG::categories`], and the functions have the same
+    /// size. In [`ComparisonMode::CrossArchitecture`] the lengths, sizes and
+    /// categories of each block are ignored, and the functions must perform the same amount of
+    /// calls instead. The instructions themselves are not compared.
+    pub fn is_identical(&self) -> bool {
+        self.identical
+    }
+
+    /// Returns the regions of the function that changed, in order.
+    ///
+    /// The slice is empty for unchanged functions, see [`FunctionMatch::is_identical`], and for
+    /// functions whose differences are not visible in the structure trees, like a different
+    /// function size.
+    pub fn changes(&self) -> &[PatchedRegion] {
+        &self.changes
+    }
+}
+
+/// Comparison of two versions of the same binary, like two releases of a program.
+///
+/// The functions of the two binaries are paired in four steps:
+/// 1. functions with the same name, if the name is unique in both binaries and not generated by
+///    the disassembler;
+/// 2. functions with the same [`StructureBlock::structural_hash`], if the hash is unique in both
+///    binaries;
+/// 3. functions called by, or calling, the functions already paired. Each pair votes for the
+///    pairs of their callees and of their callers, and two functions are paired if they are the
+///    best candidate for each other, by amount of votes and then by similarity. This is repeated
+///    until no more functions are paired, and resolves the small functions sharing the same
+///    structure with several others;
+/// 4. the remaining functions, from the most similar pair to the least similar one.
+///
+/// The pairs found by steps 3 and 4 must have a similarity of at least the threshold, see
+/// [`FunctionMatch::similarity`]. Only direct calls to the functions of the same binary are
+/// part of the call graph.
+///
+/// Functions without a [`CFG`], or whose [`CFS`](crate::analysis::CFS) is empty, are not
+/// compared. The functions of the old binary left unpaired are reported as removed, and the ones
+/// of the new binary as added.
+///
+/// Binaries of different architectures are compared in [`ComparisonMode::CrossArchitecture`],
+/// unless another mode is requested with [`BinaryDiff::with_mode`].
+#[cfg_attr(docsrs, doc(cfg(feature = "disasm")))]
+pub struct BinaryDiff {
+    mode: ComparisonMode,
+    // sorted by offset in the old binary
+    matches: Vec<FunctionMatch>,
+    added: Vec<Function>,
+    removed: Vec<Function>,
+    // by offset of the old and of the new function, only if requested
+    timings: HashMap<(u64, u64), Timings>,
+    complete: bool,
+}
+
+impl BinaryDiff {
+    /// Compares the functions of two binaries, with a threshold of `0.5` for the pairs found by
+    /// their structure alone.
+    pub fn new(old: &Binary, new: &Binary) -> BinaryDiff {
+        BinaryDiff::with_threshold(old, new, DEFAULT_THRESHOLD)
+    }
+
+    /// Compares the functions of two binaries, pairing the functions found by their structure
+    /// alone only if their similarity is at least the given threshold.
+    pub fn with_threshold(old: &Binary, new: &Binary, threshold: f64) -> BinaryDiff {
+        BinaryDiff::with_mode(old, new, detect_mode(old, new), threshold)
+    }
+
+    /// Compares the functions of two binaries using the given signals, pairing the functions
+    /// found by their structure alone only if their similarity is at least the given threshold.
+    pub fn with_mode(
+        old: &Binary,
+        new: &Binary,
+        mode: ComparisonMode,
+        threshold: f64,
+    ) -> BinaryDiff {
+        let config = AnalysisConfig::new()
+            .with_mode(mode)
+            .with_threshold(threshold);
+        BinaryDiff::with_config(old, new, &config)
+    }
+
+    /// Compares the functions of two binaries using the structuring, the signals, the threshold
+    /// and the weights of the given configuration.
+    ///
+    /// If the configuration does not force a [`ComparisonMode`], it is detected as in
+    /// [`BinaryDiff::with_threshold`].
+    pub fn with_config(old: &Binary, new: &Binary, config: &AnalysisConfig) -> BinaryDiff {
+        BinaryDiff::with_cancellation(old, new, config, &CancellationToken::new())
+    }
+
+    /// Same as [`BinaryDiff::with_config`], but stops when the given token is cancelled.
+    ///
+    /// The token is checked before structuring each function and while pairing them. Once
+    /// cancelled, the diff contains the pairs found so far, while the functions left unpaired
+    /// are reported as added or removed, and the functions not yet structured are not reported
+    /// at all. A diff stopped this way is not complete, see [`BinaryDiff::is_complete`].
+    pub fn with_cancellation(
+        old: &Binary,
+        new: &Binary,
+        config: &AnalysisConfig,
+        token: &CancellationToken,
+    ) -> BinaryDiff {
+        let mode = config.mode().unwrap_or_else(|| detect_mode(old, new));
+        let threshold = config.threshold();
+        let weights = config.weights();
+        let builder = config.cfs_builder().with_cancellation(token.clone());
+        let old = entries(old, &builder, token);
+        let new = entries(new, &builder, token);
+        let mut pairs = Vec::new();
+        let mut paired_old = vec![false; old.len()];
+        let mut paired_new = vec![false; new.len()];
+        // same unique name
+        let names_old = unique(&old, |_| true, |entry| entry.name().to_string());
+        let names_new = unique(&new, |_| true, |entry| entry.name().to_string());
+        for (name, &i) in &names_old {
+            if let Some(&j) = names_new.get(name) {
+                if !name.starts_with(GENERATED_PREFIX) {
+                    pairs.push((i, j));
+                }
+            }
+        }
+        pairs.iter().for_each(|&(i, j)| {
+            paired_old[i] = true;
+            paired_new[j] = true;
+        });
+        // same unique structure
+        let hashes_old = unique(&old, |i| !paired_old[i], |entry| entry.hash);
+        let hashes_new = unique(&new, |j| !paired_new[j], |entry| entry.hash);
+        for (hash, &i) in &hashes_old {
+            if let Some(&j) = hashes_new.get(hash) {
+                paired_old[i] = true;
+                paired_new[j] = true;
+                pairs.push((i, j));
+            }
+        }
+        // neighbours in the call graph
+        let callers_old = callers(&old);
+        let callers_new = callers(&new);
+        while !token.is_cancelled() {
+            let mut votes = HashMap::new();
+            for &(i, j) in &pairs {
+                let neighbours = [
+                    (&old[i].callees, &new[j].callees),
+                    (&callers_old[i], &callers_new[j]),
+                ];
+                for (neighbours_old, neighbours_new) in neighbours {
+                    for &a in neighbours_old.iter().filter(|&&a| !paired_old[a]) {
+                        for &b in neighbours_new.iter().filter(|&&b| !paired_new[b]) {
+                            *votes.entry((a, b)).or_insert(0) += 1;
+                        }
+                    }
+                }
+            }
+            let candidates = votes
+                .into_iter()
+                .filter_map(|((a, b), votes)| {
+                    let similarity = similarity(mode, weights, &old[a], &new[b]);
+                    (similarity >= threshold).then_some(((a, b), (votes, similarity)))
+                })
+                .collect::<Vec<_>>();
+            let best_old = best_candidates(&candidates, |&(a, _)| a);
+            let best_new = best_candidates(&candidates, |&(_, b)| b);
+            let mut accepted = candidates
+                .into_iter()
+                .map(|(pair, _)| pair)
+                .filter(|(a, b)| best_old[a] == Some((*a, *b)) && best_new[b] == Some((*a, *b)))
+                .collect::<Vec<_>>();
+            if accepted.is_empty() {
+                break;
+            }
+            accepted.sort_unstable();
+            for (a, b) in accepted {
+                paired_old[a] = true;
+                paired_new[b] = true;
+                pairs.push((a, b));
+            }
+        }
+        // most similar structure
+        let mut candidates = Vec::new();
+        for i in (0..old.len()).filter(|&i| !paired_old[i]) {
+            if token.is_cancelled() {
+                // pairing only some of the functions would favour the first ones
+                candidates.clear();
+                break;
+            }
+            for j in (0..new.len()).filter(|&j| !paired_new[j]) {
+                let similarity = similarity(mode, weights, &old[i], &new[j]);
+                if similarity >= threshold {
+                    candidates.push((similarity, i, j));
+                }
+            }
+        }
+        // entries are sorted by offset, so ties are broken by offset
+        candidates.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+        for (_, i, j) in candidates {
+            if !paired_old[i] && !paired_new[j] {
+                paired_old[i] = true;
+                paired_new[j] = true;
+                pairs.push((i, j));
+            }
+        }
+        let mut timings = HashMap::new();
+        let mut matches = pairs
+            .into_iter()
+            .map(|(i, j)| {
+                let (old, new) = (&old[i], &new[j]);
+                let start = config.timings().then(Instant::now);
+                let identical = identical(mode, old, new);
+                let changes = match identical {
+                    true => Vec::new(),
+                    false => patched_regions((&old.tree, old.cfg), (&new.tree, new.cfg), mode),
+                };
+                let similarity = similarity(mode, weights, old, new);
+                if let Some(start) = start {
+                    let mut pair = old.timings.clone();
+                    pair.add(&new.timings);
+                    pair.comparison = Some(start.elapsed());
+                    let key = (old.function.get_offset(), new.function.get_offset());
+                    timings.insert(key, pair);
+                }
+                FunctionMatch {
+                    old: old.function.clone(),
+                    new: new.function.clone(),
+                    old_ranges: old.tree.address_ranges(),
+                    new_ranges: new.tree.address_ranges(),
+                    similarity,
+                    identical,
+                    changes,
+                }
+            })
+            .collect::<Vec<_>>();
+        matches.sort_by_key(|m| m.old.get_offset());
+        let unpaired = |entries: &[Entry], paired: &[bool]| {
+            entries
+                .iter()
+                .zip(paired)
+                .filter(|(_, paired)| !**paired)
+                .map(|(entry, _)| entry.function.clone())
+                .collect()
+        };
+        BinaryDiff {
+            mode,
+            matches,
+            added: unpaired(&new, &paired_new),
+            removed: unpaired(&old, &paired_old),
+            timings,
+            complete: !token.is_cancelled(),
+        }
+    }
+
+    /// Returns false if the comparison has been cancelled before completion, see
+    /// [`BinaryDiff::with_cancellation`].
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// Returns the signals used to compare the functions.
+    pub fn mode(&self) -> ComparisonMode {
+        self.mode
+    }
+
+    /// Returns the paired functions, sorted by offset in the old binary.
+    pub fn matches(&self) -> &[FunctionMatch] {
+        &self.matches
+    }
+
+    /// Returns the functions of the new binary without a match, sorted by offset.
+    pub fn added(&self) -> &[Function] {
+        &self.added
+    }
+
+    /// Returns the functions of the old binary without a match, sorted by offset.
+    pub fn removed(&self) -> &[Function] {
+        &self.removed
+    }
+
+    /// Returns the amount of paired functions that are unchanged, see
+    /// [`FunctionMatch::is_identical`].
+    pub fn identical(&self) -> usize {
+        self.matches.iter().filter(|m| m.identical).count()
+    }
+
+    /// Returns the amount of paired functions that are changed.
+    pub fn changed(&self) -> usize {
+        self.matches.len() - self.identical()
+    }
+
+    /// Returns the overall similarity of the two binaries, between 0 and 1.
+    ///
+    /// The similarity is the sum of the similarities of the paired functions over the average
+    /// amount of functions compared in each binary, so added and removed functions count as
+    /// completely different. Two binaries without functions are considered identical.
+    pub fn similarity(&self) -> f64 {
+        let total = 2 * self.matches.len() + self.added.len() + self.removed.len();
+        if total == 0 {
+            1.0
+        } else {
+            let common = self.matches.iter().map(|m| m.similarity).sum::<f64>();
+            2.0 * common / total as f64
+        }
+    }
+
+    /// Returns at most the given amount of changed functions, from the most changed to the least
+    /// changed one.
+    ///
+    /// Functions with the same similarity are sorted by offset in the old binary.
+    pub fn most_changed(&self, amount: usize) -> Vec<&FunctionMatch> {
+        let mut changed = self
+            .matches
+            .iter()
+            .filter(|m| !m.identical)
+            .collect::<Vec<_>>();
+        // stable sort, so the matches remain sorted by offset
+        changed.sort_by(|a, b| a.similarity.total_cmp(&b.similarity));
+        changed.truncate(amount);
+        changed
+    }
+
+    /// Returns the time spent analyzing a pair of functions, if requested with
+    /// [`AnalysisConfig::record_timings`].
+    ///
+    /// The extraction and the structuring are the sum of the times of the two functions, while
+    /// the comparison is the time spent finding the changes and the similarity of the pair, see
+    /// [`Timings::comparison`]. The time spent pairing the functions is not included.
+    pub fn timings(&self, matched: &FunctionMatch) -> Option<&Timings> {
+        let key = (matched.old.get_offset(), matched.new.get_offset());
+        self.timings.get(&key)
+    }
+}
+
+// function compared by the diff.
+struct Entry<'a> {
+    function: &'a Function,
+    cfg: &'a CFG,
+    tree: StructureBlock,
+    hash: u64,
+    profile: Profile,
+    categories: Option<CategoryHistogram>,
+    timings: Timings,
+    // index of the entries directly called by this one, without duplicates
+    callees: Vec<usize>,
+}
+
+impl Entry<'_> {
+    fn name(&self) -> &str {
+        self.cfg
+            .metadata()
+            .map_or(self.function.get_name(), |meta| meta.name())
+    }
+}
+
+// signals used to compare two binaries when not requested: cross architecture only if both
+// architectures are known and different.
+fn detect_mode(old: &Binary, new: &Binary) -> ComparisonMode {
+    match (old.architecture(), new.architecture()) {
+        (Some(a), Some(b)) if a != b => ComparisonMode::CrossArchitecture,
+        _ => ComparisonMode::SameArchitecture,
+    }
+}
+
+// functions of the binary that can be compared, sorted by offset, until cancelled.
+fn entries<'a>(
+    binary: &'a Binary,
+    builder: &CfsBuilder,
+    token: &CancellationToken,
+) -> Vec<Entry<'a>> {
+    let mut entries = binary
+        .functions()
+        .iter()
+        .filter_map(|function| {
+            let cfg = binary.cfg(function.get_offset())?;
+            let cfs = builder.build(cfg).ok()?;
+            // the structure is not complete if cancelled while building it
+            if token.is_cancelled() {
+                return None;
+            }
+            let tree = cfs.get_tree()?;
+            let mut timings = cfs.timings().cloned().unwrap_or_default();
+            timings.extraction = binary.extraction_time(function.get_offset());
+            let hash = tree.structural_hash();
+            let profile = Profile::new(cfg, &tree);
+            let categories = cfg.function_categories();
+            Some(Entry {
+                function,
+                cfg,
+                tree,
+                hash,
+                profile,
+                categories,
+                timings,
+                callees: Vec::new(),
+            })
+        })
+        .collect::<Vec<_>>();
+    let index = entries
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| (entry.function.get_offset(), index))
+        .collect::<HashMap<_, _>>();
+    for entry in entries.iter_mut() {
+        let cfg = entry.cfg;
+        for call in cfg.bfs().flat_map(|bb| cfg.calls(bb)) {
+            if let CallTarget::Address(address) = call.target() {
+                if let Some(&callee) = index.get(address) {
+                    if !entry.callees.contains(&callee) {
+                        entry.callees.push(callee);
+                    }
+                }
+            }
+        }
+    }
+    entries
+}
+
+// index of the entries calling each entry.
+fn callers(entries: &[Entry]) -> Vec<Vec<usize>> {
+    let mut callers = vec![Vec::new(); entries.len()];
+    for (caller, entry) in entries.iter().enumerate() {
+        for &callee in &entry.callees {
+            callers[callee].push(caller);
+        }
+    }
+    callers
+}
+
+// best candidate pair for each function, by votes and then similarity, if not tied.
+fn best_candidates(
+    candidates: &[((usize, usize), (usize, f64))],
+    function: impl Fn(&(usize, usize)) -> usize,
+) -> HashMap<usize, Option<(usize, usize)>> {
+    // score of the best pair, the pair, and true if another pair has the same score
+    let mut best = HashMap::<usize, ((usize, f64), (usize, usize), bool)>::new();
+    for &(pair, score) in candidates {
+        let key = function(&pair);
+        match best.get_mut(&key) {
+            Some(found) => match score
+                .0
+                .cmp(&found.0 .0)
+                .then(score.1.total_cmp(&found.0 .1))
+            {
+                Ordering::Greater => *found = (score, pair, false),
+                Ordering::Equal => found.2 = true,
+                Ordering::Less => {}
+            },
+            None => {
+                best.insert(key, (score, pair, false));
+            }
+        }
+    }
+    best.into_iter()
+        .map(|(key, (_, pair, tied))| (key, (!tied).then_some(pair)))
+        .collect()
+}
+
+// index of the entries selected by the filter whose key appears only once.
+fn unique<K: Eq + std::hash::Hash>(
+    entries: &[Entry],
+    filter: impl Fn(usize) -> bool,
+    key: impl Fn(&Entry) -> K,
+) -> HashMap<K, usize> {
+    let mut indices = HashMap::new();
+    for (index, entry) in entries.iter().enumerate().filter(|(i, _)| filter(*i)) {
+        indices
+            .entry(key(entry))
+            .and_modify(|found: &mut Option<usize>| *found = None)
+            .or_insert(Some(index));
+    }
+    indices
+        .into_iter()
+        .filter_map(|(key, index)| Some((key, index?)))
+        .collect()
+}
+
+// amounts describing a function independently of its instruction set.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Profile {
+    blocks: usize,
+    loops: usize,
+    calls: usize,
+    callees: usize,
+}
+
+impl Profile {
+    fn new(cfg: &CFG, tree: &StructureBlock) -> Profile {
+        let metrics = tree.metrics();
+        let loops = [
+            BlockType::SelfLooping,
+            BlockType::While,
+            BlockType::DoWhile,
+            BlockType::SwitchLoop,
+        ]
+        .into_iter()
+        .map(|block_type| metrics.count(block_type))
+        .sum();
+        let calls = cfg
+            .bfs()
+            .flat_map(|bb| cfg.calls(bb))
+            .map(|call| call.target())
+            .collect::<Vec<_>>();
+        Profile {
+            blocks: cfg.bfs().filter(|bb| !bb.is_sink()).count(),
+            loops,
+            calls: calls.len(),
+            callees: calls.into_iter().collect::<HashSet<_>>().len(),
+        }
+    }
+
+    // average of the ratios between the smallest and the largest of each amount.
+    fn similarity(&self, other: &Profile) -> f64 {
+        let ratio = |a: usize, b: usize| {
+            if a == b {
+                1.0
+            } else {
+                a.min(b) as f64 / a.max(b) as f64
+            }
+        };
+        (ratio(self.blocks, other.blocks)
+            + ratio(self.loops, other.loops)
+            + ratio(self.calls, other.calls)
+            + ratio(self.callees, other.callees))
+            / 4.0
+    }
+}
+
+fn similarity(mode: ComparisonMode, weights: &SimilarityWeights, old: &Entry, new: &Entry) -> f64 {
+    let tree = tree_similarity_with_weights(&old.tree, &new.tree, weights);
+    let structure = match mode {
+        ComparisonMode::SameArchitecture => tree,
+        ComparisonMode::CrossArchitecture => {
+            let profile = old.profile.similarity(&new.profile);
+            CROSS_TREE_WEIGHT * tree + (1.0 - CROSS_TREE_WEIGHT) * profile
+        }
+    };
+    match (&old.categories, &new.categories) {
+        (Some(a), Some(b)) => {
+            (1.0 - CATEGORY_WEIGHT) * structure + CATEGORY_WEIGHT * a.similarity(b)
+        }
+        _ => structure,
+    }
+}
+
+fn identical(mode: ComparisonMode, old: &Entry, new: &Entry) -> bool {
+    let lengths = |cfg: &CFG| {
+        cfg.bfs()
+            .filter(|bb| !bb.is_sink())
+            .map(|bb| (bb.length, cfg.categories(bb).copied()))
+            .collect::<Vec<_>>()
+    };
+    let size = |entry: &Entry| entry.cfg.metadata().map(|meta| meta.size());
+    old.cfg.same_shape(new.cfg)
+        && match mode {
+            ComparisonMode::SameArchitecture => {
+                lengths(old.cfg) == lengths(new.cfg) && size(old) == size(new)
+            }
+            ComparisonMode::CrossArchitecture => old.profile.calls == new.profile.calls,
+        }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::test_utils::{binary, MockBinary};
+    use crate::analysis::{
+        AnalysisConfig, BinaryDiff, CancellationToken, ChangeKind, ComparisonMode, Graph, CFG,
+    };
+    use crate::disasm::radare2::BareCFG;
+    use crate::disasm::{Architecture, Binary, Disassembler, Function};
+
+    // the same functions of the wrapped binary, with the CFGs built from the blocks found by a
+    // disassembler, like radare2.
+    struct BareBinary(MockBinary);
+
+    impl Disassembler for BareBinary {
+        async fn functions(&mut self) -> Vec<Function> {
+            self.0.functions().await
+        }
+
+        async fn disassemble_function(&mut self, offset: u64) -> Option<CFG> {
+            let cfg = self.0.disassemble_function(offset).await?;
+            let blocks = cfg.blocks_sorted();
+            let bare = BareCFG {
+                root: cfg.root().map(|bb| bb.offset),
+                blocks: blocks.iter().map(|bb| (bb.offset, bb.length)).collect(),
+                edges: blocks
+                    .iter()
+                    .flat_map(|src| {
+                        cfg.neighbours(src)
+                            .iter()
+                            .map(|dst| (src.offset, dst.offset))
+                    })
+                    .collect(),
+            };
+            let stmts = self.0.statements(offset)?;
+            Some(CFG::with_statements(bare, &stmts, Architecture::X86(64)))
+        }
+
+        async fn architecture(&mut self) -> Option<Architecture> {
+            self.0.architecture().await
+        }
+    }
+
+    async fn extract(mut mock: MockBinary) -> Binary {
+        Binary::extract(&mut mock).await
+    }
+
+    async fn extract_bare(mock: MockBinary) -> Binary {
+        Binary::extract(&mut BareBinary(mock)).await
+    }
+
+    async fn binary_for(arch: Architecture, functions: Vec<(u64, &'static str, u64)>) -> Binary {
+        extract(MockBinary {
+            arch,
+            ..MockBinary::new(functions)
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn report() {
+        let old = binary(vec![
+            (0x100, "main", 5),
+            (0x200, "parse", 5),
+            (0x300, "fcn.00000300", 0),
+            (0x400, "removed", 3),
+        ])
+        .await;
+        let new = binary(vec![
+            (0x1100, "main", 5),
+            (0x1200, "parse", 7),
+            (0x1300, "fcn.00001300", 0),
+        ])
+        .await;
+        let diff = BinaryDiff::new(&old, &new);
+        assert_eq!(diff.mode(), ComparisonMode::SameArchitecture);
+        assert_eq!(diff.matches().len(), 3);
+        assert_eq!(diff.identical(), 2);
+        assert_eq!(diff.changed(), 1);
+        assert!(diff.added().is_empty());
+        assert_eq!(diff.removed()[0].get_name(), "removed");
+        let changed = diff.most_changed(10);
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].old_function().get_name(), "parse");
+        assert_eq!(changed[0].new_function().get_offset(), 0x1200);
+        // the structure is the same, only the size changed
+        assert_eq!(changed[0].similarity(), 1.0);
+        let changes = changed[0].changes();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].kind(), ChangeKind::Modified);
+        assert_eq!(changes[0].new_ranges(), vec![0x1202..0x1209]);
+        assert_eq!(diff.similarity(), 6.0 / 7.0);
+        let unnamed = &diff.matches()[2];
+        assert_eq!(unnamed.new_function().get_name(), "fcn.00001300");
+        assert!(unnamed.is_identical());
+    }
+
+    #[tokio::test]
+    async fn empty() {
+        let diff = BinaryDiff::new(&binary(Vec::new()).await, &binary(Vec::new()).await);
+        assert_eq!(diff.similarity(), 1.0);
+        assert!(diff.most_changed(1).is_empty());
+    }
+
+    #[tokio::test]
+    async fn cross_architecture() {
+        let old = binary(vec![(0x100, "main", 5), (0x200, "parse", 0)]).await;
+        let new = binary_for(
+            Architecture::Arm(32),
+            vec![(0x100, "main", 8), (0x200, "parse", 0)],
+        )
+        .await;
+        let diff = BinaryDiff::new(&old, &new);
+        assert_eq!(diff.mode(), ComparisonMode::CrossArchitecture);
+        assert_eq!(diff.matches().len(), 2);
+        // the length of the blocks depends on the instruction set
+        assert_eq!(diff.identical(), 2);
+        assert_eq!(diff.similarity(), 1.0);
+        let same = BinaryDiff::with_mode(&old, &new, ComparisonMode::SameArchitecture, 0.5);
+        assert_eq!(same.identical(), 1);
+    }
+
+    #[tokio::test]
+    async fn cross_architecture_bare() {
+        let functions = vec![(0x100, "main", 5), (0x200, "parse", 0)];
+        let binary = |arch, calls| {
+            extract_bare(MockBinary {
+                arch,
+                functions: functions.clone(),
+                loads: Vec::new(),
+                calls,
+            })
+        };
+        let old = binary(Architecture::X86(64), vec![(0x100, 0x200)]).await;
+        let same = binary(Architecture::Arm(32), vec![(0x100, 0x200)]).await;
+        let more = binary(Architecture::Arm(32), vec![(0x100, 0x200), (0x100, 0x200)]).await;
+        let diff = BinaryDiff::new(&old, &same);
+        assert_eq!(diff.mode(), ComparisonMode::CrossArchitecture);
+        assert_eq!(diff.identical(), 2);
+        // same structure, but the new main calls parse twice
+        let diff = BinaryDiff::new(&old, &more);
+        assert_eq!(diff.identical(), 1);
+        assert!(diff.similarity() < 1.0);
+    }
+
+    #[tokio::test]
+    async fn with_config() {
+        let old = binary(vec![(0x100, "main", 5), (0x200, "parse", 0)]).await;
+        let new = binary_for(
+            Architecture::Arm(32),
+            vec![(0x100, "main", 8), (0x200, "parse", 0)],
+        )
+        .await;
+        let config = AnalysisConfig::new();
+        let diff = BinaryDiff::with_config(&old, &new, &config);
+        assert_eq!(diff.mode(), ComparisonMode::CrossArchitecture);
+        let config = config.with_mode(ComparisonMode::SameArchitecture);
+        let diff = BinaryDiff::with_config(&old, &new, &config);
+        assert_eq!(diff.mode(), ComparisonMode::SameArchitecture);
+        assert_eq!(diff.identical(), 1);
+        assert!(diff.timings(&diff.matches()[0]).is_none());
+    }
+
+    #[tokio::test]
+    async fn timings() {
+        let old = binary(vec![(0x100, "main", 5), (0x200, "parse", 0)]).await;
+        let new = binary(vec![(0x100, "main", 8), (0x200, "parse", 0)]).await;
+        let config = AnalysisConfig::new().record_timings();
+        let diff = BinaryDiff::with_config(&old, &new, &config);
+        for matched in diff.matches() {
+            let timings = diff.timings(matched).unwrap();
+            let extraction = old
+                .extraction_time(matched.old_function().get_offset())
+                .unwrap()
+                + new
+                    .extraction_time(matched.new_function().get_offset())
+                    .unwrap();
+            assert_eq!(timings.extraction(), Some(extraction));
+            assert!(timings.comparison().is_some());
+            assert!(timings.total() >= timings.structuring());
+        }
+        assert!(diff
+            .timings(&diff.matches()[0])
+            .unwrap()
+            .reductions()
+            .iter()
+            .any(|(name, _)| *name == "If-Then"));
+    }
+
+    #[tokio::test]
+    async fn cancellation() {
+        let old = binary(vec![(0x100, "main", 5), (0x200, "parse", 0)]).await;
+        let new = binary(vec![(0x100, "main", 8), (0x200, "parse", 0)]).await;
+        let config = AnalysisConfig::new();
+        let token = CancellationToken::new();
+        let diff = BinaryDiff::with_cancellation(&old, &new, &config, &token);
+        assert!(diff.is_complete());
+        assert_eq!(diff.matches().len(), 2);
+        token.cancel();
+        let diff = BinaryDiff::with_cancellation(&old, &new, &config, &token);
+        assert!(!diff.is_complete());
+        assert!(diff.matches().is_empty());
+        assert!(diff.added().is_empty());
+        assert!(diff.removed().is_empty());
+    }
+
+    #[tokio::test]
+    async fn different_bodies() {
+        let old = binary(vec![(0x100, "main", 5)]).await;
+        let new = extract(MockBinary {
+            arch: Architecture::X86(64),
+            functions: vec![(0x100, "main", 5)],
+            loads: vec![0x100],
+            calls: Vec::new(),
+        })
+        .await;
+        let diff = BinaryDiff::new(&old, &new);
+        let matched = &diff.matches()[0];
+        // same structure and lengths, but a load instead of a move
+        assert!(!matched.is_identical());
+        assert!((matched.similarity() - (0.75 + 0.25 * 2.0 / 3.0)).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn different_bodies_bare() {
+        let mock = |loads| MockBinary {
+            arch: Architecture::X86(64),
+            functions: vec![(0x100, "main", 5)],
+            loads,
+            calls: Vec::new(),
+        };
+        let old = extract_bare(mock(Vec::new())).await;
+        let new = extract_bare(mock(vec![0x100])).await;
+        let diff = BinaryDiff::new(&old, &new);
+        let matched = &diff.matches()[0];
+        assert!(!matched.is_identical());
+        assert!((matched.similarity() - (0.75 + 0.25 * 2.0 / 3.0)).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn call_graph() {
+        let functions = vec![
+            (0x100, "main", 5),
+            (0x200, "parse", 7),
+            (0x400, "fcn.00000400", 0),
+            (0x500, "fcn.00000500", 0),
+        ];
+        let binary = |calls| {
+            let mock = MockBinary {
+                arch: Architecture::X86(64),
+                functions: functions.clone(),
+                loads: Vec::new(),
+                calls,
+            };
+            extract(mock)
+        };
+        let old = binary(vec![(0x100, 0x400), (0x200, 0x500)]).await;
+        // the two helpers are identical, and swapped in the new binary
+        let new = binary(vec![(0x100, 0x500), (0x200, 0x400)]).await;
+        let diff = BinaryDiff::new(&old, &new);
+        assert_eq!(diff.matches().len(), 4);
+        let pair = |offset| {
+            diff.matches()
+                .iter()
+                .find(|m| m.old_function().get_offset() == offset)
+                .map(|m| m.new_function().get_offset())
+        };
+        assert_eq!(pair(0x400), Some(0x500));
+        assert_eq!(pair(0x500), Some(0x400));
+    }
+
+    #[tokio::test]
+    async fn call_graph_bare() {
+        let functions = vec![
+            (0x100, "main", 5),
+            (0x200, "parse", 7),
+            (0x400, "fcn.00000400", 0),
+            (0x500, "fcn.00000500", 0),
+        ];
+        let binary = |calls| {
+            extract_bare(MockBinary {
+                arch: Architecture::X86(64),
+                functions: functions.clone(),
+                loads: Vec::new(),
+                calls,
+            })
+        };
+        let old = binary(vec![(0x100, 0x400), (0x200, 0x500)]).await;
+        let new = binary(vec![(0x100, 0x500), (0x200, 0x400)]).await;
+        let diff = BinaryDiff::new(&old, &new);
+        let pair = |offset| {
+            diff.matches()
+                .iter()
+                .find(|m| m.old_function().get_offset() == offset)
+                .map(|m| m.new_function().get_offset())
+        };
+        // the helpers are told apart only by their callers
+        assert_eq!(pair(0x400), Some(0x500));
+        assert_eq!(pair(0x500), Some(0x400));
+    }
+}
@@ -0,0 +1,275 @@
+use crate::analysis::diff::GENERATED_PREFIX;
+use crate::analysis::{BinaryDiff, ComparisonMode};
+use crate::disasm::Binary;
+use std::collections::HashMap;
+
+/// Known pairing between the functions of two binaries, used to evaluate a [`BinaryDiff`].
+///
+/// The pairing is usually obtained by compiling the same source twice, for example with two
+/// versions of the compiler, keeping the symbols: [`GroundTruth::from_symbols`] pairs the
+/// functions with the same name. The two binaries are then stripped and compared with
+/// [`GroundTruth::evaluate`], so the diff can not rely on the names.
+/// # Examples
+/// Basic usage:
+/// ```
+/// use bincc::analysis::GroundTruth;
+///
+/// let truth = GroundTruth::new([(0x100, 0x1100), (0x200, 0x1200)]);
+///
+/// assert_eq!(truth.len(), 2);
+/// assert_eq!(truth.get(0x200), Some(0x1200));
+/// assert_eq!(truth.get(0x300), None);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(docsrs, doc(cfg(feature = "disasm")))]
+pub struct GroundTruth {
+    // offset in the old binary -> offset in the new binary
+    old_to_new: HashMap<u64, u64>,
+    // offset in the new binary -> offset in the old binary
+    new_to_old: HashMap<u64, u64>,
+}
+
+impl GroundTruth {
+    /// Creates the ground truth from the given pairs of offsets of the old and the new binary.
+    ///
+    /// If an offset appears in multiple pairs, only the last one is kept.
+    pub fn new<I: IntoIterator<Item = (u64, u64)>>(pairs: I) -> GroundTruth {
+        let mut truth = GroundTruth::default();
+        for (old, new) in pairs {
+            if let Some(previous) = truth.old_to_new.insert(old, new) {
+                truth.new_to_old.remove(&previous);
+            }
+            if let Some(previous) = truth.new_to_old.insert(new, old) {
+                truth.old_to_new.remove(&previous);
+            }
+        }
+        truth
+    }
+
+    /// Creates the ground truth pairing the functions with the same name in the two binaries.
+    ///
+    /// Names appearing more than once in a binary, and the names generated by the disassembler
+    /// for functions without a symbol, are ignored.
+    pub fn from_symbols(old: &Binary, new: &Binary) -> GroundTruth {
+        let old_names = unique_names(old);
+        let new_names = unique_names(new);
+        GroundTruth::new(
+            old_names
+                .into_iter()
+                .filter_map(|(name, old)| Some((old, *new_names.get(name)?))),
+        )
+    }
+
+    /// Returns the offset in the new binary of the function at the given offset of the old one.
+    pub fn get(&self, old: u64) -> Option<u64> {
+        self.old_to_new.get(&old).copied()
+    }
+
+    /// Returns the amount of paired functions.
+    pub fn len(&self) -> usize {
+        self.old_to_new.len()
+    }
+
+    /// Returns true if no functions are paired.
+    pub fn is_empty(&self) -> bool {
+        self.old_to_new.is_empty()
+    }
+
+    /// Compares the two binaries with the given signals and threshold, as done by
+    /// [`BinaryDiff::with_mode`], and evaluates the result against this ground truth.
+    ///
+    /// The binaries should be stripped of their symbols, otherwise the functions are paired by
+    /// name and the evaluation measures only the quality of the symbols.
+    pub fn evaluate(
+        &self,
+        old: &Binary,
+        new: &Binary,
+        mode: ComparisonMode,
+        threshold: f64,
+    ) -> Evaluation {
+        Evaluation::new(&BinaryDiff::with_mode(old, new, mode, threshold), self)
+    }
+}
+
+/// Quality of the pairs found by a [`BinaryDiff`], measured against a [`GroundTruth`].
+///
+/// Each pair found by the diff is:
+/// - a true positive, if it is part of the ground truth.
+/// - a false positive, if any of its functions is paired differently in the ground truth.
+/// - ignored, if none of its functions is part of the ground truth.
+///
+/// Each pair of the ground truth not found by the diff is a false negative.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(docsrs, doc(cfg(feature = "disasm")))]
+pub struct Evaluation {
+    true_positives: Vec<(u64, u64)>,
+    false_positives: Vec<(u64, u64)>,
+    false_negatives: Vec<(u64, u64)>,
+}
+
+impl Evaluation {
+    /// Evaluates the pairs found by the given diff against the given ground truth.
+    pub fn new(diff: &BinaryDiff, truth: &GroundTruth) -> Evaluation {
+        let mut evaluation = Evaluation::default();
+        for matched in diff.matches() {
+            let old = matched.old_function().get_offset();
+            let new = matched.new_function().get_offset();
+            let pair = (old, new);
+            match (truth.old_to_new.get(&old), truth.new_to_old.get(&new)) {
+                (Some(&expected), _) if expected == new => evaluation.true_positives.push(pair),
+                (None, None) => {}
+                _ => evaluation.false_positives.push(pair),
+            }
+        }
+        let mut missing = truth
+            .old_to_new
+            .iter()
+            .map(|(&old, &new)| (old, new))
+            .filter(|pair| evaluation.true_positives.binary_search(pair).is_err())
+            .collect::<Vec<_>>();
+        missing.sort_unstable();
+        evaluation.false_negatives = missing;
+        evaluation
+    }
+
+    /// Returns the pairs found by the diff and part of the ground truth, sorted by offset in the
+    /// old binary.
+    pub fn true_positives(&self) -> &[(u64, u64)] {
+        &self.true_positives
+    }
+
+    /// Returns the pairs found by the diff but paired differently in the ground truth, sorted by
+    /// offset in the old binary.
+    pub fn false_positives(&self) -> &[(u64, u64)] {
+        &self.false_positives
+    }
+
+    /// Returns the pairs of the ground truth not found by the diff, sorted by offset in the old
+    /// binary.
+    pub fn false_negatives(&self) -> &[(u64, u64)] {
+        &self.false_negatives
+    }
+
+    /// Returns the fraction of the evaluated pairs found by the diff that are correct.
+    ///
+    /// Returns 0 if the diff found no evaluated pairs.
+    pub fn precision(&self) -> f64 {
+        ratio(
+            self.true_positives.len(),
+            self.true_positives.len() + self.false_positives.len(),
+        )
+    }
+
+    /// Returns the fraction of the pairs of the ground truth found by the diff.
+    ///
+    /// Returns 0 if the ground truth is empty.
+    pub fn recall(&self) -> f64 {
+        ratio(
+            self.true_positives.len(),
+            self.true_positives.len() + self.false_negatives.len(),
+        )
+    }
+
+    /// Returns the harmonic mean of [`Evaluation::precision`] and [`Evaluation::recall`].
+    pub fn f1(&self) -> f64 {
+        let precision = self.precision();
+        let recall = self.recall();
+        if precision + recall == 0.0 {
+            0.0
+        } else {
+            2.0 * precision * recall / (precision + recall)
+        }
+    }
+}
+
+fn ratio(a: usize, b: usize) -> f64 {
+    if b == 0 {
+        0.0
+    } else {
+        a as f64 / b as f64
+    }
+}
+
+// offset of the functions whose name appears only once, excluding the generated names.
+fn unique_names(binary: &Binary) -> HashMap<&str, u64> {
+    let mut names = HashMap::new();
+    for function in binary.functions() {
+        names
+            .entry(function.get_name())
+            .and_modify(|found: &mut Option<u64>| *found = None)
+            .or_insert(Some(function.get_offset()));
+    }
+    names
+        .into_iter()
+        .filter(|(name, _)| !name.starts_with(GENERATED_PREFIX))
+        .filter_map(|(name, offset)| Some((name, offset?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::test_utils::binary;
+    use crate::analysis::{BinaryDiff, ComparisonMode, Evaluation, GroundTruth};
+
+    #[tokio::test]
+    async fn from_symbols() {
+        let old = binary(vec![
+            (0x100, "main", 5),
+            (0x200, "dup", 0),
+            (0x300, "dup", 0),
+            (0x400, "fcn.00000400", 0),
+        ])
+        .await;
+        let new = binary(vec![
+            (0x1100, "main", 5),
+            (0x1200, "dup", 0),
+            (0x1400, "fcn.00000400", 0),
+        ])
+        .await;
+        let truth = GroundTruth::from_symbols(&old, &new);
+        assert_eq!(truth, GroundTruth::new([(0x100, 0x1100)]));
+    }
+
+    #[tokio::test]
+    async fn evaluate() {
+        let old = binary(vec![
+            (0x100, "fcn.00000100", 5),
+            (0x200, "fcn.00000200", 0),
+            (0x300, "fcn.00000300", 3),
+        ])
+        .await;
+        let new = binary(vec![
+            (0x1100, "fcn.00001100", 5),
+            (0x1200, "fcn.00001200", 0),
+            (0x1300, "fcn.00001300", 3),
+        ])
+        .await;
+        let truth = GroundTruth::new([(0x100, 0x1100), (0x200, 0x1300), (0x300, 0x1200)]);
+        let evaluation = truth.evaluate(&old, &new, ComparisonMode::SameArchitecture, 0.5);
+        assert_eq!(evaluation.true_positives(), &[(0x100, 0x1100)]);
+        assert_eq!(
+            evaluation.false_positives(),
+            &[(0x200, 0x1200), (0x300, 0x1300)]
+        );
+        assert_eq!(
+            evaluation.false_negatives(),
+            &[(0x200, 0x1300), (0x300, 0x1200)]
+        );
+        assert_eq!(evaluation.precision(), 1.0 / 3.0);
+        assert_eq!(evaluation.recall(), 1.0 / 3.0);
+        assert!((evaluation.f1() - 1.0 / 3.0).abs() < 1e-9);
+        let diff = BinaryDiff::new(&old, &new);
+        let perfect = GroundTruth::new([(0x100, 0x1100), (0x200, 0x1200), (0x300, 0x1300)]);
+        let evaluation = Evaluation::new(&diff, &perfect);
+        assert_eq!(evaluation.f1(), 1.0);
+        assert_eq!(Evaluation::new(&diff, &GroundTruth::default()).f1(), 0.0);
+    }
+
+    #[test]
+    fn duplicated_pairs() {
+        let truth = GroundTruth::new([(0x100, 0x1100), (0x100, 0x1200), (0x300, 0x1200)]);
+        assert_eq!(truth.len(), 1);
+        assert_eq!(truth.get(0x100), None);
+        assert_eq!(truth.get(0x300), Some(0x1200));
+    }
+}
@@ -0,0 +1,346 @@
+use crate::analysis::blocks::StructureBlock;
+use crate::analysis::cfg::BlockInfo;
+use crate::analysis::{BasicBlock, BlockType, CFG};
+use std::collections::HashMap;
+
+impl StructureBlock {
+    /// Re-expands this tree into a [`CFG`].
+    ///
+    /// Every structure is replaced by the edges it represents: for example, a
+    /// [`BlockType::Sequence`] links each child to the next one, and a [`BlockType::While`] links
+    /// its condition to the body and to the block following the loop. The first edge of a
+    /// conditional block is the fallthrough, and the second one is the jump, as recorded by
+    /// [`StructureBlock::condition_polarity`]. When the polarity is unknown, the jump is assumed
+    /// to enter the then block or the loop body.
+    ///
+    /// The edges removed while structuring the loops are re-created only if recorded by their
+    /// [`BlockType::Break`], [`BlockType::Continue`] or [`BlockType::Goto`] marker, as done by
+    /// [`CfsBuilder::preserve_loops`](crate::analysis::CfsBuilder::preserve_loops). The edges
+    /// represented by [`BlockType::AbnormalEntry`] markers are never re-created, and duplicated
+    /// blocks are merged with their original. For this reason, the CFG obtained from a tree built
+    /// by a [`CFS`](crate::analysis::CFS) matches the input of the structuring after removing
+    /// the edges returned by [`CFS::removed_edges`](crate::analysis::CFS::removed_edges).
+    ///
+    /// Returns [`None`] if the tree contains a [`BlockType::ProperInterval`],
+    /// [`BlockType::ImproperInterval`], [`BlockType::ImproperRegion`], [`BlockType::Conditional`]
+    /// or [`BlockType::Custom`], as the edges between their children are not recorded in the
+    /// tree.
+    ///
+    /// The blocks wrapped into a [`BlockType::Return`] or [`BlockType::Unreachable`] are recorded
+    /// as terminators of the resulting [`CFG`], see [`CFG::terminator`].
+    /// # Examples
+    /// Basic usage:
+    /// ```
+    /// use bincc::analysis::{BasicBlock, BlockType, Graph, NestedBlock, StructureBlock};
+    /// use std::sync::Arc;
+    ///
+    /// let bb0 = BasicBlock { offset: 0x10, length: 4 };
+    /// let bb1 = BasicBlock { offset: 0x14, length: 8 };
+    /// let seq = NestedBlock::new(
+    ///     BlockType::Sequence,
+    ///     vec![StructureBlock::from(bb0), StructureBlock::from(bb1)],
+    /// );
+    /// let cfg = StructureBlock::from(Arc::new(seq)).to_cfg().unwrap();
+    ///
+    /// assert_eq!(cfg.len(), 2);
+    /// assert_eq!(cfg.next(Some(&bb0)), Some(&bb1));
+    /// ```
+    pub fn to_cfg(&self) -> Option<CFG> {
+        // blocks leaving the function reach the sink, if any
+        let returns = self
+            .iter_preorder()
+            .filter_map(|node| match node {
+                StructureBlock::Basic(bb) if bb.is_sink() => Some(*bb),
+                _ => None,
+            })
+            .take(1)
+            .collect();
+        let mut expander = Expander {
+            edges: HashMap::new(),
+            returns,
+            info: HashMap::new(),
+        };
+        expander.expand(self, &[])?;
+        // edges preserved by the loop markers
+        let mut edges = expander.edges;
+        for node in self.iter_preorder() {
+            if matches!(
+                node.block_type(),
+                BlockType::Break | BlockType::Continue | BlockType::Goto
+            ) {
+                for (src, dst) in node.exits() {
+                    let targets = edges.entry(*src).or_default();
+                    if !targets.contains(dst) {
+                        targets.push(*dst);
+                    }
+                    edges.entry(*dst).or_default();
+                }
+            }
+        }
+        Some(CFG {
+            root: Some(entry(self)?),
+            edges,
+            info: expander.info,
+            metadata: None,
+        })
+    }
+}
+
+struct Expander {
+    edges: HashMap<BasicBlock, Vec<BasicBlock>>,
+    returns: Vec<BasicBlock>,
+    info: HashMap<BasicBlock, BlockInfo>,
+}
+
+impl Expander {
+    // adds the edges of the given node, knowing the successors of its last block.
+    fn expand(&mut self, node: &StructureBlock, succs: &[BasicBlock]) -> Option<()> {
+        if let StructureBlock::Basic(bb) = node {
+            let targets = self.edges.entry(*bb).or_default();
+            for succ in succs {
+                if !targets.contains(succ) {
+                    targets.push(*succ);
+                }
+            }
+            return Some(());
+        }
+        let children = node
+            .children()
+            .iter()
+            .filter(|child| {
+                !matches!(
+                    child.block_type(),
+                    BlockType::Break
+                        | BlockType::Continue
+                        | BlockType::Goto
+                        | BlockType::AbnormalEntry
+                )
+            })
+            .collect::<Vec<_>>();
+        let exit = succs.first().copied();
+        let jump_enters = node.condition_polarity() != Some(false);
+        match node.block_type() {
+            BlockType::Sequence | BlockType::Condition | BlockType::IfChain => {
+                for pair in children.windows(2) {
+                    self.expand(pair[0], &[entry(pair[1])?])?;
+                }
+                match children.last() {
+                    Some(last) => self.expand(last, succs),
+                    None => Some(()),
+                }
+            }
+            BlockType::Duplicate => self.expand(children.first()?, succs),
+            BlockType::Return | BlockType::Unreachable => {
+                let bb = entry(node)?;
+                let info = BlockInfo {
+                    instructions: None,
+                    terminator: Some(node.block_type()),
+                    ..Default::default()
+                };
+                self.info.insert(bb, info);
+                self.expand(children.first()?, succs)
+            }
+            BlockType::SelfLooping => {
+                let body = children.first()?;
+                let targets = exit.into_iter().chain([entry(body)?]).collect::<Vec<_>>();
+                self.expand(body, &targets)
+            }
+            BlockType::IfThen => {
+                let (then, conds) = children.split_last()?;
+                self.chain(&conditions(conds), entry(then)?, exit, jump_enters)?;
+                self.expand(then, succs)
+            }
+            BlockType::IfThenElse => {
+                let (elseb, children) = children.split_last()?;
+                let (then, conds) = children.split_last()?;
+                self.chain(&conditions(conds), entry(then)?, entry(elseb), jump_enters)?;
+                self.expand(then, succs)?;
+                self.expand(elseb, succs)
+            }
+            BlockType::SwitchLoop => {
+                // loop around a switch: the head of the switch is also the exit of the loop
+                let body = children[0];
+                let head = entry(body)?;
+                self.expand(body, &[head])?;
+                if let Some(exit) = exit {
+                    let targets = self.edges.entry(head).or_default();
+                    if !targets.contains(&exit) {
+                        targets.insert(0, exit);
+                    }
+                }
+                Some(())
+            }
+            BlockType::While => {
+                let (head, tail) = (children.first()?, children.get(1)?);
+                self.chain(&conditions(&[head]), entry(tail)?, exit, jump_enters)?;
+                self.expand(tail, &[entry(head)?])
+            }
+            BlockType::DoWhile => {
+                let (head, tail) = (children.first()?, children.get(1)?);
+                self.expand(head, &[entry(tail)?])?;
+                match children.get(2) {
+                    Some(post) => {
+                        self.chain(&conditions(&[tail]), entry(post)?, exit, true)?;
+                        self.expand(post, &[entry(head)?])
+                    }
+                    None => self.chain(&conditions(&[tail]), entry(head)?, exit, true),
+                }
+            }
+            BlockType::Switch => {
+                let (head, cases) = children.split_first()?;
+                let targets = cases
+                    .iter()
+                    .map(|case| entry(case))
+                    .collect::<Option<Vec<_>>>()?;
+                self.expand(head, &targets)?;
+                for case in cases {
+                    self.expand(case, succs)?;
+                }
+                Some(())
+            }
+            BlockType::Guard => {
+                let (cond, ret) = (children.first()?, children.get(1)?);
+                let ret_entry = entry(ret)?;
+                let targets = exit.into_iter().chain([ret_entry]).collect::<Vec<_>>();
+                self.expand(cond, &targets)?;
+                if ret_entry.is_sink() {
+                    Some(())
+                } else {
+                    let returns = self.returns.clone();
+                    self.expand(ret, &returns)
+                }
+            }
+            BlockType::Break | BlockType::Continue | BlockType::Goto | BlockType::AbnormalEntry => {
+                Some(())
+            }
+            BlockType::Basic
+            | BlockType::ProperInterval
+            | BlockType::ImproperInterval
+            | BlockType::ImproperRegion
+            | BlockType::Conditional
+            | BlockType::Custom(_) => None,
+        }
+    }
+
+    // adds the edges of a short-circuit condition: every block continues to the next one (the
+    // last to the body) or skips to the given block.
+    fn chain(
+        &mut self,
+        conds: &[&StructureBlock],
+        body: BasicBlock,
+        skip: Option<BasicBlock>,
+        jump_enters: bool,
+    ) -> Option<()> {
+        for (index, cond) in conds.iter().enumerate() {
+            let cont = match conds.get(index + 1) {
+                Some(next) => entry(next)?,
+                None => body,
+            };
+            let targets = match skip {
+                Some(skip) if jump_enters => vec![skip, cont],
+                Some(skip) => vec![cont, skip],
+                None => vec![cont],
+            };
+            self.expand(cond, &targets)?;
+        }
+        Some(())
+    }
+}
+
+// blocks computing a condition, in evaluation order.
+fn conditions<'a>(conds: &[&'a StructureBlock]) -> Vec<&'a StructureBlock> {
+    conds
+        .iter()
+        .flat_map(|cond| match cond.block_type() {
+            BlockType::Condition | BlockType::IfChain => cond.children().iter().collect(),
+            _ => vec![*cond],
+        })
+        .collect()
+}
+
+// first basic block executed by a structure.
+fn entry(node: &StructureBlock) -> Option<BasicBlock> {
+    node.iter_preorder().find_map(|node| match node {
+        StructureBlock::Basic(bb) => Some(*bb),
+        StructureBlock::Nested(_) => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::{
+        BasicBlock, BlockType, CfsBuilder, NestedBlock, StructureBlock, CFG, CFS,
+    };
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    fn bb(offset: u64) -> BasicBlock {
+        BasicBlock { offset, length: 1 }
+    }
+
+    fn cfg(edges: &[&[u64]]) -> CFG {
+        let edges = edges
+            .iter()
+            .enumerate()
+            .map(|(src, dsts)| (bb(src as u64), dsts.iter().map(|dst| bb(*dst)).collect()))
+            .collect::<HashMap<_, _>>();
+        CFG {
+            root: Some(bb(0)),
+            edges,
+            info: HashMap::new(),
+            metadata: None,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let cfg = cfg(&[&[1], &[2, 3], &[4], &[4], &[6, 5], &[4], &[7, 6], &[]]);
+        let cfs = CFS::new(&cfg).unwrap();
+        let expanded = cfs.get_tree().unwrap().to_cfg().unwrap();
+        assert_eq!(expanded.root, cfg.root);
+        assert_eq!(expanded.edges, cfg.edges);
+    }
+
+    #[test]
+    fn round_trip_short_circuit() {
+        let cfg = cfg(&[&[4, 1], &[4, 2], &[3], &[4], &[]]);
+        let cfs = CFS::new(&cfg).unwrap();
+        let tree = cfs.get_tree().unwrap();
+        assert_eq!(
+            tree.children()[0].children()[0].block_type(),
+            BlockType::IfChain
+        );
+        let expanded = tree.to_cfg().unwrap();
+        assert_eq!(expanded.edges, cfg.edges);
+        assert!(CFS::new(&expanded)
+            .unwrap()
+            .get_tree()
+            .unwrap()
+            .structural_equality(&tree));
+    }
+
+    #[test]
+    fn removed_edges_not_expanded() {
+        let original = cfg(&[&[1], &[2, 5], &[3], &[4, 5], &[1], &[]]);
+        let cfs = CFS::new(&original).unwrap();
+        let expanded = cfs.get_tree().unwrap().to_cfg().unwrap();
+        let denaturated = cfg(&[&[1], &[2, 5], &[3], &[4], &[1], &[]]);
+        assert_eq!(expanded.edges, denaturated.edges);
+    }
+
+    #[test]
+    fn preserved_edges_expanded() {
+        let original = cfg(&[&[1], &[2, 5], &[3], &[4, 6], &[1], &[6], &[]]);
+        let cfs = CfsBuilder::new().preserve_loops().build(&original).unwrap();
+        assert!(cfs.removed_edges().is_empty());
+        let expanded = cfs.get_tree().unwrap().to_cfg().unwrap();
+        assert_eq!(expanded.edges, original.edges);
+    }
+
+    #[test]
+    fn unstructured() {
+        let content = vec![StructureBlock::from(bb(0)), StructureBlock::from(bb(1))];
+        let interval = NestedBlock::new(BlockType::ProperInterval, content);
+        assert!(StructureBlock::from(Arc::new(interval)).to_cfg().is_none());
+    }
+}
@@ -0,0 +1,136 @@
+use crate::analysis::{Graph, CFS};
+
+// iterations of the Weisfeiler-Lehman hash, see CFG::wl_hash.
+const WL_ITERATIONS: usize = 3;
+
+/// Compact summary of a function, used to search similar functions in large corpora.
+///
+/// The fingerprint contains the [`StructureBlock::structural_hash`] of the [`CFS`] tree, the
+/// [`CFG::wl_hash`] of the [`CFG`], and a few metrics about both of them. Two functions with
+/// different fingerprints have different structures, while functions with the same fingerprint
+/// are likely clones.
+/// # Examples
+/// Basic usage:
+/// ```
+/// use bincc::analysis::{Fingerprint, CFG, CFS};
+/// use bincc::disasm::{Architecture, Statement, StatementFamily};
+///
+/// let stmts = vec![
+///     Statement::new(0x10, StatementFamily::CJMP, "je 0x14"),
+///     Statement::new(0x12, StatementFamily::MOV, "mov eax, 0"),
+///     Statement::new(0x14, StatementFamily::RET, "ret"),
+/// ];
+/// let cfg = CFG::new(&stmts, 0x15, Architecture::X86(64));
+/// let fingerprint = Fingerprint::new(&CFS::new(&cfg).unwrap()).unwrap();
+///
+/// assert_eq!(fingerprint.blocks(), 3);
+/// assert_eq!(fingerprint.edges(), 3);
+/// assert_eq!(fingerprint.wl_hash(), cfg.wl_hash(3));
+/// ```
+///
+/// [`StructureBlock::structural_hash`]: crate::analysis::StructureBlock::structural_hash
+/// [`CFG`]: crate::analysis::CFG
+/// [`CFG::wl_hash`]: crate::analysis::CFG::wl_hash
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Fingerprint {
+    pub(super) structural_hash: u64,
+    pub(super) wl_hash: u64,
+    pub(super) blocks: u32,
+    pub(super) edges: u32,
+    pub(super) depth: u32,
+    pub(super) nodes: u32,
+}
+
+impl Fingerprint {
+    /// Creates the fingerprint of the function represented by the given [`CFS`].
+    ///
+    /// The [`CFG::wl_hash`](crate::analysis::CFG::wl_hash) is computed with 3 iterations.
+    /// Returns [`None`] if the [`CFS`] is not a tree, see [`CFS::get_tree`].
+    pub fn new(cfs: &CFS) -> Option<Fingerprint> {
+        let tree = cfs.get_tree()?;
+        let cfg = cfs.get_cfg();
+        let metrics = tree.metrics();
+        let edges = cfg.bfs().map(|bb| cfg.neighbours(bb).len()).sum::<usize>();
+        Some(Fingerprint {
+            structural_hash: tree.structural_hash(),
+            wl_hash: cfg.wl_hash(WL_ITERATIONS),
+            blocks: cfg.len() as u32,
+            edges: edges as u32,
+            depth: metrics.depth(),
+            nodes: metrics.nodes() as u32,
+        })
+    }
+
+    /// Returns the structural hash of the [`CFS`] tree.
+    pub fn structural_hash(&self) -> u64 {
+        self.structural_hash
+    }
+
+    /// Returns the Weisfeiler-Lehman hash of the [`CFG`](crate::analysis::CFG).
+    pub fn wl_hash(&self) -> u64 {
+        self.wl_hash
+    }
+
+    /// Returns the amount of basic blocks of the [`CFG`](crate::analysis::CFG).
+    pub fn blocks(&self) -> u32 {
+        self.blocks
+    }
+
+    /// Returns the amount of edges of the [`CFG`](crate::analysis::CFG).
+    pub fn edges(&self) -> u32 {
+        self.edges
+    }
+
+    /// Returns the depth of the [`CFS`] tree.
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    /// Returns the amount of nodes of the [`CFS`] tree.
+    pub fn nodes(&self) -> u32 {
+        self.nodes
+    }
+
+    /// Returns an estimate of the similarity of two fingerprints, between 0 and 1.
+    ///
+    /// Fingerprints with the same structural hash have similarity 1, otherwise the similarity
+    /// is the average of the ratios between the smallest and the largest amount of basic blocks,
+    /// edges, tree depth and tree nodes. This is much coarser than
+    /// [`tree_similarity`](crate::analysis::tree_similarity), but requires only the fingerprints.
+    /// # Examples
+    /// Basic usage:
+    /// ```
+    /// use bincc::analysis::{Fingerprint, CFG, CFS};
+    /// use bincc::disasm::{Architecture, Statement, StatementFamily};
+    ///
+    /// let stmts = vec![
+    ///     Statement::new(0x10, StatementFamily::CJMP, "je 0x14"),
+    ///     Statement::new(0x12, StatementFamily::MOV, "mov eax, 0"),
+    ///     Statement::new(0x14, StatementFamily::RET, "ret"),
+    /// ];
+    /// let cfg = CFG::new(&stmts, 0x15, Architecture::X86(64));
+    /// let ifthen = Fingerprint::new(&CFS::new(&cfg).unwrap()).unwrap();
+    /// let cfg = CFG::new(&stmts[2..], 0x15, Architecture::X86(64));
+    /// let single = Fingerprint::new(&CFS::new(&cfg).unwrap()).unwrap();
+    ///
+    /// assert_eq!(ifthen.similarity(&ifthen), 1.0);
+    /// assert!(ifthen.similarity(&single) < 0.5);
+    /// ```
+    pub fn similarity(&self, other: &Fingerprint) -> f64 {
+        if self.structural_hash == other.structural_hash {
+            return 1.0;
+        }
+        let ratio = |a: u32, b: u32| {
+            if a == b {
+                1.0
+            } else {
+                a.min(b) as f64 / a.max(b) as f64
+            }
+        };
+        (ratio(self.blocks, other.blocks)
+            + ratio(self.edges, other.edges)
+            + ratio(self.depth, other.depth)
+            + ratio(self.nodes, other.nodes))
+            / 4.0
+    }
+}
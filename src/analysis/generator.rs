@@ -0,0 +1,309 @@
+use crate::analysis::lsh::splitmix;
+use crate::analysis::CFG;
+use crate::disasm::{Architecture, Statement, StatementFamily};
+
+// offset of the first generated block, far from the artificial entry point.
+const BASE_OFFSET: u64 = 0x1000;
+// length of every generated instruction.
+const INSTRUCTION_LEN: u64 = 4;
+// maximum amount of blocks between the head and the tail of a generated loop.
+const LOOP_SPAN: usize = 8;
+// maximum amount of instructions preceding the jump ending each block.
+const MAX_BODY: usize = 3;
+// maximum amount of blocks of the CFGs created by `Arbitrary`.
+#[cfg(feature = "arbitrary")]
+const MAX_ARBITRARY_BLOCKS: usize = 64;
+
+/// Generator of random [`CFG`]s, for property-based testing and for benchmarking the structuring.
+///
+/// Every generated [`CFG`] has exactly the requested amount of basic blocks, all reachable from
+/// the entry point. The blocks are laid out in order, the last one returns, and each other one
+/// ends with a jump:
+/// - with probability equal to the loop density, a conditional jump back to a previous block, or
+///   to the block itself;
+/// - otherwise, with probability equal to the branch density, a conditional or unconditional
+///   jump forward, or a return;
+/// - otherwise, an unconditional jump to the next block.
+///
+/// A forward jump landing inside a loop, other than on its head, makes the [`CFG`] irreducible.
+/// These jumps are kept only with probability equal to the irreducibility, so the [`CFG`]s are
+/// always reducible by default.
+///
+/// The generator is deterministic: the same seed and settings always produce the same sequence
+/// of [`CFG`]s, on every platform. Each call to [`CfgGenerator::generate`], or to
+/// [`Iterator::next`], produces the next [`CFG`] of the sequence.
+///
+/// With the `arbitrary` feature, [`CFG`] implements `arbitrary::Arbitrary` using this generator,
+/// so it can be used as fuzzing input.
+/// # Examples
+/// Basic usage:
+/// ```
+/// use bincc::analysis::{CfgGenerator, Graph, CFS};
+///
+/// let cfgs = CfgGenerator::new(42)
+///     .with_blocks(20)
+///     .with_loop_density(0.2)
+///     .take(10)
+///     .collect::<Vec<_>>();
+///
+/// for cfg in cfgs {
+///     assert_eq!(cfg.len(), 20);
+///     let tree = CFS::new(&cfg).unwrap().get_tree().unwrap();
+///     assert!(tree.validate().is_ok());
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct CfgGenerator {
+    blocks: usize,
+    loops: f64,
+    branches: f64,
+    irreducibility: f64,
+    state: u64,
+}
+
+// jump ending a generated block.
+#[derive(Copy, Clone)]
+enum Exit {
+    Jump(usize),
+    Branch(usize),
+    Return,
+}
+
+impl CfgGenerator {
+    /// Creates a generator of [`CFG`]s of 16 blocks, with a loop density of `0.1`, a branch
+    /// density of `0.3` and no irreducible loop, using the given seed.
+    pub fn new(seed: u64) -> CfgGenerator {
+        CfgGenerator {
+            blocks: 16,
+            loops: 0.1,
+            branches: 0.3,
+            irreducibility: 0.0,
+            state: seed,
+        }
+    }
+
+    /// Sets the amount of basic blocks of each [`CFG`], at least 1.
+    pub fn with_blocks(mut self, blocks: usize) -> CfgGenerator {
+        self.blocks = blocks.max(1);
+        self
+    }
+
+    /// Sets the probability, between 0 and 1, of each block ending with a jump back to a previous
+    /// block.
+    pub fn with_loop_density(mut self, density: f64) -> CfgGenerator {
+        self.loops = density;
+        self
+    }
+
+    /// Sets the probability, between 0 and 1, of each block not ending a loop ending with a jump
+    /// forward or a return.
+    pub fn with_branch_density(mut self, density: f64) -> CfgGenerator {
+        self.branches = density;
+        self
+    }
+
+    /// Sets the probability, between 0 and 1, of keeping a forward jump entering a loop
+    /// elsewhere than from its head.
+    ///
+    /// With `0`, the default, every [`CFG`] is reducible.
+    pub fn with_irreducibility(mut self, irreducibility: f64) -> CfgGenerator {
+        self.irreducibility = irreducibility;
+        self
+    }
+
+    /// Returns the next [`CFG`] of the sequence.
+    pub fn generate(&mut self) -> CFG {
+        let exits = self.exits();
+        let mut lengths = Vec::with_capacity(exits.len());
+        let mut starts = Vec::with_capacity(exits.len());
+        let mut offset = BASE_OFFSET;
+        for _ in 0..exits.len() {
+            let length = (self.below(MAX_BODY) + 1) as u64 * INSTRUCTION_LEN;
+            starts.push(offset);
+            lengths.push(length);
+            offset += length;
+        }
+        let mut stmts = Vec::new();
+        for (block, exit) in exits.into_iter().enumerate() {
+            let jump = starts[block] + lengths[block] - INSTRUCTION_LEN;
+            for (index, body) in (starts[block]..jump)
+                .step_by(INSTRUCTION_LEN as usize)
+                .enumerate()
+            {
+                let mov = format!("mov eax, {}", index);
+                stmts.push(Statement::new(body, StatementFamily::MOV, &mov));
+            }
+            let stmt = match exit {
+                Exit::Jump(target) => {
+                    let jmp = format!("jmp {:#x}", starts[target]);
+                    Statement::new(jump, StatementFamily::JMP, &jmp)
+                }
+                Exit::Branch(target) => {
+                    let je = format!("je {:#x}", starts[target]);
+                    Statement::new(jump, StatementFamily::CJMP, &je)
+                }
+                Exit::Return => Statement::new(jump, StatementFamily::RET, "ret"),
+            };
+            stmts.push(stmt);
+        }
+        CFG::new(&stmts, offset, Architecture::X86(64))
+    }
+
+    // chooses the jump ending each block.
+    fn exits(&mut self) -> Vec<Exit> {
+        let blocks = self.blocks;
+        // loops first, so the forward jumps know which ones they enter
+        let mut tails = vec![None; blocks];
+        for (tail, head) in tails.iter_mut().enumerate().take(blocks - 1) {
+            if self.chance(self.loops) {
+                *head = Some(tail - self.below(tail.min(LOOP_SPAN) + 1));
+            }
+        }
+        let mut exits = Vec::with_capacity(blocks);
+        // blocks reached by a forward jump of a previous block
+        let mut targeted = vec![false; blocks];
+        for block in 0..blocks - 1 {
+            // the next block must remain reachable if this one does not fall through
+            let reachable = targeted[block + 1];
+            let exit = if let Some(head) = tails[block] {
+                Exit::Branch(head)
+            } else if block + 2 < blocks && self.chance(self.branches) {
+                let target = block + 2 + self.below(blocks - block - 2);
+                let enters_loop = tails.iter().enumerate().any(|(tail, head)| {
+                    matches!(head, Some(head) if block < *head && *head < target && target <= tail)
+                });
+                if enters_loop && !self.chance(self.irreducibility) {
+                    Exit::Jump(block + 1)
+                } else {
+                    match self.below(4) {
+                        0 if reachable => Exit::Jump(target),
+                        1 if reachable => Exit::Return,
+                        _ => Exit::Branch(target),
+                    }
+                }
+            } else {
+                Exit::Jump(block + 1)
+            };
+            if let Exit::Jump(target) | Exit::Branch(target) = exit {
+                targeted[target] = true;
+            }
+            exits.push(exit);
+        }
+        exits.push(Exit::Return);
+        exits
+    }
+
+    // returns a random number between 0 and bound, excluded.
+    fn below(&mut self, bound: usize) -> usize {
+        (splitmix(&mut self.state) % bound as u64) as usize
+    }
+
+    // returns true with the given probability.
+    fn chance(&mut self, probability: f64) -> bool {
+        // 53 random bits, the precision of an f64
+        let value = (splitmix(&mut self.state) >> 11) as f64 / (1_u64 << 53) as f64;
+        value < probability
+    }
+}
+
+impl Iterator for CfgGenerator {
+    type Item = CFG;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.generate())
+    }
+}
+
+// every setting of the generator is taken from the input, so the fuzzer can explore them.
+#[cfg(feature = "arbitrary")]
+#[cfg_attr(docsrs, doc(cfg(feature = "arbitrary")))]
+impl<'a> arbitrary::Arbitrary<'a> for CFG {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let blocks = u.int_in_range(1..=MAX_ARBITRARY_BLOCKS)?;
+        let mut density =
+            || -> arbitrary::Result<f64> { Ok(f64::from(u.int_in_range(0..=100_u8)?) / 100.0) };
+        let loops = density()?;
+        let branches = density()?;
+        let irreducibility = density()?;
+        Ok(CfgGenerator::new(u.arbitrary()?)
+            .with_blocks(blocks)
+            .with_loop_density(loops)
+            .with_branch_density(branches)
+            .with_irreducibility(irreducibility)
+            .generate())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::{BlockType, CfgGenerator, CfsBuilder, Graph, CFS};
+    use std::collections::HashSet;
+
+    #[test]
+    fn size() {
+        for blocks in [1, 2, 3, 10, 100] {
+            let generator = CfgGenerator::new(blocks as u64)
+                .with_blocks(blocks)
+                .with_loop_density(0.3)
+                .with_branch_density(0.5)
+                .with_irreducibility(0.5);
+            for cfg in generator.take(20) {
+                assert_eq!(cfg.len(), blocks);
+                assert!(CFS::new(&cfg).unwrap().get_tree().is_some());
+            }
+        }
+    }
+
+    #[test]
+    fn deterministic() {
+        let a = CfgGenerator::new(7).take(5).collect::<Vec<_>>();
+        let b = CfgGenerator::new(7).take(5).collect::<Vec<_>>();
+        let c = CfgGenerator::new(8).take(5).collect::<Vec<_>>();
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        // each CFG of the sequence is different
+        assert_ne!(a[0], a[1]);
+    }
+
+    #[test]
+    fn reducible() {
+        let generator = CfgGenerator::new(0)
+            .with_blocks(30)
+            .with_loop_density(0.3)
+            .with_branch_density(0.5);
+        for cfg in generator.take(200) {
+            let cfs = CFS::new(&cfg).unwrap();
+            assert_eq!(cfs.stats().count(BlockType::Duplicate), 0);
+            let tree = cfs.get_tree().unwrap();
+            assert_eq!(tree.validate(), Ok(()));
+            // every block is part of the tree exactly once
+            let blocks = tree
+                .basic_blocks()
+                .into_iter()
+                .filter(|bb| !bb.is_sink() && !bb.is_entry_point())
+                .collect::<Vec<_>>();
+            assert_eq!(blocks.len(), cfg.len());
+            assert_eq!(blocks.into_iter().collect::<HashSet<_>>().len(), cfg.len());
+        }
+    }
+
+    #[test]
+    fn irreducible() {
+        let generator = CfgGenerator::new(0)
+            .with_blocks(30)
+            .with_loop_density(0.3)
+            .with_branch_density(0.5)
+            .with_irreducibility(1.0);
+        let mut duplicated = 0;
+        for cfg in generator.take(200) {
+            let cfs = CFS::new(&cfg).unwrap();
+            if cfs.stats().count(BlockType::Duplicate) > 0 {
+                duplicated += 1;
+            }
+            assert_eq!(cfs.get_tree().unwrap().validate(), Ok(()));
+            let preserved = CfsBuilder::new().preserve_loops().build(&cfg).unwrap();
+            assert_eq!(preserved.get_tree().unwrap().validate(), Ok(()));
+        }
+        assert!(duplicated > 0);
+    }
+}
@@ -0,0 +1,282 @@
+use crate::analysis::{Graph, CFS};
+#[cfg(feature = "io")]
+use std::env;
+use std::fmt::Write;
+#[cfg(feature = "io")]
+use std::fs;
+#[cfg(feature = "io")]
+use std::path::Path;
+
+// environment variable requesting to overwrite the golden files instead of checking them.
+#[cfg(feature = "io")]
+const UPDATE_VAR: &str = "BINCC_UPDATE_GOLDEN";
+// amount of unchanged lines printed around each change.
+const CONTEXT: usize = 3;
+
+impl CFS {
+    /// Returns an indented outline of the structure of this function.
+    ///
+    /// This is the outline of [`StructureBlock::pretty`] for the tree of the function or, if the
+    /// structuring is not complete, the outline of each node of the final graph in depth-first
+    /// preorder. An empty [`CFS`] results in an empty string.
+    ///
+    /// The outline depends only on the tree, so it is the canonical form compared by
+    /// [`assert_golden`].
+    ///
+    /// [`StructureBlock::pretty`]: crate::analysis::StructureBlock::pretty
+    /// # Examples
+    /// Basic usage:
+    /// ```
+    /// use bincc::analysis::{CFG, CFS};
+    /// use bincc::disasm::{Architecture, Statement, StatementFamily};
+    ///
+    /// let stmts = vec![
+    ///     Statement::new(0x10, StatementFamily::CJMP, "je 0x18"),
+    ///     Statement::new(0x14, StatementFamily::MOV, "mov eax, 6"),
+    ///     Statement::new(0x18, StatementFamily::RET, "ret"),
+    /// ];
+    /// let cfg = CFG::new(&stmts, 0x19, Architecture::X86(64));
+    /// let outline = CFS::new(&cfg).unwrap().pretty();
+    ///
+    /// assert!(outline.starts_with("Sequence {\n    If-Then {\n        0x10..0x14\n"));
+    /// ```
+    pub fn pretty(&self) -> String {
+        self.get_graph()
+            .dfs_preorder()
+            .map(|node| node.pretty())
+            .collect()
+    }
+}
+
+/// Checks the given text against the one stored in a golden file, panicking with a readable
+/// diff if they differ.
+///
+/// This is meant for regression tests of the structuring: the text is usually the
+/// [`CFS::pretty`] outline of one or more functions, so any change of the resulting trees, for
+/// example caused by a custom [`Reduction`](crate::analysis::Reduction), is shown as the lines
+/// added and removed from the stored outline. The comparison is line by line, as in
+/// [`golden_diff`].
+///
+/// If the environment variable `BINCC_UPDATE_GOLDEN` is set to a value other than `0`, the golden
+/// file, and its parent directories, are instead created or overwritten with the given text, so
+/// the snapshots can be updated after an intended change by running the tests once.
+/// # Panics
+/// Panics if the golden file can not be read or written, or if its content differs from the given
+/// text.
+/// # Examples
+/// Basic usage:
+/// ```no_run
+/// use bincc::analysis::{assert_golden, CFG, CFS};
+/// use bincc::disasm::{Architecture, Statement, StatementFamily};
+///
+/// let stmts = vec![
+///     Statement::new(0x10, StatementFamily::CJMP, "je 0x18"),
+///     Statement::new(0x14, StatementFamily::MOV, "mov eax, 6"),
+///     Statement::new(0x18, StatementFamily::RET, "ret"),
+/// ];
+/// let cfg = CFG::new(&stmts, 0x19, Architecture::X86(64));
+/// let cfs = CFS::new(&cfg).unwrap();
+///
+/// assert_golden("tests/golden/ifthen.txt", &cfs.pretty());
+/// ```
+#[cfg(feature = "io")]
+#[cfg_attr(docsrs, doc(cfg(feature = "io")))]
+pub fn assert_golden<P: AsRef<Path>>(path: P, actual: &str) {
+    let path = path.as_ref();
+    let stored = fs::read_to_string(path);
+    let update =
+        matches!(env::var(UPDATE_VAR).as_deref(), Ok(value) if !value.is_empty() && value != "0");
+    if update {
+        let unchanged = matches!(&stored, Ok(expected) if golden_diff(expected, actual).is_none());
+        if !unchanged {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)
+                    .unwrap_or_else(|e| panic!("failed to create {}: {}", dir.display(), e));
+            }
+            fs::write(path, actual)
+                .unwrap_or_else(|e| panic!("failed to write {}: {}", path.display(), e));
+        }
+        return;
+    }
+    let expected = stored.unwrap_or_else(|e| {
+        panic!(
+            "failed to read the golden file {}: {}\nrun with {}=1 to create it",
+            path.display(),
+            e,
+            UPDATE_VAR
+        )
+    });
+    if let Some(diff) = golden_diff(&expected, actual) {
+        panic!(
+            "the result does not match the golden file {}:\n{}run with {}=1 to update it",
+            path.display(),
+            diff,
+            UPDATE_VAR
+        );
+    }
+}
+
+/// Returns the differences between the expected and the actual text, or [`None`] if they are the
+/// same.
+///
+/// The texts are compared line by line, so they are considered the same even if they differ
+/// only in the line terminators, like `\n` and `\r\n`, or in the final one. Each group of changes
+/// is preceded by a header with the line where it starts in each text, like `@@ -4 +4 @@`, and
+/// surrounded by at most 3 unchanged lines. Removed lines start with `-`, added lines with `+`
+/// and unchanged lines with a space.
+/// # Examples
+/// Basic usage:
+/// ```
+/// use bincc::analysis::golden_diff;
+///
+/// let expected = "Sequence {\n    0x10..0x14\n}\n";
+/// let actual = "Sequence {\n    0x10..0x18\n}\n";
+///
+/// assert_eq!(golden_diff(expected, expected), None);
+/// assert_eq!(
+///     golden_diff(expected, actual).unwrap(),
+///     "@@ -1 +1 @@\n Sequence {\n-    0x10..0x14\n+    0x10..0x18\n }\n"
+/// );
+/// ```
+pub fn golden_diff(expected: &str, actual: &str) -> Option<String> {
+    let old = expected.lines().collect::<Vec<_>>();
+    let new = actual.lines().collect::<Vec<_>>();
+    if old == new {
+        return None;
+    }
+    // longest common subsequence of the lines following each pair of positions
+    let mut lcs = vec![vec![0_usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = match old[i] == new[j] {
+                true => lcs[i + 1][j + 1] + 1,
+                false => lcs[i + 1][j].max(lcs[i][j + 1]),
+            };
+        }
+    }
+    // each line with its marker and its position in the expected and in the actual text
+    let mut lines = Vec::with_capacity(old.len() + new.len());
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push((' ', old[i], i, j));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            lines.push(('-', old[i], i, j));
+            i += 1;
+        } else {
+            lines.push(('+', new[j], i, j));
+            j += 1;
+        }
+    }
+    let changes = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, (marker, _, _, _))| *marker != ' ')
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+    let mut diff = String::new();
+    let mut last = None;
+    for (index, &(marker, line, i, j)) in lines.iter().enumerate() {
+        let near_change = changes
+            .iter()
+            .any(|&change| change.abs_diff(index) <= CONTEXT);
+        if near_change {
+            // a new group starts after the skipped lines
+            if index == 0 || last != Some(index - 1) {
+                writeln!(diff, "@@ -{} +{} @@", i + 1, j + 1).unwrap();
+            }
+            writeln!(diff, "{}{}", marker, line).unwrap();
+            last = Some(index);
+        }
+    }
+    Some(diff)
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "io")]
+    use crate::analysis::assert_golden;
+    use crate::analysis::{golden_diff, CFG, CFS};
+    use crate::disasm::{Architecture, Statement, StatementFamily};
+    #[cfg(feature = "io")]
+    use serial_test::serial;
+    #[cfg(feature = "io")]
+    use std::env;
+    #[cfg(feature = "io")]
+    use std::fs;
+
+    fn ifthen() -> CFS {
+        let stmts = vec![
+            Statement::new(0x10, StatementFamily::CJMP, "je 0x18"),
+            Statement::new(0x14, StatementFamily::MOV, "mov eax, 6"),
+            Statement::new(0x18, StatementFamily::RET, "ret"),
+        ];
+        CFS::new(&CFG::new(&stmts, 0x19, Architecture::X86(64))).unwrap()
+    }
+
+    #[test]
+    fn diff() {
+        assert_eq!(golden_diff("a\nb\n", "a\r\nb"), None);
+        let expected = (0..20).map(|i| format!("{}\n", i)).collect::<String>();
+        let actual = (0..20)
+            .filter(|&i| i != 15)
+            .map(|i| match i {
+                3 => "three\n".to_string(),
+                _ => format!("{}\n", i),
+            })
+            .collect::<String>();
+        let diff = golden_diff(&expected, &actual).unwrap();
+        assert_eq!(
+            diff,
+            "@@ -1 +1 @@\n 0\n 1\n 2\n-3\n+three\n 4\n 5\n 6\n\
+             @@ -13 +13 @@\n 12\n 13\n 14\n-15\n 16\n 17\n 18\n"
+        );
+        assert_eq!(golden_diff("", "a\n").unwrap(), "@@ -1 +1 @@\n+a\n");
+    }
+
+    #[test]
+    fn pretty() {
+        assert_eq!(CFS::new(&CFG::default()).unwrap().pretty(), "");
+        let outline = ifthen().pretty();
+        assert_eq!(outline, ifthen().get_tree().unwrap().pretty());
+    }
+
+    #[test]
+    #[cfg(feature = "io")]
+    #[serial]
+    fn golden() {
+        env::remove_var("BINCC_UPDATE_GOLDEN");
+        let path = format!(
+            "{}/resources/tests/golden/ifthen.txt",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        assert_golden(path, &ifthen().pretty());
+    }
+
+    #[test]
+    #[cfg(feature = "io")]
+    #[serial]
+    #[should_panic(expected = "does not match the golden file")]
+    fn mismatch() {
+        env::remove_var("BINCC_UPDATE_GOLDEN");
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ifthen.txt");
+        fs::write(&path, "Sequence {\n}\n").unwrap();
+        assert_golden(&path, &ifthen().pretty());
+    }
+
+    #[test]
+    #[cfg(feature = "io")]
+    #[serial]
+    fn update() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("golden").join("ifthen.txt");
+        env::set_var("BINCC_UPDATE_GOLDEN", "1");
+        assert_golden(&path, &ifthen().pretty());
+        env::remove_var("BINCC_UPDATE_GOLDEN");
+        assert_eq!(fs::read_to_string(&path).unwrap(), ifthen().pretty());
+        assert_golden(&path, &ifthen().pretty());
+    }
+}
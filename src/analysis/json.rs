@@ -0,0 +1,340 @@
+use crate::analysis::report::json_quote;
+use std::fmt::{Display, Formatter};
+use std::iter::Peekable;
+use std::str::Chars;
+
+// maximum nesting of arrays and objects, to avoid overflowing the stack on hostile inputs.
+const MAX_DEPTH: usize = 128;
+
+/// Value of a JSON document, as read by [`Json::parse`].
+///
+/// Used to read the requests of the [`Server`](crate::analysis::Server): the documents written
+/// by the crate are built directly as strings.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    /// Members of the object, in the order they appear in the document.
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Parses a single JSON value, surrounded by any amount of whitespace.
+    ///
+    /// Returns a description of the first error if the input is not well-formed.
+    pub(crate) fn parse(string: &str) -> Result<Json, String> {
+        let mut parser = JsonParser {
+            chars: string.chars().peekable(),
+            depth: 0,
+        };
+        let value = parser.parse_value()?;
+        parser.skip_whitespace();
+        match parser.chars.next() {
+            Some(c) => Err(format!("unexpected `{}` after the value", c)),
+            None => Ok(value),
+        }
+    }
+
+    /// Returns the value of the given member, if this is an object containing it.
+    ///
+    /// If the member is repeated, the last occurrence is returned.
+    pub(crate) fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members
+                .iter()
+                .rev()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    /// Returns the string, if this is a string.
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(string) => Some(string),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Json {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(value) => write!(f, "{}", value),
+            Json::Number(value) => write!(f, "{}", value),
+            Json::String(value) => write!(f, "{}", json_quote(value)),
+            Json::Array(values) => {
+                write!(f, "[")?;
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(members) => {
+                write!(f, "{{")?;
+                for (index, (name, value)) in members.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}:{}", json_quote(name), value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+struct JsonParser<'a> {
+    chars: Peekable<Chars<'a>>,
+    depth: usize,
+}
+
+impl JsonParser<'_> {
+    fn parse_value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some('{') => self.nested(Self::parse_object),
+            Some('[') => self.nested(Self::parse_array),
+            Some('"') => self.parse_string().map(Json::String),
+            Some('t') => self.parse_literal("true", Json::Bool(true)),
+            Some('f') => self.parse_literal("false", Json::Bool(false)),
+            Some('n') => self.parse_literal("null", Json::Null),
+            Some(c) if *c == '-' || c.is_ascii_digit() => self.parse_number(),
+            Some(c) => Err(format!("unexpected `{}`", c)),
+            None => Err("unexpected end of input".to_string()),
+        }
+    }
+
+    fn nested<F>(&mut self, parse: F) -> Result<Json, String>
+    where
+        F: FnOnce(&mut Self) -> Result<Json, String>,
+    {
+        if self.depth == MAX_DEPTH {
+            return Err(format!("more than {} nested values", MAX_DEPTH));
+        }
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn parse_object(&mut self) -> Result<Json, String> {
+        self.expect('{')?;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&'}') {
+            self.chars.next();
+            return Ok(Json::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            let name = self.parse_string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            let value = self.parse_value()?;
+            members.push((name, value));
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some(',') => continue,
+                Some('}') => return Ok(Json::Object(members)),
+                Some(c) => return Err(format!("expected `,` or `}}`, found `{}`", c)),
+                None => return Err("unterminated object".to_string()),
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Json, String> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&']') {
+            self.chars.next();
+            return Ok(Json::Array(values));
+        }
+        loop {
+            values.push(self.parse_value()?);
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some(',') => continue,
+                Some(']') => return Ok(Json::Array(values)),
+                Some(c) => return Err(format!("expected `,` or `]`, found `{}`", c)),
+                None => return Err("unterminated array".to_string()),
+            }
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut string = String::new();
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(string),
+                Some('\\') => {
+                    let escaped = match self.chars.next() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => self.parse_unicode()?,
+                        Some(c) => return Err(format!("invalid escape `\\{}`", c)),
+                        None => return Err("unterminated string".to_string()),
+                    };
+                    string.push(escaped);
+                }
+                Some(c) if (c as u32) < 0x20 => {
+                    return Err("control character in string".to_string());
+                }
+                Some(c) => string.push(c),
+                None => return Err("unterminated string".to_string()),
+            }
+        }
+    }
+
+    // parses the code after `\u`, combining the surrogate pairs.
+    fn parse_unicode(&mut self) -> Result<char, String> {
+        let high = self.parse_hex4()?;
+        let code = if (0xD800..0xDC00).contains(&high) {
+            if self.chars.next() != Some('\\') || self.chars.next() != Some('u') {
+                return Err("unpaired surrogate".to_string());
+            }
+            let low = self.parse_hex4()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err("unpaired surrogate".to_string());
+            }
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| "unpaired surrogate".to_string())
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, String> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self
+                .chars
+                .next()
+                .and_then(|c| c.to_digit(16))
+                .ok_or_else(|| "invalid unicode escape".to_string())?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+
+    fn parse_number(&mut self) -> Result<Json, String> {
+        let mut number = String::new();
+        while let Some(c) = self
+            .chars
+            .peek()
+            .filter(|c| c.is_ascii_digit() || matches!(**c, '-' | '+' | '.' | 'e' | 'E'))
+        {
+            number.push(*c);
+            self.chars.next();
+        }
+        // rust accepts some forms forbidden by JSON, like `1.` or `.5`
+        let digits = number.strip_prefix('-').unwrap_or(&number);
+        let well_formed = digits.starts_with(|c: char| c.is_ascii_digit())
+            && !(digits.starts_with('0') && digits[1..].starts_with(|c: char| c.is_ascii_digit()))
+            && !digits.contains(".e")
+            && !digits.contains(".E")
+            && !digits.ends_with('.');
+        match number.parse::<f64>() {
+            Ok(value) if well_formed => Ok(Json::Number(value)),
+            _ => Err(format!("malformed number `{}`", number)),
+        }
+    }
+
+    fn parse_literal(&mut self, literal: &str, value: Json) -> Result<Json, String> {
+        for expected in literal.chars() {
+            if self.chars.next() != Some(expected) {
+                return Err(format!("expected `{}`", literal));
+            }
+        }
+        Ok(value)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.chars.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(format!("expected `{}`, found `{}`", expected, c)),
+            None => Err(format!("expected `{}`, found the end of input", expected)),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.chars.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+            self.chars.next();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Json;
+
+    #[test]
+    fn parse() {
+        let json = Json::parse(
+            " {\"a\": [1, -2.5e3, true, false, null], \
+             \"b\": {\"c\": \"d\\n\\u00e8\\ud83d\\ude00\"}} ",
+        )
+        .unwrap();
+        assert_eq!(
+            json.get("a"),
+            Some(&Json::Array(vec![
+                Json::Number(1.0),
+                Json::Number(-2500.0),
+                Json::Bool(true),
+                Json::Bool(false),
+                Json::Null
+            ]))
+        );
+        let c = json.get("b").and_then(|b| b.get("c")).unwrap();
+        assert_eq!(c.as_str(), Some("d\n\u{e8}\u{1f600}"));
+        assert_eq!(json.get("c"), None);
+    }
+
+    #[test]
+    fn round_trip() {
+        let string = "{\"a\":[1,-2.5,\"x\\\"y\"],\"b\":{},\"c\":[],\"d\":null}";
+        let json = Json::parse(string).unwrap();
+        assert_eq!(json.to_string(), string);
+    }
+
+    #[test]
+    fn malformed() {
+        let inputs = [
+            "",
+            "{",
+            "[1,]",
+            "{\"a\" 1}",
+            "{\"a\":1,}",
+            "01",
+            "1.",
+            ".5",
+            "-",
+            "tru",
+            "\"abc",
+            "\"\\x\"",
+            "\"\\ud83d\"",
+            "1 2",
+        ];
+        for input in inputs {
+            assert!(Json::parse(input).is_err(), "{} should be rejected", input);
+        }
+        let deep = "[".repeat(1000) + &"]".repeat(1000);
+        assert!(Json::parse(&deep).is_err());
+    }
+}
@@ -0,0 +1,402 @@
+use crate::analysis::StructureBlock;
+use crate::Error;
+use fnv::FnvHasher;
+use std::collections::HashMap;
+use std::hash::Hasher;
+
+// seed of the sequence generating the seeds of the hash functions.
+const SEED: u64 = 0x62636320_6d696e68;
+
+/// Compact signature of the [`StructureBlock::subtree_features`] of a tree, used to estimate the
+/// similarity of two trees without comparing them.
+///
+/// The signature is created by a [`MinHasher`]. The fraction of equal values in two signatures
+/// created by the same [`MinHasher`] estimates the weighted Jaccard similarity of the features of
+/// the two trees.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MinHash {
+    values: Vec<u64>,
+}
+
+impl MinHash {
+    /// Returns the minimum value of each hash function.
+    pub fn values(&self) -> &[u64] {
+        &self.values
+    }
+
+    /// Returns the estimated similarity of the two trees, between 0 and 1.
+    ///
+    /// The two signatures should be created by the same [`MinHasher`]: signatures of different
+    /// lengths have similarity 0.
+    pub fn similarity(&self, other: &MinHash) -> f64 {
+        if self.values.len() != other.values.len() || self.values.is_empty() {
+            0.0
+        } else {
+            let equal = self
+                .values
+                .iter()
+                .zip(&other.values)
+                .filter(|(a, b)| a == b)
+                .count();
+            equal as f64 / self.values.len() as f64
+        }
+    }
+}
+
+/// Creates the [`MinHash`] signatures of structure trees.
+///
+/// The features of each tree are the rooted subtrees up to the given depth, see
+/// [`StructureBlock::subtree_features`]. A feature appearing multiple times counts as multiple
+/// features, so trees differing only in the amount of repetitions of the same subtree are not
+/// considered equal. The hash functions are seeded with fixed values, so the signatures are
+/// stable across executions and platforms.
+/// # Examples
+/// Basic usage:
+/// ```
+/// use bincc::analysis::{BasicBlock, BlockType, MinHasher, NestedBlock, StructureBlock};
+/// use std::sync::Arc;
+///
+/// let bb = |offset| StructureBlock::from(BasicBlock { offset, length: 1 });
+/// let nest = |bt, children| StructureBlock::from(Arc::new(NestedBlock::new(bt, children)));
+/// let a = nest(BlockType::Sequence, vec![bb(0), nest(BlockType::While, vec![bb(1), bb(2)])]);
+/// let b = nest(BlockType::Sequence, vec![bb(5), nest(BlockType::While, vec![bb(6), bb(7)])]);
+/// let hasher = MinHasher::new(64, 2);
+///
+/// assert_eq!(hasher.signature(&a).similarity(&hasher.signature(&b)), 1.0);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MinHasher {
+    seeds: Vec<u64>,
+    depth: usize,
+}
+
+impl MinHasher {
+    /// Creates a hasher using the given amount of hash functions, and considering the subtrees up
+    /// to the given depth.
+    ///
+    /// More hash functions result in more precise estimates, but longer signatures.
+    pub fn new(hashes: usize, depth: usize) -> MinHasher {
+        let mut state = SEED;
+        let seeds = (0..hashes).map(|_| splitmix(&mut state)).collect();
+        MinHasher { seeds, depth }
+    }
+
+    /// Returns the amount of hash functions, that is the length of the signatures.
+    pub fn len(&self) -> usize {
+        self.seeds.len()
+    }
+
+    /// Returns true if the hasher uses no hash functions.
+    pub fn is_empty(&self) -> bool {
+        self.seeds.is_empty()
+    }
+
+    /// Returns the maximum depth of the subtrees used as features.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Creates the signature of the given tree.
+    pub fn signature(&self, tree: &StructureBlock) -> MinHash {
+        let features = weighted_features(tree, self.depth);
+        let values = self
+            .seeds
+            .iter()
+            .map(|seed| {
+                features
+                    .iter()
+                    .map(|feature| mix(feature ^ seed))
+                    .min()
+                    .unwrap_or(u64::MAX)
+            })
+            .collect();
+        MinHash { values }
+    }
+}
+
+/// Calculates the SimHash of the [`StructureBlock::subtree_features`] of a tree, up to the given
+/// depth.
+///
+/// Each feature is weighted by the amount of times it appears. Similar trees have hashes
+/// differing in few bits, see [`simhash_similarity`]. The hash is stable across executions and
+/// platforms.
+pub fn simhash(tree: &StructureBlock, depth: usize) -> u64 {
+    let mut weights = [0i64; 64];
+    for (feature, count) in tree.subtree_features(depth) {
+        let feature = mix(feature);
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            if feature & (1 << bit) != 0 {
+                *weight += count as i64;
+            } else {
+                *weight -= count as i64;
+            }
+        }
+    }
+    weights
+        .iter()
+        .enumerate()
+        .filter(|(_, weight)| **weight > 0)
+        .fold(0, |hash, (bit, _)| hash | 1 << bit)
+}
+
+/// Returns the similarity of two hashes created by [`simhash`], between 0 and 1, as the fraction
+/// of equal bits.
+pub fn simhash_similarity(a: u64, b: u64) -> f64 {
+    1.0 - (a ^ b).count_ones() as f64 / 64.0
+}
+
+/// Index of structure trees supporting the approximate search of the most similar ones.
+///
+/// The [`MinHash`] signature of every tree is split into bands of rows, and each band is hashed
+/// into a bucket. Two trees are candidates for being similar if they share at least a bucket, so
+/// a query compares only the candidates instead of every indexed tree. With `b` bands of `r`
+/// rows, two trees with similarity `s` become candidates with probability `1 - (1 - s^r)^b`, so
+/// more rows discard more dissimilar trees, and more bands miss fewer similar ones.
+///
+/// Each tree is identified by a value of type `T`, like the name of its function.
+/// # Examples
+/// Basic usage:
+/// ```
+/// use bincc::analysis::{BasicBlock, BlockType, LshIndex, NestedBlock, StructureBlock};
+/// use std::sync::Arc;
+///
+/// let bb = |offset| StructureBlock::from(BasicBlock { offset, length: 1 });
+/// let nest = |bt, children| StructureBlock::from(Arc::new(NestedBlock::new(bt, children)));
+/// let a = nest(BlockType::Sequence, vec![bb(0), nest(BlockType::While, vec![bb(1), bb(2)])]);
+/// let b = nest(BlockType::IfThen, vec![bb(0), bb(1)]);
+/// let mut index = LshIndex::new(16, 4, 2);
+/// index.insert("a", &a);
+/// index.insert("b", &b);
+///
+/// let nearest = index.nearest(&a, 10);
+/// assert_eq!(nearest[0], (&"a", 1.0));
+/// ```
+pub struct LshIndex<T> {
+    hasher: MinHasher,
+    rows: usize,
+    // for each band, the trees in each bucket
+    buckets: Vec<HashMap<u64, Vec<usize>>>,
+    items: Vec<(T, MinHash)>,
+}
+
+impl<T> LshIndex<T> {
+    /// Creates an empty index, with the given amount of bands and rows per band, and considering
+    /// the subtrees up to the given depth.
+    ///
+    /// # Panics
+    /// Panics if `rows` is 0.
+    pub fn new(bands: usize, rows: usize, depth: usize) -> LshIndex<T> {
+        assert!(rows > 0, "an LSH band must contain at least a row");
+        LshIndex {
+            hasher: MinHasher::new(bands * rows, depth),
+            rows,
+            buckets: vec![HashMap::new(); bands],
+            items: Vec::new(),
+        }
+    }
+
+    /// Returns the [`MinHasher`] used to create the signatures.
+    pub fn hasher(&self) -> &MinHasher {
+        &self.hasher
+    }
+
+    /// Adds a tree to the index.
+    pub fn insert(&mut self, id: T, tree: &StructureBlock) {
+        let signature = self.hasher.signature(tree);
+        self.push(id, signature);
+    }
+
+    /// Adds a tree to the index, given its signature.
+    ///
+    /// The signature must be created by the [`LshIndex::hasher`] of this index, so the signatures
+    /// can be computed in parallel or loaded from a previous run. Returns
+    /// [`Error::InvalidInput`] if the signature has a different length, as when created by
+    /// another [`MinHasher`].
+    pub fn insert_signature(&mut self, id: T, signature: MinHash) -> Result<(), Error> {
+        if signature.values.len() != self.hasher.len() {
+            return Err(Error::InvalidInput(format!(
+                "signature of length {} in an index of length {}",
+                signature.values.len(),
+                self.hasher.len()
+            )));
+        }
+        self.push(id, signature);
+        Ok(())
+    }
+
+    // adds the signature, already known to have the length of the ones created by the hasher.
+    fn push(&mut self, id: T, signature: MinHash) {
+        let index = self.items.len();
+        for (band, buckets) in self.buckets.iter_mut().enumerate() {
+            let bucket = band_hash(&signature, band, self.rows);
+            buckets.entry(bucket).or_default().push(index);
+        }
+        self.items.push((id, signature));
+    }
+
+    /// Returns the amount of indexed trees.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Returns true if no trees are indexed.
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Returns the trees sharing at least a bucket with the given one, in insertion order.
+    pub fn candidates(&self, tree: &StructureBlock) -> Vec<&T> {
+        let signature = self.hasher.signature(tree);
+        self.candidate_indices(&signature)
+            .into_iter()
+            .map(|index| &self.items[index].0)
+            .collect()
+    }
+
+    /// Returns at most the given amount of candidates most similar to the given tree, along with
+    /// their estimated similarity, see [`MinHash::similarity`].
+    ///
+    /// The results are sorted from the most similar to the least similar, and candidates with
+    /// the same similarity are sorted in insertion order.
+    pub fn nearest(&self, tree: &StructureBlock, amount: usize) -> Vec<(&T, f64)> {
+        let signature = self.hasher.signature(tree);
+        let mut found = self
+            .candidate_indices(&signature)
+            .into_iter()
+            .map(|index| {
+                let (id, other) = &self.items[index];
+                (id, signature.similarity(other))
+            })
+            .collect::<Vec<_>>();
+        // stable sort, so the insertion order is kept
+        found.sort_by(|a, b| b.1.total_cmp(&a.1));
+        found.truncate(amount);
+        found
+    }
+
+    fn candidate_indices(&self, signature: &MinHash) -> Vec<usize> {
+        let mut indices = self
+            .buckets
+            .iter()
+            .enumerate()
+            .filter_map(|(band, buckets)| buckets.get(&band_hash(signature, band, self.rows)))
+            .flatten()
+            .copied()
+            .collect::<Vec<_>>();
+        indices.sort_unstable();
+        indices.dedup();
+        indices
+    }
+}
+
+// features of the tree, with every repetition of the same feature as a different value.
+fn weighted_features(tree: &StructureBlock, depth: usize) -> Vec<u64> {
+    tree.subtree_features(depth)
+        .into_iter()
+        .flat_map(|(feature, count)| (0..count as u64).map(move |i| mix(feature ^ mix(i))))
+        .collect()
+}
+
+fn band_hash(signature: &MinHash, band: usize, rows: usize) -> u64 {
+    let mut hasher = FnvHasher::default();
+    // fixed endianness, so the hash does not depend on the platform
+    for value in &signature.values[band * rows..(band + 1) * rows] {
+        hasher.write(&value.to_le_bytes());
+    }
+    hasher.finish()
+}
+
+// returns the next value of a splitmix64 sequence.
+pub(super) fn splitmix(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E3779B97F4A7C15);
+    mix(*state)
+}
+
+// finalizer of splitmix64, scrambling the bits of the value.
+fn mix(mut value: u64) -> u64 {
+    value = (value ^ (value >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94D049BB133111EB);
+    value ^ (value >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::{
+        simhash, simhash_similarity, BasicBlock, BlockType, LshIndex, MinHasher, NestedBlock,
+        StructureBlock,
+    };
+    use crate::Error;
+    use std::sync::Arc;
+
+    fn bb(offset: u64) -> StructureBlock {
+        StructureBlock::from(BasicBlock { offset, length: 1 })
+    }
+
+    fn nest(bt: BlockType, children: Vec<StructureBlock>) -> StructureBlock {
+        StructureBlock::from(Arc::new(NestedBlock::new(bt, children)))
+    }
+
+    // sequence of the given amount of loops.
+    fn loops(amount: u64) -> StructureBlock {
+        let children = (0..amount)
+            .map(|i| nest(BlockType::While, vec![bb(2 * i), bb(2 * i + 1)]))
+            .collect();
+        nest(BlockType::Sequence, children)
+    }
+
+    #[test]
+    fn minhash() {
+        let hasher = MinHasher::new(128, 3);
+        assert_eq!(hasher.len(), 128);
+        let a = hasher.signature(&loops(10));
+        let b = hasher.signature(&loops(11));
+        let c = hasher.signature(&nest(BlockType::IfThen, vec![bb(0), bb(1)]));
+        assert_eq!(a, MinHasher::new(128, 3).signature(&loops(10)));
+        assert!(a.similarity(&b) > 0.5);
+        assert!(a.similarity(&c) < 0.5);
+        assert_eq!(
+            a.similarity(&MinHasher::new(1, 3).signature(&loops(10))),
+            0.0
+        );
+    }
+
+    #[test]
+    fn simhash_distance() {
+        let a = simhash(&loops(10), 3);
+        let b = simhash(&loops(11), 3);
+        assert_eq!(a, simhash(&loops(10), 3));
+        assert_eq!(simhash_similarity(a, a), 1.0);
+        assert_eq!(simhash_similarity(0, u64::MAX), 0.0);
+        assert!(simhash_similarity(a, b) > 0.5);
+    }
+
+    #[test]
+    fn nearest() {
+        let mut index = LshIndex::new(32, 4, 3);
+        for amount in 1..20 {
+            index.insert(amount, &loops(amount));
+        }
+        index.insert(100, &nest(BlockType::IfThen, vec![bb(0), bb(1)]));
+        assert_eq!(index.len(), 20);
+        let nearest = index.nearest(&loops(10), 3);
+        assert_eq!(nearest.len(), 3);
+        assert_eq!(nearest[0], (&10, 1.0));
+        assert!(nearest.iter().all(|(id, _)| **id >= 8 && **id <= 12));
+        assert!(!index.candidates(&loops(10)).contains(&&100));
+        assert!(index.nearest(&loops(10), 0).is_empty());
+    }
+
+    #[test]
+    fn insert_signature() {
+        let mut index = LshIndex::new(8, 4, 3);
+        let signature = index.hasher().signature(&loops(3));
+        assert!(index.insert_signature(3, signature).is_ok());
+        let other = MinHasher::new(16, 3).signature(&loops(3));
+        assert!(matches!(
+            index.insert_signature(4, other),
+            Err(Error::InvalidInput(_))
+        ));
+        assert_eq!(index.len(), 1);
+        assert_eq!(index.nearest(&loops(3), 2), vec![(&3, 1.0)]);
+    }
+}
@@ -0,0 +1,170 @@
+use crate::analysis::{CFG, CFS};
+
+/// Information about the function represented by a [`CFG`].
+///
+/// The metadata is not used by the analyses, but it is carried along by the [`CFS`] created from
+/// the [`CFG`], so every result can be labeled with the function it came from.
+/// # Examples
+/// Basic usage:
+/// ```
+/// use bincc::analysis::{FunctionMetadata, CFG};
+/// use bincc::disasm::{Architecture, Statement, StatementFamily};
+///
+/// let stmts = vec![
+///     Statement::new(0x1000, StatementFamily::MOV, "mov eax, 0"),
+///     Statement::new(0x1005, StatementFamily::RET, "ret"),
+/// ];
+/// let metadata = FunctionMetadata::new("_Z3foov", 0x1000, 6).with_demangled_name("foo()");
+/// let cfg = CFG::new(&stmts, 0x1006, Architecture::X86(64)).with_metadata(metadata);
+///
+/// assert_eq!(cfg.metadata().unwrap().name(), "_Z3foov");
+/// assert_eq!(cfg.metadata().unwrap().demangled_name(), Some("foo()"));
+/// assert_eq!(cfg.metadata().unwrap().display_name(), "foo()");
+/// assert_eq!(cfg.metadata().unwrap().source_file(), None);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FunctionMetadata {
+    name: String,
+    demangled: Option<String>,
+    start: u64,
+    size: u64,
+    source: Option<String>,
+}
+
+impl FunctionMetadata {
+    /// Creates the metadata of the function with the given name, starting at the given address
+    /// and spanning the given amount of bytes.
+    pub fn new(name: &str, start: u64, size: u64) -> FunctionMetadata {
+        FunctionMetadata {
+            name: name.to_string(),
+            demangled: None,
+            start,
+            size,
+            source: None,
+        }
+    }
+
+    /// Sets the demangled name of the function.
+    pub fn with_demangled_name(mut self, name: &str) -> FunctionMetadata {
+        self.demangled = Some(name.to_string());
+        self
+    }
+
+    /// Sets the source file containing the function.
+    pub fn with_source_file(mut self, file: &str) -> FunctionMetadata {
+        self.source = Some(file.to_string());
+        self
+    }
+
+    /// Returns the name of the function, as it appears in the binary.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the demangled name of the function, if known.
+    pub fn demangled_name(&self) -> Option<&str> {
+        self.demangled.as_deref()
+    }
+
+    /// Returns the name that should be shown to the user: the demangled name, if known, or the
+    /// name as it appears in the binary.
+    pub fn display_name(&self) -> &str {
+        self.demangled.as_deref().unwrap_or(&self.name)
+    }
+
+    /// Returns the address of the first instruction of the function.
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    /// Returns the size of the function in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns the source file containing the function, if known.
+    pub fn source_file(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+
+    // moves the function by the given amount of bytes.
+    pub(super) fn rebase(&mut self, delta: u64) {
+        self.start = self.start.wrapping_add(delta);
+    }
+}
+
+impl CFG {
+    /// Attaches the given metadata to this CFG, replacing the previous one.
+    pub fn with_metadata(mut self, metadata: FunctionMetadata) -> CFG {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    /// Returns the metadata of the function represented by this CFG, if attached with
+    /// [`CFG::with_metadata`].
+    pub fn metadata(&self) -> Option<&FunctionMetadata> {
+        self.metadata.as_ref()
+    }
+}
+
+impl CFS {
+    /// Returns the metadata of the function structured by this [`CFS`], as in [`CFG::metadata`].
+    pub fn metadata(&self) -> Option<&FunctionMetadata> {
+        self.cfg.metadata()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::{BasicBlock, CfsBuilder, FunctionMetadata, StructureCache, CFG, CFS};
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    fn cfg(base: u64, name: &str) -> CFG {
+        let bb = |offset| BasicBlock {
+            offset: base + offset,
+            length: 1,
+        };
+        let edges = HashMap::from([
+            (bb(0), vec![bb(1), bb(2)]),
+            (bb(1), vec![bb(2)]),
+            (bb(2), vec![]),
+        ]);
+        CFG {
+            root: Some(bb(0)),
+            edges,
+            info: HashMap::new(),
+            metadata: None,
+        }
+        .with_metadata(FunctionMetadata::new(name, base, 3))
+    }
+
+    #[test]
+    fn carried_by_cfs() {
+        let metadata = FunctionMetadata::new("_Z3barv", 0x20, 3)
+            .with_demangled_name("bar()")
+            .with_source_file("bar.cpp");
+        let cfg = cfg(0x20, "bar").with_metadata(metadata.clone());
+        let cfs = CFS::new(&cfg).unwrap();
+        assert_eq!(cfs.metadata(), Some(&metadata));
+        assert_eq!(cfs.metadata().unwrap().start(), 0x20);
+        assert_eq!(cfs.metadata().unwrap().size(), 3);
+        let loaded = CFS::from_bytes(&cfs.to_bytes()).unwrap();
+        assert_eq!(loaded.metadata(), Some(&metadata));
+        let unlabeled = CFS::new(&CFG {
+            metadata: None,
+            ..cfg
+        })
+        .unwrap();
+        assert!(unlabeled.metadata().is_none());
+    }
+
+    #[test]
+    fn not_shared_by_cache() {
+        let builder = CfsBuilder::new().with_cache(Arc::new(StructureCache::new()));
+        let first = builder.build(&cfg(0x10, "first")).unwrap();
+        let second = builder.build(&cfg(0x40, "second")).unwrap();
+        assert_eq!(first.metadata().unwrap().name(), "first");
+        assert_eq!(second.metadata().unwrap().name(), "second");
+    }
+}
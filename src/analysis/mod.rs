@@ -8,14 +8,141 @@ mod cfg;
 pub use self::cfg::BasicBlock;
 pub use self::cfg::CFG;
 pub use self::cfg::SINK_ADDR;
+mod metadata;
+pub use self::metadata::FunctionMetadata;
+mod calls;
+pub use self::calls::CallSite;
+pub use self::calls::CallTarget;
+pub use self::calls::Reference;
+pub use self::calls::ReferenceKind;
 mod blocks;
+pub use self::blocks::AbstractBlock;
 pub use self::blocks::BlockType;
 pub use self::blocks::NestedBlock;
 pub use self::blocks::StructureBlock;
+pub use self::blocks::StructurePostIter;
+pub use self::blocks::StructurePreIter;
+pub use self::blocks::TreeMetrics;
+mod cache;
+mod serial;
+pub use self::cache::StructureCache;
+mod progress;
+pub use self::progress::Phase;
+pub use self::progress::ProgressSink;
+mod cancel;
+pub use self::cancel::CancellationToken;
+mod timings;
+pub use self::timings::Timings;
 mod cfs;
+pub use self::cfs::same_structure;
+pub use self::cfs::structure_diff;
+pub use self::cfs::structure_region;
+pub use self::cfs::tree_distance;
+pub use self::cfs::tree_distance_with_costs;
+pub use self::cfs::tree_similarity;
+pub use self::cfs::tree_similarity_with_weights;
+pub use self::cfs::CfsBuilder;
+pub use self::cfs::CfsStats;
+pub use self::cfs::EditCosts;
+pub use self::cfs::Reduction;
+pub use self::cfs::ReductionKind;
+pub use self::cfs::SimilarityWeights;
+pub use self::cfs::StructuringResult;
+pub use self::cfs::TraceEntry;
 pub use self::cfs::CFS;
+mod visitor;
+pub use self::visitor::StructureRewriter;
+pub use self::visitor::StructureVisitor;
+mod validate;
+pub use self::validate::ValidationError;
+mod arena;
+mod expand;
+mod sexpr;
+pub use self::arena::NodeId;
+pub use self::arena::StructureArena;
+mod pseudocode;
+pub use self::pseudocode::PseudocodeSyntax;
+pub use self::pseudocode::PseudocodeWriter;
+#[cfg(feature = "disasm")]
+mod signatures;
+mod svg;
+mod viewer;
+#[cfg(feature = "disasm")]
+pub use self::signatures::Signature;
+#[cfg(feature = "disasm")]
+pub use self::signatures::SignatureSet;
 mod comparator;
 pub use self::comparator::CFSComparator;
 pub use self::comparator::CloneClass;
 pub use self::comparator::FVec;
 pub use self::comparator::SemanticComparator;
+mod fingerprint;
+pub use self::fingerprint::Fingerprint;
+#[cfg(feature = "compare-db")]
+mod database;
+#[cfg(feature = "compare-db")]
+pub use self::database::FingerprintDatabase;
+#[cfg(feature = "compare-db")]
+pub use self::database::FingerprintRecord;
+#[cfg(feature = "disasm")]
+mod patch;
+#[cfg(feature = "disasm")]
+pub use self::patch::ChangeKind;
+#[cfg(feature = "disasm")]
+pub use self::patch::PatchedRegion;
+#[cfg(feature = "disasm")]
+mod diff;
+#[cfg(feature = "disasm")]
+pub use self::diff::BinaryDiff;
+#[cfg(feature = "disasm")]
+pub use self::diff::ComparisonMode;
+#[cfg(feature = "disasm")]
+pub use self::diff::FunctionMatch;
+#[cfg(feature = "disasm")]
+mod config;
+#[cfg(feature = "disasm")]
+pub use self::config::AnalysisConfig;
+#[cfg(feature = "disasm")]
+mod evaluation;
+#[cfg(feature = "disasm")]
+pub use self::evaluation::Evaluation;
+#[cfg(feature = "disasm")]
+pub use self::evaluation::GroundTruth;
+#[cfg(feature = "disasm")]
+mod search;
+#[cfg(feature = "disasm")]
+pub use self::search::FunctionSearch;
+#[cfg(feature = "disasm")]
+pub use self::search::SearchCandidate;
+mod lsh;
+pub use self::lsh::simhash;
+pub use self::lsh::simhash_similarity;
+pub use self::lsh::LshIndex;
+pub use self::lsh::MinHash;
+pub use self::lsh::MinHasher;
+mod generator;
+pub use self::generator::CfgGenerator;
+mod golden;
+#[cfg(all(test, feature = "disasm"))]
+pub(crate) mod test_utils;
+#[cfg(feature = "io")]
+pub use self::golden::assert_golden;
+pub use self::golden::golden_diff;
+#[cfg(feature = "disasm")]
+mod report;
+#[cfg(feature = "disasm")]
+pub use self::report::json_quote;
+#[cfg(feature = "disasm")]
+pub use self::report::AnalysisReport;
+#[cfg(feature = "disasm")]
+pub use self::report::Report;
+#[cfg(feature = "disasm")]
+mod view;
+#[cfg(feature = "disasm")]
+pub use self::view::MatchView;
+#[cfg(feature = "disasm")]
+mod json;
+#[cfg(feature = "disasm")]
+mod server;
+#[cfg(feature = "disasm")]
+pub use self::server::Server;
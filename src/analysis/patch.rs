@@ -0,0 +1,343 @@
+use crate::analysis::{BasicBlock, BlockType, ComparisonMode, StructureBlock, CFG};
+use fnv::FnvHasher;
+use std::hash::Hasher;
+use std::ops::Range;
+
+/// Kind of a [`PatchedRegion`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(docsrs, doc(cfg(feature = "disasm")))]
+pub enum ChangeKind {
+    /// The blocks exist only in the new function.
+    Inserted,
+    /// The blocks exist only in the old function.
+    Deleted,
+    /// The blocks of the old function are replaced by different blocks in the new function.
+    Modified,
+}
+
+impl ChangeKind {
+    /// Converts the kind into a string representation.
+    pub fn to_str(&self) -> &'static str {
+        match self {
+            ChangeKind::Inserted => "inserted",
+            ChangeKind::Deleted => "deleted",
+            ChangeKind::Modified => "modified",
+        }
+    }
+}
+
+/// Portion of a function that changed between two versions, as found by
+/// [`BinaryDiff`](crate::analysis::BinaryDiff).
+///
+/// The two structure trees are aligned from the root: the children of two blocks of the same
+/// type are paired when their content is unchanged, and the region is narrowed down to the
+/// smallest subtrees that differ. A region is thus usually a single loop, condition or basic
+/// block, instead of the whole function.
+///
+/// The content of two blocks is unchanged if they have the same structure, and their basic
+/// blocks have the same length and the same [`CategoryHistogram`]. In
+/// [`ComparisonMode::CrossArchitecture`] only the structure is considered.
+///
+/// [`CategoryHistogram`]: crate::disasm::CategoryHistogram
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(docsrs, doc(cfg(feature = "disasm")))]
+pub struct PatchedRegion {
+    kind: ChangeKind,
+    old: Vec<StructureBlock>,
+    new: Vec<StructureBlock>,
+}
+
+impl PatchedRegion {
+    /// Returns the kind of change.
+    pub fn kind(&self) -> ChangeKind {
+        self.kind
+    }
+
+    /// Returns the changed subtrees of the old function, in order.
+    ///
+    /// The slice is empty for [`ChangeKind::Inserted`] regions.
+    pub fn old_blocks(&self) -> &[StructureBlock] {
+        &self.old
+    }
+
+    /// Returns the changed subtrees of the new function, in order.
+    ///
+    /// The slice is empty for [`ChangeKind::Deleted`] regions.
+    pub fn new_blocks(&self) -> &[StructureBlock] {
+        &self.new
+    }
+
+    /// Returns the address ranges covered by the changed blocks of the old function.
+    pub fn old_ranges(&self) -> Vec<Range<u64>> {
+        ranges(&self.old)
+    }
+
+    /// Returns the address ranges covered by the changed blocks of the new function.
+    pub fn new_ranges(&self) -> Vec<Range<u64>> {
+        ranges(&self.new)
+    }
+}
+
+/// Returns the regions that changed between the old and the new tree, in order.
+pub(super) fn patched_regions(
+    old: (&StructureBlock, &CFG),
+    new: (&StructureBlock, &CFG),
+    mode: ComparisonMode,
+) -> Vec<PatchedRegion> {
+    align(old, new, mode).regions
+}
+
+/// Alignment of the structure trees of two versions of the same function.
+pub(super) struct Alignment {
+    /// Regions that changed, in order.
+    pub(super) regions: Vec<PatchedRegion>,
+    /// Basic blocks of the old tree paired with the basic blocks of the new tree, and true if the
+    /// content of the pair is unchanged.
+    pub(super) blocks: Vec<(BasicBlock, BasicBlock, bool)>,
+}
+
+/// Aligns the old and the new tree, as described in [`PatchedRegion`].
+///
+/// Basic blocks are paired if they belong to two unchanged subtrees at the same position, or if
+/// they are the two sides of a modified region.
+pub(super) fn align(
+    old: (&StructureBlock, &CFG),
+    new: (&StructureBlock, &CFG),
+    mode: ComparisonMode,
+) -> Alignment {
+    let mut localizer = Localizer {
+        old_cfg: old.1,
+        new_cfg: new.1,
+        mode,
+        alignment: Alignment {
+            regions: Vec::new(),
+            blocks: Vec::new(),
+        },
+    };
+    localizer.pair(old.0, new.0);
+    localizer.alignment
+}
+
+struct Localizer<'a> {
+    old_cfg: &'a CFG,
+    new_cfg: &'a CFG,
+    mode: ComparisonMode,
+    alignment: Alignment,
+}
+
+impl Localizer<'_> {
+    // localizes the changes between two subtrees at the same position.
+    fn pair(&mut self, old: &StructureBlock, new: &StructureBlock) {
+        if self.key(old, self.old_cfg) == self.key(new, self.new_cfg) {
+            let blocks = preorder_blocks(old).zip(preorder_blocks(new));
+            self.alignment
+                .blocks
+                .extend(blocks.map(|(old, new)| (old, new, true)));
+            return;
+        }
+        let nested = old.block_type() != BlockType::Basic;
+        if nested && old.block_type() == new.block_type() {
+            self.children(old.children(), new.children());
+        } else {
+            if let (StructureBlock::Basic(old), StructureBlock::Basic(new)) = (old, new) {
+                self.alignment.blocks.push((*old, *new, false));
+            }
+            self.alignment.regions.push(PatchedRegion {
+                kind: ChangeKind::Modified,
+                old: vec![old.clone()],
+                new: vec![new.clone()],
+            });
+        }
+    }
+
+    // aligns the children of two blocks with the longest common subsequence of unchanged
+    // children, and localizes the changes between the unaligned ones.
+    fn children(&mut self, old: &[StructureBlock], new: &[StructureBlock]) {
+        let old_keys = old
+            .iter()
+            .map(|child| self.key(child, self.old_cfg))
+            .collect::<Vec<_>>();
+        let new_keys = new
+            .iter()
+            .map(|child| self.key(child, self.new_cfg))
+            .collect::<Vec<_>>();
+        let (mut i, mut j) = (0, 0);
+        for (next_i, next_j) in lcs(&old_keys, &new_keys)
+            .into_iter()
+            .chain([(old.len(), new.len())])
+        {
+            self.gap(&old[i..next_i], &new[j..next_j]);
+            if next_i < old.len() {
+                self.pair(&old[next_i], &new[next_j]);
+            }
+            (i, j) = (next_i + 1, next_j + 1);
+        }
+    }
+
+    // localizes the changes between two runs of children without unchanged ones.
+    fn gap(&mut self, old: &[StructureBlock], new: &[StructureBlock]) {
+        let kind = match (old.is_empty(), new.is_empty()) {
+            (true, true) => return,
+            (true, false) => ChangeKind::Inserted,
+            (false, true) => ChangeKind::Deleted,
+            (false, false) if old.len() == new.len() => {
+                // same amount of children: each one changed in place
+                old.iter()
+                    .zip(new)
+                    .for_each(|(old, new)| self.pair(old, new));
+                return;
+            }
+            (false, false) => ChangeKind::Modified,
+        };
+        self.alignment.regions.push(PatchedRegion {
+            kind,
+            old: old.to_vec(),
+            new: new.to_vec(),
+        });
+    }
+
+    // hash of the content of a subtree: two subtrees with the same key are unchanged.
+    fn key(&self, node: &StructureBlock, cfg: &CFG) -> u64 {
+        let mut hasher = FnvHasher::default();
+        hasher.write_u64(node.structural_hash());
+        if self.mode == ComparisonMode::SameArchitecture {
+            for bb in preorder_blocks(node) {
+                hasher.write_u64(bb.length);
+                for count in cfg.categories(&bb).map_or(&[][..], |c| c.counts()) {
+                    hasher.write_u32(*count);
+                }
+            }
+        }
+        hasher.finish()
+    }
+}
+
+// basic blocks of the subtree, in preorder.
+fn preorder_blocks(node: &StructureBlock) -> impl Iterator<Item = BasicBlock> + '_ {
+    node.iter_preorder().filter_map(|node| match node {
+        StructureBlock::Basic(bb) => Some(*bb),
+        StructureBlock::Nested(_) => None,
+    })
+}
+
+// indices of the pairs of equal elements in the longest common subsequence, in order.
+fn lcs(a: &[u64], b: &[u64]) -> Vec<(usize, usize)> {
+    // table[i][j]: length of the lcs of a[i..] and b[j..]
+    let mut table = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            table[i][j] = if a[i] == b[j] {
+                table[i + 1][j + 1] + 1
+            } else {
+                table[i + 1][j].max(table[i][j + 1])
+            };
+        }
+    }
+    let mut pairs = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            pairs.push((i, j));
+            i += 1;
+            j += 1;
+        } else if table[i + 1][j] >= table[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    pairs
+}
+
+fn ranges(blocks: &[StructureBlock]) -> Vec<Range<u64>> {
+    let mut ranges = blocks
+        .iter()
+        .flat_map(|block| block.address_ranges())
+        .collect::<Vec<_>>();
+    ranges.sort_unstable_by_key(|range| range.start);
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lcs, patched_regions, PatchedRegion};
+    use crate::analysis::{BlockType, ChangeKind, ComparisonMode, StructureBlock, CFG, CFS};
+    use crate::disasm::{Architecture, Statement, StatementFamily};
+
+    // loop followed by a condition, with the given length of the loop body
+    fn function(body: u64, condition: bool) -> CFG {
+        let head = 0x10 + body;
+        let mut stmts = vec![
+            Statement::new(0x0, StatementFamily::MOV, "mov eax, 0"),
+            Statement::new(0x4, StatementFamily::CMP, "cmp eax, 10"),
+            Statement::new(0x8, StatementFamily::CJMP, &format!("jge {:#x}", head)),
+            Statement::new(0xC, StatementFamily::ADD, "add eax, 1"),
+            Statement::new(0xC + body, StatementFamily::JMP, "jmp 0x4"),
+        ];
+        if condition {
+            stmts.extend([
+                Statement::new(head, StatementFamily::CJMP, &format!("je {:#x}", head + 6)),
+                Statement::new(head + 2, StatementFamily::MOV, "mov eax, 1"),
+            ]);
+        }
+        let end = stmts.last().unwrap().get_offset() + 4;
+        stmts.push(Statement::new(end, StatementFamily::RET, "ret"));
+        CFG::new(&stmts, end + 1, Architecture::X86(64))
+    }
+
+    fn regions(old: &CFG, new: &CFG) -> Vec<PatchedRegion> {
+        let old_tree = CFS::new(old).unwrap().get_tree().unwrap();
+        let new_tree = CFS::new(new).unwrap().get_tree().unwrap();
+        patched_regions(
+            (&old_tree, old),
+            (&new_tree, new),
+            ComparisonMode::SameArchitecture,
+        )
+    }
+
+    #[test]
+    fn unchanged() {
+        let cfg = function(4, true);
+        assert!(regions(&cfg, &cfg).is_empty());
+    }
+
+    #[test]
+    fn modified_block() {
+        let regions = regions(&function(4, false), &function(8, false));
+        assert_eq!(regions.len(), 1);
+        assert_eq!(regions[0].kind(), ChangeKind::Modified);
+        // only the loop body changed
+        assert_eq!(regions[0].old_blocks()[0].block_type(), BlockType::Basic);
+        assert_eq!(regions[0].old_ranges(), vec![0xC..0x14]);
+        assert_eq!(regions[0].new_ranges(), vec![0xC..0x18]);
+    }
+
+    #[test]
+    fn inserted_condition() {
+        let regions = regions(&function(4, false), &function(4, true));
+        let types = |blocks: &[StructureBlock]| {
+            blocks
+                .iter()
+                .flat_map(|block| block.iter_preorder())
+                .map(|node| node.block_type())
+                .collect::<Vec<_>>()
+        };
+        assert!(!regions.is_empty());
+        assert!(regions
+            .iter()
+            .any(|region| types(region.new_blocks()).contains(&BlockType::IfThen)));
+        // the loop is unchanged
+        assert!(regions
+            .iter()
+            .all(|region| !types(region.old_blocks()).contains(&BlockType::While)));
+    }
+
+    #[test]
+    fn longest_common_subsequence() {
+        assert_eq!(
+            lcs(&[1, 2, 3, 4], &[1, 3, 5, 4]),
+            vec![(0, 0), (2, 1), (3, 3)]
+        );
+        assert!(lcs(&[1, 2], &[]).is_empty());
+    }
+}
@@ -0,0 +1,73 @@
+/// Phase of a long analysis, reported to a [`ProgressSink`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Phase {
+    /// Extraction of the [`CFG`](crate::analysis::CFG) of every function of a binary, see
+    /// [`Binary::extract_with_progress`](crate::disasm::Binary::extract_with_progress).
+    Disassembly,
+    /// Creation of the [`CFS`](crate::analysis::CFS) of many functions, see
+    /// [`CfsBuilder::with_progress`](crate::analysis::CfsBuilder::with_progress).
+    Structuring,
+}
+
+impl Phase {
+    /// Converts the phase into a string representation.
+    pub fn to_str(&self) -> &'static str {
+        match self {
+            Phase::Disassembly => "disassembly",
+            Phase::Structuring => "structuring",
+        }
+    }
+}
+
+/// Receiver of the progress of a long analysis, for example to drive a progress bar or to write
+/// structured logs.
+///
+/// Each [`Phase`] starts with [`ProgressSink::phase_started`], reporting the amount of functions
+/// that will be processed, continues with a [`ProgressSink::function_completed`] for each of
+/// them, and ends with [`ProgressSink::phase_finished`]. The functions may be completed by
+/// multiple threads, in any order.
+///
+/// Every method does nothing by default, and the unit type `()` can be used when the progress
+/// is not needed.
+/// # Examples
+/// Basic usage:
+/// ```
+/// use bincc::analysis::{CfsBuilder, Phase, ProgressSink, CFG};
+/// use bincc::disasm::{Architecture, Statement, StatementFamily};
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::sync::Arc;
+///
+/// #[derive(Default)]
+/// struct Counter(AtomicUsize);
+///
+/// impl ProgressSink for Counter {
+///     fn function_completed(&self, _: Phase, _: u64) {
+///         self.0.fetch_add(1, Ordering::Relaxed);
+///     }
+/// }
+///
+/// let stmts = vec![Statement::new(0x10, StatementFamily::RET, "ret")];
+/// let cfgs = vec![CFG::new(&stmts, 0x11, Architecture::X86(64)); 3];
+/// let counter = Arc::new(Counter::default());
+/// CfsBuilder::new().with_progress(counter.clone()).build_all(cfgs);
+///
+/// assert_eq!(counter.0.load(Ordering::Relaxed), 3);
+/// ```
+pub trait ProgressSink: Send + Sync {
+    /// Called when a phase starts, with the amount of functions that will be processed.
+    fn phase_started(&self, phase: Phase, total: usize) {
+        let _ = (phase, total);
+    }
+
+    /// Called every time a function is processed, with the offset of the function.
+    fn function_completed(&self, phase: Phase, offset: u64) {
+        let _ = (phase, offset);
+    }
+
+    /// Called when every function of a phase has been processed.
+    fn phase_finished(&self, phase: Phase) {
+        let _ = phase;
+    }
+}
+
+impl ProgressSink for () {}
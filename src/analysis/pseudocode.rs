@@ -0,0 +1,751 @@
+use crate::analysis::blocks::StructureBlock;
+use crate::analysis::BlockType;
+use crate::disasm::Statement;
+use std::collections::{BTreeSet, HashSet};
+
+/// Syntax used by the [`PseudocodeWriter`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PseudocodeSyntax {
+    /// C-like syntax, with braces delimiting the blocks.
+    C,
+    /// Python-like syntax, with indentation delimiting the blocks.
+    Python,
+}
+
+/// Converts a [`StructureBlock`] tree into human readable pseudocode.
+///
+/// Basic blocks are printed as the range of addresses they span or, if the statements of the
+/// function are provided with [`PseudocodeWriter::with_statements`], as the list of their
+/// instructions.
+///
+/// Conditions are named after the offset of the block computing them, in the form `c_0x...`.
+/// # Examples
+/// Basic usage:
+/// ```
+/// use bincc::analysis::{BasicBlock, BlockType, NestedBlock, PseudocodeWriter, StructureBlock};
+/// use std::sync::Arc;
+///
+/// let head = StructureBlock::from(BasicBlock { offset: 0x10, length: 4 });
+/// let body = StructureBlock::from(BasicBlock { offset: 0x14, length: 8 });
+/// let block = NestedBlock::new(BlockType::IfThen, vec![head, body]);
+/// let tree = StructureBlock::from(Arc::new(block));
+/// let code = PseudocodeWriter::new().indentation("  ").write(&tree);
+///
+/// assert_eq!(code, "0x10..0x14\nif (c_0x10) {\n  0x14..0x1c\n}\n");
+/// ```
+#[derive(Debug, Clone)]
+pub struct PseudocodeWriter<'a> {
+    indentation: String,
+    syntax: PseudocodeSyntax,
+    statements: Option<&'a [Statement]>,
+}
+
+impl Default for PseudocodeWriter<'_> {
+    fn default() -> Self {
+        PseudocodeWriter {
+            indentation: "    ".to_string(),
+            syntax: PseudocodeSyntax::C,
+            statements: None,
+        }
+    }
+}
+
+impl<'a> PseudocodeWriter<'a> {
+    /// Creates a new writer using C syntax and four spaces of indentation.
+    pub fn new() -> PseudocodeWriter<'a> {
+        PseudocodeWriter::default()
+    }
+
+    /// Sets the string used for each indentation level.
+    pub fn indentation(mut self, indentation: &str) -> PseudocodeWriter<'a> {
+        self.indentation = indentation.to_string();
+        self
+    }
+
+    /// Sets the syntax of the generated pseudocode.
+    pub fn syntax(mut self, syntax: PseudocodeSyntax) -> PseudocodeWriter<'a> {
+        self.syntax = syntax;
+        self
+    }
+
+    /// Prints basic blocks as their instructions instead of their address range.
+    ///
+    /// The statements are expected to be sorted by offset, as in [`crate::disasm::Function`].
+    pub fn with_statements(mut self, statements: &'a [Statement]) -> PseudocodeWriter<'a> {
+        self.statements = Some(statements);
+        self
+    }
+
+    /// Returns the pseudocode of the given [`StructureBlock`] tree.
+    pub fn write(&self, tree: &StructureBlock) -> String {
+        let mut lines = Vec::new();
+        // blocks exiting a loop early, as recorded by the Break markers
+        let mut exits = HashSet::new();
+        // blocks jumping back to the loop header, as recorded by the Continue markers
+        let mut continues = HashSet::new();
+        // jumps outside the structure, as recorded by the Goto and Abnormal Entry markers
+        let mut gotos = BTreeSet::new();
+        // blocks computing the condition of a structure
+        let mut conditions = HashSet::new();
+        let mut stack = vec![tree];
+        while let Some(node) = stack.pop() {
+            match node.block_type() {
+                BlockType::Break => {
+                    exits.insert(node.offset());
+                }
+                BlockType::Continue => {
+                    continues.insert(node.offset());
+                }
+                BlockType::SwitchLoop => {
+                    // exited by the head of the switch
+                    let head = node
+                        .iter_preorder()
+                        .find(|x| x.block_type() == BlockType::Basic);
+                    exits.extend(head.map(|head| head.offset()));
+                }
+                BlockType::Goto | BlockType::AbnormalEntry => {
+                    gotos.extend(
+                        node.exits()
+                            .iter()
+                            .map(|(src, dst)| (src.offset, dst.offset)),
+                    );
+                }
+                _ => {}
+            }
+            conditions.extend(
+                condition_children(node)
+                    .into_iter()
+                    .flat_map(condition_offsets),
+            );
+            stack.extend(node.children());
+        }
+        let labels = gotos.iter().map(|(_, dst)| *dst).collect::<HashSet<_>>();
+        let mut out = Output {
+            lines: &mut lines,
+            exits: &exits,
+            continues: &continues,
+            gotos: &gotos,
+            labels: &labels,
+            conditions: &conditions,
+        };
+        self.emit(tree, 0, &mut out);
+        lines
+            .into_iter()
+            .fold(String::new(), |mut acc, (level, line)| {
+                for _ in 0..level {
+                    acc.push_str(&self.indentation);
+                }
+                acc.push_str(&line);
+                acc.push('\n');
+                acc
+            })
+    }
+
+    fn emit(&self, node: &StructureBlock, lv: usize, out: &mut Output) {
+        // markers are printed by the block performing the jump
+        let children = node
+            .children()
+            .iter()
+            .filter(|child| !is_marker(child))
+            .collect::<Vec<_>>();
+        match node.block_type() {
+            BlockType::Basic => self.emit_basic(node, lv, out),
+            BlockType::Sequence
+            | BlockType::Duplicate
+            | BlockType::Condition
+            | BlockType::IfChain => children.iter().for_each(|child| self.emit(child, lv, out)),
+            BlockType::SelfLooping => {
+                self.open_loop(lv, out);
+                self.emit(children[0], lv + 1, out);
+                self.close_loop(children[0], lv, out);
+            }
+            BlockType::SwitchLoop => {
+                // the head of the switch exits the loop, as recorded in the exits
+                self.open_endless_loop(lv, out);
+                self.emit(children[0], lv + 1, out);
+                self.close(lv, out);
+            }
+            BlockType::While => {
+                // the head may contain code other than the condition, so the loop is exited in
+                // the middle of the body
+                self.open_endless_loop(lv, out);
+                self.emit(children[0], lv + 1, out);
+                let exit = if node.condition_polarity() == Some(false) {
+                    self.exit_if_true(children[0])
+                } else {
+                    self.exit_if_false(children[0])
+                };
+                out.lines.push((lv + 1, exit));
+                children[1..]
+                    .iter()
+                    .for_each(|child| self.emit(child, lv + 1, out));
+                self.close(lv, out);
+            }
+            BlockType::DoWhile => {
+                // the second child is always the one computing the loop condition. In C a
+                // `continue` inside a `do-while` jumps to the condition instead of the header
+                let continues = node
+                    .children()
+                    .iter()
+                    .any(|child| child.block_type() == BlockType::Continue);
+                if children.len() > 2 || continues {
+                    self.open_endless_loop(lv, out);
+                    self.emit(children[0], lv + 1, out);
+                    self.emit(children[1], lv + 1, out);
+                    out.lines.push((lv + 1, self.exit_if_false(children[1])));
+                    children[2..]
+                        .iter()
+                        .for_each(|child| self.emit(child, lv + 1, out));
+                    self.close(lv, out);
+                } else {
+                    self.open_loop(lv, out);
+                    self.emit(children[0], lv + 1, out);
+                    self.emit(children[1], lv + 1, out);
+                    self.close_loop(children[1], lv, out);
+                }
+            }
+            BlockType::IfThen | BlockType::IfThenElse => {
+                let conds = if node.block_type() == BlockType::IfThen {
+                    children.len() - 1
+                } else {
+                    children.len() - 2
+                };
+                children[..conds]
+                    .iter()
+                    .for_each(|child| self.emit(child, lv, out));
+                // the short-circuit conditions are joined directly, without extra parentheses
+                let mut cond = children[..conds]
+                    .iter()
+                    .flat_map(|child| match child.block_type() {
+                        BlockType::IfChain => child.children().iter().collect(),
+                        _ => vec![*child],
+                    })
+                    .map(|child| self.condition(child))
+                    .collect::<Vec<_>>();
+                if node.condition_polarity() == Some(false) {
+                    // the then block is reached when the jump of the condition is not taken
+                    let last = cond.pop().unwrap();
+                    cond.push(self.not(&last));
+                }
+                let cond = cond.join(self.and());
+                out.lines
+                    .push((lv, self.open(&format!("if {}", self.parens(&cond)))));
+                self.emit(children[conds], lv + 1, out);
+                if node.block_type() == BlockType::IfThenElse {
+                    match self.syntax {
+                        PseudocodeSyntax::C => out.lines.push((lv, "} else {".to_string())),
+                        PseudocodeSyntax::Python => out.lines.push((lv, "else:".to_string())),
+                    }
+                    self.emit(children[conds + 1], lv + 1, out);
+                }
+                self.close(lv, out);
+            }
+            BlockType::Guard => {
+                self.emit(children[0], lv, out);
+                let cond = self.parens(&self.condition(children[0]));
+                out.lines.push((lv, self.open(&format!("if {}", cond))));
+                self.emit(children[1], lv + 1, out);
+                if !matches!(
+                    children[1].block_type(),
+                    BlockType::Return | BlockType::Unreachable
+                ) {
+                    out.lines.push((lv + 1, self.statement("return")));
+                }
+                self.close(lv, out);
+            }
+            BlockType::Return => {
+                self.emit(children[0], lv, out);
+                out.lines.push((lv, self.statement("return")));
+            }
+            BlockType::Unreachable => {
+                self.emit(children[0], lv, out);
+                out.lines
+                    .push((lv, format!("{} unreachable", self.comment())));
+            }
+            BlockType::Switch => {
+                self.emit(children[0], lv, out);
+                let head = format!("switch {}", self.parens(&self.condition(children[0])));
+                out.lines.push((lv, self.open(&head)));
+                for case in &children[1..] {
+                    let label = format!("case {:#x}", case.offset());
+                    out.lines.push((lv + 1, self.open(&label)));
+                    self.emit(case, lv + 2, out);
+                    out.lines.push((lv + 2, self.statement("break")));
+                    self.close(lv + 1, out);
+                }
+                self.close(lv, out);
+            }
+            BlockType::Conditional => {
+                let cond = self.reaching_condition(node);
+                out.lines
+                    .push((lv, self.open(&format!("if {}", self.parens(&cond)))));
+                children
+                    .iter()
+                    .for_each(|child| self.emit(child, lv + 1, out));
+                self.close(lv, out);
+            }
+            BlockType::Break | BlockType::Continue | BlockType::Goto | BlockType::AbnormalEntry => {
+            }
+            BlockType::ProperInterval | BlockType::ImproperInterval | BlockType::ImproperRegion => {
+                out.lines
+                    .push((lv, format!("{} {}", self.comment(), node.get_type_name())));
+                for child in children {
+                    out.lines.push((lv, format!("L_{:#x}:", child.offset())));
+                    self.emit(child, lv + 1, out);
+                }
+            }
+            BlockType::Custom(_) => {
+                // the semantic is unknown, so the children are printed in order
+                out.lines
+                    .push((lv, format!("{} {}", self.comment(), node.block_type())));
+                children.iter().for_each(|child| self.emit(child, lv, out));
+            }
+        }
+    }
+
+    fn emit_basic(&self, node: &StructureBlock, lv: usize, out: &mut Output) {
+        if let StructureBlock::Basic(bb) = node {
+            if out.labels.contains(&bb.offset) {
+                out.lines.push((lv, format!("L_{:#x}:", bb.offset)));
+            }
+            if bb.is_sink() || bb.is_entry_point() {
+                // artificial blocks, they contain no code
+            } else if let Some(stmts) = self.statements {
+                let start = stmts.partition_point(|stmt| stmt.get_offset() < bb.offset);
+                let end = stmts.partition_point(|stmt| stmt.get_offset() < bb.offset + bb.length);
+                for stmt in &stmts[start..end] {
+                    out.lines.push((lv, stmt.get_instruction().to_string()));
+                }
+            } else {
+                out.lines.push((
+                    lv,
+                    format!("{:#x}..{:#x}", bb.offset, bb.offset + bb.length),
+                ));
+            }
+            // a block computing the condition of a structure, or performing more than one jump,
+            // ends with a multiway branch, so each one of its jumps has a different condition
+            let mut jumps = out.conditions.contains(&bb.offset) as usize;
+            let mut jump = |stmt: &str| {
+                let cond = match jumps {
+                    0 => self.condition(node),
+                    n => format!("{}_{}", self.condition(node), n),
+                };
+                jumps += 1;
+                out.lines.push((lv, self.jump(&cond, stmt)));
+            };
+            if out.exits.contains(&bb.offset) {
+                jump("break");
+            }
+            if out.continues.contains(&bb.offset) {
+                jump("continue");
+            }
+            for (_, target) in out.gotos.range((bb.offset, 0)..=(bb.offset, u64::MAX)) {
+                jump(&format!("goto L_{:#x}", target));
+            }
+        }
+    }
+
+    fn open_loop(&self, lv: usize, out: &mut Output) {
+        match self.syntax {
+            PseudocodeSyntax::C => out.lines.push((lv, "do {".to_string())),
+            PseudocodeSyntax::Python => out.lines.push((lv, "while True:".to_string())),
+        }
+    }
+
+    // loop exited only by the breaks inside its body.
+    fn open_endless_loop(&self, lv: usize, out: &mut Output) {
+        match self.syntax {
+            PseudocodeSyntax::C => out.lines.push((lv, "while (true) {".to_string())),
+            PseudocodeSyntax::Python => out.lines.push((lv, "while True:".to_string())),
+        }
+    }
+
+    fn close_loop(&self, cond: &StructureBlock, lv: usize, out: &mut Output) {
+        match self.syntax {
+            PseudocodeSyntax::C => out
+                .lines
+                .push((lv, format!("}} while ({});", self.condition(cond)))),
+            PseudocodeSyntax::Python => out.lines.push((lv + 1, self.exit_if_false(cond))),
+        }
+    }
+
+    // conditional jump performed at the end of a block.
+    fn jump(&self, cond: &str, stmt: &str) -> String {
+        match self.syntax {
+            PseudocodeSyntax::C => format!("if ({}) {};", cond, stmt),
+            PseudocodeSyntax::Python => format!("if {}: {}", cond, stmt),
+        }
+    }
+
+    fn exit_if_false(&self, cond: &StructureBlock) -> String {
+        match self.syntax {
+            PseudocodeSyntax::C => format!("if (!{}) break;", self.condition(cond)),
+            PseudocodeSyntax::Python => format!("if not {}: break", self.condition(cond)),
+        }
+    }
+
+    fn exit_if_true(&self, cond: &StructureBlock) -> String {
+        match self.syntax {
+            PseudocodeSyntax::C => format!("if ({}) break;", self.condition(cond)),
+            PseudocodeSyntax::Python => format!("if {}: break", self.condition(cond)),
+        }
+    }
+
+    fn not(&self, expr: &str) -> String {
+        match self.syntax {
+            PseudocodeSyntax::C => format!("!{}", expr),
+            PseudocodeSyntax::Python => format!("not {}", expr),
+        }
+    }
+
+    fn open(&self, head: &str) -> String {
+        match self.syntax {
+            PseudocodeSyntax::C => format!("{} {{", head),
+            PseudocodeSyntax::Python => format!("{}:", head),
+        }
+    }
+
+    fn close(&self, lv: usize, out: &mut Output) {
+        if self.syntax == PseudocodeSyntax::C {
+            out.lines.push((lv, "}".to_string()));
+        }
+    }
+
+    fn comment(&self) -> &'static str {
+        match self.syntax {
+            PseudocodeSyntax::C => "//",
+            PseudocodeSyntax::Python => "#",
+        }
+    }
+
+    fn statement(&self, stmt: &str) -> String {
+        match self.syntax {
+            PseudocodeSyntax::C => format!("{};", stmt),
+            PseudocodeSyntax::Python => stmt.to_string(),
+        }
+    }
+
+    fn parens(&self, expr: &str) -> String {
+        match self.syntax {
+            PseudocodeSyntax::C => format!("({})", expr),
+            PseudocodeSyntax::Python => expr.to_string(),
+        }
+    }
+
+    // name of the condition computed by a block.
+    fn condition(&self, node: &StructureBlock) -> String {
+        if matches!(node.block_type(), BlockType::Condition | BlockType::IfChain) {
+            let conds = node
+                .children()
+                .iter()
+                .map(|child| self.condition(child))
+                .collect::<Vec<_>>();
+            // always parenthesized, as it may be negated
+            format!("({})", conds.join(self.and()))
+        } else {
+            format!("c_{:#x}", node.offset())
+        }
+    }
+
+    // condition of a Conditional block, as a disjunction of conjunctions.
+    fn reaching_condition(&self, node: &StructureBlock) -> String {
+        let clauses = node.reaching_condition();
+        clauses
+            .iter()
+            .map(|clause| {
+                let literals = clause
+                    .iter()
+                    .map(|(bb, taken)| {
+                        let name = format!("c_{:#x}", bb.offset);
+                        if *taken {
+                            name
+                        } else {
+                            self.not(&name)
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join(self.and());
+                if clauses.len() > 1 && clause.len() > 1 {
+                    format!("({})", literals)
+                } else {
+                    literals
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(self.or())
+    }
+
+    fn or(&self) -> &'static str {
+        match self.syntax {
+            PseudocodeSyntax::C => " || ",
+            PseudocodeSyntax::Python => " or ",
+        }
+    }
+
+    fn and(&self) -> &'static str {
+        match self.syntax {
+            PseudocodeSyntax::C => " && ",
+            PseudocodeSyntax::Python => " and ",
+        }
+    }
+}
+
+// lines generated so far, with their indentation level.
+struct Output<'a> {
+    lines: &'a mut Vec<(usize, String)>,
+    exits: &'a HashSet<u64>,
+    continues: &'a HashSet<u64>,
+    gotos: &'a BTreeSet<(u64, u64)>,
+    labels: &'a HashSet<u64>,
+    conditions: &'a HashSet<u64>,
+}
+
+// children of a structure computing its conditions, as printed by the writer.
+fn condition_children(node: &StructureBlock) -> Vec<&StructureBlock> {
+    let children = node
+        .children()
+        .iter()
+        .filter(|child| !is_marker(child))
+        .collect::<Vec<_>>();
+    match node.block_type() {
+        BlockType::While | BlockType::SelfLooping | BlockType::Guard | BlockType::Switch => {
+            children.into_iter().take(1).collect()
+        }
+        BlockType::DoWhile => children.into_iter().skip(1).take(1).collect(),
+        BlockType::IfThen => children[..children.len().saturating_sub(1)].to_vec(),
+        BlockType::IfThenElse => children[..children.len().saturating_sub(2)].to_vec(),
+        _ => Vec::new(),
+    }
+}
+
+// offsets naming the conditions computed by a block.
+fn condition_offsets(node: &StructureBlock) -> Vec<u64> {
+    match node.block_type() {
+        BlockType::Condition | BlockType::IfChain => {
+            node.children().iter().flat_map(condition_offsets).collect()
+        }
+        _ => vec![node.offset()],
+    }
+}
+
+fn is_marker(node: &StructureBlock) -> bool {
+    matches!(
+        node.block_type(),
+        BlockType::Break | BlockType::Continue | BlockType::Goto | BlockType::AbnormalEntry
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::{
+        BasicBlock, BlockType, NestedBlock, PseudocodeSyntax, PseudocodeWriter, StructureBlock,
+    };
+    use crate::disasm::{Statement, StatementFamily};
+    use std::sync::Arc;
+
+    fn bb(offset: u64) -> StructureBlock {
+        StructureBlock::from(BasicBlock { offset, length: 1 })
+    }
+
+    fn nest(bt: BlockType, children: Vec<StructureBlock>) -> StructureBlock {
+        StructureBlock::from(Arc::new(NestedBlock::new(bt, children)))
+    }
+
+    #[test]
+    fn while_ifelse_c() {
+        let ifelse = nest(BlockType::IfThenElse, vec![bb(2), bb(3), bb(4)]);
+        let body = nest(BlockType::Sequence, vec![ifelse, bb(5)]);
+        let tree = nest(BlockType::While, vec![bb(1), body]);
+        let expected = "while (true) {\n    0x1..0x2\n    if (!c_0x1) break;\n    0x2..0x3\n    \
+                        if (c_0x2) {\n        0x3..0x4\n    } else {\n        0x4..0x5\n    \
+                        }\n    0x5..0x6\n}\n";
+        assert_eq!(PseudocodeWriter::new().write(&tree), expected);
+    }
+
+    #[test]
+    fn dowhile_python() {
+        let tree = nest(BlockType::DoWhile, vec![bb(1), bb(2)]);
+        let code = PseudocodeWriter::new()
+            .syntax(PseudocodeSyntax::Python)
+            .indentation("\t")
+            .write(&tree);
+        assert_eq!(
+            code,
+            "while True:\n\t0x1..0x2\n\t0x2..0x3\n\tif not c_0x2: break\n"
+        );
+    }
+
+    #[test]
+    fn loop_exit() {
+        let marker = StructureBlock::from(Arc::new(NestedBlock::marker(
+            BlockType::Break,
+            &BasicBlock {
+                offset: 1,
+                length: 1,
+            },
+        )));
+        let tree = nest(BlockType::DoWhile, vec![bb(1), bb(2), marker]);
+        let expected =
+            "do {\n    0x1..0x2\n    if (c_0x1) break;\n    0x2..0x3\n} while (c_0x2);\n";
+        assert_eq!(PseudocodeWriter::new().write(&tree), expected);
+    }
+
+    #[test]
+    fn loop_continue() {
+        let marker = StructureBlock::from(Arc::new(NestedBlock::marker(
+            BlockType::Continue,
+            &BasicBlock {
+                offset: 2,
+                length: 1,
+            },
+        )));
+        let body = nest(BlockType::Sequence, vec![bb(2), bb(3)]);
+        let tree = nest(BlockType::While, vec![bb(1), body, marker]);
+        let expected = "while (true) {\n    0x1..0x2\n    if (!c_0x1) break;\n    0x2..0x3\n    \
+                        if (c_0x2) continue;\n    0x3..0x4\n}\n";
+        assert_eq!(PseudocodeWriter::new().write(&tree), expected);
+    }
+
+    #[test]
+    fn if_chain() {
+        let chain = nest(BlockType::IfChain, vec![bb(1), bb(2)]);
+        let tree = nest(BlockType::IfThen, vec![chain, bb(3)]);
+        let expected = "0x1..0x2\n0x2..0x3\nif c_0x1 and c_0x2:\n\t0x3..0x4\n";
+        let writer = PseudocodeWriter::new()
+            .syntax(PseudocodeSyntax::Python)
+            .indentation("\t");
+        assert_eq!(writer.write(&tree), expected);
+    }
+
+    #[test]
+    fn conditional() {
+        let cond = |offset| BasicBlock { offset, length: 1 };
+        let mut first = NestedBlock::new(BlockType::Conditional, vec![bb(2)]);
+        first.condition = vec![vec![(cond(1), false)]];
+        let mut second = NestedBlock::new(BlockType::Conditional, vec![bb(3)]);
+        second.condition = vec![
+            vec![(cond(1), false), (cond(2), true)],
+            vec![(cond(1), true)],
+        ];
+        let tree = nest(
+            BlockType::Sequence,
+            vec![
+                bb(1),
+                StructureBlock::from(Arc::new(first)),
+                StructureBlock::from(Arc::new(second)),
+            ],
+        );
+        let expected = "0x1..0x2\nif (!c_0x1) {\n    0x2..0x3\n}\n\
+                        if ((!c_0x1 && c_0x2) || c_0x1) {\n    0x3..0x4\n}\n";
+        assert_eq!(PseudocodeWriter::new().write(&tree), expected);
+    }
+
+    #[test]
+    fn switch_loop() {
+        let switch = nest(BlockType::Switch, vec![bb(1), bb(2), bb(3)]);
+        let whileb = nest(BlockType::SwitchLoop, vec![switch]);
+        let tree = nest(BlockType::Sequence, vec![bb(0), whileb, bb(4)]);
+        let expected = "0x0..0x1\nwhile (true) {\n    0x1..0x2\n    if (c_0x1_1) break;\n    \
+                        switch (c_0x1) {\n        case 0x2 {\n            0x2..0x3\n            \
+                        break;\n        }\n        case 0x3 {\n            0x3..0x4\n            \
+                        break;\n        }\n    }\n}\n0x4..0x5\n";
+        assert_eq!(PseudocodeWriter::new().write(&tree), expected);
+    }
+
+    #[test]
+    fn goto() {
+        let goto = |src, dst| {
+            let bb = |offset| BasicBlock { offset, length: 1 };
+            let mut marker = NestedBlock::marker(BlockType::Goto, &bb(src));
+            marker.exits = vec![(bb(src), bb(dst))];
+            StructureBlock::from(Arc::new(marker))
+        };
+        let dowhile = nest(BlockType::DoWhile, vec![bb(1), bb(2), goto(1, 4)]);
+        let tree = nest(BlockType::Sequence, vec![dowhile, bb(3), bb(4)]);
+        let expected = "do {\n    0x1..0x2\n    if (c_0x1) goto L_0x4;\n    0x2..0x3\n} \
+                        while (c_0x2);\n0x3..0x4\nL_0x4:\n0x4..0x5\n";
+        assert_eq!(PseudocodeWriter::new().write(&tree), expected);
+        // jumps from the block computing the loop condition, and more jumps from the same block
+        let dowhile = nest(
+            BlockType::DoWhile,
+            vec![bb(1), bb(2), goto(2, 4), goto(2, 5)],
+        );
+        let tree = nest(BlockType::Sequence, vec![dowhile, bb(3), bb(4), bb(5)]);
+        let expected = "do {\n    0x1..0x2\n    0x2..0x3\n    if (c_0x2_1) goto L_0x4;\n    \
+                        if (c_0x2_2) goto L_0x5;\n} while (c_0x2);\n0x3..0x4\nL_0x4:\n\
+                        0x4..0x5\nL_0x5:\n0x5..0x6\n";
+        assert_eq!(PseudocodeWriter::new().write(&tree), expected);
+    }
+
+    #[test]
+    fn short_circuit_loop() {
+        let cond = nest(BlockType::Condition, vec![bb(1), bb(2)]);
+        let tree = nest(BlockType::While, vec![cond, bb(3)]);
+        let expected = "while (true) {\n    0x1..0x2\n    0x2..0x3\n    \
+                        if (!(c_0x1 && c_0x2)) break;\n    \
+                        0x3..0x4\n}\n";
+        assert_eq!(PseudocodeWriter::new().write(&tree), expected);
+    }
+
+    #[test]
+    fn guard() {
+        let tree = nest(BlockType::Guard, vec![bb(1), bb(2)]);
+        let expected = "0x1..0x2\nif (c_0x1) {\n    0x2..0x3\n    return;\n}\n";
+        assert_eq!(PseudocodeWriter::new().write(&tree), expected);
+    }
+
+    #[test]
+    fn terminators() {
+        let terminator = |bt, offset| {
+            let bb = BasicBlock { offset, length: 1 };
+            StructureBlock::from(Arc::new(NestedBlock::terminator(bt, &bb)))
+        };
+        let guard = nest(
+            BlockType::Guard,
+            vec![bb(1), terminator(BlockType::Unreachable, 2)],
+        );
+        let tree = nest(
+            BlockType::Sequence,
+            vec![guard, terminator(BlockType::Return, 3)],
+        );
+        let expected = "0x1..0x2\nif c_0x1:\n    0x2..0x3\n    # unreachable\n0x3..0x4\nreturn\n";
+        let writer = PseudocodeWriter::new().syntax(PseudocodeSyntax::Python);
+        assert_eq!(writer.write(&tree), expected);
+    }
+
+    #[test]
+    fn negated_conditions() {
+        let negated = |bt, children| {
+            let block = NestedBlock::new(bt, children).with_polarity(Some(false));
+            StructureBlock::from(Arc::new(block))
+        };
+        let ifthen = negated(BlockType::IfThen, vec![bb(2), bb(3)]);
+        let tree = negated(BlockType::While, vec![bb(1), ifthen]);
+        let expected = "while (true) {\n    0x1..0x2\n    if (c_0x1) break;\n    0x2..0x3\n    \
+                        if (!c_0x2) {\n        0x3..0x4\n    }\n}\n";
+        assert_eq!(PseudocodeWriter::new().write(&tree), expected);
+        let expected = "while True:\n    0x1..0x2\n    if c_0x1: break\n    0x2..0x3\n    \
+                        if not c_0x2:\n        0x3..0x4\n";
+        let python = PseudocodeWriter::new().syntax(PseudocodeSyntax::Python);
+        assert_eq!(python.write(&tree), expected);
+    }
+
+    #[test]
+    fn statements() {
+        let stmts = vec![
+            Statement::new(0, StatementFamily::CMP, "cmp eax, 0"),
+            Statement::new(1, StatementFamily::CJMP, "jne 3"),
+            Statement::new(2, StatementFamily::ADD, "inc eax"),
+            Statement::new(3, StatementFamily::RET, "ret"),
+        ];
+        let head = StructureBlock::from(BasicBlock {
+            offset: 0,
+            length: 2,
+        });
+        let tree = nest(BlockType::IfThen, vec![head, bb(2)]);
+        let code = tree.to_pseudocode();
+        assert_eq!(code, "0x0..0x2\nif (c_0x0) {\n    0x2..0x3\n}\n");
+        let code = PseudocodeWriter::new().with_statements(&stmts).write(&tree);
+        assert_eq!(code, "cmp eax, 0\njne 3\nif (c_0x0) {\n    inc eax\n}\n");
+    }
+}
@@ -0,0 +1,1037 @@
+use crate::analysis::svg::{escape, BlockStyle, SvgGraph};
+use crate::analysis::{
+    BinaryDiff, BlockType, ComparisonMode, FunctionMatch, Graph, PatchedRegion, StructureBlock, CFS,
+};
+use crate::disasm::{Binary, Function};
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::ops::Range;
+
+const SARIF_VERSION: &str = "2.1.0";
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+// rules of the SARIF results, one for each kind of difference.
+const RULE_CHANGED: &str = "changed-function";
+const RULE_ADDED: &str = "added-function";
+const RULE_REMOVED: &str = "removed-function";
+// stylesheet of the HTML reports.
+const HTML_STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}\
+table{border-collapse:collapse;margin-bottom:1em}\
+th,td{border:1px solid #bbb;padding:2px 8px;text-align:left}\
+th{background:#eee}\
+figure{margin:0 0 2em 0;overflow-x:auto}\
+li{font-family:monospace}\n";
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Report of a [`BinaryDiff`], for dashboards, continuous integration and human reviewers.
+///
+/// The report can be written in five formats:
+/// - [`Report::to_json`], a JSON document whose schema is identified by [`Report::VERSION`];
+/// - [`Report::to_sarif`], a SARIF 2.1.0 log with a result for each changed, added and removed
+///   function;
+/// - [`Report::to_text`], a table for human readers;
+/// - [`Report::to_markdown`] and [`Report::to_html`], self-contained documents for human
+///   readers, with the graphs of the changed functions if the binaries are given with
+///   [`Report::with_binaries`].
+///
+/// The reported functions can be restricted with [`Report::with_filter`].
+///
+/// Addresses in the JSON document are hexadecimal strings, like `"0x401000"`, so they are not
+/// truncated by the parsers representing numbers as 64-bit floats.
+/// # Examples
+/// Basic usage:
+/// ```no_run
+/// use bincc::analysis::{BinaryDiff, Report};
+/// use bincc::disasm::Binary;
+/// # let old: Binary = unimplemented!();
+/// # let new: Binary = unimplemented!();
+///
+/// let diff = BinaryDiff::new(&old, &new);
+/// let json = Report::new("app-1.0", "app-1.1", &diff).to_json();
+/// ```
+#[cfg_attr(docsrs, doc(cfg(feature = "disasm")))]
+pub struct Report<'a> {
+    old: &'a str,
+    new: &'a str,
+    diff: &'a BinaryDiff,
+    filter: Option<Box<dyn Fn(&Function) -> bool + 'a>>,
+    binaries: Option<(&'a Binary, &'a Binary)>,
+}
+
+impl<'a> Report<'a> {
+    /// Version of the schema of the JSON document written by [`Report::to_json`].
+    ///
+    /// Increased every time a field is removed or changes meaning, so consumers can reject the
+    /// documents they do not understand. Adding fields does not change the version.
+    pub const VERSION: u32 = 1;
+
+    /// Creates the report of the given diff, naming the old and new binaries as given.
+    pub fn new(old: &'a str, new: &'a str, diff: &'a BinaryDiff) -> Report<'a> {
+        Report {
+            old,
+            new,
+            diff,
+            filter: None,
+            binaries: None,
+        }
+    }
+
+    /// Reports only the functions accepted by the given filter.
+    ///
+    /// A pair of matched functions is reported if any of the two functions is accepted. The
+    /// summary and the similarity of the binaries always refer to the whole diff.
+    pub fn with_filter<F: Fn(&Function) -> bool + 'a>(mut self, filter: F) -> Report<'a> {
+        self.filter = Some(Box::new(filter));
+        self
+    }
+
+    /// Embeds the graphs of the changed functions in the Markdown and HTML reports.
+    ///
+    /// The two binaries must be the ones compared by the diff. Each changed function is drawn as
+    /// in [`MatchView::to_svg`](crate::analysis::MatchView::to_svg), with the changed blocks
+    /// highlighted.
+    pub fn with_binaries(mut self, old: &'a Binary, new: &'a Binary) -> Report<'a> {
+        self.binaries = Some((old, new));
+        self
+    }
+
+    /// Writes the report as a JSON document.
+    ///
+    /// The document contains the following fields:
+    /// - `version`: the [`Report::VERSION`] of the schema;
+    /// - `old` and `new`: the names of the compared binaries;
+    /// - `mode`: the [`ComparisonMode`], either `same-architecture` or `cross-architecture`;
+    /// - `similarity`: the [`BinaryDiff::similarity`] of the two binaries;
+    /// - `summary`: the amount of `matched`, `identical`, `changed`, `added` and `removed`
+    ///   functions;
+    /// - `matches`: the paired functions, each with its `old` and `new` function, `similarity`,
+    ///   `identical` flag and `changes`. Each function has a `name`, an `offset` and the address
+    ///   `ranges` it covers, each range with its `start` (inclusive) and `end` (exclusive). Each
+    ///   change, see [`FunctionMatch::changes`], has a `kind`, the `old_ranges` and `new_ranges`
+    ///   it covers, and the `old_blocks` and `new_blocks` with the type of each changed subtree;
+    /// - `added` and `removed`: the unpaired functions, each with a `name` and an `offset`.
+    pub fn to_json(&self) -> String {
+        let diff = self.diff;
+        let mut json = String::new();
+        write!(
+            json,
+            "{{\"version\":{},\"old\":{},\"new\":{},\"mode\":\"{}\",\"similarity\":{},",
+            Report::VERSION,
+            json_quote(self.old),
+            json_quote(self.new),
+            mode_name(diff.mode()),
+            diff.similarity()
+        )
+        .unwrap();
+        write!(
+            json,
+            "\"summary\":{{\"matched\":{},\"identical\":{},\"changed\":{},\"added\":{},\
+             \"removed\":{}}},",
+            diff.matches().len(),
+            diff.identical(),
+            diff.changed(),
+            diff.added().len(),
+            diff.removed().len()
+        )
+        .unwrap();
+        let matches = self
+            .matches()
+            .into_iter()
+            .map(match_json)
+            .collect::<Vec<_>>();
+        let functions = |functions: &[Function]| {
+            self.accepted(functions)
+                .into_iter()
+                .map(|function| function_json(function, None))
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        write!(
+            json,
+            "\"matches\":[{}],\"added\":[{}],\"removed\":[{}]}}",
+            matches.join(","),
+            functions(diff.added()),
+            functions(diff.removed())
+        )
+        .unwrap();
+        json
+    }
+
+    /// Writes the report as a SARIF 2.1.0 log.
+    ///
+    /// The log contains a single run, with a result for each changed, added and removed function.
+    /// Changed functions are located at the address ranges of their changes in the new binary,
+    /// or at the whole new function if the changes are not visible in the new binary. Added and
+    /// removed functions are located at their offset. Removed functions are reported as
+    /// `warning`, the others as `note`, and unchanged functions are not reported.
+    pub fn to_sarif(&self) -> String {
+        let mut results = Vec::new();
+        for matched in self.matches().into_iter().filter(|m| !m.is_identical()) {
+            let message = format!(
+                "{} changed, similarity {:.3}",
+                matched.new_function().get_name(),
+                matched.similarity()
+            );
+            let mut ranges = matched
+                .changes()
+                .iter()
+                .flat_map(|change| change.new_ranges())
+                .collect::<Vec<_>>();
+            if ranges.is_empty() {
+                ranges = matched.new_ranges().to_vec();
+            }
+            let locations = ranges
+                .iter()
+                .map(|range| location(self.new, range.start, Some(range.end - range.start)))
+                .collect::<Vec<_>>();
+            results.push(result(RULE_CHANGED, "note", &message, &locations));
+        }
+        for function in self.accepted(self.diff.added()) {
+            let message = format!("{} added", function.get_name());
+            let locations = [location(self.new, function.get_offset(), None)];
+            results.push(result(RULE_ADDED, "note", &message, &locations));
+        }
+        for function in self.accepted(self.diff.removed()) {
+            let message = format!("{} removed", function.get_name());
+            let locations = [location(self.old, function.get_offset(), None)];
+            results.push(result(RULE_REMOVED, "warning", &message, &locations));
+        }
+        let rules = [RULE_CHANGED, RULE_ADDED, RULE_REMOVED]
+            .iter()
+            .map(|rule| format!("{{\"id\":\"{}\"}}", rule))
+            .collect::<Vec<_>>();
+        format!(
+            "{{\"version\":\"{}\",\"$schema\":\"{}\",\"runs\":[{{\"tool\":{{\"driver\":{{\
+             \"name\":\"{}\",\"version\":\"{}\",\"rules\":[{}]}}}},\"results\":[{}]}}]}}",
+            SARIF_VERSION,
+            SARIF_SCHEMA,
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION"),
+            rules.join(","),
+            results.join(",")
+        )
+    }
+
+    /// Writes the report as a plain text table.
+    ///
+    /// The table has a row for each matched, added and removed function, with its status, the
+    /// similarity of the match, and the offset and name of the old and new function. The
+    /// matched functions are sorted by offset in the old binary, followed by the added and the
+    /// removed ones. The table is preceded by the names of the binaries and followed by the
+    /// summary of the diff.
+    pub fn to_text(&self) -> String {
+        let function =
+            |function: &Function| format!("{:#x} {}", function.get_offset(), function.get_name());
+        let mut rows = vec![[
+            "STATUS".to_string(),
+            "SIMILARITY".to_string(),
+            "OLD".to_string(),
+            "NEW".to_string(),
+        ]];
+        for matched in self.matches() {
+            let status = match matched.is_identical() {
+                true => "identical",
+                false => "changed",
+            };
+            rows.push([
+                status.to_string(),
+                format!("{:.3}", matched.similarity()),
+                function(matched.old_function()),
+                function(matched.new_function()),
+            ]);
+        }
+        let none = || "-".to_string();
+        for added in self.accepted(self.diff.added()) {
+            rows.push(["added".to_string(), none(), none(), function(added)]);
+        }
+        for removed in self.accepted(self.diff.removed()) {
+            rows.push(["removed".to_string(), none(), function(removed), none()]);
+        }
+        let mut widths = [0; 3];
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+        let diff = self.diff;
+        let mut text = format!(
+            "old: {}\nnew: {}\nmode: {}\n\n",
+            self.old,
+            self.new,
+            mode_name(diff.mode())
+        );
+        for row in rows {
+            writeln!(
+                text,
+                "{:<w0$}  {:<w1$}  {:<w2$}  {}",
+                row[0],
+                row[1],
+                row[2],
+                row[3],
+                w0 = widths[0],
+                w1 = widths[1],
+                w2 = widths[2]
+            )
+            .unwrap();
+        }
+        write!(
+            text,
+            "\nmatched: {} identical: {} changed: {} added: {} removed: {} similarity: {:.3}\n",
+            diff.matches().len(),
+            diff.identical(),
+            diff.changed(),
+            diff.added().len(),
+            diff.removed().len(),
+            diff.similarity()
+        )
+        .unwrap();
+        text
+    }
+
+    /// Writes the report as a Markdown document.
+    ///
+    /// The document contains the summary of the diff, a table for each of the changed, added,
+    /// removed and identical functions, and a section for each changed function listing its
+    /// changes, see [`FunctionMatch::changes`]. If the binaries are given with
+    /// [`Report::with_binaries`], each section contains also the graphs of the two functions, as
+    /// an embedded SVG image, so the document does not depend on any other file.
+    pub fn to_markdown(&self) -> String {
+        self.to_page(Markup::Markdown)
+    }
+
+    /// Writes the report as a self-contained HTML page.
+    ///
+    /// The page has the same content of [`Report::to_markdown`], with the graphs written inline.
+    pub fn to_html(&self) -> String {
+        self.to_page(Markup::Html)
+    }
+
+    fn to_page(&self, markup: Markup) -> String {
+        let diff = self.diff;
+        let mut page = Page::new(markup, &format!("{} vs {}", self.old, self.new));
+        page.table(
+            &[
+                "Mode",
+                "Similarity",
+                "Matched",
+                "Identical",
+                "Changed",
+                "Added",
+                "Removed",
+            ],
+            &[vec![
+                mode_name(diff.mode()).to_string(),
+                format!("{:.3}", diff.similarity()),
+                diff.matches().len().to_string(),
+                diff.identical().to_string(),
+                diff.changed().to_string(),
+                diff.added().len().to_string(),
+                diff.removed().len().to_string(),
+            ]],
+        );
+        let (identical, changed) = self
+            .matches()
+            .into_iter()
+            .partition::<Vec<_>, _>(|matched| matched.is_identical());
+        if !changed.is_empty() {
+            page.heading(2, "Changed functions");
+            let rows = changed
+                .iter()
+                .map(|matched| {
+                    vec![
+                        function_text(matched.old_function()),
+                        function_text(matched.new_function()),
+                        format!("{:.3}", matched.similarity()),
+                        matched.changes().len().to_string(),
+                    ]
+                })
+                .collect::<Vec<_>>();
+            page.table(&["Old", "New", "Similarity", "Changes"], &rows);
+            for matched in &changed {
+                let title = function_text(matched.new_function());
+                page.heading(3, &title);
+                let changes = matched
+                    .changes()
+                    .iter()
+                    .map(change_text)
+                    .collect::<Vec<_>>();
+                page.list(&changes);
+                if let Some((old, new)) = self.binaries {
+                    if let Some(view) = diff.view(matched, old, new) {
+                        page.graph(&view.to_svg(), &title);
+                    }
+                }
+            }
+        }
+        for (title, functions) in [
+            ("Added functions", diff.added()),
+            ("Removed functions", diff.removed()),
+        ] {
+            let rows = self
+                .accepted(functions)
+                .into_iter()
+                .map(|function| {
+                    vec![
+                        format!("{:#x}", function.get_offset()),
+                        function.get_name().to_string(),
+                    ]
+                })
+                .collect::<Vec<_>>();
+            if !rows.is_empty() {
+                page.heading(2, title);
+                page.table(&["Offset", "Name"], &rows);
+            }
+        }
+        if !identical.is_empty() {
+            page.heading(2, "Identical functions");
+            let rows = identical
+                .iter()
+                .map(|matched| {
+                    vec![
+                        function_text(matched.old_function()),
+                        function_text(matched.new_function()),
+                    ]
+                })
+                .collect::<Vec<_>>();
+            page.table(&["Old", "New"], &rows);
+        }
+        page.finish()
+    }
+
+    // matches with any of the two functions accepted by the filter.
+    fn matches(&self) -> Vec<&FunctionMatch> {
+        self.diff
+            .matches()
+            .iter()
+            .filter(|m| self.accepts(m.old_function()) || self.accepts(m.new_function()))
+            .collect()
+    }
+
+    // functions accepted by the filter.
+    fn accepted<'b>(&self, functions: &'b [Function]) -> Vec<&'b Function> {
+        functions
+            .iter()
+            .filter(|function| self.accepts(function))
+            .collect()
+    }
+
+    fn accepts(&self, function: &Function) -> bool {
+        self.filter.as_ref().map_or(true, |filter| filter(function))
+    }
+}
+
+fn mode_name(mode: ComparisonMode) -> &'static str {
+    match mode {
+        ComparisonMode::SameArchitecture => "same-architecture",
+        ComparisonMode::CrossArchitecture => "cross-architecture",
+    }
+}
+
+fn match_json(matched: &FunctionMatch) -> String {
+    let changes = matched
+        .changes()
+        .iter()
+        .map(change_json)
+        .collect::<Vec<_>>();
+    format!(
+        "{{\"old\":{},\"new\":{},\"similarity\":{},\"identical\":{},\"changes\":[{}]}}",
+        function_json(matched.old_function(), Some(matched.old_ranges())),
+        function_json(matched.new_function(), Some(matched.new_ranges())),
+        matched.similarity(),
+        matched.is_identical(),
+        changes.join(",")
+    )
+}
+
+fn change_json(change: &PatchedRegion) -> String {
+    let types = |blocks: &[StructureBlock]| {
+        blocks
+            .iter()
+            .map(|block| json_quote(&block.block_type().to_string()))
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    format!(
+        "{{\"kind\":\"{}\",\"old_ranges\":{},\"new_ranges\":{},\"old_blocks\":[{}],\
+         \"new_blocks\":[{}]}}",
+        change.kind().to_str(),
+        ranges_json(&change.old_ranges()),
+        ranges_json(&change.new_ranges()),
+        types(change.old_blocks()),
+        types(change.new_blocks())
+    )
+}
+
+fn function_json(function: &Function, ranges: Option<&[Range<u64>]>) -> String {
+    let mut json = format!(
+        "{{\"name\":{},\"offset\":\"{:#x}\"",
+        json_quote(function.get_name()),
+        function.get_offset()
+    );
+    if let Some(ranges) = ranges {
+        write!(json, ",\"ranges\":{}", ranges_json(ranges)).unwrap();
+    }
+    json.push('}');
+    json
+}
+
+fn ranges_json(ranges: &[Range<u64>]) -> String {
+    let ranges = ranges
+        .iter()
+        .map(|range| {
+            format!(
+                "{{\"start\":\"{:#x}\",\"end\":\"{:#x}\"}}",
+                range.start, range.end
+            )
+        })
+        .collect::<Vec<_>>();
+    format!("[{}]", ranges.join(","))
+}
+
+/// Human-readable report of the structure of the functions of a [`Binary`].
+///
+/// The report can be written as a Markdown document, with [`AnalysisReport::to_markdown`], or as
+/// an HTML page, with [`AnalysisReport::to_html`]. Both contain a table with the metrics of each
+/// function and, unless disabled with [`AnalysisReport::with_graphs`], the [`CFG`] of each
+/// function drawn as an embedded SVG image. The blocks that could not be structured, because they
+/// are part of a [`BlockType::ImproperRegion`], have a red border.
+///
+/// [`CFG`]: crate::analysis::CFG
+/// # Examples
+/// Basic usage:
+/// ```no_run
+/// use bincc::analysis::{AnalysisReport, CFS};
+/// use bincc::disasm::Binary;
+/// # let binary: Binary = unimplemented!();
+///
+/// let structures = binary
+///     .functions()
+///     .iter()
+///     .filter_map(|function| {
+///         let cfs = CFS::new(binary.cfg(function.get_offset())?).ok()?;
+///         Some((function, cfs))
+///     })
+///     .collect::<Vec<_>>();
+/// let html = AnalysisReport::new("app", &binary, &structures).to_html();
+/// ```
+#[cfg_attr(docsrs, doc(cfg(feature = "disasm")))]
+pub struct AnalysisReport<'a> {
+    name: &'a str,
+    binary: &'a Binary,
+    structures: &'a [(&'a Function, CFS)],
+    graphs: bool,
+}
+
+impl<'a> AnalysisReport<'a> {
+    /// Creates the report of the given binary, named as given.
+    ///
+    /// Each function is reported together with its structure, in the given order.
+    pub fn new(
+        name: &'a str,
+        binary: &'a Binary,
+        structures: &'a [(&'a Function, CFS)],
+    ) -> AnalysisReport<'a> {
+        AnalysisReport {
+            name,
+            binary,
+            structures,
+            graphs: true,
+        }
+    }
+
+    /// Embeds or omits the graph of each function.
+    ///
+    /// The graphs are embedded by default.
+    pub fn with_graphs(mut self, graphs: bool) -> AnalysisReport<'a> {
+        self.graphs = graphs;
+        self
+    }
+
+    /// Writes the report as a Markdown document.
+    ///
+    /// The document contains the architecture of the binary, the amount of functions fully
+    /// structured, and a table with the offset, name, amount of blocks and edges, depth and nodes
+    /// of the structure tree, and [`CFS::fidelity`] of each function. The graphs follow the table,
+    /// embedded as SVG images, so the document does not depend on any other file.
+    pub fn to_markdown(&self) -> String {
+        self.to_page(Markup::Markdown)
+    }
+
+    /// Writes the report as a self-contained HTML page.
+    ///
+    /// The page has the same content of [`AnalysisReport::to_markdown`], with the graphs written
+    /// inline.
+    pub fn to_html(&self) -> String {
+        self.to_page(Markup::Html)
+    }
+
+    fn to_page(&self, markup: Markup) -> String {
+        let mut page = Page::new(markup, &format!("Analysis of {}", self.name));
+        let architecture = self
+            .binary
+            .architecture()
+            .map_or("unknown".to_string(), |arch| {
+                format!("{} {}-bit", arch.name(), arch.bits())
+            });
+        let complete = self
+            .structures
+            .iter()
+            .filter(|(_, cfs)| cfs.result().is_complete())
+            .count();
+        page.table(
+            &["Architecture", "Functions", "Complete"],
+            &[vec![
+                architecture,
+                self.structures.len().to_string(),
+                complete.to_string(),
+            ]],
+        );
+        let rows = self
+            .structures
+            .iter()
+            .map(|(function, cfs)| {
+                let cfg = cfs.get_cfg();
+                let edges = cfg
+                    .blocks_sorted()
+                    .into_iter()
+                    .map(|bb| cfg.neighbours(bb).len())
+                    .sum::<usize>();
+                let (depth, nodes) =
+                    cfs.get_tree()
+                        .map_or(("-".to_string(), "-".to_string()), |tree| {
+                            let metrics = tree.metrics();
+                            (metrics.depth().to_string(), metrics.nodes().to_string())
+                        });
+                vec![
+                    format!("{:#x}", function.get_offset()),
+                    function.get_name().to_string(),
+                    cfg.len().to_string(),
+                    edges.to_string(),
+                    depth,
+                    nodes,
+                    if cfs.result().is_complete() {
+                        "yes"
+                    } else {
+                        "no"
+                    }
+                    .to_string(),
+                    format!("{:.3}", cfs.fidelity()),
+                ]
+            })
+            .collect::<Vec<_>>();
+        if !rows.is_empty() {
+            page.heading(2, "Functions");
+            page.table(
+                &[
+                    "Offset", "Name", "Blocks", "Edges", "Depth", "Nodes", "Complete", "Fidelity",
+                ],
+                &rows,
+            );
+        }
+        if self.graphs {
+            for (function, cfs) in self.structures {
+                let title = function_text(function);
+                page.heading(3, &title);
+                page.graph(&structure_svg(cfs), &title);
+            }
+        }
+        page.finish()
+    }
+}
+
+// draws the CFG of the structure, highlighting the blocks left unstructured.
+fn structure_svg(cfs: &CFS) -> String {
+    // the nodes left unreduced when the reduction stalls end up in an ImproperRegion of the tree
+    let tree = cfs.get_tree();
+    let unstructured = tree
+        .iter()
+        .flat_map(StructureBlock::iter_preorder)
+        .filter(|block| block.block_type() == BlockType::ImproperRegion)
+        .flat_map(StructureBlock::basic_blocks)
+        .collect::<HashSet<_>>();
+    SvgGraph::new(cfs.get_cfg(), |bb| match unstructured.contains(bb) {
+        true => BlockStyle {
+            border: "red",
+            highlight: true,
+            ..BlockStyle::default()
+        },
+        false => BlockStyle::default(),
+    })
+    .to_svg()
+}
+
+fn function_text(function: &Function) -> String {
+    format!("{:#x} {}", function.get_offset(), function.get_name())
+}
+
+// kind of the change, followed by the ranges and block types it covers in both functions.
+fn change_text(change: &PatchedRegion) -> String {
+    let side = |ranges: Vec<Range<u64>>, blocks: &[StructureBlock]| {
+        if ranges.is_empty() {
+            return "-".to_string();
+        }
+        let ranges = ranges
+            .iter()
+            .map(|range| format!("{:#x}..{:#x}", range.start, range.end))
+            .collect::<Vec<_>>();
+        let types = blocks
+            .iter()
+            .map(|block| block.block_type().to_string())
+            .collect::<Vec<_>>();
+        format!("{} ({})", ranges.join(", "), types.join(", "))
+    };
+    format!(
+        "{}: old {}, new {}",
+        change.kind().to_str(),
+        side(change.old_ranges(), change.old_blocks()),
+        side(change.new_ranges(), change.new_blocks())
+    )
+}
+
+#[derive(Copy, Clone)]
+enum Markup {
+    Markdown,
+    Html,
+}
+
+// document written by the human-readable reports, one element at a time.
+struct Page {
+    markup: Markup,
+    title: String,
+    body: String,
+}
+
+impl Page {
+    fn new(markup: Markup, title: &str) -> Page {
+        let mut page = Page {
+            markup,
+            title: title.to_string(),
+            body: String::new(),
+        };
+        page.heading(1, title);
+        page
+    }
+
+    fn heading(&mut self, level: usize, text: &str) {
+        match self.markup {
+            Markup::Markdown => {
+                writeln!(self.body, "{} {}\n", "#".repeat(level), md_escape(text))
+            }
+            Markup::Html => writeln!(self.body, "<h{0}>{1}</h{0}>", level, escape(text)),
+        }
+        .unwrap();
+    }
+
+    fn table(&mut self, header: &[&str], rows: &[Vec<String>]) {
+        match self.markup {
+            Markup::Markdown => {
+                let row = |cells: &[&str]| {
+                    let cells = cells.iter().map(|cell| md_escape(cell)).collect::<Vec<_>>();
+                    format!("| {} |\n", cells.join(" | "))
+                };
+                self.body.push_str(&row(header));
+                writeln!(self.body, "|{}", "---|".repeat(header.len())).unwrap();
+                for cells in rows {
+                    self.body
+                        .push_str(&row(&cells.iter().map(String::as_str).collect::<Vec<_>>()));
+                }
+                self.body.push('\n');
+            }
+            Markup::Html => {
+                let row = |tag: &str, cells: &[&str]| {
+                    let cells = cells
+                        .iter()
+                        .map(|cell| format!("<{0}>{1}</{0}>", tag, escape(cell)))
+                        .collect::<String>();
+                    format!("<tr>{}</tr>\n", cells)
+                };
+                self.body.push_str("<table>\n");
+                self.body.push_str(&row("th", header));
+                for cells in rows {
+                    self.body.push_str(&row(
+                        "td",
+                        &cells.iter().map(String::as_str).collect::<Vec<_>>(),
+                    ));
+                }
+                self.body.push_str("</table>\n");
+            }
+        }
+    }
+
+    fn list(&mut self, items: &[String]) {
+        match self.markup {
+            Markup::Markdown => {
+                for item in items {
+                    writeln!(self.body, "- {}", md_escape(item)).unwrap();
+                }
+                self.body.push('\n');
+            }
+            Markup::Html => {
+                self.body.push_str("<ul>\n");
+                for item in items {
+                    writeln!(self.body, "<li>{}</li>", escape(item)).unwrap();
+                }
+                self.body.push_str("</ul>\n");
+            }
+        }
+    }
+
+    // embeds the SVG document, as a data URI in Markdown, as it is in HTML.
+    fn graph(&mut self, svg: &str, description: &str) {
+        match self.markup {
+            Markup::Markdown => writeln!(
+                self.body,
+                "![{}](data:image/svg+xml;base64,{})\n",
+                md_escape(description),
+                base64(svg.as_bytes())
+            ),
+            Markup::Html => write!(self.body, "<figure>\n{}</figure>\n", svg),
+        }
+        .unwrap();
+    }
+
+    fn finish(self) -> String {
+        match self.markup {
+            Markup::Markdown => self.body,
+            Markup::Html => format!(
+                "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+                 <title>{}</title>\n<style>\n{}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+                escape(&self.title),
+                HTML_STYLE,
+                self.body
+            ),
+        }
+    }
+}
+
+// escapes the characters with a special meaning in Markdown, also inside tables.
+fn md_escape(string: &str) -> String {
+    let mut escaped = String::with_capacity(string.len());
+    for c in string.chars() {
+        match c {
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '|' | '#' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' | '\r' => escaped.push(' '),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn base64(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let value = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for index in 0..4 {
+            if index <= chunk.len() {
+                let sextet = (value >> (18 - 6 * index)) & 0x3F;
+                encoded.push(BASE64_ALPHABET[sextet as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+fn result(rule: &str, level: &str, message: &str, locations: &[String]) -> String {
+    format!(
+        "{{\"ruleId\":\"{}\",\"level\":\"{}\",\"message\":{{\"text\":{}}},\"locations\":[{}]}}",
+        rule,
+        level,
+        json_quote(message),
+        locations.join(",")
+    )
+}
+
+fn location(binary: &str, address: u64, length: Option<u64>) -> String {
+    let length = length.map_or(String::new(), |length| format!(",\"length\":{}", length));
+    format!(
+        "{{\"physicalLocation\":{{\"artifactLocation\":{{\"uri\":{}}},\
+         \"address\":{{\"absoluteAddress\":{}{}}}}}}}",
+        json_quote(binary),
+        address,
+        length
+    )
+}
+
+/// Encodes the given text as a JSON string, with the surrounding quotes.
+///
+/// This is the escaping used by every JSON document written by this crate, like the
+/// [`AnalysisReport`] and the responses of the [`Server`](crate::analysis::Server).
+/// # Examples
+/// Basic usage:
+/// ```
+/// use bincc::analysis::json_quote;
+///
+/// assert_eq!(json_quote("say \"hi\"\n"), r#""say \"hi\"\n""#);
+/// ```
+#[cfg_attr(docsrs, doc(cfg(feature = "disasm")))]
+pub fn json_quote(string: &str) -> String {
+    let mut quoted = String::with_capacity(string.len() + 2);
+    quoted.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(quoted, "\\u{:04x}", c as u32).unwrap(),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{base64, json_quote, md_escape};
+    use crate::analysis::test_utils::binary;
+    use crate::analysis::{AnalysisReport, BinaryDiff, Report, CFS};
+
+    #[tokio::test]
+    async fn json() {
+        let old = binary(vec![(0x100, "main", 5), (0x200, "old\"name", 3)]).await;
+        let new = binary(vec![(0x100, "main", 7)]).await;
+        let diff = BinaryDiff::new(&old, &new);
+        let json = Report::new("a-1.0", "a-1.1", &diff).to_json();
+        assert!(json.starts_with(&format!("{{\"version\":{},", Report::VERSION)));
+        assert!(json.contains("\"mode\":\"same-architecture\""));
+        assert!(json.contains(
+            "\"summary\":{\"matched\":1,\"identical\":0,\"changed\":1,\"added\":0,\"removed\":1}"
+        ));
+        assert!(json.contains(
+            "\"new\":{\"name\":\"main\",\"offset\":\"0x100\",\
+             \"ranges\":[{\"start\":\"0x100\",\"end\":\"0x10a\"}]}"
+        ));
+        assert!(json.contains("\"removed\":[{\"name\":\"old\\\"name\",\"offset\":\"0x200\"}]"));
+        assert!(json.contains(
+            "\"changes\":[{\"kind\":\"modified\",\
+             \"old_ranges\":[{\"start\":\"0x102\",\"end\":\"0x107\"}],\
+             \"new_ranges\":[{\"start\":\"0x102\",\"end\":\"0x109\"}],\
+             \"old_blocks\":[\"Basic Block\"],\"new_blocks\":[\"Basic Block\"]}]"
+        ));
+        assert_eq!(json.matches('{').count(), json.matches('}').count());
+    }
+
+    #[tokio::test]
+    async fn sarif() {
+        let old = binary(vec![(0x100, "main", 5)]).await;
+        let new = binary(vec![(0x100, "main", 7), (0x200, "added", 3)]).await;
+        let diff = BinaryDiff::new(&old, &new);
+        let sarif = Report::new("a-1.0", "a-1.1", &diff).to_sarif();
+        assert!(sarif.starts_with("{\"version\":\"2.1.0\""));
+        assert_eq!(sarif.matches("\"ruleId\"").count(), 2);
+        // the location is the changed block, not the whole function
+        assert!(sarif.contains("\"address\":{\"absoluteAddress\":258,\"length\":7}"));
+        assert!(sarif.contains("\"message\":{\"text\":\"added added\"}"));
+    }
+
+    #[tokio::test]
+    async fn text() {
+        let old = binary(vec![(0x100, "main", 5), (0x200, "parse", 3)]).await;
+        let new = binary(vec![(0x100, "main", 7), (0x300, "added", 4)]).await;
+        let diff = BinaryDiff::new(&old, &new);
+        let text = Report::new("a-1.0", "a-1.1", &diff).to_text();
+        assert!(text.starts_with("old: a-1.0\nnew: a-1.1\nmode: same-architecture\n"));
+        assert!(text.contains("\nSTATUS   SIMILARITY  OLD          NEW\n"));
+        assert!(text.contains("\nchanged  1.000       0x100 main   0x100 main\n"));
+        assert!(text.contains("\nmatched: 2 identical: 0 changed: 2 added: 0 removed: 0"));
+        let filtered = Report::new("a-1.0", "a-1.1", &diff)
+            .with_filter(|function| function.get_name() == "main")
+            .to_text();
+        assert!(filtered.contains("0x100 main"));
+        assert!(!filtered.contains("0x200 parse"));
+        let json = Report::new("a-1.0", "a-1.1", &diff)
+            .with_filter(|function| function.get_offset() == 0x300)
+            .to_json();
+        assert!(json.contains("\"offset\":\"0x300\""));
+        assert!(!json.contains("\"offset\":\"0x100\""));
+    }
+
+    #[tokio::test]
+    async fn markdown() {
+        let old = binary(vec![(0x100, "main", 5), (0x200, "parse", 3)]).await;
+        let new = binary(vec![(0x100, "main", 7), (0x300, "do_it", 4)]).await;
+        let diff = BinaryDiff::new(&old, &new);
+        let markdown = Report::new("a-1.0", "a-1.1", &diff).to_markdown();
+        assert!(markdown.starts_with("# a-1.0 vs a-1.1\n"));
+        assert!(markdown.contains("\n## Changed functions\n"));
+        assert!(markdown.contains("| 0x100 main | 0x100 main | 1.000 | 1 |\n"));
+        assert!(markdown.contains(
+            "- modified: old 0x102..0x107 (Basic Block), new 0x102..0x109 (Basic Block)\n"
+        ));
+        assert!(!markdown.contains("## Added functions"));
+        assert!(!markdown.contains("data:image/svg+xml"));
+        let with_graphs = Report::new("a-1.0", "a-1.1", &diff)
+            .with_binaries(&old, &new)
+            .to_markdown();
+        assert_eq!(
+            with_graphs.matches("](data:image/svg+xml;base64,").count(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn html() {
+        let old = binary(vec![(0x100, "main", 5), (0x200, "a<b", 3)]).await;
+        let new = binary(vec![(0x100, "main", 7)]).await;
+        let diff = BinaryDiff::new(&old, &new);
+        let html = Report::new("a-1.0", "a-1.1", &diff)
+            .with_binaries(&old, &new)
+            .to_html();
+        assert!(html.starts_with("<!DOCTYPE html>\n"));
+        assert!(html.contains("<title>a-1.0 vs a-1.1</title>"));
+        assert!(html.contains("<h2>Removed functions</h2>"));
+        assert!(html.contains("<tr><td>0x200</td><td>a&lt;b</td></tr>"));
+        assert_eq!(html.matches("<svg").count(), 1);
+        assert!(html.ends_with("</html>\n"));
+    }
+
+    #[tokio::test]
+    async fn analysis() {
+        let binary = binary(vec![(0x100, "main", 5), (0x200, "parse", 3)]).await;
+        let structures = binary
+            .functions()
+            .iter()
+            .map(|function| {
+                let cfs = CFS::new(binary.cfg(function.get_offset()).unwrap()).unwrap();
+                (function, cfs)
+            })
+            .collect::<Vec<_>>();
+        let report = AnalysisReport::new("a_1", &binary, &structures);
+        let markdown = report.to_markdown();
+        assert!(markdown.starts_with("# Analysis of a\\_1\n"));
+        assert!(markdown.contains("| x86 64-bit | 2 | 2 |\n"));
+        assert!(markdown.contains("\n| 0x100 | main | 3 | 3 | "));
+        assert!(markdown.contains(" | yes | 1.000 |\n"));
+        assert_eq!(markdown.matches("![").count(), 2);
+        let html = report.with_graphs(false).to_html();
+        assert_eq!(html.matches("<tr>").count(), 5);
+        assert!(!html.contains("<svg"));
+    }
+
+    #[test]
+    fn encoding() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+        assert_eq!(md_escape("a_b|c\n*"), "a\\_b\\|c \\*");
+    }
+
+    #[test]
+    fn escape() {
+        assert_eq!(json_quote("a\"b\\c\n\u{1}"), "\"a\\\"b\\\\c\\n\\u0001\"");
+        assert_eq!(json_quote("función"), "\"función\"");
+    }
+}
@@ -0,0 +1,349 @@
+#[cfg(feature = "compare-db")]
+use crate::analysis::FingerprintDatabase;
+use crate::analysis::{tree_similarity, Fingerprint, StructureBlock, CFG, CFS};
+use crate::disasm::{Binary, CategoryHistogram};
+#[cfg(feature = "compare-db")]
+use crate::Error;
+use std::ops::Range;
+
+// minimum similarity of the candidates, unless specified otherwise.
+const DEFAULT_THRESHOLD: f64 = 0.8;
+// weight of the similarity of the instruction categories, when known for both functions.
+const CATEGORY_WEIGHT: f64 = 0.25;
+
+/// Function found by a [`FunctionSearch`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(docsrs, doc(cfg(feature = "disasm")))]
+pub struct SearchCandidate {
+    binary: String,
+    function: String,
+    offset: u64,
+    ranges: Vec<Range<u64>>,
+    similarity: f64,
+}
+
+impl SearchCandidate {
+    /// Returns the name of the binary containing the candidate.
+    pub fn binary(&self) -> &str {
+        &self.binary
+    }
+
+    /// Returns the name of the candidate function.
+    pub fn function(&self) -> &str {
+        &self.function
+    }
+
+    /// Returns the offset of the candidate function.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the address ranges covered by the candidate function, sorted by address.
+    ///
+    /// The ranges are empty for the candidates found in a
+    /// [`FingerprintDatabase`](crate::analysis::FingerprintDatabase), as only the offset of the
+    /// functions is stored.
+    pub fn ranges(&self) -> &[Range<u64>] {
+        &self.ranges
+    }
+
+    /// Returns the similarity of the candidate with the reference function, between 0 and 1.
+    pub fn similarity(&self) -> f64 {
+        self.similarity
+    }
+}
+
+/// Search of the copies of a reference function in a corpus of binaries.
+///
+/// This is meant to find the functions of a library affected by a known vulnerability in other
+/// executables, for example statically linked firmware: the reference function is the vulnerable
+/// one, and each function of the corpus is ranked by its similarity with it.
+///
+/// The similarity is given by the [`tree_similarity`] of the structure trees, combined with the
+/// similarity of the [`CategoryHistogram`]s of the instructions when known for both functions.
+///
+/// Candidates in a [`FingerprintDatabase`](crate::analysis::FingerprintDatabase) are instead
+/// ranked with [`Fingerprint::similarity`], as the structure trees are not stored.
+/// # Examples
+/// Basic usage:
+/// ```
+/// use bincc::analysis::{FunctionSearch, CFG};
+/// use bincc::disasm::{Architecture, Statement, StatementFamily};
+///
+/// let stmts = vec![
+///     Statement::new(0x10, StatementFamily::CJMP, "je 0x14"),
+///     Statement::new(0x12, StatementFamily::MOV, "mov eax, 0"),
+///     Statement::new(0x14, StatementFamily::RET, "ret"),
+/// ];
+/// let vulnerable = CFG::new(&stmts, 0x15, Architecture::X86(64));
+/// let search = FunctionSearch::new(&vulnerable).unwrap().with_threshold(0.9);
+///
+/// assert_eq!(search.similarity(&vulnerable), 1.0);
+/// ```
+#[cfg_attr(docsrs, doc(cfg(feature = "disasm")))]
+pub struct FunctionSearch {
+    tree: StructureBlock,
+    fingerprint: Fingerprint,
+    categories: Option<CategoryHistogram>,
+    threshold: f64,
+    limit: Option<usize>,
+}
+
+impl FunctionSearch {
+    /// Creates the search of the function with the given [`CFG`].
+    ///
+    /// The candidates must have a similarity of at least `0.8`, and their amount is not limited.
+    /// Returns [`None`] if the [`CFG`] is empty or can not be structured.
+    pub fn new(reference: &CFG) -> Option<FunctionSearch> {
+        let cfs = CFS::new(reference).ok()?;
+        Some(FunctionSearch {
+            tree: cfs.get_tree()?,
+            fingerprint: Fingerprint::new(&cfs)?,
+            categories: reference.function_categories(),
+            threshold: DEFAULT_THRESHOLD,
+            limit: None,
+        })
+    }
+
+    /// Sets the minimum similarity of the candidates.
+    pub fn with_threshold(mut self, threshold: f64) -> FunctionSearch {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Sets the maximum amount of candidates returned by each search.
+    pub fn with_limit(mut self, amount: usize) -> FunctionSearch {
+        self.limit = Some(amount);
+        self
+    }
+
+    /// Returns the fingerprint of the reference function.
+    pub fn fingerprint(&self) -> &Fingerprint {
+        &self.fingerprint
+    }
+
+    /// Returns the similarity of the function with the given [`CFG`] with the reference
+    /// function, between 0 and 1.
+    pub fn similarity(&self, cfg: &CFG) -> f64 {
+        CFS::new(cfg)
+            .ok()
+            .and_then(|cfs| cfs.get_tree())
+            .map_or(0.0, |tree| self.score(&tree, cfg.function_categories()))
+    }
+
+    /// Searches the reference function in the given binary, labeled with the given name.
+    ///
+    /// Returns the candidates sorted by decreasing similarity, then by offset.
+    pub fn search_binary(&self, name: &str, binary: &Binary) -> Vec<SearchCandidate> {
+        self.search_corpus([(name, binary)])
+    }
+
+    /// Searches the reference function in every given binary, labeled with its name.
+    ///
+    /// Returns the candidates sorted by decreasing similarity, then by the order of the binaries
+    /// and by offset.
+    pub fn search_corpus<'a, I: IntoIterator<Item = (&'a str, &'a Binary)>>(
+        &self,
+        corpus: I,
+    ) -> Vec<SearchCandidate> {
+        let mut candidates = Vec::new();
+        for (name, binary) in corpus {
+            for function in binary.functions() {
+                let offset = function.get_offset();
+                let tree = binary.cfg(offset).and_then(|cfg| {
+                    let tree = CFS::new(cfg).ok()?.get_tree()?;
+                    Some((self.score(&tree, cfg.function_categories()), tree))
+                });
+                if let Some((similarity, tree)) = tree {
+                    if similarity >= self.threshold {
+                        candidates.push(SearchCandidate {
+                            binary: name.to_string(),
+                            function: function.get_name().to_string(),
+                            offset,
+                            ranges: tree.address_ranges(),
+                            similarity,
+                        });
+                    }
+                }
+            }
+        }
+        // stable sort: ties keep the order of the corpus
+        candidates.sort_by(|a, b| b.similarity.total_cmp(&a.similarity));
+        candidates.truncate(self.limit.unwrap_or(usize::MAX));
+        candidates
+    }
+
+    /// Searches the reference function in the given database, using only the fingerprints.
+    ///
+    /// Returns the candidates sorted by decreasing similarity, then by binary and offset.
+    /// Candidates found in a database have no [`SearchCandidate::ranges`].
+    ///
+    /// This method is available only with the `compare-db` feature.
+    #[cfg(feature = "compare-db")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "compare-db")))]
+    pub fn search_database(
+        &self,
+        database: &FingerprintDatabase,
+    ) -> Result<Vec<SearchCandidate>, Error> {
+        let found = database.find_similar(&self.fingerprint, self.threshold)?;
+        Ok(found
+            .into_iter()
+            .take(self.limit.unwrap_or(usize::MAX))
+            .map(|(record, similarity)| SearchCandidate {
+                binary: record.binary().to_string(),
+                function: record.function().to_string(),
+                offset: record.offset(),
+                ranges: Vec::new(),
+                similarity,
+            })
+            .collect())
+    }
+
+    fn score(&self, tree: &StructureBlock, categories: Option<CategoryHistogram>) -> f64 {
+        let structure = tree_similarity(&self.tree, tree);
+        match (&self.categories, categories) {
+            (Some(a), Some(b)) => {
+                (1.0 - CATEGORY_WEIGHT) * structure + CATEGORY_WEIGHT * a.similarity(&b)
+            }
+            _ => structure,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::{FunctionSearch, CFG};
+    use crate::disasm::{Architecture, Binary, Disassembler, Function, Statement, StatementFamily};
+
+    // binary containing the given functions
+    struct MockBinary {
+        functions: Vec<(u64, &'static str, Vec<Statement>)>,
+    }
+
+    impl Disassembler for MockBinary {
+        async fn functions(&mut self) -> Vec<Function> {
+            self.functions
+                .iter()
+                .map(|(offset, name, _)| Function::new(*offset, name))
+                .collect()
+        }
+
+        async fn disassemble_function(&mut self, offset: u64) -> Option<CFG> {
+            let (_, _, stmts) = self.functions.iter().find(|func| func.0 == offset)?;
+            let end = stmts.last()?.get_offset() + 1;
+            Some(CFG::new(stmts, end, Architecture::X86(64)))
+        }
+
+        async fn architecture(&mut self) -> Option<Architecture> {
+            Some(Architecture::X86(64))
+        }
+    }
+
+    // loop adding 1 until 10, starting at the given offset and ending with the given statement
+    fn counter(offset: u64, last: Statement) -> Vec<Statement> {
+        vec![
+            Statement::new(offset, StatementFamily::MOV, "mov eax, 0"),
+            Statement::new(offset + 0x4, StatementFamily::CMP, "cmp eax, 10"),
+            Statement::new(
+                offset + 0x8,
+                StatementFamily::CJMP,
+                &format!("jge {:#x}", offset + 0x10),
+            ),
+            Statement::new(offset + 0xC, StatementFamily::ADD, "add eax, 1"),
+            Statement::new(
+                offset + 0xE,
+                StatementFamily::JMP,
+                &format!("jmp {:#x}", offset + 0x4),
+            ),
+            last,
+        ]
+    }
+
+    fn reference() -> CFG {
+        let stmts = counter(0x0, Statement::new(0x10, StatementFamily::RET, "ret"));
+        CFG::new(&stmts, 0x11, Architecture::X86(64))
+    }
+
+    async fn corpus() -> Binary {
+        // same function, function with a condition after the loop, and unrelated function
+        let copy = counter(0x100, Statement::new(0x110, StatementFamily::RET, "ret"));
+        let mut patched = counter(
+            0x200,
+            Statement::new(0x210, StatementFamily::CJMP, "je 0x216"),
+        );
+        patched.extend([
+            Statement::new(0x212, StatementFamily::MOV, "mov eax, 1"),
+            Statement::new(0x216, StatementFamily::RET, "ret"),
+        ]);
+        let unrelated = vec![Statement::new(0x300, StatementFamily::RET, "ret")];
+        let mut mock = MockBinary {
+            functions: vec![
+                (0x100, "copy", copy),
+                (0x200, "patched", patched),
+                (0x300, "unrelated", unrelated),
+            ],
+        };
+        Binary::extract(&mut mock).await
+    }
+
+    #[tokio::test]
+    async fn search_binary() {
+        let binary = corpus().await;
+        let search = FunctionSearch::new(&reference()).unwrap();
+        let found = search.search_binary("firmware", &binary);
+        assert_eq!(found[0].function(), "copy");
+        assert_eq!(found[0].binary(), "firmware");
+        assert_eq!(found[0].offset(), 0x100);
+        assert_eq!(found[0].similarity(), 1.0);
+        assert_eq!(found[0].ranges(), &[0x100..0x111]);
+        assert!(found.iter().all(|candidate| candidate.similarity() >= 0.8));
+        assert!(found
+            .iter()
+            .all(|candidate| candidate.function() != "unrelated"));
+        let found = search.with_limit(1).search_binary("firmware", &binary);
+        assert_eq!(found.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn search_corpus() {
+        let binary = corpus().await;
+        let search = FunctionSearch::new(&reference())
+            .unwrap()
+            .with_threshold(0.0);
+        let found = search.search_corpus([("a", &binary), ("b", &binary)]);
+        let names = found
+            .iter()
+            .map(|candidate| (candidate.binary(), candidate.function()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                ("a", "copy"),
+                ("b", "copy"),
+                ("a", "patched"),
+                ("b", "patched"),
+                ("a", "unrelated"),
+                ("b", "unrelated")
+            ]
+        );
+        assert!(FunctionSearch::new(&CFG::default()).is_none());
+    }
+
+    #[cfg(feature = "compare-db")]
+    #[test]
+    fn search_database() {
+        use crate::analysis::{Fingerprint, FingerprintDatabase, CFS};
+
+        let reference = reference();
+        let fingerprint = Fingerprint::new(&CFS::new(&reference).unwrap()).unwrap();
+        let mut db = FingerprintDatabase::open_in_memory().unwrap();
+        db.insert("firmware", "copy", 0x100, &fingerprint).unwrap();
+        let found = FunctionSearch::new(&reference)
+            .unwrap()
+            .search_database(&db)
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].offset(), 0x100);
+        assert!(found[0].ranges().is_empty());
+    }
+}
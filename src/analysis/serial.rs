@@ -0,0 +1,972 @@
+use crate::analysis::blocks::StructureBlock;
+use crate::analysis::cfg::BlockInfo;
+use crate::analysis::cfs::{CfsStats, TraceEntry};
+use crate::analysis::{
+    BasicBlock, BlockType, CallSite, CallTarget, DirectedGraph, FunctionMetadata, NestedBlock,
+    Reference, ReferenceKind, CFG, CFS,
+};
+use crate::disasm::{CategoryHistogram, InstructionCategory};
+use std::collections::{BTreeSet, HashMap, HashSet};
+#[cfg(feature = "io")]
+use std::fs::File;
+use std::io;
+use std::io::ErrorKind;
+#[cfg(feature = "io")]
+use std::io::{Read, Write};
+#[cfg(feature = "io")]
+use std::path::Path;
+use std::sync::Arc;
+
+/// Version of the binary format used by [`StructureCache`](crate::analysis::StructureCache) and
+/// [`CFS::to_bytes`](crate::analysis::CFS::to_bytes).
+///
+/// Must be increased every time the encoding changes, so files written by older versions are
+/// rejected instead of being decoded incorrectly.
+pub(crate) const FORMAT_VERSION: u32 = 10;
+const CFS_MAGIC: &[u8; 4] = b"BCCF";
+const CFG_MAGIC: &[u8; 4] = b"BCCG";
+// tag of a basic block in the encoded tree. Nested blocks use the tag of their type.
+const BASIC_TAG: u8 = 0;
+// followed by the identifier of the custom type
+const CUSTOM_TAG: u8 = 21;
+// maximum nesting of the decoded trees, so malformed data can not overflow the stack.
+const MAX_NESTING: usize = 1024;
+
+/// Numbering of the basic blocks referenced by an encoded tree.
+pub(crate) struct BlockTable {
+    blocks: Vec<BasicBlock>,
+    index: HashMap<BasicBlock, u32>,
+    // index of a block at the given offset, used for the offset of nested blocks.
+    offsets: HashMap<u64, u32>,
+}
+
+impl BlockTable {
+    /// Numbers the given blocks in order.
+    pub(crate) fn new(blocks: Vec<BasicBlock>) -> BlockTable {
+        let index = blocks
+            .iter()
+            .enumerate()
+            .map(|(index, bb)| (*bb, index as u32))
+            .collect::<HashMap<_, _>>();
+        let offsets = index.iter().map(|(bb, id)| (bb.offset, *id)).collect();
+        BlockTable {
+            blocks,
+            index,
+            offsets,
+        }
+    }
+
+    /// Returns the block with the given number.
+    pub(crate) fn block(&self, id: u32) -> Option<BasicBlock> {
+        self.blocks.get(id as usize).copied()
+    }
+
+    /// Returns the number of the given block.
+    pub(crate) fn index(&self, bb: &BasicBlock) -> Option<u32> {
+        self.index.get(bb).copied()
+    }
+}
+
+impl CFS {
+    /// Serializes this [`CFS`] into a binary representation.
+    ///
+    /// The representation contains the input [`CFG`], along with its metadata, the edges removed
+    /// from its loops (so also the denaturated [`CFG`] effectively structured), the resulting
+    /// graph, even if the structuring is not complete, and the [`CFS::trace`] of every step
+    /// performed. It can be loaded again with [`CFS::from_bytes`] by any version of this library
+    /// using the same format. The [`CFS::snapshots`] are not stored.
+    /// # Examples
+    /// Basic usage:
+    /// ```
+    /// use bincc::analysis::{CFG, CFS};
+    /// use bincc::disasm::{Architecture, Statement, StatementFamily};
+    ///
+    /// let stmts = vec![
+    ///     Statement::new(0x610, StatementFamily::CJMP, "je 0x618"),
+    ///     Statement::new(0x614, StatementFamily::MOV, "mov eax, 6"),
+    ///     Statement::new(0x618, StatementFamily::RET, "ret"),
+    /// ];
+    /// let cfs = CFS::new(&CFG::new(&stmts, 0x619, Architecture::X86(64))).unwrap();
+    /// let loaded = CFS::from_bytes(&cfs.to_bytes()).unwrap();
+    ///
+    /// assert_eq!(loaded.get_tree(), cfs.get_tree());
+    /// assert_eq!(loaded.trace(), cfs.trace());
+    /// ```
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let table = self.block_table();
+        let mut out = CFS_MAGIC.to_vec();
+        out.extend(FORMAT_VERSION.to_le_bytes());
+        encode_table(&table, &mut out);
+        // the table contains every block, so the encoding can not fail
+        self.encode(&table, &mut out)
+            .expect("block missing from the table");
+        out
+    }
+
+    /// Loads a [`CFS`] previously serialized with [`CFS::to_bytes`].
+    ///
+    /// This method returns [`std::io::Error`] in case of malformed input or if the data was
+    /// written by a version of this library using a different format.
+    pub fn from_bytes(data: &[u8]) -> Result<CFS, io::Error> {
+        let mut reader = Reader::new(data);
+        read_header(&mut reader, CFS_MAGIC, "structure")?;
+        decode_cfs(&mut reader)
+            .filter(|_| reader.is_empty())
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "malformed structure"))
+    }
+
+    /// Writes the binary representation of this [`CFS`] into a file.
+    ///
+    /// See [`CFS::to_bytes`] for the content of the file.
+    #[cfg(feature = "io")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "io")))]
+    pub fn save<S: AsRef<Path>>(&self, filename: S) -> Result<(), io::Error> {
+        let mut file = File::create(filename)?;
+        file.write_all(&self.to_bytes())
+    }
+
+    /// Loads a [`CFS`] previously written with [`CFS::save`].
+    ///
+    /// This method returns [`std::io::Error`] in case of malformed input or if the file was
+    /// written by a version of this library using a different format.
+    #[cfg(feature = "io")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "io")))]
+    pub fn load<S: AsRef<Path>>(filename: S) -> Result<CFS, io::Error> {
+        let mut file = File::open(filename)?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        CFS::from_bytes(&data)
+    }
+
+    // numbers every block referenced by this CFS, ordered by offset.
+    fn block_table(&self) -> BlockTable {
+        let mut blocks = BTreeSet::new();
+        let mut offsets = HashSet::new();
+        blocks.extend(self.cfg.root);
+        for (src, dsts) in &self.cfg.edges {
+            blocks.insert(*src);
+            blocks.extend(dsts.iter().copied());
+        }
+        blocks.extend(self.removed.iter().flat_map(|(src, dst)| [*src, *dst]));
+        let trees = std::iter::once(&self.tree)
+            .chain(&self.partial)
+            .flat_map(|graph| graph.adjacency.keys())
+            .chain(
+                self.trace
+                    .iter()
+                    .flat_map(|entry| entry.consumed.iter().chain([&entry.result])),
+            );
+        for node in trees.flat_map(|tree| tree.iter_preorder()) {
+            match node {
+                StructureBlock::Basic(bb) => {
+                    blocks.insert(*bb);
+                }
+                StructureBlock::Nested(nb) => {
+                    offsets.insert(nb.offset);
+                    blocks.extend(nb.exits.iter().flat_map(|(src, dst)| [*src, *dst]));
+                    blocks.extend(nb.condition.iter().flatten().map(|(bb, _)| *bb));
+                }
+            }
+        }
+        // nested blocks always start at one of their basic blocks, unless created manually by a
+        // custom reduction: in this case a placeholder is added to record their offset.
+        let known = blocks.iter().map(|bb| bb.offset).collect::<HashSet<_>>();
+        offsets.retain(|offset| !known.contains(offset));
+        blocks.extend(
+            offsets
+                .into_iter()
+                .map(|offset| BasicBlock { offset, length: 0 }),
+        );
+        BlockTable::new(blocks.into_iter().collect())
+    }
+
+    fn encode(&self, table: &BlockTable, out: &mut Vec<u8>) -> Option<()> {
+        encode_cfg(&self.cfg, table, out)?;
+        out.extend((self.removed.len() as u64).to_le_bytes());
+        for (src, dst) in &self.removed {
+            out.extend(table.index(src)?.to_le_bytes());
+            out.extend(table.index(dst)?.to_le_bytes());
+        }
+        encode_graph(&self.tree, table, out)?;
+        match &self.partial {
+            Some(partial) => {
+                out.push(1);
+                encode_graph(partial, table, out)?;
+            }
+            None => out.push(0),
+        }
+        out.extend((self.duplicated as u64).to_le_bytes());
+        out.extend((self.stats.iterations() as u64).to_le_bytes());
+        out.extend((self.trace.len() as u64).to_le_bytes());
+        for entry in &self.trace {
+            encode_string(&entry.pattern, out);
+            out.extend((entry.consumed.len() as u32).to_le_bytes());
+            for node in &entry.consumed {
+                encode_tree(node, table, out)?;
+            }
+            encode_tree(&entry.result, table, out)?;
+        }
+        Some(())
+    }
+}
+
+impl CFG {
+    /// Serializes this [`CFG`] into a binary representation.
+    ///
+    /// The representation contains the edges of the [`CFG`], the information about its blocks
+    /// recorded by [`CFG::new`], like [`CFG::instruction_count`] and [`CFG::calls`], and its
+    /// metadata. It can be loaded again with [`CFG::from_bytes`].
+    /// # Examples
+    /// Basic usage:
+    /// ```
+    /// use bincc::analysis::{BasicBlock, CFG};
+    /// use bincc::disasm::{Architecture, Statement, StatementFamily};
+    ///
+    /// let stmts = vec![
+    ///     Statement::new(0x610, StatementFamily::CJMP, "je 0x618"),
+    ///     Statement::new(0x614, StatementFamily::MOV, "mov eax, 6"),
+    ///     Statement::new(0x618, StatementFamily::RET, "ret"),
+    /// ];
+    /// let cfg = CFG::new(&stmts, 0x619, Architecture::X86(64));
+    /// let loaded = CFG::from_bytes(&cfg.to_bytes()).unwrap();
+    /// let head = BasicBlock { offset: 0x610, length: 4 };
+    ///
+    /// assert_eq!(loaded.to_string(), cfg.to_string());
+    /// assert_eq!(loaded.instruction_count(&head), Some(1));
+    /// ```
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut blocks = BTreeSet::new();
+        blocks.extend(self.root);
+        for (src, dsts) in &self.edges {
+            blocks.insert(*src);
+            blocks.extend(dsts.iter().copied());
+        }
+        let table = BlockTable::new(blocks.into_iter().collect());
+        let mut out = CFG_MAGIC.to_vec();
+        out.extend(FORMAT_VERSION.to_le_bytes());
+        encode_table(&table, &mut out);
+        encode_cfg(self, &table, &mut out).expect("block missing from the table");
+        out
+    }
+
+    /// Loads a [`CFG`] previously serialized with [`CFG::to_bytes`].
+    ///
+    /// This method returns [`std::io::Error`] in case of malformed input or if the data was
+    /// written by a version of this library using a different format.
+    pub fn from_bytes(data: &[u8]) -> Result<CFG, io::Error> {
+        let mut reader = Reader::new(data);
+        read_header(&mut reader, CFG_MAGIC, "CFG")?;
+        decode_table(&mut reader)
+            .and_then(|table| decode_cfg(&mut reader, &table))
+            .filter(|_| reader.is_empty())
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "malformed CFG"))
+    }
+}
+
+// checks the magic number and the format version at the beginning of the data.
+fn read_header(reader: &mut Reader, magic: &[u8], what: &str) -> Result<(), io::Error> {
+    if reader.bytes(magic.len()) != Some(magic) {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("malformed {}", what),
+        ));
+    }
+    if reader.u32() != Some(FORMAT_VERSION) {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("unsupported {} format version", what),
+        ));
+    }
+    Ok(())
+}
+
+fn encode_table(table: &BlockTable, out: &mut Vec<u8>) {
+    out.extend((table.blocks.len() as u64).to_le_bytes());
+    for bb in &table.blocks {
+        out.extend(bb.offset.to_le_bytes());
+        out.extend(bb.length.to_le_bytes());
+    }
+}
+
+fn decode_table(reader: &mut Reader) -> Option<BlockTable> {
+    let blocks_len = reader.u64()?;
+    let blocks = (0..blocks_len)
+        .map(|_| {
+            Some(BasicBlock {
+                offset: reader.u64()?,
+                length: reader.u64()?,
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(BlockTable::new(blocks))
+}
+
+// Encodes the CFG, with the information about its blocks and its metadata.
+fn encode_cfg(cfg: &CFG, table: &BlockTable, out: &mut Vec<u8>) -> Option<()> {
+    match &cfg.root {
+        Some(root) => out.extend(table.index(root)?.to_le_bytes()),
+        None => out.extend(u32::MAX.to_le_bytes()),
+    }
+    out.extend((cfg.edges.len() as u64).to_le_bytes());
+    for src in cfg.blocks_sorted() {
+        let dsts = &cfg.edges[src];
+        out.extend(table.index(src)?.to_le_bytes());
+        out.extend((dsts.len() as u32).to_le_bytes());
+        for dst in dsts {
+            out.extend(table.index(dst)?.to_le_bytes());
+        }
+    }
+    // unknown values are encoded as u64::MAX and as the tag of a basic block.
+    let mut info = cfg.info.iter().collect::<Vec<_>>();
+    info.sort_unstable_by_key(|(bb, _)| **bb);
+    out.extend((info.len() as u64).to_le_bytes());
+    for (bb, info) in info {
+        out.extend(table.index(bb)?.to_le_bytes());
+        let instructions = info.instructions.map_or(u64::MAX, |count| count as u64);
+        out.extend(instructions.to_le_bytes());
+        out.push(info.terminator.map_or(BASIC_TAG, type_tag));
+        out.push(info.call as u8 | (info.indirect as u8) << 1);
+        out.extend((info.calls.len() as u32).to_le_bytes());
+        for call in &info.calls {
+            out.extend(call.offset.to_le_bytes());
+            out.extend(call.length.to_le_bytes());
+            encode_target(&call.target, out);
+        }
+        out.extend((info.references.len() as u32).to_le_bytes());
+        for reference in &info.references {
+            out.extend(reference.offset.to_le_bytes());
+            out.extend(reference.length.to_le_bytes());
+            out.push(match reference.kind {
+                ReferenceKind::Jump => 0,
+                ReferenceKind::Data => 1,
+            });
+            encode_target(&reference.target, out);
+        }
+        match &info.categories {
+            Some(categories) => {
+                out.push(1);
+                categories
+                    .counts()
+                    .iter()
+                    .for_each(|count| out.extend(count.to_le_bytes()));
+            }
+            None => out.push(0),
+        }
+    }
+    match &cfg.metadata {
+        Some(metadata) => {
+            out.push(1);
+            encode_string(metadata.name(), out);
+            encode_optional_string(metadata.demangled_name(), out);
+            out.extend(metadata.start().to_le_bytes());
+            out.extend(metadata.size().to_le_bytes());
+            encode_optional_string(metadata.source_file(), out);
+        }
+        None => out.push(0),
+    }
+    Some(())
+}
+
+fn decode_cfs(reader: &mut Reader) -> Option<CFS> {
+    let table = decode_table(reader)?;
+    let cfg = decode_cfg(reader, &table)?;
+    let removed_len = reader.u64()?;
+    let removed = (0..removed_len)
+        .map(|_| Some((table.block(reader.u32()?)?, table.block(reader.u32()?)?)))
+        .collect::<Option<Vec<_>>>()?;
+    let tree = decode_graph(reader, &table)?;
+    let partial = match reader.u8()? {
+        0 => None,
+        1 => Some(decode_graph(reader, &table)?),
+        _ => return None,
+    };
+    let duplicated = reader.u64()? as usize;
+    let iterations = reader.u64()? as usize;
+    let trace_len = reader.u64()?;
+    let trace = (0..trace_len)
+        .map(|_| {
+            let pattern = reader.string()?;
+            let consumed_len = reader.u32()?;
+            let consumed = (0..consumed_len)
+                .map(|_| decode_tree(reader, &table))
+                .collect::<Option<Vec<_>>>()?;
+            let result = decode_tree(reader, &table)?;
+            Some(TraceEntry {
+                pattern,
+                consumed,
+                result,
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let stats = CfsStats::new(&tree, iterations, removed.len());
+    Some(CFS {
+        cfg,
+        tree,
+        partial,
+        duplicated,
+        removed,
+        trace,
+        snapshots: Vec::new(),
+        stats,
+        timings: None,
+    })
+}
+
+fn decode_cfg(reader: &mut Reader, table: &BlockTable) -> Option<CFG> {
+    let root = match reader.u32()? {
+        u32::MAX => None,
+        id => Some(table.block(id)?),
+    };
+    let edges_len = reader.u64()?;
+    let mut edges = HashMap::new();
+    for _ in 0..edges_len {
+        let src = table.block(reader.u32()?)?;
+        let dsts_len = reader.u32()?;
+        let dsts = (0..dsts_len)
+            .map(|_| table.block(reader.u32()?))
+            .collect::<Option<Vec<_>>>()?;
+        edges.insert(src, dsts);
+    }
+    let info_len = reader.u64()?;
+    let info = (0..info_len)
+        .map(|_| {
+            let bb = table.block(reader.u32()?)?;
+            let instructions = match reader.u64()? {
+                u64::MAX => None,
+                count => Some(count as usize),
+            };
+            let terminator = match reader.u8()? {
+                BASIC_TAG => None,
+                tag => Some(tag_type(tag)?),
+            };
+            let flags = reader.u8()?;
+            if flags > 0b11 {
+                return None;
+            }
+            let calls_len = reader.u32()?;
+            let calls = (0..calls_len)
+                .map(|_| {
+                    Some(CallSite {
+                        offset: reader.u64()?,
+                        length: reader.u64()?,
+                        target: decode_target(reader)?,
+                    })
+                })
+                .collect::<Option<Vec<_>>>()?;
+            let references_len = reader.u32()?;
+            let references = (0..references_len)
+                .map(|_| {
+                    let offset = reader.u64()?;
+                    let length = reader.u64()?;
+                    let kind = match reader.u8()? {
+                        0 => ReferenceKind::Jump,
+                        1 => ReferenceKind::Data,
+                        _ => return None,
+                    };
+                    Some(Reference {
+                        offset,
+                        length,
+                        kind,
+                        target: decode_target(reader)?,
+                    })
+                })
+                .collect::<Option<Vec<_>>>()?;
+            let categories = match reader.u8()? {
+                0 => None,
+                1 => {
+                    let mut counts = [0; InstructionCategory::ALL.len()];
+                    for count in counts.iter_mut() {
+                        *count = reader.u32()?;
+                    }
+                    Some(CategoryHistogram::from_counts(counts))
+                }
+                _ => return None,
+            };
+            Some((
+                bb,
+                BlockInfo {
+                    instructions,
+                    terminator,
+                    call: flags & 1 != 0,
+                    indirect: flags & 2 != 0,
+                    calls,
+                    references,
+                    categories,
+                },
+            ))
+        })
+        .collect::<Option<HashMap<_, _>>>()?;
+    let metadata = match reader.u8()? {
+        0 => None,
+        1 => {
+            let name = reader.string()?;
+            let demangled = reader.optional_string()?;
+            let mut metadata = FunctionMetadata::new(&name, reader.u64()?, reader.u64()?);
+            if let Some(demangled) = demangled {
+                metadata = metadata.with_demangled_name(&demangled);
+            }
+            if let Some(source) = reader.optional_string()? {
+                metadata = metadata.with_source_file(&source);
+            }
+            Some(metadata)
+        }
+        _ => return None,
+    };
+    Some(CFG {
+        root,
+        edges,
+        info,
+        metadata,
+    })
+}
+
+// Encodes the target of a call or of a reference as its tag followed by its value.
+fn encode_target(target: &CallTarget, out: &mut Vec<u8>) {
+    match target {
+        CallTarget::Address(address) => {
+            out.push(0);
+            out.extend(address.to_le_bytes());
+        }
+        CallTarget::Symbol(symbol) => {
+            out.push(1);
+            encode_string(symbol, out);
+        }
+        CallTarget::Indirect(operand) => {
+            out.push(2);
+            encode_string(operand, out);
+        }
+    }
+}
+
+fn decode_target(reader: &mut Reader) -> Option<CallTarget> {
+    match reader.u8()? {
+        0 => Some(CallTarget::Address(reader.u64()?)),
+        1 => Some(CallTarget::Symbol(reader.string()?)),
+        2 => Some(CallTarget::Indirect(reader.string()?)),
+        _ => None,
+    }
+}
+
+// Encodes the nodes of the graph, ordered by offset, followed by the root and the edges.
+fn encode_graph(
+    graph: &DirectedGraph<StructureBlock>,
+    table: &BlockTable,
+    out: &mut Vec<u8>,
+) -> Option<()> {
+    let mut nodes = graph.adjacency.keys().collect::<Vec<_>>();
+    nodes.sort_unstable_by(|a, b| a.offset().cmp(&b.offset()).then(a.strict_cmp(b)));
+    let index = nodes
+        .iter()
+        .enumerate()
+        .map(|(index, node)| (*node, index as u32))
+        .collect::<HashMap<_, _>>();
+    out.extend((nodes.len() as u64).to_le_bytes());
+    for node in &nodes {
+        encode_tree(node, table, out)?;
+    }
+    match &graph.root {
+        Some(root) => out.extend(index.get(root)?.to_le_bytes()),
+        None => out.extend(u32::MAX.to_le_bytes()),
+    }
+    for node in &nodes {
+        let children = &graph.adjacency[*node];
+        out.extend((children.len() as u32).to_le_bytes());
+        for child in children {
+            out.extend(index.get(child)?.to_le_bytes());
+        }
+    }
+    Some(())
+}
+
+fn decode_graph(reader: &mut Reader, table: &BlockTable) -> Option<DirectedGraph<StructureBlock>> {
+    let nodes_len = reader.u64()?;
+    let nodes = (0..nodes_len)
+        .map(|_| decode_tree(reader, table))
+        .collect::<Option<Vec<_>>>()?;
+    let root = match reader.u32()? {
+        u32::MAX => None,
+        id => Some(nodes.get(id as usize)?.clone()),
+    };
+    let mut adjacency = HashMap::with_capacity(nodes.len());
+    for node in &nodes {
+        let children_len = reader.u32()?;
+        let children = (0..children_len)
+            .map(|_| nodes.get(reader.u32()? as usize).cloned())
+            .collect::<Option<Vec<_>>>()?;
+        adjacency.insert(node.clone(), children);
+    }
+    Some(DirectedGraph { root, adjacency })
+}
+
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Reader { data }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub(crate) fn bytes(&mut self, amount: usize) -> Option<&'a [u8]> {
+        if self.data.len() < amount {
+            None
+        } else {
+            let (read, rest) = self.data.split_at(amount);
+            self.data = rest;
+            Some(read)
+        }
+    }
+
+    pub(crate) fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.bytes(2)
+            .map(|b| u16::from_le_bytes(b.try_into().unwrap()))
+    }
+
+    pub(crate) fn u32(&mut self) -> Option<u32> {
+        self.bytes(4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+    }
+
+    pub(crate) fn u64(&mut self) -> Option<u64> {
+        self.bytes(8)
+            .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+    }
+
+    pub(crate) fn string(&mut self) -> Option<String> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.bytes(len)?.to_vec()).ok()
+    }
+
+    // returns None if the input is malformed, and Some(None) if the string is missing.
+    fn optional_string(&mut self) -> Option<Option<String>> {
+        match self.u8()? {
+            0 => Some(None),
+            1 => self.string().map(Some),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) fn encode_string(string: &str, out: &mut Vec<u8>) {
+    out.extend((string.len() as u32).to_le_bytes());
+    out.extend(string.as_bytes());
+}
+
+fn encode_optional_string(string: Option<&str>, out: &mut Vec<u8>) {
+    match string {
+        Some(string) => {
+            out.push(1);
+            encode_string(string, out);
+        }
+        None => out.push(0),
+    }
+}
+
+pub(crate) fn type_tag(block_type: BlockType) -> u8 {
+    match block_type {
+        BlockType::Basic => BASIC_TAG,
+        BlockType::SelfLooping => 1,
+        BlockType::Sequence => 2,
+        BlockType::IfThen => 3,
+        BlockType::IfThenElse => 4,
+        BlockType::While => 5,
+        BlockType::DoWhile => 6,
+        BlockType::Switch => 7,
+        BlockType::ProperInterval => 8,
+        BlockType::ImproperInterval => 9,
+        BlockType::Break => 10,
+        BlockType::Duplicate => 11,
+        BlockType::ImproperRegion => 12,
+        BlockType::Condition => 13,
+        BlockType::Guard => 14,
+        BlockType::Goto => 15,
+        BlockType::IfChain => 16,
+        BlockType::Conditional => 17,
+        BlockType::AbnormalEntry => 18,
+        BlockType::Return => 19,
+        BlockType::Unreachable => 20,
+        BlockType::Continue => 21,
+        BlockType::SwitchLoop => 22,
+        BlockType::Custom(_) => CUSTOM_TAG,
+    }
+}
+
+fn tag_type(tag: u8) -> Option<BlockType> {
+    match tag {
+        1 => Some(BlockType::SelfLooping),
+        2 => Some(BlockType::Sequence),
+        3 => Some(BlockType::IfThen),
+        4 => Some(BlockType::IfThenElse),
+        5 => Some(BlockType::While),
+        6 => Some(BlockType::DoWhile),
+        7 => Some(BlockType::Switch),
+        8 => Some(BlockType::ProperInterval),
+        9 => Some(BlockType::ImproperInterval),
+        10 => Some(BlockType::Break),
+        11 => Some(BlockType::Duplicate),
+        12 => Some(BlockType::ImproperRegion),
+        13 => Some(BlockType::Condition),
+        14 => Some(BlockType::Guard),
+        15 => Some(BlockType::Goto),
+        16 => Some(BlockType::IfChain),
+        17 => Some(BlockType::Conditional),
+        18 => Some(BlockType::AbnormalEntry),
+        19 => Some(BlockType::Return),
+        20 => Some(BlockType::Unreachable),
+        21 => Some(BlockType::Continue),
+        22 => Some(BlockType::SwitchLoop),
+        _ => None,
+    }
+}
+
+// Encodes the tree in preorder. Basic blocks are replaced by their index in the table, so the
+// encoding is independent of the offset of the function if the table is.
+pub(crate) fn encode_tree(
+    node: &StructureBlock,
+    table: &BlockTable,
+    out: &mut Vec<u8>,
+) -> Option<()> {
+    match node {
+        StructureBlock::Basic(bb) => {
+            out.push(BASIC_TAG);
+            out.extend(table.index(bb)?.to_le_bytes());
+        }
+        StructureBlock::Nested(nb) => {
+            out.push(type_tag(nb.block_type));
+            if let BlockType::Custom(id) = nb.block_type {
+                out.extend(id.to_le_bytes());
+            }
+            out.extend(table.offsets.get(&nb.offset)?.to_le_bytes());
+            out.extend(nb.depth.to_le_bytes());
+            out.push(match nb.polarity {
+                None => 0,
+                Some(false) => 1,
+                Some(true) => 2,
+            });
+            out.extend((nb.content.len() as u32).to_le_bytes());
+            out.extend((nb.exits.len() as u32).to_le_bytes());
+            for (src, dst) in &nb.exits {
+                out.extend(table.index(src)?.to_le_bytes());
+                out.extend(table.index(dst)?.to_le_bytes());
+            }
+            out.extend((nb.condition.len() as u32).to_le_bytes());
+            for clause in &nb.condition {
+                out.extend((clause.len() as u32).to_le_bytes());
+                for (bb, taken) in clause {
+                    out.extend(table.index(bb)?.to_le_bytes());
+                    out.push(*taken as u8);
+                }
+            }
+            for child in &nb.content {
+                encode_tree(child, table, out)?;
+            }
+        }
+    }
+    Some(())
+}
+
+pub(crate) fn decode_tree(reader: &mut Reader, table: &BlockTable) -> Option<StructureBlock> {
+    decode_nested(reader, table, 0)
+}
+
+// decodes a tree whose root is contained in `nesting` other blocks.
+fn decode_nested(
+    reader: &mut Reader,
+    table: &BlockTable,
+    nesting: usize,
+) -> Option<StructureBlock> {
+    if nesting == MAX_NESTING {
+        return None;
+    }
+    let block = |id: u32| table.block(id);
+    let tag = reader.u8()?;
+    if tag == BASIC_TAG {
+        return block(reader.u32()?).map(StructureBlock::from);
+    }
+    let block_type = match tag {
+        CUSTOM_TAG => BlockType::Custom(reader.u16()?),
+        tag => tag_type(tag)?,
+    };
+    let offset = block(reader.u32()?)?.offset;
+    let depth = reader.u32()?;
+    let polarity = match reader.u8()? {
+        0 => None,
+        1 => Some(false),
+        2 => Some(true),
+        _ => return None,
+    };
+    let content_len = reader.u32()?;
+    let exits_len = reader.u32()?;
+    let exits = (0..exits_len)
+        .map(|_| Some((block(reader.u32()?)?, block(reader.u32()?)?)))
+        .collect::<Option<Vec<_>>>()?;
+    let condition_len = reader.u32()?;
+    let condition = (0..condition_len)
+        .map(|_| {
+            let clause_len = reader.u32()?;
+            (0..clause_len)
+                .map(|_| {
+                    let bb = block(reader.u32()?)?;
+                    match reader.u8()? {
+                        0 => Some((bb, false)),
+                        1 => Some((bb, true)),
+                        _ => None,
+                    }
+                })
+                .collect::<Option<Vec<_>>>()
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let content = (0..content_len)
+        .map(|_| decode_nested(reader, table, nesting + 1))
+        .collect::<Option<Vec<_>>>()?;
+    Some(StructureBlock::from(Arc::new(NestedBlock {
+        offset,
+        block_type,
+        content,
+        depth,
+        exits,
+        polarity,
+        condition,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_tree, BlockTable, Reader, MAX_NESTING};
+    use crate::analysis::{BasicBlock, BlockType, CfsBuilder, CFG, CFS};
+    use std::collections::HashMap;
+    #[cfg(feature = "io")]
+    use std::error::Error;
+    #[cfg(feature = "io")]
+    use tempfile::tempdir;
+
+    fn cfg(edges: &[&[u64]], base: u64) -> CFG {
+        let bb = |offset: u64| BasicBlock {
+            offset: base + offset * 4,
+            length: 4,
+        };
+        let edges = edges
+            .iter()
+            .enumerate()
+            .map(|(src, dsts)| (bb(src as u64), dsts.iter().map(|dst| bb(*dst)).collect()))
+            .collect::<HashMap<_, _>>();
+        CFG {
+            root: Some(bb(0)),
+            edges,
+            info: HashMap::new(),
+            metadata: None,
+        }
+    }
+
+    fn assert_same(loaded: &CFS, cfs: &CFS) {
+        assert_eq!(loaded.get_cfg().root, cfs.get_cfg().root);
+        assert_eq!(loaded.get_cfg().edges, cfs.get_cfg().edges);
+        assert_eq!(loaded.get_graph().root, cfs.get_graph().root);
+        assert_eq!(loaded.get_graph().adjacency, cfs.get_graph().adjacency);
+        assert_eq!(
+            loaded.result().get_graph().adjacency,
+            cfs.result().get_graph().adjacency
+        );
+        assert_eq!(loaded.result().is_complete(), cfs.result().is_complete());
+        assert_eq!(loaded.removed_edges(), cfs.removed_edges());
+        assert_eq!(loaded.duplicated(), cfs.duplicated());
+        assert_eq!(loaded.trace(), cfs.trace());
+        assert_eq!(loaded.stats(), cfs.stats());
+    }
+
+    #[test]
+    fn round_trip() {
+        let multi_exit = cfg(&[&[1], &[2, 5], &[3], &[4, 5], &[1], &[]], 0x100);
+        let irreducible = cfg(&[&[1, 3], &[2], &[3, 4], &[1], &[]], 0x200);
+        let improper = cfg(&[&[1, 2], &[1], &[]], 0x300);
+        for cfg in [multi_exit, irreducible, improper] {
+            let cfs = CFS::new(&cfg).unwrap();
+            assert_same(&CFS::from_bytes(&cfs.to_bytes()).unwrap(), &cfs);
+        }
+        let empty = CFS::new(&CFG {
+            root: None,
+            edges: HashMap::new(),
+            info: HashMap::new(),
+            metadata: None,
+        })
+        .unwrap();
+        assert_same(&CFS::from_bytes(&empty.to_bytes()).unwrap(), &empty);
+        let interval = cfg(&[&[1, 2], &[2, 3], &[3], &[]], 0x400);
+        let refined = CfsBuilder::new()
+            .refine_conditions()
+            .build(&interval)
+            .unwrap();
+        assert_same(&CFS::from_bytes(&refined.to_bytes()).unwrap(), &refined);
+    }
+
+    #[test]
+    fn round_trip_incomplete() {
+        let multi_exit = cfg(&[&[1], &[2, 5], &[3], &[4, 5], &[1], &[]], 0x100);
+        let cfs = CfsBuilder::new()
+            .max_iterations(1)
+            .build(&multi_exit)
+            .unwrap();
+        assert!(!cfs.result().is_complete());
+        assert_same(&CFS::from_bytes(&cfs.to_bytes()).unwrap(), &cfs);
+    }
+
+    #[test]
+    #[cfg(feature = "io")]
+    fn save_and_load() -> Result<(), Box<dyn Error>> {
+        let dir = tempdir()?;
+        let path = dir.path().join("cfs.bin");
+        let cfs = CFS::new(&cfg(&[&[1], &[2, 3], &[4], &[4], &[]], 0x100)).unwrap();
+        cfs.save(&path)?;
+        assert_same(&CFS::load(&path)?, &cfs);
+        Ok(())
+    }
+
+    #[test]
+    fn load_malformed() {
+        let cfs = CFS::new(&cfg(&[&[1], &[2, 3], &[4], &[4], &[]], 0x100)).unwrap();
+        let data = cfs.to_bytes();
+        assert!(CFS::from_bytes(&data[..data.len() - 1]).is_err());
+        assert!(CFS::from_bytes(b"BCCS").is_err());
+        let mut other_version = data.clone();
+        other_version[4] = other_version[4].wrapping_add(1);
+        let err = CFS::from_bytes(&other_version).err().unwrap();
+        assert_eq!(err.to_string(), "unsupported structure format version");
+    }
+
+    #[test]
+    fn decode_deeply_nested() {
+        let bb = BasicBlock {
+            offset: 0x100,
+            length: 4,
+        };
+        let table = BlockTable::new(vec![bb]);
+        // chain of sequences, each one containing only the next, ending with the basic block.
+        let nested = |levels: usize| {
+            let mut data = Vec::new();
+            for _ in 0..levels {
+                data.push(2);
+                data.extend([0_u32, 0].iter().flat_map(|x| x.to_le_bytes()));
+                data.push(0);
+                data.extend([1_u32, 0, 0].iter().flat_map(|x| x.to_le_bytes()));
+            }
+            data.push(0);
+            data.extend(0_u32.to_le_bytes());
+            data
+        };
+        let data = nested(MAX_NESTING - 1);
+        let tree = decode_tree(&mut Reader::new(&data), &table).unwrap();
+        assert_eq!(tree.block_type(), BlockType::Sequence);
+        let data = nested(MAX_NESTING);
+        assert!(decode_tree(&mut Reader::new(&data), &table).is_none());
+        let data = nested(100_000);
+        assert!(decode_tree(&mut Reader::new(&data), &table).is_none());
+    }
+}
@@ -0,0 +1,614 @@
+use crate::analysis::cfg::parse_number;
+use crate::analysis::json::Json;
+use crate::analysis::report::json_quote;
+use crate::analysis::{
+    same_structure, tree_distance, tree_similarity_with_weights, AnalysisConfig, Graph, CFS,
+};
+use crate::disasm::{Binary, Function};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+// error codes defined by the JSON-RPC 2.0 specification.
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+// first of the codes reserved to the implementation, used when a function can not be analyzed.
+const ANALYSIS_ERROR: i64 = -32000;
+// largest offset represented exactly by a JSON number, as the numbers are read as f64.
+const MAX_EXACT_OFFSET: f64 = 9_007_199_254_740_991.0;
+
+/// Long-running analysis server, answering JSON-RPC 2.0 queries about the binaries it keeps in
+/// memory.
+///
+/// Binaries are loaded once, with [`Server::insert`] or with the `load` method, and each
+/// function is structured the first time it is queried, so plugins of IDEs and disassemblers can
+/// ask for a single function with interactive latency. The server does not handle the transport:
+/// every request is passed to [`Server::handle`] as a string, and the response is returned as a
+/// string to be sent back on the same channel, like a line of a Unix socket.
+///
+/// Parameters are always passed by name. Functions are identified by their name or by their
+/// offset, either as a string like `"0x401000"` or as a number. Numbers are exact only up to
+/// 2^53 - 1, so larger offsets are accepted only as strings. Offsets in the results are always
+/// hexadecimal strings. The following methods are available:
+/// - `load` with `path` and an optional `name`, defaulting to the path: disassembles the file
+///   with radare2 and keeps it in memory with the given name. Returns the `name` and the amount
+///   of `functions`. Available only with the `radare2` feature;
+/// - `unload` with `name`: forgets the binary, returning `true` if it was loaded;
+/// - `binaries`: returns the `name` and the amount of `functions` of every binary in memory;
+/// - `functions` with `binary`: returns the `name` and `offset` of every function;
+/// - `cfg` with `binary` and `function`: returns the `offset` of the function, its `blocks`,
+///   each with `offset`, `length` and `successors`, and the CFG in Graphviz `dot` format;
+/// - `structure` with `binary` and `function`: returns the structure `tree` as an s-expression
+///   (see [`StructureBlock::to_sexpr`](crate::analysis::StructureBlock::to_sexpr)), its
+///   `pseudocode`, and whether the structuring is `complete` together with its `fidelity`;
+/// - `compare` with `old`, `old_function`, `new` and `new_function`: compares two functions,
+///   possibly of different binaries, returning their `similarity`, the edit `distance` of the
+///   trees and whether they have the `same_structure`;
+/// - `shutdown`: returns `null`, after which [`Server::is_shutdown`] returns true.
+///
+/// The extraction and structuring settings, and the weights of the similarity, are given by the
+/// [`AnalysisConfig`] of the server.
+/// # Examples
+/// Basic usage:
+/// ```no_run
+/// use bincc::analysis::Server;
+/// use bincc::disasm::Binary;
+/// # async fn run() {
+/// # let binary: Binary = unimplemented!();
+///
+/// let server = Server::new();
+/// server.insert("app", binary);
+/// let request = r#"{"jsonrpc":"2.0","id":1,"method":"structure",
+///                   "params":{"binary":"app","function":"main"}}"#;
+/// let response = server.handle(request).await.unwrap();
+/// # }
+/// ```
+#[cfg_attr(docsrs, doc(cfg(feature = "disasm")))]
+pub struct Server {
+    config: AnalysisConfig,
+    binaries: RwLock<HashMap<String, Arc<LoadedBinary>>>,
+    shutdown: AtomicBool,
+}
+
+struct LoadedBinary {
+    binary: Binary,
+    // structures already computed, by offset of the function
+    structures: Mutex<HashMap<u64, Arc<CFS>>>,
+}
+
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: String) -> RpcError {
+        RpcError { code, message }
+    }
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Server {
+            config: AnalysisConfig::default(),
+            binaries: RwLock::new(HashMap::new()),
+            shutdown: AtomicBool::new(false),
+        }
+    }
+}
+
+impl Server {
+    /// Creates a server without binaries, using the default [`AnalysisConfig`].
+    pub fn new() -> Server {
+        Server::default()
+    }
+
+    /// Uses the given settings to extract, structure and compare the functions.
+    pub fn with_config(mut self, config: AnalysisConfig) -> Server {
+        self.config = config;
+        self
+    }
+
+    /// Keeps the given binary in memory, replacing any binary with the same name.
+    pub fn insert(&self, name: &str, binary: Binary) {
+        let loaded = LoadedBinary {
+            binary,
+            structures: Mutex::new(HashMap::new()),
+        };
+        self.binaries
+            .write()
+            .unwrap()
+            .insert(name.to_string(), Arc::new(loaded));
+    }
+
+    /// Forgets the binary with the given name, returning true if it was in memory.
+    pub fn remove(&self, name: &str) -> bool {
+        self.binaries.write().unwrap().remove(name).is_some()
+    }
+
+    /// Returns true if a client requested the `shutdown` of the server.
+    pub fn is_shutdown(&self) -> bool {
+        self.shutdown.load(Ordering::Relaxed)
+    }
+
+    /// Answers a single JSON-RPC 2.0 request.
+    ///
+    /// Returns the response to be sent to the client, or None if the request is a notification,
+    /// i.e. a request without `id`. Batch requests are not supported.
+    pub async fn handle(&self, request: &str) -> Option<String> {
+        let request = match Json::parse(request) {
+            Ok(request) => request,
+            Err(message) => {
+                let error = RpcError::new(PARSE_ERROR, message);
+                return Some(response(&Json::Null, Err(error)));
+            }
+        };
+        let method = match (request.get("jsonrpc"), request.get("method")) {
+            (Some(Json::String(version)), Some(Json::String(method))) if version == "2.0" => method,
+            _ => {
+                let id = request.get("id").unwrap_or(&Json::Null);
+                let error =
+                    RpcError::new(INVALID_REQUEST, "not a JSON-RPC 2.0 request".to_string());
+                return Some(response(id, Err(error)));
+            }
+        };
+        let no_params = Json::Object(Vec::new());
+        let params = request.get("params").unwrap_or(&no_params);
+        let result = self.call(method, params).await;
+        request.get("id").map(|id| response(id, result))
+    }
+
+    async fn call(&self, method: &str, params: &Json) -> Result<String, RpcError> {
+        match method {
+            #[cfg(feature = "radare2")]
+            "load" => self.load(params).await,
+            "unload" => Ok(self.remove(string_param(params, "name")?).to_string()),
+            "binaries" => Ok(self.list()),
+            "functions" => self.functions(params),
+            "cfg" => self.cfg(params),
+            "structure" => self.structure(params),
+            "compare" => self.compare(params),
+            "shutdown" => {
+                self.shutdown.store(true, Ordering::Relaxed);
+                Ok("null".to_string())
+            }
+            _ => Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("unknown method `{}`", method),
+            )),
+        }
+    }
+
+    #[cfg(feature = "radare2")]
+    async fn load(&self, params: &Json) -> Result<String, RpcError> {
+        let path = string_param(params, "path")?;
+        let name = match params.get("name") {
+            Some(_) => string_param(params, "name")?,
+            None => path,
+        };
+        let binary = Binary::analyze_with(path, self.config.extraction())
+            .await
+            .map_err(|err| RpcError::new(ANALYSIS_ERROR, err.to_string()))?;
+        let functions = binary.functions().len();
+        self.insert(name, binary);
+        Ok(format!(
+            "{{\"name\":{},\"functions\":{}}}",
+            json_quote(name),
+            functions
+        ))
+    }
+
+    fn list(&self) -> String {
+        let binaries = self.binaries.read().unwrap();
+        let mut names = binaries.keys().collect::<Vec<_>>();
+        names.sort_unstable();
+        let entries = names
+            .into_iter()
+            .map(|name| {
+                format!(
+                    "{{\"name\":{},\"functions\":{}}}",
+                    json_quote(name),
+                    binaries[name].binary.functions().len()
+                )
+            })
+            .collect::<Vec<_>>();
+        format!("[{}]", entries.join(","))
+    }
+
+    fn functions(&self, params: &Json) -> Result<String, RpcError> {
+        let loaded = self.binary(string_param(params, "binary")?)?;
+        let functions = loaded
+            .binary
+            .functions()
+            .iter()
+            .map(|function| {
+                format!(
+                    "{{\"name\":{},\"offset\":\"{:#x}\"}}",
+                    json_quote(function.get_name()),
+                    function.get_offset()
+                )
+            })
+            .collect::<Vec<_>>();
+        Ok(format!("[{}]", functions.join(",")))
+    }
+
+    fn cfg(&self, params: &Json) -> Result<String, RpcError> {
+        let loaded = self.binary(string_param(params, "binary")?)?;
+        let offset = loaded.function(param(params, "function")?)?;
+        let cfg = loaded.binary.cfg(offset).unwrap();
+        let blocks = cfg
+            .blocks_sorted()
+            .into_iter()
+            .filter(|bb| !bb.is_sink())
+            .map(|bb| {
+                let successors = cfg
+                    .neighbours(bb)
+                    .iter()
+                    .filter(|next| !next.is_sink())
+                    .map(|next| format!("\"{:#x}\"", next.offset))
+                    .collect::<Vec<_>>();
+                format!(
+                    "{{\"offset\":\"{:#x}\",\"length\":{},\"successors\":[{}]}}",
+                    bb.offset,
+                    bb.length,
+                    successors.join(",")
+                )
+            })
+            .collect::<Vec<_>>();
+        Ok(format!(
+            "{{\"offset\":\"{:#x}\",\"blocks\":[{}],\"dot\":{}}}",
+            offset,
+            blocks.join(","),
+            json_quote(&cfg.to_dot())
+        ))
+    }
+
+    fn structure(&self, params: &Json) -> Result<String, RpcError> {
+        let (offset, cfs) = self.structure_param(params, "binary", "function")?;
+        let tree = cfs.get_tree();
+        Ok(format!(
+            "{{\"offset\":\"{:#x}\",\"tree\":{},\"pseudocode\":{},\"complete\":{},\
+             \"fidelity\":{}}}",
+            offset,
+            tree.as_ref()
+                .map_or("null".to_string(), |tree| json_quote(&tree.to_sexpr())),
+            tree.as_ref()
+                .map_or("null".to_string(), |tree| json_quote(&tree.to_pseudocode())),
+            cfs.result().is_complete(),
+            cfs.fidelity()
+        ))
+    }
+
+    fn compare(&self, params: &Json) -> Result<String, RpcError> {
+        let (_, old) = self.structure_param(params, "old", "old_function")?;
+        let (_, new) = self.structure_param(params, "new", "new_function")?;
+        match (old.get_tree(), new.get_tree()) {
+            (Some(old_tree), Some(new_tree)) => Ok(format!(
+                "{{\"similarity\":{},\"distance\":{},\"same_structure\":{}}}",
+                tree_similarity_with_weights(&old_tree, &new_tree, self.config.weights()),
+                tree_distance(&old_tree, &new_tree),
+                same_structure(&old, &new)
+            )),
+            _ => Err(RpcError::new(
+                ANALYSIS_ERROR,
+                "functions without blocks can not be compared".to_string(),
+            )),
+        }
+    }
+
+    // structure of the function identified by the given parameters, along with its offset.
+    fn structure_param(
+        &self,
+        params: &Json,
+        binary: &str,
+        function: &str,
+    ) -> Result<(u64, Arc<CFS>), RpcError> {
+        let loaded = self.binary(string_param(params, binary)?)?;
+        let offset = loaded.function(param(params, function)?)?;
+        let cfs = loaded.structure(offset, &self.config)?;
+        Ok((offset, cfs))
+    }
+
+    fn binary(&self, name: &str) -> Result<Arc<LoadedBinary>, RpcError> {
+        self.binaries
+            .read()
+            .unwrap()
+            .get(name)
+            .cloned()
+            .ok_or_else(|| {
+                RpcError::new(INVALID_PARAMS, format!("binary `{}` is not loaded", name))
+            })
+    }
+}
+
+impl LoadedBinary {
+    // offset of the function identified by the given parameter, if it has a CFG.
+    fn function(&self, param: &Json) -> Result<u64, RpcError> {
+        let offset = match param {
+            Json::Number(offset)
+                if (0.0..=MAX_EXACT_OFFSET).contains(offset) && offset.fract() == 0.0 =>
+            {
+                Some(*offset as u64)
+            }
+            Json::String(name) => self
+                .binary
+                .functions()
+                .iter()
+                .find(|function| function.get_name() == name)
+                .map(Function::get_offset)
+                .or_else(|| parse_number(name)),
+            _ => None,
+        };
+        offset
+            .filter(|offset| self.binary.cfg(*offset).is_some())
+            .ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("function {} not found", param)))
+    }
+
+    // structures the function at the given offset, or returns the structure already computed.
+    fn structure(&self, offset: u64, config: &AnalysisConfig) -> Result<Arc<CFS>, RpcError> {
+        if let Some(cfs) = self.structures.lock().unwrap().get(&offset) {
+            return Ok(Arc::clone(cfs));
+        }
+        // the lock is not held while structuring, so other functions can be queried meanwhile
+        let cfs = config
+            .cfs_builder()
+            .build(self.binary.cfg(offset).unwrap())
+            .map_err(|err| RpcError::new(ANALYSIS_ERROR, err.to_string()))?;
+        let cfs = Arc::new(cfs);
+        self.structures
+            .lock()
+            .unwrap()
+            .insert(offset, Arc::clone(&cfs));
+        Ok(cfs)
+    }
+}
+
+fn param<'a>(params: &'a Json, name: &str) -> Result<&'a Json, RpcError> {
+    params
+        .get(name)
+        .ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("missing parameter `{}`", name)))
+}
+
+fn string_param<'a>(params: &'a Json, name: &str) -> Result<&'a str, RpcError> {
+    param(params, name)?.as_str().ok_or_else(|| {
+        RpcError::new(
+            INVALID_PARAMS,
+            format!("parameter `{}` must be a string", name),
+        )
+    })
+}
+
+fn response(id: &Json, result: Result<String, RpcError>) -> String {
+    match result {
+        Ok(result) => format!(
+            "{{\"jsonrpc\":\"2.0\",\"id\":{},\"result\":{}}}",
+            id, result
+        ),
+        Err(error) => format!(
+            "{{\"jsonrpc\":\"2.0\",\"id\":{},\"error\":{{\"code\":{},\"message\":{}}}}}",
+            id,
+            error.code,
+            json_quote(&error.message)
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::json::Json;
+    use crate::analysis::test_utils::binary;
+    use crate::analysis::{Server, CFS};
+
+    async fn server() -> Server {
+        let old = binary(vec![(0x100, "main", 5), (0x200, "init", 0)]).await;
+        let new = binary(vec![(0x1100, "main", 7), (0x1200, "init", 0)]).await;
+        let server = Server::new();
+        server.insert("old", old);
+        server.insert("new", new);
+        server
+    }
+
+    // sends the request and returns the result, panicking in case of errors.
+    async fn call(server: &Server, method: &str, params: &str) -> Json {
+        let request = format!(
+            "{{\"jsonrpc\":\"2.0\",\"id\":7,\"method\":\"{}\",\"params\":{}}}",
+            method, params
+        );
+        let response = Json::parse(&server.handle(&request).await.unwrap()).unwrap();
+        assert_eq!(response.get("id"), Some(&Json::Number(7.0)));
+        assert_eq!(response.get("error"), None, "{}", response);
+        response.get("result").unwrap().clone()
+    }
+
+    // sends the request and returns the code of the error.
+    async fn error(server: &Server, request: &str) -> f64 {
+        let response = Json::parse(&server.handle(request).await.unwrap()).unwrap();
+        assert_eq!(response.get("result"), None);
+        match response.get("error").and_then(|error| error.get("code")) {
+            Some(Json::Number(code)) => *code,
+            _ => panic!("missing error code in {}", response),
+        }
+    }
+
+    #[tokio::test]
+    async fn queries() {
+        let server = server().await;
+        let binaries = call(&server, "binaries", "{}").await.to_string();
+        assert_eq!(
+            binaries,
+            "[{\"name\":\"new\",\"functions\":2},{\"name\":\"old\",\"functions\":2}]"
+        );
+        let functions = call(&server, "functions", "{\"binary\":\"old\"}").await;
+        assert_eq!(
+            functions.to_string(),
+            "[{\"name\":\"main\",\"offset\":\"0x100\"},{\"name\":\"init\",\"offset\":\"0x200\"}]"
+        );
+        let cfg = call(&server, "cfg", "{\"binary\":\"old\",\"function\":\"main\"}").await;
+        assert_eq!(cfg.get("offset").and_then(Json::as_str), Some("0x100"));
+        match cfg.get("blocks") {
+            Some(Json::Array(blocks)) => {
+                assert_eq!(blocks.len(), 3);
+                let mut successors = match blocks[0].get("successors") {
+                    Some(Json::Array(successors)) => successors
+                        .iter()
+                        .filter_map(Json::as_str)
+                        .collect::<Vec<_>>(),
+                    _ => panic!("missing successors"),
+                };
+                successors.sort_unstable();
+                assert_eq!(successors, vec!["0x102", "0x107"]);
+            }
+            _ => panic!("missing blocks"),
+        }
+        assert!(cfg.get("dot").and_then(Json::as_str).is_some());
+        let structure = call(
+            &server,
+            "structure",
+            "{\"binary\":\"old\",\"function\":256}",
+        )
+        .await;
+        let expected = CFS::new(
+            server.binaries.read().unwrap()["old"]
+                .binary
+                .cfg(0x100)
+                .unwrap(),
+        )
+        .unwrap()
+        .get_tree()
+        .unwrap()
+        .to_sexpr();
+        assert_eq!(
+            structure.get("tree").and_then(Json::as_str),
+            Some(expected.as_str())
+        );
+        assert_eq!(structure.get("complete"), Some(&Json::Bool(true)));
+        let compare = call(
+            &server,
+            "compare",
+            "{\"old\":\"old\",\"old_function\":\"0x100\",\
+             \"new\":\"new\",\"new_function\":\"main\"}",
+        )
+        .await;
+        assert_eq!(compare.get("similarity"), Some(&Json::Number(1.0)));
+        assert_eq!(compare.get("distance"), Some(&Json::Number(0.0)));
+        assert_eq!(compare.get("same_structure"), Some(&Json::Bool(true)));
+        let compare = call(
+            &server,
+            "compare",
+            "{\"old\":\"old\",\"old_function\":\"main\",\"new\":\"new\",\"new_function\":\"init\"}",
+        )
+        .await;
+        assert_eq!(compare.get("same_structure"), Some(&Json::Bool(false)));
+    }
+
+    #[tokio::test]
+    async fn errors() {
+        let server = server().await;
+        assert_eq!(error(&server, "{\"jsonrpc\":").await, -32700.0);
+        assert_eq!(
+            error(&server, "{\"id\":1,\"method\":\"binaries\"}").await,
+            -32600.0
+        );
+        assert_eq!(error(&server, "[]").await, -32600.0);
+        let request = |method: &str, params: &str| {
+            format!(
+                "{{\"jsonrpc\":\"2.0\",\"id\":\"a\",\"method\":\"{}\",\"params\":{}}}",
+                method, params
+            )
+        };
+        assert_eq!(error(&server, &request("decompile", "{}")).await, -32601.0);
+        assert_eq!(error(&server, &request("functions", "{}")).await, -32602.0);
+        assert_eq!(
+            error(&server, &request("functions", "{\"binary\":1}")).await,
+            -32602.0
+        );
+        assert_eq!(
+            error(&server, &request("functions", "{\"binary\":\"none\"}")).await,
+            -32602.0
+        );
+        assert_eq!(
+            error(
+                &server,
+                &request("cfg", "{\"binary\":\"old\",\"function\":\"0x300\"}")
+            )
+            .await,
+            -32602.0
+        );
+        assert_eq!(
+            error(
+                &server,
+                &request("cfg", "{\"binary\":\"old\",\"function\":-1}")
+            )
+            .await,
+            -32602.0
+        );
+    }
+
+    #[tokio::test]
+    async fn high_offsets() {
+        let server = Server::new();
+        let functions = vec![
+            (0x20_0000_0000_0000, "exact", 5),
+            (0xffff_ffff_ffff_ff00, "high", 5),
+        ];
+        server.insert("app", binary(functions).await);
+        let cfg = |function: &str| format!("{{\"binary\":\"app\",\"function\":{}}}", function);
+        let high = call(&server, "cfg", &cfg("\"0xffffffffffffff00\"")).await;
+        assert_eq!(
+            high.get("offset").and_then(Json::as_str),
+            Some("0xffffffffffffff00")
+        );
+        let exact = call(&server, "cfg", &cfg("\"0x20000000000000\"")).await;
+        assert_eq!(
+            exact.get("offset").and_then(Json::as_str),
+            Some("0x20000000000000")
+        );
+        // 2^53 + 1 would be read as 2^53, so numbers past 2^53 - 1 are rejected
+        for number in [
+            "9007199254740992",
+            "9007199254740993",
+            "18446744073709551360",
+        ] {
+            let request = format!(
+                "{{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"cfg\",\"params\":{}}}",
+                cfg(number)
+            );
+            assert_eq!(error(&server, &request).await, -32602.0);
+        }
+    }
+
+    #[tokio::test]
+    async fn notifications() {
+        let server = server().await;
+        let unload = "{\"jsonrpc\":\"2.0\",\"method\":\"unload\",\"params\":{\"name\":\"old\"}}";
+        assert_eq!(server.handle(unload).await, None);
+        assert_eq!(
+            call(&server, "binaries", "{}")
+                .await
+                .to_string()
+                .matches("name")
+                .count(),
+            1
+        );
+        assert_eq!(
+            call(&server, "unload", "{\"name\":\"old\"}").await,
+            Json::Bool(false)
+        );
+        assert!(!server.is_shutdown());
+        assert_eq!(call(&server, "shutdown", "{}").await, Json::Null);
+        assert!(server.is_shutdown());
+    }
+
+    #[tokio::test]
+    async fn cached_structures() {
+        let server = server().await;
+        let params = "{\"binary\":\"new\",\"function\":\"main\"}";
+        let first = call(&server, "structure", params).await;
+        let second = call(&server, "structure", params).await;
+        assert_eq!(first, second);
+        let binaries = server.binaries.read().unwrap();
+        assert_eq!(binaries["new"].structures.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,388 @@
+use crate::analysis::blocks::StructureBlock;
+use crate::analysis::{BasicBlock, BlockType, NestedBlock};
+use std::io::{Error, ErrorKind};
+use std::sync::Arc;
+
+// maximum nesting of the parsed trees, so they can be visited recursively.
+const MAX_DEPTH: usize = 1024;
+
+// keyword of every nested block type in the textual form.
+const KEYWORDS: [(BlockType, &str); 22] = [
+    (BlockType::SelfLooping, "self"),
+    (BlockType::Sequence, "seq"),
+    (BlockType::IfThen, "if"),
+    (BlockType::IfThenElse, "ifelse"),
+    (BlockType::While, "while"),
+    (BlockType::DoWhile, "dowhile"),
+    (BlockType::Switch, "switch"),
+    (BlockType::ProperInterval, "proper"),
+    (BlockType::ImproperInterval, "improper"),
+    (BlockType::Break, "break"),
+    (BlockType::Duplicate, "dup"),
+    (BlockType::ImproperRegion, "region"),
+    (BlockType::Condition, "cond"),
+    (BlockType::Guard, "guard"),
+    (BlockType::Goto, "goto"),
+    (BlockType::IfChain, "chain"),
+    (BlockType::Conditional, "conditional"),
+    (BlockType::AbnormalEntry, "abnormal"),
+    (BlockType::Return, "return"),
+    (BlockType::Unreachable, "unreachable"),
+    (BlockType::Continue, "continue"),
+    (BlockType::SwitchLoop, "switchloop"),
+];
+
+impl StructureBlock {
+    /// Returns a compact textual representation of this tree, as an s-expression.
+    ///
+    /// Each nested block is written as a list starting with a keyword for its type, followed by
+    /// its children, like `(seq b0 (while b1 b2) b3)`. Basic blocks are written as `b` followed
+    /// by their offset in hexadecimal, and their length after a `+` if different from 1, like
+    /// `b1a+4`. The artificial entry point and sink are written as `entry` and `sink`.
+    /// Markers, like [`BlockType::Break`] and [`BlockType::Goto`], are written with the source of
+    /// the edge they represent and its destination, if known, like `(goto b2 b8)`.
+    ///
+    /// Exits, condition polarities and reaching conditions are not represented, so
+    /// [`StructureBlock::from_sexpr`] recovers the same tree only if they are not set.
+    ///
+    /// The keywords are `seq`, `self`, `if`, `ifelse`, `while`, `dowhile`, `switch`, `proper`,
+    /// `improper`, `break`, `dup`, `region`, `cond`, `guard`, `goto`, `chain`, `conditional`,
+    /// `abnormal`, `return`, `unreachable`, `continue` and `switchloop`, one for each
+    /// [`BlockType`] other than [`BlockType::Basic`]. A [`BlockType::Custom`] is written as
+    /// `custom:` followed by its identifier in decimal, like `(custom:7 b0 b1)`.
+    /// # Examples
+    /// Basic usage:
+    /// ```
+    /// use bincc::analysis::{BasicBlock, BlockType, NestedBlock, StructureBlock};
+    /// use std::sync::Arc;
+    ///
+    /// let bb0 = StructureBlock::from(BasicBlock { offset: 0x10, length: 1 });
+    /// let bb1 = StructureBlock::from(BasicBlock { offset: 0x11, length: 4 });
+    /// let ifthen = NestedBlock::new(BlockType::IfThen, vec![bb0, bb1]);
+    ///
+    /// assert_eq!(StructureBlock::from(Arc::new(ifthen)).to_sexpr(), "(if b10 b11+4)");
+    /// ```
+    pub fn to_sexpr(&self) -> String {
+        let mut string = String::new();
+        write_sexpr(self, &mut string);
+        string
+    }
+
+    /// Parses a tree from the s-expression written by [`StructureBlock::to_sexpr`].
+    ///
+    /// Tokens can be separated by any amount of whitespace. Returns an [`std::io::Error`] of kind
+    /// [`ErrorKind::InvalidInput`] if the input is not a single well-formed tree, or if it nests
+    /// more than 1024 blocks.
+    /// # Examples
+    /// Basic usage:
+    /// ```
+    /// use bincc::analysis::{BlockType, StructureBlock};
+    ///
+    /// let tree = StructureBlock::from_sexpr("(seq b0 (while b1 b2) b3)").unwrap();
+    ///
+    /// assert_eq!(tree.block_type(), BlockType::Sequence);
+    /// assert_eq!(tree.children()[1].block_type(), BlockType::While);
+    /// assert_eq!(tree.children()[1].offset(), 1);
+    /// assert_eq!(tree.to_sexpr(), "(seq b0 (while b1 b2) b3)");
+    /// ```
+    pub fn from_sexpr(string: &str) -> Result<StructureBlock, Error> {
+        let tokens = tokenize(string);
+        let mut parser = Parser { tokens, next: 0 };
+        let tree = parser.parse_block()?;
+        match parser.tokens.get(parser.next) {
+            Some(token) => Err(invalid(format!("unexpected `{}` after the tree", token))),
+            None => Ok(tree),
+        }
+    }
+}
+
+fn write_sexpr(node: &StructureBlock, string: &mut String) {
+    match node {
+        StructureBlock::Basic(bb) => string.push_str(&leaf(bb)),
+        StructureBlock::Nested(nb) => {
+            string.push('(');
+            string.push_str(&type_keyword(nb.block_type));
+            match nb.block_type {
+                BlockType::Break
+                | BlockType::Continue
+                | BlockType::Goto
+                | BlockType::AbnormalEntry => match nb.exits.first() {
+                    Some((src, dst)) => {
+                        string.push_str(&format!(" {} {}", leaf(src), leaf(dst)));
+                    }
+                    None => {
+                        let src = BasicBlock {
+                            offset: nb.offset,
+                            length: 1,
+                        };
+                        string.push_str(&format!(" {}", leaf(&src)));
+                    }
+                },
+                _ => {
+                    for child in &nb.content {
+                        string.push(' ');
+                        write_sexpr(child, string);
+                    }
+                }
+            }
+            string.push(')');
+        }
+    }
+}
+
+fn leaf(bb: &BasicBlock) -> String {
+    if bb.is_entry_point() {
+        "entry".to_string()
+    } else if bb.is_sink() {
+        "sink".to_string()
+    } else if bb.length == 1 {
+        format!("b{:x}", bb.offset)
+    } else {
+        format!("b{:x}+{:x}", bb.offset, bb.length)
+    }
+}
+
+// keyword of a block type in the textual form, or `basic` for the basic blocks.
+pub(super) fn type_keyword(block_type: BlockType) -> String {
+    match block_type {
+        BlockType::Custom(id) => format!("custom:{}", id),
+        block_type => KEYWORDS
+            .iter()
+            .find(|(bt, _)| *bt == block_type)
+            .map_or("basic", |(_, keyword)| *keyword)
+            .to_string(),
+    }
+}
+
+// block type of a keyword in the textual form, other than the basic blocks.
+pub(super) fn keyword_type(keyword: &str) -> Option<BlockType> {
+    match keyword.strip_prefix("custom:") {
+        Some(id) => id.parse().ok().map(BlockType::Custom),
+        None => KEYWORDS
+            .iter()
+            .find(|(_, kw)| *kw == keyword)
+            .map(|(bt, _)| *bt),
+    }
+}
+
+fn tokenize(string: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (index, c) in string.char_indices() {
+        if c == '(' || c == ')' || c.is_whitespace() {
+            if let Some(start) = start.take() {
+                tokens.push(&string[start..index]);
+            }
+            if !c.is_whitespace() {
+                tokens.push(&string[index..index + 1]);
+            }
+        } else if start.is_none() {
+            start = Some(index);
+        }
+    }
+    if let Some(start) = start {
+        tokens.push(&string[start..]);
+    }
+    tokens
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidInput, message)
+}
+
+struct Parser<'a> {
+    tokens: Vec<&'a str>,
+    next: usize,
+}
+
+impl<'a> Parser<'a> {
+    // parses a tree with an explicit stack, so a deeply nested input cannot overflow the call
+    // stack.
+    fn parse_block(&mut self) -> Result<StructureBlock, Error> {
+        // lists still open, with their keyword and the children parsed so far
+        let mut open: Vec<(&'a str, BlockType, Vec<StructureBlock>)> = Vec::new();
+        loop {
+            let block = match self.pop()? {
+                "(" => {
+                    if open.len() == MAX_DEPTH {
+                        return Err(invalid(format!("more than {} nested blocks", MAX_DEPTH)));
+                    }
+                    let name = self.pop()?;
+                    let block_type = keyword_type(name)
+                        .ok_or_else(|| invalid(format!("unknown block type `{}`", name)))?;
+                    open.push((name, block_type, Vec::new()));
+                    continue;
+                }
+                ")" => match open.pop() {
+                    Some((name, block_type, children)) => nested_block(name, block_type, children)?,
+                    None => return Err(invalid("unexpected `)`".to_string())),
+                },
+                token => StructureBlock::from(parse_leaf(token)?),
+            };
+            match open.last_mut() {
+                Some((_, _, children)) => children.push(block),
+                None => return Ok(block),
+            }
+        }
+    }
+
+    fn pop(&mut self) -> Result<&'a str, Error> {
+        let token = *self
+            .tokens
+            .get(self.next)
+            .ok_or_else(|| invalid("unexpected end of input".to_string()))?;
+        self.next += 1;
+        Ok(token)
+    }
+}
+
+// builds a nested block from its keyword, its type and its children.
+fn nested_block(
+    name: &str,
+    block_type: BlockType,
+    children: Vec<StructureBlock>,
+) -> Result<StructureBlock, Error> {
+    let nb = match (block_type, children.as_slice()) {
+        (
+            BlockType::Break | BlockType::Continue | BlockType::Goto | BlockType::AbnormalEntry,
+            [StructureBlock::Basic(src)],
+        ) => NestedBlock::marker(block_type, src),
+        (
+            BlockType::Break | BlockType::Continue | BlockType::Goto | BlockType::AbnormalEntry,
+            [StructureBlock::Basic(src), StructureBlock::Basic(dst)],
+        ) => {
+            let mut marker = NestedBlock::marker(block_type, src);
+            marker.exits = vec![(*src, *dst)];
+            marker
+        }
+        (
+            BlockType::Break | BlockType::Continue | BlockType::Goto | BlockType::AbnormalEntry,
+            _,
+        ) => {
+            return Err(invalid(format!("malformed `{}` marker", name)));
+        }
+        (BlockType::Duplicate, [original]) => NestedBlock::duplicate(original),
+        (BlockType::Duplicate, _) => {
+            return Err(invalid("`dup` requires exactly one child".to_string()));
+        }
+        (BlockType::Return | BlockType::Unreachable, [StructureBlock::Basic(bb)]) => {
+            NestedBlock::terminator(block_type, bb)
+        }
+        (BlockType::Return | BlockType::Unreachable, _) => {
+            return Err(invalid(format!("`{}` requires a basic block", name)));
+        }
+        (_, []) => return Err(invalid(format!("`{}` without children", name))),
+        _ => NestedBlock::new(block_type, children),
+    };
+    Ok(StructureBlock::from(Arc::new(nb)))
+}
+
+fn parse_leaf(token: &str) -> Result<BasicBlock, Error> {
+    match token {
+        "entry" => return Ok(BasicBlock::new_entry_point()),
+        "sink" => return Ok(BasicBlock::new_sink()),
+        _ => {}
+    }
+    let malformed = || invalid(format!("malformed basic block `{}`", token));
+    let hex = token.strip_prefix('b').ok_or_else(malformed)?;
+    let (offset, length) = match hex.split_once('+') {
+        Some((offset, length)) => (offset, length),
+        None => (hex, "1"),
+    };
+    Ok(BasicBlock {
+        offset: u64::from_str_radix(offset, 16).map_err(|_| malformed())?,
+        length: u64::from_str_radix(length, 16).map_err(|_| malformed())?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::{BasicBlock, BlockType, CfsBuilder, StructureBlock, CFG, CFS};
+    use std::collections::HashMap;
+
+    fn cfg(edges: &[&[u64]]) -> CFG {
+        let bb = |offset| BasicBlock { offset, length: 1 };
+        let edges = edges
+            .iter()
+            .enumerate()
+            .map(|(src, dsts)| (bb(src as u64), dsts.iter().map(|dst| bb(*dst)).collect()))
+            .collect::<HashMap<_, _>>();
+        CFG {
+            root: Some(bb(0)),
+            edges,
+            info: HashMap::new(),
+            metadata: None,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let sources = [
+            "(seq b0 (while b1 b2) b3)",
+            "(seq entry (dowhile (ifelse b1a+4 b1e b22) b26+10) sink)",
+            "(seq b0 (dup (if b1 b2)) (switch b3 b4 b5))",
+            "(dowhile b1 b2 (break b2 b5) (goto b1 b7) (break b1))",
+            "(seq b0 (dowhile b1 (seq b2 b3) (abnormal b0 b3)) b4)",
+            "(seq b0 (guard b1 (unreachable b2)) (return b3))",
+            "(seq b0 (custom:7 b1 b2) b3)",
+        ];
+        for source in sources {
+            let tree = StructureBlock::from_sexpr(source).unwrap();
+            assert_eq!(tree.to_sexpr(), source);
+        }
+    }
+
+    #[test]
+    fn round_trip_cfs() {
+        let cfg = cfg(&[&[1], &[2, 5], &[3], &[4, 5], &[1], &[]]);
+        let cfs = CfsBuilder::new().preserve_loops().build(&cfg).unwrap();
+        let tree = cfs.get_tree().unwrap();
+        let parsed = StructureBlock::from_sexpr(&tree.to_sexpr()).unwrap();
+        assert!(parsed.structural_equality(&tree));
+        assert_eq!(parsed.basic_blocks(), tree.basic_blocks());
+        let cfs = CFS::new(&cfg).unwrap();
+        assert_eq!(
+            cfs.get_tree().unwrap().to_sexpr(),
+            "(seq b0 (while b1 (seq b2 b3 b4) (break b3)) b5)"
+        );
+    }
+
+    #[test]
+    fn whitespace() {
+        let tree = StructureBlock::from_sexpr("  (seq\n\tb0 (self b1 )b2)\n").unwrap();
+        assert_eq!(tree.block_type(), BlockType::Sequence);
+        assert_eq!(tree.children()[1].block_type(), BlockType::SelfLooping);
+        assert_eq!(tree.to_sexpr(), "(seq b0 (self b1) b2)");
+    }
+
+    #[test]
+    fn malformed() {
+        let inputs = [
+            "",
+            "(seq b0",
+            "(seq b0 b1))",
+            "(loop b0 b1)",
+            "(seq b0 bx)",
+            "(seq)",
+            "(dup b0 b1)",
+            "(break b0 b1 b2)",
+            "(seq b0 0x10)",
+            "(return (seq b0 b1))",
+            "(custom:x b0)",
+            "(custom:70000 b0)",
+            ")",
+        ];
+        for input in inputs {
+            assert!(StructureBlock::from_sexpr(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn nesting() {
+        let nested = |depth| format!("{}b0{}", "(self ".repeat(depth), ")".repeat(depth));
+        let tree = StructureBlock::from_sexpr(&nested(1024)).unwrap();
+        assert_eq!(tree.block_type(), BlockType::SelfLooping);
+        assert!(StructureBlock::from_sexpr(&nested(1025)).is_err());
+        assert!(StructureBlock::from_sexpr(&"(".repeat(1_000_000)).is_err());
+    }
+}
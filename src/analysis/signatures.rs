@@ -0,0 +1,480 @@
+use crate::analysis::serial::{encode_string, Reader};
+use crate::analysis::{CFG, CFS};
+use crate::disasm::{Binary, Relocation};
+use std::collections::HashMap;
+use std::io;
+use std::io::ErrorKind;
+
+const SIGNATURES_MAGIC: &[u8; 4] = b"BCCS";
+const SIGNATURES_VERSION: u32 = 1;
+// amount of bytes at the beginning of a function recorded by its signature.
+const PATTERN_LEN: usize = 32;
+// functions shorter than this are too generic to be identified by their bytes.
+const MIN_PATTERN_LEN: usize = 8;
+
+/// Fingerprint of a known library function.
+///
+/// A signature is composed of the first bytes of the function, with the bytes patched by the
+/// relocations masked out, and of the [`StructureBlock::structural_hash`] of its [`CFS`]. A
+/// function matches the signature only if both its bytes and its structure match, so short
+/// functions sharing the same prologue are not confused.
+///
+/// [`StructureBlock::structural_hash`]: crate::analysis::StructureBlock::structural_hash
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(docsrs, doc(cfg(feature = "disasm")))]
+pub struct Signature {
+    name: String,
+    pattern: Vec<u8>,
+    // false if the byte in the same position of the pattern can have any value
+    mask: Vec<bool>,
+    structure: u64,
+}
+
+impl Signature {
+    /// Creates the signature of the function with the given name, starting with the given bytes
+    /// and with the given structural hash.
+    ///
+    /// The bytes of the pattern whose mask is `false` are ignored when matching. The mask is
+    /// truncated or extended with `true` to the length of the pattern.
+    pub fn new(name: &str, pattern: Vec<u8>, mut mask: Vec<bool>, structure: u64) -> Signature {
+        mask.resize(pattern.len(), true);
+        Signature {
+            name: name.to_string(),
+            pattern,
+            mask,
+            structure,
+        }
+    }
+
+    /// Creates the signature of the function starting at the given offset of a library build.
+    ///
+    /// Returns [`None`] if the function has no [`CFG`], its structure can not be computed, its
+    /// bytes are not contained in the [`Binary::memory`], that must be loaded beforehand with
+    /// [`Binary::load_memory`], or they are too few to identify it.
+    /// The bytes patched by the [`Binary::relocations`] are masked out, so the library should be
+    /// built as an object or static archive, where every reference to other functions is
+    /// relocated. A relocation patches the bytes up to the end of the call, jump or data access
+    /// containing it, see [`CFG::calls`] and [`CFG::references`], as in [`Binary::extract`].
+    pub fn from_function(binary: &Binary, offset: u64) -> Option<Signature> {
+        let cfg = binary.cfg(offset)?;
+        let bytes = function_bytes(binary, cfg, offset)?;
+        let mask = relocation_mask(binary, cfg, offset, bytes.len());
+        let name = cfg
+            .metadata()
+            .map(|metadata| metadata.name().to_string())
+            .or_else(|| {
+                binary
+                    .functions()
+                    .iter()
+                    .find(|func| func.get_offset() == offset)
+                    .map(|func| func.get_name().to_string())
+            })?;
+        Some(Signature::new(
+            &name,
+            bytes.to_vec(),
+            mask,
+            structural_hash(cfg)?,
+        ))
+    }
+
+    /// Returns the name of the library function.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the first bytes of the function.
+    pub fn pattern(&self) -> &[u8] {
+        &self.pattern
+    }
+
+    /// Returns, for each byte of the [`Signature::pattern`], true if the byte must match.
+    pub fn mask(&self) -> &[bool] {
+        &self.mask
+    }
+
+    /// Returns the structural hash of the function.
+    pub fn structural_hash(&self) -> u64 {
+        self.structure
+    }
+
+    /// Returns true if a function starting with the given bytes and with the given structural hash
+    /// matches this signature.
+    pub fn matches(&self, bytes: &[u8], structure: u64) -> bool {
+        structure == self.structure
+            && bytes.len() >= self.pattern.len()
+            && self
+                .pattern
+                .iter()
+                .zip(&self.mask)
+                .zip(bytes)
+                .all(|((expected, relevant), byte)| !relevant || expected == byte)
+    }
+}
+
+/// Collection of [`Signature`]s used to recognize statically linked library functions.
+///
+/// The signatures are generated from the known builds of a library with
+/// [`SignatureSet::from_binary`], stored with [`SignatureSet::to_bytes`], and matched against the
+/// functions of a target binary with [`SignatureSet::identify`]. The identified functions should
+/// be excluded from the clone detection, as the same library code is shared by many unrelated
+/// binaries.
+/// # Examples
+/// Basic usage:
+/// ```
+/// use bincc::analysis::{Signature, SignatureSet};
+///
+/// let mut signatures = SignatureSet::new();
+/// signatures.insert(Signature::new("memcpy", vec![0x55, 0xE8, 0x00], vec![true, true, false], 7));
+///
+/// assert_eq!(signatures.matching(&[0x55, 0xE8, 0xFF, 0x90], 7)[0].name(), "memcpy");
+/// assert!(signatures.matching(&[0x55, 0xE9, 0xFF, 0x90], 7).is_empty());
+/// assert!(signatures.matching(&[0x55, 0xE8, 0xFF, 0x90], 8).is_empty());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(docsrs, doc(cfg(feature = "disasm")))]
+pub struct SignatureSet {
+    signatures: Vec<Signature>,
+    // index of the signatures with the given structural hash
+    by_structure: HashMap<u64, Vec<usize>>,
+}
+
+impl SignatureSet {
+    /// Creates an empty set of signatures.
+    pub fn new() -> SignatureSet {
+        SignatureSet::default()
+    }
+
+    /// Creates the signatures of every function of the given library build.
+    ///
+    /// See [`Signature::from_function`] for the functions that can not be signed.
+    pub fn from_binary(binary: &Binary) -> SignatureSet {
+        let mut set = SignatureSet::new();
+        binary
+            .functions()
+            .iter()
+            .filter_map(|func| Signature::from_function(binary, func.get_offset()))
+            .for_each(|signature| set.insert(signature));
+        set
+    }
+
+    /// Adds a signature to the set.
+    ///
+    /// Adding a signature equal to an existing one has no effect.
+    pub fn insert(&mut self, signature: Signature) {
+        let candidates = self.by_structure.entry(signature.structure).or_default();
+        if !candidates
+            .iter()
+            .any(|&index| self.signatures[index] == signature)
+        {
+            candidates.push(self.signatures.len());
+            self.signatures.push(signature);
+        }
+    }
+
+    /// Adds every signature of another set to this one.
+    pub fn merge(&mut self, other: SignatureSet) {
+        other
+            .signatures
+            .into_iter()
+            .for_each(|signature| self.insert(signature));
+    }
+
+    /// Returns the amount of signatures in the set.
+    pub fn len(&self) -> usize {
+        self.signatures.len()
+    }
+
+    /// Returns true if the set contains no signatures.
+    pub fn is_empty(&self) -> bool {
+        self.signatures.is_empty()
+    }
+
+    /// Returns an iterator over the signatures, in insertion order.
+    pub fn iter(&self) -> impl Iterator<Item = &Signature> {
+        self.signatures.iter()
+    }
+
+    /// Returns every signature matched by a function starting with the given bytes and with the
+    /// given structural hash, see [`Signature::matches`].
+    pub fn matching(&self, bytes: &[u8], structure: u64) -> Vec<&Signature> {
+        self.by_structure
+            .get(&structure)
+            .into_iter()
+            .flatten()
+            .map(|&index| &self.signatures[index])
+            .filter(|signature| signature.matches(bytes, structure))
+            .collect()
+    }
+
+    /// Identifies the library functions of the given binary.
+    ///
+    /// Returns the offset of every identified function along with the name of the library
+    /// function. Functions matching signatures with different names are ambiguous, and are not
+    /// part of the result. The bytes of the functions are read from the [`Binary::memory`], that
+    /// must be loaded beforehand with [`Binary::load_memory`].
+    pub fn identify<'a>(&'a self, binary: &Binary) -> HashMap<u64, &'a str> {
+        let mut identified = HashMap::new();
+        for function in binary.functions() {
+            let offset = function.get_offset();
+            let cfg = match binary.cfg(offset) {
+                Some(cfg) => cfg,
+                None => continue,
+            };
+            let matching = match (function_bytes(binary, cfg, offset), structural_hash(cfg)) {
+                (Some(bytes), Some(structure)) => self.matching(bytes, structure),
+                _ => continue,
+            };
+            if let Some(first) = matching.first() {
+                if matching.iter().all(|sig| sig.name == first.name) {
+                    identified.insert(offset, first.name());
+                }
+            }
+        }
+        identified
+    }
+
+    /// Serializes the signatures into a binary representation.
+    ///
+    /// The result can be loaded again with [`SignatureSet::from_bytes`].
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = SIGNATURES_MAGIC.to_vec();
+        out.extend(SIGNATURES_VERSION.to_le_bytes());
+        out.extend((self.signatures.len() as u64).to_le_bytes());
+        for signature in &self.signatures {
+            encode_string(&signature.name, &mut out);
+            out.extend(signature.structure.to_le_bytes());
+            out.extend((signature.pattern.len() as u32).to_le_bytes());
+            out.extend(&signature.pattern);
+            out.extend(signature.mask.iter().map(|&relevant| relevant as u8));
+        }
+        out
+    }
+
+    /// Loads the signatures previously serialized with [`SignatureSet::to_bytes`].
+    ///
+    /// This method returns [`std::io::Error`] in case of malformed input or if the data was
+    /// written by a version of this library using a different format.
+    pub fn from_bytes(data: &[u8]) -> Result<SignatureSet, io::Error> {
+        let malformed = || io::Error::new(ErrorKind::InvalidData, "malformed signatures");
+        let mut reader = Reader::new(data);
+        if reader.bytes(SIGNATURES_MAGIC.len()) != Some(SIGNATURES_MAGIC) {
+            return Err(malformed());
+        }
+        if reader.u32() != Some(SIGNATURES_VERSION) {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "unsupported signatures format version",
+            ));
+        }
+        let mut set = SignatureSet::new();
+        let len = reader.u64().ok_or_else(malformed)?;
+        for _ in 0..len {
+            let name = reader.string().ok_or_else(malformed)?;
+            let structure = reader.u64().ok_or_else(malformed)?;
+            let pattern_len = reader.u32().ok_or_else(malformed)? as usize;
+            let pattern = reader.bytes(pattern_len).ok_or_else(malformed)?.to_vec();
+            let mask = reader
+                .bytes(pattern_len)
+                .ok_or_else(malformed)?
+                .iter()
+                .map(|relevant| match relevant {
+                    0 => Some(false),
+                    1 => Some(true),
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(malformed)?;
+            set.insert(Signature::new(&name, pattern, mask, structure));
+        }
+        if reader.is_empty() {
+            Ok(set)
+        } else {
+            Err(malformed())
+        }
+    }
+}
+
+// first bytes of the function starting at the given offset, or None if too short.
+fn function_bytes<'a>(binary: &'a Binary, cfg: &CFG, offset: u64) -> Option<&'a [u8]> {
+    let size = cfg
+        .metadata()
+        .map_or(PATTERN_LEN as u64, |meta| meta.size());
+    let len = (size as usize).min(PATTERN_LEN);
+    if len < MIN_PATTERN_LEN {
+        None
+    } else {
+        binary.memory()?.read(offset, len)
+    }
+}
+
+// mask of the given amount of bytes starting at the given offset, false for the bytes patched by
+// a relocation.
+fn relocation_mask(binary: &Binary, cfg: &CFG, offset: u64, len: usize) -> Vec<bool> {
+    let mut mask = vec![true; len];
+    let end = offset + len as u64;
+    for bb in cfg.blocks_sorted() {
+        let calls = cfg
+            .calls(bb)
+            .iter()
+            .map(|call| (call.offset(), call.length()));
+        let references = cfg
+            .references(bb)
+            .iter()
+            .map(|reference| (reference.offset(), reference.length()));
+        for (start, length) in calls.chain(references) {
+            if let Some(reloc) = Relocation::patching(binary.relocations(), start, length) {
+                let from = reloc.address().clamp(offset, end) - offset;
+                let to = start.saturating_add(length).clamp(offset, end) - offset;
+                mask[from as usize..to as usize].fill(false);
+            }
+        }
+    }
+    mask
+}
+
+fn structural_hash(cfg: &CFG) -> Option<u64> {
+    CFS::new(cfg)
+        .ok()?
+        .get_tree()
+        .map(|tree| tree.structural_hash())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::{Signature, SignatureSet, CFG};
+    use crate::disasm::{
+        Architecture, Binary, Disassembler, Endianness, Function, MemoryImage, Relocation,
+        Statement, StatementFamily,
+    };
+
+    // a single function, `memcpy`, calling another one.
+    struct MockLibrary {
+        base: u64,
+        bytes: Vec<u8>,
+        relocations: Vec<Relocation>,
+    }
+
+    impl MockLibrary {
+        fn new(base: u64, call: [u8; 4], relocations: Vec<Relocation>) -> MockLibrary {
+            let mut bytes = vec![0x74, 0x06, 0xE8];
+            bytes.extend(call);
+            bytes.extend([0x90, 0xC3]);
+            MockLibrary {
+                base,
+                bytes,
+                relocations,
+            }
+        }
+    }
+
+    impl Disassembler for MockLibrary {
+        async fn functions(&mut self) -> Vec<Function> {
+            vec![Function::new(self.base, "memcpy")]
+        }
+
+        async fn disassemble_function(&mut self, offset: u64) -> Option<CFG> {
+            let stmts = vec![
+                Statement::new(
+                    offset,
+                    StatementFamily::CJMP,
+                    &format!("je {:#x}", offset + 0x8),
+                ),
+                Statement::new(offset + 0x2, StatementFamily::CALL, "call 0x100"),
+                Statement::new(offset + 0x7, StatementFamily::NOP, "nop"),
+                Statement::new(offset + 0x8, StatementFamily::RET, "ret"),
+            ];
+            Some(CFG::new(&stmts, offset + 0x9, Architecture::X86(64)))
+                .filter(|_| offset == self.base)
+        }
+
+        async fn architecture(&mut self) -> Option<Architecture> {
+            Some(Architecture::X86(64))
+        }
+
+        async fn relocations(&mut self) -> Vec<Relocation> {
+            self.relocations.clone()
+        }
+
+        async fn memory(&mut self) -> Option<MemoryImage> {
+            let memory = MemoryImage::new(Endianness::Little);
+            Some(memory.with_segment(self.base, self.bytes.clone()))
+        }
+    }
+
+    #[tokio::test]
+    async fn identify() {
+        let reloc = Relocation::new(0x3, "memmove");
+        let mut mock = MockLibrary::new(0x0, [0; 4], vec![reloc]);
+        let mut library = Binary::extract(&mut mock).await;
+        // without the memory the bytes of the functions are not known
+        assert!(SignatureSet::from_binary(&library).is_empty());
+        library.load_memory(&mut mock).await;
+        let signatures = SignatureSet::from_binary(&library);
+        assert_eq!(signatures.len(), 1);
+        let signature = signatures.iter().next().unwrap();
+        assert_eq!(signature.name(), "memcpy");
+        assert_eq!(signature.pattern().len(), 9);
+        assert_eq!(
+            signature
+                .mask()
+                .iter()
+                .filter(|relevant| !**relevant)
+                .count(),
+            4
+        );
+        let mut mock = MockLibrary::new(0x1000, [0x11, 0x22, 0x33, 0x44], Vec::new());
+        let mut target = Binary::extract(&mut mock).await;
+        target.load_memory(&mut mock).await;
+        let identified = signatures.identify(&target);
+        assert_eq!(identified.get(&0x1000), Some(&"memcpy"));
+        let mut changed = MockLibrary::new(0x1000, [0; 4], Vec::new());
+        changed.bytes[7] = 0xCC;
+        let mut binary = Binary::extract(&mut changed).await;
+        binary.load_memory(&mut changed).await;
+        let changed = binary;
+        assert!(signatures.identify(&changed).is_empty());
+    }
+
+    #[test]
+    fn masked_match() {
+        let signature = Signature::new("f", vec![1, 2, 3, 4], vec![true, false], 0xAB);
+        assert_eq!(signature.mask(), &[true, false, true, true]);
+        assert!(signature.matches(&[1, 9, 3, 4], 0xAB));
+        assert!(signature.matches(&[1, 2, 3, 4, 5], 0xAB));
+        assert!(!signature.matches(&[1, 2, 3, 5], 0xAB));
+        assert!(!signature.matches(&[1, 2, 3], 0xAB));
+        assert!(!signature.matches(&[1, 2, 3, 4], 0xAC));
+    }
+
+    #[test]
+    fn duplicates() {
+        let mut set = SignatureSet::new();
+        set.insert(Signature::new("f", vec![1, 2], vec![], 1));
+        set.insert(Signature::new("f", vec![1, 2], vec![], 1));
+        set.insert(Signature::new("g", vec![1, 2], vec![], 1));
+        assert_eq!(set.len(), 2);
+        assert_eq!(set.matching(&[1, 2], 1).len(), 2);
+        let mut other = SignatureSet::new();
+        other.insert(Signature::new("h", vec![3], vec![], 2));
+        set.merge(other);
+        assert_eq!(set.len(), 3);
+    }
+
+    #[test]
+    fn serialization() {
+        let mut set = SignatureSet::new();
+        set.insert(Signature::new(
+            "memcpy",
+            vec![0x55, 0xE8, 0, 0],
+            vec![true, true],
+            7,
+        ));
+        set.insert(Signature::new("strlen", vec![0x31, 0xC0], vec![], 9));
+        let loaded = SignatureSet::from_bytes(&set.to_bytes()).unwrap();
+        assert_eq!(loaded, set);
+        let mut data = set.to_bytes();
+        data.pop();
+        assert!(SignatureSet::from_bytes(&data).is_err());
+        assert!(SignatureSet::from_bytes(b"BCCS").is_err());
+    }
+}
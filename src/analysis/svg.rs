@@ -0,0 +1,366 @@
+use crate::analysis::{BasicBlock, Graph, CFG};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+// the labels are written in a monospace font, so their width depends only on their length.
+const FONT_SIZE: i64 = 12;
+const CHAR_WIDTH: i64 = 7;
+const PADDING: i64 = 8;
+const MARGIN: i64 = 10;
+const NODE_HEIGHT: i64 = 24;
+const NODE_GAP: i64 = 16;
+const LAYER_GAP: i64 = 36;
+// horizontal distance of the curve of an edge reaching a block in the same or a previous layer.
+const BACK_EDGE_BEND: i64 = 32;
+const ARROW_LENGTH: f64 = 6.0;
+#[cfg(feature = "disasm")]
+const TITLE_HEIGHT: i64 = 20;
+
+/// Colours of a block in a rendered [`CFG`].
+pub(super) struct BlockStyle {
+    pub(super) fill: &'static str,
+    pub(super) border: &'static str,
+    /// Draws a thicker border.
+    pub(super) highlight: bool,
+}
+
+impl Default for BlockStyle {
+    fn default() -> Self {
+        BlockStyle {
+            fill: "white",
+            border: "black",
+            highlight: false,
+        }
+    }
+}
+
+/// A [`CFG`] laid out in layers, from the root at the top to the exits at the bottom.
+///
+/// Each block is placed one layer below its lowest predecessor, ignoring the edges closing a
+/// loop, and the blocks of a layer are sorted by the position of their predecessors. The edges
+/// going down are straight lines, the others are dashed curves on the right of the blocks.
+pub(super) struct SvgGraph {
+    width: i64,
+    height: i64,
+    // elements of the graph, relative to the top left corner
+    body: String,
+}
+
+impl SvgGraph {
+    /// Lays out the given CFG, drawing each block with the given style.
+    pub(super) fn new<F: Fn(&BasicBlock) -> BlockStyle>(cfg: &CFG, style: F) -> SvgGraph {
+        let (order, back_edges) = depth_first(cfg);
+        let mut rank = HashMap::new();
+        for bb in &order {
+            let current = *rank.entry(*bb).or_insert(0);
+            for next in cfg.neighbours(bb) {
+                if !back_edges.contains(&(*bb, *next)) {
+                    let next_rank = rank.entry(*next).or_insert(0);
+                    *next_rank = (*next_rank).max(current + 1);
+                }
+            }
+        }
+        let mut layers = Vec::<Vec<BasicBlock>>::new();
+        for bb in &order {
+            let index = rank[bb];
+            if layers.len() <= index {
+                layers.resize(index + 1, Vec::new());
+            }
+            layers[index].push(*bb);
+        }
+        let mut preds = HashMap::<BasicBlock, Vec<BasicBlock>>::new();
+        for bb in &order {
+            for next in cfg.neighbours(bb) {
+                preds.entry(*next).or_default().push(*bb);
+            }
+        }
+        // relative position of the blocks already placed, between 0 and 1
+        let mut position = HashMap::<BasicBlock, f64>::new();
+        for layer in layers.iter_mut() {
+            let key = |bb: &BasicBlock| {
+                let placed = preds
+                    .get(bb)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|pred| position.get(pred))
+                    .collect::<Vec<_>>();
+                match placed.len() {
+                    0 => f64::INFINITY,
+                    len => placed.into_iter().sum::<f64>() / len as f64,
+                }
+            };
+            layer.sort_by(|a, b| key(a).total_cmp(&key(b)).then(a.cmp(b)));
+            let len = layer.len() as f64;
+            for (index, bb) in layer.iter().enumerate() {
+                position.insert(*bb, (index as f64 + 0.5) / len);
+            }
+        }
+        let label_width = |bb: &BasicBlock| bb.to_string().len() as i64 * CHAR_WIDTH + 2 * PADDING;
+        let layer_widths = layers
+            .iter()
+            .map(|layer| {
+                layer.iter().map(label_width).sum::<i64>()
+                    + NODE_GAP * (layer.len() as i64 - 1).max(0)
+            })
+            .collect::<Vec<_>>();
+        let content_width = layer_widths.iter().copied().max().unwrap_or(0);
+        // top left corner and width of each block
+        let mut boxes = HashMap::new();
+        for (index, (layer, layer_width)) in layers.iter().zip(&layer_widths).enumerate() {
+            let mut x = MARGIN + (content_width - layer_width) / 2;
+            let y = MARGIN + index as i64 * (NODE_HEIGHT + LAYER_GAP);
+            for bb in layer {
+                let width = label_width(bb);
+                boxes.insert(*bb, (x, y, width));
+                x += width + NODE_GAP;
+            }
+        }
+        let mut body = String::new();
+        for bb in &order {
+            let (sx, sy, sw) = boxes[bb];
+            for next in cfg.neighbours(bb) {
+                let (dx, dy, dw) = boxes[next];
+                if rank[next] > rank[bb] {
+                    let (x1, y1) = (sx + sw / 2, sy + NODE_HEIGHT);
+                    let (x2, y2) = (dx + dw / 2, dy);
+                    writeln!(
+                        body,
+                        "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"black\"/>",
+                        x1, y1, x2, y2
+                    )
+                    .unwrap();
+                    body.push_str(&arrow((x1, y1), (x2, y2)));
+                } else {
+                    let (x1, y1) = (sx + sw, sy + NODE_HEIGHT * 2 / 3);
+                    let (x2, y2) = (dx + dw, dy + NODE_HEIGHT / 3);
+                    let bend = x1.max(x2) + BACK_EDGE_BEND;
+                    writeln!(
+                        body,
+                        "<path d=\"M{} {} C{} {} {} {} {} {}\" fill=\"none\" stroke=\"black\" \
+                         stroke-dasharray=\"4 2\"/>",
+                        x1, y1, bend, y1, bend, y2, x2, y2
+                    )
+                    .unwrap();
+                    body.push_str(&arrow((bend, y2), (x2, y2)));
+                }
+            }
+        }
+        for bb in &order {
+            let (x, y, width) = boxes[bb];
+            let style = style(bb);
+            writeln!(
+                body,
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\" stroke=\"{}\" \
+                 stroke-width=\"{}\" data-offset=\"{:#x}\"/>",
+                x,
+                y,
+                width,
+                NODE_HEIGHT,
+                style.fill,
+                style.border,
+                if style.highlight { 3 } else { 1 },
+                bb.offset
+            )
+            .unwrap();
+            writeln!(
+                body,
+                "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\" font-family=\"monospace\" \
+                 font-size=\"{}\">{}</text>",
+                x + width / 2,
+                y + NODE_HEIGHT / 2 + FONT_SIZE / 3,
+                FONT_SIZE,
+                bb
+            )
+            .unwrap();
+        }
+        let width = 2 * MARGIN + content_width + BACK_EDGE_BEND;
+        let height = match layers.len() as i64 {
+            0 => 2 * MARGIN,
+            count => 2 * MARGIN + count * (NODE_HEIGHT + LAYER_GAP) - LAYER_GAP,
+        };
+        SvgGraph {
+            width,
+            height,
+            body,
+        }
+    }
+
+    /// Returns the graph as a standalone SVG document.
+    pub(super) fn to_svg(&self) -> String {
+        document(self.width, self.height, &self.body)
+    }
+}
+
+/// Returns the given graphs side by side as a single SVG document, each with its title on top.
+#[cfg(feature = "disasm")]
+pub(super) fn side_by_side(graphs: &[(&str, &SvgGraph)]) -> String {
+    let mut body = String::new();
+    let mut x = 0;
+    for (title, graph) in graphs {
+        writeln!(
+            body,
+            "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\" font-family=\"sans-serif\" \
+             font-size=\"{}\" font-weight=\"bold\">{}</text>",
+            x + graph.width / 2,
+            TITLE_HEIGHT - 4,
+            FONT_SIZE + 2,
+            escape(title)
+        )
+        .unwrap();
+        writeln!(
+            body,
+            "<g transform=\"translate({},{})\">\n{}</g>",
+            x, TITLE_HEIGHT, graph.body
+        )
+        .unwrap();
+        x += graph.width;
+    }
+    let height = graphs
+        .iter()
+        .map(|(_, graph)| graph.height)
+        .max()
+        .unwrap_or(0);
+    document(x, height + TITLE_HEIGHT, &body)
+}
+
+impl CFG {
+    /// Returns the CFG as an SVG image, with a box for each basic block labelled with its address
+    /// range.
+    ///
+    /// The blocks are laid out in layers, from the root at the top to the exits at the bottom.
+    /// The edges closing a loop, and the ones reaching a block in the same layer, are dashed.
+    /// Unlike [`CFG::to_dot`], the image does not require Graphviz to be rendered.
+    /// # Examples
+    /// Basic usage:
+    /// ```
+    /// use bincc::analysis::CFG;
+    /// use bincc::disasm::{Architecture, Statement, StatementFamily};
+    ///
+    /// let stmts = vec![
+    ///     Statement::new(0x10, StatementFamily::CJMP, "je 0x14"),
+    ///     Statement::new(0x12, StatementFamily::MOV, "mov eax, 0"),
+    ///     Statement::new(0x14, StatementFamily::RET, "ret"),
+    /// ];
+    /// let cfg = CFG::new(&stmts, 0x15, Architecture::X86(64));
+    /// let svg = cfg.to_svg();
+    ///
+    /// assert!(svg.starts_with("<svg"));
+    /// assert!(svg.contains("0x12..0x14"));
+    /// ```
+    pub fn to_svg(&self) -> String {
+        SvgGraph::new(self, |_| BlockStyle::default()).to_svg()
+    }
+}
+
+/// Escapes the characters with a special meaning in HTML and SVG.
+pub(super) fn escape(string: &str) -> String {
+    string
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn document(width: i64, height: i64, body: &str) -> String {
+    format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+         viewBox=\"0 0 {} {}\">\n{}</svg>\n",
+        width, height, width, height, body
+    )
+}
+
+// head of an arrow pointing to `to`, in the direction of the segment from `from`.
+fn arrow(from: (i64, i64), to: (i64, i64)) -> String {
+    let (dx, dy) = ((to.0 - from.0) as f64, (to.1 - from.1) as f64);
+    let length = dx.hypot(dy).max(f64::EPSILON);
+    let (ux, uy) = (dx / length, dy / length);
+    let (tx, ty) = (to.0 as f64, to.1 as f64);
+    let (bx, by) = (tx - ux * ARROW_LENGTH, ty - uy * ARROW_LENGTH);
+    let (nx, ny) = (-uy * ARROW_LENGTH / 2.0, ux * ARROW_LENGTH / 2.0);
+    format!(
+        "<polygon points=\"{:.1},{:.1} {:.1},{:.1} {:.1},{:.1}\"/>\n",
+        tx,
+        ty,
+        bx + nx,
+        by + ny,
+        bx - nx,
+        by - ny
+    )
+}
+
+// blocks in reverse postorder, visiting first the root and then the unreachable blocks, and the
+// edges reaching a block still being visited, that close a loop.
+fn depth_first(cfg: &CFG) -> (Vec<BasicBlock>, HashSet<(BasicBlock, BasicBlock)>) {
+    let mut postorder = Vec::new();
+    let mut back_edges = HashSet::new();
+    // false while the block is being visited, true when all its successors have been visited
+    let mut visited = HashMap::new();
+    let starts = cfg
+        .root()
+        .into_iter()
+        .chain(cfg.blocks_sorted())
+        .copied()
+        .collect::<Vec<_>>();
+    for start in starts {
+        if visited.contains_key(&start) {
+            continue;
+        }
+        visited.insert(start, false);
+        let mut stack = vec![(start, 0)];
+        while let Some((bb, index)) = stack.pop() {
+            match cfg.neighbours(&bb).get(index) {
+                Some(next) => {
+                    stack.push((bb, index + 1));
+                    match visited.get(next) {
+                        Some(false) => {
+                            back_edges.insert((bb, *next));
+                        }
+                        Some(true) => {}
+                        None => {
+                            visited.insert(*next, false);
+                            stack.push((*next, 0));
+                        }
+                    }
+                }
+                None => {
+                    visited.insert(bb, true);
+                    postorder.push(bb);
+                }
+            }
+        }
+    }
+    postorder.reverse();
+    (postorder, back_edges)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::{Graph, CFG};
+    use crate::disasm::{Architecture, Statement, StatementFamily};
+
+    #[test]
+    fn layers() {
+        // while loop: 0x10 -> 0x12 -> 0x10, 0x10 -> 0x14
+        let stmts = vec![
+            Statement::new(0x10, StatementFamily::CJMP, "je 0x14"),
+            Statement::new(0x12, StatementFamily::JMP, "jmp 0x10"),
+            Statement::new(0x14, StatementFamily::RET, "ret"),
+        ];
+        let cfg = CFG::new(&stmts, 0x15, Architecture::X86(64));
+        let svg = cfg.to_svg();
+        assert_eq!(svg.matches("<rect").count(), cfg.len());
+        assert_eq!(svg.matches("<polygon").count(), 3);
+        // the back edge of the loop is the only curve
+        assert_eq!(svg.matches("<path").count(), 1);
+        assert_eq!(svg.matches("<line").count(), 2);
+        let header = svg.lines().next().unwrap();
+        assert!(header.contains("viewBox=\"0 0 "));
+    }
+
+    #[test]
+    fn empty() {
+        let svg = CFG::default().to_svg();
+        assert!(!svg.contains("<rect"));
+        assert!(svg.ends_with("</svg>\n"));
+    }
+}
@@ -0,0 +1,85 @@
+use crate::analysis::CFG;
+use crate::disasm::{Architecture, Binary, Disassembler, Function, Statement, StatementFamily};
+
+// functions made of an if-then whose body has the given length, or a sequence if the length is 0.
+// functions in `loads` read from memory instead of writing a constant, and each (caller, callee)
+// pair in `calls` adds a call at the beginning of the caller.
+pub(crate) struct MockBinary {
+    pub(crate) arch: Architecture,
+    pub(crate) functions: Vec<(u64, &'static str, u64)>,
+    pub(crate) loads: Vec<u64>,
+    pub(crate) calls: Vec<(u64, u64)>,
+}
+
+impl Disassembler for MockBinary {
+    async fn functions(&mut self) -> Vec<Function> {
+        self.functions
+            .iter()
+            .map(|(offset, name, _)| Function::new(*offset, name))
+            .collect()
+    }
+
+    async fn disassemble_function(&mut self, offset: u64) -> Option<CFG> {
+        let stmts = self.statements(offset)?;
+        let end = stmts.last()?.get_offset() + 1;
+        Some(CFG::new(&stmts, end, Architecture::X86(64)))
+    }
+
+    async fn architecture(&mut self) -> Option<Architecture> {
+        Some(self.arch)
+    }
+}
+
+impl MockBinary {
+    // x86 binary with the given functions, without loads and calls.
+    pub(crate) fn new(functions: Vec<(u64, &'static str, u64)>) -> MockBinary {
+        MockBinary {
+            arch: Architecture::X86(64),
+            functions,
+            loads: Vec::new(),
+            calls: Vec::new(),
+        }
+    }
+
+    pub(crate) fn statements(&self, offset: u64) -> Option<Vec<Statement>> {
+        let (_, _, len) = self.functions.iter().find(|func| func.0 == offset)?;
+        let mut stmts = self
+            .calls
+            .iter()
+            .filter(|(caller, _)| *caller == offset)
+            .enumerate()
+            .map(|(i, (_, callee))| {
+                let call = format!("call {:#x}", callee);
+                Statement::new(offset + 5 * i as u64, StatementFamily::CALL, &call)
+            })
+            .collect::<Vec<_>>();
+        let start = offset + 5 * stmts.len() as u64;
+        if *len == 0 {
+            stmts.extend([
+                Statement::new(
+                    start,
+                    StatementFamily::JMP,
+                    &format!("jmp {:#x}", start + 2),
+                ),
+                Statement::new(start + 2, StatementFamily::RET, "ret"),
+            ]);
+        } else {
+            let end = start + 2 + len;
+            let body = match self.loads.contains(&offset) {
+                true => "mov eax, [rbx]",
+                false => "mov eax, 0",
+            };
+            stmts.extend([
+                Statement::new(start, StatementFamily::CJMP, &format!("je {:#x}", end)),
+                Statement::new(start + 2, StatementFamily::MOV, body),
+                Statement::new(end, StatementFamily::RET, "ret"),
+            ]);
+        }
+        Some(stmts)
+    }
+}
+
+// extracts the x86 binary with the given functions.
+pub(crate) async fn binary(functions: Vec<(u64, &'static str, u64)>) -> Binary {
+    Binary::extract(&mut MockBinary::new(functions)).await
+}
@@ -0,0 +1,163 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Time spent in each phase of the analysis of a function, to find the functions dominating the
+/// runtime of a large binary.
+///
+/// The structuring is timed only if requested with
+/// [`CfsBuilder::record_timings`](crate::analysis::CfsBuilder::record_timings), and the timings
+/// are returned by [`CFS::timings`](crate::analysis::CFS::timings). The extraction and the
+/// comparison are timed by the functions performing them, and are [`None`] in the timings of a
+/// [`CFS`](crate::analysis::CFS).
+/// # Examples
+/// Basic usage:
+/// ```
+/// use bincc::analysis::{CfsBuilder, CFG};
+/// use bincc::disasm::{Architecture, Statement, StatementFamily};
+///
+/// let stmts = vec![
+///     Statement::new(0x10, StatementFamily::CJMP, "je 0x14"),
+///     Statement::new(0x12, StatementFamily::MOV, "mov eax, 0"),
+///     Statement::new(0x14, StatementFamily::RET, "ret"),
+/// ];
+/// let cfg = CFG::new(&stmts, 0x15, Architecture::X86(64));
+/// let cfs = CfsBuilder::new().record_timings().build(&cfg).unwrap();
+/// let timings = cfs.timings().unwrap();
+///
+/// assert!(timings.structuring() >= timings.denaturation());
+/// assert!(timings.reductions().iter().any(|(name, _)| *name == "If-Then"));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Timings {
+    pub(super) extraction: Option<Duration>,
+    pub(super) denaturation: Duration,
+    // time spent attempting each reduction, by name
+    pub(super) reductions: BTreeMap<String, Duration>,
+    pub(super) structuring: Duration,
+    pub(super) comparison: Option<Duration>,
+}
+
+impl Timings {
+    /// Creates empty timings, where every phase took no time.
+    pub fn new() -> Timings {
+        Timings::default()
+    }
+
+    /// Sets the time spent extracting the [`CFG`](crate::analysis::CFG) of the function.
+    pub fn with_extraction(mut self, extraction: Duration) -> Timings {
+        self.extraction = Some(extraction);
+        self
+    }
+
+    /// Returns the time spent extracting the [`CFG`](crate::analysis::CFG) of the function, if
+    /// known.
+    pub fn extraction(&self) -> Option<Duration> {
+        self.extraction
+    }
+
+    /// Returns the time spent removing the extra exits of the natural loops before the
+    /// reduction.
+    pub fn denaturation(&self) -> Duration {
+        self.denaturation
+    }
+
+    /// Returns the time spent attempting each reduction, from the slowest to the fastest.
+    ///
+    /// Reductions are named as in [`CFS::trace`](crate::analysis::CFS::trace), and the time
+    /// includes both the attempts that matched and the ones that did not. The duplication of the
+    /// nodes of irreducible regions is reported as `Duplicate`.
+    pub fn reductions(&self) -> Vec<(&str, Duration)> {
+        let mut reductions = self
+            .reductions
+            .iter()
+            .map(|(name, elapsed)| (name.as_str(), *elapsed))
+            .collect::<Vec<_>>();
+        reductions.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        reductions
+    }
+
+    /// Returns the whole time spent structuring the function, including the denaturation and
+    /// the reductions.
+    pub fn structuring(&self) -> Duration {
+        self.structuring
+    }
+
+    /// Returns the time spent comparing the function with its counterpart, if compared.
+    pub fn comparison(&self) -> Option<Duration> {
+        self.comparison
+    }
+
+    /// Returns the time spent in every phase.
+    pub fn total(&self) -> Duration {
+        self.extraction.unwrap_or_default() + self.structuring + self.comparison.unwrap_or_default()
+    }
+
+    /// Adds the time spent in each phase by another analysis, like the analysis of the other
+    /// function of a pair.
+    ///
+    /// A phase is known if it is known in any of the two timings.
+    pub fn add(&mut self, other: &Timings) {
+        let sum = |a: Option<Duration>, b: Option<Duration>| match (a, b) {
+            (None, None) => None,
+            (a, b) => Some(a.unwrap_or_default() + b.unwrap_or_default()),
+        };
+        self.extraction = sum(self.extraction, other.extraction);
+        self.denaturation += other.denaturation;
+        for (name, elapsed) in &other.reductions {
+            *self.reductions.entry(name.clone()).or_default() += *elapsed;
+        }
+        self.structuring += other.structuring;
+        self.comparison = sum(self.comparison, other.comparison);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::{CfsBuilder, Timings, CFG};
+    use crate::disasm::{Architecture, Statement, StatementFamily};
+    use std::time::Duration;
+
+    #[test]
+    fn optional() {
+        let stmts = vec![Statement::new(0x10, StatementFamily::RET, "ret")];
+        let cfg = CFG::new(&stmts, 0x11, Architecture::X86(64));
+        assert!(CfsBuilder::new().build(&cfg).unwrap().timings().is_none());
+        let timings = CfsBuilder::new()
+            .record_timings()
+            .build(&cfg)
+            .unwrap()
+            .timings()
+            .cloned()
+            .unwrap();
+        // a single block is not reduced
+        assert!(timings.reductions().is_empty());
+        assert_eq!(timings.extraction(), None);
+        assert_eq!(timings.comparison(), None);
+    }
+
+    #[test]
+    fn add() {
+        let mut a = Timings::new().with_extraction(Duration::from_millis(2));
+        a.reductions
+            .insert("Loop".to_string(), Duration::from_millis(1));
+        a.structuring = Duration::from_millis(3);
+        let mut b = Timings::new();
+        b.reductions
+            .insert("Loop".to_string(), Duration::from_millis(4));
+        b.reductions
+            .insert("If-Then".to_string(), Duration::from_millis(1));
+        b.structuring = Duration::from_millis(5);
+        b.comparison = Some(Duration::from_millis(1));
+        a.add(&b);
+        assert_eq!(a.extraction(), Some(Duration::from_millis(2)));
+        assert_eq!(
+            a.reductions(),
+            vec![
+                ("Loop", Duration::from_millis(5)),
+                ("If-Then", Duration::from_millis(1))
+            ]
+        );
+        assert_eq!(a.structuring(), Duration::from_millis(8));
+        assert_eq!(a.total(), Duration::from_millis(11));
+    }
+}
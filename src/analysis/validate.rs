@@ -0,0 +1,282 @@
+use crate::analysis::blocks::StructureBlock;
+use crate::analysis::{BasicBlock, BlockType};
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+/// Inconsistency found by [`StructureBlock::validate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationError {
+    /// The depth recorded in the block at the given offset differs from the one of its children.
+    Depth {
+        /// Offset of the inconsistent block.
+        offset: u64,
+        /// Depth computed from the children of the block.
+        expected: u32,
+        /// Depth recorded in the block.
+        found: u32,
+    },
+    /// The block at the given offset has an amount of children not allowed by its type.
+    Arity {
+        /// Offset of the inconsistent block.
+        offset: u64,
+        /// Type of the inconsistent block.
+        block_type: BlockType,
+        /// Amount of children of the block, excluding the markers.
+        children: usize,
+    },
+    /// The basic block appears more than once in the tree, outside a [`BlockType::Duplicate`].
+    Repeated(BasicBlock),
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValidationError::Depth {
+                offset,
+                expected,
+                found,
+            } => write!(
+                f,
+                "block at {:#x} has depth {} instead of {}",
+                offset, found, expected
+            ),
+            ValidationError::Arity {
+                offset,
+                block_type,
+                children,
+            } => write!(
+                f,
+                "{} block at {:#x} can not have {} children",
+                block_type, offset, children
+            ),
+            ValidationError::Repeated(bb) => write!(f, "basic block {} appears twice", bb),
+        }
+    }
+}
+
+impl Error for ValidationError {}
+
+impl StructureBlock {
+    /// Checks the invariants of this tree, returning the first inconsistency found.
+    ///
+    /// The trees created by a [`CFS`](crate::analysis::CFS) always satisfy these invariants, so
+    /// this method is mostly useful to check the trees returned by custom reductions or rewriters:
+    /// - the depth of every block is consistent with its children, as computed by
+    ///   [`NestedBlock::new`](crate::analysis::NestedBlock::new);
+    /// - every block has an amount of children allowed by its type: for example, a
+    ///   [`BlockType::IfThenElse`] has exactly three children, markers have none and every other
+    ///   structure has at least one, except for a [`BlockType::Custom`], which is not checked;
+    /// - no basic block appears twice in the tree, except for the copies inside a
+    ///   [`BlockType::Duplicate`].
+    ///
+    /// Markers are not counted as children when checking the arity.
+    /// # Examples
+    /// Basic usage:
+    /// ```
+    /// use bincc::analysis::{BasicBlock, BlockType, NestedBlock, StructureBlock, ValidationError};
+    /// use std::sync::Arc;
+    ///
+    /// let bb0 = StructureBlock::from(BasicBlock { offset: 0x10, length: 4 });
+    /// let bb1 = StructureBlock::from(BasicBlock { offset: 0x14, length: 8 });
+    /// let ifthen = NestedBlock::new(BlockType::IfThen, vec![bb0.clone(), bb1]);
+    /// assert!(StructureBlock::from(Arc::new(ifthen)).validate().is_ok());
+    ///
+    /// let ifelse = NestedBlock::new(BlockType::IfThenElse, vec![bb0.clone(), bb0]);
+    /// assert_eq!(
+    ///     StructureBlock::from(Arc::new(ifelse)).validate(),
+    ///     Err(ValidationError::Arity {
+    ///         offset: 0x10,
+    ///         block_type: BlockType::IfThenElse,
+    ///         children: 2
+    ///     })
+    /// );
+    /// ```
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let mut seen = HashSet::new();
+        validate_rec(self, &mut seen, false)
+    }
+}
+
+// the copies inside a Duplicate are not added to the set of seen basic blocks.
+fn validate_rec(
+    node: &StructureBlock,
+    seen: &mut HashSet<BasicBlock>,
+    copy: bool,
+) -> Result<(), ValidationError> {
+    let nb = match node {
+        StructureBlock::Basic(bb) => {
+            return if copy || seen.insert(*bb) {
+                Ok(())
+            } else {
+                Err(ValidationError::Repeated(*bb))
+            };
+        }
+        StructureBlock::Nested(nb) => nb,
+    };
+    let children = nb.content.iter().filter(|child| !is_marker(child)).count();
+    let allowed = match nb.block_type {
+        BlockType::Basic => false,
+        BlockType::Break | BlockType::Continue | BlockType::Goto | BlockType::AbnormalEntry => {
+            nb.content.is_empty()
+        }
+        BlockType::Duplicate | BlockType::Conditional => children == 1,
+        BlockType::Return | BlockType::Unreachable => {
+            matches!(nb.content.as_slice(), [StructureBlock::Basic(_)])
+        }
+        BlockType::IfThen | BlockType::Guard => children == 2,
+        BlockType::IfThenElse => children == 3,
+        BlockType::While => children == 2,
+        BlockType::SwitchLoop => children == 1,
+        BlockType::DoWhile => children == 2 || children == 3,
+        BlockType::Switch | BlockType::Condition | BlockType::IfChain => children >= 2,
+        // a self loop can also contain the blocks of a looping sequence
+        BlockType::SelfLooping
+        | BlockType::Sequence
+        | BlockType::ProperInterval
+        | BlockType::ImproperInterval
+        | BlockType::ImproperRegion => children >= 1,
+        BlockType::Custom(_) => true,
+    };
+    if !allowed {
+        return Err(ValidationError::Arity {
+            offset: nb.offset,
+            block_type: nb.block_type,
+            children,
+        });
+    }
+    let max_depth = nb.content.iter().map(|child| child.depth()).max();
+    let expected = match nb.block_type {
+        BlockType::Break | BlockType::Continue | BlockType::Goto | BlockType::AbnormalEntry => 0,
+        BlockType::Duplicate | BlockType::Return | BlockType::Unreachable => max_depth.unwrap_or(0),
+        _ => max_depth.unwrap_or(0) + 1,
+    };
+    if nb.depth != expected {
+        return Err(ValidationError::Depth {
+            offset: nb.offset,
+            expected,
+            found: nb.depth,
+        });
+    }
+    let copy = copy || nb.block_type == BlockType::Duplicate;
+    for child in &nb.content {
+        validate_rec(child, seen, copy)?;
+    }
+    Ok(())
+}
+
+fn is_marker(node: &StructureBlock) -> bool {
+    matches!(
+        node.block_type(),
+        BlockType::Break | BlockType::Continue | BlockType::Goto | BlockType::AbnormalEntry
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::{
+        BasicBlock, BlockType, CfsBuilder, NestedBlock, StructureBlock, ValidationError, CFG, CFS,
+    };
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    fn bb(offset: u64) -> StructureBlock {
+        StructureBlock::from(BasicBlock { offset, length: 1 })
+    }
+
+    fn nest(bt: BlockType, children: Vec<StructureBlock>) -> StructureBlock {
+        StructureBlock::from(Arc::new(NestedBlock::new(bt, children)))
+    }
+
+    fn cfg(edges: &[&[u64]]) -> CFG {
+        let bb = |offset| BasicBlock { offset, length: 1 };
+        let edges = edges
+            .iter()
+            .enumerate()
+            .map(|(src, dsts)| (bb(src as u64), dsts.iter().map(|dst| bb(*dst)).collect()))
+            .collect::<HashMap<_, _>>();
+        CFG {
+            root: Some(bb(0)),
+            edges,
+            info: HashMap::new(),
+            metadata: None,
+        }
+    }
+
+    #[test]
+    fn valid_cfs() {
+        let cfgs = [
+            cfg(&[&[1], &[2, 5], &[3], &[4, 5], &[1], &[]]),
+            cfg(&[&[1, 3], &[2], &[3, 4], &[1], &[]]),
+            cfg(&[&[1, 2], &[1], &[]]),
+            cfg(&[&[1, 2, 3], &[0], &[0], &[]]),
+        ];
+        for cfg in cfgs {
+            assert_eq!(
+                CFS::new(&cfg).unwrap().get_tree().unwrap().validate(),
+                Ok(())
+            );
+            let tree = CfsBuilder::new()
+                .preserve_loops()
+                .build(&cfg)
+                .unwrap()
+                .get_tree();
+            assert_eq!(tree.unwrap().validate(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn invalid_depth() {
+        let mut seq = NestedBlock::new(BlockType::Sequence, vec![bb(0), bb(1)]);
+        seq.depth = 3;
+        assert_eq!(
+            StructureBlock::from(Arc::new(seq)).validate(),
+            Err(ValidationError::Depth {
+                offset: 0,
+                expected: 1,
+                found: 3
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_arity() {
+        let empty = nest(BlockType::Sequence, Vec::new());
+        assert!(matches!(
+            nest(BlockType::IfThen, vec![bb(0), empty]).validate(),
+            Err(ValidationError::Arity {
+                block_type: BlockType::Sequence,
+                children: 0,
+                ..
+            })
+        ));
+        let marker = StructureBlock::from(Arc::new(NestedBlock::marker(
+            BlockType::Break,
+            &BasicBlock {
+                offset: 1,
+                length: 1,
+            },
+        )));
+        let body = nest(BlockType::Sequence, vec![bb(1), bb(2)]);
+        let whileb = nest(BlockType::While, vec![bb(0), body, marker.clone()]);
+        assert_eq!(whileb.validate(), Ok(()));
+        assert!(nest(BlockType::Guard, vec![bb(0), marker])
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn repeated_nodes() {
+        let tree = nest(BlockType::Sequence, vec![bb(0), bb(1), bb(0)]);
+        assert_eq!(
+            tree.validate(),
+            Err(ValidationError::Repeated(BasicBlock {
+                offset: 0,
+                length: 1
+            }))
+        );
+        let copy = StructureBlock::from(Arc::new(NestedBlock::duplicate(&bb(0))));
+        let tree = nest(BlockType::Sequence, vec![bb(0), bb(1), copy]);
+        assert_eq!(tree.validate(), Ok(()));
+    }
+}
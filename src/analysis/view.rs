@@ -0,0 +1,343 @@
+use crate::analysis::patch::align;
+use crate::analysis::svg::{escape, side_by_side, BlockStyle, SvgGraph};
+use crate::analysis::{BasicBlock, BinaryDiff, ComparisonMode, FunctionMatch, Graph, CFG, CFS};
+use crate::disasm::Binary;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+// colours of the paired blocks, assigned in order and repeated if there are more pairs.
+const PALETTE: [&str; 12] = [
+    "#8dd3c7", "#ffffb3", "#bebada", "#fb8072", "#80b1d3", "#fdb462", "#b3de69", "#fccde5",
+    "#d9d9d9", "#bc80bd", "#ccebc5", "#ffed6f",
+];
+// colour of the blocks without a counterpart.
+const UNPAIRED_COLOUR: &str = "white";
+// border of the paired blocks whose content changed.
+const CHANGED_BORDER: &str = "red";
+
+// fill colour of the paired blocks, and true if the pair is unchanged.
+type StyleMap = HashMap<BasicBlock, (&'static str, bool)>;
+
+/// Side-by-side view of two versions of the same function, for reviewing a [`FunctionMatch`].
+///
+/// The basic blocks of the two [`CFG`]s are paired by aligning their structure trees, as done
+/// for the [`PatchedRegion`](crate::analysis::PatchedRegion)s: paired blocks share the same
+/// colour, paired blocks whose content changed have a red border, and blocks without a
+/// counterpart are white.
+/// # Examples
+/// Basic usage:
+/// ```
+/// use bincc::analysis::{ComparisonMode, MatchView, CFG};
+/// use bincc::disasm::{Architecture, Statement, StatementFamily};
+///
+/// let old = vec![
+///     Statement::new(0x10, StatementFamily::CJMP, "je 0x14"),
+///     Statement::new(0x12, StatementFamily::MOV, "mov eax, 0"),
+///     Statement::new(0x14, StatementFamily::RET, "ret"),
+/// ];
+/// let new = vec![
+///     Statement::new(0x10, StatementFamily::CJMP, "je 0x16"),
+///     Statement::new(0x12, StatementFamily::MOV, "mov eax, [rbx]"),
+///     Statement::new(0x16, StatementFamily::RET, "ret"),
+/// ];
+/// let old = CFG::new(&old, 0x15, Architecture::X86(64));
+/// let new = CFG::new(&new, 0x17, Architecture::X86(64));
+/// let view = MatchView::new(&old, &new, ComparisonMode::SameArchitecture);
+///
+/// assert_eq!(view.block_pairs().len(), 3);
+/// assert_eq!(view.block_pairs().iter().filter(|(_, _, same)| !same).count(), 1);
+/// let dot = view.to_dot();
+/// ```
+#[cfg_attr(docsrs, doc(cfg(feature = "disasm")))]
+pub struct MatchView<'a> {
+    old: &'a CFG,
+    new: &'a CFG,
+    old_title: String,
+    new_title: String,
+    // sorted by offset in the old function
+    pairs: Vec<(BasicBlock, BasicBlock, bool)>,
+}
+
+impl<'a> MatchView<'a> {
+    /// Creates the view of the two given functions, comparing their blocks with the signals of
+    /// the given mode.
+    ///
+    /// If any of the two functions can not be structured, no blocks are paired.
+    pub fn new(old: &'a CFG, new: &'a CFG, mode: ComparisonMode) -> MatchView<'a> {
+        let old_tree = CFS::new(old).ok().and_then(|cfs| cfs.get_tree());
+        let new_tree = CFS::new(new).ok().and_then(|cfs| cfs.get_tree());
+        let mut pairs = match (old_tree, new_tree) {
+            (Some(old_tree), Some(new_tree)) => {
+                align((&old_tree, old), (&new_tree, new), mode).blocks
+            }
+            _ => Vec::new(),
+        };
+        // the sink and entry point added by the structuring may not be part of the CFGs
+        let old_blocks = old.blocks_sorted().into_iter().collect::<HashSet<_>>();
+        let new_blocks = new.blocks_sorted().into_iter().collect::<HashSet<_>>();
+        pairs.retain(|(a, b, _)| old_blocks.contains(a) && new_blocks.contains(b));
+        pairs.sort_unstable_by_key(|(bb, _, _)| *bb);
+        MatchView {
+            old,
+            new,
+            old_title: "old".to_string(),
+            new_title: "new".to_string(),
+            pairs,
+        }
+    }
+
+    /// Sets the titles of the two functions, `old` and `new` by default.
+    pub fn with_titles(mut self, old: &str, new: &str) -> MatchView<'a> {
+        self.old_title = old.to_string();
+        self.new_title = new.to_string();
+        self
+    }
+
+    /// Returns the paired blocks of the old and new function, sorted by offset in the old
+    /// function, and true if the content of the two blocks is unchanged.
+    pub fn block_pairs(&self) -> &[(BasicBlock, BasicBlock, bool)] {
+        &self.pairs
+    }
+
+    /// Returns the two functions side by side in Graphviz dot format.
+    ///
+    /// Each function is a cluster, labelled with its title, and each block is labelled with its
+    /// address range.
+    pub fn to_dot(&self) -> String {
+        let (old_style, new_style) = self.styles();
+        let mut dot = "digraph {\ngraph[fontsize=8];\n".to_string();
+        writeln!(
+            dot,
+            "node[shape=box,style=filled,fillcolor=\"{}\"];",
+            UNPAIRED_COLOUR
+        )
+        .unwrap();
+        for (prefix, cfg, title, styles) in [
+            ("o", self.old, &self.old_title, old_style),
+            ("n", self.new, &self.new_title, new_style),
+        ] {
+            writeln!(dot, "subgraph cluster_{} {{", prefix).unwrap();
+            writeln!(dot, "label={};", quote(title)).unwrap();
+            for bb in cfg.blocks_sorted() {
+                write!(dot, "{}{}[label=\"{}\"", prefix, bb.offset, bb).unwrap();
+                if let Some((colour, same)) = styles.get(bb) {
+                    write!(dot, ",fillcolor=\"{}\"", colour).unwrap();
+                    if !same {
+                        write!(dot, ",color=\"{}\",penwidth=2", CHANGED_BORDER).unwrap();
+                    }
+                }
+                dot.push_str("];\n");
+            }
+            for bb in cfg.blocks_sorted() {
+                for child in cfg.neighbours(bb) {
+                    writeln!(dot, "{}{}->{}{};", prefix, bb.offset, prefix, child.offset).unwrap();
+                }
+            }
+            dot.push_str("}\n");
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Returns the two functions side by side as a self-contained HTML page.
+    ///
+    /// The page contains a table with the blocks of the old function on the left and the blocks
+    /// of the new function on the right. Paired blocks are on the same row, sorted by offset in
+    /// the old function, followed by the blocks without a counterpart. Each block lists its
+    /// address range and its successors.
+    pub fn to_html(&self) -> String {
+        let (old_style, new_style) = self.styles();
+        let mut rows = self
+            .pairs
+            .iter()
+            .map(|(a, b, _)| (Some(a), Some(b)))
+            .collect::<Vec<_>>();
+        let unpaired = |cfg: &'a CFG, styles: &StyleMap| {
+            cfg.blocks_sorted()
+                .into_iter()
+                .filter(|bb| !styles.contains_key(bb))
+                .collect::<Vec<_>>()
+        };
+        rows.extend(
+            unpaired(self.old, &old_style)
+                .into_iter()
+                .map(|a| (Some(a), None)),
+        );
+        rows.extend(
+            unpaired(self.new, &new_style)
+                .into_iter()
+                .map(|b| (None, Some(b))),
+        );
+        let mut html = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{} vs {}</title>\n\
+             <style>\ntable{{border-collapse:separate;border-spacing:8px;font-family:monospace}}\n\
+             td{{vertical-align:top;width:50%}}\n\
+             .block{{border:1px solid black;padding:4px}}\n\
+             .changed{{border:2px solid {}}}\n</style>\n</head>\n<body>\n<table>\n\
+             <tr><th>{}</th><th>{}</th></tr>\n",
+            escape(&self.old_title),
+            escape(&self.new_title),
+            CHANGED_BORDER,
+            escape(&self.old_title),
+            escape(&self.new_title)
+        );
+        for (a, b) in rows {
+            html.push_str("<tr>");
+            for (bb, cfg, styles) in [(a, self.old, &old_style), (b, self.new, &new_style)] {
+                match bb {
+                    Some(bb) => {
+                        let (colour, same) =
+                            styles.get(bb).copied().unwrap_or((UNPAIRED_COLOUR, true));
+                        let class = if same { "block" } else { "block changed" };
+                        let successors = cfg
+                            .neighbours(bb)
+                            .iter()
+                            .map(|child| child.to_string())
+                            .collect::<Vec<_>>()
+                            .join(", ");
+                        write!(
+                            html,
+                            "<td><div class=\"{}\" style=\"background:{}\">\
+                             {}<br>&rarr; {}</div></td>",
+                            class, colour, bb, successors
+                        )
+                        .unwrap();
+                    }
+                    None => html.push_str("<td></td>"),
+                }
+            }
+            html.push_str("</tr>\n");
+        }
+        html.push_str("</table>\n</body>\n</html>\n");
+        html
+    }
+
+    /// Returns the two functions side by side as an SVG image, each with its title on top.
+    ///
+    /// The blocks are coloured as in [`MatchView::to_dot`], and laid out as in
+    /// [`CFG::to_svg`].
+    pub fn to_svg(&self) -> String {
+        let (old_style, new_style) = self.styles();
+        let style = |styles: &StyleMap, bb: &BasicBlock| match styles.get(bb) {
+            Some((colour, same)) => BlockStyle {
+                fill: *colour,
+                border: if *same { "black" } else { CHANGED_BORDER },
+                highlight: !same,
+            },
+            None => BlockStyle {
+                fill: UNPAIRED_COLOUR,
+                ..BlockStyle::default()
+            },
+        };
+        let old = SvgGraph::new(self.old, |bb| style(&old_style, bb));
+        let new = SvgGraph::new(self.new, |bb| style(&new_style, bb));
+        side_by_side(&[
+            (self.old_title.as_str(), &old),
+            (self.new_title.as_str(), &new),
+        ])
+    }
+
+    // styles of the paired blocks of the old and of the new function.
+    fn styles(&self) -> (StyleMap, StyleMap) {
+        let mut old = HashMap::new();
+        let mut new = HashMap::new();
+        for (index, (a, b, same)) in self.pairs.iter().enumerate() {
+            let colour = PALETTE[index % PALETTE.len()];
+            old.insert(*a, (colour, *same));
+            new.insert(*b, (colour, *same));
+        }
+        (old, new)
+    }
+}
+
+impl BinaryDiff {
+    /// Creates the side-by-side view of a pair of functions found by this diff.
+    ///
+    /// The two binaries must be the ones compared by this diff. The functions are titled with
+    /// their names. Returns [`None`] if any of the two functions has no [`CFG`].
+    pub fn view<'a>(
+        &self,
+        matched: &FunctionMatch,
+        old: &'a Binary,
+        new: &'a Binary,
+    ) -> Option<MatchView<'a>> {
+        let old_cfg = old.cfg(matched.old_function().get_offset())?;
+        let new_cfg = new.cfg(matched.new_function().get_offset())?;
+        let view = MatchView::new(old_cfg, new_cfg, self.mode()).with_titles(
+            matched.old_function().get_name(),
+            matched.new_function().get_name(),
+        );
+        Some(view)
+    }
+}
+
+// encodes the string as a quoted dot string.
+fn quote(string: &str) -> String {
+    format!("\"{}\"", string.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::{ComparisonMode, Graph, MatchView, CFG};
+    use crate::disasm::{Architecture, Statement, StatementFamily};
+
+    fn function(body: &str, end: u64) -> CFG {
+        let stmts = vec![
+            Statement::new(0x10, StatementFamily::CJMP, &format!("je {:#x}", end)),
+            Statement::new(0x12, StatementFamily::MOV, body),
+            Statement::new(end, StatementFamily::RET, "ret"),
+        ];
+        CFG::new(&stmts, end + 1, Architecture::X86(64))
+    }
+
+    #[test]
+    fn dot() {
+        let old = function("mov eax, 0", 0x14);
+        let new = function("mov eax, [rbx]", 0x16);
+        let view = MatchView::new(&old, &new, ComparisonMode::SameArchitecture)
+            .with_titles("main", "main\"2");
+        let dot = view.to_dot();
+        assert!(dot.contains("subgraph cluster_o {\nlabel=\"main\";"));
+        assert!(dot.contains("label=\"main\\\"2\";"));
+        // the unchanged head has the same colour on both sides
+        assert!(dot.contains("o16[label=\"0x10..0x12\",fillcolor=\"#8dd3c7\"];"));
+        assert!(dot.contains("n16[label=\"0x10..0x12\",fillcolor=\"#8dd3c7\"];"));
+        assert!(dot.contains("o16->o18;"));
+        assert_eq!(dot.matches("penwidth=2").count(), 2);
+    }
+
+    #[test]
+    fn html() {
+        let old = function("mov eax, 0", 0x14);
+        let new = function("mov eax, 0", 0x14);
+        let html = MatchView::new(&old, &new, ComparisonMode::SameArchitecture)
+            .with_titles("<old>", "new")
+            .to_html();
+        assert!(html.contains("<title>&lt;old&gt; vs new</title>"));
+        assert_eq!(html.matches("<tr><td><div class=\"block\"").count(), 3);
+        assert!(!html.contains("class=\"block changed\""));
+    }
+
+    #[test]
+    fn svg() {
+        let old = function("mov eax, 0", 0x14);
+        let new = function("mov eax, [rbx]", 0x16);
+        let svg = MatchView::new(&old, &new, ComparisonMode::SameArchitecture)
+            .with_titles("<old>", "new")
+            .to_svg();
+        assert!(svg.contains(">&lt;old&gt;</text>"));
+        assert_eq!(svg.matches("<g transform=").count(), 2);
+        assert_eq!(svg.matches("<rect").count(), old.len() + new.len());
+        assert_eq!(svg.matches("fill=\"#8dd3c7\"").count(), 2);
+        assert_eq!(svg.matches("stroke=\"red\" stroke-width=\"3\"").count(), 2);
+    }
+
+    #[test]
+    fn unstructured() {
+        let old = function("mov eax, 0", 0x14);
+        let new = CFG::default();
+        let view = MatchView::new(&old, &new, ComparisonMode::SameArchitecture);
+        assert!(view.block_pairs().is_empty());
+        assert!(view.to_html().contains("<td></td>"));
+    }
+}
@@ -0,0 +1,205 @@
+use crate::analysis::svg::escape;
+use crate::analysis::{Graph, StructureBlock, CFS};
+use std::fmt::Write;
+
+// the tree takes the left side of the page, the graph fills the rest.
+const VIEWER_STYLE: &str = r##"body{margin:0;display:flex;height:100vh;font-family:sans-serif}
+#tree{width:30%;overflow:auto;padding:0 1em;border-right:1px solid #bbb;font-size:13px}
+#tree h1{font-size:16px}
+#graph{flex:1;overflow:hidden;cursor:grab}
+#graph svg{width:100%;height:100%;user-select:none}
+details{margin-left:1em}
+.node{cursor:pointer;font-family:monospace;padding:1px 2px}
+.leaf{margin-left:2em}
+.node.selected{background:#ffd54f}
+rect{cursor:pointer}
+rect.selected{fill:#ffd54f}
+"##;
+
+// moves the graph by dragging it, scales it with the wheel, and links the tree to the blocks.
+const VIEWER_SCRIPT: &str = r##"const svg = document.querySelector("#graph svg");
+svg.removeAttribute("width");
+svg.removeAttribute("height");
+const initial = svg.getAttribute("viewBox");
+let drag = null;
+let moved = false;
+function viewBox() {
+  return svg.getAttribute("viewBox").split(" ").map(Number);
+}
+function toGraph(event) {
+  const point = new DOMPoint(event.clientX, event.clientY);
+  return point.matrixTransform(svg.getScreenCTM().inverse());
+}
+svg.addEventListener("wheel", (event) => {
+  event.preventDefault();
+  const [x, y, w, h] = viewBox();
+  const scale = event.deltaY < 0 ? 0.8 : 1.25;
+  const p = toGraph(event);
+  const box = [p.x - (p.x - x) * scale, p.y - (p.y - y) * scale, w * scale, h * scale];
+  svg.setAttribute("viewBox", box.join(" "));
+}, { passive: false });
+svg.addEventListener("mousedown", (event) => {
+  drag = toGraph(event);
+  moved = false;
+});
+window.addEventListener("mousemove", (event) => {
+  if (drag) {
+    const [x, y, w, h] = viewBox();
+    const p = toGraph(event);
+    svg.setAttribute("viewBox", [x + drag.x - p.x, y + drag.y - p.y, w, h].join(" "));
+    moved = true;
+  }
+});
+window.addEventListener("mouseup", () => {
+  drag = null;
+});
+svg.addEventListener("dblclick", () => svg.setAttribute("viewBox", initial));
+function select(node) {
+  for (const element of document.querySelectorAll(".selected")) {
+    element.classList.remove("selected");
+  }
+  const offsets = node.dataset.blocks.split(" ");
+  for (const rect of svg.querySelectorAll("rect")) {
+    if (offsets.includes(rect.dataset.offset)) {
+      rect.classList.add("selected");
+    }
+  }
+  node.classList.add("selected");
+}
+for (const node of document.querySelectorAll("#tree .node")) {
+  node.addEventListener("click", () => select(node));
+}
+for (const rect of svg.querySelectorAll("rect")) {
+  rect.addEventListener("click", () => {
+    const leaf = document.querySelector(`#tree .leaf[data-blocks="${rect.dataset.offset}"]`);
+    if (leaf && !moved) {
+      for (let parent = leaf.parentElement; parent; parent = parent.parentElement) {
+        if (parent.tagName === "DETAILS") {
+          parent.open = true;
+        }
+      }
+      select(leaf);
+      leaf.scrollIntoView({ block: "center" });
+    }
+  });
+}
+"##;
+
+impl CFS {
+    /// Returns an interactive HTML page showing the structure tree and the [`CFG`] of this
+    /// function, side by side.
+    ///
+    /// The graph is laid out as in [`CFG::to_svg`], and can be moved by dragging it and scaled
+    /// with the mouse wheel, while a double click restores the initial view. Each node of the
+    /// structure tree can be collapsed, and clicking it highlights its basic blocks in the graph.
+    /// Clicking a basic block in the graph reveals it in the tree.
+    ///
+    /// If the structuring is not complete, the tree has a root for each node of the final graph.
+    /// The page is self-contained, with the layout computed in advance: unlike the Graphviz
+    /// renders of [`CFS::to_dot`], it remains usable for functions with hundreds of blocks.
+    ///
+    /// [`CFG`]: crate::analysis::CFG
+    /// [`CFG::to_svg`]: crate::analysis::CFG::to_svg
+    /// # Examples
+    /// Basic usage:
+    /// ```
+    /// use bincc::analysis::{CFG, CFS};
+    /// use bincc::disasm::{Architecture, Statement, StatementFamily};
+    ///
+    /// let stmts = vec![
+    ///     Statement::new(0x10, StatementFamily::CJMP, "je 0x14"),
+    ///     Statement::new(0x12, StatementFamily::MOV, "mov eax, 0"),
+    ///     Statement::new(0x14, StatementFamily::RET, "ret"),
+    /// ];
+    /// let cfg = CFG::new(&stmts, 0x15, Architecture::X86(64));
+    /// let html = CFS::new(&cfg).unwrap().to_html("main");
+    ///
+    /// assert!(html.contains("<title>main</title>"));
+    /// assert!(html.contains("<summary class=\"node\" data-blocks=\"0x10 0x12 0x14\">"));
+    /// ```
+    pub fn to_html(&self, title: &str) -> String {
+        let mut tree = String::new();
+        for root in self.get_graph().dfs_preorder() {
+            tree_node(root, &mut tree);
+        }
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+             <style>\n{}</style>\n</head>\n<body>\n<div id=\"tree\">\n<h1>{}</h1>\n{}</div>\n\
+             <div id=\"graph\">\n{}</div>\n<script>\n{}</script>\n</body>\n</html>\n",
+            escape(title),
+            VIEWER_STYLE,
+            escape(title),
+            tree,
+            self.get_cfg().to_svg(),
+            VIEWER_SCRIPT
+        )
+    }
+}
+
+// writes the node as an HTML element, listing in `data-blocks` the offsets of its basic blocks.
+fn tree_node(block: &StructureBlock, html: &mut String) {
+    let blocks = block
+        .basic_blocks()
+        .iter()
+        .map(|bb| format!("{:#x}", bb.offset))
+        .collect::<Vec<_>>()
+        .join(" ");
+    match block {
+        StructureBlock::Basic(bb) => {
+            writeln!(
+                html,
+                "<div class=\"node leaf\" data-blocks=\"{}\">{}</div>",
+                blocks, bb
+            )
+            .unwrap();
+        }
+        StructureBlock::Nested(_) => {
+            writeln!(
+                html,
+                "<details open><summary class=\"node\" data-blocks=\"{}\">{} {:#x}</summary>",
+                blocks,
+                escape(&block.block_type().to_string()),
+                block.offset()
+            )
+            .unwrap();
+            for child in block.children() {
+                tree_node(child, html);
+            }
+            html.push_str("</details>\n");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::{CFG, CFS};
+    use crate::disasm::{Architecture, Statement, StatementFamily};
+
+    #[test]
+    fn tree() {
+        // while loop followed by a return
+        let stmts = vec![
+            Statement::new(0x10, StatementFamily::CJMP, "je 0x14"),
+            Statement::new(0x12, StatementFamily::JMP, "jmp 0x10"),
+            Statement::new(0x14, StatementFamily::RET, "ret"),
+        ];
+        let cfg = CFG::new(&stmts, 0x15, Architecture::X86(64));
+        let html = CFS::new(&cfg).unwrap().to_html("a<b");
+        assert!(html.contains("<title>a&lt;b</title>"));
+        assert!(html.contains("<summary class=\"node\" data-blocks=\"0x10 0x12\">While 0x10"));
+        assert!(html.contains("<div class=\"node leaf\" data-blocks=\"0x14\">0x14..0x15</div>"));
+        assert_eq!(
+            html.matches("<details").count(),
+            html.matches("</details>").count()
+        );
+        assert_eq!(html.matches("data-offset=").count(), 3);
+        assert!(html.ends_with("</html>\n"));
+    }
+
+    #[test]
+    fn empty() {
+        let html = CFS::new(&CFG::default()).unwrap().to_html("empty");
+        assert!(!html.contains("class=\"node"));
+        assert!(html.contains("<svg"));
+    }
+}
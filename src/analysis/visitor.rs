@@ -0,0 +1,282 @@
+use crate::analysis::blocks::StructureBlock;
+use crate::analysis::{BlockType, NestedBlock};
+use std::sync::Arc;
+
+/// Callbacks invoked while visiting a [`StructureBlock`] tree with [`StructureBlock::accept`].
+///
+/// The tree is visited depth-first: [`StructureVisitor::enter_block`] is called before visiting
+/// the children of a block, in order, and [`StructureVisitor::leave_block`] after all of them
+/// have been visited. Both callbacks receive the depth of the block in the visited tree, 0 being
+/// the root.
+/// # Examples
+/// Basic usage:
+/// ```
+/// use bincc::analysis::{BasicBlock, BlockType, NestedBlock, StructureBlock, StructureVisitor};
+/// use std::sync::Arc;
+///
+/// #[derive(Default)]
+/// struct LoopCounter {
+///     loops: usize,
+/// }
+///
+/// impl StructureVisitor for LoopCounter {
+///     fn enter_block(&mut self, block: &StructureBlock, _: usize) -> bool {
+///         if block.block_type() == BlockType::SelfLooping {
+///             self.loops += 1;
+///         }
+///         true
+///     }
+/// }
+///
+/// let bb0 = StructureBlock::from(BasicBlock { offset: 0x10, length: 4 });
+/// let bb1 = StructureBlock::from(BasicBlock { offset: 0x20, length: 8 });
+/// let self_loop = NestedBlock::new(BlockType::SelfLooping, vec![bb1]);
+/// let seq = NestedBlock::new(
+///     BlockType::Sequence,
+///     vec![bb0, StructureBlock::from(Arc::new(self_loop))],
+/// );
+/// let mut counter = LoopCounter::default();
+/// StructureBlock::from(Arc::new(seq)).accept(&mut counter);
+///
+/// assert_eq!(counter.loops, 1);
+/// ```
+pub trait StructureVisitor {
+    /// Called when a block is reached, before visiting its children.
+    ///
+    /// If this method returns false, the children of the block are not visited. The default
+    /// implementation visits every child.
+    fn enter_block(&mut self, _block: &StructureBlock, _depth: usize) -> bool {
+        true
+    }
+
+    /// Called after visiting all the children of a block.
+    ///
+    /// This method is called even if the children were skipped by
+    /// [`StructureVisitor::enter_block`].
+    fn leave_block(&mut self, _block: &StructureBlock, _depth: usize) {}
+}
+
+/// Callbacks invoked while rewriting a [`StructureBlock`] tree with [`StructureBlock::rewrite`].
+///
+/// The visit order is the same of [`StructureVisitor`], but
+/// [`StructureRewriter::leave_block`] receives the block with its children already rewritten and
+/// returns the block that will replace it in the resulting tree.
+pub trait StructureRewriter {
+    /// Called when a block is reached, before rewriting its children.
+    ///
+    /// If this method returns false, the children of the block are kept as they are. The default
+    /// implementation rewrites every child.
+    fn enter_block(&mut self, _block: &StructureBlock, _depth: usize) -> bool {
+        true
+    }
+
+    /// Called after rewriting all the children of a block, returning its replacement.
+    ///
+    /// The default implementation returns the block unchanged.
+    fn leave_block(&mut self, block: StructureBlock, _depth: usize) -> StructureBlock {
+        block
+    }
+}
+
+impl StructureBlock {
+    /// Visits this block and all its descendants with the given [`StructureVisitor`].
+    pub fn accept<V: StructureVisitor + ?Sized>(&self, visitor: &mut V) {
+        accept_rec(self, visitor, 0);
+    }
+
+    /// Creates a new tree by applying the given [`StructureRewriter`] to this block and all its
+    /// descendants.
+    ///
+    /// The original tree is not modified. Blocks whose children are changed by the rewriter are
+    /// rebuilt, updating their offset and depth, while the others are shared with the original
+    /// tree.
+    pub fn rewrite<R: StructureRewriter + ?Sized>(&self, rewriter: &mut R) -> StructureBlock {
+        rewrite_rec(self, rewriter, 0)
+    }
+}
+
+fn accept_rec<V: StructureVisitor + ?Sized>(node: &StructureBlock, visitor: &mut V, depth: usize) {
+    if visitor.enter_block(node, depth) {
+        for child in node.children() {
+            accept_rec(child, visitor, depth + 1);
+        }
+    }
+    visitor.leave_block(node, depth);
+}
+
+fn rewrite_rec<R: StructureRewriter + ?Sized>(
+    node: &StructureBlock,
+    rewriter: &mut R,
+    depth: usize,
+) -> StructureBlock {
+    let mut rewritten = node.clone();
+    if rewriter.enter_block(node, depth) {
+        if let StructureBlock::Nested(nb) = node {
+            let content = nb
+                .content
+                .iter()
+                .map(|child| rewrite_rec(child, rewriter, depth + 1))
+                .collect::<Vec<_>>();
+            let unchanged = content
+                .iter()
+                .zip(&nb.content)
+                .all(|(new, old)| same(new, old));
+            if !unchanged {
+                rewritten = rebuild(nb, content);
+            }
+        }
+    }
+    rewriter.leave_block(rewritten, depth)
+}
+
+// Shallow equality: unchanged nested blocks are the same instance, so there is no need to compare
+// the whole subtree.
+fn same(a: &StructureBlock, b: &StructureBlock) -> bool {
+    match (a, b) {
+        (StructureBlock::Nested(a), StructureBlock::Nested(b)) => Arc::ptr_eq(a, b),
+        _ => a == b,
+    }
+}
+
+// Creates a copy of the nested block with different children, keeping every other property.
+fn rebuild(original: &NestedBlock, content: Vec<StructureBlock>) -> StructureBlock {
+    let mut nb = match (original.block_type, content.as_slice()) {
+        (BlockType::Duplicate, [child]) => NestedBlock::duplicate(child),
+        (BlockType::Return | BlockType::Unreachable, [StructureBlock::Basic(bb)]) => {
+            NestedBlock::terminator(original.block_type, bb)
+        }
+        _ => NestedBlock::new(original.block_type, content),
+    };
+    nb.exits = original.exits.clone();
+    nb.polarity = original.polarity;
+    nb.condition = original.condition.clone();
+    StructureBlock::from(Arc::new(nb))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::{
+        BasicBlock, BlockType, NestedBlock, StructureBlock, StructureRewriter, StructureVisitor,
+    };
+    use std::sync::Arc;
+
+    fn bb(offset: u64) -> StructureBlock {
+        StructureBlock::from(BasicBlock { offset, length: 1 })
+    }
+
+    fn nest(bt: BlockType, children: Vec<StructureBlock>) -> StructureBlock {
+        StructureBlock::from(Arc::new(NestedBlock::new(bt, children)))
+    }
+
+    fn tree() -> StructureBlock {
+        let ifthen = nest(BlockType::IfThen, vec![bb(2), bb(3)]);
+        nest(BlockType::Sequence, vec![bb(1), ifthen, bb(4)])
+    }
+
+    #[derive(Default)]
+    struct Recorder {
+        events: Vec<String>,
+        skip: Option<BlockType>,
+    }
+
+    impl StructureVisitor for Recorder {
+        fn enter_block(&mut self, block: &StructureBlock, depth: usize) -> bool {
+            self.events.push(format!("+{}@{}", block, depth));
+            self.skip != Some(block.block_type())
+        }
+
+        fn leave_block(&mut self, block: &StructureBlock, depth: usize) {
+            self.events.push(format!("-{}@{}", block, depth));
+        }
+    }
+
+    #[test]
+    fn accept_order() {
+        let mut recorder = Recorder::default();
+        tree().accept(&mut recorder);
+        let expected = [
+            "+Sequence_1@0",
+            "+0x1..0x2@1",
+            "-0x1..0x2@1",
+            "+If-Then_2@1",
+            "+0x2..0x3@2",
+            "-0x2..0x3@2",
+            "+0x3..0x4@2",
+            "-0x3..0x4@2",
+            "-If-Then_2@1",
+            "+0x4..0x5@1",
+            "-0x4..0x5@1",
+            "-Sequence_1@0",
+        ];
+        assert_eq!(recorder.events, expected);
+    }
+
+    #[test]
+    fn accept_skip_children() {
+        let mut recorder = Recorder {
+            skip: Some(BlockType::IfThen),
+            ..Default::default()
+        };
+        tree().accept(&mut recorder);
+        assert!(recorder.events.contains(&"-If-Then_2@1".to_string()));
+        assert!(!recorder.events.contains(&"+0x2..0x3@2".to_string()));
+    }
+
+    struct Shift(u64);
+
+    impl StructureRewriter for Shift {
+        fn leave_block(&mut self, block: StructureBlock, _: usize) -> StructureBlock {
+            match block {
+                StructureBlock::Basic(bb) => StructureBlock::from(BasicBlock {
+                    offset: bb.offset + self.0,
+                    length: bb.length,
+                }),
+                nested => nested,
+            }
+        }
+    }
+
+    #[test]
+    fn rewrite_rebuilds_parents() {
+        let original = tree();
+        let rewritten = original.rewrite(&mut Shift(0x100));
+        assert_eq!(original.offset(), 1);
+        assert_eq!(rewritten.offset(), 0x101);
+        assert_eq!(rewritten.children()[1].offset(), 0x102);
+        assert_eq!(rewritten.depth(), original.depth());
+        assert!(rewritten.structural_equality(&original));
+    }
+
+    struct FlattenIfThen;
+
+    impl StructureRewriter for FlattenIfThen {
+        fn leave_block(&mut self, block: StructureBlock, _: usize) -> StructureBlock {
+            if block.block_type() == BlockType::IfThen {
+                nest(BlockType::Sequence, block.children().to_vec())
+            } else {
+                block
+            }
+        }
+    }
+
+    #[test]
+    fn rewrite_replace_block() {
+        let rewritten = tree().rewrite(&mut FlattenIfThen);
+        assert_eq!(rewritten.children()[1].block_type(), BlockType::Sequence);
+        assert_eq!(rewritten.basic_blocks(), tree().basic_blocks());
+    }
+
+    struct Identity;
+
+    impl StructureRewriter for Identity {}
+
+    #[test]
+    fn rewrite_unchanged_shares_nodes() {
+        let original = tree();
+        let rewritten = original.rewrite(&mut Identity);
+        match (&original, &rewritten) {
+            (StructureBlock::Nested(a), StructureBlock::Nested(b)) => assert!(Arc::ptr_eq(a, b)),
+            _ => panic!("expected nested blocks"),
+        }
+    }
+}
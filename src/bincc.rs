@@ -1,19 +1,36 @@
 use bincc::analysis::{
-    CFSComparator, CloneClass, FVec, Graph, SemanticComparator, StructureBlock, CFG, CFS,
+    json_quote, AnalysisConfig, AnalysisReport, BinaryDiff, CFSComparator, CancellationToken,
+    CfgGenerator, CloneClass, ComparisonMode, FVec, Graph, Phase, ProgressSink, Report,
+    SemanticComparator, Server, StructureBlock, Timings, CFG, CFS,
 };
 use bincc::disasm::radare2::R2Disasm;
+use bincc::disasm::{demangle, Binary, Function};
 use clap::Parser;
 use fnv::FnvHashMap;
 use futures::stream::FuturesUnordered;
 use futures::StreamExt;
 use indicatif::{ProgressBar, ProgressStyle};
+use std::borrow::Cow;
 use std::cmp::Reverse;
 use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write as _};
 use std::path::Path;
+use std::rc::Rc;
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::task::LocalSet;
 use tokio::time::timeout;
 
+// version of the schema of the functions.json file written by the analyze subcommand.
+const ANALYSIS_VERSION: u32 = 1;
+// amount of functions listed by the analyze subcommand when measuring the timings.
+const SLOWEST_FUNCTIONS: usize = 10;
+
 #[derive(clap::ValueEnum, Copy, Clone)]
 enum SortResult {
     /// Do not sort the results.
@@ -36,6 +53,204 @@ enum SemanticAnalysisType {
     Cross,
 }
 
+#[derive(clap::ValueEnum, Copy, Clone, PartialEq, Eq)]
+enum AnalysisFormat {
+    /// Writes the structure and metrics of every function in a single JSON file.
+    Json,
+    /// Writes the CFG and the structure tree of every function in Graphviz dot format.
+    Dot,
+    /// Writes a Markdown report with the metrics and the CFG of every function.
+    Markdown,
+    /// Writes an HTML report with the metrics and the CFG of every function.
+    Html,
+    /// Writes the JSON, the Graphviz dot and the report files.
+    All,
+}
+
+#[derive(clap::ValueEnum, Copy, Clone, PartialEq, Eq)]
+enum ReportFormat {
+    /// Prints a table with a row for each function.
+    Text,
+    /// Prints a JSON document.
+    Json,
+    /// Prints a SARIF 2.1.0 log.
+    Sarif,
+    /// Prints a Markdown document, with the graphs of the changed functions.
+    Markdown,
+    /// Prints an HTML page, with the graphs of the changed functions.
+    Html,
+}
+
+#[derive(clap::Subcommand, Clone)]
+enum Command {
+    /// Extracts the structure of every function of a binary file.
+    ///
+    /// The output directory will contain the following files, depending on the chosen format:
+    ///
+    /// functions.json       the name, address ranges, metrics and structure tree of each function
+    /// <offset>.cfg.dot     the CFG of the function starting at <offset>
+    /// <offset>.cfs.dot     the structure tree of the function starting at <offset>
+    /// report.md            the metrics and the CFG of each function, in Markdown format
+    /// report.html          the metrics and the CFG of each function, in HTML format
+    ///
+    /// The structure trees in the JSON file are written as s-expressions, like
+    /// (seq b401000+4 (while b401004+8 b40100c+2) b40100e+1).
+    #[clap(verbatim_doc_comment)]
+    Analyze(AnalyzeArgs),
+    /// Compares the functions of two versions of the same binary file.
+    ///
+    /// Functions are paired by name, then by structure, and each pair is reported as identical
+    /// or changed, together with the functions added to and removed from the new version.
+    Compare(CompareArgs),
+    /// Writes an interactive HTML page with the CFG and the structure tree of a single function.
+    ///
+    /// The graph can be moved by dragging it and scaled with the mouse wheel, and clicking a node
+    /// of the structure tree highlights its basic blocks in the graph.
+    View(ViewArgs),
+    /// Keeps the analyzed binaries in memory and answers JSON-RPC 2.0 queries about them.
+    ///
+    /// Each request and each response is a JSON document on a single line. Requests are read
+    /// from stdin and responses written to stdout, unless a Unix socket is given. The available
+    /// methods are load, unload, binaries, functions, cfg, structure, compare and shutdown, for
+    /// example:
+    ///
+    /// {"jsonrpc":"2.0","id":1,"method":"load","params":{"path":"/bin/ls","name":"ls"}}
+    /// {"jsonrpc":"2.0","id":2,"method":"structure","params":{"binary":"ls","function":"main"}}
+    #[clap(verbatim_doc_comment)]
+    Serve(ServeArgs),
+    /// Structures randomly generated CFGs, reporting the time spent in each reduction.
+    ///
+    /// The CFGs are always the same for the same seed and settings, so the command can be used
+    /// as a benchmark. The trees are validated, and the command fails if any of them is invalid.
+    Stress(StressArgs),
+}
+
+#[derive(clap::Args, Clone)]
+struct AnalyzeArgs {
+    /// File that will be analyzed.
+    input: String,
+    /// Directory where the results will be written, created if it does not exist.
+    #[clap(short, long, default_value = ".")]
+    output: String,
+    /// Format of the results.
+    #[clap(short, long, default_value = "all")]
+    format: AnalysisFormat,
+    /// Prints the function names as they appear in the binary, without demangling them.
+    #[clap(long)]
+    mangled: bool,
+    /// Maximum time limit for the analysis, in seconds.
+    #[clap(short, long, default_value_t = u64::MAX)]
+    timeout: u64,
+    /// File with the settings of the extraction and of the structuring.
+    ///
+    /// The file contains a `key = value` setting per line, like `max_size = 5000`.
+    #[clap(short, long)]
+    config: Option<String>,
+    /// Measures the time spent extracting and structuring each function.
+    ///
+    /// The times are added to the JSON results, and the slowest functions are listed.
+    #[clap(long)]
+    timings: bool,
+    /// Analyzes one function at a time, writing its results before extracting the next one.
+    ///
+    /// Only the function being analyzed is kept in memory, for binaries too large to be analyzed
+    /// at once. The functions are structured sequentially, and the Markdown and HTML reports,
+    /// requiring every function, are not written.
+    #[clap(long)]
+    streaming: bool,
+}
+
+#[derive(clap::Args, Clone)]
+struct CompareArgs {
+    /// Old version of the file.
+    old: String,
+    /// New version of the file.
+    new: String,
+    /// Specify if the two files belong to the same architecture or not.
+    ///
+    /// If this parameter is not provided, it will be taken from the configuration file or detected
+    /// by the disassembler.
+    #[clap(short, long)]
+    architecture: Option<SemanticAnalysisType>,
+    /// Minimum similarity, between 0 and 1, of two functions paired by their structure alone.
+    ///
+    /// If this parameter is not provided, it will be taken from the configuration file or set to
+    /// 0.5.
+    #[clap(long)]
+    threshold: Option<f64>,
+    /// Format of the report.
+    #[clap(short, long, default_value = "text")]
+    format: ReportFormat,
+    /// Reports only the functions whose name contains the given string.
+    #[clap(short, long)]
+    name: Option<String>,
+    /// Reports only the functions starting at the given address, in decimal or hexadecimal.
+    #[clap(long, value_parser = parse_address)]
+    address: Option<u64>,
+    /// Maximum time limit for the analysis of each file, in seconds.
+    #[clap(short, long, default_value_t = u64::MAX)]
+    timeout: u64,
+    /// File with the settings of the extraction, of the structuring and of the comparison.
+    ///
+    /// The file contains a `key = value` setting per line, like `threshold = 0.6`.
+    #[clap(short, long)]
+    config: Option<String>,
+}
+
+#[derive(clap::Args, Clone)]
+struct ViewArgs {
+    /// File containing the function.
+    input: String,
+    /// Name of the function, or its address in decimal or hexadecimal.
+    function: String,
+    /// File where the page will be written, instead of stdout.
+    #[clap(short, long)]
+    output: Option<String>,
+    /// Maximum time limit for the analysis of the file, in seconds.
+    #[clap(short, long, default_value_t = u64::MAX)]
+    timeout: u64,
+    /// File with the settings of the extraction and of the structuring.
+    #[clap(short, long)]
+    config: Option<String>,
+}
+
+#[derive(clap::Args, Clone)]
+struct StressArgs {
+    /// Amount of generated CFGs.
+    #[clap(short = 'n', long, default_value_t = 1000)]
+    count: usize,
+    /// Amount of basic blocks of each CFG.
+    #[clap(short, long, default_value_t = 64)]
+    blocks: usize,
+    /// Probability, between 0 and 1, of each block ending a loop.
+    #[clap(long, default_value_t = 0.1)]
+    loops: f64,
+    /// Probability, between 0 and 1, of each block jumping forward or returning.
+    #[clap(long, default_value_t = 0.3)]
+    branches: f64,
+    /// Probability, between 0 and 1, of keeping a jump entering a loop elsewhere than its head.
+    #[clap(long, default_value_t = 0.0)]
+    irreducibility: f64,
+    /// Seed of the generated CFGs.
+    #[clap(short, long, default_value_t = 0)]
+    seed: u64,
+    /// File with the settings of the structuring.
+    #[clap(short, long)]
+    config: Option<String>,
+}
+
+#[derive(clap::Args, Clone)]
+struct ServeArgs {
+    /// Files analyzed at startup, each named after its path.
+    input: Vec<String>,
+    /// Unix socket where the clients connect, instead of using stdin and stdout.
+    #[clap(short, long)]
+    socket: Option<String>,
+    /// File with the settings of the extraction, of the structuring and of the comparison.
+    #[clap(short, long)]
+    config: Option<String>,
+}
+
 /// Detects code clones in the given binary files.
 ///
 /// The report will be printed to stdout and will contains all the clones divided in clone classes.
@@ -46,9 +261,14 @@ enum SemanticAnalysisType {
 ///
 /// For example CLONE CLASS (3) means that the clone class contains clones of at least 3 nested
 /// structures.
+///
+/// Use the subcommands to analyze a single binary instead.
 #[derive(Parser, Clone)]
 #[clap(author, version, about, verbatim_doc_comment)]
+#[clap(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
     /// Files that will be compared against eachother for function clones.
     #[clap(required = true)]
     input: Vec<String>,
@@ -88,6 +308,9 @@ struct Args {
     /// Don't remove duplicate clone classes from the results.
     #[clap(long)]
     no_filter: bool,
+    /// Prints the function names as they appear in the binary, without demangling them.
+    #[clap(long)]
+    mangled: bool,
     /// Limits the maximum amount of applications analysed concurrently.
     #[clap(short='l', long="limit", default_value_t = num_cpus::get())]
     limit_concurrent: usize,
@@ -99,6 +322,17 @@ struct Args {
 #[tokio::main]
 async fn main() {
     let args = Args::parse();
+    match args.command.clone() {
+        Some(Command::Analyze(analyze)) => analyze_binary(analyze).await,
+        Some(Command::Compare(compare)) => compare_binaries(compare).await,
+        Some(Command::View(view)) => view_function(view).await,
+        Some(Command::Serve(serve)) => serve_binaries(serve).await,
+        Some(Command::Stress(stress)) => stress_structuring(stress),
+        None => detect_clones(args).await,
+    }
+}
+
+async fn detect_clones(args: Args) {
     // First detect the architecture for the semantic analysis
     let cross_arch = if let Some(semtype) = args.architecture {
         semtype == SemanticAnalysisType::Cross
@@ -127,7 +361,499 @@ async fn main() {
         args.basic_blocks,
         args.csv,
         !args.no_filter,
+        args.mangled,
+    );
+}
+
+// progress of the subcommands, drawn as a bar replaced at every phase.
+struct ProgressBarSink {
+    style: ProgressStyle,
+    bar: Mutex<ProgressBar>,
+}
+
+impl ProgressBarSink {
+    fn new() -> ProgressBarSink {
+        let style = ProgressStyle::default_bar()
+            .template("{msg} {pos:>7}/{len:7} [{bar:40.cyan/blue}] [{elapsed_precise}]")
+            .unwrap()
+            .progress_chars("#>-");
+        ProgressBarSink {
+            style,
+            bar: Mutex::new(ProgressBar::hidden()),
+        }
+    }
+}
+
+impl ProgressSink for ProgressBarSink {
+    fn phase_started(&self, phase: Phase, total: usize) {
+        let message = match phase {
+            Phase::Disassembly => "Disassembling...",
+            Phase::Structuring => "Structuring...",
+        };
+        *self.bar.lock().unwrap() = ProgressBar::new(total as u64)
+            .with_style(self.style.clone())
+            .with_message(message);
+    }
+
+    fn function_completed(&self, _: Phase, _: u64) {
+        self.bar.lock().unwrap().inc(1);
+    }
+
+    fn phase_finished(&self, _: Phase) {
+        self.bar.lock().unwrap().finish();
+    }
+}
+
+// reads the configuration of the subcommands, exiting in case of errors.
+fn load_config(path: Option<&str>) -> AnalysisConfig {
+    match path.map(AnalysisConfig::from_file) {
+        Some(Ok(config)) => config,
+        Some(Err(error)) => {
+            eprintln!(
+                "Invalid configuration {}: {}",
+                path.unwrap_or_default(),
+                error
+            );
+            std::process::exit(1)
+        }
+        None => AnalysisConfig::default(),
+    }
+}
+
+// analyzes the binary, exiting in case of errors.
+async fn load_binary(
+    path: &str,
+    timeout_secs: u64,
+    config: &AnalysisConfig,
+    progress: &dyn ProgressSink,
+) -> Binary {
+    let analysis = Binary::analyze_with_progress(path, config.extraction(), progress);
+    match timeout(Duration::from_secs(timeout_secs), analysis).await {
+        Ok(Ok(binary)) => binary,
+        Ok(Err(error)) => {
+            eprintln!("Disassembler error for {}: {}", path, error);
+            std::process::exit(1)
+        }
+        Err(_) => {
+            eprintln!("Killed {} (timeout)", path);
+            std::process::exit(1)
+        }
+    }
+}
+
+fn parse_address(address: &str) -> Result<u64, String> {
+    match address.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => address.parse::<u64>(),
+    }
+    .map_err(|error| error.to_string())
+}
+
+async fn compare_binaries(args: CompareArgs) {
+    let mut config = load_config(args.config.as_deref());
+    config = match args.architecture {
+        Some(SemanticAnalysisType::Same) => config.with_mode(ComparisonMode::SameArchitecture),
+        Some(SemanticAnalysisType::Cross) => config.with_mode(ComparisonMode::CrossArchitecture),
+        None => config,
+    };
+    if let Some(threshold) = args.threshold {
+        config = config.with_threshold(threshold);
+    }
+    let progress = ProgressBarSink::new();
+    let old = load_binary(&args.old, args.timeout, &config, &progress).await;
+    let new = load_binary(&args.new, args.timeout, &config, &progress).await;
+    let diff = BinaryDiff::with_config(&old, &new, &config);
+    let mut report = Report::new(&args.old, &args.new, &diff);
+    if args.name.is_some() || args.address.is_some() {
+        let (name, address) = (args.name.as_deref(), args.address);
+        report = report.with_filter(move |function: &Function| {
+            name.map_or(true, |name| {
+                function.get_name().contains(name)
+                    || function_name(function.get_name(), false).contains(name)
+            }) && address.map_or(true, |address| function.get_offset() == address)
+        });
+    }
+    match args.format {
+        ReportFormat::Text => print!("{}", report.to_text()),
+        ReportFormat::Json => println!("{}", report.to_json()),
+        ReportFormat::Sarif => println!("{}", report.to_sarif()),
+        ReportFormat::Markdown => print!("{}", report.with_binaries(&old, &new).to_markdown()),
+        ReportFormat::Html => print!("{}", report.with_binaries(&old, &new).to_html()),
+    }
+}
+
+async fn view_function(args: ViewArgs) {
+    let config = load_config(args.config.as_deref());
+    let progress = ProgressBarSink::new();
+    let binary = load_binary(&args.input, args.timeout, &config, &progress).await;
+    let function = binary.functions().iter().find(|function| {
+        function.get_name() == args.function
+            || function_name(function.get_name(), false) == args.function
+    });
+    let offset = function
+        .map(Function::get_offset)
+        .or_else(|| parse_address(&args.function).ok());
+    let cfg = match offset.and_then(|offset| binary.cfg(offset)) {
+        Some(cfg) => cfg,
+        None => {
+            eprintln!("Function {} not found in {}", args.function, args.input);
+            std::process::exit(1)
+        }
+    };
+    let cfs = match config.cfs_builder().build(cfg) {
+        Ok(cfs) => cfs,
+        Err(error) => {
+            eprintln!("Failed to structure {}: {}", args.function, error);
+            std::process::exit(1)
+        }
+    };
+    let title = function.map_or(Cow::Borrowed(args.function.as_str()), |function| {
+        function_name(function.get_name(), false)
+    });
+    let html = cfs.to_html(&title);
+    match args.output {
+        Some(path) => {
+            if let Err(error) = fs::write(&path, html) {
+                eprintln!("Failed to write {}: {}", path, error);
+                std::process::exit(1)
+            }
+        }
+        None => print!("{}", html),
+    }
+}
+
+fn stress_structuring(args: StressArgs) {
+    let builder = load_config(args.config.as_deref())
+        .record_timings()
+        .cfs_builder();
+    let generator = CfgGenerator::new(args.seed)
+        .with_blocks(args.blocks)
+        .with_loop_density(args.loops)
+        .with_branch_density(args.branches)
+        .with_irreducibility(args.irreducibility);
+    let mut timings = Timings::new();
+    let mut incomplete = 0;
+    let mut failed = 0;
+    let start = Instant::now();
+    for (index, cfg) in generator.take(args.count).enumerate() {
+        let cfs = match builder.build(&cfg) {
+            Ok(cfs) => cfs,
+            Err(error) => {
+                eprintln!("Failed to structure CFG {}: {}", index, error);
+                failed += 1;
+                continue;
+            }
+        };
+        if let Some(Err(error)) = cfs.get_tree().map(|tree| tree.validate()) {
+            eprintln!("Invalid tree for CFG {}: {}", index, error);
+            failed += 1;
+        }
+        if !cfs.result().is_complete() {
+            incomplete += 1;
+        }
+        if let Some(elapsed) = cfs.timings() {
+            timings.add(elapsed);
+        }
+    }
+    println!(
+        "Structured {} CFGs of {} blocks in {:.3?}: {} incomplete, {} failed",
+        args.count,
+        args.blocks,
+        start.elapsed(),
+        incomplete,
+        failed
+    );
+    println!("{:>10.3?} Denaturation", timings.denaturation());
+    for (name, elapsed) in timings.reductions() {
+        println!("{:>10.3?} {}", elapsed, name);
+    }
+    if failed > 0 {
+        std::process::exit(1)
+    }
+}
+
+async fn analyze_binary(args: AnalyzeArgs) {
+    let mut config = load_config(args.config.as_deref());
+    if args.timings {
+        config = config.record_timings();
+    }
+    let progress = Arc::new(ProgressBarSink::new());
+    if args.streaming {
+        if matches!(args.format, AnalysisFormat::Markdown | AnalysisFormat::Html) {
+            eprintln!("The reports can not be written when streaming the analysis");
+            std::process::exit(1)
+        }
+        let analysis = stream_analysis(&args, &config, progress.as_ref());
+        match timeout(Duration::from_secs(args.timeout), analysis).await {
+            Ok(Ok(analyzed)) => eprintln!("Analyzed {} functions of {}", analyzed, args.input),
+            Ok(Err(error)) => {
+                eprintln!("Failed to analyze {}: {}", args.input, error);
+                std::process::exit(1)
+            }
+            Err(_) => {
+                eprintln!("Killed {} (timeout)", args.input);
+                std::process::exit(1)
+            }
+        }
+        return;
+    }
+    let binary = load_binary(&args.input, args.timeout, &config, progress.as_ref()).await;
+    match write_analysis(&args, &binary, &config, progress) {
+        Ok(analyzed) => eprintln!("Analyzed {} functions of {}", analyzed, args.input),
+        Err(error) => {
+            eprintln!("Failed to write the results in {}: {}", args.output, error);
+            std::process::exit(1)
+        }
+    }
+}
+
+// writes the results of the analyze subcommand, returning the amount of analyzed functions.
+fn write_analysis(
+    args: &AnalyzeArgs,
+    binary: &Binary,
+    config: &AnalysisConfig,
+    progress: Arc<dyn ProgressSink>,
+) -> Result<usize, io::Error> {
+    let dir = Path::new(&args.output);
+    fs::create_dir_all(dir)?;
+    let analyzed = binary
+        .functions()
+        .iter()
+        .filter_map(|function| Some((function, binary.cfg(function.get_offset())?)))
+        .collect::<Vec<_>>();
+    let cfss = config
+        .cfs_builder()
+        .with_progress(progress)
+        .build_all(analyzed.iter().map(|(_, cfg)| (*cfg).clone()));
+    let mut functions = Vec::new();
+    let mut structures = Vec::new();
+    let mut slowest = Vec::new();
+    for ((function, cfg), cfs) in analyzed.into_iter().zip(cfss) {
+        let offset = function.get_offset();
+        let cfs = match cfs {
+            Ok(cfs) => cfs,
+            Err(error) => {
+                eprintln!("Skipped function at {:#x}: {}", offset, error);
+                continue;
+            }
+        };
+        if matches!(args.format, AnalysisFormat::Dot | AnalysisFormat::All) {
+            cfg.to_file(dir.join(format!("{:#x}.cfg.dot", offset)))?;
+            cfs.to_file_tree(dir.join(format!("{:#x}.cfs.dot", offset)))?;
+        }
+        let timings = cfs
+            .timings()
+            .map(|timings| match binary.extraction_time(offset) {
+                Some(extraction) => timings.clone().with_extraction(extraction),
+                None => timings.clone(),
+            });
+        functions.push(analysis_json(
+            function,
+            &cfs,
+            timings.as_ref(),
+            args.mangled,
+        ));
+        if let Some(timings) = timings {
+            slowest.push((timings.total(), function));
+        }
+        structures.push((function, cfs));
+    }
+    print_slowest(slowest, args.mangled);
+    let report = AnalysisReport::new(&args.input, binary, &structures);
+    if matches!(args.format, AnalysisFormat::Markdown | AnalysisFormat::All) {
+        fs::write(dir.join("report.md"), report.to_markdown())?;
+    }
+    if matches!(args.format, AnalysisFormat::Html | AnalysisFormat::All) {
+        fs::write(dir.join("report.html"), report.to_html())?;
+    }
+    if matches!(args.format, AnalysisFormat::Json | AnalysisFormat::All) {
+        let json = format!(
+            "{{\"version\":{},\"binary\":{},\"architecture\":{},\"functions\":[{}]}}\n",
+            ANALYSIS_VERSION,
+            json_quote(&args.input),
+            architecture_json(binary),
+            functions.join(",")
+        );
+        fs::write(dir.join("functions.json"), json)?;
+    }
+    Ok(functions.len())
+}
+
+// same as write_analysis, but extracts, structures and writes one function at a time.
+async fn stream_analysis(
+    args: &AnalyzeArgs,
+    config: &AnalysisConfig,
+    progress: &dyn ProgressSink,
+) -> Result<usize, io::Error> {
+    let dir = Path::new(&args.output);
+    fs::create_dir_all(dir)?;
+    let dot = matches!(args.format, AnalysisFormat::Dot | AnalysisFormat::All);
+    let mut json = match args.format {
+        AnalysisFormat::Json | AnalysisFormat::All => {
+            let mut file = BufWriter::new(File::create(dir.join("functions.json"))?);
+            // the architecture is known only at the end, so it follows the functions
+            write!(
+                file,
+                "{{\"version\":{},\"binary\":{},\"functions\":[",
+                ANALYSIS_VERSION,
+                json_quote(&args.input)
+            )?;
+            Some(file)
+        }
+        _ => None,
+    };
+    let builder = config.cfs_builder();
+    let mut analyzed = 0;
+    let mut slowest = Vec::new();
+    let binary = Binary::analyze_streaming(
+        &args.input,
+        config.extraction(),
+        progress,
+        &CancellationToken::new(),
+        |function, cfg, extraction| {
+            let offset = function.get_offset();
+            let cfs = match builder.build(&cfg) {
+                Ok(cfs) => cfs,
+                Err(error) => {
+                    eprintln!("Skipped function at {:#x}: {}", offset, error);
+                    return Ok(());
+                }
+            };
+            if dot {
+                cfg.to_file(dir.join(format!("{:#x}.cfg.dot", offset)))?;
+                cfs.to_file_tree(dir.join(format!("{:#x}.cfs.dot", offset)))?;
+            }
+            let timings = cfs
+                .timings()
+                .map(|timings| timings.clone().with_extraction(extraction));
+            if let Some(file) = json.as_mut() {
+                if analyzed > 0 {
+                    file.write_all(b",")?;
+                }
+                let encoded = analysis_json(function, &cfs, timings.as_ref(), args.mangled);
+                file.write_all(encoded.as_bytes())?;
+            }
+            if let Some(timings) = timings {
+                slowest.push((timings.total(), offset));
+            }
+            analyzed += 1;
+            Ok(())
+        },
+    )
+    .await?;
+    if let Some(mut file) = json {
+        writeln!(file, "],\"architecture\":{}}}", architecture_json(&binary))?;
+        file.flush()?;
+    }
+    let functions = binary.functions();
+    let slowest = slowest
+        .into_iter()
+        .filter_map(|(total, offset)| {
+            let index = functions
+                .binary_search_by_key(&offset, Function::get_offset)
+                .ok()?;
+            Some((total, &functions[index]))
+        })
+        .collect();
+    print_slowest(slowest, args.mangled);
+    Ok(analyzed)
+}
+
+// prints the functions taking the longest time to analyze, if any.
+fn print_slowest(mut slowest: Vec<(Duration, &Function)>, mangled: bool) {
+    slowest.sort_by_key(|(total, function)| (Reverse(*total), function.get_offset()));
+    if !slowest.is_empty() {
+        eprintln!("Slowest functions:");
+    }
+    for (total, function) in slowest.iter().take(SLOWEST_FUNCTIONS) {
+        eprintln!(
+            "{:>10.3?} {:#x} {}",
+            total,
+            function.get_offset(),
+            function_name(function.get_name(), mangled)
+        );
+    }
+}
+
+// encodes the architecture of the binary as a JSON object, or null if unknown.
+fn architecture_json(binary: &Binary) -> String {
+    binary.architecture().map_or("null".to_string(), |arch| {
+        format!(
+            "{{\"name\":{},\"bits\":{}}}",
+            json_quote(arch.name()),
+            arch.bits()
+        )
+    })
+}
+
+fn analysis_json(
+    function: &Function,
+    cfs: &CFS,
+    timings: Option<&Timings>,
+    mangled: bool,
+) -> String {
+    let cfg = cfs.get_cfg();
+    let edges = cfg.bfs().map(|bb| cfg.neighbours(bb).len()).sum::<usize>();
+    let mut json = format!(
+        "{{\"name\":{},\"offset\":\"{:#x}\",\"blocks\":{},\"edges\":{},",
+        json_quote(&function_name(function.get_name(), mangled)),
+        function.get_offset(),
+        cfg.len(),
+        edges
     );
+    match cfs.get_tree() {
+        Some(tree) => {
+            let metrics = tree.metrics();
+            let ranges = tree
+                .address_ranges()
+                .iter()
+                .map(|range| {
+                    format!(
+                        "{{\"start\":\"{:#x}\",\"end\":\"{:#x}\"}}",
+                        range.start, range.end
+                    )
+                })
+                .collect::<Vec<_>>();
+            write!(
+                json,
+                "\"ranges\":[{}],\"depth\":{},\"nodes\":{},\"leaves\":{},\
+                 \"structural_hash\":\"{:#x}\",\"tree\":{}",
+                ranges.join(","),
+                metrics.depth(),
+                metrics.nodes(),
+                metrics.leaves(),
+                tree.structural_hash(),
+                json_quote(&tree.to_sexpr())
+            )
+            .unwrap();
+        }
+        // the CFG is empty
+        None => json.push_str("\"ranges\":[],\"tree\":null"),
+    }
+    if let Some(timings) = timings {
+        write!(json, ",\"timings\":{}", timings_json(timings)).unwrap();
+    }
+    json.push('}');
+    json
+}
+
+// encodes the timings as a JSON object, in seconds.
+fn timings_json(timings: &Timings) -> String {
+    let reductions = timings
+        .reductions()
+        .into_iter()
+        .map(|(name, elapsed)| format!("{}:{}", json_quote(name), elapsed.as_secs_f64()))
+        .collect::<Vec<_>>();
+    let extraction = timings.extraction().map_or("null".to_string(), |elapsed| {
+        elapsed.as_secs_f64().to_string()
+    });
+    format!(
+        "{{\"extraction\":{},\"denaturation\":{},\"reductions\":{{{}}},\"structuring\":{}}}",
+        extraction,
+        timings.denaturation().as_secs_f64(),
+        reductions.join(","),
+        timings.structuring().as_secs_f64()
+    )
 }
 
 fn structural_analysis_only(analysis_res: &AnalysisResult, threshold: u32) -> Vec<CloneClass> {
@@ -237,6 +963,7 @@ fn print_results(
     bbs: bool,
     csv: bool,
     filter: bool,
+    mangled: bool,
 ) {
     if filter {
         let mut map: HashMap<Vec<(&str, &str)>, CloneClass> = HashMap::new();
@@ -261,19 +988,28 @@ fn print_results(
         SortResult::SizeDesc => classes.sort_unstable_by_key(|a| Reverse(a.len())),
     }
     if csv {
-        print_csv(classes, bbs)
+        print_csv(classes, bbs, mangled)
+    } else {
+        print_stdout(classes, bbs, mangled)
+    }
+}
+
+fn function_name(func: &str, mangled: bool) -> Cow<str> {
+    if mangled {
+        Cow::Borrowed(func)
     } else {
-        print_stdout(classes, bbs)
+        demangle(func).map_or(Cow::Borrowed(func), Cow::Owned)
     }
 }
 
-fn print_stdout(classes: Vec<CloneClass>, bbs: bool) {
+fn print_stdout(classes: Vec<CloneClass>, bbs: bool, mangled: bool) {
     let mut clones = 0;
     let classes_no = classes.len();
     for class in classes {
         println!("----- CLONE CLASS ({}) -----", class.depth());
         for (bin, func, maybe_cfs) in class {
             clones += 1;
+            let func = function_name(func, mangled);
             if !bbs {
                 println!("{} :: {}", bin, func);
             } else if let Some(cfs) = maybe_cfs {
@@ -292,7 +1028,7 @@ fn print_stdout(classes: Vec<CloneClass>, bbs: bool) {
     println!("Classes: {} Clones: {}", classes_no, clones);
 }
 
-fn print_csv(classes: Vec<CloneClass>, bbs: bool) {
+fn print_csv(classes: Vec<CloneClass>, bbs: bool, mangled: bool) {
     print!("arch,bits,binary,function,clone_class_id,class_depth");
     if bbs {
         println!(",basic_blocks");
@@ -309,6 +1045,13 @@ fn print_csv(classes: Vec<CloneClass>, bbs: bool) {
             let arch_substring = &bin[1..arch_substring_end + 1];
             let bits_substring = &bin[arch_substring_end + 2..archbits_substring_end];
             let bin_substring = &bin[archbits_substring_end + 1..];
+            // demangled names may contain commas
+            let func = function_name(func, mangled);
+            let func = if func.contains(',') {
+                Cow::Owned(format!("\"{}\"", func.replace('"', "\"\"")))
+            } else {
+                func
+            };
             print!(
                 "{},{},{},{},{},{}",
                 arch_substring, bits_substring, bin_substring, func, class_id, class_depth
@@ -333,6 +1076,87 @@ fn print_csv(classes: Vec<CloneClass>, bbs: bool) {
     }
 }
 
+async fn serve_binaries(args: ServeArgs) {
+    let config = load_config(args.config.as_deref());
+    let server = Server::new().with_config(config.clone());
+    for path in &args.input {
+        let binary = load_binary(path, u64::MAX, &config, &()).await;
+        server.insert(path, binary);
+    }
+    // the clients of the socket are served by tasks on the current thread, sharing the server
+    let server = Rc::new(server);
+    let served = match &args.socket {
+        Some(socket) => {
+            LocalSet::new()
+                .run_until(serve_socket(server, socket))
+                .await
+        }
+        None => serve_client(&server, tokio::io::stdin(), tokio::io::stdout()).await,
+    };
+    if let Err(error) = served {
+        eprintln!("Server error: {}", error);
+        std::process::exit(1)
+    }
+}
+
+// accepts clients on the unix socket until one of them requests the shutdown.
+#[cfg(unix)]
+async fn serve_socket(server: Rc<Server>, socket: &str) -> Result<(), io::Error> {
+    let listener = tokio::net::UnixListener::bind(socket)?;
+    let shutdown = Rc::new(tokio::sync::Notify::new());
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (stream, _) = accepted?;
+                let server = Rc::clone(&server);
+                let shutdown = Rc::clone(&shutdown);
+                tokio::task::spawn_local(async move {
+                    let (reader, writer) = stream.into_split();
+                    if let Err(error) = serve_client(&server, reader, writer).await {
+                        eprintln!("Client error: {}", error);
+                    }
+                    if server.is_shutdown() {
+                        shutdown.notify_one();
+                    }
+                });
+            }
+            _ = shutdown.notified() => break,
+        }
+    }
+    fs::remove_file(socket)
+}
+
+#[cfg(not(unix))]
+async fn serve_socket(_: Rc<Server>, _: &str) -> Result<(), io::Error> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "unix sockets are not supported on this platform",
+    ))
+}
+
+// answers the requests of a client, one per line, until it disconnects or requests the shutdown.
+async fn serve_client<R, W>(server: &Server, reader: R, mut writer: W) -> Result<(), io::Error>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = server.handle(&line).await {
+            writer.write_all(response.as_bytes()).await?;
+            writer.write_all(b"\n").await?;
+            writer.flush().await?;
+        }
+        if server.is_shutdown() {
+            break;
+        }
+    }
+    Ok(())
+}
+
 struct AnalysisResult {
     // reversed cache containing all the bin/fun names
     string_cache: FnvHashMap<u32, String>,
@@ -454,15 +1278,17 @@ async fn gather_analysis_data_job(
             for func in funcs {
                 if let Some(bare) = disassembler.get_function_cfg(func).await {
                     if let Some(func_name) = names.get(&func) {
-                        let cfg = CFG::from(bare);
+                        let stmts = disassembler.get_function_body(func).await;
+                        let body = stmts.as_deref().unwrap_or_default();
+                        let cfg = CFG::with_statements(bare, body, arch);
                         if cfg.len() > 1 {
                             let cfs = if !disable_structural {
-                                CFS::new(&cfg).get_tree()
+                                CFS::from_cfg(cfg).ok().and_then(|cfs| cfs.get_tree())
                             } else {
                                 None
                             };
                             let fvec = if !disable_semantic {
-                                disassembler.get_function_body(func).await.map(|stmts| {
+                                stmts.map(|stmts| {
                                     FVec::new(stmts, &mut opcode_cache.lock().unwrap(), cross_arch)
                                 })
                             } else {
@@ -75,6 +75,45 @@ impl Architecture {
         }
     }
 
+    /// Returns the [`Architecture`] with the given name and amount of bits.
+    ///
+    /// The name is the one returned by [`Architecture::name`]. The bits are ignored by the
+    /// architectures available in a single size. Returns [`None`] if the name is unknown.
+    /// # Examples
+    /// Basic usage:
+    /// ```
+    /// # use bincc::disasm::Architecture;
+    /// let arch = Architecture::from_name("x86", 64);
+    ///
+    /// assert_eq!(arch, Some(Architecture::X86(64)));
+    /// assert_eq!(Architecture::from_name("pdp11", 16), None);
+    /// ```
+    pub fn from_name(name: &str, bits: u32) -> Option<Architecture> {
+        match name {
+            "arc" => Some(Architecture::ARC(bits)),
+            "avr" => Some(Architecture::AVR),
+            "arm" => Some(Architecture::Arm(bits)),
+            "i4004" => Some(Architecture::I4004),
+            "8051" => Some(Architecture::I8051(bits)),
+            "i8080" => Some(Architecture::I8080),
+            "lm32" => Some(Architecture::LM32),
+            "LH5801" => Some(Architecture::Lh5801),
+            "6502" => Some(Architecture::M6502),
+            "m68k" => Some(Architecture::M68K),
+            "msp430" => Some(Architecture::MSP430),
+            "propeller" => Some(Architecture::Propeller),
+            "v850" => Some(Architecture::V850),
+            "z80" => Some(Architecture::Z80),
+            "s390" => Some(Architecture::S390(bits)),
+            "ppc" => Some(Architecture::PowerPC(bits)),
+            "mips" => Some(Architecture::Mips(bits)),
+            "riscv" => Some(Architecture::Riscv(bits)),
+            "sparc" => Some(Architecture::Sparc(bits)),
+            "x86" => Some(Architecture::X86(bits)),
+            _ => None,
+        }
+    }
+
     /// Returns the number of bits of this [`Architecture`].
     pub fn bits(&self) -> u32 {
         match self {
@@ -102,6 +141,9 @@ impl Architecture {
     }
 
     /// Returns the type of jump of the input instruction
+    ///
+    /// Jumps whose target is computed at runtime, like `jmp rax` or `br x16`, are reported as
+    /// jumps as well.
     /// # Examples
     /// Basic usage:
     /// ```
@@ -111,13 +153,58 @@ impl Architecture {
     ///
     /// assert_eq!(jmp_type, JumpType::JumpConditional);
     /// ```
+    /// # Panics
+    /// Panics if the jumps of this [`Architecture`] are not known, see
+    /// [`Architecture::supports_jumps`].
     pub fn jump(&self, mnemonic: &str) -> JumpType {
         match self {
             Architecture::X86(_) => jump_x86(mnemonic),
+            Architecture::Arm(64) => jump_aarch64(mnemonic),
             Architecture::Arm(_) => jump_arm(mnemonic),
+            Architecture::Mips(_) => jump_mips(mnemonic),
+            Architecture::PowerPC(_) => jump_ppc(mnemonic),
             _ => unimplemented!(),
         }
     }
+
+    /// Returns true if [`Architecture::jump`] knows the jumps of this [`Architecture`].
+    ///
+    /// The CFGs of the other architectures can not be built from their statements, but only from
+    /// the blocks and edges found by a disassembler.
+    /// # Examples
+    /// Basic usage:
+    /// ```
+    /// # use bincc::disasm::Architecture;
+    /// assert!(Architecture::Mips(32).supports_jumps());
+    /// assert!(!Architecture::Riscv(64).supports_jumps());
+    /// ```
+    pub fn supports_jumps(&self) -> bool {
+        matches!(
+            self,
+            Architecture::X86(_)
+                | Architecture::Arm(_)
+                | Architecture::Mips(_)
+                | Architecture::PowerPC(_)
+        )
+    }
+
+    /// Returns the amount of delay slots of the jumps of this [`Architecture`].
+    ///
+    /// The instructions in the delay slots follow a jump, but are executed before the jump is
+    /// taken. For this reason, they belong to the same basic block of the jump.
+    /// # Examples
+    /// Basic usage:
+    /// ```
+    /// # use bincc::disasm::Architecture;
+    /// assert_eq!(Architecture::Mips(32).delay_slots(), 1);
+    /// assert_eq!(Architecture::X86(64).delay_slots(), 0);
+    /// ```
+    pub fn delay_slots(&self) -> usize {
+        match self {
+            Architecture::Mips(_) => 1,
+            _ => 0,
+        }
+    }
 }
 
 fn jump_x86(mnemonic: &str) -> JumpType {
@@ -177,6 +264,9 @@ fn jump_arm(mnemonic: &str) -> JumpType {
         } else {
             JumpType::JumpUnconditional
         }
+    } else if mnemonic == "cbz" || mnemonic == "cbnz" {
+        // thumb compare and branch
+        JumpType::JumpConditional
     } else if conditionless_mnemonic == "bx" {
         if conditionless_mnemonic != mnemonic {
             JumpType::RetConditional
@@ -188,6 +278,61 @@ fn jump_arm(mnemonic: &str) -> JumpType {
     }
 }
 
+fn jump_mips(mnemonic: &str) -> JumpType {
+    let conditional = |mnemonic: &str| {
+        matches!(
+            mnemonic,
+            "beq" | "bne" | "beqz" | "bnez" | "bgez" | "bgtz" | "blez" | "bltz" | "bc1t" | "bc1f"
+        )
+    };
+    match mnemonic {
+        "b" | "j" | "jr" | "jr.hb" => JumpType::JumpUnconditional,
+        "eret" | "deret" => JumpType::RetUnconditional,
+        // branch likely instructions differ only in the delay slot
+        _ if conditional(mnemonic) || mnemonic.strip_suffix('l').is_some_and(conditional) => {
+            JumpType::JumpConditional
+        }
+        _ => JumpType::NoJump,
+    }
+}
+
+fn jump_ppc(mnemonic: &str) -> JumpType {
+    // branch prediction hints
+    let mnemonic = mnemonic.trim_end_matches(['+', '-']);
+    let conditional = |condition: &str| {
+        [
+            "eq", "ne", "lt", "gt", "le", "ge", "nl", "ng", "so", "ns", "un", "nu", "dnz", "dz",
+            "dnzt", "dnzf", "dzt", "dzf", "c", "t", "f",
+        ]
+        .contains(&condition)
+    };
+    match mnemonic {
+        "b" | "ba" | "bctr" => JumpType::JumpUnconditional,
+        "blr" | "rfi" | "rfid" => JumpType::RetUnconditional,
+        // the variants ending with `l` are calls, as they update the link register
+        _ => match mnemonic.strip_prefix('b') {
+            Some(cond) if conditional(cond) => JumpType::JumpConditional,
+            Some(cond) if cond.strip_suffix("ctr").is_some_and(conditional) => {
+                JumpType::JumpConditional
+            }
+            Some(cond) if cond.strip_suffix("lr").is_some_and(conditional) => {
+                JumpType::RetConditional
+            }
+            _ => JumpType::NoJump,
+        },
+    }
+}
+
+fn jump_aarch64(mnemonic: &str) -> JumpType {
+    match mnemonic {
+        "ret" | "retaa" | "retab" | "eret" => JumpType::RetUnconditional,
+        "b" | "br" | "braa" | "brab" | "braaz" | "brabz" => JumpType::JumpUnconditional,
+        "cbz" | "cbnz" | "tbz" | "tbnz" => JumpType::JumpConditional,
+        _ if remove_condition_arm(mnemonic) == "b" && mnemonic != "b" => JumpType::JumpConditional,
+        _ => JumpType::NoJump,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::disasm::{architectures::remove_condition_arm, Architecture, JumpType};
@@ -286,6 +431,16 @@ mod tests {
         assert_eq!(arch.jump(mne), JumpType::RetUnconditional);
     }
 
+    #[test]
+    fn supports_jumps() {
+        assert!(Architecture::X86(32).supports_jumps());
+        assert!(Architecture::Arm(64).supports_jumps());
+        assert!(Architecture::PowerPC(32).supports_jumps());
+        assert!(!Architecture::Riscv(64).supports_jumps());
+        assert!(!Architecture::Sparc(32).supports_jumps());
+        assert!(!Architecture::AVR.supports_jumps());
+    }
+
     #[test]
     fn arm_jump() {
         let arch = Architecture::Arm(32);
@@ -332,6 +487,87 @@ mod tests {
         assert_eq!(arch.jump(mne), JumpType::RetUnconditional);
         mne = "ret";
         assert_eq!(arch.jump(mne), JumpType::NoJump);
+        mne = "cbnz";
+        assert_eq!(arch.jump(mne), JumpType::JumpConditional);
+    }
+
+    #[test]
+    fn mips_jump() {
+        let arch = Architecture::Mips(32);
+        let expected = [
+            ("beq", JumpType::JumpConditional),
+            ("bnez", JumpType::JumpConditional),
+            ("bltz", JumpType::JumpConditional),
+            ("beql", JumpType::JumpConditional),
+            ("bc1fl", JumpType::JumpConditional),
+            ("b", JumpType::JumpUnconditional),
+            ("j", JumpType::JumpUnconditional),
+            ("jr", JumpType::JumpUnconditional),
+            ("eret", JumpType::RetUnconditional),
+            ("jal", JumpType::NoJump),
+            ("jalr", JumpType::NoJump),
+            ("bal", JumpType::NoJump),
+            ("bgezal", JumpType::NoJump),
+            ("lw", JumpType::NoJump),
+        ];
+        for (mne, jump) in expected {
+            assert_eq!(arch.jump(mne), jump, "{}", mne);
+        }
+    }
+
+    #[test]
+    fn ppc_jump() {
+        let arch = Architecture::PowerPC(32);
+        let expected = [
+            ("beq", JumpType::JumpConditional),
+            ("bne-", JumpType::JumpConditional),
+            ("bge+", JumpType::JumpConditional),
+            ("bdnz", JumpType::JumpConditional),
+            ("bdz", JumpType::JumpConditional),
+            ("bc", JumpType::JumpConditional),
+            ("bnectr", JumpType::JumpConditional),
+            ("bcctr", JumpType::JumpConditional),
+            ("b", JumpType::JumpUnconditional),
+            ("bctr", JumpType::JumpUnconditional),
+            ("blr", JumpType::RetUnconditional),
+            ("beqlr", JumpType::RetConditional),
+            ("bdnzlr", JumpType::RetConditional),
+            ("bclr", JumpType::RetConditional),
+            ("bl", JumpType::NoJump),
+            ("bctrl", JumpType::NoJump),
+            ("blrl", JumpType::NoJump),
+            ("beql", JumpType::NoJump),
+            ("mtctr", JumpType::NoJump),
+        ];
+        for (mne, jump) in expected {
+            assert_eq!(arch.jump(mne), jump, "{}", mne);
+        }
+    }
+
+    #[test]
+    fn aarch64_jump() {
+        let arch = Architecture::Arm(64);
+        let expected = [
+            ("b.eq", JumpType::JumpConditional),
+            ("b.hs", JumpType::JumpConditional),
+            ("bne", JumpType::JumpConditional),
+            ("cbz", JumpType::JumpConditional),
+            ("cbnz", JumpType::JumpConditional),
+            ("tbz", JumpType::JumpConditional),
+            ("tbnz", JumpType::JumpConditional),
+            ("b", JumpType::JumpUnconditional),
+            ("br", JumpType::JumpUnconditional),
+            ("braaz", JumpType::JumpUnconditional),
+            ("ret", JumpType::RetUnconditional),
+            ("retab", JumpType::RetUnconditional),
+            ("bl", JumpType::NoJump),
+            ("blr", JumpType::NoJump),
+            ("bx", JumpType::NoJump),
+            ("adrp", JumpType::NoJump),
+        ];
+        for (mne, jump) in expected {
+            assert_eq!(arch.jump(mne), jump, "{}", mne);
+        }
     }
 
     #[test]
@@ -0,0 +1,1426 @@
+#[cfg(feature = "tracing")]
+use crate::analysis::Graph;
+use crate::analysis::{CallTarget, CancellationToken, FunctionMetadata, Phase, ProgressSink, CFG};
+#[cfg(feature = "radare2")]
+use crate::disasm::radare2::R2Disasm;
+use crate::disasm::{
+    demangle, Architecture, Disassembler, Endianness, Function, MemoryImage, Relocation, Symbol,
+    SymbolKind, SymbolTable,
+};
+#[cfg(feature = "radare2")]
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+#[cfg(feature = "radare2")]
+use std::fs;
+use std::io;
+use std::io::ErrorKind;
+#[cfg(feature = "radare2")]
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const BINARY_MAGIC: &[u8; 4] = b"BCCB";
+// version of the container, the CFGs inside have their own version.
+const BINARY_VERSION: u32 = 6;
+
+/// Options of the extraction performed by [`Binary::analyze_with`].
+/// # Examples
+/// Basic usage:
+/// ```
+/// use bincc::disasm::{Architecture, ExtractionConfig};
+///
+/// let config = ExtractionConfig::new()
+///     .with_arch(Architecture::Arm(32))
+///     .with_bits(16);
+///
+/// assert_eq!(config.arch(), Some("arm"));
+/// assert_eq!(config.bits(), Some(16));
+/// assert_eq!(config.endianness(), None);
+/// assert_eq!(ExtractionConfig::default().bits(), None);
+/// ```
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(docsrs, doc(cfg(feature = "disasm")))]
+pub struct ExtractionConfig {
+    arch: Option<&'static str>,
+    bits: Option<u32>,
+    endianness: Option<Endianness>,
+}
+
+impl ExtractionConfig {
+    /// Creates the default configuration, detecting everything from the executable.
+    pub fn new() -> ExtractionConfig {
+        ExtractionConfig::default()
+    }
+
+    /// Forces the architecture used to decode the instructions, like ARM for a raw firmware.
+    ///
+    /// By default, the architecture is detected from the executable. Only the name of the given
+    /// architecture, see [`Architecture::name`], is forced: the amount of bits is detected as
+    /// well, unless forced with [`ExtractionConfig::with_bits`].
+    pub fn with_arch(mut self, arch: Architecture) -> ExtractionConfig {
+        self.arch = Some(arch.name());
+        self
+    }
+
+    /// Returns the name of the architecture used to decode the instructions, if forced.
+    pub fn arch(&self) -> Option<&'static str> {
+        self.arch
+    }
+
+    /// Forces the amount of bits used to decode the instructions, like 16 for real-mode x86.
+    ///
+    /// By default, the bits are detected from the executable and from the analysis of each
+    /// function, see [`Binary::function_architecture`].
+    pub fn with_bits(mut self, bits: u32) -> ExtractionConfig {
+        self.bits = Some(bits);
+        self
+    }
+
+    /// Returns the amount of bits used to decode the instructions, if forced.
+    pub fn bits(&self) -> Option<u32> {
+        self.bits
+    }
+
+    /// Forces the endianness of the executable, for the architectures supporting both.
+    ///
+    /// By default, the endianness is detected from the executable. This is the endianness used
+    /// to decode the instructions and to read the values of the
+    /// [`MemoryImage`](crate::disasm::MemoryImage).
+    pub fn with_endianness(mut self, endianness: Endianness) -> ExtractionConfig {
+        self.endianness = Some(endianness);
+        self
+    }
+
+    /// Returns the endianness of the executable, if forced.
+    pub fn endianness(&self) -> Option<Endianness> {
+        self.endianness
+    }
+}
+
+/// Functions extracted from an executable, along with their [`CFG`].
+///
+/// Extracting the control flow of every function is usually the most expensive part of an
+/// analysis, so the result can be stored with [`Binary::analyze_cached`] and reused by the
+/// following runs.
+#[cfg_attr(docsrs, doc(cfg(feature = "disasm")))]
+pub struct Binary {
+    architecture: Option<Architecture>,
+    // sorted by offset
+    functions: Vec<Function>,
+    cfgs: HashMap<u64, CFG>,
+    symbols: SymbolTable,
+    // sorted by address
+    relocations: Vec<Relocation>,
+    // functions whose architecture differs from the one of the executable
+    function_architectures: HashMap<u64, Architecture>,
+    // import reached by each thunk, derived from the other fields
+    thunks: HashMap<u64, String>,
+    memory: Option<MemoryImage>,
+    // time spent disassembling each function, known only for the extracted binaries
+    extraction_times: HashMap<u64, Duration>,
+}
+
+impl Binary {
+    /// Disassembles the executable at the given path with radare2, and extracts the [`CFG`] of
+    /// every function found.
+    ///
+    /// Returns the same errors of [`R2Disasm::new`], or [`io::ErrorKind::InvalidInput`] if the
+    /// path is not valid unicode.
+    #[cfg(feature = "radare2")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "radare2")))]
+    pub async fn analyze<P: AsRef<Path>>(path: P) -> Result<Binary, io::Error> {
+        Binary::analyze_with(path, ExtractionConfig::default()).await
+    }
+
+    /// Same as [`Binary::analyze`], but with the given configuration.
+    #[cfg(feature = "radare2")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "radare2")))]
+    pub async fn analyze_with<P: AsRef<Path>>(
+        path: P,
+        config: ExtractionConfig,
+    ) -> Result<Binary, io::Error> {
+        Binary::analyze_with_progress(path, config, &()).await
+    }
+
+    /// Same as [`Binary::analyze_with`], but reports the progress of the extraction to the given
+    /// sink, see [`Binary::extract_with_progress`].
+    ///
+    /// The analysis performed by radare2 before the extraction is not reported.
+    #[cfg(feature = "radare2")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "radare2")))]
+    pub async fn analyze_with_progress<P: AsRef<Path>>(
+        path: P,
+        config: ExtractionConfig,
+        progress: &dyn ProgressSink,
+    ) -> Result<Binary, io::Error> {
+        let token = CancellationToken::new();
+        Binary::analyze_with_cancellation(path, config, progress, &token).await
+    }
+
+    /// Same as [`Binary::analyze_with_progress`], but stops the extraction when the given token
+    /// is cancelled, see [`Binary::extract_with_cancellation`].
+    ///
+    /// The analysis performed by radare2 before the extraction can not be interrupted: the token
+    /// is checked only once it completes.
+    #[cfg(feature = "radare2")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "radare2")))]
+    pub async fn analyze_with_cancellation<P: AsRef<Path>>(
+        path: P,
+        config: ExtractionConfig,
+        progress: &dyn ProgressSink,
+        token: &CancellationToken,
+    ) -> Result<Binary, io::Error> {
+        let mut disassembler = open(path.as_ref(), config).await?;
+        Ok(Binary::extract_with_cancellation(&mut disassembler, progress, token).await)
+    }
+
+    /// Same as [`Binary::analyze_with_cancellation`], but passes each [`CFG`] to the given
+    /// function instead of storing it, see [`Binary::extract_streaming`].
+    #[cfg(feature = "radare2")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "radare2")))]
+    pub async fn analyze_streaming<P, F>(
+        path: P,
+        config: ExtractionConfig,
+        progress: &dyn ProgressSink,
+        token: &CancellationToken,
+        sink: F,
+    ) -> Result<Binary, io::Error>
+    where
+        P: AsRef<Path>,
+        F: FnMut(&Function, CFG, Duration) -> Result<(), io::Error>,
+    {
+        let mut disassembler = open(path.as_ref(), config).await?;
+        Binary::extract_streaming(&mut disassembler, progress, token, sink).await
+    }
+
+    /// Same as [`Binary::analyze`], but stores the result in the given directory, and returns
+    /// the stored result if the same executable has already been analyzed.
+    ///
+    /// The results are addressed by a hash of the content of the executable, so moving or
+    /// renaming it does not invalidate them, while modifying it does. Stored results that can
+    /// not be read, like the ones written by a different version of this library, are replaced.
+    /// The results are written to a temporary file and then renamed, so concurrent analyses of
+    /// the same executable never observe a partially written result.
+    #[cfg(feature = "radare2")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "radare2")))]
+    pub async fn analyze_cached<P: AsRef<Path>, Q: AsRef<Path>>(
+        path: P,
+        cache_dir: Q,
+    ) -> Result<Binary, io::Error> {
+        Binary::analyze_cached_with(path, cache_dir, ExtractionConfig::default()).await
+    }
+
+    /// Same as [`Binary::analyze_cached`], but with the given configuration.
+    ///
+    /// Results obtained with different configurations are stored separately.
+    #[cfg(feature = "radare2")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "radare2")))]
+    pub async fn analyze_cached_with<P: AsRef<Path>, Q: AsRef<Path>>(
+        path: P,
+        cache_dir: Q,
+        config: ExtractionConfig,
+    ) -> Result<Binary, io::Error> {
+        let cached = cache_path(&fs::read(&path)?, config, cache_dir.as_ref());
+        if let Some(binary) = fs::read(&cached)
+            .ok()
+            .and_then(|data| Binary::from_bytes(&data).ok())
+        {
+            return Ok(binary);
+        }
+        let binary = Binary::analyze_with(path, config).await?;
+        fs::create_dir_all(cache_dir)?;
+        // the rename is atomic, so a concurrent analysis never reads a partially written result
+        let partial = cached.with_extension(format!("{}.tmp", std::process::id()));
+        fs::write(&partial, binary.to_bytes())?;
+        if let Err(error) = fs::rename(&partial, &cached) {
+            let _ = fs::remove_file(&partial);
+            return Err(error);
+        }
+        Ok(binary)
+    }
+
+    /// Extracts the [`CFG`] of every function found by the given [`Disassembler`].
+    ///
+    /// Each [`CFG`] is labeled with the name, demangled with [`demangle`], and size of its
+    /// function, see [`CFG::metadata`].
+    /// The size is taken from the [`Disassembler::symbols`] if known, otherwise it spans the basic
+    /// blocks of the [`CFG`]. Functions whose [`CFG`] can not be computed are reported by
+    /// [`Binary::functions`] but have no [`CFG`].
+    ///
+    /// The calls patched by a relocation, see [`Disassembler::relocations`], and the calls to an
+    /// import thunk, see [`Binary::thunk_target`], are recorded as calls to the
+    /// [`CallTarget::Symbol`] named after the relocated symbol or the import. The same holds for
+    /// the relocated jumps and data accesses, see [`CFG::references`], as in the object files.
+    pub async fn extract<D: Disassembler>(disassembler: &mut D) -> Binary {
+        Binary::extract_with_progress(disassembler, &()).await
+    }
+
+    /// Same as [`Binary::extract`], but reports the progress to the given sink as
+    /// [`Phase::Disassembly`].
+    ///
+    /// Every function found by the [`Disassembler`] is reported as completed, including the ones
+    /// whose [`CFG`] can not be computed.
+    pub async fn extract_with_progress<D: Disassembler>(
+        disassembler: &mut D,
+        progress: &dyn ProgressSink,
+    ) -> Binary {
+        Binary::extract_with_cancellation(disassembler, progress, &CancellationToken::new()).await
+    }
+
+    /// Same as [`Binary::extract_with_progress`], but stops when the given token is cancelled.
+    ///
+    /// The token is checked before extracting each function. Once cancelled, the returned
+    /// [`Binary`] contains the [`CFG`]s extracted so far, while the remaining functions are
+    /// reported by [`Binary::functions`] without a [`CFG`], as if they could not be computed.
+    /// The phase is finished without reporting the remaining functions as completed.
+    pub async fn extract_with_cancellation<D: Disassembler>(
+        disassembler: &mut D,
+        progress: &dyn ProgressSink,
+        token: &CancellationToken,
+    ) -> Binary {
+        let architecture = disassembler.architecture().await;
+        let functions = disassembler.functions().await;
+        let symbols = disassembler.symbols().await;
+        let relocations = disassembler.relocations().await;
+        let mut cfgs = HashMap::with_capacity(functions.len());
+        let mut function_architectures = HashMap::new();
+        let mut extraction_times = HashMap::with_capacity(functions.len());
+        progress.phase_started(Phase::Disassembly, functions.len());
+        for function in &functions {
+            if token.is_cancelled() {
+                break;
+            }
+            let offset = function.get_offset();
+            let extracted = extract_function(
+                disassembler,
+                function,
+                architecture,
+                &symbols,
+                &mut function_architectures,
+                &mut extraction_times,
+            )
+            .await;
+            if let Some(cfg) = extracted {
+                cfgs.insert(offset, cfg);
+            }
+            progress.function_completed(Phase::Disassembly, offset);
+        }
+        progress.phase_finished(Phase::Disassembly);
+        let thunks = find_thunks(&functions, &cfgs, &symbols);
+        let names = thunk_names(&functions, &thunks);
+        for cfg in cfgs.values_mut() {
+            resolve_calls(cfg, &relocations, &thunks, &names);
+        }
+        Binary {
+            architecture,
+            functions,
+            cfgs,
+            symbols,
+            relocations,
+            function_architectures,
+            thunks,
+            memory: None,
+            extraction_times,
+        }
+    }
+
+    /// Same as [`Binary::extract_with_cancellation`], but passes each [`CFG`] to the given
+    /// function as soon as it is extracted, together with the time spent extracting it, instead
+    /// of storing it.
+    ///
+    /// This is meant for executables too large for all their [`CFG`]s to fit in memory: the
+    /// function can analyze each [`CFG`] and write the results to disk before the next one is
+    /// extracted, so only one [`CFG`] at a time is kept in memory. The extraction stops at the
+    /// first error returned by the function, returning it. The memory used by the
+    /// [`Disassembler`], like the analysis held by radare2, and by the tables of the executable,
+    /// like its symbols and relocations, is still proportional to the size of the executable.
+    ///
+    /// The [`CFG`]s are labeled and their calls resolved as in [`Binary::extract`]. The thunks
+    /// recognized by [`CFG::is_thunk`] must be known before extracting their callers, so the
+    /// functions small enough to be a thunk are disassembled twice: once to find the thunks, and
+    /// once to be passed to the function. The returned [`Binary`] contains everything but the
+    /// [`CFG`]s.
+    /// # Examples
+    /// Basic usage:
+    /// ```no_run
+    /// use bincc::analysis::{CancellationToken, CFS};
+    /// use bincc::disasm::{Binary, Disassembler};
+    /// use std::fs::File;
+    /// use std::io::{BufWriter, Write};
+    ///
+    /// # async fn run<D: Disassembler>(mut disassembler: D) -> Result<(), std::io::Error> {
+    /// let mut out = BufWriter::new(File::create("depths.txt")?);
+    /// let token = CancellationToken::new();
+    /// let binary = Binary::extract_streaming(&mut disassembler, &(), &token, |function, cfg, _| {
+    ///     if let Some(tree) = CFS::new(&cfg).ok().and_then(|cfs| cfs.get_tree()) {
+    ///         writeln!(out, "{} {}", function.get_name(), tree.depth())?;
+    ///     }
+    ///     Ok(())
+    /// })
+    /// .await?;
+    ///
+    /// assert!(binary.cfg(binary.functions()[0].get_offset()).is_none());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn extract_streaming<D, F>(
+        disassembler: &mut D,
+        progress: &dyn ProgressSink,
+        token: &CancellationToken,
+        mut sink: F,
+    ) -> Result<Binary, io::Error>
+    where
+        D: Disassembler,
+        F: FnMut(&Function, CFG, Duration) -> Result<(), io::Error>,
+    {
+        let architecture = disassembler.architecture().await;
+        let functions = disassembler.functions().await;
+        let symbols = disassembler.symbols().await;
+        let relocations = disassembler.relocations().await;
+        let mut function_architectures = HashMap::new();
+        let mut extraction_times = HashMap::with_capacity(functions.len());
+        let thunks = scan_thunks(disassembler, &functions, &symbols).await;
+        let names = thunk_names(&functions, &thunks);
+        progress.phase_started(Phase::Disassembly, functions.len());
+        for function in &functions {
+            if token.is_cancelled() {
+                break;
+            }
+            let offset = function.get_offset();
+            let extracted = extract_function(
+                disassembler,
+                function,
+                architecture,
+                &symbols,
+                &mut function_architectures,
+                &mut extraction_times,
+            )
+            .await;
+            if let Some(mut cfg) = extracted {
+                resolve_calls(&mut cfg, &relocations, &thunks, &names);
+                sink(function, cfg, extraction_times[&offset])?;
+            }
+            progress.function_completed(Phase::Disassembly, offset);
+        }
+        progress.phase_finished(Phase::Disassembly);
+        Ok(Binary {
+            architecture,
+            functions,
+            cfgs: HashMap::new(),
+            symbols,
+            relocations,
+            function_architectures,
+            thunks,
+            memory: None,
+            extraction_times,
+        })
+    }
+
+    /// Returns the architecture of the executable, if recognized by the disassembler.
+    pub fn architecture(&self) -> Option<Architecture> {
+        self.architecture
+    }
+
+    /// Returns the architecture of the function starting at the given offset.
+    ///
+    /// This is usually the same of [`Binary::architecture`], except for the executables mixing
+    /// code of different bitness, like the 32-bit code of a 64-bit WoW64 executable.
+    pub fn function_architecture(&self, offset: u64) -> Option<Architecture> {
+        self.function_architectures
+            .get(&offset)
+            .copied()
+            .or(self.architecture)
+    }
+
+    /// Returns every function of the executable, sorted by offset.
+    pub fn functions(&self) -> &[Function] {
+        &self.functions
+    }
+
+    /// Returns the [`CFG`] of the function starting at the given offset.
+    pub fn cfg(&self, offset: u64) -> Option<&CFG> {
+        self.cfgs.get(&offset)
+    }
+
+    /// Returns the time spent extracting the [`CFG`] of the function starting at the given
+    /// offset.
+    ///
+    /// Only known for binaries returned by [`Binary::extract`]: the times are not written by
+    /// [`Binary::to_bytes`].
+    pub fn extraction_time(&self, offset: u64) -> Option<Duration> {
+        self.extraction_times.get(&offset).copied()
+    }
+
+    /// Returns the memory of the executable, if loaded with [`Binary::load_memory`].
+    pub fn memory(&self) -> Option<&MemoryImage> {
+        self.memory.as_ref()
+    }
+
+    /// Requests the memory of the executable to the given [`Disassembler`], unless already
+    /// loaded, and returns it.
+    ///
+    /// The memory contains the whole content of the executable, so it is not requested by
+    /// [`Binary::extract`] and it is not written by [`Binary::to_bytes`]: it should be loaded
+    /// only by the analyses reading the bytes of the executable, like
+    /// [`SignatureSet::from_binary`](crate::analysis::SignatureSet::from_binary).
+    /// See [`Disassembler::memory`].
+    pub async fn load_memory<D: Disassembler>(
+        &mut self,
+        disassembler: &mut D,
+    ) -> Option<&MemoryImage> {
+        if self.memory.is_none() {
+            self.memory = disassembler.memory().await;
+        }
+        self.memory.as_ref()
+    }
+
+    /// Returns the relocations of the executable, sorted by address.
+    pub fn relocations(&self) -> &[Relocation] {
+        &self.relocations
+    }
+
+    /// Returns the name of the import reached by the thunk starting at the given offset.
+    ///
+    /// Thunks are the functions located at an import, like the PLT stubs, the ones named by
+    /// radare2 as `sym.imp.*`, and the ones recognized by [`CFG::is_thunk`]. Returns [`None`] if
+    /// the function is not a thunk.
+    pub fn thunk_target(&self, offset: u64) -> Option<&str> {
+        self.thunks.get(&offset).map(String::as_str)
+    }
+
+    /// Returns the symbols of the executable, used to name the addresses found in the [`CFG`]s.
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    /// Moves the whole analysis from the load address `from` to the load address `to`.
+    ///
+    /// Every address is moved by the same amount: the functions, their [`CFG`]s, as in
+    /// [`CFG::rebase`], the symbols, the relocations, the thunks and the memory. This is useful to
+    /// compare two analyses of the same position independent executable loaded at different
+    /// addresses, like two memory dumps.
+    #[must_use]
+    pub fn rebase(self, from: u64, to: u64) -> Binary {
+        let delta = to.wrapping_sub(from);
+        let mut functions = self.functions;
+        functions.iter_mut().for_each(|func| func.rebase(delta));
+        // keep the functions sorted if some of them wrapped around the address space
+        functions.sort();
+        let cfgs = self
+            .cfgs
+            .into_iter()
+            .map(|(offset, cfg)| (offset.wrapping_add(delta), cfg.rebase(from, to)))
+            .collect();
+        let mut relocations = self.relocations;
+        relocations.iter_mut().for_each(|reloc| reloc.rebase(delta));
+        relocations.sort_by_key(Relocation::address);
+        Binary {
+            architecture: self.architecture,
+            functions,
+            cfgs,
+            symbols: self.symbols.rebase(from, to),
+            relocations,
+            function_architectures: rebase_keys(self.function_architectures, delta),
+            thunks: rebase_keys(self.thunks, delta),
+            memory: self.memory.map(|memory| memory.rebase(from, to)),
+            extraction_times: rebase_keys(self.extraction_times, delta),
+        }
+    }
+
+    /// Serializes the extracted functions into a binary representation.
+    ///
+    /// The [`CFG`]s are written with [`CFG::to_bytes`]. The result can be loaded again with
+    /// [`Binary::from_bytes`].
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = BINARY_MAGIC.to_vec();
+        out.extend(BINARY_VERSION.to_le_bytes());
+        match self.architecture {
+            Some(arch) => {
+                out.push(1);
+                encode_bytes(arch.name().as_bytes(), &mut out);
+                out.extend(arch.bits().to_le_bytes());
+            }
+            None => out.push(0),
+        }
+        out.extend((self.functions.len() as u64).to_le_bytes());
+        for function in &self.functions {
+            out.extend(function.get_offset().to_le_bytes());
+            encode_bytes(function.get_name().as_bytes(), &mut out);
+            match self.cfgs.get(&function.get_offset()) {
+                Some(cfg) => {
+                    out.push(1);
+                    encode_bytes(&cfg.to_bytes(), &mut out);
+                }
+                None => out.push(0),
+            }
+            // 0 if the same of the executable
+            let bits = self
+                .function_architectures
+                .get(&function.get_offset())
+                .map_or(0, Architecture::bits);
+            out.extend(bits.to_le_bytes());
+        }
+        out.extend((self.symbols.len() as u64).to_le_bytes());
+        for symbol in self.symbols.iter() {
+            encode_bytes(symbol.name().as_bytes(), &mut out);
+            out.extend(symbol.address().to_le_bytes());
+            out.extend(symbol.size().to_le_bytes());
+            out.push(match symbol.kind() {
+                SymbolKind::Function => 0,
+                SymbolKind::Import => 1,
+                SymbolKind::Export => 2,
+            });
+        }
+        out.extend((self.relocations.len() as u64).to_le_bytes());
+        for reloc in &self.relocations {
+            out.extend(reloc.address().to_le_bytes());
+            encode_bytes(reloc.symbol().as_bytes(), &mut out);
+        }
+        out
+    }
+
+    /// Loads the functions previously serialized with [`Binary::to_bytes`].
+    ///
+    /// This method returns [`std::io::Error`] in case of malformed input or if the data was
+    /// written by a version of this library using a different format.
+    pub fn from_bytes(data: &[u8]) -> Result<Binary, io::Error> {
+        let malformed = || io::Error::new(ErrorKind::InvalidData, "malformed binary");
+        let mut data = data;
+        if take(&mut data, BINARY_MAGIC.len()) != Some(BINARY_MAGIC) {
+            return Err(malformed());
+        }
+        if take_u32(&mut data) != Some(BINARY_VERSION) {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "unsupported binary format version",
+            ));
+        }
+        let architecture = match take(&mut data, 1).ok_or_else(malformed)? {
+            [0] => None,
+            [1] => {
+                let name = take_string(&mut data).ok_or_else(malformed)?;
+                let bits = take_u32(&mut data).ok_or_else(malformed)?;
+                Some(Architecture::from_name(&name, bits).ok_or_else(malformed)?)
+            }
+            _ => return Err(malformed()),
+        };
+        let functions_len = take_u64(&mut data).ok_or_else(malformed)?;
+        let mut functions = Vec::new();
+        let mut cfgs = HashMap::new();
+        let mut function_architectures = HashMap::new();
+        for _ in 0..functions_len {
+            let offset = take_u64(&mut data).ok_or_else(malformed)?;
+            let name = take_string(&mut data).ok_or_else(malformed)?;
+            match take(&mut data, 1).ok_or_else(malformed)? {
+                [0] => {}
+                [1] => {
+                    let len = take_u64(&mut data).ok_or_else(malformed)? as usize;
+                    let cfg = CFG::from_bytes(take(&mut data, len).ok_or_else(malformed)?)?;
+                    cfgs.insert(offset, cfg);
+                }
+                _ => return Err(malformed()),
+            }
+            let bits = take_u32(&mut data).ok_or_else(malformed)?;
+            if bits != 0 {
+                let arch = architecture.ok_or_else(malformed)?;
+                let arch = Architecture::from_name(arch.name(), bits).ok_or_else(malformed)?;
+                function_architectures.insert(offset, arch);
+            }
+            functions.push(Function::new(offset, &name));
+        }
+        let symbols_len = take_u64(&mut data).ok_or_else(malformed)?;
+        let mut symbols = SymbolTable::new();
+        for _ in 0..symbols_len {
+            let name = take_string(&mut data).ok_or_else(malformed)?;
+            let address = take_u64(&mut data).ok_or_else(malformed)?;
+            let size = take_u64(&mut data).ok_or_else(malformed)?;
+            let kind = match take(&mut data, 1).ok_or_else(malformed)? {
+                [0] => SymbolKind::Function,
+                [1] => SymbolKind::Import,
+                [2] => SymbolKind::Export,
+                _ => return Err(malformed()),
+            };
+            symbols.insert(Symbol::new(&name, address, size, kind));
+        }
+        let relocations_len = take_u64(&mut data).ok_or_else(malformed)?;
+        let mut relocations = Vec::new();
+        for _ in 0..relocations_len {
+            let address = take_u64(&mut data).ok_or_else(malformed)?;
+            let symbol = take_string(&mut data).ok_or_else(malformed)?;
+            relocations.push(Relocation::new(address, &symbol));
+        }
+        if !data.is_empty() {
+            return Err(malformed());
+        }
+        let thunks = find_thunks(&functions, &cfgs, &symbols);
+        Ok(Binary {
+            architecture,
+            functions,
+            cfgs,
+            symbols,
+            relocations,
+            function_architectures,
+            thunks,
+            memory: None,
+            extraction_times: HashMap::new(),
+        })
+    }
+}
+
+// extracts the CFG of the function, decoded with its architecture and labeled with its metadata,
+// recording its architecture, if different from the one of the executable, and the time spent
+// extracting it.
+async fn extract_function<D: Disassembler>(
+    disassembler: &mut D,
+    function: &Function,
+    architecture: Option<Architecture>,
+    symbols: &SymbolTable,
+    function_architectures: &mut HashMap<u64, Architecture>,
+    extraction_times: &mut HashMap<u64, Duration>,
+) -> Option<CFG> {
+    let offset = function.get_offset();
+    let function_architecture = disassembler.function_architecture(offset).await;
+    match function_architecture {
+        Some(arch) if Some(arch) != architecture => {
+            function_architectures.insert(offset, arch);
+        }
+        _ => {}
+    }
+    let start = Instant::now();
+    let disassembled = disassembler
+        .disassemble_function_with(offset, function_architecture.or(architecture))
+        .await;
+    extraction_times.insert(offset, start.elapsed());
+    #[cfg(feature = "tracing")]
+    tracing::debug!(
+        offset,
+        name = function.get_name(),
+        blocks = disassembled.as_ref().map(|cfg| cfg.len() as u64),
+        elapsed = ?extraction_times[&offset],
+        "function extracted"
+    );
+    let cfg = disassembled?;
+    let end = cfg
+        .blocks_sorted()
+        .into_iter()
+        .map(|bb| bb.offset + bb.length)
+        .max()
+        .unwrap_or(offset);
+    let size = symbols
+        .by_name(function.get_name())
+        .filter(|sym| sym.address() == offset && sym.size() > 0)
+        .map_or(end.saturating_sub(offset), Symbol::size);
+    let mut metadata = FunctionMetadata::new(function.get_name(), offset, size);
+    if let Some(demangled) = demangle(function.get_name()) {
+        metadata = metadata.with_demangled_name(&demangled);
+    }
+    Some(cfg.with_metadata(metadata))
+}
+
+// moves every key of the map by the given amount of bytes.
+fn rebase_keys<T>(map: HashMap<u64, T>, delta: u64) -> HashMap<u64, T> {
+    map.into_iter()
+        .map(|(offset, value)| (offset.wrapping_add(delta), value))
+        .collect()
+}
+
+// name of the import reached by each thunk, by name of the thunk.
+fn thunk_names(functions: &[Function], thunks: &HashMap<u64, String>) -> HashMap<String, String> {
+    functions
+        .iter()
+        .filter_map(|func| {
+            let import = thunks.get(&func.get_offset())?;
+            Some((func.get_name().to_string(), import.clone()))
+        })
+        .collect()
+}
+
+// records the calls and references patched by a relocation, and the calls to a thunk, as
+// targeting the named symbol.
+fn resolve_calls(
+    cfg: &mut CFG,
+    relocations: &[Relocation],
+    thunks: &HashMap<u64, String>,
+    names: &HashMap<String, String>,
+) {
+    cfg.resolve_references(|reference| {
+        let reloc = Relocation::patching(relocations, reference.offset(), reference.length())?;
+        Some(CallTarget::Symbol(reloc.symbol().to_string()))
+    });
+    cfg.resolve_calls(|call| {
+        let target = match Relocation::patching(relocations, call.offset(), call.length()) {
+            Some(reloc) => CallTarget::Symbol(reloc.symbol().to_string()),
+            None => call.target().clone(),
+        };
+        let import = match &target {
+            CallTarget::Address(address) => thunks.get(address),
+            CallTarget::Symbol(name) => names.get(name),
+            CallTarget::Indirect(_) => None,
+        };
+        Some(import.map_or(target, |import| CallTarget::Symbol(import.clone())))
+    });
+}
+
+fn find_thunks(
+    functions: &[Function],
+    cfgs: &HashMap<u64, CFG>,
+    symbols: &SymbolTable,
+) -> HashMap<u64, String> {
+    const IMPORT_PREFIX: &str = "sym.imp.";
+    functions
+        .iter()
+        .filter_map(|function| {
+            let offset = function.get_offset();
+            let name = function.get_name();
+            let import = symbols
+                .at(offset)
+                .iter()
+                .find(|sym| sym.kind() == SymbolKind::Import);
+            let name = match import {
+                Some(import) => import.name(),
+                None if name.starts_with(IMPORT_PREFIX) => &name[IMPORT_PREFIX.len()..],
+                None if cfgs.get(&offset).is_some_and(CFG::is_thunk) => name,
+                None => return None,
+            };
+            Some((offset, name.to_string()))
+        })
+        .collect()
+}
+
+// finds the thunks as find_thunks would do once every CFG is extracted, disassembling only the
+// functions small enough to be a thunk: the ones whose symbol, or the gap up to the following
+// function, spans a few instructions.
+async fn scan_thunks<D: Disassembler>(
+    disassembler: &mut D,
+    functions: &[Function],
+    symbols: &SymbolTable,
+) -> HashMap<u64, String> {
+    // the four instructions of the longest thunk, with the longest encoding of x86
+    const MAX_THUNK_SIZE: u64 = 4 * 15;
+    let mut cfgs = HashMap::new();
+    for (index, function) in functions.iter().enumerate() {
+        let offset = function.get_offset();
+        let size = symbols
+            .by_name(function.get_name())
+            .filter(|sym| sym.address() == offset && sym.size() > 0)
+            .map(Symbol::size)
+            .or_else(|| {
+                let next = functions.get(index + 1)?;
+                Some(next.get_offset().saturating_sub(offset))
+            });
+        if size.map_or(true, |size| size <= MAX_THUNK_SIZE) {
+            if let Some(cfg) = disassembler.disassemble_function(offset).await {
+                if cfg.is_thunk() {
+                    cfgs.insert(offset, cfg);
+                }
+            }
+        }
+    }
+    find_thunks(functions, &cfgs, symbols)
+}
+
+// opens and analyzes the executable with radare2, using the given configuration.
+#[cfg(feature = "radare2")]
+async fn open(path: &Path, config: ExtractionConfig) -> Result<R2Disasm, io::Error> {
+    let path = path
+        .to_str()
+        .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "invalid path"))?;
+    let mut disassembler = R2Disasm::new(path).await?;
+    if let Some(arch) = config.arch() {
+        disassembler.set_arch(arch).await;
+    }
+    if let Some(bits) = config.bits() {
+        disassembler.set_bits(bits).await;
+    }
+    if let Some(endianness) = config.endianness() {
+        disassembler.set_endianness(endianness).await;
+    }
+    disassembler.analyse().await;
+    Ok(disassembler)
+}
+
+// file storing the analysis of the executable with the given content.
+// a cryptographic hash, as a collision would silently return the result of another executable.
+// the length of the content and the tag of each optional setting keep the hashed data unambiguous.
+#[cfg(feature = "radare2")]
+fn cache_path(content: &[u8], config: ExtractionConfig, cache_dir: &Path) -> PathBuf {
+    let mut hasher = Sha256::new();
+    hasher.update((content.len() as u64).to_le_bytes());
+    hasher.update(content);
+    match config.arch() {
+        Some(arch) => {
+            hasher.update([1]);
+            hasher.update((arch.len() as u64).to_le_bytes());
+            hasher.update(arch);
+        }
+        None => hasher.update([0]),
+    }
+    match config.bits() {
+        Some(bits) => {
+            hasher.update([1]);
+            hasher.update(bits.to_le_bytes());
+        }
+        None => hasher.update([0]),
+    }
+    match config.endianness() {
+        Some(endianness) => hasher.update([1, endianness as u8]),
+        None => hasher.update([0]),
+    }
+    let name = hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+    cache_dir.join(format!("{}.bin", name))
+}
+
+fn encode_bytes(bytes: &[u8], out: &mut Vec<u8>) {
+    out.extend((bytes.len() as u64).to_le_bytes());
+    out.extend(bytes);
+}
+
+// removes the given amount of bytes from the beginning of the data.
+fn take<'a>(data: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if data.len() < len {
+        None
+    } else {
+        let (taken, rest) = data.split_at(len);
+        *data = rest;
+        Some(taken)
+    }
+}
+
+fn take_u32(data: &mut &[u8]) -> Option<u32> {
+    take(data, 4).map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+}
+
+fn take_u64(data: &mut &[u8]) -> Option<u64> {
+    take(data, 8).map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+}
+
+fn take_string(data: &mut &[u8]) -> Option<String> {
+    let len = take_u64(data)? as usize;
+    String::from_utf8(take(data, len)?.to_vec()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::{
+        BasicBlock, CallTarget, CancellationToken, Graph, Phase, ProgressSink, ReferenceKind, CFG,
+    };
+    #[cfg(feature = "radare2")]
+    use crate::disasm::binary::cache_path;
+    use crate::disasm::radare2::BareCFG;
+    use crate::disasm::{
+        Architecture, Binary, Disassembler, Endianness, ExtractionConfig, Function, MemoryImage,
+        Permissions, Relocation, Segment, Statement, StatementFamily, Symbol, SymbolKind,
+        SymbolTable,
+    };
+    use std::collections::HashMap;
+    use std::io;
+    use std::sync::Mutex;
+
+    // disassembler returning two functions, one of them without a CFG.
+    struct MockDisassembler;
+
+    impl Disassembler for MockDisassembler {
+        async fn functions(&mut self) -> Vec<Function> {
+            vec![Function::new(0x10, "main"), Function::new(0x40, "broken")]
+        }
+
+        async fn disassemble_function(&mut self, offset: u64) -> Option<CFG> {
+            let stmts = vec![
+                Statement::new(0x10, StatementFamily::CJMP, "je 0x14"),
+                Statement::new(0x12, StatementFamily::CALL, "call 0x40"),
+                Statement::new(0x14, StatementFamily::RET, "ret"),
+            ];
+            match offset {
+                0x10 => Some(CFG::new(&stmts, 0x15, Architecture::X86(64))),
+                _ => None,
+            }
+        }
+
+        async fn architecture(&mut self) -> Option<Architecture> {
+            Some(Architecture::X86(64))
+        }
+
+        async fn symbols(&mut self) -> SymbolTable {
+            let mut symbols = SymbolTable::new();
+            symbols.insert(Symbol::new("main", 0x10, 0, SymbolKind::Function));
+            symbols.insert(Symbol::new("puts", 0x40, 0, SymbolKind::Import));
+            symbols
+        }
+
+        async fn relocations(&mut self) -> Vec<Relocation> {
+            // the first relocation belongs to the previous instruction
+            vec![
+                Relocation::new(0x11, "abort"),
+                Relocation::new(0x13, "exit"),
+            ]
+        }
+
+        async fn function_architecture(&mut self, offset: u64) -> Option<Architecture> {
+            match offset {
+                0x40 => Some(Architecture::X86(32)),
+                _ => Some(Architecture::X86(64)),
+            }
+        }
+
+        async fn memory(&mut self) -> Option<MemoryImage> {
+            Some(MemoryImage::new(Endianness::Little).with_segment(0x10, vec![0x74, 0x02]))
+        }
+    }
+
+    // disassembler building the CFGs from the blocks found by the backend, like radare2, with a
+    // thunk recognizable only from its instructions.
+    struct BareDisassembler;
+
+    impl Disassembler for BareDisassembler {
+        async fn functions(&mut self) -> Vec<Function> {
+            vec![
+                Function::new(0x10, "main"),
+                Function::new(0x40, "puts_stub"),
+            ]
+        }
+
+        async fn disassemble_function(&mut self, offset: u64) -> Option<CFG> {
+            let (bare, stmts) = match offset {
+                0x10 => (
+                    BareCFG {
+                        root: Some(0x10),
+                        blocks: vec![(0x10, 6)],
+                        edges: Vec::new(),
+                    },
+                    vec![
+                        Statement::new(0x10, StatementFamily::CALL, "call 0x40"),
+                        Statement::new(0x15, StatementFamily::RET, "ret"),
+                    ],
+                ),
+                0x40 => (
+                    BareCFG {
+                        root: Some(0x40),
+                        blocks: vec![(0x40, 6)],
+                        edges: Vec::new(),
+                    },
+                    vec![Statement::new(
+                        0x40,
+                        StatementFamily::JMP,
+                        "jmp qword [rip + 0x2fe2]",
+                    )],
+                ),
+                _ => return None,
+            };
+            Some(CFG::with_statements(bare, &stmts, Architecture::X86(64)))
+        }
+
+        async fn architecture(&mut self) -> Option<Architecture> {
+            Some(Architecture::X86(64))
+        }
+    }
+
+    // disassembler of an object file, whose calls, jumps and data accesses are patched by the
+    // relocations.
+    struct ObjectDisassembler;
+
+    impl Disassembler for ObjectDisassembler {
+        async fn functions(&mut self) -> Vec<Function> {
+            vec![Function::new(0x10, "main")]
+        }
+
+        async fn disassemble_function(&mut self, offset: u64) -> Option<CFG> {
+            let bare = BareCFG {
+                root: Some(offset),
+                blocks: vec![(0x10, 0x11)],
+                edges: Vec::new(),
+            };
+            let stmts = vec![
+                Statement::new(0x10, StatementFamily::LEA, "lea rdi, [rip]"),
+                Statement::new(0x17, StatementFamily::CALL, "call 0x1c"),
+                Statement::new(0x1C, StatementFamily::JMP, "jmp 0x21"),
+            ];
+            Some(CFG::with_statements(bare, &stmts, Architecture::X86(64)))
+        }
+
+        async fn architecture(&mut self) -> Option<Architecture> {
+            Some(Architecture::X86(64))
+        }
+
+        async fn relocations(&mut self) -> Vec<Relocation> {
+            vec![
+                Relocation::new(0x13, "message"),
+                Relocation::new(0x18, "puts"),
+                Relocation::new(0x1D, "exit"),
+            ]
+        }
+    }
+
+    // disassembler recording the architecture used to decode each function.
+    #[derive(Default)]
+    struct ArchDisassembler {
+        decoded: Vec<(u64, Option<Architecture>)>,
+    }
+
+    impl Disassembler for ArchDisassembler {
+        async fn functions(&mut self) -> Vec<Function> {
+            vec![Function::new(0x10, "main"), Function::new(0x40, "thumb")]
+        }
+
+        async fn disassemble_function(&mut self, _: u64) -> Option<CFG> {
+            panic!("the architecture of the function should be reused")
+        }
+
+        async fn disassemble_function_with(
+            &mut self,
+            offset: u64,
+            arch: Option<Architecture>,
+        ) -> Option<CFG> {
+            self.decoded.push((offset, arch));
+            None
+        }
+
+        async fn architecture(&mut self) -> Option<Architecture> {
+            Some(Architecture::Arm(32))
+        }
+
+        async fn function_architecture(&mut self, offset: u64) -> Option<Architecture> {
+            match offset {
+                0x40 => Some(Architecture::Arm(16)),
+                _ => None,
+            }
+        }
+    }
+
+    // disassembler labeling the blocks with the architecture of the executable, like radare2, for
+    // an architecture whose jumps are not known.
+    struct RiscvDisassembler;
+
+    impl Disassembler for RiscvDisassembler {
+        async fn functions(&mut self) -> Vec<Function> {
+            vec![Function::new(0x10, "main")]
+        }
+
+        async fn disassemble_function(&mut self, offset: u64) -> Option<CFG> {
+            let arch = self.architecture().await;
+            self.disassemble_function_with(offset, arch).await
+        }
+
+        async fn disassemble_function_with(
+            &mut self,
+            offset: u64,
+            arch: Option<Architecture>,
+        ) -> Option<CFG> {
+            let bare = BareCFG {
+                root: Some(offset),
+                blocks: vec![(offset, 8), (offset + 8, 4)],
+                edges: vec![(offset, offset + 8)],
+            };
+            let stmts = vec![
+                Statement::new(offset, StatementFamily::CALL, "jal ra, 0x400"),
+                Statement::new(offset + 4, StatementFamily::CJMP, "beqz a0, 0x18"),
+                Statement::new(offset + 8, StatementFamily::RET, "ret"),
+            ];
+            Some(CFG::with_statements(bare, &stmts, arch?))
+        }
+
+        async fn architecture(&mut self) -> Option<Architecture> {
+            Some(Architecture::Riscv(64))
+        }
+    }
+
+    fn binary() -> Binary {
+        let stmts = vec![
+            Statement::new(0x10, StatementFamily::CJMP, "je 0x14"),
+            Statement::new(0x12, StatementFamily::CALL, "call 0x40"),
+            Statement::new(0x14, StatementFamily::RET, "ret"),
+        ];
+        let cfg = CFG::new(&stmts, 0x15, Architecture::X86(64));
+        let mut symbols = SymbolTable::new();
+        symbols.insert(Symbol::new("main", 0x10, 5, SymbolKind::Function));
+        symbols.insert(Symbol::new("puts", 0x40, 0, SymbolKind::Import));
+        let text = Segment::new(
+            ".text",
+            0x10,
+            vec![0x74, 0x02],
+            Permissions::new(true, false, true),
+        )
+        .with_size(0x40);
+        let memory = MemoryImage::new(Endianness::Big).with_mapping(text);
+        Binary {
+            architecture: Some(Architecture::Arm(32)),
+            functions: vec![Function::new(0x10, "main"), Function::new(0x40, "broken")],
+            cfgs: HashMap::from([(0x10, cfg)]),
+            symbols,
+            relocations: vec![Relocation::new(0x13, "exit")],
+            function_architectures: HashMap::from([(0x40, Architecture::Arm(64))]),
+            thunks: HashMap::from([(0x40, "puts".to_string())]),
+            memory: Some(memory),
+            extraction_times: HashMap::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn extract() {
+        let binary = Binary::extract(&mut MockDisassembler).await;
+        assert_eq!(binary.architecture(), Some(Architecture::X86(64)));
+        assert_eq!(binary.functions().len(), 2);
+        let cfg = binary.cfg(0x10).unwrap();
+        assert_eq!(cfg.len(), 3);
+        assert_eq!(cfg.metadata().unwrap().name(), "main");
+        assert_eq!(cfg.metadata().unwrap().size(), 5);
+        assert!(binary.cfg(0x40).is_none());
+        assert_eq!(binary.symbols().len(), 2);
+        assert_eq!(binary.symbols().resolve(0x40).unwrap().name(), "puts");
+        assert_eq!(binary.thunk_target(0x40), Some("puts"));
+        assert_eq!(binary.thunk_target(0x10), None);
+        let call = &cfg.calls(&BasicBlock {
+            offset: 0x12,
+            length: 2,
+        })[0];
+        // the relocation takes precedence over the thunk
+        assert_eq!(call.target(), &CallTarget::Symbol("exit".to_string()));
+        assert_eq!(binary.relocations().len(), 2);
+        let arch = binary.function_architecture(0x10);
+        assert_eq!(arch, Some(Architecture::X86(64)));
+        let arch = binary.function_architecture(0x40);
+        assert_eq!(arch, Some(Architecture::X86(32)));
+        // the memory is requested only when needed
+        assert!(binary.memory().is_none());
+        let mut binary = binary;
+        let memory = binary.load_memory(&mut MockDisassembler).await.unwrap();
+        assert_eq!(memory.read_u16(0x10), Some(0x0274));
+    }
+
+    #[tokio::test]
+    async fn extract_function_architecture() {
+        let mut disassembler = ArchDisassembler::default();
+        let binary = Binary::extract(&mut disassembler).await;
+        let expected = vec![
+            (0x10, Some(Architecture::Arm(32))),
+            (0x40, Some(Architecture::Arm(16))),
+        ];
+        assert_eq!(disassembler.decoded, expected);
+        assert_eq!(
+            binary.function_architecture(0x40),
+            Some(Architecture::Arm(16))
+        );
+    }
+
+    #[tokio::test]
+    async fn extract_unsupported_architecture() {
+        let binary = Binary::extract(&mut RiscvDisassembler).await;
+        let cfg = binary.cfg(0x10).unwrap();
+        assert_eq!(cfg.len(), 2);
+        assert!(cfg
+            .blocks_sorted()
+            .iter()
+            .all(|bb| cfg.calls(bb).is_empty()));
+    }
+
+    #[tokio::test]
+    async fn extract_bare_thunks() {
+        let binary = Binary::extract(&mut BareDisassembler).await;
+        assert_eq!(binary.thunk_target(0x40), Some("puts_stub"));
+        assert_eq!(binary.thunk_target(0x10), None);
+        let call = &binary.cfg(0x10).unwrap().calls(&BasicBlock {
+            offset: 0x10,
+            length: 6,
+        })[0];
+        assert_eq!(call.target(), &CallTarget::Symbol("puts_stub".to_string()));
+    }
+
+    #[tokio::test]
+    async fn extract_relocated() {
+        let binary = Binary::extract(&mut ObjectDisassembler).await;
+        let cfg = binary.cfg(0x10).unwrap();
+        let block = BasicBlock {
+            offset: 0x10,
+            length: 0x11,
+        };
+        let symbol = |name: &str| CallTarget::Symbol(name.to_string());
+        assert_eq!(cfg.calls(&block)[0].target(), &symbol("puts"));
+        let refs = cfg.references(&block);
+        assert_eq!(refs.len(), 2);
+        assert_eq!(refs[0].kind(), ReferenceKind::Data);
+        assert_eq!(refs[0].target(), &symbol("message"));
+        assert_eq!(refs[1].kind(), ReferenceKind::Jump);
+        assert_eq!(refs[1].target(), &symbol("exit"));
+    }
+
+    #[derive(Default)]
+    struct ProgressLog(Mutex<Vec<(Phase, Option<usize>, Option<u64>)>>);
+
+    impl ProgressSink for ProgressLog {
+        fn phase_started(&self, phase: Phase, total: usize) {
+            self.0.lock().unwrap().push((phase, Some(total), None));
+        }
+
+        fn function_completed(&self, phase: Phase, offset: u64) {
+            self.0.lock().unwrap().push((phase, None, Some(offset)));
+        }
+
+        fn phase_finished(&self, phase: Phase) {
+            self.0.lock().unwrap().push((phase, None, None));
+        }
+    }
+
+    #[tokio::test]
+    async fn extract_with_progress() {
+        let log = ProgressLog::default();
+        let binary = Binary::extract_with_progress(&mut MockDisassembler, &log).await;
+        assert_eq!(binary.functions().len(), 2);
+        let expected = vec![
+            (Phase::Disassembly, Some(2), None),
+            (Phase::Disassembly, None, Some(0x10)),
+            (Phase::Disassembly, None, Some(0x40)),
+            (Phase::Disassembly, None, None),
+        ];
+        assert_eq!(log.0.into_inner().unwrap(), expected);
+    }
+
+    // cancels the extraction once the first function is completed.
+    struct Canceller(CancellationToken);
+
+    impl ProgressSink for Canceller {
+        fn function_completed(&self, _: Phase, _: u64) {
+            self.0.cancel();
+        }
+    }
+
+    #[tokio::test]
+    async fn extract_with_cancellation() {
+        let token = CancellationToken::new();
+        let progress = Canceller(token.clone());
+        let binary =
+            Binary::extract_with_cancellation(&mut MockDisassembler, &progress, &token).await;
+        assert_eq!(binary.functions().len(), 2);
+        assert!(binary.cfg(0x10).is_some());
+        assert!(binary.extraction_time(0x10).is_some());
+        // not even attempted
+        assert!(binary.extraction_time(0x40).is_none());
+    }
+
+    #[tokio::test]
+    async fn extract_streaming() {
+        let extracted = Binary::extract(&mut MockDisassembler).await;
+        let token = CancellationToken::new();
+        let mut streamed = Vec::new();
+        let binary =
+            Binary::extract_streaming(&mut MockDisassembler, &(), &token, |function, cfg, _| {
+                streamed.push((function.get_offset(), cfg));
+                Ok(())
+            })
+            .await
+            .unwrap();
+        assert_eq!(streamed.len(), 1);
+        let (offset, cfg) = &streamed[0];
+        assert_eq!(*offset, 0x10);
+        assert_eq!(cfg, extracted.cfg(0x10).unwrap());
+        assert_eq!(cfg.metadata(), extracted.cfg(0x10).unwrap().metadata());
+        assert!(binary.cfg(0x10).is_none());
+        assert!(binary.memory().is_none());
+        assert_eq!(binary.functions(), extracted.functions());
+        assert_eq!(binary.thunk_target(0x40), Some("puts"));
+        let arch = binary.function_architecture(0x40);
+        assert_eq!(arch, Some(Architecture::X86(32)));
+        assert!(binary.extraction_time(0x40).is_some());
+        // the errors of the sink stop the extraction
+        let failed = Binary::extract_streaming(&mut MockDisassembler, &(), &token, |_, _, _| {
+            Err(io::Error::new(io::ErrorKind::Other, "disk full"))
+        })
+        .await;
+        assert_eq!(failed.err().unwrap().to_string(), "disk full");
+    }
+
+    #[tokio::test]
+    async fn extract_streaming_thunks() {
+        // the thunk follows its caller, but the call is resolved as in the extraction
+        let extracted = Binary::extract(&mut BareDisassembler).await;
+        let token = CancellationToken::new();
+        let mut streamed = HashMap::new();
+        let binary =
+            Binary::extract_streaming(&mut BareDisassembler, &(), &token, |function, cfg, _| {
+                streamed.insert(function.get_offset(), cfg);
+                Ok(())
+            })
+            .await
+            .unwrap();
+        let block = BasicBlock {
+            offset: 0x10,
+            length: 6,
+        };
+        let call = &streamed[&0x10].calls(&block)[0];
+        assert_eq!(call.target(), &CallTarget::Symbol("puts_stub".to_string()));
+        assert_eq!(&streamed[&0x10], extracted.cfg(0x10).unwrap());
+        assert_eq!(binary.thunk_target(0x40), Some("puts_stub"));
+    }
+
+    #[test]
+    fn serialization() {
+        let binary = binary();
+        let loaded = Binary::from_bytes(&binary.to_bytes()).unwrap();
+        assert_eq!(loaded.architecture(), Some(Architecture::Arm(32)));
+        assert_eq!(loaded.functions(), binary.functions());
+        assert_eq!(
+            loaded.cfg(0x10).unwrap().to_string(),
+            binary.cfg(0x10).unwrap().to_string()
+        );
+        let head = BasicBlock {
+            offset: 0x10,
+            length: 2,
+        };
+        assert_eq!(loaded.cfg(0x10).unwrap().instruction_count(&head), Some(1));
+        assert!(loaded.cfg(0x40).is_none());
+        assert_eq!(loaded.symbols(), binary.symbols());
+        assert_eq!(loaded.thunk_target(0x40), Some("puts"));
+        assert_eq!(loaded.relocations(), binary.relocations());
+        // the memory is not stored
+        assert!(binary.memory().is_some());
+        assert!(loaded.memory().is_none());
+        let arch = loaded.function_architecture(0x10);
+        assert_eq!(arch, Some(Architecture::Arm(32)));
+        let arch = loaded.function_architecture(0x40);
+        assert_eq!(arch, Some(Architecture::Arm(64)));
+        let mut data = binary.to_bytes();
+        data.pop();
+        assert!(Binary::from_bytes(&data).is_err());
+        assert!(Binary::from_bytes(b"BCCB").is_err());
+    }
+
+    #[test]
+    fn rebase() {
+        let original = binary();
+        let rebased = binary().rebase(0x0, 0x5000);
+        let offsets = rebased.functions().iter().map(Function::get_offset);
+        assert_eq!(offsets.collect::<Vec<_>>(), vec![0x5010, 0x5040]);
+        let cfg = rebased.cfg(0x5010).unwrap();
+        assert!(cfg.same_shape(original.cfg(0x10).unwrap()));
+        assert_eq!(rebased.symbols().resolve(0x5012).unwrap().name(), "main");
+        assert_eq!(rebased.relocations()[0].address(), 0x5013);
+        assert_eq!(rebased.thunk_target(0x5040), Some("puts"));
+        assert!(rebased.memory().unwrap().is_executable(0x5011));
+        let arch = rebased.function_architecture(0x5040);
+        assert_eq!(arch, Some(Architecture::Arm(64)));
+        assert!(rebased.cfg(0x10).is_none());
+        let restored = rebased.rebase(0x5000, 0x0);
+        assert_eq!(restored.to_bytes(), original.to_bytes());
+    }
+
+    #[test]
+    #[cfg(feature = "radare2")]
+    fn content_addressed() {
+        let dir = std::path::Path::new("/tmp/cache");
+        let path = |content: &[u8], config| cache_path(content, config, dir);
+        let config = ExtractionConfig::default();
+        let forced = ExtractionConfig::new().with_bits(32);
+        let big = ExtractionConfig::new().with_endianness(Endianness::Big);
+        let arm = ExtractionConfig::new().with_arch(Architecture::Arm(32));
+        assert_eq!(path(b"ELF", config), path(b"ELF", config));
+        assert_ne!(path(b"ELF", config), path(b"ELF2", config));
+        assert_ne!(path(b"ELF", config), path(b"ELF", forced));
+        assert_ne!(path(b"ELF", config), path(b"ELF", big));
+        assert_ne!(path(b"ELF", config), path(b"ELF", arm));
+        // the settings can not be confused with the content
+        assert_ne!(path(b"ELF\x20\0\0\0", config), path(b"ELF", forced));
+        assert_ne!(path(b"ELF\x01", config), path(b"ELF", big));
+        assert_eq!(path(b"ELF", config).parent(), Some(dir));
+    }
+}
@@ -0,0 +1,325 @@
+use crate::disasm::{Statement, StatementFamily};
+
+// prefixes of the mnemonics of the cryptographic extensions of x86, ARM and RISC-V.
+const CRYPTO_PREFIXES: [&str; 10] = [
+    "aes", "vaes", "sha1", "sha256", "sha512", "sm3", "sm4", "pclmul", "vpclmul", "crc32",
+];
+
+/// Abstract category of an instruction, independent of the instruction set.
+///
+/// The category is coarser than the [`StatementFamily`]: the many families of bitwise operations,
+/// for example, all belong to [`InstructionCategory::Logic`]. This makes the category histograms
+/// of two functions comparable even when they are compiled for different architectures.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum InstructionCategory {
+    /// Integer and floating point arithmetic, including address computations.
+    Arithmetic,
+    /// Bitwise operations, shifts and rotations.
+    Logic,
+    /// Moves between registers, conversions and exchanges.
+    Move,
+    /// Reads from memory, including pops from the stack.
+    Load,
+    /// Writes to memory, including pushes to the stack.
+    Store,
+    /// Comparisons and tests.
+    Compare,
+    /// Conditional and unconditional jumps.
+    Branch,
+    /// Function calls.
+    Call,
+    /// Function returns.
+    Return,
+    /// Instructions of the cryptographic extensions, like `aesenc` or `sha256h`.
+    Crypto,
+    /// System calls, traps, privileged and synchronization instructions.
+    System,
+    /// Every other instruction, like nops and unknown instructions.
+    Other,
+}
+
+impl InstructionCategory {
+    /// Every category, in order.
+    pub const ALL: [InstructionCategory; 12] = [
+        InstructionCategory::Arithmetic,
+        InstructionCategory::Logic,
+        InstructionCategory::Move,
+        InstructionCategory::Load,
+        InstructionCategory::Store,
+        InstructionCategory::Compare,
+        InstructionCategory::Branch,
+        InstructionCategory::Call,
+        InstructionCategory::Return,
+        InstructionCategory::Crypto,
+        InstructionCategory::System,
+        InstructionCategory::Other,
+    ];
+
+    /// Converts the category into a string representation.
+    pub fn to_str(&self) -> &'static str {
+        match self {
+            InstructionCategory::Arithmetic => "arithmetic",
+            InstructionCategory::Logic => "logic",
+            InstructionCategory::Move => "move",
+            InstructionCategory::Load => "load",
+            InstructionCategory::Store => "store",
+            InstructionCategory::Compare => "compare",
+            InstructionCategory::Branch => "branch",
+            InstructionCategory::Call => "call",
+            InstructionCategory::Return => "return",
+            InstructionCategory::Crypto => "crypto",
+            InstructionCategory::System => "system",
+            InstructionCategory::Other => "other",
+        }
+    }
+}
+
+impl From<StatementFamily> for InstructionCategory {
+    fn from(family: StatementFamily) -> Self {
+        match family {
+            StatementFamily::ABS
+            | StatementFamily::ADD
+            | StatementFamily::DIV
+            | StatementFamily::FPU
+            | StatementFamily::LEA
+            | StatementFamily::MOD
+            | StatementFamily::MUL
+            | StatementFamily::SUB => InstructionCategory::Arithmetic,
+            StatementFamily::AND
+            | StatementFamily::CPL
+            | StatementFamily::MASK
+            | StatementFamily::NOR
+            | StatementFamily::NOT
+            | StatementFamily::OR
+            | StatementFamily::ROL
+            | StatementFamily::ROR
+            | StatementFamily::SAL
+            | StatementFamily::SAR
+            | StatementFamily::SHL
+            | StatementFamily::SHR
+            | StatementFamily::XOR => InstructionCategory::Logic,
+            StatementFamily::CAST
+            | StatementFamily::CMOV
+            | StatementFamily::MOV
+            | StatementFamily::XCHG => InstructionCategory::Move,
+            StatementFamily::LEAVE | StatementFamily::LOAD | StatementFamily::POP => {
+                InstructionCategory::Load
+            }
+            StatementFamily::PUSH | StatementFamily::STORE => InstructionCategory::Store,
+            StatementFamily::CMP => InstructionCategory::Compare,
+            StatementFamily::CJMP | StatementFamily::JMP => InstructionCategory::Branch,
+            StatementFamily::CALL => InstructionCategory::Call,
+            StatementFamily::RET => InstructionCategory::Return,
+            StatementFamily::CRYPTO => InstructionCategory::Crypto,
+            StatementFamily::DEBUG
+            | StatementFamily::IO
+            | StatementFamily::PRIV
+            | StatementFamily::SWI
+            | StatementFamily::SYNC
+            | StatementFamily::TRAP => InstructionCategory::System,
+            StatementFamily::ILL
+            | StatementFamily::LENGTH
+            | StatementFamily::NEW
+            | StatementFamily::NOP
+            | StatementFamily::NULL
+            | StatementFamily::UNK => InstructionCategory::Other,
+        }
+    }
+}
+
+impl Statement {
+    /// Returns the abstract category of this statement.
+    ///
+    /// The category is given by the [`StatementFamily`], refined with the instruction itself:
+    /// moves reading or writing a memory operand are loads and stores, and the instructions of
+    /// the cryptographic extensions are recognized by their mnemonic.
+    /// # Examples
+    /// Basic usage:
+    /// ```
+    /// # use bincc::disasm::{InstructionCategory, Statement, StatementFamily};
+    /// let load = Statement::new(0x600, StatementFamily::MOV, "mov ecx, dword [ebp + 8]");
+    /// let store = Statement::new(0x603, StatementFamily::MOV, "mov dword [ebp + 8], ecx");
+    /// let aes = Statement::new(0x606, StatementFamily::UNK, "aesenc xmm0, xmm1");
+    ///
+    /// assert_eq!(load.category(), InstructionCategory::Load);
+    /// assert_eq!(store.category(), InstructionCategory::Store);
+    /// assert_eq!(aes.category(), InstructionCategory::Crypto);
+    /// ```
+    pub fn category(&self) -> InstructionCategory {
+        let mnemonic = self.get_mnemonic();
+        if CRYPTO_PREFIXES
+            .iter()
+            .any(|prefix| mnemonic.starts_with(prefix))
+        {
+            return InstructionCategory::Crypto;
+        }
+        let category = InstructionCategory::from(self.get_family());
+        match (category, self.get_args().split_once(',')) {
+            (InstructionCategory::Move, Some((dst, _))) if dst.contains('[') => {
+                InstructionCategory::Store
+            }
+            (InstructionCategory::Move, Some((_, src))) if src.contains('[') => {
+                InstructionCategory::Load
+            }
+            _ => category,
+        }
+    }
+}
+
+/// Amount of instructions of each [`InstructionCategory`] in a sequence of [`Statement`]s.
+///
+/// Histograms are recorded for each basic block by [`CFG::new`](crate::analysis::CFG::new), and
+/// used to tell apart functions with the same structure but different bodies.
+/// # Examples
+/// Basic usage:
+/// ```
+/// use bincc::disasm::{CategoryHistogram, InstructionCategory, Statement, StatementFamily};
+///
+/// let stmts = vec![
+///     Statement::new(0x10, StatementFamily::ADD, "add eax, 1"),
+///     Statement::new(0x13, StatementFamily::XOR, "xor ebx, ebx"),
+///     Statement::new(0x15, StatementFamily::RET, "ret"),
+/// ];
+/// let histogram = CategoryHistogram::new(&stmts);
+///
+/// assert_eq!(histogram.count(InstructionCategory::Arithmetic), 1);
+/// assert_eq!(histogram.count(InstructionCategory::Load), 0);
+/// assert_eq!(histogram.total(), 3);
+/// ```
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct CategoryHistogram {
+    counts: [u32; InstructionCategory::ALL.len()],
+}
+
+impl CategoryHistogram {
+    /// Counts the categories of the given statements.
+    pub fn new(stmts: &[Statement]) -> CategoryHistogram {
+        let mut histogram = CategoryHistogram::default();
+        for stmt in stmts {
+            histogram.counts[stmt.category() as usize] += 1;
+        }
+        histogram
+    }
+
+    /// Creates an histogram with the given amount of instructions for each category, in the
+    /// order of [`InstructionCategory::ALL`].
+    pub fn from_counts(counts: [u32; InstructionCategory::ALL.len()]) -> CategoryHistogram {
+        CategoryHistogram { counts }
+    }
+
+    /// Returns the amount of instructions of the given category.
+    pub fn count(&self, category: InstructionCategory) -> u32 {
+        self.counts[category as usize]
+    }
+
+    /// Returns the amount of instructions of each category, in the order of
+    /// [`InstructionCategory::ALL`].
+    pub fn counts(&self) -> &[u32] {
+        &self.counts
+    }
+
+    /// Returns the amount of instructions in the histogram.
+    pub fn total(&self) -> u32 {
+        self.counts.iter().sum()
+    }
+
+    /// Adds the instructions of another histogram to this one.
+    pub fn merge(&mut self, other: &CategoryHistogram) {
+        self.counts
+            .iter_mut()
+            .zip(other.counts)
+            .for_each(|(count, other)| *count += other);
+    }
+
+    /// Returns the cosine similarity of two histograms, between 0 and 1.
+    ///
+    /// Two empty histograms are considered identical, while an empty histogram has similarity 0
+    /// with any other histogram.
+    pub fn similarity(&self, other: &CategoryHistogram) -> f64 {
+        let squared_norm = |histogram: &CategoryHistogram| {
+            histogram
+                .counts
+                .iter()
+                .map(|&count| count as f64 * count as f64)
+                .sum::<f64>()
+        };
+        match (self.total(), other.total()) {
+            (0, 0) => 1.0,
+            (0, _) | (_, 0) => 0.0,
+            _ => {
+                let dot = self
+                    .counts
+                    .iter()
+                    .zip(other.counts)
+                    .map(|(&a, b)| a as f64 * b as f64)
+                    .sum::<f64>();
+                // a single square root keeps the similarity of equal histograms exactly 1
+                dot / (squared_norm(self) * squared_norm(other)).sqrt()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::disasm::{CategoryHistogram, InstructionCategory, Statement, StatementFamily};
+
+    #[test]
+    fn categories() {
+        let category = |family, instruction| Statement::new(0, family, instruction).category();
+        assert_eq!(
+            category(StatementFamily::SHL, "shl eax, 2"),
+            InstructionCategory::Logic
+        );
+        assert_eq!(
+            category(StatementFamily::MOV, "mov eax, ebx"),
+            InstructionCategory::Move
+        );
+        assert_eq!(
+            category(StatementFamily::MOV, "mov eax, dword [rbp - 4]"),
+            InstructionCategory::Load
+        );
+        assert_eq!(
+            category(StatementFamily::PUSH, "push rbp"),
+            InstructionCategory::Store
+        );
+        assert_eq!(
+            category(StatementFamily::LOAD, "ldr r0, [r1]"),
+            InstructionCategory::Load
+        );
+        assert_eq!(
+            category(StatementFamily::UNK, "aese v0.16b, v1.16b"),
+            InstructionCategory::Crypto
+        );
+        assert_eq!(
+            category(StatementFamily::SWI, "syscall"),
+            InstructionCategory::System
+        );
+        for (index, category) in InstructionCategory::ALL.iter().enumerate() {
+            assert_eq!(*category as usize, index);
+        }
+    }
+
+    #[test]
+    fn histogram_similarity() {
+        let arith = CategoryHistogram::new(&[
+            Statement::new(0x0, StatementFamily::ADD, "add eax, 1"),
+            Statement::new(0x3, StatementFamily::MUL, "imul eax, ebx"),
+        ]);
+        let mut memory = CategoryHistogram::new(&[
+            Statement::new(0x0, StatementFamily::LOAD, "ldr r0, [r1]"),
+            Statement::new(0x4, StatementFamily::STORE, "str r0, [r2]"),
+        ]);
+        assert_eq!(arith.similarity(&arith), 1.0);
+        assert_eq!(arith.similarity(&memory), 0.0);
+        assert_eq!(arith.similarity(&CategoryHistogram::default()), 0.0);
+        assert_eq!(
+            CategoryHistogram::default().similarity(&CategoryHistogram::default()),
+            1.0
+        );
+        memory.merge(&arith);
+        assert_eq!(memory.total(), 4);
+        assert_eq!(memory.count(InstructionCategory::Arithmetic), 2);
+        assert!((arith.similarity(&memory) - 2.0 / 6.0_f64.sqrt()).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,90 @@
+/// Demangles the given function name.
+///
+/// Both Rust and C++ (Itanium ABI) names are supported, respectively with the `rustc-demangle`
+/// and `cpp_demangle` features, enabled by default. The prefixes added by radare2, like `sym.` or
+/// `sym.imp.`, are ignored. The hash appended to the Rust names is omitted.
+///
+/// Returns [`None`] if the name is not mangled, or if the support for its mangling scheme is not
+/// enabled.
+/// # Examples
+/// Basic usage:
+/// ```
+/// use bincc::disasm::demangle;
+///
+/// # if cfg!(all(feature = "cpp_demangle", feature = "rustc-demangle")) {
+/// assert_eq!(demangle("sym._Z3addii").as_deref(), Some("add(int, int)"));
+/// assert_eq!(
+///     demangle("_ZN4core3fmt5write17h8b7e3a57e1834e9cE").as_deref(),
+///     Some("core::fmt::write")
+/// );
+/// # }
+/// assert_eq!(demangle("main"), None);
+/// ```
+pub fn demangle(name: &str) -> Option<String> {
+    let name = ["sym.imp.", "sym.", "imp.", "reloc."]
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .unwrap_or(name);
+    demangle_rust(name).or_else(|| demangle_cpp(name))
+}
+
+#[cfg(feature = "rustc-demangle")]
+fn demangle_rust(name: &str) -> Option<String> {
+    rustc_demangle::try_demangle(name)
+        .ok()
+        .map(|demangled| format!("{:#}", demangled))
+}
+
+#[cfg(not(feature = "rustc-demangle"))]
+fn demangle_rust(_: &str) -> Option<String> {
+    None
+}
+
+#[cfg(feature = "cpp_demangle")]
+fn demangle_cpp(name: &str) -> Option<String> {
+    // Mach-O names have an additional leading underscore
+    let name = name
+        .strip_prefix('_')
+        .filter(|rest| rest.starts_with("_Z"))
+        .unwrap_or(name);
+    cpp_demangle::Symbol::new(name)
+        .ok()?
+        .demangle(&cpp_demangle::DemangleOptions::default())
+        .ok()
+}
+
+#[cfg(not(feature = "cpp_demangle"))]
+fn demangle_cpp(_: &str) -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::disasm::demangle;
+
+    #[test]
+    fn not_mangled() {
+        assert!(demangle("main").is_none());
+        assert!(demangle("sym.imp.puts").is_none());
+        assert!(demangle("fcn.00001140").is_none());
+        assert!(demangle("").is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "cpp_demangle")]
+    fn cpp() {
+        let expected = Some("foo::bar(int, char)");
+        assert_eq!(demangle("_ZN3foo3barEic").as_deref(), expected);
+        assert_eq!(demangle("sym.imp._ZN3foo3barEic").as_deref(), expected);
+        assert_eq!(demangle("__ZN3foo3barEic").as_deref(), expected);
+    }
+
+    #[test]
+    #[cfg(feature = "rustc-demangle")]
+    fn rust() {
+        let legacy = "sym._ZN3std2io5stdio6_print17h9a2ce6cc38e4e6d4E";
+        assert_eq!(demangle(legacy).as_deref(), Some("std::io::stdio::_print"));
+        let v0 = "_RNvC7mycrate4main";
+        assert_eq!(demangle(v0).as_deref(), Some("mycrate::main"));
+    }
+}
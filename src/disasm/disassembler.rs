@@ -0,0 +1,87 @@
+use crate::analysis::CFG;
+use crate::disasm::{
+    Architecture, Function, MemoryImage, Relocation, Symbol, SymbolKind, SymbolTable,
+};
+use std::future::Future;
+
+/// Interface shared by the tools extracting functions and their control flow from a binary.
+///
+/// Every backend, like [`R2Disasm`](crate::disasm::radare2::R2Disasm), provides the same
+/// information through this trait, so the analyses can be run regardless of the tool used to
+/// disassemble the binary.
+///
+/// The methods are asynchronous as the backends usually communicate with an external process.
+#[cfg_attr(docsrs, doc(cfg(feature = "disasm")))]
+pub trait Disassembler {
+    /// Returns every function found in the binary, sorted by offset.
+    fn functions(&mut self) -> impl Future<Output = Vec<Function>>;
+
+    /// Returns the [`CFG`] of the function starting at the given offset.
+    ///
+    /// Returns [`None`] if the offset does not correspond to a function or the backend can not
+    /// compute its control flow.
+    fn disassemble_function(&mut self, offset: u64) -> impl Future<Output = Option<CFG>>;
+
+    /// Same as [`Disassembler::disassemble_function`], but with the architecture of the function
+    /// already known, as returned by [`Disassembler::function_architecture`].
+    ///
+    /// This spares the backends querying the architecture of each function again. The default
+    /// implementation ignores the architecture and calls
+    /// [`Disassembler::disassemble_function`].
+    fn disassemble_function_with(
+        &mut self,
+        offset: u64,
+        _arch: Option<Architecture>,
+    ) -> impl Future<Output = Option<CFG>> {
+        self.disassemble_function(offset)
+    }
+
+    /// Returns the architecture of the binary, or [`None`] if not supported.
+    fn architecture(&mut self) -> impl Future<Output = Option<Architecture>>;
+
+    /// Returns the architecture of the function starting at the given offset.
+    ///
+    /// The architecture of a function may differ from the one of the binary in the amount of
+    /// bits, as in the 32-bit code of a 64-bit executable. The default implementation returns
+    /// [`Disassembler::architecture`].
+    fn function_architecture(
+        &mut self,
+        _offset: u64,
+    ) -> impl Future<Output = Option<Architecture>> {
+        self.architecture()
+    }
+
+    /// Returns the symbols of the binary: functions, imports and exports.
+    ///
+    /// The default implementation contains only the functions returned by
+    /// [`Disassembler::functions`], with unknown size.
+    fn symbols(&mut self) -> impl Future<Output = SymbolTable> {
+        async {
+            let mut table = SymbolTable::new();
+            for function in self.functions().await {
+                let offset = function.get_offset();
+                let symbol = Symbol::new(function.get_name(), offset, 0, SymbolKind::Function);
+                table.insert(symbol);
+            }
+            table
+        }
+    }
+
+    /// Returns the relocations of the binary, sorted by address.
+    ///
+    /// The default implementation returns no relocations.
+    fn relocations(&mut self) -> impl Future<Output = Vec<Relocation>> {
+        async { Vec::new() }
+    }
+
+    /// Returns the content of the memory of the binary, used to read the values it contains
+    /// with the correct endianness.
+    ///
+    /// The memory contains every section of the binary, so it is requested only by
+    /// [`Binary::load_memory`](crate::disasm::Binary::load_memory), when needed.
+    ///
+    /// The default implementation returns [`None`], as the memory is not available.
+    fn memory(&mut self) -> impl Future<Output = Option<MemoryImage>> {
+        async { None }
+    }
+}
@@ -59,6 +59,12 @@ impl Function {
     pub fn get_offset(&self) -> u64 {
         self.offset
     }
+
+    // moves the function by the given amount of bytes.
+    #[cfg(feature = "disasm")]
+    pub(super) fn rebase(&mut self, delta: u64) {
+        self.offset = self.offset.wrapping_add(delta);
+    }
 }
 
 impl Ord for Function {
@@ -0,0 +1,345 @@
+use std::collections::BTreeMap;
+
+/// Order of the bytes of the values stored in memory.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(docsrs, doc(cfg(feature = "disasm")))]
+pub enum Endianness {
+    /// Least significant byte first, as in x86.
+    Little,
+    /// Most significant byte first, as in most MIPS and PowerPC firmware.
+    Big,
+}
+
+/// Access rights of a [`Segment`] of memory.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(docsrs, doc(cfg(feature = "disasm")))]
+pub struct Permissions {
+    read: bool,
+    write: bool,
+    execute: bool,
+}
+
+impl Permissions {
+    /// Creates the given access rights.
+    pub fn new(read: bool, write: bool, execute: bool) -> Permissions {
+        Permissions {
+            read,
+            write,
+            execute,
+        }
+    }
+
+    /// Returns true if the segment can be read.
+    pub fn is_readable(&self) -> bool {
+        self.read
+    }
+
+    /// Returns true if the segment can be written.
+    pub fn is_writable(&self) -> bool {
+        self.write
+    }
+
+    /// Returns true if the segment contains code.
+    pub fn is_executable(&self) -> bool {
+        self.execute
+    }
+}
+
+/// Range of virtual addresses of a binary, like a section, loaded from the file.
+///
+/// The segment may be larger than the content stored in the file: the remaining addresses, like
+/// the ones of a `.bss` section, belong to the segment but their value is not known.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(docsrs, doc(cfg(feature = "disasm")))]
+pub struct Segment {
+    name: String,
+    address: u64,
+    size: u64,
+    bytes: Vec<u8>,
+    permissions: Permissions,
+}
+
+impl Segment {
+    /// Creates a segment with the given name and permissions, containing the given bytes starting
+    /// at the given address.
+    pub fn new(name: &str, address: u64, bytes: Vec<u8>, permissions: Permissions) -> Segment {
+        Segment {
+            name: name.to_string(),
+            address,
+            size: bytes.len() as u64,
+            bytes,
+            permissions,
+        }
+    }
+
+    /// Sets the amount of virtual addresses spanned by the segment.
+    ///
+    /// The size can not be smaller than the amount of bytes contained in the segment.
+    pub fn with_size(mut self, size: u64) -> Segment {
+        self.size = size.max(self.bytes.len() as u64);
+        self
+    }
+
+    /// Returns the name of the segment, empty if unknown.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the first address of the segment.
+    pub fn address(&self) -> u64 {
+        self.address
+    }
+
+    /// Returns the amount of virtual addresses spanned by the segment.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns the content of the segment stored in the file.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Returns the access rights of the segment.
+    pub fn permissions(&self) -> Permissions {
+        self.permissions
+    }
+
+    /// Returns true if the given address belongs to the segment.
+    pub fn contains(&self, address: u64) -> bool {
+        address >= self.address && address - self.address < self.size
+    }
+}
+
+/// Content of the memory of a binary, as loaded at runtime.
+///
+/// The memory is composed of [`Segment`]s, each one mapping a range of virtual addresses to the
+/// bytes of the file and to its access rights.
+/// Every read of the values contained in the binary, like the entries of a jump table or the
+/// pointers of a literal pool, should use this struct, so the values are decoded with the
+/// endianness of the binary. For example, [`CFG::with_memory`] reads the jump tables of a function
+/// from it.
+///
+/// [`CFG::with_memory`]: crate::analysis::CFG::with_memory
+/// # Examples
+/// Basic usage:
+/// ```
+/// use bincc::disasm::{Endianness, MemoryImage, Permissions, Segment};
+///
+/// let code = Segment::new(".text", 0x2000, vec![0xC3], Permissions::new(true, false, true));
+/// let memory = MemoryImage::new(Endianness::Big)
+///     .with_segment(0x1000, vec![0x12, 0x34, 0x56, 0x78])
+///     .with_mapping(code);
+///
+/// assert_eq!(memory.read_u16(0x1002), Some(0x5678));
+/// assert_eq!(memory.read_pointer(0x1000, 32), Some(0x12345678));
+/// assert_eq!(memory.read_u32(0x1002), None);
+/// assert!(memory.is_executable(0x2000));
+/// assert!(!memory.is_executable(0x1000));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(docsrs, doc(cfg(feature = "disasm")))]
+pub struct MemoryImage {
+    endianness: Endianness,
+    // segments indexed by their first address
+    segments: BTreeMap<u64, Segment>,
+}
+
+impl MemoryImage {
+    /// Creates an empty memory with the given endianness.
+    pub fn new(endianness: Endianness) -> MemoryImage {
+        MemoryImage {
+            endianness,
+            segments: BTreeMap::new(),
+        }
+    }
+
+    /// Adds the given bytes to the memory, starting at the given address.
+    ///
+    /// The bytes are added as an unnamed, read-only [`Segment`], see
+    /// [`MemoryImage::with_mapping`].
+    pub fn with_segment(self, address: u64, bytes: Vec<u8>) -> MemoryImage {
+        let permissions = Permissions::new(true, false, false);
+        self.with_mapping(Segment::new("", address, bytes, permissions))
+    }
+
+    /// Adds the given segment to the memory.
+    ///
+    /// The segments should not overlap: a segment starting at the same address of an existing one
+    /// replaces it.
+    pub fn with_mapping(mut self, segment: Segment) -> MemoryImage {
+        self.segments.insert(segment.address, segment);
+        self
+    }
+
+    /// Returns the endianness used to decode the values.
+    pub fn endianness(&self) -> Endianness {
+        self.endianness
+    }
+
+    /// Returns every segment of the memory, sorted by address.
+    pub fn segments(&self) -> impl Iterator<Item = &Segment> {
+        self.segments.values()
+    }
+
+    /// Returns the segment containing the given address, if any.
+    pub fn segment(&self, address: u64) -> Option<&Segment> {
+        self.segments
+            .range(..=address)
+            .next_back()
+            .map(|(_, segment)| segment)
+            .filter(|segment| segment.contains(address))
+    }
+
+    /// Returns the access rights of the given address, or [`None`] if the address is not mapped.
+    pub fn permissions(&self, address: u64) -> Option<Permissions> {
+        self.segment(address).map(Segment::permissions)
+    }
+
+    /// Returns true if the given address is mapped and contains code.
+    pub fn is_executable(&self, address: u64) -> bool {
+        self.permissions(address)
+            .is_some_and(|perms| perms.is_executable())
+    }
+
+    /// Moves every segment from the load address `from` to the load address `to`.
+    #[must_use]
+    pub fn rebase(self, from: u64, to: u64) -> MemoryImage {
+        let delta = to.wrapping_sub(from);
+        let segments = self
+            .segments
+            .into_values()
+            .map(|mut segment| {
+                segment.address = segment.address.wrapping_add(delta);
+                (segment.address, segment)
+            })
+            .collect();
+        MemoryImage {
+            endianness: self.endianness,
+            segments,
+        }
+    }
+
+    /// Returns the given amount of bytes starting at the given address.
+    ///
+    /// Returns [`None`] if any of the bytes is not contained in the same segment, or is not stored
+    /// in the file.
+    pub fn read(&self, address: u64, len: usize) -> Option<&[u8]> {
+        let (start, segment) = self.segments.range(..=address).next_back()?;
+        let offset = usize::try_from(address - start).ok()?;
+        segment.bytes.get(offset..offset.checked_add(len)?)
+    }
+
+    /// Reads a 16-bit value at the given address.
+    pub fn read_u16(&self, address: u64) -> Option<u16> {
+        self.read_array(address).map(|bytes| match self.endianness {
+            Endianness::Little => u16::from_le_bytes(bytes),
+            Endianness::Big => u16::from_be_bytes(bytes),
+        })
+    }
+
+    /// Reads a 32-bit value at the given address.
+    pub fn read_u32(&self, address: u64) -> Option<u32> {
+        self.read_array(address).map(|bytes| match self.endianness {
+            Endianness::Little => u32::from_le_bytes(bytes),
+            Endianness::Big => u32::from_be_bytes(bytes),
+        })
+    }
+
+    /// Reads a 64-bit value at the given address.
+    pub fn read_u64(&self, address: u64) -> Option<u64> {
+        self.read_array(address).map(|bytes| match self.endianness {
+            Endianness::Little => u64::from_le_bytes(bytes),
+            Endianness::Big => u64::from_be_bytes(bytes),
+        })
+    }
+
+    /// Reads a pointer of the given amount of bits, usually [`Architecture::bits`], at the given
+    /// address.
+    ///
+    /// Returns [`None`] also if the amount of bits is not 16, 32 or 64.
+    ///
+    /// [`Architecture::bits`]: crate::disasm::Architecture::bits
+    pub fn read_pointer(&self, address: u64, bits: u32) -> Option<u64> {
+        match bits {
+            16 => self.read_u16(address).map(u64::from),
+            32 => self.read_u32(address).map(u64::from),
+            64 => self.read_u64(address),
+            _ => None,
+        }
+    }
+
+    fn read_array<const N: usize>(&self, address: u64) -> Option<[u8; N]> {
+        self.read(address, N).map(|bytes| bytes.try_into().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::disasm::{Endianness, MemoryImage, Permissions, Segment};
+
+    #[test]
+    fn endianness() {
+        let bytes = vec![0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08];
+        let little = MemoryImage::new(Endianness::Little).with_segment(0x100, bytes.clone());
+        let big = MemoryImage::new(Endianness::Big).with_segment(0x100, bytes);
+        assert_eq!(little.read_u16(0x100), Some(0x0201));
+        assert_eq!(big.read_u16(0x100), Some(0x0102));
+        assert_eq!(little.read_u32(0x104), Some(0x08070605));
+        assert_eq!(big.read_u32(0x104), Some(0x05060708));
+        assert_eq!(little.read_u64(0x100), Some(0x0807060504030201));
+        assert_eq!(big.read_u64(0x100), Some(0x0102030405060708));
+        assert_eq!(big.read_pointer(0x102, 16), Some(0x0304));
+        assert_eq!(big.read_pointer(0x100, 24), None);
+    }
+
+    #[test]
+    fn bounds() {
+        let memory = MemoryImage::new(Endianness::Little)
+            .with_segment(0x100, vec![0xAA; 4])
+            .with_segment(0x104, vec![0xBB; 4]);
+        assert_eq!(memory.read(0x104, 4), Some(&[0xBB; 4][..]));
+        assert_eq!(memory.read(0x102, 2), Some(&[0xAA; 2][..]));
+        // values spanning two segments are not read
+        assert!(memory.read_u32(0x102).is_none());
+        assert!(memory.read_u32(0x106).is_none());
+        assert!(memory.read(0xFF, 1).is_none());
+        assert!(memory.read(0x100, usize::MAX).is_none());
+        assert_eq!(memory.read(0x108, 0), Some(&[][..]));
+    }
+
+    #[test]
+    fn segments() {
+        let text = Segment::new(
+            ".text",
+            0x100,
+            vec![0x90; 4],
+            Permissions::new(true, false, true),
+        );
+        let bss = Segment::new(".bss", 0x200, vec![], Permissions::new(true, true, false))
+            .with_size(0x10);
+        let memory = MemoryImage::new(Endianness::Little)
+            .with_mapping(bss)
+            .with_mapping(text);
+        let names = memory.segments().map(Segment::name).collect::<Vec<_>>();
+        assert_eq!(names, vec![".text", ".bss"]);
+        assert_eq!(memory.segment(0x103).unwrap().name(), ".text");
+        assert!(memory.segment(0x104).is_none());
+        assert_eq!(memory.segment(0x20F).unwrap().size(), 0x10);
+        assert!(memory.is_executable(0x100));
+        assert!(!memory.is_executable(0x200));
+        assert!(memory.permissions(0x208).unwrap().is_writable());
+        assert!(memory.permissions(0x300).is_none());
+        // the content of the .bss is not in the file
+        assert!(memory.read(0x200, 1).is_none());
+    }
+
+    #[test]
+    fn rebase() {
+        let memory = MemoryImage::new(Endianness::Little).with_segment(0x1000, vec![0xAA; 4]);
+        let rebased = memory.clone().rebase(0x1000, 0x7000);
+        assert_eq!(rebased.read(0x7002, 2), Some(&[0xAA; 2][..]));
+        assert!(rebased.read(0x1000, 1).is_none());
+        assert_eq!(rebased.rebase(0x7000, 0x1000), memory);
+    }
+}
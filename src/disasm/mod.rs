@@ -1,11 +1,49 @@
 mod statement;
 pub use self::statement::Statement;
 pub use self::statement::StatementFamily;
+mod category;
+pub use self::category::CategoryHistogram;
+pub use self::category::InstructionCategory;
 mod function;
 pub use self::function::Function;
 mod architectures;
 pub use self::architectures::Architecture;
 pub use self::architectures::JumpType;
+#[cfg(feature = "disasm")]
+mod memory;
+#[cfg(feature = "disasm")]
+pub use self::memory::Endianness;
+#[cfg(feature = "disasm")]
+pub use self::memory::MemoryImage;
+#[cfg(feature = "disasm")]
+pub use self::memory::Permissions;
+#[cfg(feature = "disasm")]
+pub use self::memory::Segment;
+#[cfg(feature = "disasm")]
+mod disassembler;
+#[cfg(feature = "disasm")]
+pub use self::disassembler::Disassembler;
+mod demangle;
+pub use self::demangle::demangle;
+#[cfg(feature = "disasm")]
+mod symbols;
+#[cfg(feature = "disasm")]
+pub use self::symbols::Relocation;
+#[cfg(feature = "disasm")]
+pub use self::symbols::Symbol;
+#[cfg(feature = "disasm")]
+pub use self::symbols::SymbolKind;
+#[cfg(feature = "disasm")]
+pub use self::symbols::SymbolTable;
+#[cfg(feature = "disasm")]
+mod binary;
+#[cfg(feature = "disasm")]
+pub use self::binary::Binary;
+#[cfg(feature = "disasm")]
+pub use self::binary::ExtractionConfig;
 
 /// Contains disassembler routines specific to radare2.
+///
+/// The [`R2Disasm`](radare2::R2Disasm) backend spawns an external process, and requires the
+/// `radare2` feature, enabled also by the `loaders` feature.
 pub mod radare2;
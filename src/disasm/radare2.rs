@@ -1,12 +1,27 @@
+#[cfg(feature = "radare2")]
+use crate::analysis::CFG;
+#[cfg(feature = "radare2")]
 use crate::disasm::architectures::Architecture;
-use crate::disasm::{Statement, StatementFamily};
+#[cfg(feature = "radare2")]
+use crate::disasm::{
+    Disassembler, Endianness, Function, MemoryImage, Permissions, Relocation, Segment, Statement,
+    StatementFamily, Symbol, SymbolKind, SymbolTable,
+};
+#[cfg(feature = "radare2")]
 use fnv::{FnvHashMap, FnvHashSet};
+#[cfg(feature = "radare2")]
 use lazy_static::lazy_static;
+#[cfg(feature = "radare2")]
 use r2pipe::{R2PipeAsync, R2PipeSpawnOptions};
+#[cfg(feature = "radare2")]
 use regex::Regex;
+#[cfg(feature = "radare2")]
 use std::collections::HashMap;
+#[cfg(feature = "radare2")]
 use std::io::ErrorKind;
+#[cfg(feature = "radare2")]
 use std::str::FromStr;
+#[cfg(feature = "radare2")]
 use std::{fs, io};
 
 /// A very basic Control Flow Graph.
@@ -33,13 +48,26 @@ pub struct BareCFG {
 /// Disassembler using the radare2 backend.
 ///
 /// Using this struct requires having installed radare2, with the `r2` binary on the path.
+#[cfg(feature = "radare2")]
+#[cfg_attr(docsrs, doc(cfg(feature = "radare2")))]
 pub struct R2Disasm {
     // pipe to the external r2 command.
     // no need for a mutex as it is not possible to invoke commands to the same external process
     // at the same time (this struct does not implement copy or clone)
     pipe: R2PipeAsync,
+    // name of the architecture forced with set_arch
+    forced_arch: Option<&'static str>,
+    // bits forced with set_bits
+    bits: Option<u32>,
+    // endianness forced with set_endianness
+    endianness: Option<Endianness>,
+    // architecture of the executable, queried once
+    arch: Option<Option<Architecture>>,
+    // bits of every function, queried once after the analysis
+    function_bits: Option<FnvHashMap<u64, u32>>,
 }
 
+#[cfg(feature = "radare2")]
 impl R2Disasm {
     /// Creates a new radare2 disassembling interface.
     ///
@@ -56,7 +84,14 @@ impl R2Disasm {
             };
             let maybe_pipe = R2PipeAsync::spawn(binary, Some(flags)).await;
             match maybe_pipe {
-                Ok(pipe) => Ok(Self { pipe }),
+                Ok(pipe) => Ok(Self {
+                    pipe,
+                    forced_arch: None,
+                    bits: None,
+                    endianness: None,
+                    arch: None,
+                    function_bits: None,
+                }),
                 Err(err) => Err(io::Error::new(ErrorKind::BrokenPipe, err)),
             }
         } else {
@@ -64,8 +99,58 @@ impl R2Disasm {
         }
     }
 
+    /// Forces the architecture used to decode the instructions, given with the name returned by
+    /// [`Architecture::name`], like `"arm"`.
+    ///
+    /// By default, the architecture is detected from the executable. This operation should be
+    /// called before [R2Disasm::analyse].
+    pub async fn set_arch(&mut self, arch: &'static str) {
+        match self.pipe.cmd(&format!("e asm.arch={}", arch)).await {
+            Ok(_) => {
+                self.forced_arch = Some(arch);
+                self.arch = None;
+                self.function_bits = None;
+            }
+            Err(error) => {
+                log::error!("{}", error);
+            }
+        }
+    }
+
+    /// Forces the amount of bits used to decode the instructions, like 16 for real-mode x86.
+    ///
+    /// By default, the bits are detected from the executable. This operation should be called
+    /// before [R2Disasm::analyse].
+    pub async fn set_bits(&mut self, bits: u32) {
+        match self.pipe.cmd(&format!("e asm.bits={}", bits)).await {
+            Ok(_) => {
+                self.bits = Some(bits);
+                self.arch = None;
+                self.function_bits = None;
+            }
+            Err(error) => {
+                log::error!("{}", error);
+            }
+        }
+    }
+
+    /// Forces the endianness used to decode the instructions and the values in memory.
+    ///
+    /// By default, the endianness is detected from the executable. This operation should be
+    /// called before [R2Disasm::analyse].
+    pub async fn set_endianness(&mut self, endianness: Endianness) {
+        let big = endianness == Endianness::Big;
+        match self.pipe.cmd(&format!("e cfg.bigendian={}", big)).await {
+            Ok(_) => self.endianness = Some(endianness),
+            Err(error) => {
+                log::error!("{}", error);
+            }
+        }
+    }
+
     /// Performs analysis on the underlying binary.
     pub async fn analyse(&mut self) {
+        self.function_bits = None;
         match self.pipe.cmd("aaa").await {
             Ok(_) => {}
             Err(error) => {
@@ -79,6 +164,7 @@ impl R2Disasm {
     /// The default implementation calls [R2Disasm::analyse] thus performing a full-binary
     /// analysis.
     pub async fn analyse_functions(&mut self) {
+        self.function_bits = None;
         match self.pipe.cmd("aa").await {
             Ok(_) => match self.pipe.cmd("aac").await {
                 Ok(_) => {}
@@ -96,36 +182,43 @@ impl R2Disasm {
     ///
     /// This operation *DOES NOT* require to run [R2Disasm::analyse] first.
     ///
-    /// If the architecture can not be recognized, None is returned.
+    /// If the architecture can not be recognized, None is returned. The architecture and the bits
+    /// forced with [R2Disasm::set_arch] and [R2Disasm::set_bits] replace the ones of the file.
     pub async fn get_arch(&mut self) -> Option<Architecture> {
         match self.pipe.cmdj("ij").await {
             Ok(json) => {
-                let bits = json["bin"]["bits"].as_u64()?;
-                let arch = json["bin"]["arch"].as_str()?;
-                match arch {
-                    "arc" => Some(Architecture::ARC(bits as u32)),
-                    "avr" => Some(Architecture::AVR),
-                    "arm" => Some(Architecture::Arm(bits as u32)),
-                    "i4004" => Some(Architecture::I4004),
-                    "8051" => Some(Architecture::I8051(bits as u32)),
-                    "i8080" => Some(Architecture::I8080),
-                    "lm32" => Some(Architecture::LM32),
-                    "LH5801" => Some(Architecture::Lh5801),
-                    "6502" => Some(Architecture::M6502),
-                    "m68k" => Some(Architecture::M68K),
-                    "msp430" => Some(Architecture::MSP430),
-                    "propeller" => Some(Architecture::Propeller),
-                    "v850" => Some(Architecture::V850),
-                    "z80" => Some(Architecture::Z80),
-                    "s390" => Some(Architecture::S390(bits as u32)),
-                    "ppc" => Some(Architecture::PowerPC(bits as u32)),
-                    "mips" => Some(Architecture::Mips(bits as u32)),
-                    "riscv" => Some(Architecture::Riscv(bits as u32)),
-                    "sparc" => Some(Architecture::Sparc(bits as u32)),
-                    "x86" => Some(Architecture::X86(bits as u32)),
-                    _ => None,
-                }
+                let bits = match self.bits {
+                    Some(bits) => bits,
+                    None => json["bin"]["bits"].as_u64()? as u32,
+                };
+                let arch = match self.forced_arch {
+                    Some(arch) => arch,
+                    None => json["bin"]["arch"].as_str()?,
+                };
+                Architecture::from_name(arch, bits)
+            }
+            Err(error) => {
+                log::error!("{}", error);
+                None
             }
+        }
+    }
+
+    /// Returns the endianness of a specific file.
+    ///
+    /// This operation *DOES NOT* require to run [R2Disasm::analyse] first.
+    ///
+    /// The endianness forced with [R2Disasm::set_endianness] replaces the one of the file.
+    pub async fn get_endianness(&mut self) -> Option<Endianness> {
+        if self.endianness.is_some() {
+            return self.endianness;
+        }
+        match self.pipe.cmdj("ij").await {
+            Ok(json) => match json["bin"]["endian"].as_str()? {
+                "little" => Some(Endianness::Little),
+                "big" => Some(Endianness::Big),
+                _ => None,
+            },
             Err(error) => {
                 log::error!("{}", error);
                 None
@@ -133,6 +226,54 @@ impl R2Disasm {
         }
     }
 
+    /// Returns the sections of the executable, as mapped in memory.
+    ///
+    /// This operation *DOES NOT* require to run [R2Disasm::analyse] first.
+    ///
+    /// Only the content stored in the file is loaded, so sections like `.bss` are part of the
+    /// result but contain no bytes. Sections that are not mapped in memory, like `.comment`, are
+    /// skipped.
+    pub async fn get_memory_image(&mut self) -> Option<MemoryImage> {
+        let mut memory = MemoryImage::new(self.get_endianness().await?);
+        let sections = match self.pipe.cmdj("iSj").await {
+            Ok(json) => json,
+            Err(error) => {
+                log::error!("{}", error);
+                return None;
+            }
+        };
+        for section in sections.as_array().into_iter().flatten() {
+            let maybe_address = section["vaddr"].as_u64();
+            let maybe_size = section["size"].as_u64();
+            let maybe_perm = section["perm"].as_str().map(parse_permissions);
+            if let (Some(address), Some(size), Some(perm)) = (maybe_address, maybe_size, maybe_perm)
+            {
+                if perm == Permissions::default() {
+                    continue;
+                }
+                let bytes = if size == 0 {
+                    Vec::new()
+                } else {
+                    match self.pipe.cmd(&format!("p8 {} @ {}", size, address)).await {
+                        Ok(hex) => match parse_hex(&hex) {
+                            Some(bytes) => bytes,
+                            None => continue,
+                        },
+                        Err(error) => {
+                            log::error!("{}", error);
+                            continue;
+                        }
+                    }
+                };
+                let name = section["name"].as_str().unwrap_or_default();
+                let vsize = section["vsize"].as_u64().unwrap_or(size);
+                let segment = Segment::new(name, address, bytes, perm).with_size(vsize);
+                memory = memory.with_mapping(segment);
+            }
+        }
+        Some(memory)
+    }
+
     /// Returns the starting offset of each function contained in the disassembled executable
     ///
     /// This operation requires calling [R2Disasm::analyse] first.
@@ -181,6 +322,44 @@ impl R2Disasm {
         retval
     }
 
+    /// Returns the amount of bits used by the function starting at the given offset.
+    ///
+    /// The bits may differ from the ones of the executable, for example in the 32-bit code of a
+    /// 64-bit executable, or if set with a radare2 hint. This operation requires calling
+    /// [R2Disasm::analyse] first.
+    pub async fn get_function_bits(&mut self, function: u64) -> Option<u32> {
+        match self.pipe.cmdj(&format!("afij @ {}", function)).await {
+            Ok(json) => json[0]["bits"].as_u64().map(|bits| bits as u32),
+            Err(error) => {
+                log::error!("{}", error);
+                None
+            }
+        }
+    }
+
+    /// Returns the amount of bits used by every function, as pairs `(function offset, bits)`.
+    ///
+    /// Same as [R2Disasm::get_function_bits], but with a single query for the whole executable.
+    /// This operation requires calling [R2Disasm::analyse] first.
+    pub async fn get_functions_bits(&mut self) -> FnvHashMap<u64, u32> {
+        let mut retval = FnvHashMap::default();
+        match self.pipe.cmdj("aflj").await {
+            Ok(json) => {
+                for func in json.as_array().into_iter().flatten() {
+                    let maybe_offset = func["offset"].as_u64();
+                    let maybe_bits = func["bits"].as_u64();
+                    if let (Some(offset), Some(bits)) = (maybe_offset, maybe_bits) {
+                        retval.insert(offset, bits as u32);
+                    }
+                }
+            }
+            Err(error) => {
+                log::error!("{}", error)
+            }
+        }
+        retval
+    }
+
     /// Returns the statements composing a single basic block.
     ///
     /// This operation requires calling [R2Disasm::analyse] first.
@@ -317,6 +496,165 @@ impl R2Disasm {
     }
 }
 
+#[cfg(feature = "radare2")]
+impl Disassembler for R2Disasm {
+    /// Returns every function found in the binary, sorted by offset.
+    ///
+    /// This operation requires calling [R2Disasm::analyse] first.
+    async fn functions(&mut self) -> Vec<Function> {
+        let mut functions = self
+            .get_function_names()
+            .await
+            .into_iter()
+            .map(|(name, offset)| Function::new(offset, &name))
+            .collect::<Vec<_>>();
+        functions.sort();
+        functions
+    }
+
+    /// Returns the [CFG] of the function starting at the given offset, as computed by radare2.
+    ///
+    /// The blocks and edges are the ones of [R2Disasm::get_function_cfg], labeled with the
+    /// statements of [R2Disasm::get_function_body] as in [CFG::with_statements], so the calls,
+    /// terminators and instructions of each block are known. This operation requires calling
+    /// [R2Disasm::analyse] first.
+    async fn disassemble_function(&mut self, offset: u64) -> Option<CFG> {
+        let arch = self.function_architecture(offset).await;
+        self.disassemble_function_with(offset, arch).await
+    }
+
+    /// Same as [R2Disasm::disassemble_function], but labels the blocks with the statements
+    /// decoded for the given architecture.
+    ///
+    /// The blocks are not labeled if the jumps of the architecture are not known, see
+    /// [Architecture::supports_jumps].
+    async fn disassemble_function_with(
+        &mut self,
+        offset: u64,
+        arch: Option<Architecture>,
+    ) -> Option<CFG> {
+        let bare = self.get_function_cfg(offset).await?;
+        let stmts = self.get_function_body(offset).await.unwrap_or_default();
+        match arch {
+            Some(arch) => Some(CFG::with_statements(bare, &stmts, arch)),
+            None => Some(CFG::from(bare)),
+        }
+    }
+
+    /// Returns the architecture of the executable, see [R2Disasm::get_arch].
+    ///
+    /// The architecture is queried only the first time.
+    async fn architecture(&mut self) -> Option<Architecture> {
+        if self.arch.is_none() {
+            self.arch = Some(self.get_arch().await);
+        }
+        self.arch.flatten()
+    }
+
+    /// Returns the architecture of the function, with the bits found by
+    /// [R2Disasm::get_functions_bits].
+    ///
+    /// The bits of every function are queried only the first time. This operation requires
+    /// calling [R2Disasm::analyse] first.
+    async fn function_architecture(&mut self, offset: u64) -> Option<Architecture> {
+        let arch = self.architecture().await?;
+        if self.bits.is_some() {
+            return Some(arch);
+        }
+        if self.function_bits.is_none() {
+            self.function_bits = Some(self.get_functions_bits().await);
+        }
+        match self
+            .function_bits
+            .as_ref()
+            .and_then(|bits| bits.get(&offset))
+        {
+            Some(&bits) => Architecture::from_name(arch.name(), bits),
+            None => Some(arch),
+        }
+    }
+
+    /// Returns the relocations of the binary naming a symbol, sorted by address.
+    ///
+    /// This operation *DOES NOT* require to run [R2Disasm::analyse] first.
+    async fn relocations(&mut self) -> Vec<Relocation> {
+        let mut relocations = Vec::new();
+        match self.pipe.cmdj("irj").await {
+            Ok(json) => {
+                for reloc in json.as_array().into_iter().flatten() {
+                    let maybe_address = reloc["vaddr"].as_u64();
+                    let maybe_name = reloc["name"].as_str();
+                    if let (Some(address), Some(name)) = (maybe_address, maybe_name) {
+                        relocations.push(Relocation::new(address, name));
+                    }
+                }
+            }
+            Err(error) => {
+                log::error!("{}", error)
+            }
+        }
+        relocations.sort_by_key(Relocation::address);
+        relocations
+    }
+
+    /// Returns the content of the sections of the binary, see [R2Disasm::get_memory_image].
+    async fn memory(&mut self) -> Option<MemoryImage> {
+        self.get_memory_image().await
+    }
+
+    /// Returns the functions, imports and exports of the binary.
+    ///
+    /// Imports are located at their PLT entry, if any. This operation requires calling
+    /// [R2Disasm::analyse] first.
+    async fn symbols(&mut self) -> SymbolTable {
+        let mut table = SymbolTable::new();
+        // command, kind, key of the address and key of the size
+        let queries = [
+            ("aflj", SymbolKind::Function, "offset", "size"),
+            ("iij", SymbolKind::Import, "plt", "size"),
+            ("iEj", SymbolKind::Export, "vaddr", "size"),
+        ];
+        for (cmd, kind, address_key, size_key) in queries {
+            match self.pipe.cmdj(cmd).await {
+                Ok(json) => {
+                    for sym in json.as_array().into_iter().flatten() {
+                        let maybe_name = sym["name"].as_str();
+                        let maybe_address = sym[address_key].as_u64();
+                        if let (Some(name), Some(address)) = (maybe_name, maybe_address) {
+                            let size = sym[size_key].as_u64().unwrap_or(0);
+                            table.insert(Symbol::new(name, address, size, kind));
+                        }
+                    }
+                }
+                Err(error) => {
+                    log::error!("{}", error)
+                }
+            }
+        }
+        table
+    }
+}
+
+// parses the permissions of a section, like `-r-x`.
+#[cfg(feature = "radare2")]
+fn parse_permissions(perm: &str) -> Permissions {
+    Permissions::new(perm.contains('r'), perm.contains('w'), perm.contains('x'))
+}
+
+// parses the output of `p8`, like `7f454c46`.
+#[cfg(feature = "radare2")]
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    hex.trim()
+        .as_bytes()
+        .chunks(2)
+        .map(|digits| match std::str::from_utf8(digits) {
+            Ok(digits) if digits.len() == 2 => u8::from_str_radix(digits, 16).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(feature = "radare2")]
 fn radare_dot_to_bare_cfg_edges(dot: &str) -> Vec<(u64, u64)> {
     let mut edges = Vec::new();
     lazy_static! {
@@ -341,6 +679,7 @@ fn radare_dot_to_bare_cfg_edges(dot: &str) -> Vec<(u64, u64)> {
     edges
 }
 
+#[cfg(feature = "radare2")]
 fn radare_dot_to_bare_cfg_nodes(bbs: &str) -> Vec<(u64, u64)> {
     let mut blocks = Vec::new();
     lazy_static! {
@@ -365,10 +704,11 @@ fn radare_dot_to_bare_cfg_nodes(bbs: &str) -> Vec<(u64, u64)> {
     blocks
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "radare2"))]
 mod tests {
-    use crate::disasm::radare2::{BareCFG, R2Disasm};
-    use crate::disasm::Architecture;
+    use crate::analysis::{Graph, CFG};
+    use crate::disasm::radare2::{parse_hex, parse_permissions, BareCFG, R2Disasm};
+    use crate::disasm::{Architecture, Disassembler, Endianness, Permissions};
     use serial_test::serial;
     use std::io::ErrorKind;
     use std::{fs, io};
@@ -616,4 +956,66 @@ mod tests {
         assert_eq!(cfg, expected);
         Ok(())
     }
+
+    #[tokio::test]
+    async fn disassembler_interface() -> Result<(), io::Error> {
+        let project_root = env!("CARGO_MANIFEST_DIR");
+        let x86_64 = format!("{}/{}", project_root, "resources/tests/x86_64");
+        let mut disassembler = R2Disasm::new(&x86_64).await?;
+        disassembler.analyse().await;
+        let arch = disassembler.architecture().await.unwrap();
+        assert_eq!(arch.bits(), Architecture::X86(64).bits());
+        let functions = disassembler.functions().await;
+        assert!(functions.iter().any(|func| func.get_offset() == 0x1000));
+        assert!(functions
+            .windows(2)
+            .all(|pair| pair[0].get_offset() <= pair[1].get_offset()));
+        let cfg = disassembler.disassemble_function(0x1000).await.unwrap();
+        let expected = CFG::from(disassembler.get_function_cfg(0x1000).await.unwrap());
+        assert_eq!(cfg.len(), expected.len());
+        assert_eq!(cfg.len(), 3);
+        // the blocks are labeled with the statements of the function
+        let root = *cfg.root().unwrap();
+        assert!(cfg.instruction_count(&root).unwrap() > 0);
+        assert!(expected.instruction_count(&root).is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn memory_image() -> Result<(), io::Error> {
+        let project_root = env!("CARGO_MANIFEST_DIR");
+        let x86_64 = format!("{}/{}", project_root, "resources/tests/x86_64");
+        let mut disassembler = R2Disasm::new(&x86_64).await?;
+        let memory = disassembler.get_memory_image().await.unwrap();
+        assert_eq!(memory.endianness(), Endianness::Little);
+        assert!(memory.read_u32(0x1149).is_some());
+        assert!(memory.is_executable(0x1149));
+        let text = memory.segments().find(|seg| seg.name() == ".text").unwrap();
+        assert!(!text.permissions().is_writable());
+        disassembler.set_endianness(Endianness::Big).await;
+        let memory = disassembler.memory().await.unwrap();
+        assert_eq!(memory.endianness(), Endianness::Big);
+        Ok(())
+    }
+
+    #[test]
+    fn hex_content() {
+        assert_eq!(parse_hex("7f454c46\n"), Some(vec![0x7f, 0x45, 0x4c, 0x46]));
+        assert_eq!(parse_hex(""), Some(vec![]));
+        assert!(parse_hex("7f4").is_none());
+        assert!(parse_hex("zz").is_none());
+    }
+
+    #[test]
+    fn section_permissions() {
+        assert_eq!(
+            parse_permissions("-r-x"),
+            Permissions::new(true, false, true)
+        );
+        assert_eq!(
+            parse_permissions("-rw-"),
+            Permissions::new(true, true, false)
+        );
+        assert_eq!(parse_permissions("----"), Permissions::default());
+    }
 }
@@ -0,0 +1,310 @@
+use crate::analysis::CallTarget;
+use crate::disasm::demangle;
+use std::collections::{BTreeMap, HashMap};
+
+/// Kind of a [`Symbol`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(docsrs, doc(cfg(feature = "disasm")))]
+pub enum SymbolKind {
+    /// Function defined in the binary.
+    Function,
+    /// Function defined in another binary and imported by this one.
+    Import,
+    /// Symbol defined in the binary and visible to the other binaries.
+    Export,
+}
+
+/// Named address range of a binary.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(docsrs, doc(cfg(feature = "disasm")))]
+pub struct Symbol {
+    name: String,
+    demangled: Option<String>,
+    address: u64,
+    size: u64,
+    kind: SymbolKind,
+}
+
+impl Symbol {
+    /// Creates a new symbol with the given name, starting at the given address and spanning the
+    /// given amount of bytes.
+    ///
+    /// The size can be 0 if unknown, as for most imports. The name is demangled with
+    /// [`demangle`].
+    pub fn new(name: &str, address: u64, size: u64, kind: SymbolKind) -> Symbol {
+        Symbol {
+            name: name.to_string(),
+            demangled: demangle(name),
+            address,
+            size,
+            kind,
+        }
+    }
+
+    /// Returns the name of the symbol, as it appears in the binary.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the demangled name of the symbol, if the name is mangled.
+    pub fn demangled_name(&self) -> Option<&str> {
+        self.demangled.as_deref()
+    }
+
+    /// Returns the name that should be shown to the user: the demangled name, if any, or the name
+    /// as it appears in the binary.
+    pub fn display_name(&self) -> &str {
+        self.demangled.as_deref().unwrap_or(&self.name)
+    }
+
+    /// Returns the first address of the symbol.
+    pub fn address(&self) -> u64 {
+        self.address
+    }
+
+    /// Returns the size of the symbol in bytes, or 0 if unknown.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns the kind of the symbol.
+    pub fn kind(&self) -> SymbolKind {
+        self.kind
+    }
+
+    /// Returns true if the given address belongs to the symbol.
+    ///
+    /// Symbols with unknown size contain only their first address.
+    pub fn contains(&self, address: u64) -> bool {
+        address == self.address || (address > self.address && address - self.address < self.size)
+    }
+}
+
+/// Address of a binary patched by the loader with the address of a symbol.
+///
+/// In the partially linked objects, like `.o` files and kernel modules, the relocations are the
+/// only source of the targets of the calls to other objects.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(docsrs, doc(cfg(feature = "disasm")))]
+pub struct Relocation {
+    address: u64,
+    symbol: String,
+}
+
+impl Relocation {
+    /// Creates a relocation patching the given address with the address of the given symbol.
+    pub fn new(address: u64, symbol: &str) -> Relocation {
+        Relocation {
+            address,
+            symbol: symbol.to_string(),
+        }
+    }
+
+    /// Returns the address patched by the relocation.
+    pub fn address(&self) -> u64 {
+        self.address
+    }
+
+    /// Returns the name of the symbol written by the relocation.
+    pub fn symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    // returns the relocation patching the instruction of the given length at the given offset,
+    // given the relocations sorted by address. A relocation patches the bytes from its address to
+    // the end of the instruction containing it.
+    pub(crate) fn patching(
+        relocations: &[Relocation],
+        offset: u64,
+        length: u64,
+    ) -> Option<&Relocation> {
+        let first = relocations.partition_point(|reloc| reloc.address() < offset);
+        relocations
+            .get(first)
+            .filter(|reloc| reloc.address() < offset.saturating_add(length))
+    }
+
+    // moves the patched address by the given amount of bytes.
+    pub(super) fn rebase(&mut self, delta: u64) {
+        self.address = self.address.wrapping_add(delta);
+    }
+}
+
+/// Symbols of a binary, used to resolve addresses into names.
+///
+/// Usually retrieved with [`Disassembler::symbols`](crate::disasm::Disassembler::symbols).
+/// # Examples
+/// Basic usage:
+/// ```
+/// use bincc::analysis::CallTarget;
+/// use bincc::disasm::{Symbol, SymbolKind, SymbolTable};
+///
+/// let mut table = SymbolTable::new();
+/// table.insert(Symbol::new("main", 0x1140, 0x40, SymbolKind::Function));
+/// table.insert(Symbol::new("puts", 0x1030, 0, SymbolKind::Import));
+///
+/// assert_eq!(table.resolve(0x1150).unwrap().name(), "main");
+/// assert!(table.resolve(0x1180).is_none());
+/// assert_eq!(table.by_name("puts").unwrap().address(), 0x1030);
+/// assert_eq!(table.call_name(&CallTarget::Address(0x1030)), Some("puts"));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(docsrs, doc(cfg(feature = "disasm")))]
+pub struct SymbolTable {
+    // symbols sharing the same address are kept in insertion order
+    symbols: BTreeMap<u64, Vec<Symbol>>,
+    names: HashMap<String, u64>,
+}
+
+impl SymbolTable {
+    /// Creates an empty symbol table.
+    pub fn new() -> SymbolTable {
+        SymbolTable::default()
+    }
+
+    /// Adds a symbol to the table.
+    ///
+    /// Symbols with the same name and address of an existing one replace it.
+    pub fn insert(&mut self, symbol: Symbol) {
+        let symbols = self.symbols.entry(symbol.address).or_default();
+        match symbols.iter_mut().find(|sym| sym.name == symbol.name) {
+            Some(existing) => *existing = symbol,
+            None => {
+                self.names
+                    .entry(symbol.name.clone())
+                    .or_insert(symbol.address);
+                symbols.push(symbol);
+            }
+        }
+    }
+
+    /// Returns the amount of symbols in the table.
+    pub fn len(&self) -> usize {
+        self.symbols.values().map(Vec::len).sum()
+    }
+
+    /// Returns true if the table contains no symbols.
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    /// Returns every symbol, sorted by address.
+    pub fn iter(&self) -> impl Iterator<Item = &Symbol> + '_ {
+        self.symbols.values().flatten()
+    }
+
+    /// Returns every symbol of the given kind, sorted by address.
+    pub fn of_kind(&self, kind: SymbolKind) -> impl Iterator<Item = &Symbol> + '_ {
+        self.iter().filter(move |sym| sym.kind == kind)
+    }
+
+    /// Returns the symbol containing the given address.
+    ///
+    /// If several symbols contain the address, the one starting closer to it is returned,
+    /// preferring [`SymbolKind::Function`] over the other kinds.
+    pub fn resolve(&self, address: u64) -> Option<&Symbol> {
+        self.symbols
+            .range(..=address)
+            .rev()
+            .map(|(_, symbols)| {
+                symbols
+                    .iter()
+                    .filter(|sym| sym.contains(address))
+                    .min_by_key(|sym| sym.kind != SymbolKind::Function)
+            })
+            .find(Option::is_some)
+            .flatten()
+    }
+
+    /// Returns every symbol starting at the given address, in insertion order.
+    pub fn at(&self, address: u64) -> &[Symbol] {
+        self.symbols.get(&address).map_or(&[], Vec::as_slice)
+    }
+
+    /// Returns the first symbol inserted with the given name.
+    pub fn by_name(&self, name: &str) -> Option<&Symbol> {
+        let address = self.names.get(name)?;
+        self.symbols[address].iter().find(|sym| sym.name == name)
+    }
+
+    /// Moves every symbol from the load address `from` to the load address `to`.
+    ///
+    /// The symbols sharing the same address keep their insertion order.
+    #[must_use]
+    pub fn rebase(self, from: u64, to: u64) -> SymbolTable {
+        let delta = to.wrapping_sub(from);
+        let mut table = SymbolTable::new();
+        for mut symbol in self.symbols.into_values().flatten() {
+            symbol.address = symbol.address.wrapping_add(delta);
+            table.insert(symbol);
+        }
+        table
+    }
+
+    /// Returns the name of the function called by the given target, if known.
+    pub fn call_name<'a>(&'a self, target: &'a CallTarget) -> Option<&'a str> {
+        match target {
+            CallTarget::Address(address) => self
+                .symbols
+                .get(address)
+                .and_then(|symbols| symbols.first())
+                .map(Symbol::name),
+            CallTarget::Symbol(name) => Some(name),
+            CallTarget::Indirect(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::disasm::{Symbol, SymbolKind, SymbolTable};
+
+    #[test]
+    fn resolve_overlapping() {
+        let mut table = SymbolTable::new();
+        table.insert(Symbol::new("exported", 0x100, 0x10, SymbolKind::Export));
+        table.insert(Symbol::new("outer", 0x100, 0x40, SymbolKind::Function));
+        table.insert(Symbol::new("inner", 0x120, 0x8, SymbolKind::Function));
+        table.insert(Symbol::new("imp", 0x200, 0, SymbolKind::Import));
+        assert_eq!(table.len(), 4);
+        assert_eq!(table.resolve(0x104).unwrap().name(), "outer");
+        assert_eq!(table.resolve(0x124).unwrap().name(), "inner");
+        assert_eq!(table.resolve(0x130).unwrap().name(), "outer");
+        assert_eq!(table.resolve(0x200).unwrap().name(), "imp");
+        assert!(table.resolve(0x201).is_none());
+        assert!(table.resolve(0x10).is_none());
+        let exports = table.of_kind(SymbolKind::Export).collect::<Vec<_>>();
+        assert_eq!(exports.len(), 1);
+        assert_eq!(exports[0].name(), "exported");
+        assert_eq!(table.at(0x100).len(), 2);
+        assert!(table.at(0x104).is_empty());
+    }
+
+    #[test]
+    fn rebase() {
+        let mut table = SymbolTable::new();
+        table.insert(Symbol::new("main", 0x1100, 0x40, SymbolKind::Function));
+        table.insert(Symbol::new("_start", 0x1000, 0x20, SymbolKind::Function));
+        table.insert(Symbol::new("start", 0x1000, 0x20, SymbolKind::Export));
+        let rebased = table.clone().rebase(0x1000, 0x400000);
+        assert_eq!(rebased.len(), 3);
+        assert_eq!(rebased.resolve(0x400110).unwrap().name(), "main");
+        assert_eq!(rebased.by_name("start").unwrap().address(), 0x400000);
+        let at = rebased.at(0x400000);
+        assert_eq!(at[0].name(), "_start");
+        assert_eq!(at[1].name(), "start");
+        assert!(rebased.resolve(0x1100).is_none());
+        assert_eq!(rebased.rebase(0x400000, 0x1000), table);
+    }
+
+    #[test]
+    fn insert_replaces() {
+        let mut table = SymbolTable::new();
+        table.insert(Symbol::new("main", 0x100, 0, SymbolKind::Function));
+        table.insert(Symbol::new("main", 0x100, 0x20, SymbolKind::Function));
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.by_name("main").unwrap().size(), 0x20);
+        assert!(table.by_name("missing").is_none());
+        assert!(SymbolTable::new().is_empty());
+    }
+}
@@ -0,0 +1,42 @@
+use std::io;
+use thiserror::Error;
+
+/// Error returned by the fallible operations of this crate.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// Error while reading or writing a file, or while communicating with the disassembler.
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// The input of an importer, like [`CFG::from_dot`](crate::analysis::CFG::from_dot), is not
+    /// well-formed.
+    #[error("invalid input: {0}")]
+    InvalidInput(String),
+    /// The [`CFG`](crate::analysis::CFG) is inconsistent and can not be structured, for example
+    /// because an edge reaches a block that does not exist.
+    #[error("malformed CFG: {0}")]
+    MalformedCfg(String),
+    /// The structuring reached an inconsistent state, for example because a custom
+    /// [`Reduction`](crate::analysis::Reduction) replaced some nodes with a block already in the
+    /// graph.
+    #[error("inconsistent structuring: {0}")]
+    Structuring(String),
+    /// The input [`CFG`](crate::analysis::CFG) is larger than
+    /// [`CfsBuilder::max_size`](crate::analysis::CfsBuilder::max_size) or its structuring required
+    /// more steps than
+    /// [`CfsBuilder::max_iterations`](crate::analysis::CfsBuilder::max_iterations).
+    #[error("structuring budget exceeded")]
+    Budget,
+    /// The analysis has been stopped through a
+    /// [`CancellationToken`](crate::analysis::CancellationToken).
+    #[error("analysis cancelled")]
+    Cancelled,
+    /// SQLite failed while accessing a
+    /// [`FingerprintDatabase`](crate::analysis::FingerprintDatabase).
+    #[cfg(feature = "compare-db")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "compare-db")))]
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+/// Result type of the fallible operations of this crate.
+pub type Result<T> = std::result::Result<T, Error>;
@@ -8,3 +8,6 @@
 pub mod analysis;
 /// Module providing disassembler bindings.
 pub mod disasm;
+mod error;
+pub use self::error::Error;
+pub use self::error::Result;